@@ -0,0 +1,239 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::field_access::Access;
+use crate::models::{AnswerRequest, AnswerResponse, PromptTemplate};
+
+/// Identifies answers that can be reused: same index, same question and same options
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    index: String,
+    /// Every request option except the question, plus the resolved prompt template
+    params: String,
+    question: String,
+}
+
+impl CacheKey {
+    pub fn new(
+        index_name: &str,
+        request: &AnswerRequest,
+        template: Option<&PromptTemplate>,
+    ) -> Self {
+        let mut options = request.clone();
+        options.query = String::new();
+        options.stream = false;
+        let params = serde_json::json!({ "request": options, "template": template }).to_string();
+
+        Self {
+            index: index_name.to_string(),
+            params,
+            question: normalize_question(&request.query),
+        }
+    }
+}
+
+impl CacheKey {
+    /// Answers are only reused for callers who see the same fields and documents
+    pub fn for_access(mut self, access: &Access) -> Self {
+        if !access.hidden_fields.is_empty() || !access.filters.is_empty() {
+            let access = serde_json::json!({
+                "hidden": access.hidden_fields,
+                "filters": access.filters,
+            });
+            self.params = format!("{}|{}", self.params, access);
+        }
+        self
+    }
+}
+
+/// Lowercase a question and collapse whitespace, so trivial variations share an entry
+fn normalize_question(question: &str) -> String {
+    question
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Words of a question compared by the near-duplicate lookup
+fn question_terms(question: &str) -> HashSet<String> {
+    question
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 1)
+        .map(str::to_string)
+        .collect()
+}
+
+/// Share of terms two questions have in common (Jaccard similarity)
+fn similarity(a: &HashSet<String>, b: &HashSet<String>) -> f32 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f32 / union as f32
+}
+
+struct CacheEntry {
+    response: AnswerResponse,
+    /// Commit opstamp of the index when the answer was generated
+    opstamp: u64,
+    terms: HashSet<String>,
+    inserted: Instant,
+}
+
+/// Generated answers kept for a while, so repeated questions skip the LLM
+pub struct AnswerCache {
+    ttl: Option<Duration>,
+    capacity: usize,
+    /// Minimum term overlap for reusing the answer to a similar question
+    min_similarity: Option<f32>,
+    entries: Mutex<HashMap<CacheKey, CacheEntry>>,
+}
+
+impl AnswerCache {
+    /// Cache from `ANSWER_CACHE_TTL_SECONDS` (unset or 0 disables caching),
+    /// `ANSWER_CACHE_SIZE` (default: 1000) and `ANSWER_CACHE_SIMILARITY` (0-1, unset
+    /// only reuses answers to the same question)
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            std::env::var(name).ok().and_then(|v| v.trim().parse().ok())
+        }
+
+        Self {
+            ttl: var::<u64>("ANSWER_CACHE_TTL_SECONDS")
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+            capacity: var("ANSWER_CACHE_SIZE").unwrap_or(1000),
+            min_similarity: var::<f32>("ANSWER_CACHE_SIMILARITY").filter(|s| *s > 0.0 && *s <= 1.0),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.ttl.is_some() && self.capacity > 0
+    }
+
+    /// Cached answer for a question, unless it expired or the index changed since.
+    /// Falls back to the most similar cached question when near-duplicates are allowed.
+    pub fn get(&self, key: &CacheKey, opstamp: u64) -> Option<AnswerResponse> {
+        let ttl = self.ttl?;
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, entry| entry.inserted.elapsed() < ttl);
+
+        if let Some(entry) = entries.get(key) {
+            return (entry.opstamp == opstamp).then(|| entry.response.clone());
+        }
+
+        let min_similarity = self.min_similarity?;
+        let terms = question_terms(&key.question);
+        entries
+            .iter()
+            .filter(|(other, entry)| {
+                other.index == key.index && other.params == key.params && entry.opstamp == opstamp
+            })
+            .map(|(_, entry)| (similarity(&terms, &entry.terms), entry))
+            .filter(|(score, _)| *score >= min_similarity)
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, entry)| entry.response.clone())
+    }
+
+    pub fn insert(&self, key: CacheKey, opstamp: u64, response: AnswerResponse) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.inserted)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        let terms = question_terms(&key.question);
+        entries.insert(
+            key,
+            CacheEntry {
+                response,
+                opstamp,
+                terms,
+                inserted: Instant::now(),
+            },
+        );
+    }
+
+    /// Drop the cached answers of an index, e.g. when it is deleted
+    pub fn invalidate(&self, index_name: &str) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|key, _| key.index != index_name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(query: &str, search_limit: usize) -> AnswerRequest {
+        serde_json::from_value(serde_json::json!({
+            "query": query,
+            "search_limit": search_limit,
+        }))
+        .unwrap()
+    }
+
+    fn response(answer: &str) -> AnswerResponse {
+        AnswerResponse {
+            answer: answer.to_string(),
+            model: "m".to_string(),
+            search_took_ms: 0.0,
+            llm_took_ms: 0.0,
+            total_took_ms: 0.0,
+            sources: Vec::new(),
+            citations: Vec::new(),
+            rewritten_query: None,
+            groundedness: None,
+            cached: false,
+            usage: None,
+        }
+    }
+
+    fn cache(min_similarity: Option<f32>) -> AnswerCache {
+        AnswerCache {
+            ttl: Some(Duration::from_secs(60)),
+            capacity: 10,
+            min_similarity,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    #[test]
+    fn test_exact_lookup() {
+        let cache = cache(None);
+        let key = CacheKey::new("kb", &request("Når åpner  barnehagen?", 5), None);
+        cache.insert(key, 7, response("kl 7"));
+
+        let same = CacheKey::new("kb", &request("når åpner barnehagen?", 5), None);
+        assert_eq!(cache.get(&same, 7).unwrap().answer, "kl 7");
+        // A commit to the index makes the answer stale
+        assert!(cache.get(&same, 8).is_none());
+        // Different options don't share answers
+        let other = CacheKey::new("kb", &request("når åpner barnehagen?", 3), None);
+        assert!(cache.get(&other, 7).is_none());
+    }
+
+    #[test]
+    fn test_similar_lookup() {
+        let cache = cache(Some(0.7));
+        let key = CacheKey::new("kb", &request("når åpner barnehagen i bergen", 5), None);
+        cache.insert(key, 1, response("kl 7"));
+
+        let similar = CacheKey::new("kb", &request("når åpner barnehagen bergen?", 5), None);
+        assert_eq!(cache.get(&similar, 1).unwrap().answer, "kl 7");
+        let different = CacheKey::new("kb", &request("når stenger barnehagen", 5), None);
+        assert!(cache.get(&different, 1).is_none());
+    }
+}
@@ -0,0 +1,140 @@
+use axum::{
+    body::Body,
+    extract::{MatchedPath, State},
+    http::Request,
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::sync::Arc;
+
+use crate::models::{AuditEntry, IngestOutcome};
+use crate::quota;
+use crate::AppState;
+
+/// Requests recorded in the audit log, by method and route, with the action they perform
+const AUDITED_ROUTES: &[(&str, &str, &str)] = &[
+    ("POST", "/indices", "index.create"),
+    ("DELETE", "/indices/:name", "index.delete"),
+    ("POST", "/indices/:name/sync", "index.sync"),
+    ("POST", "/indices/:name/close", "index.close"),
+    ("POST", "/indices/:name/open", "index.open"),
+    ("POST", "/indices/:name/rollover", "index.rollover"),
+    ("POST", "/indices/:name/migrate", "index.migrate"),
+    ("PUT", "/indices/:name/settings", "settings.update"),
+    ("POST", "/indices/:name/documents", "documents.add"),
+    ("POST", "/indices/:name/documents/upload", "documents.upload"),
+    ("DELETE", "/indices/:name/documents/:id", "documents.delete"),
+    ("POST", "/indices/:name/bulk", "documents.bulk"),
+    ("POST", "/indices/:name/_txn", "documents.transaction"),
+    ("POST", "/indices/:name/crawlers", "crawler.create"),
+    ("DELETE", "/indices/:name/crawlers/:id", "crawler.delete"),
+    ("POST", "/indices/:name/crawlers/:id/run", "crawler.run"),
+    ("POST", "/indices/:name/synonyms", "synonyms.add"),
+    ("DELETE", "/indices/:name/synonyms", "synonyms.clear"),
+    ("POST", "/indices/:name/pinned", "pinned.add"),
+    ("DELETE", "/indices/:name/pinned", "pinned.clear"),
+    ("POST", "/indices/:name/filters", "filters.add"),
+    ("DELETE", "/indices/:name/filters/:filter", "filters.delete"),
+    ("POST", "/indices/:name/prompts", "prompts.add"),
+    ("DELETE", "/indices/:name/prompts/:prompt", "prompts.delete"),
+    ("POST", "/indices/:name/presets", "presets.add"),
+    ("DELETE", "/indices/:name/presets/:preset", "presets.delete"),
+    ("POST", "/keys", "key.create"),
+    ("POST", "/admin/jobs", "job.create"),
+    ("DELETE", "/admin/jobs/:id", "job.delete"),
+    ("POST", "/admin/jobs/:id/run", "job.run"),
+];
+
+/// What a request changed beyond what its path says, such as the IDs of the documents
+/// it wrote. Handlers put it in their response's extensions for the audit log.
+#[derive(Debug, Clone)]
+pub struct AuditDetails(pub serde_json::Value);
+
+impl AuditDetails {
+    /// Documents written, replaced and deleted by a batch
+    pub fn documents(outcome: &IngestOutcome) -> Self {
+        let mut details = serde_json::Map::new();
+        for (name, ids) in [
+            ("indexed", &outcome.indexed_ids),
+            ("updated", &outcome.updated_ids),
+            ("replaced", &outcome.replaced_ids),
+            ("deleted", &outcome.deleted_ids),
+        ] {
+            if !ids.is_empty() {
+                details.insert(name.to_string(), serde_json::json!(ids));
+            }
+        }
+        Self(serde_json::Value::Object(details))
+    }
+}
+
+/// Timestamp of an audit entry. Entries are filtered by comparing timestamps as text,
+/// so they all use UTC and millisecond precision.
+pub fn timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn action(method: &str, route: &str) -> Option<&'static str> {
+    AUDITED_ROUTES
+        .iter()
+        .find(|(m, r, _)| *m == method && *r == route)
+        .map(|(_, _, action)| *action)
+}
+
+/// Record administrative requests and document writes in the audit log: who (a hash of
+/// the caller's token), when, the action and its outcome, whether it succeeded or not
+pub async fn audit_log(
+    State(state): State<Arc<AppState>>,
+    matched_path: Option<MatchedPath>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let method = req.method().to_string();
+    let Some(action) = matched_path.and_then(|route| action(&method, route.as_str())) else {
+        return next.run(req).await;
+    };
+    let actor = quota::api_key_id(req.headers());
+    let path = req.uri().path().to_string();
+
+    let mut response = next.run(req).await;
+
+    let details = response.extensions_mut().remove::<AuditDetails>().map(|d| d.0);
+    // A created index is named in the request body rather than the path
+    let index = match path.strip_prefix("/indices/") {
+        Some(rest) => rest.split('/').next().map(str::to_string),
+        None => details
+            .as_ref()
+            .and_then(|details| details.get("index"))
+            .and_then(|index| index.as_str())
+            .map(str::to_string),
+    };
+    let entry = AuditEntry {
+        id: 0,
+        timestamp: timestamp(Utc::now()),
+        actor,
+        action: action.to_string(),
+        index,
+        method,
+        path,
+        status: response.status().as_u16(),
+        details,
+    };
+    if let Err(e) = state.metadata_store.record_audit(&entry) {
+        tracing::error!("Failed to record {} in the audit log: {}", entry.action, e);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audits_changes_only() {
+        assert_eq!(action("POST", "/indices"), Some("index.create"));
+        assert_eq!(action("DELETE", "/indices/:name/documents/:id"), Some("documents.delete"));
+        assert_eq!(action("GET", "/indices/:name/synonyms"), None);
+        assert_eq!(action("POST", "/indices/:name/search"), None);
+    }
+}
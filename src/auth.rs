@@ -1,14 +1,168 @@
 use axum::{
     body::Body,
     extract::State,
-    http::{Request, StatusCode},
+    http::{HeaderMap, Request, StatusCode},
     middleware::Next,
     response::Response,
 };
+use chrono::Utc;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
+use crate::error::SearchError;
+use crate::field_access::{self, Access};
+use crate::index_patterns;
+use crate::search_keys;
 use crate::AppState;
 
+/// Bearer tokens accepted by the API and the scopes each one grants
+#[derive(Debug, Default)]
+pub struct ApiTokens {
+    scopes: HashMap<String, HashSet<String>>,
+}
+
+impl ApiTokens {
+    /// Read comma-separated tokens from `API_TOKENS`; a token can be followed by the
+    /// scopes it grants, e.g. `secret|internal|billing`
+    pub fn from_env() -> Self {
+        Self::parse(&std::env::var("API_TOKENS").unwrap_or_default())
+    }
+
+    pub fn parse(value: &str) -> Self {
+        let scopes = value
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| {
+                let mut parts = entry.split('|').map(str::trim);
+                let token = parts.next().filter(|token| !token.is_empty())?;
+                let scopes = parts.filter(|scope| !scope.is_empty()).map(str::to_string);
+                Some((token.to_string(), scopes.collect()))
+            })
+            .collect();
+        Self { scopes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scopes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scopes.len()
+    }
+
+    pub fn contains(&self, token: &str) -> bool {
+        self.scopes.contains_key(token)
+    }
+
+    /// First token matching a predicate
+    pub fn find(&self, predicate: impl Fn(&str) -> bool) -> Option<&str> {
+        self.scopes.keys().map(String::as_str).find(|token| predicate(token))
+    }
+
+    /// Scopes granted by a token, if it is one of ours
+    pub fn scopes(&self, token: &str) -> Option<&HashSet<String>> {
+        self.scopes.get(token)
+    }
+}
+
+/// Bearer token from the Authorization header
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+}
+
+/// What the caller may see in an index: restricted fields its token doesn't grant, and
+/// the filter of its search key. Nothing is restricted while authentication is disabled.
+pub fn search_access(
+    state: &AppState,
+    index_name: &str,
+    headers: &HeaderMap,
+) -> Result<Access, SearchError> {
+    if state.api_tokens.is_empty() {
+        return Ok(Access::default());
+    }
+    // A field restricted in any of the searched indices stays restricted
+    let mut restricted = HashMap::new();
+    for name in state.search_engine.search_indices(index_name) {
+        if let Some(settings) = state.search_engine.index_settings(&name) {
+            restricted.extend(settings.restricted_fields);
+        }
+    }
+
+    let token = bearer_token(headers);
+    let Some(key) = token.filter(|token| search_keys::is_search_key(token)) else {
+        let scopes = token.and_then(|token| state.api_tokens.scopes(token));
+        return Ok(Access {
+            hidden_fields: field_access::hidden_fields(&restricted, scopes),
+            filters: Vec::new(),
+        });
+    };
+
+    // A bad key is rejected even on public routes, where leaving it out would not be
+    let (claims, parent) = search_keys::verify(key, &state.api_tokens)
+        .ok_or_else(|| SearchError::InvalidKey("Invalid search key".to_string()))?;
+    if claims.is_expired(Utc::now().timestamp()) {
+        return Err(SearchError::InvalidKey("Search key has expired".to_string()));
+    }
+    // A pattern needs a key valid for every index and alias it matches
+    let index_names = if index_patterns::is_pattern(index_name) {
+        state.search_engine.resolve_pattern(index_name)
+    } else {
+        vec![index_name.to_string()]
+    };
+    if let Some(name) = index_names.iter().find(|name| !claims.allows_index(name)) {
+        return Err(SearchError::KeyNotAllowed(format!(
+            "Search key is not valid for index: {}",
+            name
+        )));
+    }
+
+    // Scopes removed from the parent token since the key was made no longer apply
+    let parent_scopes = state.api_tokens.scopes(parent).cloned().unwrap_or_default();
+    let scopes: HashSet<String> = claims
+        .scopes
+        .into_iter()
+        .filter(|scope| parent_scopes.contains(scope))
+        .collect();
+    Ok(Access {
+        hidden_fields: field_access::hidden_fields(&restricted, Some(&scopes)),
+        filters: vec![claims.filter],
+    })
+}
+
+/// Routes search keys can be used on: `/indices/:name/search` and `/indices/:name/answer`
+fn is_search_route(path: &str) -> bool {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    matches!(segments.as_slice(), ["indices", _, "search" | "answer"])
+}
+
+/// Read-only routes that are public unless listed in `PROTECTED_ROUTES`
+pub const OPTIONAL_ROUTES: &[&str] =
+    &["indices", "search", "suggest", "answer", "stats", "fields"];
+
+/// Read-only routes that should require a token, from the comma-separated
+/// `PROTECTED_ROUTES` (e.g. `answer,suggest`, or `all`)
+pub fn protected_routes_from_env() -> HashSet<String> {
+    let value = std::env::var("PROTECTED_ROUTES").unwrap_or_default();
+    let mut routes = HashSet::new();
+    for name in value.split(',').map(|s| s.trim().to_lowercase()) {
+        if name.is_empty() {
+            continue;
+        }
+        if name == "all" {
+            routes.extend(OPTIONAL_ROUTES.iter().map(|r| r.to_string()));
+        } else if OPTIONAL_ROUTES.contains(&name.as_str()) {
+            routes.insert(name);
+        } else {
+            tracing::warn!("Ignoring unknown route '{}' in PROTECTED_ROUTES", name);
+        }
+    }
+    routes
+}
+
 pub async fn auth_middleware(
     State(state): State<Arc<AppState>>,
     req: Request<Body>,
@@ -19,15 +173,39 @@ pub async fn auth_middleware(
         return Ok(next.run(req).await);
     }
 
-    // Extract Bearer token from Authorization header
-    let token = req
-        .headers()
-        .get("Authorization")
-        .and_then(|h| h.to_str().ok())
-        .and_then(|h| h.strip_prefix("Bearer "));
-
-    match token {
-        Some(t) if state.api_tokens.contains(&t.to_string()) => Ok(next.run(req).await),
+    match bearer_token(req.headers()) {
+        Some(t) if state.api_tokens.contains(t) => Ok(next.run(req).await),
+        // Search keys only open search and answers; handlers check their index and expiry
+        Some(t)
+            if search_keys::is_search_key(t)
+                && is_search_route(req.uri().path())
+                && search_keys::verify(t, &state.api_tokens).is_some() =>
+        {
+            Ok(next.run(req).await)
+        }
         _ => Err(StatusCode::UNAUTHORIZED),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_tokens_with_scopes() {
+        let tokens = ApiTokens::parse("plain, admin|internal|billing ,|orphan,");
+        assert_eq!(tokens.len(), 2);
+        assert!(tokens.scopes("plain").unwrap().is_empty());
+        let scopes = tokens.scopes("admin").unwrap();
+        assert!(scopes.contains("internal") && scopes.contains("billing"));
+        assert!(!tokens.contains("admin|internal|billing"));
+    }
+
+    #[test]
+    fn test_search_keys_only_open_search_routes() {
+        assert!(is_search_route("/indices/products/search"));
+        assert!(is_search_route("/indices/products/answer"));
+        assert!(!is_search_route("/indices/products/documents"));
+        assert!(!is_search_route("/indices/products/search/extra"));
+    }
+}
@@ -0,0 +1,214 @@
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::models::{ApiResponse, WriteQueueStats};
+use crate::AppState;
+
+/// Weight of the latest write in an index's average write latency
+const LATENCY_SMOOTHING: f64 = 0.2;
+
+/// Longest wait suggested by `Retry-After`
+const MAX_RETRY_AFTER_SECS: u64 = 60;
+
+/// Writes queued per index. Writes to an index take turns on its writers, so once
+/// ingestion falls behind, further writes are refused with 429 instead of queueing up
+/// and holding request threads that searches need.
+pub struct WriteQueues {
+    /// Writes an index runs or queues at once
+    max_pending: usize,
+    /// Average write latency at which writes are refused while others are queued
+    max_lag: Duration,
+    queues: Arc<Mutex<HashMap<String, WriteQueue>>>,
+}
+
+#[derive(Default)]
+struct WriteQueue {
+    pending: usize,
+    /// Moving average of the time from admitting a write to its response, queueing
+    /// included; 0 until the first write finishes
+    avg_latency_ms: f64,
+    rejected: u64,
+}
+
+impl WriteQueue {
+    /// Seconds until the queued writes are likely done, at the current latency
+    fn retry_after_secs(&self) -> u64 {
+        let secs = (self.avg_latency_ms * (self.pending + 1) as f64 / 1000.0).ceil() as u64;
+        secs.clamp(1, MAX_RETRY_AFTER_SECS)
+    }
+}
+
+/// A write refused because its index is saturated
+#[derive(Debug)]
+pub struct Saturated {
+    pub message: String,
+    pub retry_after_secs: u64,
+}
+
+impl WriteQueues {
+    /// Limits from `WRITE_QUEUE_MAX_PENDING` (default 16) and `WRITE_QUEUE_MAX_LAG_MS`
+    /// (default 10000)
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            std::env::var(name).ok().and_then(|v| v.trim().parse().ok())
+        }
+
+        Self::new(
+            var::<usize>("WRITE_QUEUE_MAX_PENDING").filter(|n| *n > 0).unwrap_or(16),
+            Duration::from_millis(var("WRITE_QUEUE_MAX_LAG_MS").unwrap_or(10_000)),
+        )
+    }
+
+    fn new(max_pending: usize, max_lag: Duration) -> Self {
+        Self {
+            max_pending,
+            max_lag,
+            queues: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Admit a write to an index until the permit is dropped, unless `max_pending`
+    /// writes are queued already, or writes are queued and take `max_lag` on average.
+    /// The first write is always admitted, so the average recovers once load drops.
+    pub fn try_acquire(&self, index_name: &str) -> Result<WritePermit, Saturated> {
+        let mut queues = self.queues.lock().unwrap();
+        let queue = queues.entry(index_name.to_string()).or_default();
+        let max_lag_ms = self.max_lag.as_secs_f64() * 1000.0;
+        let reason = if queue.pending >= self.max_pending {
+            Some(format!("{} writes already queued", queue.pending))
+        } else if queue.pending > 0 && queue.avg_latency_ms >= max_lag_ms {
+            Some(format!("writes take {:.0}ms", queue.avg_latency_ms))
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            queue.rejected += 1;
+            return Err(Saturated {
+                message: format!("Index '{}' is busy ({}); retry later", index_name, reason),
+                retry_after_secs: queue.retry_after_secs(),
+            });
+        }
+        queue.pending += 1;
+
+        Ok(WritePermit {
+            queues: self.queues.clone(),
+            index_name: index_name.to_string(),
+            started: Instant::now(),
+        })
+    }
+
+    pub fn stats(&self, index_name: &str) -> WriteQueueStats {
+        let queues = self.queues.lock().unwrap();
+        queues
+            .get(index_name)
+            .map(|queue| WriteQueueStats {
+                pending: queue.pending,
+                avg_latency_ms: queue.avg_latency_ms,
+                rejected: queue.rejected,
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// A write admitted to an index's queue, recording its latency when dropped
+pub struct WritePermit {
+    queues: Arc<Mutex<HashMap<String, WriteQueue>>>,
+    index_name: String,
+    started: Instant,
+}
+
+impl Drop for WritePermit {
+    fn drop(&mut self) {
+        let latency_ms = self.started.elapsed().as_secs_f64() * 1000.0;
+        let mut queues = self.queues.lock().unwrap();
+        if let Some(queue) = queues.get_mut(&self.index_name) {
+            queue.pending = queue.pending.saturating_sub(1);
+            queue.avg_latency_ms = if queue.avg_latency_ms == 0.0 {
+                latency_ms
+            } else {
+                queue.avg_latency_ms * (1.0 - LATENCY_SMOOTHING) + latency_ms * LATENCY_SMOOTHING
+            };
+        }
+    }
+}
+
+/// Refuse writes to a saturated index with 429 and `Retry-After`. The index is the
+/// `:name` of `/indices/:name/...`, resolved to the write index of a rolling index.
+pub async fn write_backpressure(
+    State(state): State<Arc<AppState>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(name) = req.uri().path().split('/').nth(2) else {
+        return next.run(req).await;
+    };
+    let index_name = state.search_engine.resolve_write_index(name);
+
+    match state.write_queues.try_acquire(&index_name) {
+        Ok(_permit) => next.run(req).await,
+        Err(saturated) => {
+            tracing::debug!("{}", saturated.message);
+            let mut response = (
+                StatusCode::TOO_MANY_REQUESTS,
+                Json(ApiResponse::<()>::error(saturated.message)),
+            )
+                .into_response();
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(saturated.retry_after_secs));
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refuses_writes_beyond_the_queue() {
+        let queues = WriteQueues::new(2, Duration::from_secs(10));
+        let first = queues.try_acquire("a").unwrap();
+        let _second = queues.try_acquire("a").unwrap();
+        let Err(refused) = queues.try_acquire("a") else {
+            panic!("a third write was admitted");
+        };
+        assert!(refused.retry_after_secs >= 1);
+        assert!(queues.try_acquire("b").is_ok());
+        assert_eq!(queues.stats("a").pending, 2);
+        assert_eq!(queues.stats("a").rejected, 1);
+
+        drop(first);
+        assert!(queues.try_acquire("a").is_ok());
+    }
+
+    #[test]
+    fn test_refuses_queued_writes_while_lagging() {
+        let queues = WriteQueues::new(10, Duration::ZERO);
+        // Nothing is queued, so a lagging index still takes one write at a time
+        let first = queues.try_acquire("a").unwrap();
+        drop(first);
+        let _running = queues.try_acquire("a").unwrap();
+        assert!(queues.try_acquire("a").is_err());
+    }
+
+    #[test]
+    fn test_suggests_waiting_for_the_queue() {
+        let queue = WriteQueue {
+            pending: 3,
+            avg_latency_ms: 1500.0,
+            rejected: 0,
+        };
+        assert_eq!(queue.retry_after_secs(), 6);
+        assert_eq!(WriteQueue::default().retry_after_secs(), 1);
+    }
+}
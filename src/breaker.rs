@@ -0,0 +1,160 @@
+use crate::geo;
+use crate::models::{AggregationRequest, MemoryEstimate};
+use crate::search::{DEFAULT_HISTOGRAM_INTERVAL, DEFAULT_TERMS_SIZE};
+
+/// Bytes held per returned hit, with its stored fields and highlights
+const HIT_BYTES: u64 = 4 * 1024;
+
+/// Bytes per candidate kept by the top documents collector of a segment
+const TOP_DOC_BYTES: u64 = 24;
+
+/// Bytes per aggregation bucket, with its key and counts
+const BUCKET_BYTES: u64 = 256;
+
+/// Bytes of a percentiles or cardinality sketch
+const SKETCH_BYTES: u64 = 32 * 1024;
+
+/// Bytes per value counted by a terms aggregation over stored values
+const STORED_VALUE_BYTES: u64 = 64;
+
+/// Geohash cells per precision level
+const GEOHASH_CELLS: u64 = 32;
+
+/// Memory for the hit window: every segment keeps its best `window` candidates, and
+/// up to `limit` of them are loaded as hits
+pub fn hits_bytes(window: usize, limit: usize, segments: usize, docs: u64) -> u64 {
+    let candidates = (window as u64).min(docs);
+    let hits = (limit as u64).min(docs);
+    candidates
+        .saturating_mul(segments as u64)
+        .saturating_mul(TOP_DOC_BYTES)
+        .saturating_add(hits.saturating_mul(HIT_BYTES))
+}
+
+/// Memory for wildcard and fuzzy terms, each of which collects its matches into a
+/// bitset over the documents of every segment, per searched field
+pub fn expansion_bytes(terms: usize, fields: usize, max_doc: u64) -> u64 {
+    (terms as u64)
+        .saturating_mul(fields as u64)
+        .saturating_mul(max_doc.div_ceil(8))
+}
+
+/// Buckets and memory of one aggregation over at most `docs` documents. A histogram's
+/// span is the distance between the smallest and largest value of its field, when the
+/// field has fast values; its empty buckets within the span are returned too.
+pub fn aggregation_cost(
+    agg: &AggregationRequest,
+    docs: u64,
+    histogram_span: Option<f64>,
+    stored_terms: bool,
+) -> (u64, u64) {
+    let buckets = match agg.agg_type.as_str() {
+        "terms" => agg.size.unwrap_or(DEFAULT_TERMS_SIZE) as u64,
+        "histogram" => {
+            let interval = agg.interval.unwrap_or(DEFAULT_HISTOGRAM_INTERVAL);
+            match histogram_span {
+                // The float to integer cast saturates on absurd spans
+                Some(span) if interval > 0.0 => {
+                    ((span / interval).floor() as u64).saturating_add(1)
+                }
+                _ => 1,
+            }
+        }
+        "range" => agg.ranges.as_ref().map_or(0, |ranges| ranges.len() as u64),
+        "geohash_grid" => {
+            let precision = agg
+                .precision
+                .unwrap_or(geo::DEFAULT_GEOHASH_PRECISION)
+                .clamp(1, geo::MAX_GEOHASH_PRECISION);
+            GEOHASH_CELLS.saturating_pow(precision as u32).min(docs)
+        }
+        _ => 0,
+    };
+    // percentile_ranks and weighted_avg read each match as it is found and keep only
+    // counts and sums, so they need nothing per document
+    let values = match agg.agg_type.as_str() {
+        "terms" if stored_terms => docs.saturating_mul(STORED_VALUE_BYTES),
+        "percentiles" | "cardinality" => SKETCH_BYTES,
+        _ => 0,
+    };
+    (buckets, buckets.saturating_mul(BUCKET_BYTES).saturating_add(values))
+}
+
+/// Why a search with this estimate must not run, if it exceeds its limits
+pub fn exceeded(estimate: &MemoryEstimate) -> Option<String> {
+    if estimate.buckets > estimate.max_buckets {
+        return Some(format!(
+            "Aggregations would create {} buckets (maximum {}); lower their size or use \
+             a wider interval",
+            estimate.buckets, estimate.max_buckets
+        ));
+    }
+    if estimate.estimated_bytes > estimate.max_bytes {
+        return Some(format!(
+            "Search would need an estimated {} of memory (maximum {}); lower the limit, \
+             offset or aggregation sizes, or use fewer wildcards",
+            format_bytes(estimate.estimated_bytes),
+            format_bytes(estimate.max_bytes)
+        ));
+    }
+    None
+}
+
+fn format_bytes(bytes: u64) -> String {
+    if bytes >= 1 << 30 {
+        format!("{:.1}GB", bytes as f64 / (1u64 << 30) as f64)
+    } else if bytes >= 1 << 20 {
+        format!("{:.1}MB", bytes as f64 / (1u64 << 20) as f64)
+    } else {
+        format!("{:.1}KB", bytes as f64 / 1024.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn agg(value: serde_json::Value) -> AggregationRequest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_counts_requested_and_spanned_buckets() {
+        let terms = agg(json!({
+            "name": "tags", "agg_type": "terms", "field": "tags", "size": 1_000_000
+        }));
+        assert_eq!(aggregation_cost(&terms, 100, None, false).0, 1_000_000);
+
+        let histogram = agg(json!({
+            "name": "prices", "agg_type": "histogram", "field": "price", "interval": 0.5
+        }));
+        assert_eq!(aggregation_cost(&histogram, 100, Some(100.0), false).0, 201);
+        assert_eq!(aggregation_cost(&histogram, 100, None, false).0, 1);
+
+        // There can't be more cells with documents than documents
+        let grid = agg(json!({
+            "name": "grid", "agg_type": "geohash_grid", "field": "location", "precision": 12
+        }));
+        assert_eq!(aggregation_cost(&grid, 5000, None, false).0, 5000);
+    }
+
+    #[test]
+    fn test_refuses_estimates_beyond_the_limits() {
+        let mut estimate = MemoryEstimate {
+            estimated_bytes: 1 << 20,
+            max_bytes: 256 << 20,
+            buckets: 10,
+            max_buckets: 65_536,
+            ..Default::default()
+        };
+        assert_eq!(exceeded(&estimate), None);
+
+        estimate.buckets = 1_000_000;
+        assert!(exceeded(&estimate).unwrap().contains("1000000 buckets"));
+
+        estimate.buckets = 10;
+        estimate.estimated_bytes = 512 << 20;
+        assert!(exceeded(&estimate).unwrap().contains("512.0MB"));
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+
+use crate::models::{AnswerSource, ChunkingOptions, Passage, SearchHit};
+
+/// Byte spans of the sentences in a text, split after `.`, `!` or `?` followed by
+/// whitespace and at line breaks
+pub fn sentence_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let next_is_space = chars.peek().is_none_or(|(_, next)| next.is_whitespace());
+        if c == '\n' || (matches!(c, '.' | '!' | '?') && next_is_space) {
+            let end = i + c.len_utf8();
+            spans.push((start, end));
+            start = end;
+        }
+    }
+    spans.push((start, text.len()));
+
+    // Trim surrounding whitespace and drop empty spans
+    spans
+        .into_iter()
+        .filter_map(|(start, end)| {
+            let sentence = &text[start..end];
+            let trimmed = sentence.trim();
+            if trimmed.is_empty() {
+                return None;
+            }
+            let offset = start + (sentence.len() - sentence.trim_start().len());
+            Some((offset, offset + trimmed.len()))
+        })
+        .collect()
+}
+
+/// Split a span longer than `max_chars` at word boundaries
+fn split_long_span(
+    text: &str,
+    (start, end): (usize, usize),
+    max_chars: usize,
+) -> Vec<(usize, usize)> {
+    if text[start..end].chars().count() <= max_chars {
+        return vec![(start, end)];
+    }
+
+    let mut pieces = Vec::new();
+    let mut piece_start = start;
+    let mut piece_end = start;
+    let mut word_start = None;
+    for (i, c) in text[start..end]
+        .char_indices()
+        .map(|(i, c)| (start + i, c))
+        .chain(std::iter::once((end, ' ')))
+    {
+        match (word_start, c.is_whitespace()) {
+            (None, false) => word_start = Some(i),
+            (Some(word), true) => {
+                if piece_end > piece_start && text[piece_start..i].chars().count() > max_chars {
+                    pieces.push((piece_start, piece_end));
+                    piece_start = word;
+                }
+                piece_end = i;
+                word_start = None;
+            }
+            _ => {}
+        }
+    }
+    pieces.push((piece_start, piece_end));
+    pieces
+}
+
+/// Split text into passages of at most `chunk_size` characters made of whole
+/// sentences, each repeating up to `overlap` characters of the previous passage
+pub fn chunk_text(text: &str, chunk_size: usize, overlap: usize) -> Vec<(usize, usize)> {
+    let pieces: Vec<(usize, usize)> = sentence_spans(text)
+        .into_iter()
+        .flat_map(|span| split_long_span(text, span, chunk_size))
+        .collect();
+    let chars = |start: usize, end: usize| text[start..end].chars().count();
+
+    let mut passages = Vec::new();
+    let mut i = 0;
+    while i < pieces.len() {
+        let start = pieces[i].0;
+        let mut j = i;
+        while j + 1 < pieces.len() && chars(start, pieces[j + 1].1) <= chunk_size {
+            j += 1;
+        }
+        let end = pieces[j].1;
+        passages.push((start, end));
+        if j + 1 >= pieces.len() {
+            break;
+        }
+
+        // Start the next passage with the trailing sentences that fit in the overlap
+        let mut next = j + 1;
+        while next > i + 1 && chars(pieces[next - 1].0, end) <= overlap {
+            next -= 1;
+        }
+        i = next;
+    }
+    passages
+}
+
+/// Lowercased words of a query, without operators and one-letter words
+fn query_terms(query: &str) -> Vec<String> {
+    query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 1)
+        .filter(|word| !matches!(*word, "AND" | "OR" | "NOT" | "TO"))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Rank a passage by the number of distinct query terms it contains, then by how
+/// often they occur
+fn passage_score(passage: &str, terms: &[String]) -> (usize, usize) {
+    let passage = passage.to_lowercase();
+    terms.iter().fold((0, 0), |(distinct, total), term| {
+        let count = passage.matches(term.as_str()).count();
+        (distinct + usize::from(count > 0), total + count)
+    })
+}
+
+/// Turn search hits into answer sources. Text fields longer than the chunk size are
+/// split into passages, and the passages that best match the query are returned as
+/// separate sources referencing their document; other fields are kept as they are.
+pub fn answer_sources(
+    hits: &[SearchHit],
+    query: &str,
+    options: &ChunkingOptions,
+) -> Vec<AnswerSource> {
+    let terms = query_terms(query);
+    let mut sources = Vec::new();
+
+    for hit in hits {
+        let is_long = |value: &serde_json::Value| {
+            value
+                .as_str()
+                .is_some_and(|text| text.chars().count() > options.chunk_size)
+        };
+        let fields: HashMap<String, serde_json::Value> = hit
+            .fields
+            .iter()
+            .filter(|(_, value)| !is_long(value))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+
+        let mut long_fields: Vec<(&String, &str)> = hit
+            .fields
+            .iter()
+            .filter(|(_, value)| is_long(value))
+            .filter_map(|(name, value)| value.as_str().map(|text| (name, text)))
+            .collect();
+        long_fields.sort_by_key(|(name, _)| *name);
+
+        let mut candidates = Vec::new();
+        for (field, text) in long_fields {
+            for (start, end) in chunk_text(text, options.chunk_size, options.chunk_overlap) {
+                let score = passage_score(&text[start..end], &terms);
+                candidates.push((score, field, text, start, end));
+            }
+        }
+
+        if candidates.is_empty() {
+            sources.push(AnswerSource {
+                id: hit.id.clone(),
+                score: hit.score,
+                fields,
+                passage: None,
+            });
+            continue;
+        }
+
+        // Best matching passages first; the sort is stable, so ties keep document order
+        candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.0));
+        for (_, field, text, start, end) in
+            candidates.into_iter().take(options.passages_per_document)
+        {
+            sources.push(AnswerSource {
+                id: hit.id.clone(),
+                score: hit.score,
+                fields: fields.clone(),
+                passage: Some(Passage {
+                    field: field.clone(),
+                    text: text[start..end].to_string(),
+                    start: text[..start].chars().count(),
+                    end: text[..end].chars().count(),
+                }),
+            });
+        }
+    }
+
+    sources
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_text_keeps_sentences_and_overlaps() {
+        let text = "First sentence here. Second one is here! Third? Fourth sentence ends.";
+        let passages: Vec<&str> = chunk_text(text, 45, 0)
+            .into_iter()
+            .map(|(start, end)| &text[start..end])
+            .collect();
+        assert_eq!(
+            passages,
+            vec![
+                "First sentence here. Second one is here!",
+                "Third? Fourth sentence ends.",
+            ]
+        );
+
+        // Overlap repeats the trailing sentences of the previous passage
+        let passages: Vec<&str> = chunk_text(text, 30, 25)
+            .into_iter()
+            .map(|(start, end)| &text[start..end])
+            .collect();
+        assert_eq!(
+            passages,
+            vec![
+                "First sentence here.",
+                "Second one is here! Third?",
+                "Third? Fourth sentence ends.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_long_sentences_split_at_words() {
+        let text = "alpha beta gamma delta epsilon";
+        let passages: Vec<&str> = chunk_text(text, 12, 0)
+            .into_iter()
+            .map(|(start, end)| &text[start..end])
+            .collect();
+        assert_eq!(passages, vec!["alpha beta", "gamma delta", "epsilon"]);
+    }
+}
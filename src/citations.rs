@@ -0,0 +1,89 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::models::{AnswerSource, Citation};
+
+/// Instruction added to the prompt so the model marks which sources it used
+pub const CITATION_INSTRUCTION: &str = "Cite the sources that support each statement by their \
+number in square brackets, e.g. [1] or [2][3]. Only cite numbers from the list of sources.";
+
+/// Citation markers like `[1]`, `[2][3]` (matched one at a time) or `[1, 4]`
+static MARKER_PATTERN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\[(\d+(?:\s*,\s*\d+)*)\]").expect("Invalid regex pattern"));
+
+/// Find citation markers in an answer and map them to the numbered sources.
+/// Numbers without a matching source are ignored.
+pub fn extract_citations(answer: &str, sources: &[AnswerSource]) -> Vec<Citation> {
+    let mut citations = Vec::new();
+    for captures in MARKER_PATTERN.captures_iter(answer) {
+        let marker = captures.get(0).unwrap();
+        let start = answer[..marker.start()].chars().count();
+        let end = start + marker.as_str().chars().count();
+
+        for number in captures[1].split(',') {
+            let Ok(number) = number.trim().parse::<usize>() else {
+                continue;
+            };
+            let Some(source) = number.checked_sub(1).and_then(|i| sources.get(i)) else {
+                continue;
+            };
+            citations.push(Citation {
+                marker: number,
+                id: source.id.clone(),
+                start,
+                end,
+                passage: source.passage.as_ref().map(|passage| passage.location()),
+            });
+        }
+    }
+    citations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Passage;
+    use std::collections::HashMap;
+
+    fn source(id: &str, passage: Option<Passage>) -> AnswerSource {
+        AnswerSource {
+            id: id.to_string(),
+            score: 1.0,
+            fields: HashMap::new(),
+            passage,
+        }
+    }
+
+    #[test]
+    fn test_extract_citations() {
+        let sources = vec![
+            source("a", None),
+            source(
+                "b",
+                Some(Passage {
+                    field: "content".to_string(),
+                    text: "...".to_string(),
+                    start: 100,
+                    end: 250,
+                }),
+            ),
+        ];
+        let answer = "Åpner kl. 7 [2]. Ligger i Bergen [1, 2][9].";
+        let citations = extract_citations(answer, &sources);
+
+        let summary: Vec<(usize, &str, usize, usize)> = citations
+            .iter()
+            .map(|c| (c.marker, c.id.as_str(), c.start, c.end))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![(2, "b", 12, 15), (1, "a", 33, 39), (2, "b", 33, 39)]
+        );
+        let passage = citations[0].passage.as_ref().unwrap();
+        assert_eq!(
+            (passage.field.as_str(), passage.start, passage.end),
+            ("content", 100, 250)
+        );
+        assert!(citations[1].passage.is_none());
+    }
+}
@@ -0,0 +1,138 @@
+use crate::models::{AnswerSource, ContextOptions};
+
+/// Field value as prompt text: strings as they are, other values as JSON
+fn value_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Array(values) => {
+            values.iter().map(value_text).collect::<Vec<_>>().join(", ")
+        }
+        other => other.to_string(),
+    }
+}
+
+/// Cut text to `max_chars` characters, marking the cut with an ellipsis
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// Prompt text for one source: a `name: value` line per included field, followed by
+/// the passage when the source is part of a long field
+fn source_text(source: &AnswerSource, options: &ContextOptions) -> String {
+    let included =
+        |name: &str| options.fields.is_empty() || options.fields.iter().any(|f| f == name);
+
+    let mut names: Vec<&String> = source.fields.keys().filter(|name| included(name)).collect();
+    if options.fields.is_empty() {
+        names.sort();
+    } else {
+        names.sort_by_key(|name| options.fields.iter().position(|f| f == *name));
+    }
+
+    let mut lines: Vec<String> = names
+        .into_iter()
+        .map(|name| format!("{}: {}", name, value_text(&source.fields[name])))
+        .collect();
+    if let Some(passage) = source.passage.as_ref().filter(|p| included(&p.field)) {
+        lines.push(format!("{}: {}", passage.field, passage.text));
+    }
+
+    let text = lines.join("\n");
+    match options.max_source_chars {
+        Some(max_chars) => truncate(&text, max_chars),
+        None => text,
+    }
+}
+
+/// Build the sources section of the answer prompt. Sources are numbered from 1 and added
+/// in order until `max_context_chars` is reached; returns the text and how many sources
+/// it includes. The first source is always included, truncated if needed.
+pub fn build_context(sources: &[AnswerSource], options: &ContextOptions) -> (String, usize) {
+    let mut blocks: Vec<String> = Vec::new();
+    let mut total = 0;
+    for (idx, source) in sources.iter().enumerate() {
+        let mut block = format!("[{}] {}", idx + 1, source_text(source, options));
+        let length = block.chars().count();
+        if let Some(max_chars) = options.max_context_chars {
+            if total + length > max_chars {
+                if blocks.is_empty() {
+                    block = truncate(&block, max_chars);
+                    blocks.push(block);
+                }
+                break;
+            }
+        }
+        total += length + 2;
+        blocks.push(block);
+    }
+
+    let count = blocks.len();
+    (blocks.join("\n\n"), count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Passage;
+    use std::collections::HashMap;
+
+    fn source(
+        id: &str,
+        fields: &[(&str, serde_json::Value)],
+        passage: Option<&str>,
+    ) -> AnswerSource {
+        AnswerSource {
+            id: id.to_string(),
+            score: 1.0,
+            fields: fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect::<HashMap<_, _>>(),
+            passage: passage.map(|text| Passage {
+                field: "content".to_string(),
+                text: text.to_string(),
+                start: 0,
+                end: text.chars().count(),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_build_context() {
+        let sources = vec![
+            source(
+                "a",
+                &[
+                    ("title", serde_json::json!("Lekeland")),
+                    ("internal_rank", serde_json::json!(3)),
+                    ("tags", serde_json::json!(["ute", "mat"])),
+                ],
+                Some("Åpent 7-17."),
+            ),
+            source("b", &[("title", serde_json::json!("Solsikken"))], None),
+        ];
+
+        let options = ContextOptions::default();
+        let (text, count) = build_context(&sources, &options);
+        assert_eq!(count, 2);
+        assert_eq!(
+            text,
+            "[1] internal_rank: 3\ntags: ute, mat\ntitle: Lekeland\ncontent: Åpent 7-17.\n\n\
+             [2] title: Solsikken"
+        );
+
+        let options = ContextOptions {
+            fields: vec!["title".to_string(), "content".to_string()],
+            max_source_chars: Some(20),
+            max_context_chars: Some(40),
+        };
+        let (text, count) = build_context(&sources, &options);
+        assert_eq!(count, 1);
+        assert_eq!(text, "[1] title: Lekeland\ncon…");
+    }
+}
@@ -0,0 +1,252 @@
+use axum::http::{HeaderName, HeaderValue, Method};
+use std::time::Duration;
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, Any, CorsLayer};
+
+/// Methods allowed for configured origins when CORS_ALLOW_METHODS is unset
+const DEFAULT_METHODS: [Method; 6] = [
+    Method::GET,
+    Method::POST,
+    Method::PUT,
+    Method::PATCH,
+    Method::DELETE,
+    Method::OPTIONS,
+];
+
+/// Headers allowed for configured origins when CORS_ALLOW_HEADERS is unset
+const DEFAULT_HEADERS: [HeaderName; 2] = [
+    axum::http::header::CONTENT_TYPE,
+    axum::http::header::AUTHORIZATION,
+];
+
+/// An allowed origin from CORS_ORIGINS
+#[derive(Debug, PartialEq)]
+enum OriginPattern {
+    /// `https://app.example.com`
+    Exact(String),
+    /// `https://*.example.com`, or `*.example.com` for any scheme
+    Subdomain {
+        scheme: Option<String>,
+        domain: String,
+    },
+}
+
+impl OriginPattern {
+    fn parse(value: &str) -> Option<Self> {
+        let value = value.trim().trim_end_matches('/').to_ascii_lowercase();
+        let (scheme, host) = match value.split_once("://") {
+            Some((scheme, host)) => (Some(scheme.to_string()), host),
+            None => (None, value.as_str()),
+        };
+        match host.strip_prefix("*.") {
+            Some(domain) if !domain.is_empty() && !domain.contains('*') => {
+                Some(OriginPattern::Subdomain {
+                    scheme,
+                    domain: domain.to_string(),
+                })
+            }
+            Some(_) => None,
+            None if value.is_empty() || value.contains('*') => None,
+            None => Some(OriginPattern::Exact(value)),
+        }
+    }
+
+    fn matches(&self, origin: &str) -> bool {
+        match self {
+            OriginPattern::Exact(allowed) => origin.eq_ignore_ascii_case(allowed),
+            OriginPattern::Subdomain { scheme, domain } => {
+                let origin = origin.to_ascii_lowercase();
+                let Some((origin_scheme, host)) = origin.split_once("://") else {
+                    return false;
+                };
+                if scheme.as_ref().is_some_and(|scheme| scheme != origin_scheme) {
+                    return false;
+                }
+                host.strip_suffix(domain.as_str())
+                    .and_then(|sub| sub.strip_suffix('.'))
+                    .is_some_and(|sub| !sub.is_empty())
+            }
+        }
+    }
+}
+
+/// Parse CORS_ORIGINS; `None` allows any origin
+fn parse_origins(value: &str) -> Option<Vec<OriginPattern>> {
+    if value.trim().is_empty() || value.trim() == "*" {
+        return None;
+    }
+    let patterns: Vec<OriginPattern> = value
+        .split(',')
+        .filter(|s| !s.trim().is_empty())
+        .filter_map(|s| {
+            let pattern = OriginPattern::parse(s);
+            if pattern.is_none() {
+                tracing::warn!("Ignoring invalid CORS origin: {}", s.trim());
+            }
+            pattern
+        })
+        .collect();
+    (!patterns.is_empty()).then_some(patterns)
+}
+
+/// Parse a comma-separated list; `None` for `*`
+fn parse_list<T>(value: &str, parse: impl Fn(&str) -> Option<T>) -> Option<Vec<T>> {
+    if value.trim() == "*" {
+        return None;
+    }
+    Some(
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| {
+                let parsed = parse(s);
+                if parsed.is_none() {
+                    tracing::warn!("Ignoring invalid CORS entry: {}", s);
+                }
+                parsed
+            })
+            .collect(),
+    )
+}
+
+/// Allowed methods and headers: a list, or `*` for any
+enum Allowed<T> {
+    Default,
+    Any,
+    List(Vec<T>),
+}
+
+impl<T> Allowed<T> {
+    fn from_env(name: &str, parse: impl Fn(&str) -> Option<T>) -> Self {
+        match std::env::var(name) {
+            Ok(value) if !value.trim().is_empty() => match parse_list(&value, parse) {
+                Some(list) => Allowed::List(list),
+                None => Allowed::Any,
+            },
+            _ => Allowed::Default,
+        }
+    }
+}
+
+/// CORS settings read from the environment
+struct CorsConfig {
+    /// `None` allows any origin
+    origins: Option<Vec<OriginPattern>>,
+    methods: Allowed<Method>,
+    headers: Allowed<HeaderName>,
+    allow_credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl CorsConfig {
+    fn from_env() -> Self {
+        Self {
+            origins: parse_origins(&std::env::var("CORS_ORIGINS").unwrap_or_default()),
+            methods: Allowed::from_env("CORS_ALLOW_METHODS", |s| {
+                Method::from_bytes(s.to_ascii_uppercase().as_bytes()).ok()
+            }),
+            headers: Allowed::from_env("CORS_ALLOW_HEADERS", |s| s.parse().ok()),
+            allow_credentials: std::env::var("CORS_ALLOW_CREDENTIALS")
+                .map(|v| matches!(v.trim().to_ascii_lowercase().as_str(), "true" | "1"))
+                .unwrap_or(false),
+            max_age: std::env::var("CORS_MAX_AGE_SECS")
+                .ok()
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .map(Duration::from_secs),
+        }
+    }
+}
+
+/// Build the CORS layer from CORS_ORIGINS, CORS_ALLOW_METHODS, CORS_ALLOW_HEADERS,
+/// CORS_ALLOW_CREDENTIALS and CORS_MAX_AGE_SECS
+pub fn build_cors_layer() -> CorsLayer {
+    let config = CorsConfig::from_env();
+
+    let mut layer = match config.origins {
+        None => {
+            tracing::warn!("CORS_ORIGINS not set or set to '*' - allowing all origins (not recommended for production)");
+            if config.allow_credentials {
+                tracing::warn!("CORS_ALLOW_CREDENTIALS ignored - it requires listed CORS_ORIGINS");
+            }
+            // Without listed origins, methods and headers default to any
+            let layer = CorsLayer::permissive();
+            let layer = match config.methods {
+                Allowed::List(methods) => layer.allow_methods(methods),
+                Allowed::Default | Allowed::Any => layer,
+            };
+            match config.headers {
+                Allowed::List(headers) => layer.allow_headers(headers),
+                Allowed::Default | Allowed::Any => layer,
+            }
+        }
+        Some(patterns) => {
+            tracing::info!("CORS configured for {} origin(s)", patterns.len());
+            let allow_origin = AllowOrigin::predicate(move |origin: &HeaderValue, _| {
+                origin
+                    .to_str()
+                    .is_ok_and(|origin| patterns.iter().any(|p| p.matches(origin)))
+            });
+
+            // Browsers reject `*` on credentialed requests, so echo the request instead
+            let allow_methods = match config.methods {
+                Allowed::Default => AllowMethods::list(DEFAULT_METHODS),
+                Allowed::List(methods) => AllowMethods::list(methods),
+                Allowed::Any if config.allow_credentials => AllowMethods::mirror_request(),
+                Allowed::Any => AllowMethods::any(),
+            };
+            let allow_headers = match config.headers {
+                Allowed::Default => AllowHeaders::list(DEFAULT_HEADERS),
+                Allowed::List(headers) => AllowHeaders::list(headers),
+                Allowed::Any if config.allow_credentials => AllowHeaders::mirror_request(),
+                Allowed::Any => AllowHeaders::from(Any),
+            };
+
+            CorsLayer::new()
+                .allow_origin(allow_origin)
+                .allow_methods(allow_methods)
+                .allow_headers(allow_headers)
+                .allow_credentials(config.allow_credentials)
+        }
+    };
+
+    if let Some(max_age) = config.max_age {
+        layer = layer.max_age(max_age);
+    }
+    layer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_exact_origins() {
+        let pattern = OriginPattern::parse("https://app.example.com/").unwrap();
+        assert!(pattern.matches("https://app.example.com"));
+        assert!(pattern.matches("https://APP.example.com"));
+        assert!(!pattern.matches("http://app.example.com"));
+    }
+
+    #[test]
+    fn test_matches_wildcard_subdomains() {
+        let pattern = OriginPattern::parse("https://*.example.com").unwrap();
+        assert!(pattern.matches("https://app.example.com"));
+        assert!(pattern.matches("https://a.b.example.com"));
+        assert!(!pattern.matches("https://example.com"));
+        assert!(!pattern.matches("https://badexample.com"));
+        assert!(!pattern.matches("http://app.example.com"));
+
+        let any_scheme = OriginPattern::parse("*.example.com").unwrap();
+        assert!(any_scheme.matches("http://app.example.com"));
+        assert!(!any_scheme.matches("https://app.example.com.evil.io"));
+    }
+
+    #[test]
+    fn test_parses_origin_lists() {
+        assert!(parse_origins("").is_none());
+        assert!(parse_origins("*").is_none());
+        assert!(parse_origins("https://*").is_none());
+        let origins = parse_origins("https://a.com, *.b.com,").unwrap();
+        assert_eq!(origins.len(), 2);
+    }
+}
@@ -0,0 +1,529 @@
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use reqwest::{Client, Url};
+use scraper::{Html, Node, Selector};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::models::{CrawlReport, CrawlerConfig, Document};
+use crate::validation::MAX_DOCUMENTS_PER_REQUEST;
+use crate::AppState;
+
+/// Product token the crawler identifies itself with, and matches robots.txt groups by
+const USER_AGENT_TOKEN: &str = "simple-search-service-crawler";
+
+const DEFAULT_CRAWL_DELAY_MS: u64 = 1000;
+
+/// Longest `Crawl-delay` of a robots.txt that is honoured
+const MAX_ROBOTS_CRAWL_DELAY: Duration = Duration::from_secs(30);
+
+/// Elements whose text is navigation or markup noise rather than page content
+const BOILERPLATE_ELEMENTS: &[&str] = &[
+    "script", "style", "noscript", "template", "svg", "nav", "header", "footer", "aside", "form",
+];
+
+/// Read the delay between requests to the same host from CRAWL_DELAY_MS
+pub fn crawl_delay_from_env() -> Duration {
+    let millis = std::env::var("CRAWL_DELAY_MS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_CRAWL_DELAY_MS);
+    Duration::from_millis(millis)
+}
+
+/// Fetches pages for registered crawlers
+pub struct Crawler {
+    http: Client,
+    /// Least time between two requests to the same host
+    delay: Duration,
+    /// Earliest time of the next request to each host, shared by concurrent crawls
+    next_request: Mutex<HashMap<String, Instant>>,
+}
+
+/// Rules of a robots.txt that apply to this crawler
+#[derive(Debug, Default)]
+struct RobotsRules {
+    /// Path pattern, its length and whether it allows
+    rules: Vec<(Regex, usize, bool)>,
+    crawl_delay: Option<Duration>,
+}
+
+impl RobotsRules {
+    /// Everything is disallowed, as when robots.txt can't be reached
+    fn disallow_all() -> Self {
+        Self {
+            rules: vec![(Regex::new("^").unwrap(), 1, false)],
+            crawl_delay: None,
+        }
+    }
+
+    /// Parse a robots.txt, keeping the groups naming this crawler, or the `*` groups
+    /// when none does
+    fn parse(body: &str) -> Self {
+        struct Group {
+            agents: Vec<String>,
+            rules: Vec<(String, bool)>,
+            crawl_delay: Option<f64>,
+        }
+
+        let mut groups: Vec<Group> = Vec::new();
+        let mut in_agents = false;
+        for line in body.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    // Consecutive user-agent lines share one group
+                    if !in_agents {
+                        groups.push(Group {
+                            agents: Vec::new(),
+                            rules: Vec::new(),
+                            crawl_delay: None,
+                        });
+                    }
+                    in_agents = true;
+                    if let Some(group) = groups.last_mut() {
+                        group.agents.push(value.to_ascii_lowercase());
+                    }
+                }
+                key @ ("allow" | "disallow" | "crawl-delay") => {
+                    in_agents = false;
+                    let Some(group) = groups.last_mut() else {
+                        continue;
+                    };
+                    match key {
+                        "crawl-delay" => group.crawl_delay = value.parse().ok(),
+                        // An empty disallow allows everything
+                        _ if value.is_empty() => {}
+                        _ => group.rules.push((value.to_string(), key == "allow")),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Agents are matched by their product token, without a version
+        let named = |agent: &str| agent.split('/').next() == Some(USER_AGENT_TOKEN);
+        let matching: Vec<&Group> = if groups.iter().any(|g| g.agents.iter().any(|a| named(a))) {
+            groups
+                .iter()
+                .filter(|g| g.agents.iter().any(|a| named(a)))
+                .collect()
+        } else {
+            groups.iter().filter(|g| g.agents.iter().any(|a| a == "*")).collect()
+        };
+
+        let mut robots = Self::default();
+        for group in matching {
+            for (pattern, allow) in &group.rules {
+                if let Some(regex) = robots_pattern(pattern) {
+                    robots.rules.push((regex, pattern.len(), *allow));
+                }
+            }
+            if let Some(delay) = group.crawl_delay.filter(|d| d.is_finite() && *d >= 0.0) {
+                robots.crawl_delay =
+                    Some(Duration::from_secs_f64(delay.min(MAX_ROBOTS_CRAWL_DELAY.as_secs_f64())));
+            }
+        }
+        robots
+    }
+
+    /// Whether a URL may be fetched: the longest matching rule decides, an allow
+    /// winning a tie
+    fn allows(&self, url: &Url) -> bool {
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        self.rules
+            .iter()
+            .filter(|(regex, ..)| regex.is_match(&path))
+            .max_by_key(|(_, length, allow)| (*length, *allow))
+            .map(|(.., allow)| *allow)
+            .unwrap_or(true)
+    }
+}
+
+/// Regex for a robots.txt path pattern, matching from the path's start, where `*`
+/// matches anything and a trailing `$` ends the path
+fn robots_pattern(pattern: &str) -> Option<Regex> {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let parts: Vec<String> = pattern.split('*').map(regex::escape).collect();
+    let end = if anchored { "$" } else { "" };
+    Regex::new(&format!("^{}{}", parts.join(".*"), end)).ok()
+}
+
+/// Text and links extracted from a single HTML page
+struct ParsedPage {
+    title: String,
+    content: String,
+    links: Vec<Url>,
+}
+
+/// Compiled include/exclude patterns for a crawler
+struct UrlFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl UrlFilter {
+    fn new(config: &CrawlerConfig) -> Result<Self> {
+        Ok(Self {
+            include: compile_patterns(&config.include_patterns)?,
+            exclude: compile_patterns(&config.exclude_patterns)?,
+        })
+    }
+
+    fn allows(&self, url: &Url) -> bool {
+        let url = url.as_str();
+        (self.include.is_empty() || self.include.iter().any(|r| r.is_match(url)))
+            && !self.exclude.iter().any(|r| r.is_match(url))
+    }
+}
+
+/// Compile regex patterns, reporting the first invalid one
+pub fn compile_patterns(patterns: &[String]) -> Result<Vec<Regex>> {
+    patterns
+        .iter()
+        .map(|p| Regex::new(p).map_err(|e| anyhow!("Invalid pattern '{}': {}", p, e)))
+        .collect()
+}
+
+impl Crawler {
+    pub fn new(delay: Duration) -> Result<Self> {
+        let http = Client::builder()
+            .timeout(Duration::from_secs(20))
+            .user_agent(format!("{}/{}", USER_AGENT_TOKEN, env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Ok(Self {
+            http,
+            delay,
+            next_request: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Wait until a request to `host` keeps `delay` from the one before it
+    async fn wait_turn(&self, host: &str, delay: Duration) {
+        let turn = {
+            let mut next_request = self.next_request.lock().unwrap();
+            let now = Instant::now();
+            next_request.retain(|_, next| *next > now);
+            let turn = next_request.get(host).copied().unwrap_or(now).max(now);
+            next_request.insert(host.to_string(), turn + delay);
+            turn
+        };
+        tokio::time::sleep_until(turn.into()).await;
+    }
+
+    /// Rules of the seed host's robots.txt. Without one everything is allowed; when it
+    /// can't be fetched, nothing is.
+    async fn robots(&self, seed: &Url, host: &str) -> Result<RobotsRules> {
+        let robots_url = seed.join("/robots.txt")?;
+        self.wait_turn(host, self.delay).await;
+        let response = match self.http.get(robots_url).send().await {
+            Ok(response) => response,
+            Err(e) => return Err(anyhow!("robots.txt unreachable: {}", e)),
+        };
+        let status = response.status();
+        if status.is_client_error() {
+            return Ok(RobotsRules::default());
+        }
+        if !status.is_success() {
+            return Err(anyhow!("robots.txt unreachable: {}", status));
+        }
+        Ok(RobotsRules::parse(&response.text().await?))
+    }
+
+    /// Breadth-first crawl from the seed URL, staying on the seed's host
+    pub async fn crawl(&self, config: &CrawlerConfig) -> Result<(Vec<Document>, CrawlReport)> {
+        let start = Instant::now();
+        let seed = Url::parse(&config.seed_url)?;
+        let host = seed
+            .host_str()
+            .ok_or_else(|| anyhow!("Seed URL has no host: {}", config.seed_url))?
+            .to_string();
+        let filter = UrlFilter::new(config)?;
+
+        let mut report = CrawlReport::default();
+        let robots = match self.robots(&seed, &host).await {
+            Ok(robots) => robots,
+            Err(e) => {
+                report.errors.push(e.to_string());
+                RobotsRules::disallow_all()
+            }
+        };
+        let delay = robots.crawl_delay.map_or(self.delay, |d| d.max(self.delay));
+        let mut documents = Vec::new();
+        let mut visited: HashSet<String> = HashSet::new();
+        let mut queue: VecDeque<(Url, usize)> = VecDeque::new();
+
+        visited.insert(seed.to_string());
+        queue.push_back((seed, 0));
+
+        while let Some((url, depth)) = queue.pop_front() {
+            if report.pages_fetched >= config.max_pages {
+                break;
+            }
+            if !robots.allows(&url) {
+                continue;
+            }
+
+            self.wait_turn(&host, delay).await;
+            let body = match self.fetch_html(&url).await {
+                Ok(Some(body)) => body,
+                Ok(None) => continue,
+                Err(e) => {
+                    report.errors.push(format!("{}: {}", url, e));
+                    continue;
+                }
+            };
+            report.pages_fetched += 1;
+
+            let page = parse_page(&url, &body);
+
+            if filter.allows(&url) && !page.content.is_empty() {
+                let mut fields = HashMap::new();
+                fields.insert("title".to_string(), serde_json::json!(page.title));
+                fields.insert("content".to_string(), serde_json::json!(page.content));
+                fields.insert("url".to_string(), serde_json::json!(url.as_str()));
+                documents.push(Document {
+                    id: url.to_string(),
+                    fields,
+                });
+            }
+
+            if depth >= config.max_depth {
+                continue;
+            }
+
+            for link in page.links {
+                if link.host_str() != Some(host.as_str()) || !filter.allows(&link) {
+                    continue;
+                }
+                if visited.insert(link.to_string()) {
+                    queue.push_back((link, depth + 1));
+                }
+            }
+        }
+
+        report.took_ms = start.elapsed().as_secs_f64() * 1000.0;
+        Ok((documents, report))
+    }
+
+    /// Fetch a page, returning `None` for non-HTML responses
+    async fn fetch_html(&self, url: &Url) -> Result<Option<String>> {
+        let response = self
+            .http
+            .get(url.clone())
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let is_html = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.contains("text/html"))
+            .unwrap_or(false);
+        if !is_html {
+            return Ok(None);
+        }
+
+        Ok(Some(response.text().await?))
+    }
+}
+
+/// Extract the title, main text content, and outgoing links from an HTML page
+fn parse_page(url: &Url, body: &str) -> ParsedPage {
+    let html = Html::parse_document(body);
+
+    let title_selector = Selector::parse("title, h1").unwrap();
+    let title = html
+        .select(&title_selector)
+        .map(|e| normalize_whitespace(&e.text().collect::<String>()))
+        .find(|t| !t.is_empty())
+        .unwrap_or_else(|| url.to_string());
+
+    // Prefer the main content region when the page marks one up
+    let root_selector = Selector::parse("main, article, [role=main], body").unwrap();
+    let content = html
+        .select(&root_selector)
+        .next()
+        .map(|root| {
+            let text: Vec<&str> = root
+                .descendants()
+                .filter_map(|node| match node.value() {
+                    Node::Text(text) => {
+                        let in_boilerplate = node.ancestors().any(|ancestor| {
+                            ancestor
+                                .value()
+                                .as_element()
+                                .map(|e| BOILERPLATE_ELEMENTS.contains(&e.name()))
+                                .unwrap_or(false)
+                        });
+                        (!in_boilerplate).then_some(&**text)
+                    }
+                    _ => None,
+                })
+                .collect();
+            normalize_whitespace(&text.join(" "))
+        })
+        .unwrap_or_default();
+
+    let link_selector = Selector::parse("a[href]").unwrap();
+    let links = html
+        .select(&link_selector)
+        .filter_map(|a| a.value().attr("href"))
+        .filter_map(|href| url.join(href).ok())
+        .filter(|link| matches!(link.scheme(), "http" | "https"))
+        .map(|mut link| {
+            link.set_fragment(None);
+            link
+        })
+        .collect();
+
+    ParsedPage {
+        title,
+        content,
+        links,
+    }
+}
+
+fn normalize_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Run a crawler, index the pages it found, and record the outcome
+pub async fn run_crawler(state: &AppState, config: &CrawlerConfig) -> Result<CrawlReport> {
+    let result = async {
+        let (documents, mut report) = state.crawler.crawl(config).await?;
+
+        let _permit = state.request_pools.indexing.acquire().await;
+        for chunk in documents.chunks(MAX_DOCUMENTS_PER_REQUEST) {
+            let outcome = state
+                .search_engine
+                .add_documents(&config.index_name, chunk)?;
+            state
+                .metadata_store
+                .apply_ingest_outcome(&config.index_name, &outcome)?;
+            report.documents_indexed += outcome.indexed_ids.len();
+        }
+
+        Ok::<_, anyhow::Error>(report)
+    }
+    .await;
+
+    let (status, message) = match &result {
+        Ok(report) => (
+            "success",
+            format!(
+                "Fetched {} page(s), indexed {} document(s), {} error(s)",
+                report.pages_fetched,
+                report.documents_indexed,
+                report.errors.len()
+            ),
+        ),
+        Err(e) => ("failed", e.to_string()),
+    };
+    if let Err(e) = state
+        .metadata_store
+        .record_crawl_run(&config.id, status, &message)
+    {
+        tracing::warn!("Failed to record crawl run for '{}': {}", config.id, e);
+    }
+
+    result
+}
+
+/// Whether a crawler with an interval is due to run again
+pub fn is_due(config: &CrawlerConfig, now: chrono::DateTime<chrono::Utc>) -> bool {
+    let Some(interval) = config.interval_seconds else {
+        return false;
+    };
+    config
+        .last_run_at
+        .as_deref()
+        .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+        .map(|last| (now - last.with_timezone(&chrono::Utc)).num_seconds() >= interval as i64)
+        .unwrap_or(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_robots_rules_pick_the_crawlers_group() {
+        let robots = RobotsRules::parse(
+            "User-agent: *\n\
+             Disallow: /\n\
+             \n\
+             User-agent: other-bot\n\
+             User-agent: simple-search-service-crawler/2.0\n\
+             Disallow: /private # staff only\n\
+             Allow: /private/docs\n\
+             Disallow: /*.pdf$\n\
+             Crawl-delay: 2\n",
+        );
+        let base = Url::parse("https://example.com").unwrap();
+        let allows = |path: &str| robots.allows(&base.join(path).unwrap());
+
+        assert!(allows("/"));
+        assert!(allows("/docs/intro"));
+        assert!(!allows("/private/notes"));
+        assert!(allows("/private/docs/guide"));
+        assert!(!allows("/files/report.pdf"));
+        assert!(allows("/files/report.pdf?page=2"));
+        assert_eq!(robots.crawl_delay, Some(Duration::from_secs(2)));
+
+        // Other crawlers get the `*` group
+        let robots = RobotsRules::parse("User-agent: *\nDisallow: /search\nDisallow:\n");
+        assert!(!robots.allows(&Url::parse("https://example.com/search?q=1").unwrap()));
+        assert!(robots.allows(&Url::parse("https://example.com/about").unwrap()));
+        assert!(!RobotsRules::disallow_all().allows(&Url::parse("https://example.com/").unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_requests_to_a_host_wait_for_the_delay() {
+        let crawler = Crawler::new(Duration::from_millis(50)).unwrap();
+        let start = Instant::now();
+        crawler.wait_turn("example.com", crawler.delay).await;
+        crawler.wait_turn("example.org", crawler.delay).await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+        crawler.wait_turn("example.com", crawler.delay).await;
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_crawler_is_due_after_its_interval() {
+        let now = chrono::Utc::now();
+        let mut config = CrawlerConfig {
+            id: "c".to_string(),
+            index_name: "site".to_string(),
+            seed_url: "https://example.com".to_string(),
+            max_depth: 1,
+            max_pages: 10,
+            include_patterns: Vec::new(),
+            exclude_patterns: Vec::new(),
+            interval_seconds: None,
+            created_at: now.to_rfc3339(),
+            last_run_at: None,
+            last_status: None,
+            last_message: None,
+        };
+        assert!(!is_due(&config, now));
+        config.interval_seconds = Some(60);
+        assert!(is_due(&config, now));
+        config.last_run_at = Some((now - chrono::Duration::seconds(30)).to_rfc3339());
+        assert!(!is_due(&config, now));
+        config.last_run_at = Some((now - chrono::Duration::seconds(60)).to_rfc3339());
+        assert!(is_due(&config, now));
+    }
+}
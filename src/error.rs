@@ -0,0 +1,71 @@
+use axum::http::StatusCode;
+use tantivy::query::QueryParserError;
+use tantivy::TantivyError;
+
+use crate::models::MemoryEstimate;
+
+/// Errors returned by the search engine, separating client mistakes from internal failures
+#[derive(Debug, thiserror::Error)]
+pub enum SearchError {
+    #[error("Index not found: {0}")]
+    IndexNotFound(String),
+    #[error("Index already exists: {0}")]
+    IndexExists(String),
+    /// The query, sort, or filter in the request cannot be executed against the index
+    #[error("{0}")]
+    InvalidQuery(String),
+    /// A document with the same ID already exists and the index rejects conflicts
+    #[error("{0}")]
+    Conflict(String),
+    /// The caller's API token doesn't grant access to a restricted field
+    #[error("Field '{0}' is restricted")]
+    RestrictedField(String),
+    /// A search key that wasn't signed by a configured token, or has expired
+    #[error("{0}")]
+    InvalidKey(String),
+    /// The caller's search key doesn't cover the index or route
+    #[error("{0}")]
+    KeyNotAllowed(String),
+    /// The index only accepts searches
+    #[error("Index is read-only: {0}")]
+    ReadOnly(String),
+    /// The request gave up waiting for the index, e.g. for a commit it asked to see, or ran
+    /// out of the time it was given, e.g. to match a regex
+    #[error("{0}")]
+    Timeout(String),
+    /// The search is estimated to need more memory or aggregation buckets than allowed
+    #[error("{0}")]
+    MemoryLimit(String, MemoryEstimate),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl SearchError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            SearchError::IndexNotFound(_) => StatusCode::NOT_FOUND,
+            SearchError::IndexExists(_) => StatusCode::CONFLICT,
+            SearchError::InvalidQuery(_) => StatusCode::BAD_REQUEST,
+            SearchError::Conflict(_) => StatusCode::CONFLICT,
+            SearchError::ReadOnly(_) => StatusCode::FORBIDDEN,
+            SearchError::RestrictedField(_) => StatusCode::FORBIDDEN,
+            SearchError::InvalidKey(_) => StatusCode::UNAUTHORIZED,
+            SearchError::KeyNotAllowed(_) => StatusCode::FORBIDDEN,
+            SearchError::Timeout(_) => StatusCode::SERVICE_UNAVAILABLE,
+            SearchError::MemoryLimit(..) => StatusCode::BAD_REQUEST,
+            SearchError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<QueryParserError> for SearchError {
+    fn from(e: QueryParserError) -> Self {
+        SearchError::InvalidQuery(format!("Invalid query: {}", e))
+    }
+}
+
+impl From<TantivyError> for SearchError {
+    fn from(e: TantivyError) -> Self {
+        SearchError::Internal(e.into())
+    }
+}
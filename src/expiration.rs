@@ -0,0 +1,56 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::AppState;
+
+/// Default interval between expiration sweeps (seconds)
+const DEFAULT_SWEEP_INTERVAL_SECS: u64 = 60;
+
+/// Read the sweep interval from TTL_SWEEP_INTERVAL_SECS
+pub fn sweep_interval_from_env() -> Duration {
+    let secs = std::env::var("TTL_SWEEP_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_SWEEP_INTERVAL_SECS);
+    Duration::from_secs(secs)
+}
+
+/// Periodically delete expired documents from every index and update metadata
+pub async fn run_sweeper(state: Arc<AppState>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+
+    loop {
+        ticker.tick().await;
+
+        // Closed indices are left closed until they are used
+        for index_name in state.search_engine.open_indices() {
+            match state.search_engine.delete_expired(&index_name) {
+                Ok(expired_ids) if !expired_ids.is_empty() => {
+                    for doc_id in &expired_ids {
+                        if let Err(e) = state.metadata_store.delete_document(&index_name, doc_id) {
+                            tracing::warn!(
+                                "Failed to remove metadata for expired document '{}': {}",
+                                doc_id,
+                                e
+                            );
+                        }
+                    }
+                    tracing::info!(
+                        "Deleted {} expired document(s) from index '{}'",
+                        expired_ids.len(),
+                        index_name
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::warn!(
+                        "Failed to sweep expired documents in index '{}': {}",
+                        index_name,
+                        e
+                    );
+                }
+            }
+        }
+    }
+}
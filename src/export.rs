@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tantivy::Searcher;
+
+use crate::error::SearchError;
+use crate::models::ExportPage;
+use crate::search::{SearchEngine, SnapshotCursor};
+
+/// An export in progress. Its searchers keep the segments of the snapshot readable,
+/// so documents added, updated or deleted meanwhile don't show up in later pages.
+struct ExportSnapshot {
+    /// Index, rolling index or pattern the export was started on
+    index: String,
+    /// Searchers of each index read, pinned when the export started
+    indices: Vec<(String, Vec<Searcher>)>,
+    /// Index being read, and the position in it
+    position: usize,
+    cursor: SnapshotCursor,
+    total: u64,
+    generation: Option<u64>,
+    expires_at: Instant,
+}
+
+/// Exports waiting for their next page, by cursor. Expired ones are dropped whenever
+/// an export is started or continued.
+#[derive(Default)]
+pub struct Exports {
+    snapshots: Mutex<HashMap<String, ExportSnapshot>>,
+}
+
+impl Exports {
+    /// Take a snapshot of an index and read its first page
+    pub fn start(
+        &self,
+        engine: &SearchEngine,
+        index_name: &str,
+        size: usize,
+        keep_alive: Duration,
+    ) -> Result<ExportPage, SearchError> {
+        // Read before pinning, since the searchers may see later commits but not earlier
+        let generation = engine.index_generation(index_name);
+        let indices = engine.pin_searchers(index_name)?;
+        let total = indices
+            .iter()
+            .flat_map(|(_, searchers)| searchers)
+            .map(|searcher| searcher.num_docs())
+            .sum();
+        let snapshot = ExportSnapshot {
+            index: index_name.to_string(),
+            indices,
+            position: 0,
+            cursor: SnapshotCursor::default(),
+            total,
+            generation,
+            expires_at: Instant::now() + keep_alive,
+        };
+        self.read_page(engine, uuid::Uuid::new_v4().to_string(), snapshot, size, keep_alive)
+    }
+
+    /// Read the next page of an export
+    pub fn next(
+        &self,
+        engine: &SearchEngine,
+        index_name: &str,
+        cursor: &str,
+        size: usize,
+        keep_alive: Duration,
+    ) -> Result<ExportPage, SearchError> {
+        // Taken out while the page is read, so the same page isn't read twice at once
+        let snapshot = self.take(index_name, cursor).ok_or_else(|| {
+            SearchError::InvalidQuery(format!("Export cursor not found or expired: {}", cursor))
+        })?;
+        self.read_page(engine, cursor.to_string(), snapshot, size, keep_alive)
+    }
+
+    /// Drop an export before it expires, returning false if there is none
+    pub fn release(&self, index_name: &str, cursor: &str) -> bool {
+        self.take(index_name, cursor).is_some()
+    }
+
+    fn take(&self, index_name: &str, cursor: &str) -> Option<ExportSnapshot> {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        Self::drop_expired(&mut snapshots);
+        // A cursor only continues the export of the index it was started on
+        if snapshots.get(cursor).is_none_or(|snapshot| snapshot.index != index_name) {
+            return None;
+        }
+        snapshots.remove(cursor)
+    }
+
+    fn read_page(
+        &self,
+        engine: &SearchEngine,
+        cursor: String,
+        mut snapshot: ExportSnapshot,
+        size: usize,
+        keep_alive: Duration,
+    ) -> Result<ExportPage, SearchError> {
+        let mut documents = Vec::new();
+        while let Some((name, searchers)) = snapshot.indices.get(snapshot.position) {
+            let (page, next) = engine.snapshot_documents(
+                name,
+                searchers,
+                snapshot.cursor,
+                size - documents.len(),
+            )?;
+            documents.extend(page);
+            match next {
+                Some(next) => {
+                    snapshot.cursor = next;
+                    break;
+                }
+                None => {
+                    snapshot.position += 1;
+                    snapshot.cursor = SnapshotCursor::default();
+                }
+            }
+        }
+
+        let done = snapshot.position == snapshot.indices.len();
+        let page = ExportPage {
+            documents,
+            total: snapshot.total,
+            generation: snapshot.generation,
+            cursor: (!done).then(|| cursor.clone()),
+        };
+        if !done {
+            let mut snapshots = self.snapshots.lock().unwrap();
+            Self::drop_expired(&mut snapshots);
+            snapshot.expires_at = Instant::now() + keep_alive;
+            snapshots.insert(cursor, snapshot);
+        }
+        Ok(page)
+    }
+
+    fn drop_expired(snapshots: &mut HashMap<String, ExportSnapshot>) {
+        let now = Instant::now();
+        snapshots.retain(|_, snapshot| snapshot.expires_at > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter_cache::FilterCache;
+    use crate::models::{Document, FieldConfig, IndexSettings};
+    use crate::search::QueryLimits;
+
+    fn documents(ids: &[&str]) -> Vec<Document> {
+        ids.iter()
+            .map(|id| Document {
+                id: id.to_string(),
+                fields: HashMap::from([("title".to_string(), serde_json::json!(id))]),
+            })
+            .collect()
+    }
+
+    fn ids(page: &ExportPage) -> Vec<&str> {
+        page.documents.iter().map(|doc| doc.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_export_pages_read_the_snapshot() {
+        let path = std::env::temp_dir().join(format!("export-test-{}", uuid::Uuid::new_v4()));
+        let engine = SearchEngine::new(
+            path.to_str().unwrap(),
+            1,
+            QueryLimits::default(),
+            FilterCache::from_env(),
+        )
+        .unwrap();
+        let fields: Vec<FieldConfig> =
+            serde_json::from_value(serde_json::json!([{"name": "title", "stored": true}]))
+                .unwrap();
+        engine.create_index("docs", &fields, &IndexSettings::default(), 1).unwrap();
+        engine.add_documents("docs", &documents(&["1", "2", "3"])).unwrap();
+        let exports = Exports::default();
+        let keep_alive = Duration::from_secs(60);
+
+        let first = exports.start(&engine, "docs", 2, keep_alive).unwrap();
+        assert_eq!((first.total, first.documents.len()), (3, 2));
+        assert_eq!(first.generation, engine.index_generation("docs"));
+        let cursor = first.cursor.clone().unwrap();
+
+        // Writes after the export started don't show up in its pages
+        engine.add_documents("docs", &documents(&["4"])).unwrap();
+        engine.delete_document("docs", "3").unwrap();
+        let other = exports.next(&engine, "other", &cursor, 2, keep_alive);
+        assert!(matches!(other, Err(SearchError::InvalidQuery(_))));
+        let second = exports.next(&engine, "docs", &cursor, 2, keep_alive).unwrap();
+        assert!(second.cursor.is_none());
+        let mut exported = [ids(&first), ids(&second)].concat();
+        exported.sort();
+        assert_eq!(exported, ["1", "2", "3"]);
+        // A finished export can't be continued
+        assert!(exports.next(&engine, "docs", &cursor, 2, keep_alive).is_err());
+
+        let released = exports.start(&engine, "docs", 1, keep_alive).unwrap();
+        assert!(exports.release("docs", released.cursor.as_deref().unwrap()));
+        let expiring = exports.start(&engine, "docs", 1, Duration::ZERO).unwrap();
+        let cursor = expiring.cursor.unwrap();
+        let expired = exports.next(&engine, "docs", &cursor, 1, keep_alive);
+        assert!(matches!(expired, Err(SearchError::InvalidQuery(_))));
+        let _ = std::fs::remove_dir_all(&path);
+    }
+}
@@ -0,0 +1,218 @@
+use anyhow::{anyhow, Result};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::io::{Cursor, Read};
+
+/// Supported attachment formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    Pdf,
+    Docx,
+    Text,
+}
+
+impl FileKind {
+    /// Determine the file format from its MIME type, falling back to the file extension
+    pub fn detect(filename: &str, mime_type: Option<&str>) -> Option<Self> {
+        match mime_type.map(|m| m.split(';').next().unwrap_or(m).trim()) {
+            Some("application/pdf") => return Some(Self::Pdf),
+            Some("application/vnd.openxmlformats-officedocument.wordprocessingml.document") => {
+                return Some(Self::Docx)
+            }
+            Some("text/plain") | Some("text/markdown") => return Some(Self::Text),
+            _ => {}
+        }
+
+        let extension = filename.rsplit_once('.')?.1.to_lowercase();
+        match extension.as_str() {
+            "pdf" => Some(Self::Pdf),
+            "docx" => Some(Self::Docx),
+            "txt" | "md" | "text" => Some(Self::Text),
+            _ => None,
+        }
+    }
+
+    /// Canonical MIME type for the format
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Pdf => "application/pdf",
+            Self::Docx => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+            Self::Text => "text/plain",
+        }
+    }
+}
+
+/// Extract plain text from an uploaded file
+pub fn extract_text(kind: FileKind, bytes: &[u8]) -> Result<String> {
+    let text = match kind {
+        FileKind::Pdf => extract_pdf(bytes)?,
+        FileKind::Docx => extract_docx(bytes)?,
+        FileKind::Text => String::from_utf8_lossy(bytes).into_owned(),
+    };
+    Ok(text.trim().to_string())
+}
+
+fn extract_pdf(bytes: &[u8]) -> Result<String> {
+    // The PDF parser panics on some malformed input, so treat panics as extraction errors
+    std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem(bytes))
+        .map_err(|_| anyhow!("Failed to parse PDF"))?
+        .map_err(|e| anyhow!("Failed to extract PDF text: {}", e))
+}
+
+fn extract_docx(bytes: &[u8]) -> Result<String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))
+        .map_err(|e| anyhow!("Invalid DOCX archive: {}", e))?;
+    let mut xml = String::new();
+    archive
+        .by_name("word/document.xml")
+        .map_err(|_| anyhow!("DOCX file has no word/document.xml"))?
+        .read_to_string(&mut xml)?;
+
+    let mut reader = Reader::from_str(&xml);
+    let mut text = String::new();
+    let mut in_text_run = false;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) if e.name().as_ref() == b"w:t" => in_text_run = true,
+            Ok(Event::End(e)) => match e.name().as_ref() {
+                b"w:t" => in_text_run = false,
+                b"w:p" => text.push('\n'),
+                _ => {}
+            },
+            Ok(Event::Empty(e)) => match e.name().as_ref() {
+                b"w:tab" => text.push('\t'),
+                b"w:br" => text.push('\n'),
+                _ => {}
+            },
+            Ok(Event::Text(e)) if in_text_run => {
+                text.push_str(
+                    &e.unescape()
+                        .map_err(|e| anyhow!("Invalid DOCX text: {}", e))?,
+                );
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(anyhow!("Invalid DOCX XML: {}", e)),
+            _ => {}
+        }
+    }
+
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_detect_file_kind() {
+        assert_eq!(FileKind::detect("report.PDF", None), Some(FileKind::Pdf));
+        assert_eq!(
+            FileKind::detect("notes", Some("text/plain; charset=utf-8")),
+            Some(FileKind::Text)
+        );
+        assert_eq!(
+            FileKind::detect("letter.docx", Some("application/octet-stream")),
+            Some(FileKind::Docx)
+        );
+        assert_eq!(FileKind::detect("image.png", Some("image/png")), None);
+    }
+
+    #[test]
+    fn test_extract_docx() {
+        let mut buffer = Cursor::new(Vec::new());
+        {
+            let mut writer = zip::ZipWriter::new(&mut buffer);
+            writer
+                .start_file(
+                    "word/document.xml",
+                    zip::write::SimpleFileOptions::default(),
+                )
+                .unwrap();
+            writer
+                .write_all(
+                    br#"<w:document><w:body><w:p><w:r><w:t>Hello</w:t></w:r><w:r><w:tab/><w:t>world &amp; co</w:t></w:r></w:p><w:p><w:r><w:t>Second</w:t></w:r></w:p></w:body></w:document>"#,
+                )
+                .unwrap();
+            writer.finish().unwrap();
+        }
+
+        let text = extract_text(FileKind::Docx, buffer.get_ref()).unwrap();
+        assert_eq!(text, "Hello\tworld & co\nSecond");
+    }
+
+    /// DOCX archive holding `document` as its word/document.xml
+    fn docx(document: &str) -> Vec<u8> {
+        let mut buffer = Cursor::new(Vec::new());
+        let mut writer = zip::ZipWriter::new(&mut buffer);
+        writer
+            .start_file("word/document.xml", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(document.as_bytes()).unwrap();
+        writer.finish().unwrap();
+        buffer.into_inner()
+    }
+
+    #[test]
+    fn test_detect_prefers_the_mime_type_over_the_extension() {
+        let docx_mime = FileKind::Docx.mime_type();
+        assert_eq!(FileKind::detect("scan.txt", Some("application/pdf")), Some(FileKind::Pdf));
+        assert_eq!(FileKind::detect("upload", Some(docx_mime)), Some(FileKind::Docx));
+        assert_eq!(FileKind::detect("README", Some("text/markdown")), Some(FileKind::Text));
+        // Unknown or generic MIME types fall back to the extension
+        let unknown = Some("application/x-unknown");
+        assert_eq!(FileKind::detect("notes.md", unknown), Some(FileKind::Text));
+        assert_eq!(FileKind::detect("Letter.DOCX", None), Some(FileKind::Docx));
+        assert_eq!(FileKind::detect("archive.tar.text", None), Some(FileKind::Text));
+        assert_eq!(FileKind::detect("upload", None), None);
+        assert_eq!(FileKind::detect("photo.jpg", Some("application/octet-stream")), None);
+        for kind in [FileKind::Pdf, FileKind::Docx, FileKind::Text] {
+            assert_eq!(FileKind::detect("upload", Some(kind.mime_type())), Some(kind));
+        }
+    }
+
+    #[test]
+    fn test_extract_docx_with_nested_runs() {
+        let document = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+  <w:body>
+    <w:p>
+      <w:pPr><w:pStyle w:val="Title"/></w:pPr>
+      <w:r><w:rPr><w:b/></w:rPr><w:t>Annual</w:t></w:r>
+      <w:r><w:t xml:space="preserve"> report</w:t></w:r>
+    </w:p>
+    <w:p>
+      <w:hyperlink w:history="1"><w:r><w:t>See</w:t></w:r></w:hyperlink>
+      <w:ins w:author="Ada"><w:r><w:t xml:space="preserve"> the </w:t></w:r></w:ins>
+      <w:smartTag><w:r><w:t>appendix</w:t><w:br/><w:t>below</w:t></w:r></w:smartTag>
+    </w:p>
+    <w:tbl><w:tr><w:tc><w:p><w:r><w:t>Cell</w:t></w:r></w:p></w:tc></w:tr></w:tbl>
+    <w:p><w:r><w:instrText>PAGE</w:instrText></w:r></w:p>
+  </w:body>
+</w:document>"#;
+        let text = extract_text(FileKind::Docx, &docx(document)).unwrap();
+        assert_eq!(text, "Annual report\nSee the appendix\nbelow\nCell");
+    }
+
+    #[test]
+    fn test_extraction_failures() {
+        let not_a_zip = extract_text(FileKind::Docx, b"plain text").unwrap_err();
+        assert!(not_a_zip.to_string().starts_with("Invalid DOCX archive"), "{not_a_zip}");
+
+        let mut buffer = Cursor::new(Vec::new());
+        let mut writer = zip::ZipWriter::new(&mut buffer);
+        writer.start_file("other.xml", zip::write::SimpleFileOptions::default()).unwrap();
+        writer.finish().unwrap();
+        let missing = extract_text(FileKind::Docx, buffer.get_ref()).unwrap_err();
+        assert_eq!(missing.to_string(), "DOCX file has no word/document.xml");
+
+        let broken = extract_text(FileKind::Docx, &docx("<w:p><w:t>a</w:p>")).unwrap_err();
+        assert!(broken.to_string().starts_with("Invalid DOCX XML"), "{broken}");
+        assert!(extract_text(FileKind::Pdf, b"%PDF-1.4 not really").is_err());
+
+        // Text is read as UTF-8, replacing what isn't
+        let text = extract_text(FileKind::Text, b"  caf\xc3\xa9 \xff\n").unwrap();
+        assert_eq!(text, "caf\u{e9} \u{fffd}");
+    }
+}
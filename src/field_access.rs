@@ -0,0 +1,125 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+
+use crate::language;
+use crate::models::SearchRequest;
+
+/// `field:` and `_exists_:field` references in a query string
+static FIELD_REFERENCE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?:_exists_:\s*([A-Za-z_][\w.]*))|([A-Za-z_][\w.]*)\s*:")
+        .expect("Invalid regex pattern")
+});
+
+/// What a caller may see: restricted fields hidden from it, and the filters of its
+/// search key that every hit must match
+#[derive(Debug, Default, Clone)]
+pub struct Access {
+    pub hidden_fields: Vec<String>,
+    pub filters: Vec<String>,
+}
+
+/// Restricted fields (field -> scope) that a caller holding `scopes` may not see
+pub fn hidden_fields(
+    restricted: &HashMap<String, String>,
+    scopes: Option<&HashSet<String>>,
+) -> Vec<String> {
+    let mut hidden: Vec<String> = restricted
+        .iter()
+        .filter(|(_, scope)| !scopes.is_some_and(|scopes| scopes.contains(*scope)))
+        .map(|(field, _)| field.clone())
+        .collect();
+    hidden.sort();
+    hidden
+}
+
+/// Whether `name` is a hidden field, a JSON sub-path of one or one of its language
+/// sub-fields
+pub fn is_hidden(name: &str, hidden: &[String]) -> bool {
+    let base = language::parse_sub_field_name(name).map_or(name, |(base, _)| base);
+    hidden.iter().any(|field| {
+        base == field
+            || base
+                .strip_prefix(field.as_str())
+                .is_some_and(|rest| rest.starts_with('.'))
+    })
+}
+
+/// First hidden field referenced with `field:` syntax in a query string
+pub fn hidden_query_reference(query: &str, hidden: &[String]) -> Option<String> {
+    if hidden.is_empty() {
+        return None;
+    }
+    FIELD_REFERENCE
+        .captures_iter(query)
+        .filter_map(|captures| captures.get(1).or_else(|| captures.get(2)))
+        .map(|name| name.as_str())
+        .find(|name| is_hidden(name, hidden))
+        .map(str::to_string)
+}
+
+/// First hidden field a search request refers to, outside of its query strings
+pub fn hidden_request_field(request: &SearchRequest, hidden: &[String]) -> Option<String> {
+    if hidden.is_empty() {
+        return None;
+    }
+    let mut names: Vec<&String> = Vec::new();
+    names.extend(&request.fields);
+    names.extend(request.boost.keys());
+    names.extend(request.sort.as_ref().map(|sort| &sort.field));
+    if let Some(highlight) = &request.highlight {
+        names.extend(&highlight.fields);
+    }
+    for agg in &request.aggregations {
+        names.push(&agg.field);
+        names.extend(&agg.weight_field);
+    }
+    names.extend(request.geo_filters.iter().map(|filter| &filter.field));
+    names.extend(&request.include_fields);
+    names.extend(request.field_mapping.keys());
+    names.extend(request.lookups.iter().map(|lookup| &lookup.field));
+    names.extend(&request.columns);
+    names.extend(request.regex.as_ref().map(|regex| &regex.field));
+
+    names
+        .into_iter()
+        .find(|name| is_hidden(name, hidden))
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hidden() -> Vec<String> {
+        vec!["notes".to_string(), "meta".to_string()]
+    }
+
+    #[test]
+    fn test_hides_fields_without_a_matching_scope() {
+        let restricted = HashMap::from([
+            ("notes".to_string(), "internal".to_string()),
+            ("salary".to_string(), "hr".to_string()),
+        ]);
+        let scopes = HashSet::from(["internal".to_string()]);
+        assert_eq!(hidden_fields(&restricted, Some(&scopes)), vec!["salary"]);
+        assert_eq!(hidden_fields(&restricted, None), vec!["notes", "salary"]);
+    }
+
+    #[test]
+    fn test_matches_sub_paths_and_language_sub_fields() {
+        assert!(is_hidden("notes", &hidden()));
+        assert!(is_hidden("notes__en", &hidden()));
+        assert!(is_hidden("meta.author", &hidden()));
+        assert!(!is_hidden("notes_public", &hidden()));
+        assert!(!is_hidden("metadata", &hidden()));
+    }
+
+    #[test]
+    fn test_finds_field_references_in_queries() {
+        assert_eq!(hidden_query_reference("title:a AND notes:b", &hidden()), Some("notes".into()));
+        assert_eq!(hidden_query_reference("_exists_:notes", &hidden()), Some("notes".into()));
+        assert_eq!(hidden_query_reference("(meta.x : y)", &hidden()), Some("meta.x".into()));
+        assert_eq!(hidden_query_reference("notes about title:a", &hidden()), None);
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use tantivy::index::SegmentId;
+use tantivy::query::{EnableScoring, Explanation, Query, Scorer, Weight};
+use tantivy::{DocId, DocSet, Score, Searcher, SegmentReader, Term, TERMINATED};
+
+use crate::models::FilterCacheStats;
+use crate::validation;
+
+/// Default memory budget of the filter cache (64MB)
+const DEFAULT_FILTER_CACHE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Documents of one segment matching a filter, one bit per document
+struct FilterBits {
+    words: Vec<u64>,
+}
+
+impl FilterBits {
+    fn new(max_doc: u32) -> Self {
+        Self {
+            words: vec![0; (max_doc as usize).div_ceil(64)],
+        }
+    }
+
+    fn insert(&mut self, doc: DocId) {
+        self.words[doc as usize / 64] |= 1 << (doc % 64);
+    }
+
+    /// First document at or after `from`, or `TERMINATED`
+    fn next_from(&self, from: DocId) -> DocId {
+        let mut word_index = from as usize / 64;
+        let Some(first) = self.words.get(word_index) else {
+            return TERMINATED;
+        };
+        let mut word = first & (u64::MAX << (from % 64));
+        loop {
+            if word != 0 {
+                return (word_index * 64) as DocId + word.trailing_zeros();
+            }
+            word_index += 1;
+            match self.words.get(word_index) {
+                Some(next) => word = *next,
+                None => return TERMINATED,
+            }
+        }
+    }
+
+    fn len(&self) -> u32 {
+        self.words.iter().map(|word| word.count_ones()).sum()
+    }
+
+    fn size_bytes(&self) -> usize {
+        self.words.len() * std::mem::size_of::<u64>()
+    }
+}
+
+/// A filter evaluated on one segment of an index. Segments never change once written
+/// (deletes are applied separately), so the bits stay valid until the segment is merged
+/// away.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    index: String,
+    segment: SegmentId,
+    /// Debug form of the filter's query, which names its fields and terms
+    filter: String,
+}
+
+struct CacheEntry {
+    bits: Arc<FilterBits>,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<CacheKey, CacheEntry>,
+    size_bytes: usize,
+    /// Increases on every lookup, so the least recently used entry can be evicted
+    clock: u64,
+    /// Hits and misses per index
+    lookups: HashMap<String, (u64, u64)>,
+}
+
+/// Filters (stored filters and search key filters) evaluated per segment, so repeated
+/// filter combinations skip re-running the same queries on every search
+pub struct FilterCache {
+    capacity_bytes: usize,
+    state: Mutex<CacheState>,
+}
+
+impl FilterCache {
+    /// Cache from `FILTER_CACHE_SIZE`, in bytes or with a `KB`, `MB` or `GB` suffix
+    /// (default: 64MB, 0 disables caching)
+    pub fn from_env() -> Self {
+        let capacity_bytes = match std::env::var("FILTER_CACHE_SIZE") {
+            Ok(value) if value.trim() == "0" => 0,
+            Ok(value) => validation::parse_byte_size(&value).unwrap_or_else(|| {
+                tracing::warn!(
+                    "Invalid FILTER_CACHE_SIZE: {}, using {} bytes",
+                    value,
+                    DEFAULT_FILTER_CACHE_SIZE
+                );
+                DEFAULT_FILTER_CACHE_SIZE
+            }),
+            Err(_) => DEFAULT_FILTER_CACHE_SIZE,
+        };
+        Self::with_capacity(capacity_bytes)
+    }
+
+    fn with_capacity(capacity_bytes: usize) -> Self {
+        Self {
+            capacity_bytes,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    fn get(&self, key: &CacheKey) -> Option<Arc<FilterBits>> {
+        let mut state = self.state.lock().unwrap();
+        state.clock += 1;
+        let clock = state.clock;
+        let bits = state.entries.get_mut(key).map(|entry| {
+            entry.last_used = clock;
+            entry.bits.clone()
+        });
+        let lookups = state.lookups.entry(key.index.clone()).or_default();
+        match bits {
+            Some(_) => lookups.0 += 1,
+            None => lookups.1 += 1,
+        }
+        bits
+    }
+
+    fn insert(&self, key: CacheKey, bits: Arc<FilterBits>) {
+        let size = bits.size_bytes();
+        if size > self.capacity_bytes {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        while state.size_bytes + size > self.capacity_bytes {
+            let Some(oldest) = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            if let Some(entry) = state.entries.remove(&oldest) {
+                state.size_bytes -= entry.bits.size_bytes();
+            }
+        }
+        let last_used = state.clock;
+        if let Some(replaced) = state.entries.insert(key, CacheEntry { bits, last_used }) {
+            state.size_bytes -= replaced.bits.size_bytes();
+        }
+        state.size_bytes += size;
+    }
+
+    /// Wrap a filter of an index so its matches are cached per segment. The filter is
+    /// returned as is while caching is disabled.
+    pub fn wrap(self: &Arc<Self>, index_name: &str, filter: Box<dyn Query>) -> Box<dyn Query> {
+        if self.capacity_bytes == 0 {
+            return filter;
+        }
+        Box::new(CachedFilter {
+            index: index_name.to_string(),
+            key: format!("{:?}", filter),
+            query: filter,
+            cache: self.clone(),
+        })
+    }
+
+    /// Drop the entries of an index's segments that its searchers no longer see, after
+    /// commits and merges replaced them
+    pub fn retain_segments(&self, index_name: &str, searchers: &[Searcher]) {
+        let live: HashSet<SegmentId> = searchers
+            .iter()
+            .flat_map(Searcher::segment_readers)
+            .map(SegmentReader::segment_id)
+            .collect();
+        self.remove_where(|key| key.index == index_name && !live.contains(&key.segment));
+    }
+
+    /// Drop everything cached for an index, e.g. when it is deleted or closed
+    pub fn invalidate(&self, index_name: &str) {
+        self.remove_where(|key| key.index == index_name);
+        self.state.lock().unwrap().lookups.remove(index_name);
+    }
+
+    fn remove_where(&self, remove: impl Fn(&CacheKey) -> bool) {
+        let mut state = self.state.lock().unwrap();
+        let mut freed = 0;
+        state.entries.retain(|key, entry| {
+            let keep = !remove(key);
+            if !keep {
+                freed += entry.bits.size_bytes();
+            }
+            keep
+        });
+        state.size_bytes -= freed;
+    }
+
+    /// Entries, memory and hit rate of an index's cached filters
+    pub fn stats(&self, index_name: &str) -> FilterCacheStats {
+        let state = self.state.lock().unwrap();
+        let (entries, size_bytes) = state
+            .entries
+            .iter()
+            .filter(|(key, _)| key.index == index_name)
+            .fold((0, 0), |(count, size), (_, entry)| {
+                (count + 1, size + entry.bits.size_bytes())
+            });
+        let (hits, misses) = state.lookups.get(index_name).copied().unwrap_or_default();
+        FilterCacheStats {
+            entries,
+            size_bytes: size_bytes as u64,
+            hits,
+            misses,
+        }
+    }
+}
+
+/// A filter whose matches are looked up in the cache, evaluating the wrapped query only
+/// for segments not cached yet
+struct CachedFilter {
+    index: String,
+    key: String,
+    query: Box<dyn Query>,
+    cache: Arc<FilterCache>,
+}
+
+impl Clone for CachedFilter {
+    fn clone(&self) -> Self {
+        Self {
+            index: self.index.clone(),
+            key: self.key.clone(),
+            query: self.query.box_clone(),
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for CachedFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CachedFilter").field(&self.query).finish()
+    }
+}
+
+impl Query for CachedFilter {
+    fn weight(&self, enable_scoring: EnableScoring<'_>) -> tantivy::Result<Box<dyn Weight>> {
+        // Filters only decide what matches, so the wrapped query never needs scores
+        let scoring = match enable_scoring.searcher() {
+            Some(searcher) => EnableScoring::disabled_from_searcher(searcher),
+            None => EnableScoring::disabled_from_schema(enable_scoring.schema()),
+        };
+        Ok(Box::new(CachedFilterWeight {
+            filter: self.clone(),
+            weight: self.query.weight(scoring)?,
+        }))
+    }
+
+    fn query_terms<'a>(&'a self, visitor: &mut dyn FnMut(&'a Term, bool)) {
+        self.query.query_terms(visitor);
+    }
+}
+
+struct CachedFilterWeight {
+    filter: CachedFilter,
+    weight: Box<dyn Weight>,
+}
+
+impl Weight for CachedFilterWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> tantivy::Result<Box<dyn Scorer>> {
+        let key = CacheKey {
+            index: self.filter.index.clone(),
+            segment: reader.segment_id(),
+            filter: self.filter.key.clone(),
+        };
+        let bits = match self.filter.cache.get(&key) {
+            Some(bits) => bits,
+            None => {
+                let mut bits = FilterBits::new(reader.max_doc());
+                let mut scorer = self.weight.scorer(reader, 1.0)?;
+                let mut doc = scorer.doc();
+                while doc != TERMINATED {
+                    bits.insert(doc);
+                    doc = scorer.advance();
+                }
+                let bits = Arc::new(bits);
+                self.filter.cache.insert(key, bits.clone());
+                bits
+            }
+        };
+        Ok(Box::new(BitsScorer::new(bits, boost)))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> tantivy::Result<Explanation> {
+        self.weight.explain(reader, doc)
+    }
+}
+
+/// Matches of a cached filter, all with the same score
+struct BitsScorer {
+    bits: Arc<FilterBits>,
+    doc: DocId,
+    score: Score,
+}
+
+impl BitsScorer {
+    fn new(bits: Arc<FilterBits>, score: Score) -> Self {
+        let doc = bits.next_from(0);
+        Self { bits, doc, score }
+    }
+}
+
+impl DocSet for BitsScorer {
+    fn advance(&mut self) -> DocId {
+        if self.doc != TERMINATED {
+            self.doc = self.bits.next_from(self.doc + 1);
+        }
+        self.doc
+    }
+
+    fn seek(&mut self, target: DocId) -> DocId {
+        if self.doc < target {
+            self.doc = self.bits.next_from(target);
+        }
+        self.doc
+    }
+
+    fn doc(&self) -> DocId {
+        self.doc
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.bits.len()
+    }
+}
+
+impl Scorer for BitsScorer {
+    fn score(&mut self) -> Score {
+        self.score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(max_doc: u32, docs: &[DocId]) -> FilterBits {
+        let mut bits = FilterBits::new(max_doc);
+        for doc in docs {
+            bits.insert(*doc);
+        }
+        bits
+    }
+
+    #[test]
+    fn test_iterates_matching_documents() {
+        let mut scorer = BitsScorer::new(Arc::new(bits(200, &[3, 64, 65, 199])), 1.0);
+        assert_eq!(scorer.doc(), 3);
+        assert_eq!(scorer.advance(), 64);
+        assert_eq!(scorer.seek(66), 199);
+        assert_eq!(scorer.advance(), TERMINATED);
+        assert_eq!(scorer.advance(), TERMINATED);
+        assert_eq!(BitsScorer::new(Arc::new(bits(10, &[])), 1.0).doc(), TERMINATED);
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let key = |filter: &str| CacheKey {
+            index: "products".to_string(),
+            segment: SegmentId::generate_random(),
+            filter: filter.to_string(),
+        };
+        // Each entry takes 8 bytes, so two fit
+        let cache = FilterCache::with_capacity(16);
+        let (a, b, c) = (key("a"), key("b"), key("c"));
+        cache.insert(a.clone(), Arc::new(bits(64, &[1])));
+        cache.insert(b.clone(), Arc::new(bits(64, &[2])));
+        assert!(cache.get(&a).is_some());
+        cache.insert(c.clone(), Arc::new(bits(64, &[3])));
+
+        assert!(cache.get(&b).is_none());
+        assert!(cache.get(&a).is_some() && cache.get(&c).is_some());
+        let stats = cache.stats("products");
+        assert_eq!((stats.entries, stats.size_bytes), (2, 16));
+        assert_eq!((stats.hits, stats.misses), (3, 1));
+
+        cache.invalidate("products");
+        assert_eq!(cache.stats("products").entries, 0);
+    }
+}
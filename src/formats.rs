@@ -0,0 +1,275 @@
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use futures_util::Stream;
+
+use crate::models::{Document, SearchHit};
+
+/// Row of a CSV or NDJSON response: column name -> value
+pub type Row = serde_json::Map<String, serde_json::Value>;
+
+/// Formats hits and exported documents can be streamed in instead of JSON, one row
+/// per hit or document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowFormat {
+    Csv,
+    Ndjson,
+}
+
+impl RowFormat {
+    /// Row format the `Accept` header prefers, if any. JSON is kept for anything else,
+    /// including `*/*` and a missing header.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let accept = headers.get(header::ACCEPT)?.to_str().ok()?;
+        preferences(accept)
+            .iter()
+            .find_map(|media_type| match media_type.as_str() {
+                "text/csv" => Some(Some(Self::Csv)),
+                "application/x-ndjson" | "application/ndjson" => Some(Some(Self::Ndjson)),
+                "application/json" | "application/*" | "*/*" => Some(None),
+                _ => None,
+            })
+            .flatten()
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Ndjson => "application/x-ndjson",
+        }
+    }
+}
+
+/// Values of an `Accept`-style header, lowercased, most preferred first. Values with
+/// `q=0` are left out; equally preferred ones keep their order.
+pub fn preferences(header: &str) -> Vec<String> {
+    let mut values: Vec<(f32, String)> = header
+        .split(',')
+        .filter_map(|value| {
+            let mut parts = value.split(';');
+            let name = parts.next()?.trim().to_ascii_lowercase();
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse().ok())
+                .unwrap_or(1.0);
+            Some((quality, name))
+        })
+        .filter(|(quality, name)| *quality > 0.0 && !name.is_empty())
+        .collect();
+    values.sort_by(|a, b| b.0.total_cmp(&a.0));
+    values.into_iter().map(|(_, name)| name).collect()
+}
+
+/// Writes rows with a fixed list of columns: CSV lines under a header line, or one
+/// JSON object per line holding the columns (null where a row has no value)
+pub struct RowWriter {
+    format: RowFormat,
+    columns: Vec<String>,
+}
+
+impl RowWriter {
+    pub fn new(format: RowFormat, columns: Vec<String>) -> Self {
+        Self { format, columns }
+    }
+
+    /// Text before the first row: the CSV header line
+    pub fn header(&self) -> String {
+        match self.format {
+            RowFormat::Csv => csv_line(self.columns.iter().map(|column| csv_quote(column))),
+            RowFormat::Ndjson => String::new(),
+        }
+    }
+
+    /// Text of the rows, each ending with a line break
+    pub fn rows<'a>(&self, rows: impl IntoIterator<Item = &'a Row>) -> String {
+        let mut text = String::new();
+        for row in rows {
+            match self.format {
+                RowFormat::Csv => text.push_str(&csv_line(
+                    self.columns.iter().map(|column| csv_cell(column_value(row, column))),
+                )),
+                RowFormat::Ndjson => {
+                    let object = serde_json::Value::Object(select(row, &self.columns));
+                    text.push_str(&object.to_string());
+                    text.push('\n');
+                }
+            }
+        }
+        text
+    }
+}
+
+/// The columns of a row, null where it has no value
+pub fn select(row: &Row, columns: &[String]) -> Row {
+    columns
+        .iter()
+        .map(|column| (column.clone(), column_value(row, column).cloned().unwrap_or_default()))
+        .collect()
+}
+
+/// Row of a search hit: its ID, index when the search spans several, score and fields
+pub fn hit_row(hit: &SearchHit) -> Row {
+    let mut row: Row = hit.fields.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+    row.insert("id".to_string(), serde_json::Value::String(hit.id.clone()));
+    if let Some(index) = &hit.index {
+        row.insert("_index".to_string(), serde_json::Value::String(index.clone()));
+    }
+    // Through its shortest text, so the score reads as in JSON responses rather than
+    // with the digits of its widening to f64
+    let score: f64 = hit.score.to_string().parse().unwrap_or_default();
+    row.insert("_score".to_string(), serde_json::json!(score));
+    row
+}
+
+/// Rows of a flat search response: the hits' rows, limited to `columns` when given
+pub fn flat_rows(hits: &[SearchHit], columns: &[String]) -> Vec<Row> {
+    hits.iter()
+        .map(hit_row)
+        .map(|row| if columns.is_empty() { row } else { select(&row, columns) })
+        .collect()
+}
+
+/// Row of an exported document: its ID and fields
+pub fn document_row(document: &Document) -> Row {
+    let mut row: Row = document.fields.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+    row.insert("id".to_string(), serde_json::Value::String(document.id.clone()));
+    row
+}
+
+/// Columns of rows when the request doesn't list them: `id`, `_index` and `_score`
+/// first, then every other field of any row by name
+pub fn columns_of(rows: &[Row]) -> Vec<String> {
+    const LEADING: [&str; 3] = ["id", "_index", "_score"];
+    let mut columns: Vec<String> = LEADING
+        .iter()
+        .filter(|column| rows.iter().any(|row| row.contains_key(**column)))
+        .map(|column| column.to_string())
+        .collect();
+    let mut fields: Vec<&String> = rows
+        .iter()
+        .flat_map(|row| row.keys())
+        .filter(|name| !LEADING.contains(&name.as_str()))
+        .collect();
+    fields.sort();
+    fields.dedup();
+    columns.extend(fields.into_iter().cloned());
+    columns
+}
+
+/// Response streaming the chunks of text of a row format
+pub fn response<S, E>(format: RowFormat, chunks: S) -> Response
+where
+    S: Stream<Item = Result<String, E>> + Send + 'static,
+    E: Into<axum::BoxError> + 'static,
+{
+    let mut response = Body::from_stream(chunks).into_response();
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(format.content_type()));
+    response
+}
+
+/// Value of a column; a dotted column like `meta.author` reads inside JSON fields
+fn column_value<'a>(row: &'a Row, column: &str) -> Option<&'a serde_json::Value> {
+    if let Some(value) = row.get(column) {
+        return Some(value);
+    }
+    let (base, path) = column.split_once('.')?;
+    path.split('.').try_fold(row.get(base)?, |value, key| value.get(key))
+}
+
+fn csv_line(cells: impl Iterator<Item = String>) -> String {
+    let mut line = cells.collect::<Vec<_>>().join(",");
+    line.push_str("\r\n");
+    line
+}
+
+fn csv_quote(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// CSV cell of a value: text as is, arrays and objects as JSON, nothing for null
+fn csv_cell(value: Option<&serde_json::Value>) -> String {
+    match value {
+        None | Some(serde_json::Value::Null) => String::new(),
+        // Spreadsheets run text starting with these as a formula
+        Some(serde_json::Value::String(text))
+            if text.starts_with(['=', '+', '-', '@', '\t', '\r']) =>
+        {
+            csv_quote(&format!("'{}", text))
+        }
+        Some(serde_json::Value::String(text)) => csv_quote(text),
+        Some(value) => csv_quote(&value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn accept(value: &'static str) -> HeaderMap {
+        HeaderMap::from_iter([(header::ACCEPT, HeaderValue::from_static(value))])
+    }
+
+    #[test]
+    fn test_negotiates_row_formats() {
+        assert_eq!(RowFormat::from_headers(&accept("text/csv")), Some(RowFormat::Csv));
+        assert_eq!(
+            RowFormat::from_headers(&accept("application/json;q=0.5, application/x-ndjson")),
+            Some(RowFormat::Ndjson)
+        );
+        assert_eq!(RowFormat::from_headers(&accept("*/*, text/csv")), None);
+        assert_eq!(RowFormat::from_headers(&accept("text/csv;q=0")), None);
+        assert_eq!(RowFormat::from_headers(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_writes_csv_rows() {
+        let row = json!({"id": "1", "title": "Boots, \"waterproof\"", "tags": ["a"],
+            "formula": "=SUM(A1)", "meta": {"author": "Ada"}});
+        let row = row.as_object().unwrap().clone();
+        let columns = ["id", "title", "tags", "formula", "meta.author", "missing"];
+        let writer = RowWriter::new(RowFormat::Csv, columns.map(String::from).to_vec());
+        assert_eq!(writer.header(), "id,title,tags,formula,meta.author,missing\r\n");
+        assert_eq!(
+            writer.rows([&row]),
+            "1,\"Boots, \"\"waterproof\"\"\",\"[\"\"a\"\"]\",'=SUM(A1),Ada,\r\n"
+        );
+    }
+
+    #[test]
+    fn test_flat_rows_hold_hit_fields_next_to_the_id() {
+        let hit = SearchHit {
+            id: "1".to_string(),
+            index: None,
+            shard: None,
+            score: 1.5,
+            fields: std::collections::HashMap::from([
+                ("title".to_string(), json!("Boots")),
+                ("meta".to_string(), json!({"author": "Ada"})),
+            ]),
+            highlights: None,
+            matched_queries: Vec::new(),
+            children: Vec::new(),
+        };
+
+        let rows = flat_rows(std::slice::from_ref(&hit), &[]);
+        assert_eq!(
+            serde_json::Value::Object(rows[0].clone()),
+            json!({"id": "1", "_score": 1.5, "title": "Boots", "meta": {"author": "Ada"}})
+        );
+        let columns = ["id", "meta.author", "missing"].map(String::from);
+        let rows = flat_rows(&[hit], &columns);
+        assert_eq!(
+            serde_json::Value::Object(rows[0].clone()),
+            json!({"id": "1", "meta.author": "Ada", "missing": null})
+        );
+    }
+}
@@ -0,0 +1,357 @@
+use std::fmt;
+use tantivy::columnar::Column;
+use tantivy::query::{EnableScoring, Explanation, Query, Scorer, Weight};
+use tantivy::{DocId, DocSet, Score, SegmentReader, TantivyError, Term, TERMINATED};
+
+use crate::models::GeoPoint;
+
+/// Field type for latitude/longitude points
+pub const GEO_POINT_TYPE: &str = "geo_point";
+
+/// Default geohash length for grid aggregations (cells of roughly 5km x 5km)
+pub const DEFAULT_GEOHASH_PRECISION: usize = 5;
+
+/// Longest supported geohash
+pub const MAX_GEOHASH_PRECISION: usize = 12;
+
+/// Aggregation type that buckets geo points by geohash cell
+pub const GEOHASH_GRID_AGGREGATION: &str = "geohash_grid";
+
+const LAT_SUFFIX: &str = "__lat";
+const LON_SUFFIX: &str = "__lon";
+
+const GEOHASH_ALPHABET: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// Name of the hidden latitude sub-field backing a geo_point field
+pub fn lat_field_name(field_name: &str) -> String {
+    format!("{}{}", field_name, LAT_SUFFIX)
+}
+
+/// Name of the hidden longitude sub-field backing a geo_point field
+pub fn lon_field_name(field_name: &str) -> String {
+    format!("{}{}", field_name, LON_SUFFIX)
+}
+
+/// Returns the geo_point field name if `name` is one of its sub-fields
+pub fn parse_sub_field_name(name: &str) -> Option<&str> {
+    name.strip_suffix(LAT_SUFFIX)
+        .or_else(|| name.strip_suffix(LON_SUFFIX))
+        .filter(|base| !base.is_empty())
+}
+
+/// Parse a point from `{"lat": .., "lon": ..}`, a GeoJSON-style `[lon, lat]`
+/// array, or a `"lat,lon"` string. Out-of-range coordinates are rejected.
+pub fn parse_point(value: &serde_json::Value) -> Option<GeoPoint> {
+    let (lat, lon) = match value {
+        serde_json::Value::Object(object) => {
+            (object.get("lat")?.as_f64()?, object.get("lon")?.as_f64()?)
+        }
+        serde_json::Value::Array(items) if items.len() == 2 => {
+            (items[1].as_f64()?, items[0].as_f64()?)
+        }
+        serde_json::Value::String(s) => {
+            let (lat, lon) = s.split_once(',')?;
+            (lat.trim().parse().ok()?, lon.trim().parse().ok()?)
+        }
+        _ => return None,
+    };
+    let point = GeoPoint { lat, lon };
+    is_valid(&point).then_some(point)
+}
+
+pub fn is_valid(point: &GeoPoint) -> bool {
+    (-90.0..=90.0).contains(&point.lat) && (-180.0..=180.0).contains(&point.lon)
+}
+
+/// Encode a point as a geohash of the given length
+pub fn geohash(point: &GeoPoint, precision: usize) -> String {
+    let (mut lat_range, mut lon_range) = ((-90.0, 90.0), (-180.0, 180.0));
+    let mut hash = String::with_capacity(precision);
+    let mut even_bit = true;
+    let (mut bits, mut bit_count) = (0usize, 0);
+
+    while hash.len() < precision {
+        let (range, value) = if even_bit {
+            (&mut lon_range, point.lon)
+        } else {
+            (&mut lat_range, point.lat)
+        };
+        let mid = (range.0 + range.1) / 2.0;
+        bits <<= 1;
+        if value >= mid {
+            bits |= 1;
+            range.0 = mid;
+        } else {
+            range.1 = mid;
+        }
+        even_bit = !even_bit;
+
+        bit_count += 1;
+        if bit_count == 5 {
+            hash.push(GEOHASH_ALPHABET[bits] as char);
+            bits = 0;
+            bit_count = 0;
+        }
+    }
+
+    hash
+}
+
+/// Ray-casting point-in-polygon test. The polygon is closed implicitly.
+pub fn polygon_contains(polygon: &[GeoPoint], point: &GeoPoint) -> bool {
+    let mut inside = false;
+    let mut j = polygon.len() - 1;
+    for i in 0..polygon.len() {
+        let (a, b) = (&polygon[i], &polygon[j]);
+        if (a.lat > point.lat) != (b.lat > point.lat)
+            && point.lon < (b.lon - a.lon) * (point.lat - a.lat) / (b.lat - a.lat) + a.lon
+        {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+/// Smallest box around a polygon as (top_left, bottom_right)
+pub fn bounding_box(polygon: &[GeoPoint]) -> (GeoPoint, GeoPoint) {
+    let mut top_left = GeoPoint {
+        lat: f64::MIN,
+        lon: f64::MAX,
+    };
+    let mut bottom_right = GeoPoint {
+        lat: f64::MAX,
+        lon: f64::MIN,
+    };
+    for point in polygon {
+        top_left.lat = top_left.lat.max(point.lat);
+        top_left.lon = top_left.lon.min(point.lon);
+        bottom_right.lat = bottom_right.lat.min(point.lat);
+        bottom_right.lon = bottom_right.lon.max(point.lon);
+    }
+    (top_left, bottom_right)
+}
+
+/// Documents of `candidates` whose point lies inside a polygon. The point of each
+/// candidate is read from the field's fast columns, segment by segment.
+pub struct PolygonQuery {
+    field: String,
+    polygon: Vec<GeoPoint>,
+    candidates: Box<dyn Query>,
+}
+
+impl PolygonQuery {
+    pub fn new(field: &str, polygon: Vec<GeoPoint>, candidates: Box<dyn Query>) -> Self {
+        Self { field: field.to_string(), polygon, candidates }
+    }
+}
+
+impl Clone for PolygonQuery {
+    fn clone(&self) -> Self {
+        Self {
+            field: self.field.clone(),
+            polygon: self.polygon.clone(),
+            candidates: self.candidates.box_clone(),
+        }
+    }
+}
+
+impl fmt::Debug for PolygonQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PolygonQuery")
+            .field("field", &self.field)
+            .field("polygon", &self.polygon)
+            .finish()
+    }
+}
+
+impl Query for PolygonQuery {
+    fn weight(&self, enable_scoring: EnableScoring<'_>) -> tantivy::Result<Box<dyn Weight>> {
+        Ok(Box::new(PolygonWeight {
+            field: self.field.clone(),
+            polygon: self.polygon.clone(),
+            candidates: self.candidates.weight(enable_scoring)?,
+        }))
+    }
+
+    fn query_terms<'a>(&'a self, visitor: &mut dyn FnMut(&'a Term, bool)) {
+        self.candidates.query_terms(visitor);
+    }
+}
+
+struct PolygonWeight {
+    field: String,
+    polygon: Vec<GeoPoint>,
+    candidates: Box<dyn Weight>,
+}
+
+impl PolygonWeight {
+    fn polygon_scorer(
+        &self,
+        reader: &SegmentReader,
+        boost: Score,
+    ) -> tantivy::Result<PolygonScorer> {
+        let fast_fields = reader.fast_fields();
+        let mut scorer = PolygonScorer {
+            candidates: self.candidates.scorer(reader, boost)?,
+            lat: fast_fields.f64(&lat_field_name(&self.field))?,
+            lon: fast_fields.f64(&lon_field_name(&self.field))?,
+            polygon: self.polygon.clone(),
+        };
+        scorer.skip_outside();
+        Ok(scorer)
+    }
+}
+
+impl Weight for PolygonWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> tantivy::Result<Box<dyn Scorer>> {
+        Ok(Box::new(self.polygon_scorer(reader, boost)?))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> tantivy::Result<Explanation> {
+        if self.polygon_scorer(reader, 1.0)?.seek(doc) != doc {
+            return Err(TantivyError::InvalidArgument(format!(
+                "Document {} is not inside the polygon",
+                doc
+            )));
+        }
+        self.candidates.explain(reader, doc)
+    }
+}
+
+/// Candidates of a polygon query, skipping those whose point is outside the polygon
+struct PolygonScorer {
+    candidates: Box<dyn Scorer>,
+    lat: Column<f64>,
+    lon: Column<f64>,
+    polygon: Vec<GeoPoint>,
+}
+
+impl PolygonScorer {
+    fn inside(&self, doc: DocId) -> bool {
+        match (self.lat.first(doc), self.lon.first(doc)) {
+            (Some(lat), Some(lon)) => polygon_contains(&self.polygon, &GeoPoint { lat, lon }),
+            _ => false,
+        }
+    }
+
+    /// Move to the first candidate from the current one that is inside the polygon
+    fn skip_outside(&mut self) -> DocId {
+        let mut doc = self.candidates.doc();
+        while doc != TERMINATED && !self.inside(doc) {
+            doc = self.candidates.advance();
+        }
+        doc
+    }
+}
+
+impl DocSet for PolygonScorer {
+    fn advance(&mut self) -> DocId {
+        self.candidates.advance();
+        self.skip_outside()
+    }
+
+    fn seek(&mut self, target: DocId) -> DocId {
+        self.candidates.seek(target);
+        self.skip_outside()
+    }
+
+    fn doc(&self) -> DocId {
+        self.candidates.doc()
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.candidates.size_hint()
+    }
+}
+
+impl Scorer for PolygonScorer {
+    fn score(&mut self) -> Score {
+        self.candidates.score()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_point_formats() {
+        let expected = Some(GeoPoint {
+            lat: 59.91,
+            lon: 10.75,
+        });
+        assert_eq!(
+            parse_point(&serde_json::json!({"lat": 59.91, "lon": 10.75})),
+            expected
+        );
+        assert_eq!(parse_point(&serde_json::json!([10.75, 59.91])), expected);
+        assert_eq!(parse_point(&serde_json::json!("59.91, 10.75")), expected);
+        assert_eq!(
+            parse_point(&serde_json::json!({"lat": 91.0, "lon": 0.0})),
+            None
+        );
+    }
+
+    #[test]
+    fn test_geohash() {
+        let oslo = GeoPoint {
+            lat: 59.9139,
+            lon: 10.7522,
+        };
+        assert_eq!(geohash(&oslo, 5), "u4xsu");
+        assert_eq!(
+            geohash(
+                &GeoPoint {
+                    lat: 57.64911,
+                    lon: 10.40744
+                },
+                11
+            ),
+            "u4pruydqqvj"
+        );
+    }
+
+    #[test]
+    fn test_polygon_contains() {
+        let square = vec![
+            GeoPoint { lat: 0.0, lon: 0.0 },
+            GeoPoint {
+                lat: 0.0,
+                lon: 10.0,
+            },
+            GeoPoint {
+                lat: 10.0,
+                lon: 10.0,
+            },
+            GeoPoint {
+                lat: 10.0,
+                lon: 0.0,
+            },
+        ];
+        assert!(polygon_contains(&square, &GeoPoint { lat: 5.0, lon: 5.0 }));
+        assert!(!polygon_contains(
+            &square,
+            &GeoPoint {
+                lat: 5.0,
+                lon: 15.0
+            }
+        ));
+
+        let (top_left, bottom_right) = bounding_box(&square);
+        assert_eq!(
+            top_left,
+            GeoPoint {
+                lat: 10.0,
+                lon: 0.0
+            }
+        );
+        assert_eq!(
+            bottom_right,
+            GeoPoint {
+                lat: 0.0,
+                lon: 10.0
+            }
+        );
+    }
+}
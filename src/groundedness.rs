@@ -0,0 +1,106 @@
+use crate::llm::ChatMessage;
+use crate::models::{Groundedness, SentenceSupport};
+
+/// Sentences scoring below this are flagged as unsupported
+pub const SUPPORT_THRESHOLD: f32 = 0.5;
+
+/// Instruction for scoring how well the sources support each sentence
+const VERIFY_PROMPT: &str = "You check answers against sources. For each numbered sentence \
+of the answer, rate from 0 to 1 how well the sources support it, where 0 means not supported \
+or contradicted and 1 means fully supported. Sentences without a factual claim, such as \
+saying the answer is unknown, count as supported. Reply with only a JSON array with one \
+number per sentence, e.g. [1, 0.2].";
+
+/// Messages asking the model to score each sentence (byte spans from
+/// `chunking::sentence_spans`) against the sources
+pub fn verification_messages(
+    answer: &str,
+    sentences: &[(usize, usize)],
+    sources_text: &str,
+) -> Vec<ChatMessage> {
+    let numbered: Vec<String> = sentences
+        .iter()
+        .enumerate()
+        .map(|(idx, (start, end))| format!("{}. {}", idx + 1, &answer[*start..*end]))
+        .collect();
+
+    vec![
+        ChatMessage {
+            role: "system".to_string(),
+            content: VERIFY_PROMPT.to_string(),
+        },
+        ChatMessage {
+            role: "user".to_string(),
+            content: format!(
+                "Sources:\n{}\n\nAnswer sentences:\n{}",
+                sources_text,
+                numbered.join("\n")
+            ),
+        },
+    ]
+}
+
+/// Read the model's scores and combine them with the sentences. Returns `None` unless
+/// the reply has exactly one score per sentence.
+pub fn parse_groundedness(
+    reply: &str,
+    answer: &str,
+    sentences: &[(usize, usize)],
+) -> Option<Groundedness> {
+    let start = reply.find('[')?;
+    let end = start + reply[start..].find(']')?;
+    let scores: Vec<f32> = serde_json::from_str(&reply[start..=end]).ok()?;
+    if scores.len() != sentences.len() || sentences.is_empty() {
+        return None;
+    }
+
+    let chars = |byte: usize| answer[..byte].chars().count();
+    let sentences: Vec<SentenceSupport> = sentences
+        .iter()
+        .zip(scores)
+        .map(|((start, end), score)| {
+            let score = score.clamp(0.0, 1.0);
+            SentenceSupport {
+                text: answer[*start..*end].to_string(),
+                start: chars(*start),
+                end: chars(*end),
+                score,
+                supported: score >= SUPPORT_THRESHOLD,
+            }
+        })
+        .collect();
+    let score = sentences.iter().map(|s| s.score).sum::<f32>() / sentences.len() as f32;
+
+    Some(Groundedness { score, sentences })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::sentence_spans;
+
+    #[test]
+    fn test_parse_groundedness() {
+        let answer = "Barnehagen åpner klokken 7. Den har 40 plasser [1].";
+        let sentences = sentence_spans(answer);
+        assert_eq!(sentences.len(), 2);
+
+        let groundedness = parse_groundedness("```[1, 0.25]```", answer, &sentences).unwrap();
+        assert_eq!(groundedness.score, 0.625);
+        let summary: Vec<(&str, usize, usize, bool)> = groundedness
+            .sentences
+            .iter()
+            .map(|s| (s.text.as_str(), s.start, s.end, s.supported))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Barnehagen åpner klokken 7.", 0, 27, true),
+                ("Den har 40 plasser [1].", 28, 51, false),
+            ]
+        );
+
+        // A score per sentence is required
+        assert!(parse_groundedness("[1]", answer, &sentences).is_none());
+    }
+}
@@ -1,19 +1,59 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{rejection::JsonRejection, Multipart, Path, Query, State},
+    Extension,
+    http::{HeaderMap, StatusCode},
     response::{sse::{Event, KeepAlive, Sse}, IntoResponse, Response},
     Json,
 };
 use futures_util::StreamExt;
+use std::collections::HashMap;
 use std::convert::Infallible;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use crate::llm::{ChatCompletionRequest, ChatCompletionStreamChunk, ChatMessage};
+use crate::answer_cache;
+use crate::audit::{self, AuditDetails};
+use crate::auth;
+use crate::chunking;
+use crate::citations;
+use crate::context;
+use crate::crawler::compile_patterns;
+use crate::error::SearchError;
+use crate::jobs;
+use crate::migration;
+use crate::extraction::{extract_text, FileKind};
+use crate::field_access;
+use crate::formats::{self, RowFormat, RowWriter};
+use crate::groundedness;
+use crate::http_cache;
+use crate::index_patterns;
+use crate::llm::{
+    ChatCompletionRequest, ChatCompletionStreamChunk, ChatMessage, LlmClient, Usage,
+};
+use crate::lookup;
 use crate::models::*;
+use crate::prompts;
+use crate::query_syntax;
+use crate::quota;
+use crate::reconcile;
+use crate::redaction::Redactions;
+use crate::rerank;
+use crate::rewrite;
+use crate::rollover;
+use crate::search::SearchEngine;
+use crate::search_keys;
+use crate::summary;
+use crate::relevance_report;
+use crate::synonym_suggestions;
 use crate::validation::{
     clamp_pagination_limit, validate_bulk_operation_count, validate_document_count,
-    validate_index_name,
+    validate_chunking, validate_context, validate_export, validate_index_name,
+    validate_index_pattern,
+    validate_index_settings, validate_offset, validate_query_suggestions, validate_regex,
+    validate_rerank, validate_llm_settings, validate_shard_count, validate_summary,
+    validate_synonym_suggestions,
+    validate_trending_window, PaginationLimits, MAX_QUERY_SUGGESTIONS, MAX_SUGGEST_SAMPLE_QUERIES,
+    MAX_TERMS_LIMIT,
 };
 use crate::AppState;
 
@@ -46,11 +86,38 @@ pub async fn health_check(
     )
 }
 
+/// Ready once the service is healthy and the startup metadata rebuild has finished;
+/// document counts from the metadata store may be incomplete until then
+pub async fn readiness_check(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let database = state.metadata_store.health_check().is_ok();
+    let rebuild = state.metadata_rebuild.status();
+    let warmed = state.warmup.is_startup_finished();
+
+    let (status_code, status) = match (database, rebuild.finished && warmed) {
+        (false, _) => (StatusCode::SERVICE_UNAVAILABLE, "unhealthy"),
+        (true, false) => (StatusCode::SERVICE_UNAVAILABLE, "starting"),
+        (true, true) => (StatusCode::OK, "ready"),
+    };
+
+    (
+        status_code,
+        Json(serde_json::json!({
+            "status": status,
+            "checks": {
+                "database": if database { "healthy" } else { "unhealthy" },
+                "metadata_rebuild": rebuild,
+                "warmup": if warmed { "finished" } else { "running" }
+            }
+        })),
+    )
+}
+
 pub async fn create_index(
     State(state): State<Arc<AppState>>,
     Json(payload): Json<CreateIndexRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
     validate_index_name(&payload.name)?;
+    validate_shard_count(payload.shards)?;
 
     // Set default fields if none provided
     let fields = if payload.fields.is_empty() {
@@ -62,6 +129,7 @@ pub async fn create_index(
                 indexed: true,
                 analyzer: "default".to_string(),
                 fast: false,
+                languages: Vec::new(),
             },
             FieldConfig {
                 name: "content".to_string(),
@@ -70,37 +138,116 @@ pub async fn create_index(
                 indexed: true,
                 analyzer: "default".to_string(),
                 fast: false,
+                languages: Vec::new(),
             },
         ]
     } else {
         payload.fields
     };
 
+    // The summary field is created with the source field's analyzer when not defined
+    let mut fields = fields;
+    if let Some(settings) = &payload.settings.summary {
+        validate_summary(settings, &fields)?;
+        if !fields.iter().any(|field| field.name == settings.target_field) {
+            let analyzer = fields
+                .iter()
+                .find(|field| field.name == settings.source_field)
+                .map(|field| field.analyzer.clone())
+                .unwrap_or_else(|| "default".to_string());
+            fields.push(FieldConfig {
+                name: settings.target_field.clone(),
+                field_type: "text".to_string(),
+                stored: true,
+                indexed: true,
+                analyzer,
+                fast: false,
+                languages: Vec::new(),
+            });
+        }
+    }
+
+    if let Some(settings) = &payload.settings.llm {
+        validate_llm_settings(settings)?;
+        check_llm_provider(&state, settings)?;
+    }
+    validate_index_settings(&payload.settings, &fields)?;
+
+    // A rolling index is an alias over generations named after it
+    let index_name = match &payload.rollover {
+        Some(_) => {
+            let engine = &state.search_engine;
+            if engine.rollover_alias(&payload.name).is_some()
+                || engine.list_indices().contains(&payload.name)
+            {
+                return Err((
+                    StatusCode::CONFLICT,
+                    Json(ApiResponse::error(format!("Index already exists: {}", payload.name))),
+                ));
+            }
+            let generation = rollover::generation_name(&payload.name, 1);
+            validate_index_name(&generation)?;
+            generation
+        }
+        None => payload.name.clone(),
+    };
+
     state
         .search_engine
-        .create_index(&payload.name, &fields)
-        .map_err(|e| {
-            (
+        .create_index(&index_name, &fields, &payload.settings, payload.shards)
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+
+    // A metadata row left by an index deleted outside the API also blocks the name,
+    // until it is cleared with DELETE /indices/:name
+    let recorded = state.metadata_store.create_index(&index_name);
+    if !matches!(recorded, Ok(true)) {
+        if let Err(e) = state.search_engine.delete_index(&index_name) {
+            tracing::warn!("Failed to roll back index '{}': {}", index_name, e);
+        }
+        return Err(match recorded {
+            Err(e) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(ApiResponse::error(e.to_string())),
-            )
-        })?;
+            ),
+            _ => (
+                StatusCode::CONFLICT,
+                Json(ApiResponse::error(format!(
+                    "Index already exists in metadata: {}",
+                    index_name
+                ))),
+            ),
+        });
+    }
 
-    state
-        .metadata_store
-        .create_index(&payload.name)
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ApiResponse::error(e.to_string())),
-            )
-        })?;
+    let details = AuditDetails(serde_json::json!({ "index": payload.name }));
+    let Some(policy) = payload.rollover else {
+        return Ok((
+            StatusCode::CREATED,
+            Extension(details),
+            Json(ApiResponse::success(serde_json::json!({
+                "message": "Index created successfully",
+                "name": payload.name
+            }))),
+        ));
+    };
+
+    if let Err(e) = rollover::create_alias(&state, &payload.name, &index_name, policy) {
+        if let Err(e) = state.search_engine.delete_index(&index_name) {
+            tracing::warn!("Failed to roll back index '{}': {}", index_name, e);
+        }
+        if let Err(e) = state.metadata_store.delete_index(&index_name) {
+            tracing::warn!("Failed to roll back metadata of index '{}': {}", index_name, e);
+        }
+        return Err((e.status_code(), Json(ApiResponse::error(e.to_string()))));
+    }
 
     Ok((
         StatusCode::CREATED,
+        Extension(details),
         Json(ApiResponse::success(serde_json::json!({
-            "message": "Index created successfully",
-            "name": payload.name
+            "message": "Rolling index created successfully",
+            "name": payload.name,
+            "write_index": index_name
         }))),
     ))
 }
@@ -108,35 +255,284 @@ pub async fn create_index(
 pub async fn list_indices(
     State(state): State<Arc<AppState>>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<Vec<IndexInfo>>>)> {
-    let indices = state.metadata_store.list_indices().map_err(|e| {
+    let mut indices = state.metadata_store.list_indices().map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ApiResponse::error(e.to_string())),
         )
     })?;
+    for index in &mut indices {
+        index.closed = state.search_engine.is_closed(&index.name);
+    }
 
     Ok(Json(ApiResponse::success(indices)))
 }
 
-pub async fn delete_index(
+pub async fn close_index(
     State(state): State<Arc<AppState>>,
     Path(name): Path<String>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
     validate_index_name(&name)?;
 
-    state.search_engine.delete_index(&name).map_err(|e| {
+    state
+        .search_engine
+        .close_index(&name)
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+
+    Ok(Json(ApiResponse::success(serde_json::json!({
+        "message": "Index closed successfully",
+        "name": name
+    }))))
+}
+
+pub async fn open_index(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&name)?;
+
+    state
+        .search_engine
+        .open_index(&name)
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+
+    Ok(Json(ApiResponse::success(serde_json::json!({
+        "message": "Index opened successfully",
+        "name": name
+    }))))
+}
+
+/// Roll a rolling index over to a new generation when a condition is met, and delete the
+/// old generations its retention policy no longer keeps
+pub async fn rollover_index(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    payload: Option<Json<RolloverRequest>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<RolloverResponse>>)> {
+    validate_index_name(&name).map_err(|e| {
+        (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
+    })?;
+    let request = payload.map(|Json(request)| request).unwrap_or_default();
+
+    let response = rollover::rollover(&state, &name, &request)
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+
+    Ok(Json(ApiResponse::success(response)))
+}
+
+/// Generations and policy of a rolling index
+pub async fn get_rollover(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<RolloverInfo>>)> {
+    validate_index_name(&name).map_err(|e| {
+        (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
+    })?;
+
+    let rolling = state.search_engine.rollover_alias(&name).ok_or_else(|| {
         (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ApiResponse::error(e.to_string())),
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(format!("Rolling index not found: {}", name))),
+        )
+    })?;
+
+    Ok(Json(ApiResponse::success(RolloverInfo {
+        alias: name,
+        write_index: rolling.write_index().to_string(),
+        state: rolling,
+    })))
+}
+
+/// Start moving an index or rolling index to new fields; progress is reported by
+/// `GET /indices/:name/migrate`
+pub async fn migrate_index(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Json(payload): Json<MigrationRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&name)?;
+    if payload.fields.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error("fields must not be empty".to_string())),
+        ));
+    }
+    if let Some(shards) = payload.shards {
+        validate_shard_count(shards)?;
+    }
+
+    let settings = match payload.settings {
+        Some(settings) => {
+            if let Some(llm) = &settings.llm {
+                validate_llm_settings(llm)?;
+                check_llm_provider(&state, llm)?;
+            }
+            settings
+        }
+        None => {
+            let write_index = state.search_engine.resolve_write_index(&name);
+            state.search_engine.index_settings(&write_index).ok_or_else(|| {
+                (
+                    StatusCode::NOT_FOUND,
+                    Json(ApiResponse::error(format!("Index not found: {}", name))),
+                )
+            })?
+        }
+    };
+    if let Some(summary) = &settings.summary {
+        validate_summary(summary, &payload.fields)?;
+    }
+    validate_index_settings(&settings, &payload.fields)?;
+
+    let status = migration::start(&state, &name, payload.fields, settings, payload.shards)
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+
+    Ok((StatusCode::ACCEPTED, Json(ApiResponse::success(status))))
+}
+
+/// Read all documents of an index, rolling index or index pattern page by page. The
+/// first page takes a snapshot, and the pages after it read from the same snapshot
+/// however the index changes meanwhile. With `Accept: text/csv` or
+/// `application/x-ndjson`, every page from the cursor on is streamed in one response.
+pub async fn export_documents(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    Json(payload): Json<ExportRequest>,
+) -> Result<Response, (StatusCode, Json<ApiResponse<ExportPage>>)> {
+    let invalid = |e: (StatusCode, Json<ApiResponse<()>>)| {
+        (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
+    };
+    validate_index_pattern(&name).map_err(invalid)?;
+    validate_export(&payload).map_err(invalid)?;
+
+    let row_format = RowFormat::from_headers(&headers);
+    let mut columns = payload.columns.clone();
+    if row_format.is_some() {
+        let stored_fields = state.search_engine.stored_field_names(&name);
+        let known = |column: &str| {
+            let base = column.split_once('.').map_or(column, |(base, _)| base);
+            base == "id" || stored_fields.iter().any(|field| field == base)
+        };
+        if let Some((i, column)) = columns.iter().enumerate().find(|(_, c)| !known(c)) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::error(format!("columns[{}]: unknown field '{}'", i, column))),
+            ));
+        }
+        if columns.is_empty() {
+            columns = std::iter::once("id".to_string()).chain(stored_fields).collect();
+        }
+    }
+
+    let (engine, exports) = (&state.search_engine, &state.exports);
+    let keep_alive = Duration::from_secs(payload.keep_alive_secs);
+    let page = match &payload.cursor {
+        Some(cursor) => exports.next(engine, &name, cursor, payload.size, keep_alive),
+        None => exports.start(engine, &name, payload.size, keep_alive),
+    }
+    .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+
+    let Some(row_format) = row_format else {
+        return Ok(Json(ApiResponse::success(page)).into_response());
+    };
+    let mut response_headers = HeaderMap::new();
+    insert_generation_header(&mut response_headers, page.generation);
+    response_headers.insert("x-total-count", page.total.into());
+
+    // Pages are read as the client takes them; an error after the first one can only
+    // end the response early
+    let writer = RowWriter::new(row_format, columns);
+    let size = payload.size;
+    let stream = async_stream::stream! {
+        let rows: Vec<_> = page.documents.iter().map(formats::document_row).collect();
+        yield Ok::<String, SearchError>(writer.header() + &writer.rows(&rows));
+        let mut cursor = page.cursor;
+        while let Some(next) = cursor {
+            match state.exports.next(&state.search_engine, &name, &next, size, keep_alive) {
+                Ok(page) => {
+                    let rows: Vec<_> = page.documents.iter().map(formats::document_row).collect();
+                    yield Ok(writer.rows(&rows));
+                    cursor = page.cursor;
+                }
+                Err(e) => {
+                    tracing::warn!("Export of index '{}' failed: {}", name, e);
+                    yield Err(e);
+                    break;
+                }
+            }
+        }
+    };
+    Ok((response_headers, formats::response(row_format, stream)).into_response())
+}
+
+/// Release the snapshot of an export before it expires
+pub async fn delete_export(
+    State(state): State<Arc<AppState>>,
+    Path((name, cursor)): Path<(String, String)>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_pattern(&name)?;
+    if !state.exports.release(&name, &cursor) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(format!("Export cursor not found or expired: {}", cursor))),
+        ));
+    }
+
+    Ok(Json(ApiResponse::success(serde_json::json!({
+        "message": "Export released"
+    }))))
+}
+
+/// Progress of the last migration of an index
+pub async fn get_migration(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&name)?;
+
+    let status = state.migrations.get(&name).ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(format!("No migration of index: {}", name))),
         )
     })?;
 
+    Ok(Json(ApiResponse::success(status)))
+}
+
+pub async fn delete_index(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&name)?;
+
+    // Deleting a rolling index deletes all of its generations
+    if state.search_engine.rollover_alias(&name).is_some() {
+        rollover::delete_alias(&state, &name)
+            .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+        return Ok((
+            StatusCode::OK,
+            Json(ApiResponse::success(serde_json::json!({
+                "message": "Index deleted successfully"
+            }))),
+        ));
+    }
+
+    state
+        .search_engine
+        .delete_index(&name)
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+
     state.metadata_store.delete_index(&name).map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ApiResponse::error(e.to_string())),
         )
     })?;
+    state.query_rewrites.invalidate(&name);
+    state.answer_cache.invalidate(&name);
+    state.metadata_sync.remove(&name);
 
     Ok((
         StatusCode::OK,
@@ -149,39 +545,248 @@ pub async fn delete_index(
 pub async fn add_documents(
     State(state): State<Arc<AppState>>,
     Path(index_name): Path<String>,
-    Json(payload): Json<AddDocumentsRequest>,
+    Query(params): Query<WriteParams>,
+    Json(mut payload): Json<AddDocumentsRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
     validate_index_name(&index_name)?;
+    // Writes to a rolling index go to its write index
+    let index_name = state.search_engine.resolve_write_index(&index_name);
     validate_document_count(payload.documents.len())?;
+    payload.documents.iter_mut().for_each(assign_document_id);
+    add_llm_summaries(&state, &index_name, payload.documents.iter_mut().collect()).await;
+
+    let ids: Vec<String> = payload.documents.iter().map(|doc| doc.id.clone()).collect();
+    let write_index = index_name.clone();
+    let outcome = run_write(&state, move |state| {
+        state.search_engine.add_documents(&write_index, &payload.documents)
+    })
+    .await
+    .map_err(|e| {
+        (
+            e.status_code(),
+            Json(ApiResponse::error(e.to_string())),
+        )
+    })?;
 
-    state
-        .search_engine
-        .add_documents(&index_name, &payload.documents)
+    // Update metadata
+    state.metadata_store.apply_ingest_outcome(&index_name, &outcome).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(e.to_string())),
+        )
+    })?;
+    wait_for_refresh(&state.search_engine, &index_name, params.refresh, &outcome.commit)
+        .await
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+
+    Ok((
+        StatusCode::CREATED,
+        Extension(AuditDetails::documents(&outcome)),
+        Json(ApiResponse::success(serde_json::json!({
+            "message": "Documents added successfully",
+            "count": outcome.indexed_ids.len(),
+            "ids": ids,
+            "replaced": outcome.updated_ids.len(),
+            "skipped_existing": outcome.conflict_ids.len(),
+            "skipped_duplicates": outcome.skipped_ids.len(),
+            "overwritten_duplicates": outcome.replaced_ids.len(),
+            "commit": outcome.commit
+        }))),
+    ))
+}
+
+/// Run an engine write on a blocking thread. Writes wait for the index writers and
+/// commit to disk, which would otherwise hold up the searches sharing the runtime thread.
+async fn run_write<T: Send + 'static>(
+    state: &Arc<AppState>,
+    write: impl FnOnce(&AppState) -> Result<T, SearchError> + Send + 'static,
+) -> Result<T, SearchError> {
+    let state = state.clone();
+    tokio::task::spawn_blocking(move || write(&state))
+        .await
+        .map_err(|e| SearchError::Internal(e.into()))?
+}
+
+/// With `refresh=wait_for`, hold a write's response until searches see its commit.
+/// Writes are committed before responding and searches open a reader on the latest
+/// commit, so this only confirms what the other modes assume.
+async fn wait_for_refresh(
+    engine: &SearchEngine,
+    index_name: &str,
+    refresh: Option<Refresh>,
+    commit: &CommitStats,
+) -> Result<(), SearchError> {
+    match refresh {
+        Some(Refresh::WaitFor) => wait_for_generation(engine, index_name, commit.opstamp).await,
+        _ => Ok(()),
+    }
+}
+
+/// Give a document sent without an `id` a generated one
+fn assign_document_id(doc: &mut Document) {
+    if doc.id.is_empty() {
+        doc.id = uuid::Uuid::new_v4().to_string();
+    }
+}
+
+/// Document read from a multipart upload, with the text extracted from its file
+struct UploadedDocument {
+    document: Document,
+    filename: String,
+    kind: FileKind,
+    content_field: String,
+    extracted_chars: usize,
+}
+
+/// Read the `file`, `id`, `content_field` and `fields` parts of an upload and extract
+/// the file's text into the content field
+async fn read_upload(
+    mut multipart: Multipart,
+) -> Result<UploadedDocument, (StatusCode, Json<ApiResponse<()>>)> {
+    let bad_request =
+        |message: String| (StatusCode::BAD_REQUEST, Json(ApiResponse::error(message)));
+
+    let mut file: Option<(String, Option<String>, Vec<u8>)> = None;
+    let mut doc_id: Option<String> = None;
+    let mut fields: HashMap<String, serde_json::Value> = HashMap::new();
+    let mut content_field = "content".to_string();
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| bad_request(e.to_string()))?
+    {
+        let name = field.name().unwrap_or_default().to_string();
+        match name.as_str() {
+            "file" => {
+                let filename = field.file_name().unwrap_or("upload").to_string();
+                let mime_type = field.content_type().map(|m| m.to_string());
+                let bytes = field.bytes().await.map_err(|e| bad_request(e.to_string()))?;
+                file = Some((filename, mime_type, bytes.to_vec()));
+            }
+            "id" => {
+                doc_id = Some(field.text().await.map_err(|e| bad_request(e.to_string()))?);
+            }
+            "content_field" => {
+                content_field = field.text().await.map_err(|e| bad_request(e.to_string()))?;
+            }
+            "fields" => {
+                let text = field.text().await.map_err(|e| bad_request(e.to_string()))?;
+                fields = serde_json::from_str(&text)
+                    .map_err(|e| bad_request(format!("Invalid fields JSON: {}", e)))?;
+            }
+            _ => {}
+        }
+    }
+
+    let (filename, mime_type, bytes) =
+        file.ok_or_else(|| bad_request("Missing 'file' field".to_string()))?;
+
+    let kind = FileKind::detect(&filename, mime_type.as_deref()).ok_or_else(|| {
+        (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Json(ApiResponse::error(format!(
+                "Unsupported file type for '{}'. Supported: PDF, DOCX, TXT",
+                filename
+            ))),
+        )
+    })?;
+
+    let text = tokio::task::spawn_blocking(move || extract_text(kind, &bytes))
+        .await
         .map_err(|e| {
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(ApiResponse::error(e.to_string())),
             )
+        })?
+        .map_err(|e| {
+            (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(ApiResponse::error(e.to_string())),
+            )
         })?;
 
-    // Update metadata
-    for doc in &payload.documents {
-        state
-            .metadata_store
-            .add_document(&index_name, &doc.id)
-            .map_err(|e| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(ApiResponse::error(e.to_string())),
-                )
-            })?;
-    }
+    let extracted_chars = text.chars().count();
+    fields.insert(content_field.clone(), serde_json::Value::String(text));
+    fields.insert("filename".to_string(), serde_json::json!(filename));
+    fields.insert("mime_type".to_string(), serde_json::json!(kind.mime_type()));
+
+    let document = Document {
+        id: doc_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+        fields,
+    };
+    Ok(UploadedDocument {
+        document,
+        filename,
+        kind,
+        content_field,
+        extracted_chars,
+    })
+}
+
+/// Upload a PDF, DOCX, or text file and index its extracted text.
+///
+/// Multipart fields: `file` (required), `id`, `fields` (JSON object of extra
+/// field values), and `content_field` (target field for the text, default `content`).
+pub async fn upload_document(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+    Query(params): Query<WriteParams>,
+    multipart: Multipart,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&index_name)?;
+    // Writes to a rolling index go to its write index
+    let index_name = state.search_engine.resolve_write_index(&index_name);
+
+    let UploadedDocument {
+        mut document,
+        filename,
+        kind,
+        content_field,
+        extracted_chars,
+    } = read_upload(multipart).await?;
+    add_llm_summaries(&state, &index_name, vec![&mut document]).await;
+
+    let doc_id = document.id.clone();
+    let write_index = index_name.clone();
+    let outcome = run_write(&state, move |state| {
+        state.search_engine.add_documents(&write_index, std::slice::from_ref(&document))
+    })
+    .await
+    .map_err(|e| {
+        (
+            e.status_code(),
+            Json(ApiResponse::error(e.to_string())),
+        )
+    })?;
+
+    state.metadata_store.apply_ingest_outcome(&index_name, &outcome).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(e.to_string())),
+        )
+    })?;
+    wait_for_refresh(&state.search_engine, &index_name, params.refresh, &outcome.commit)
+        .await
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
 
     Ok((
         StatusCode::CREATED,
+        Extension(AuditDetails::documents(&outcome)),
         Json(ApiResponse::success(serde_json::json!({
-            "message": "Documents added successfully",
-            "count": payload.documents.len()
+            "message": "Document uploaded successfully",
+            "id": doc_id,
+            "filename": filename,
+            "mime_type": kind.mime_type(),
+            "content_field": content_field,
+            "extracted_chars": extracted_chars,
+            "count": outcome.indexed_ids.len(),
+            "replaced": outcome.updated_ids.len(),
+            "skipped_existing": outcome.conflict_ids.len(),
+            "skipped_duplicates": outcome.skipped_ids.len(),
+            "overwritten_duplicates": outcome.replaced_ids.len(),
+            "commit": outcome.commit
         }))),
     ))
 }
@@ -191,18 +796,22 @@ pub async fn delete_document(
     Path((index_name, doc_id)): Path<(String, String)>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
     validate_index_name(&index_name)?;
+    // Writes to a rolling index go to its write index
+    let index_name = state.search_engine.resolve_write_index(&index_name);
+
+    let (write_index, write_id) = (index_name.clone(), doc_id.clone());
+    run_write(&state, move |state| {
+        state.search_engine.delete_document(&write_index, &write_id)
+    })
+    .await
+    .map_err(|e| {
+        (
+            e.status_code(),
+            Json(ApiResponse::error(e.to_string())),
+        )
+    })?;
 
-    state
-        .search_engine
-        .delete_document(&index_name, &doc_id)
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ApiResponse::error(e.to_string())),
-            )
-        })?;
-
-    state.metadata_store.delete_document(&doc_id).map_err(|e| {
+    state.metadata_store.delete_document(&index_name, &doc_id).map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ApiResponse::error(e.to_string())),
@@ -217,40 +826,246 @@ pub async fn delete_document(
     ))
 }
 
-pub async fn search(
-    State(state): State<Arc<AppState>>,
-    Path(index_name): Path<String>,
-    Json(payload): Json<SearchRequest>,
-) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<SearchResponse>>)> {
-    validate_index_name(&index_name).map_err(|e| {
+/// Error response of a failed search. A search refused for its memory use comes with
+/// the estimate, so clients can tell which part of the request to scale down.
+fn search_error<T>(e: SearchError) -> (StatusCode, Json<ApiResponse<T>>) {
+    let response = ApiResponse::error(e.to_string());
+    let response = match &e {
+        SearchError::MemoryLimit(_, estimate) => response.with_details(serde_json::json!(estimate)),
+        _ => response,
+    };
+    (e.status_code(), Json(response))
+}
+
+/// Deployment pagination limits, lowered by the index's own settings
+fn pagination_limits(state: &AppState, index_name: &str) -> PaginationLimits {
+    // Searches across several indices get the strictest limits among them
+    state
+        .search_engine
+        .search_indices(index_name)
+        .iter()
+        .filter_map(|name| state.search_engine.index_settings(name))
+        .fold(state.pagination, |limits, settings| limits.for_index(&settings))
+}
+
+pub async fn search(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+    headers: HeaderMap,
+    payload: Result<Json<SearchRequest>, JsonRejection>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<SearchResponse>>)> {
+    validate_index_pattern(&index_name).map_err(|e| {
+        (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
+    })?;
+
+    // Deserialization errors (e.g. a negative offset) name the offending path
+    let Json(mut payload) = payload.map_err(|e| {
+        let status = match e.status() {
+            StatusCode::PAYLOAD_TOO_LARGE => StatusCode::PAYLOAD_TOO_LARGE,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        (status, Json(ApiResponse::error(e.body_text())))
+    })?;
+    // The preset's settings are checked and keyed like the request's own
+    if let Some(name) = &payload.preset {
+        let preset = state
+            .search_engine
+            .get_search_preset(&index_name, name)
+            .ok_or_else(|| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    Json(ApiResponse::error(format!("Unknown search preset: {}", name))),
+                )
+            })?;
+        preset.apply(&mut payload);
+    }
+    // The query log and reranking get the query as typed
+    let typed_query = payload.query.clone();
+    if payload.simple_query {
+        payload.query = query_syntax::simple_query(&payload.query);
+    } else if payload.escape_special_chars {
+        payload.query = query_syntax::escape(&payload.query);
+    }
+    state
+        .search_engine
+        .validate_search_request(&index_name, &payload)
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+    if let Some(regex) = &payload.regex {
+        validate_regex(regex).map_err(|e| {
+            (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
+        })?;
+    }
+    let access = auth::search_access(&state, &index_name, &headers)
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+    check_hidden_fields(&payload, &access.hidden_fields)
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+
+    let pagination = pagination_limits(&state, &index_name);
+    validate_offset(payload.offset, &pagination).map_err(|e| {
         (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
     })?;
+    let limit = clamp_pagination_limit(payload.limit, &pagination);
+
+    let mut reranker = None;
+    if let Some(options) = &payload.rerank {
+        validate_rerank(options).map_err(|e| {
+            (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
+        })?;
+        let settings = index_llm_settings(&state, &index_name, &LlmSettings::default());
+        let client = llm_client_for(&state, &settings).map_err(|e| {
+            (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
+        })?;
+        reranker = Some((client, options.top_k));
+    }
+    // Reranking reorders the top hits, so fetch them all and page afterwards
+    let (search_offset, search_limit) = match &reranker {
+        Some((_, top_k)) => (0, (payload.offset + limit).max(*top_k)),
+        None => (payload.offset, limit),
+    };
 
-    let limit = clamp_pagination_limit(payload.limit);
+    // Read-your-writes: hold the search until the client's commit is searchable
+    if let Some(generation) = payload.wait_for_generation {
+        wait_for_generation(&state.search_engine, &index_name, generation)
+            .await
+            .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+    }
+    // Read before searching, since the searchers may see later commits but not earlier
+    let generation = state.search_engine.index_generation(&index_name);
+
+    // Clients holding the current results get 304 without searching again. Reranked
+    // results depend on the LLM and looked-up documents on other indices, so they get
+    // no ETag, and neither do CSV and NDJSON responses.
+    let row_format = RowFormat::from_headers(&headers);
+    let cacheable = reranker.is_none() && payload.lookups.is_empty() && row_format.is_none();
+    let etag = match (cacheable, state.search_engine.commit_opstamp(&index_name)) {
+        (true, Some(opstamp)) => state.search_cache.etag(
+            &index_name,
+            opstamp,
+            state.search_engine.config_generation(),
+            &payload,
+            &access,
+        ),
+        _ => None,
+    };
+    if let Some(etag) = etag.as_ref().filter(|etag| http_cache::not_modified(&headers, etag)) {
+        let mut headers = state.search_cache.headers(Some(etag));
+        insert_generation_header(&mut headers, generation);
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
 
-    let (hits, total, took_ms, aggregations) = state
+    let (mut hits, total, took_ms, aggregations, aggregation_errors) = state
         .search_engine
         .search_with_options(
             &index_name,
             &payload.query,
-            limit,
-            payload.offset,
+            search_limit,
+            search_offset,
             &payload.fields,
             payload.highlight.as_ref(),
             &payload.aggregations,
             payload.fuzzy,
             payload.sort.as_ref(),
             payload.minimum_should_match,
+            payload.proximity,
+            payload.default_operator,
+            &payload.geo_filters,
+            &payload.filters,
+            payload.has_child.as_ref(),
+            payload.has_parent.as_ref(),
+            payload.regex.as_ref(),
+            payload.remove_stopwords,
+            &access,
         )
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ApiResponse::error(e.to_string())),
-            )
-        })?;
+        .map_err(search_error)?;
+    remove_hidden_fields(&mut hits, &access.hidden_fields);
+
+    // The query log counts each search once, on its first page. Searches across an
+    // index pattern aren't logged, since the log is kept per index.
+    if payload.offset == 0 && !index_patterns::is_pattern(&index_name) {
+        let query = typed_query.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+        if !query.is_empty() && query != "*" {
+            let session = search_session(&headers);
+            if let Err(e) = state.metadata_store.record_search(
+                &index_name,
+                &query,
+                total,
+                session.as_deref(),
+            ) {
+                tracing::warn!("Failed to record search for index '{}': {}", index_name, e);
+            }
+        }
+    }
+
+    let mut reranked = false;
+    if let Some((client, top_k)) = &reranker {
+        let mut redactions = state.redactor.session();
+        reranked =
+            rerank_hits(client, &mut redactions, &typed_query, &mut hits, *top_k).await;
+        hits = hits.into_iter().skip(payload.offset).take(limit).collect();
+    }
+    let lookup_keys = lookup::hit_keys(&payload.lookups, &hits);
+
+    // The displayed fields of a hit's index apply unless the request picks its own
+    if payload.include_fields.is_empty() {
+        let mut displayed_fields: HashMap<String, Vec<String>> = HashMap::new();
+        for hit in &mut hits {
+            let source = hit.index.as_deref().unwrap_or(&index_name);
+            let displayed = displayed_fields.entry(source.to_string()).or_insert_with(|| {
+                state
+                    .search_engine
+                    .index_settings(source)
+                    .map(|settings| settings.displayed_fields)
+                    .unwrap_or_default()
+            });
+            if !displayed.is_empty() {
+                hit.retain_displayed(displayed);
+            }
+        }
+    }
+
+    // Fields are renamed first so include/exclude refer to the names clients see
+    if !payload.field_mapping.is_empty() {
+        for hit in &mut hits {
+            hit.apply_field_mapping(&payload.field_mapping);
+        }
+    }
+    if !payload.include_fields.is_empty() || !payload.exclude_fields.is_empty() {
+        for hit in &mut hits {
+            hit.retain_fields(&payload.include_fields, &payload.exclude_fields);
+        }
+    }
+    lookup::enrich_hits(&state, &headers, &payload.lookups, lookup_keys, &mut hits)
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
 
     let has_more = payload.offset + hits.len() < total;
 
+    // Rows only hold the hits; the total is sent in a header
+    if let Some(row_format) = row_format {
+        let rows: Vec<_> = hits.iter().map(formats::hit_row).collect();
+        let columns = if payload.columns.is_empty() {
+            formats::columns_of(&rows)
+        } else {
+            payload.columns.clone()
+        };
+        let writer = RowWriter::new(row_format, columns);
+        let text = writer.header() + &writer.rows(&rows);
+        let mut headers = state.search_cache.headers(None);
+        insert_generation_header(&mut headers, generation);
+        headers.insert("x-total-count", total.into());
+        let chunks = futures_util::stream::iter([Ok::<String, Infallible>(text)]);
+        let body = formats::response(row_format, chunks);
+        return Ok((headers, body).into_response());
+    }
+
+    // Widgets get the hits alone, with their fields next to the ID
+    if payload.response_format == ResponseFormat::Flat {
+        let hits = formats::flat_rows(&hits, &payload.columns);
+        let mut headers = state.search_cache.headers(etag.as_ref());
+        insert_generation_header(&mut headers, generation);
+        headers.insert("x-total-count", total.into());
+        return Ok((headers, Json(hits)).into_response());
+    }
+
     let response = SearchResponse {
         took_ms,
         total,
@@ -259,84 +1074,605 @@ pub async fn search(
         has_more,
         hits,
         aggregations,
+        aggregation_errors,
+        reranked,
+        generation,
     };
 
-    Ok(Json(ApiResponse::success(response)))
+    let mut headers = state.search_cache.headers(etag.as_ref());
+    insert_generation_header(&mut headers, generation);
+    Ok((headers, Json(ApiResponse::success(response))).into_response())
+}
+
+/// Longest a search waits for `wait_for_generation`
+const GENERATION_WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the index is checked while waiting for a generation
+const GENERATION_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Wait until an index's commit generation reaches `generation`. Searches open a reader
+/// on the latest commit, so a committed generation is searchable right away.
+async fn wait_for_generation(
+    engine: &SearchEngine,
+    index_name: &str,
+    generation: u64,
+) -> Result<(), SearchError> {
+    if index_patterns::is_pattern(index_name) {
+        return Err(SearchError::InvalidQuery(
+            "wait_for_generation can't be used with index patterns".to_string(),
+        ));
+    }
+    let started = Instant::now();
+    loop {
+        let current = engine
+            .index_generation(index_name)
+            .ok_or_else(|| SearchError::IndexNotFound(index_name.to_string()))?;
+        if current >= generation {
+            return Ok(());
+        }
+        if started.elapsed() >= GENERATION_WAIT_TIMEOUT {
+            return Err(SearchError::Timeout(format!(
+                "Index '{}' did not reach generation {} within {}s (current generation {})",
+                index_name,
+                generation,
+                GENERATION_WAIT_TIMEOUT.as_secs(),
+                current
+            )));
+        }
+        tokio::time::sleep(GENERATION_POLL_INTERVAL).await;
+    }
+}
+
+/// Name the commit generation a response reflects in `X-Index-Generation`
+fn insert_generation_header(headers: &mut HeaderMap, generation: Option<u64>) {
+    if let Some(generation) = generation {
+        headers.insert("x-index-generation", generation.into());
+    }
+}
+
+/// Reject requests naming a field the caller isn't allowed to see
+fn check_hidden_fields(
+    request: &SearchRequest,
+    hidden_fields: &[String],
+) -> Result<(), SearchError> {
+    match field_access::hidden_request_field(request, hidden_fields) {
+        Some(field) => Err(SearchError::RestrictedField(field)),
+        None => Ok(()),
+    }
+}
+
+/// Drop restricted fields from hits before they are returned or sent to the LLM
+fn remove_hidden_fields(hits: &mut [SearchHit], hidden_fields: &[String]) {
+    if hidden_fields.is_empty() {
+        return;
+    }
+    for hit in hits {
+        hit.remove_hidden(hidden_fields);
+    }
+}
+
+/// Reject a provider the LLM client doesn't know. Without a configured LLM there is
+/// nothing to check against, and requests fail later with 501.
+fn check_llm_provider(
+    state: &AppState,
+    settings: &LlmSettings,
+) -> Result<(), (StatusCode, Json<ApiResponse<()>>)> {
+    match (&state.llm_client, &settings.provider) {
+        (Some(client), Some(provider)) if !client.has_provider(provider) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(format!("Unknown LLM provider: {}", provider))),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// LLM options for a request on an index: the index's `llm` settings with the request's
+/// `overrides` applied on top
+fn index_llm_settings(state: &AppState, index_name: &str, overrides: &LlmSettings) -> LlmSettings {
+    state
+        .search_engine
+        .index_settings(index_name)
+        .and_then(|settings| settings.llm)
+        .unwrap_or_default()
+        .merged(overrides)
+}
+
+/// LLM client sending to the provider and model chosen in `settings`
+fn llm_client_for(
+    state: &AppState,
+    settings: &LlmSettings,
+) -> Result<LlmClient, (StatusCode, Json<ApiResponse<()>>)> {
+    let Some(llm_client) = &state.llm_client else {
+        return Err((
+            StatusCode::NOT_IMPLEMENTED,
+            Json(ApiResponse::error("MISTRAL_API_KEY not configured".to_string())),
+        ));
+    };
+    check_llm_provider(state, settings)?;
+    llm_client
+        .with_model(settings.provider.as_deref(), settings.model.as_deref())
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(ApiResponse::error(e.to_string()))))
+}
+
+/// Documents summarized by the LLM at the same time
+const SUMMARY_CONCURRENCY: usize = 4;
+
+/// Add LLM summaries to documents for indices that summarize with the LLM. Documents whose
+/// summary fails are left as they are and get an extractive summary when indexed.
+async fn add_llm_summaries(state: &AppState, index_name: &str, documents: Vec<&mut Document>) {
+    let Some(settings) = state
+        .search_engine
+        .index_settings(index_name)
+        .and_then(|settings| settings.summary)
+        .filter(|settings| settings.method == SummaryMethod::Llm)
+    else {
+        return;
+    };
+    let llm_settings = index_llm_settings(state, index_name, &LlmSettings::default());
+    let llm_client = match llm_client_for(state, &llm_settings) {
+        Ok(llm_client) => llm_client,
+        Err((_, Json(response))) => {
+            if state.llm_client.is_some() {
+                tracing::warn!("Summarizing with the LLM: {}", response.error.unwrap_or_default());
+            }
+            return;
+        }
+    };
+    let llm_client = &llm_client;
+
+    let (pending, texts): (Vec<&mut Document>, Vec<String>) = documents
+        .into_iter()
+        .filter_map(|doc| {
+            let text = summary::text_to_summarize(&doc.fields, &settings)?.to_string();
+            Some((doc, text))
+        })
+        .unzip();
+    let max_chars = settings.max_chars;
+    let summaries: Vec<Option<String>> = futures_util::stream::iter(texts.into_iter().map(
+        |text| async move { summarize_text(state, llm_client, &text, max_chars).await },
+    ))
+    .buffered(SUMMARY_CONCURRENCY)
+    .collect()
+    .await;
+
+    for (doc, summary) in pending.into_iter().zip(summaries) {
+        if let Some(summary) = summary {
+            doc.fields.insert(settings.target_field.clone(), summary.into());
+        }
+    }
+}
+
+/// Ask the LLM for a summary of at most `max_chars` characters. Failures are logged and
+/// yield `None`.
+async fn summarize_text(
+    state: &AppState,
+    llm_client: &LlmClient,
+    text: &str,
+    max_chars: usize,
+) -> Option<String> {
+    let mut redactions = state.redactor.session();
+    let mut messages = summary::summary_messages(text, max_chars);
+    redactions.redact_messages(&mut messages);
+    let request = ChatCompletionRequest {
+        model: llm_client.model().to_string(),
+        messages,
+        temperature: Some(0.0),
+        // Roughly four characters per token, with room for the model to overshoot
+        max_tokens: Some((max_chars / 2 + 50) as u32),
+        stream: false,
+    };
+    let reply = match llm_client.complete(request).await {
+        Ok(response) => response.choices.into_iter().next()?.message.content,
+        Err(e) => {
+            tracing::warn!("Summarizing document failed: {}", e);
+            return None;
+        }
+    };
+    summary::parse_summary(&redactions.restore(&reply), max_chars)
+}
+
+/// Reorder the first `top_k` hits by asking the LLM to rank them. Failures are logged
+/// and leave the order unchanged; returns whether the hits were reordered.
+async fn rerank_hits(
+    llm_client: &LlmClient,
+    redactions: &mut Redactions,
+    query: &str,
+    hits: &mut Vec<SearchHit>,
+    top_k: usize,
+) -> bool {
+    let count = top_k.min(hits.len());
+    if count < 2 {
+        return false;
+    }
+
+    let mut messages = rerank::rerank_messages(query, &hits[..count]);
+    redactions.redact_messages(&mut messages);
+    let request = ChatCompletionRequest {
+        model: llm_client.model().to_string(),
+        messages,
+        temperature: Some(0.0),
+        max_tokens: Some(200),
+        stream: false,
+    };
+    let reply = match llm_client.complete(request).await {
+        Ok(response) => response
+            .choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message.content)
+            .unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Reranking failed: {}", e);
+            return false;
+        }
+    };
+
+    match rerank::parse_ranking(&reply, count) {
+        Some(order) => {
+            rerank::apply_ranking(hits, &order);
+            true
+        }
+        None => {
+            tracing::warn!("Ignoring unusable reranker reply: {}", reply);
+            false
+        }
+    }
+}
+
+/// Ask the LLM how well the sources support each sentence of an answer. Failures are
+/// logged and yield `None`.
+async fn check_groundedness(
+    llm_client: &LlmClient,
+    redactions: &mut Redactions,
+    answer: &str,
+    sources_text: &str,
+) -> Option<Groundedness> {
+    let sentences = chunking::sentence_spans(answer);
+    if sentences.is_empty() {
+        return None;
+    }
+
+    let mut messages = groundedness::verification_messages(answer, &sentences, sources_text);
+    redactions.redact_messages(&mut messages);
+    let request = ChatCompletionRequest {
+        model: llm_client.model().to_string(),
+        messages,
+        temperature: Some(0.0),
+        max_tokens: Some(500),
+        stream: false,
+    };
+    let reply = match llm_client.complete(request).await {
+        Ok(response) => response.choices.into_iter().next()?.message.content,
+        Err(e) => {
+            tracing::warn!("Groundedness check failed: {}", e);
+            return None;
+        }
+    };
+
+    let groundedness = groundedness::parse_groundedness(&reply, answer, &sentences);
+    if groundedness.is_none() {
+        tracing::warn!("Ignoring unusable groundedness reply: {}", reply);
+    }
+    groundedness
+}
+
+/// Ask the LLM to rewrite a question into keywords and filters for an index, reusing
+/// cached rewrites. Failures are logged and yield `None`, so the question is searched as is.
+async fn rewrite_question(
+    state: &AppState,
+    llm_client: &LlmClient,
+    redactions: &mut Redactions,
+    index_name: &str,
+    question: &str,
+) -> Option<RewrittenQuery> {
+    if let Some(rewritten) = state.query_rewrites.get(index_name, question) {
+        return Some(rewritten);
+    }
+
+    let fields = state.search_engine.index_fields(index_name)?;
+    let mut messages = rewrite::rewrite_messages(question, &fields);
+    redactions.redact_messages(&mut messages);
+    let request = ChatCompletionRequest {
+        model: llm_client.model().to_string(),
+        messages,
+        temperature: Some(0.0),
+        max_tokens: Some(200),
+        stream: false,
+    };
+    let reply = match llm_client.complete(request).await {
+        Ok(response) => response.choices.into_iter().next()?.message.content,
+        Err(e) => {
+            tracing::warn!("Query rewrite failed for index '{}': {}", index_name, e);
+            return None;
+        }
+    };
+
+    // Redacted values are put back so the rewritten query searches for them
+    let rewritten = rewrite::parse_rewrite(&redactions.restore(&reply), &fields);
+    match &rewritten {
+        Some(rewritten) => {
+            state
+                .query_rewrites
+                .insert(index_name, question, rewritten.clone())
+        }
+        None => tracing::warn!("Ignoring unusable query rewrite: {}", reply),
+    }
+    rewritten
+}
+
+/// Answer stream event with a JSON payload
+fn json_event(name: &str, data: &impl serde::Serialize) -> Event {
+    Event::default()
+        .event(name)
+        .data(serde_json::to_string(data).unwrap_or_default())
+}
+
+/// `token` event with the next piece of answer text
+fn token_event(text: &str) -> Event {
+    json_event("token", &serde_json::json!({ "text": text }))
+}
+
+/// `usage` event with token counts (`null` when the provider doesn't report them) and timings
+fn usage_event(usage: Option<&Usage>, llm_took_ms: f64, total_took_ms: f64) -> Event {
+    let usage = serde_json::json!({
+        "prompt_tokens": usage.map(|u| u.prompt_tokens),
+        "completion_tokens": usage.map(|u| u.completion_tokens),
+        "total_tokens": usage.map(|u| u.total_tokens),
+        "llm_took_ms": llm_took_ms,
+        "total_took_ms": total_took_ms,
+    });
+    json_event("usage", &usage)
+}
+
+/// Final `done` event with the complete answer and all of its citations
+fn done_event(answer: &str, citations: &[Citation]) -> Event {
+    json_event("done", &serde_json::json!({ "answer": answer, "citations": citations }))
+}
+
+/// Events replaying a cached answer in the same shape as a live answer stream
+fn cached_answer_events(cached: &AnswerResponse) -> Vec<Event> {
+    let meta = serde_json::json!({
+        "model": cached.model,
+        "search_took_ms": cached.search_took_ms,
+        "sources": cached.sources,
+        "rewritten_query": cached.rewritten_query,
+        "cached": true,
+    });
+
+    let mut events = vec![json_event("meta", &meta), token_event(&cached.answer)];
+    events.extend(cached.citations.iter().map(|citation| json_event("citation", citation)));
+    if let Some(groundedness) = &cached.groundedness {
+        events.push(json_event("groundedness", groundedness));
+    }
+    events.push(usage_event(cached.usage.as_ref(), cached.llm_took_ms, cached.total_took_ms));
+    events.push(done_event(&cached.answer, &cached.citations));
+    events
 }
 
 pub async fn answer(
     State(state): State<Arc<AppState>>,
     Path(index_name): Path<String>,
+    headers: HeaderMap,
     Json(payload): Json<AnswerRequest>,
 ) -> Result<Response, (StatusCode, Json<ApiResponse<()>>)> {
     validate_index_name(&index_name).map_err(|e| {
         (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
     })?;
 
-    let llm_client = match state.llm_client.clone() {
-        Some(client) => client,
-        None => {
-            return Err((
-                StatusCode::NOT_IMPLEMENTED,
-                Json(ApiResponse::error(
-                    "MISTRAL_API_KEY not configured".to_string(),
-                )),
-            ))
+    validate_llm_settings(&payload.llm_settings())?;
+    let llm_settings = index_llm_settings(&state, &index_name, &payload.llm_settings());
+    let llm_client = llm_client_for(&state, &llm_settings)?;
+
+    state
+        .search_engine
+        .validate_search_request(&index_name, &payload.search_request())
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+    let access = auth::search_access(&state, &index_name, &headers)
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+    let context_field = payload
+        .context
+        .fields
+        .iter()
+        .find(|field| field_access::is_hidden(field, &access.hidden_fields));
+    let restricted = match context_field {
+        Some(field) => Err(SearchError::RestrictedField(field.clone())),
+        None => check_hidden_fields(&payload.search_request(), &access.hidden_fields),
+    };
+    restricted.map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+    validate_chunking(&payload.chunking)?;
+    if let Some(options) = &payload.rerank {
+        validate_rerank(options)?;
+    }
+    if let Some(fields) = state.search_engine.index_fields(&index_name) {
+        validate_context(&payload.context, &fields)?;
+    }
+    let template = match &payload.prompt_template {
+        Some(name) => Some(
+            state
+                .search_engine
+                .get_prompt_template(&index_name, name)
+                .ok_or_else(|| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        Json(ApiResponse::error(format!("Unknown prompt template: {}", name))),
+                    )
+                })?,
+        ),
+        None => None,
+    };
+
+    // Answers are reused until they expire or the index changes; cache hits don't
+    // call the LLM, so they skip the answer budget
+    let cache_entry = if payload.cache && state.answer_cache.is_enabled() {
+        state.search_engine.commit_opstamp(&index_name).map(|opstamp| {
+            let key = answer_cache::CacheKey::new(&index_name, &payload, template.as_ref())
+                .for_access(&access);
+            (key, opstamp)
+        })
+    } else {
+        None
+    };
+    if let Some((key, opstamp)) = &cache_entry {
+        if let Some(mut cached) = state.answer_cache.get(key, *opstamp) {
+            cached.cached = true;
+            if payload.stream {
+                let events = cached_answer_events(&cached).into_iter().map(Ok::<Event, Infallible>);
+                return Ok(Sse::new(futures_util::stream::iter(events)).into_response());
+            }
+            return Ok(Json(ApiResponse::success(cached)).into_response());
         }
+    }
+
+    // Every answer is an LLM call, so callers are limited per API key
+    let api_key = quota::api_key_id(&headers);
+    let budget = &state.answer_budget;
+    let Some(in_flight) = budget.try_acquire(&api_key) else {
+        return Ok((
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(ApiResponse::<()>::error(format!(
+                "Too many concurrent answer requests (maximum {})",
+                budget.max_concurrent.unwrap_or_default()
+            ))),
+        )
+            .into_response());
     };
 
-    let limit = clamp_pagination_limit(payload.search_limit);
+    let mut rate_limit_headers = HeaderMap::new();
+    if budget.has_quota() {
+        let now = chrono::Utc::now();
+        let (day, month) = quota::current_periods(now);
+        let usage = state
+            .metadata_store
+            .consume_answer_quota(
+                &api_key,
+                (&day, &month),
+                budget.daily_quota,
+                budget.monthly_quota,
+            )
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ApiResponse::error(e.to_string())),
+                )
+            })?;
+        rate_limit_headers = quota::rate_limit_headers(budget, &usage, now);
+        if !usage.allowed {
+            return Ok((
+                StatusCode::TOO_MANY_REQUESTS,
+                rate_limit_headers,
+                Json(ApiResponse::<()>::error("Answer quota exceeded".to_string())),
+            )
+                .into_response());
+        }
+    }
+
+    let limit =
+        clamp_pagination_limit(payload.search_limit, &pagination_limits(&state, &index_name));
     let total_start = Instant::now();
 
-    let (hits, _total, search_took_ms, _aggregations) = state
-        .search_engine
-        .search_with_options(
+    // With reranking, the best `search_limit` hits are picked from the top `top_k`
+    let search_depth = payload
+        .rerank
+        .as_ref()
+        .map_or(limit, |options| options.top_k.max(limit));
+    let search = |query: &str| {
+        state.search_engine.search_with_options(
             &index_name,
-            &payload.query,
-            limit,
+            query,
+            search_depth,
             0,
             &payload.fields,
             None,
             &[],
             payload.fuzzy,
-            None,
+            payload.sort.as_ref(),
             None, // minimum_should_match not needed for generative search
+            None,
+            None,
+            &payload.geo_filters,
+            &payload.filters,
+            None,
+            None,
+            None,
+            None,
+            &access,
         )
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ApiResponse::error(e.to_string())),
-            )
-        })?;
+    };
 
-    let mut sources_lines = Vec::new();
-    for (idx, hit) in hits.iter().enumerate() {
-        let fields_json = serde_json::to_string(&hit.fields).unwrap_or_default();
-        sources_lines.push(format!(
-            "[{}] id={} score={:.3} fields={}",
-            idx + 1,
-            hit.id,
-            hit.score,
-            fields_json
-        ));
+    // Personal data is replaced with placeholders in everything sent to the LLM
+    let mut redactions = state.redactor.session();
+
+    let mut rewritten_query = None;
+    if payload.rewrite_query {
+        rewritten_query = rewrite_question(
+            &state,
+            &llm_client,
+            &mut redactions,
+            &index_name,
+            &payload.query,
+        )
+        .await;
+    }
+    // A rewrite that finds nothing (or doesn't parse) falls back to the question itself
+    let rewritten_results = rewritten_query
+        .as_ref()
+        .and_then(|rewritten| search(&rewrite::to_query_string(rewritten)).ok())
+        .filter(|(hits, ..)| !hits.is_empty());
+    if rewritten_results.is_none() {
+        rewritten_query = None;
+    }
+    let (mut hits, _total, search_took_ms, _aggregations, _aggregation_errors) =
+        match rewritten_results {
+            Some(results) => results,
+            None => search(&payload.query).map_err(search_error)?,
+        };
+    remove_hidden_fields(&mut hits, &access.hidden_fields);
+
+    if let Some(options) = &payload.rerank {
+        rerank_hits(
+            &llm_client,
+            &mut redactions,
+            &payload.query,
+            &mut hits,
+            options.top_k,
+        )
+        .await;
+        hits.truncate(limit);
     }
 
-    let sources_text = if sources_lines.is_empty() {
+    // Long text fields are cut into passages so the prompt fits the context window
+    let mut sources = chunking::answer_sources(&hits, &payload.query, &payload.chunking);
+
+    // Sources that don't fit in the context are dropped, so `sources` matches the prompt
+    let (sources_text, included) = context::build_context(&sources, &payload.context);
+    sources.truncate(included);
+    let sources_text = if sources.is_empty() {
         "No sources found.".to_string()
     } else {
-        sources_lines.join("\n")
+        sources_text
     };
 
-    let system_prompt = payload.system_prompt.unwrap_or_else(|| {
-        "You are a helpful assistant. Answer the user's question using only the provided sources. If the answer is not contained in the sources, say you don't know. Use the input language for your answer.".to_string()
-    });
+    // An explicit system prompt wins over the template's
+    let render = |prompt: &str| prompts::render(prompt, &payload.query, &sources_text);
+    let system_prompt = payload
+        .system_prompt
+        .clone()
+        .or_else(|| template.as_ref()?.system.as_deref().map(render))
+        .unwrap_or_else(|| {
+            "You are a helpful assistant. Answer the user's question using only the provided sources. If the answer is not contained in the sources, say you don't know. Use the input language for your answer.".to_string()
+        });
 
-    let user_prompt = format!(
-        "Question: {}\n\nSources:\n{}",
-        payload.query, sources_text
-    );
+    let user_prompt = match template.as_ref().and_then(|t| t.user.as_deref()) {
+        Some(user) => format!("{}\n\n{}", render(user), citations::CITATION_INSTRUCTION),
+        None => format!(
+            "Question: {}\n\nSources:\n{}\n\n{}",
+            payload.query,
+            sources_text,
+            citations::CITATION_INSTRUCTION
+        ),
+    };
 
-    let messages = vec![
+    let mut messages = vec![
         ChatMessage {
             role: "system".to_string(),
             content: system_prompt,
@@ -346,34 +1682,47 @@ pub async fn answer(
             content: user_prompt,
         },
     ];
+    redactions.redact_messages(&mut messages);
 
     let llm_request = ChatCompletionRequest {
         model: llm_client.model().to_string(),
         messages,
-        temperature: payload.temperature,
-        max_tokens: payload.max_tokens,
+        temperature: llm_settings.temperature,
+        max_tokens: llm_settings.max_tokens,
         stream: payload.stream,
     };
 
     if payload.stream {
-        let response = llm_client.stream(llm_request).await.map_err(|e| {
+        let llm_start = Instant::now();
+        let (response, model) = llm_client.stream(llm_request).await.map_err(|e| {
             (
                 StatusCode::BAD_GATEWAY,
                 Json(ApiResponse::error(e.to_string())),
             )
         })?;
 
-        let model = llm_client.model().to_string();
         let meta = serde_json::json!({
             "model": model,
             "search_took_ms": search_took_ms,
-            "sources": hits,
+            "sources": sources,
+            "rewritten_query": rewritten_query,
         });
+        let stream_sources = sources.clone();
+        let verify = payload.verify.then(|| (llm_client.clone(), sources_text.clone()));
+        let cache = cache_entry.map(|entry| (state.clone(), entry));
 
         let stream = async_stream::stream! {
-            yield Ok::<Event, Infallible>(Event::default().event("meta").data(meta.to_string()));
+            // Hold the concurrency slot until the stream finishes
+            let _in_flight = in_flight;
+            let mut redactions = redactions;
+            yield Ok::<Event, Infallible>(json_event("meta", &meta));
 
             let mut buffer = String::new();
+            let mut answer = String::new();
+            // Text held back until a possibly split placeholder is complete
+            let mut pending = String::new();
+            let mut citations = Vec::new();
+            let mut usage = None;
             let mut bytes_stream = response.bytes_stream();
 
             while let Some(chunk) = bytes_stream.next().await {
@@ -392,15 +1741,64 @@ pub async fn answer(
                             if let Some(data) = trimmed.strip_prefix("data:") {
                                 let data = data.trim();
                                 if data == "[DONE]" {
-                                    yield Ok::<Event, Infallible>(Event::default().event("done").data(""));
+                                    let rest = redactions.restore_chunk(&mut pending, "", true);
+                                    if !rest.is_empty() {
+                                        answer.push_str(&rest);
+                                        yield Ok::<Event, Infallible>(token_event(&rest));
+                                    }
+                                    // Markers completed by the held-back text
+                                    for citation in citations::extract_citations(&answer, &stream_sources).into_iter().skip(citations.len()) {
+                                        yield Ok::<Event, Infallible>(json_event("citation", &citation));
+                                        citations.push(citation);
+                                    }
+                                    let llm_took_ms = llm_start.elapsed().as_secs_f64() * 1000.0;
+                                    let mut groundedness = None;
+                                    if let Some((client, sources_text)) = &verify {
+                                        groundedness = check_groundedness(client, &mut redactions, &answer, sources_text).await;
+                                        if let Some(groundedness) = &groundedness {
+                                            yield Ok::<Event, Infallible>(json_event("groundedness", groundedness));
+                                        }
+                                    }
+                                    let total_took_ms = total_start.elapsed().as_secs_f64() * 1000.0;
+                                    yield Ok::<Event, Infallible>(usage_event(usage.as_ref(), llm_took_ms, total_took_ms));
+                                    yield Ok::<Event, Infallible>(done_event(&answer, &citations));
+                                    if let Some((state, (key, opstamp))) = &cache {
+                                        let response = AnswerResponse {
+                                            answer,
+                                            model: model.clone(),
+                                            search_took_ms,
+                                            llm_took_ms,
+                                            total_took_ms,
+                                            sources: stream_sources.clone(),
+                                            citations,
+                                            rewritten_query: rewritten_query.clone(),
+                                            groundedness,
+                                            cached: false,
+                                            usage,
+                                        };
+                                        state.answer_cache.insert(key.clone(), *opstamp, response);
+                                    }
                                     return;
                                 }
 
                                 match serde_json::from_str::<ChatCompletionStreamChunk>(data) {
                                     Ok(chunk) => {
+                                        if chunk.usage.is_some() {
+                                            usage = chunk.usage;
+                                        }
                                         for choice in chunk.choices {
                                             if let Some(content) = choice.delta.content {
-                                                yield Ok::<Event, Infallible>(Event::default().data(content));
+                                                let content = redactions.restore_chunk(&mut pending, &content, false);
+                                                if content.is_empty() {
+                                                    continue;
+                                                }
+                                                answer.push_str(&content);
+                                                yield Ok::<Event, Infallible>(token_event(&content));
+                                                // Citations are sent as soon as their marker is complete
+                                                for citation in citations::extract_citations(&answer, &stream_sources).into_iter().skip(citations.len()) {
+                                                    yield Ok::<Event, Infallible>(json_event("citation", &citation));
+                                                    citations.push(citation);
+                                                }
                                             }
                                         }
                                     }
@@ -425,7 +1823,7 @@ pub async fn answer(
                 .text("keep-alive"),
         );
 
-        return Ok(sse.into_response());
+        return Ok((rate_limit_headers, sse).into_response());
     }
 
     let llm_start = Instant::now();
@@ -439,22 +1837,39 @@ pub async fn answer(
     let answer = response
         .choices
         .first()
-        .map(|choice| choice.message.content.clone())
+        .map(|choice| redactions.restore(&choice.message.content))
         .unwrap_or_default();
+    let model = response.model;
+    let usage = response.usage;
 
+    let citations = citations::extract_citations(&answer, &sources);
     let llm_took_ms = llm_start.elapsed().as_secs_f64() * 1000.0;
+    let groundedness = if payload.verify {
+        check_groundedness(&llm_client, &mut redactions, &answer, &sources_text).await
+    } else {
+        None
+    };
     let total_took_ms = total_start.elapsed().as_secs_f64() * 1000.0;
 
     let response = AnswerResponse {
         answer,
-        model: llm_client.model().to_string(),
+        model,
         search_took_ms,
         llm_took_ms,
         total_took_ms,
-        sources: hits,
+        sources,
+        citations,
+        rewritten_query,
+        groundedness,
+        cached: false,
+        usage,
     };
+    if let Some((key, opstamp)) = cache_entry {
+        state.answer_cache.insert(key, opstamp, response.clone());
+    }
 
-    Ok(Json(ApiResponse::success(response)).into_response())
+    drop(in_flight);
+    Ok((rate_limit_headers, Json(ApiResponse::success(response))).into_response())
 }
 
 pub async fn get_index_stats(
@@ -480,196 +1895,446 @@ pub async fn get_index_stats(
         )
     })?;
 
-    let stats = state
+    let mut stats = state
         .search_engine
         .get_index_stats(&name, &index_info.created_at)
         .map_err(|e| {
+            (
+                e.status_code(),
+                Json(ApiResponse::error(e.to_string())),
+            )
+        })?;
+
+    stats.metadata_document_count =
+        state.metadata_store.get_document_count(&name).map_err(|e| {
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(ApiResponse::error(e.to_string())),
             )
         })?;
+    stats.last_sync = state.metadata_sync.get(&name);
+    stats.last_warmup = state.warmup.last_report(&name);
+    stats.write_queue = state.write_queues.stats(&name);
 
     Ok(Json(ApiResponse::success(stats)))
 }
 
-pub async fn suggest(
+/// Type, analyzer and capabilities of each field of an index. Fields hidden from the
+/// caller's search key are left out.
+pub async fn get_field_capabilities(
     State(state): State<Arc<AppState>>,
-    Path(index_name): Path<String>,
-    Json(payload): Json<SuggestRequest>,
-) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<SuggestResponse>>)> {
-    validate_index_name(&index_name).map_err(|e| {
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<Vec<FieldCapabilities>>>)> {
+    validate_index_name(&name).map_err(|e| {
         (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
     })?;
 
-    let (suggestions, took_ms) = state
-        .search_engine
-        .suggest(
-            &index_name,
-            &payload.prefix,
-            payload.field.as_deref(),
-            payload.limit,
+    let access = auth::search_access(&state, &name, &headers)
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+    let mut fields = state.search_engine.field_capabilities(&name).map_err(|e| {
+        (
+            e.status_code(),
+            Json(ApiResponse::error(e.to_string())),
         )
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ApiResponse::error(e.to_string())),
-            )
-        })?;
-
-    let response = SuggestResponse {
-        suggestions,
-        took_ms,
-    };
+    })?;
+    fields.retain(|field| !field_access::is_hidden(&field.name, &access.hidden_fields));
 
-    Ok(Json(ApiResponse::success(response)))
+    Ok(Json(ApiResponse::success(fields)))
 }
 
-pub async fn bulk_operation(
+/// Run text through an analyzer and return its tokens, to see why a query does or
+/// doesn't match
+pub async fn analyze(
     State(state): State<Arc<AppState>>,
-    Path(index_name): Path<String>,
-    Json(payload): Json<BulkRequest>,
-) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<BulkResponse>>)> {
-    validate_index_name(&index_name).map_err(|e| {
+    Path(name): Path<String>,
+    Json(payload): Json<AnalyzeRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<AnalyzeResponse>>)> {
+    validate_index_name(&name).map_err(|e| {
         (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
     })?;
-    validate_bulk_operation_count(payload.operations.len()).map_err(|e| {
+
+    let (analyzer, tokens) = state
+        .search_engine
+        .analyze(
+            &name,
+            &payload.text,
+            payload.analyzer.as_deref(),
+            payload.field.as_deref(),
+        )
+        .map_err(|e| {
+            (
+                e.status_code(),
+                Json(ApiResponse::error(e.to_string())),
+            )
+        })?;
+
+    Ok(Json(ApiResponse::success(AnalyzeResponse { analyzer, tokens })))
+}
+
+/// Terms of a document's fields with their frequencies and positions. Fields hidden
+/// from the caller are left out.
+pub async fn get_term_vectors(
+    State(state): State<Arc<AppState>>,
+    Path((name, id)): Path<(String, String)>,
+    Query(params): Query<TermVectorsParams>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<TermVectors>>)> {
+    validate_index_name(&name).map_err(|e| {
         (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
     })?;
 
-    let mut successful = 0;
-    let mut failed = 0;
-    let mut errors = Vec::new();
-
-    for (idx, op) in payload.operations.iter().enumerate() {
-        let result = match op.operation.as_str() {
-            "index" => {
-                if let Some(doc) = &op.document {
-                    match state
-                        .search_engine
-                        .add_documents(&index_name, std::slice::from_ref(doc))
-                    {
-                        Ok(_) => {
-                            let _ = state.metadata_store.add_document(&index_name, &doc.id);
-                            Ok(())
-                        }
-                        Err(e) => Err(e),
-                    }
-                } else {
-                    Err(anyhow::anyhow!("Missing document for index operation"))
-                }
-            }
-            "delete" => {
-                if let Some(id) = &op.id {
-                    match state.search_engine.delete_document(&index_name, id) {
-                        Ok(_) => {
-                            let _ = state.metadata_store.delete_document(id);
-                            Ok(())
-                        }
-                        Err(e) => Err(e),
-                    }
-                } else {
-                    Err(anyhow::anyhow!("Missing id for delete operation"))
-                }
-            }
-            _ => Err(anyhow::anyhow!("Unknown operation: {}", op.operation)),
-        };
-
-        match result {
-            Ok(_) => successful += 1,
-            Err(e) => {
-                failed += 1;
-                errors.push(format!("Operation {} failed: {}", idx, e));
-            }
-        }
+    let access = auth::search_access(&state, &name, &headers)
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+    let fields: Vec<String> = params
+        .fields
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(str::to_string)
+        .collect();
+    if let Some(field) = fields
+        .iter()
+        .find(|field| field_access::is_hidden(field, &access.hidden_fields))
+    {
+        let e = SearchError::RestrictedField(field.clone());
+        return Err((e.status_code(), Json(ApiResponse::error(e.to_string()))));
     }
 
-    let response = BulkResponse {
-        total: payload.operations.len(),
-        successful,
-        failed,
-        errors,
-    };
+    let mut vectors = state
+        .search_engine
+        .term_vectors(&name, &id, &fields)
+        .map_err(|e| {
+            (
+                e.status_code(),
+                Json(ApiResponse::error(e.to_string())),
+            )
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(ApiResponse::error(format!("Document not found: {}", id))),
+            )
+        })?;
+    vectors
+        .fields
+        .retain(|field| !field_access::is_hidden(&field.field, &access.hidden_fields));
 
-    Ok(Json(ApiResponse::success(response)))
+    Ok(Json(ApiResponse::success(vectors)))
 }
 
-/// Add synonyms to an index
-pub async fn add_synonyms(
+/// Browse the term dictionary of a field, with the number of documents containing
+/// each term
+pub async fn list_terms(
     State(state): State<Arc<AppState>>,
-    Path(index_name): Path<String>,
-    Json(payload): Json<AddSynonymsRequest>,
-) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
-    validate_index_name(&index_name).map_err(|e| {
+    Path(name): Path<String>,
+    Query(params): Query<TermsParams>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<TermsResponse>>)> {
+    validate_index_name(&name).map_err(|e| {
         (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
     })?;
+    let field = params.field.ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error("Query parameter 'field' is required".to_string())),
+        )
+    })?;
 
-    state
+    let access = auth::search_access(&state, &name, &headers)
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+    if field_access::is_hidden(&field, &access.hidden_fields) {
+        let e = SearchError::RestrictedField(field);
+        return Err((e.status_code(), Json(ApiResponse::error(e.to_string()))));
+    }
+
+    let limit = params.limit.clamp(1, MAX_TERMS_LIMIT);
+    let terms = state
         .search_engine
-        .add_synonyms(&index_name, payload.synonyms)
+        .list_terms(&name, &field, &params.prefix, params.after.as_deref(), limit)
         .map_err(|e| {
             (
-                StatusCode::INTERNAL_SERVER_ERROR,
+                e.status_code(),
                 Json(ApiResponse::error(e.to_string())),
             )
         })?;
+    let next = (terms.len() == limit)
+        .then(|| terms.last().map(|term| term.term.clone()))
+        .flatten();
 
-    Ok(Json(ApiResponse::success(serde_json::json!({
-        "message": "Synonyms added successfully"
-    }))))
+    Ok(Json(ApiResponse::success(TermsResponse { field, terms, next })))
 }
 
-/// Get synonyms for an index
-pub async fn get_synonyms(
+pub async fn get_index_settings(
     State(state): State<Arc<AppState>>,
-    Path(index_name): Path<String>,
+    Path(name): Path<String>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
-    validate_index_name(&index_name).map_err(|e| {
+    validate_index_name(&name)?;
+
+    let settings = state.search_engine.index_settings(&name).ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(format!("Index not found: {}", name))),
+        )
+    })?;
+
+    Ok(Json(ApiResponse::success(settings)))
+}
+
+/// Replace an index's settings. The schema can't change, so a summary field must
+/// already exist.
+pub async fn update_index_settings(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Json(settings): Json<IndexSettings>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&name)?;
+
+    let fields = state.search_engine.index_fields(&name).ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(format!("Index not found: {}", name))),
+        )
+    })?;
+
+    if let Some(summary) = &settings.summary {
+        validate_summary(summary, &fields)?;
+        if !fields.iter().any(|field| field.name == summary.target_field) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::error(format!(
+                    "summary.target_field: unknown field '{}'; summary fields are only added \
+                     when an index is created",
+                    summary.target_field
+                ))),
+            ));
+        }
+    }
+    if let Some(llm) = &settings.llm {
+        validate_llm_settings(llm)?;
+        check_llm_provider(&state, llm)?;
+    }
+    validate_index_settings(&settings, &fields)?;
+
+    state
+        .search_engine
+        .update_index_settings(&name, settings.clone())
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+
+    // Rewrites and answers may depend on the old LLM and search settings
+    state.query_rewrites.invalidate(&name);
+    state.answer_cache.invalidate(&name);
+
+    Ok(Json(ApiResponse::success(settings)))
+}
+
+/// Compare the index with its metadata rows and repair any drift
+pub async fn sync_index_metadata(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<SyncReport>>)> {
+    validate_index_name(&name).map_err(|e| {
         (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
     })?;
 
-    let synonyms = state.search_engine.get_synonyms(&index_name);
+    if !state.search_engine.list_indices().contains(&name) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(format!("Index not found: {}", name))),
+        ));
+    }
 
-    Ok(Json(ApiResponse::success(SynonymsResponse { synonyms })))
+    let report = reconcile::sync_index(&state, &name).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(e.to_string())),
+        )
+    })?;
+
+    Ok(Json(ApiResponse::success(report)))
 }
 
-/// Clear all synonyms for an index
-pub async fn clear_synonyms(
+pub async fn suggest(
     State(state): State<Arc<AppState>>,
     Path(index_name): Path<String>,
-) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    headers: HeaderMap,
+    Json(payload): Json<SuggestRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<SuggestResponse>>)> {
     validate_index_name(&index_name).map_err(|e| {
         (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
     })?;
 
-    state
+    // Suggestions can't be limited by a search key's filter
+    let access = auth::search_access(&state, &index_name, &headers)
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+    if !access.filters.is_empty() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ApiResponse::error(
+                "Search keys can only be used to search and answer".to_string(),
+            )),
+        ));
+    }
+
+    let (suggestions, took_ms) = state
         .search_engine
-        .clear_synonyms(&index_name)
+        .suggest(
+            &index_name,
+            &payload.prefix,
+            payload.field.as_deref(),
+            payload.limit,
+            &access.hidden_fields,
+        )
         .map_err(|e| {
             (
-                StatusCode::INTERNAL_SERVER_ERROR,
+                e.status_code(),
                 Json(ApiResponse::error(e.to_string())),
             )
         })?;
 
-    Ok(Json(ApiResponse::success(serde_json::json!({
-        "message": "Synonyms cleared successfully"
-    }))))
+    let response = SuggestResponse {
+        suggestions,
+        took_ms,
+    };
+
+    Ok(Json(ApiResponse::success(response)))
 }
 
-/// Add pinned rules to an index
-pub async fn add_pinned_rules(
+/// Session a search belongs to, from the `X-Search-Session` header. Only a hash of the
+/// ID is logged.
+fn search_session(headers: &HeaderMap) -> Option<String> {
+    let session = headers.get("x-search-session")?.to_str().ok()?.trim();
+    (!session.is_empty()).then(|| quota::token_id(session))
+}
+
+/// Searches a logged query needs before it is suggested, so one-off typos stay out
+const MIN_QUERY_SUGGESTION_COUNT: u64 = 2;
+
+/// Suggest completed queries for a search box from the query log: the most searched ones
+/// starting with the prefix, and those searched more lately than before. Unlike `suggest`,
+/// which completes single terms from the index, these are whole queries users ran.
+pub async fn suggest_queries(
     State(state): State<Arc<AppState>>,
     Path(index_name): Path<String>,
-    Json(payload): Json<AddPinnedRulesRequest>,
-) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    headers: HeaderMap,
+    Json(payload): Json<QuerySuggestRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<QuerySuggestResponse>>)> {
+    let start = std::time::Instant::now();
     validate_index_name(&index_name).map_err(|e| {
         (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
     })?;
+    validate_query_suggestions(&payload).map_err(|e| {
+        (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
+    })?;
+    if !state.search_engine.list_indices().contains(&index_name) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(format!("Index not found: {}", index_name))),
+        ));
+    }
 
-    state
-        .search_engine
-        .add_pinned_rules(&index_name, payload.rules)
+    // Logged queries can't be limited by a search key's filter
+    let access = auth::search_access(&state, &index_name, &headers)
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+    if !access.filters.is_empty() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ApiResponse::error(
+                "Search keys can only be used to search and answer".to_string(),
+            )),
+        ));
+    }
+
+    // Queries are logged lowercased with single spaces; a trailing space is kept so
+    // "red " only completes queries with more words
+    let mut prefix = payload.prefix.split_whitespace().collect::<Vec<_>>().join(" ");
+    prefix = prefix.to_lowercase();
+    if !prefix.is_empty() && payload.prefix.ends_with(char::is_whitespace) {
+        prefix.push(' ');
+    }
+    let keep = |query: &str| {
+        field_access::hidden_query_reference(query, &access.hidden_fields).is_none()
+    };
+    let internal = |e: anyhow::Error| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(e.to_string())),
+        )
+    };
+
+    let popular = state
+        .metadata_store
+        .popular_queries(&index_name, &prefix, MIN_QUERY_SUGGESTION_COUNT, payload.limit, keep)
+        .map_err(internal)?;
+    let trending = state
+        .metadata_store
+        .trending_queries(
+            &index_name,
+            &prefix,
+            payload.window_days,
+            MIN_QUERY_SUGGESTION_COUNT,
+            payload.limit,
+            |trending| !trending.zero_results && keep(&trending.query),
+        )
+        .map_err(internal)?;
+
+    let response = QuerySuggestResponse {
+        popular: popular
+            .into_iter()
+            .map(|(query, count)| QuerySuggestion { query, count })
+            .collect(),
+        trending: trending
+            .into_iter()
+            .map(|trending| QuerySuggestion { query: trending.query, count: trending.count })
+            .collect(),
+        took_ms: start.elapsed().as_secs_f64() * 1000.0,
+    };
+
+    Ok(Json(ApiResponse::success(response)))
+}
+
+/// Sessions two queries need in common before they are related, so a single visitor's
+/// searches aren't shown to others
+const MIN_RELATED_SESSIONS: u64 = 2;
+
+/// Queries of the query log searched more in the last days than in as many days before,
+/// including those that found nothing
+pub async fn get_trending_queries(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(params): Query<TrendingParams>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<TrendingResponse>>)> {
+    validate_index_name(&name).map_err(|e| {
+        (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
+    })?;
+    validate_trending_window(params.window_days).map_err(|e| {
+        (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
+    })?;
+    if !state.search_engine.list_indices().contains(&name) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(format!("Index not found: {}", name))),
+        ));
+    }
+
+    let access = auth::search_access(&state, &name, &headers)
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+    let queries = state
+        .metadata_store
+        .trending_queries(
+            &name,
+            "",
+            params.window_days,
+            1,
+            params.limit.clamp(1, MAX_QUERY_SUGGESTIONS),
+            |trending| {
+                field_access::hidden_query_reference(&trending.query, &access.hidden_fields)
+                    .is_none()
+            },
+        )
         .map_err(|e| {
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -677,45 +2342,1366 @@ pub async fn add_pinned_rules(
             )
         })?;
 
-    Ok(Json(ApiResponse::success(serde_json::json!({
-        "message": "Pinned rules added successfully"
-    }))))
+    Ok(Json(ApiResponse::success(TrendingResponse {
+        window_days: params.window_days,
+        queries,
+    })))
 }
 
-/// Get pinned rules for an index
-pub async fn get_pinned_rules(
+/// Queries searched in the same sessions as the `q` query parameter, most shared first
+pub async fn get_related_queries(
     State(state): State<Arc<AppState>>,
-    Path(index_name): Path<String>,
-) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
-    validate_index_name(&index_name).map_err(|e| {
+    Path(name): Path<String>,
+    Query(params): Query<RelatedQueriesParams>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<RelatedQueriesResponse>>)> {
+    validate_index_name(&name).map_err(|e| {
         (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
     })?;
+    // Looked up as it is logged
+    let query = params
+        .q
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    if query.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error("Query parameter 'q' is required".to_string())),
+        ));
+    }
+    if !state.search_engine.list_indices().contains(&name) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(format!("Index not found: {}", name))),
+        ));
+    }
 
-    let rules = state.search_engine.get_pinned_rules(&index_name);
+    let access = auth::search_access(&state, &name, &headers)
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+    let hidden = |query: &str| {
+        field_access::hidden_query_reference(query, &access.hidden_fields).is_some()
+    };
+    if hidden(&query) {
+        return Ok(Json(ApiResponse::success(RelatedQueriesResponse {
+            query,
+            related: Vec::new(),
+        })));
+    }
+    let related = state
+        .metadata_store
+        .related_queries(
+            &name,
+            &query,
+            MIN_RELATED_SESSIONS,
+            params.limit.clamp(1, MAX_QUERY_SUGGESTIONS),
+            |query| !hidden(query),
+        )
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e.to_string())),
+            )
+        })?
+        .into_iter()
+        .map(|(query, sessions)| RelatedQuery { query, sessions })
+        .collect();
 
-    Ok(Json(ApiResponse::success(PinnedRulesResponse { rules })))
+    Ok(Json(ApiResponse::success(RelatedQueriesResponse { query, related })))
 }
 
-/// Clear all pinned rules for an index
-pub async fn clear_pinned_rules(
+pub async fn bulk_operation(
     State(state): State<Arc<AppState>>,
     Path(index_name): Path<String>,
-) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    Query(params): Query<WriteParams>,
+    Json(mut payload): Json<BulkRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<BulkResponse>>)> {
     validate_index_name(&index_name).map_err(|e| {
         (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
     })?;
+    validate_bulk_operation_count(payload.operations.len()).map_err(|e| {
+        (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
+    })?;
+    // Writes to a rolling index go to its write index
+    let index_name = state.search_engine.resolve_write_index(&index_name);
+    prepare_bulk_documents(&state, &index_name, &mut payload.operations).await;
+
+    let total = payload.operations.len();
+    let write_index = index_name.clone();
+    let (outcome, items) = run_write(&state, move |state| {
+        state.search_engine.bulk(&write_index, &payload.operations)
+    })
+    .await
+    .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+    if let Err(e) = state.metadata_store.apply_ingest_outcome(&index_name, &outcome) {
+        tracing::warn!("Failed to update metadata for bulk operation: {}", e);
+    }
+    wait_for_refresh(&state.search_engine, &index_name, params.refresh, &outcome.commit)
+        .await
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+
+    let errors: Vec<String> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, item)| {
+            let error = item.error.as_ref()?;
+            Some(format!("Operation {} failed: {}", idx, error))
+        })
+        .collect();
+    let failed = errors.len();
+    let successful = total - failed;
+
+    let details = AuditDetails::documents(&outcome);
+    let response = BulkResponse {
+        total,
+        successful,
+        failed,
+        errors,
+        items,
+        commit: outcome.commit,
+    };
 
-    state
-        .search_engine
-        .clear_pinned_rules(&index_name)
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ApiResponse::error(e.to_string())),
-            )
-        })?;
+    Ok((Extension(details), Json(ApiResponse::success(response))))
+}
+
+/// Apply bulk operations atomically: all of them are committed, along with their
+/// metadata, or none is
+pub async fn transaction(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+    Query(params): Query<WriteParams>,
+    Json(mut payload): Json<TransactionRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&index_name)?;
+    validate_bulk_operation_count(payload.operations.len())?;
+    // Writes to a rolling index go to its write index
+    let index_name = state.search_engine.resolve_write_index(&index_name);
+    prepare_bulk_documents(&state, &index_name, &mut payload.operations).await;
+
+    let write_index = index_name.clone();
+    let (outcome, items) = run_write(&state, move |state| {
+        state.search_engine.transaction(&write_index, &payload.operations, |outcome| {
+            state
+                .metadata_store
+                .apply_ingest_outcome(&write_index, outcome)
+                .map_err(SearchError::Internal)
+        })
+    })
+    .await
+    .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+    wait_for_refresh(&state.search_engine, &index_name, params.refresh, &outcome.commit)
+        .await
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+
+    let details = AuditDetails::documents(&outcome);
+    Ok((
+        Extension(details),
+        Json(ApiResponse::success(TransactionResponse {
+            items,
+            commit: outcome.commit,
+        })),
+    ))
+}
+
+/// Give documents of bulk operations their IDs and summaries. A document without an
+/// `id` takes the operation's, or gets a generated one when the operation can create it.
+async fn prepare_bulk_documents(
+    state: &AppState,
+    index_name: &str,
+    operations: &mut [BulkOperation],
+) {
+    for op in operations.iter_mut() {
+        let Some(doc) = op.document.as_mut().filter(|doc| doc.id.is_empty()) else {
+            continue;
+        };
+        match (&op.id, op.operation.as_str()) {
+            (Some(id), _) => doc.id = id.clone(),
+            (None, "index" | "upsert") => assign_document_id(doc),
+            _ => {}
+        }
+    }
+    let documents = operations
+        .iter_mut()
+        .filter(|op| matches!(op.operation.as_str(), "index" | "update" | "upsert"))
+        .filter_map(|op| op.document.as_mut())
+        .collect();
+    add_llm_summaries(state, index_name, documents).await;
+}
+
+/// Add synonyms to an index
+pub async fn add_synonyms(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+    Json(payload): Json<AddSynonymsRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&index_name).map_err(|e| {
+        (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
+    })?;
+
+    let details = AuditDetails(serde_json::json!({ "synonyms": payload.synonyms }));
+    state
+        .search_engine
+        .add_synonyms(&index_name, payload.synonyms)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e.to_string())),
+            )
+        })?;
+
+    Ok((
+        Extension(details),
+        Json(ApiResponse::success(serde_json::json!({
+            "message": "Synonyms added successfully"
+        }))),
+    ))
+}
+
+/// Documents read when sampling index terms for synonym suggestions
+const SYNONYM_SAMPLE_DOCS: usize = 1000;
+
+/// Ask the LLM to propose synonym groups and stopwords from the index's most common terms
+/// and its zero-result queries. Nothing is applied; curators review the suggestions and
+/// add the ones they want through the synonyms endpoint.
+pub async fn suggest_synonyms(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+    payload: Option<Json<SuggestSynonymsRequest>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&index_name).map_err(|e| {
+        (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
+    })?;
+    let payload = payload.map(|Json(payload)| payload).unwrap_or_default();
+    validate_synonym_suggestions(&payload)?;
+
+    let llm_settings = index_llm_settings(&state, &index_name, &LlmSettings::default());
+    let llm_client = llm_client_for(&state, &llm_settings)?;
+
+    let terms = state
+        .search_engine
+        .sample_terms(&index_name, payload.sample_terms, SYNONYM_SAMPLE_DOCS)
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+    let queries = state
+        .metadata_store
+        .zero_result_queries(&index_name, payload.sample_queries)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e.to_string())),
+            )
+        })?;
+    if terms.is_empty() && queries.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(
+                "Index has no text or zero-result queries to base suggestions on".to_string(),
+            )),
+        ));
+    }
+
+    let existing = state.search_engine.get_synonyms(&index_name);
+    let mut messages = synonym_suggestions::suggestion_messages(&terms, &queries, &existing);
+    state.redactor.session().redact_messages(&mut messages);
+    let request = ChatCompletionRequest {
+        model: llm_client.model().to_string(),
+        messages,
+        temperature: Some(0.0),
+        max_tokens: Some(1500),
+        stream: false,
+    };
+    let response = llm_client.complete(request).await.map_err(|e| {
+        (
+            StatusCode::BAD_GATEWAY,
+            Json(ApiResponse::error(e.to_string())),
+        )
+    })?;
+    let reply = response
+        .choices
+        .into_iter()
+        .next()
+        .map(|choice| choice.message.content)
+        .unwrap_or_default();
+
+    let suggestions =
+        synonym_suggestions::parse_suggestions(&reply, &existing).ok_or_else(|| {
+            tracing::warn!("Unusable synonym suggestions reply: {}", reply);
+            (
+                StatusCode::BAD_GATEWAY,
+                Json(ApiResponse::error("LLM reply could not be read as suggestions".to_string())),
+            )
+        })?;
+
+    Ok(Json(ApiResponse::success(SynonymSuggestionsResponse {
+        suggestions,
+        model: response.model,
+        sampled_terms: terms.len(),
+        sampled_queries: queries.len(),
+    })))
+}
+
+/// Documents read for the index terms zero-result queries are compared with
+const REPORT_SAMPLE_DOCS: usize = 5000;
+
+/// Most index terms zero-result queries are compared with
+const REPORT_SAMPLE_TERMS: usize = 20_000;
+
+/// Report on the zero-result queries of an index: variants of a query grouped together,
+/// index terms close to the words that aren't in the index and synonyms that would join
+/// them, and words the index has nothing close to. Nothing is applied.
+pub async fn get_relevance_report(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<RelevanceReportParams>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<RelevanceReport>>)> {
+    let index_name = params.index.ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error("Query parameter 'index' is required".to_string())),
+        )
+    })?;
+    validate_index_name(&index_name).map_err(|e| {
+        (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
+    })?;
+    let access = auth::search_access(&state, &index_name, &headers)
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+
+    let terms = state
+        .search_engine
+        .sample_terms(&index_name, REPORT_SAMPLE_TERMS, REPORT_SAMPLE_DOCS)
+        .map_err(|e| (e.status_code(), Json(ApiResponse::error(e.to_string()))))?;
+    let queries: Vec<(String, u64)> = state
+        .metadata_store
+        .zero_result_queries(
+            &index_name,
+            params.sample_queries.clamp(1, MAX_SUGGEST_SAMPLE_QUERIES),
+        )
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e.to_string())),
+            )
+        })?
+        .into_iter()
+        .filter(|(query, _)| {
+            field_access::hidden_query_reference(query, &access.hidden_fields).is_none()
+        })
+        .collect();
+
+    let vocabulary: HashMap<String, u64> = terms.into_iter().collect();
+    let existing = state.search_engine.get_synonyms(&index_name);
+    let clusters = relevance_report::cluster(&queries, &vocabulary, &existing);
+
+    Ok(Json(ApiResponse::success(RelevanceReport {
+        index: index_name,
+        clusters,
+        sampled_queries: queries.len(),
+        sampled_terms: vocabulary.len(),
+    })))
+}
+
+/// Get synonyms for an index
+pub async fn get_synonyms(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&index_name).map_err(|e| {
+        (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
+    })?;
+
+    let synonyms = state.search_engine.get_synonyms(&index_name);
+
+    Ok(Json(ApiResponse::success(SynonymsResponse { synonyms })))
+}
+
+/// Clear all synonyms for an index
+pub async fn clear_synonyms(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&index_name).map_err(|e| {
+        (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
+    })?;
+
+    state
+        .search_engine
+        .clear_synonyms(&index_name)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e.to_string())),
+            )
+        })?;
+
+    Ok(Json(ApiResponse::success(serde_json::json!({
+        "message": "Synonyms cleared successfully"
+    }))))
+}
+
+/// Add pinned rules to an index
+pub async fn add_pinned_rules(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+    Json(payload): Json<AddPinnedRulesRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&index_name).map_err(|e| {
+        (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
+    })?;
+
+    let details = AuditDetails(serde_json::json!({ "rules": payload.rules }));
+    state
+        .search_engine
+        .add_pinned_rules(&index_name, payload.rules)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e.to_string())),
+            )
+        })?;
+
+    Ok((
+        Extension(details),
+        Json(ApiResponse::success(serde_json::json!({
+            "message": "Pinned rules added successfully"
+        }))),
+    ))
+}
+
+/// Get pinned rules for an index
+pub async fn get_pinned_rules(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&index_name).map_err(|e| {
+        (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
+    })?;
+
+    let rules = state.search_engine.get_pinned_rules(&index_name);
+
+    Ok(Json(ApiResponse::success(PinnedRulesResponse { rules })))
+}
+
+/// Clear all pinned rules for an index
+pub async fn clear_pinned_rules(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&index_name).map_err(|e| {
+        (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
+    })?;
+
+    state
+        .search_engine
+        .clear_pinned_rules(&index_name)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e.to_string())),
+            )
+        })?;
 
     Ok(Json(ApiResponse::success(serde_json::json!({
         "message": "Pinned rules cleared successfully"
     }))))
 }
+
+pub async fn add_stored_filters(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+    Json(payload): Json<AddStoredFiltersRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&index_name)?;
+
+    for filter in &payload.filters {
+        state
+            .search_engine
+            .validate_stored_filter(&index_name, filter)
+            .map_err(|e| {
+                (
+                    e.status_code(),
+                    Json(ApiResponse::error(format!(
+                        "Invalid filter '{}': {}",
+                        filter.name, e
+                    ))),
+                )
+            })?;
+    }
+
+    state
+        .search_engine
+        .add_stored_filters(&index_name, payload.filters)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e.to_string())),
+            )
+        })?;
+
+    Ok(Json(ApiResponse::success(serde_json::json!({
+        "message": "Filters saved successfully"
+    }))))
+}
+
+pub async fn get_stored_filters(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&index_name)?;
+
+    let filters = state.search_engine.get_stored_filters(&index_name);
+
+    Ok(Json(ApiResponse::success(StoredFiltersResponse { filters })))
+}
+
+pub async fn delete_stored_filter(
+    State(state): State<Arc<AppState>>,
+    Path((index_name, filter_name)): Path<(String, String)>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&index_name)?;
+
+    let deleted = state
+        .search_engine
+        .delete_stored_filter(&index_name, &filter_name)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e.to_string())),
+            )
+        })?;
+
+    if !deleted {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(format!("Filter not found: {}", filter_name))),
+        ));
+    }
+
+    Ok(Json(ApiResponse::success(serde_json::json!({
+        "message": "Filter deleted successfully"
+    }))))
+}
+
+pub async fn add_prompt_templates(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+    Json(payload): Json<AddPromptTemplatesRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&index_name)?;
+
+    if state.search_engine.index_settings(&index_name).is_none() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(format!("Index not found: {}", index_name))),
+        ));
+    }
+    for template in &payload.templates {
+        prompts::validate_template(template).map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::error(format!(
+                    "Invalid template '{}': {}",
+                    template.name, e
+                ))),
+            )
+        })?;
+    }
+
+    state
+        .search_engine
+        .add_prompt_templates(&index_name, payload.templates)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e.to_string())),
+            )
+        })?;
+
+    Ok(Json(ApiResponse::success(serde_json::json!({
+        "message": "Templates saved successfully"
+    }))))
+}
+
+pub async fn get_prompt_templates(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&index_name)?;
+
+    let templates = state.search_engine.get_prompt_templates(&index_name);
+
+    Ok(Json(ApiResponse::success(PromptTemplatesResponse { templates })))
+}
+
+pub async fn delete_prompt_template(
+    State(state): State<Arc<AppState>>,
+    Path((index_name, template_name)): Path<(String, String)>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&index_name)?;
+
+    let deleted = state
+        .search_engine
+        .delete_prompt_template(&index_name, &template_name)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e.to_string())),
+            )
+        })?;
+
+    if !deleted {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(format!("Template not found: {}", template_name))),
+        ));
+    }
+
+    Ok(Json(ApiResponse::success(serde_json::json!({
+        "message": "Template deleted successfully"
+    }))))
+}
+
+pub async fn add_search_presets(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+    Json(payload): Json<AddSearchPresetsRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&index_name)?;
+
+    for preset in &payload.presets {
+        state
+            .search_engine
+            .validate_search_preset(&index_name, preset)
+            .map_err(|e| {
+                (
+                    e.status_code(),
+                    Json(ApiResponse::error(format!(
+                        "Invalid preset '{}': {}",
+                        preset.name, e
+                    ))),
+                )
+            })?;
+    }
+
+    state
+        .search_engine
+        .add_search_presets(&index_name, payload.presets)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e.to_string())),
+            )
+        })?;
+
+    Ok(Json(ApiResponse::success(serde_json::json!({
+        "message": "Presets saved successfully"
+    }))))
+}
+
+pub async fn get_search_presets(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&index_name)?;
+
+    let presets = state.search_engine.get_search_presets(&index_name);
+
+    Ok(Json(ApiResponse::success(SearchPresetsResponse { presets })))
+}
+
+pub async fn delete_search_preset(
+    State(state): State<Arc<AppState>>,
+    Path((index_name, preset_name)): Path<(String, String)>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&index_name)?;
+
+    let deleted = state
+        .search_engine
+        .delete_search_preset(&index_name, &preset_name)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e.to_string())),
+            )
+        })?;
+
+    if !deleted {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(format!("Preset not found: {}", preset_name))),
+        ));
+    }
+
+    Ok(Json(ApiResponse::success(serde_json::json!({
+        "message": "Preset deleted successfully"
+    }))))
+}
+
+/// Register a web crawler for an index
+pub async fn create_crawler(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+    Json(payload): Json<CreateCrawlerRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&index_name)?;
+
+    let seed = reqwest::Url::parse(&payload.seed_url).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(format!("Invalid seed_url: {}", e))),
+        )
+    })?;
+    if !matches!(seed.scheme(), "http" | "https") {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error("seed_url must be an http(s) URL".to_string())),
+        ));
+    }
+
+    for patterns in [&payload.include_patterns, &payload.exclude_patterns] {
+        compile_patterns(patterns).map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::error(e.to_string())),
+            )
+        })?;
+    }
+
+    if !state.search_engine.list_indices().contains(&index_name) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(format!("Index not found: {}", index_name))),
+        ));
+    }
+
+    let crawler = CrawlerConfig {
+        id: uuid::Uuid::new_v4().to_string(),
+        index_name,
+        seed_url: seed.to_string(),
+        max_depth: payload.max_depth,
+        max_pages: payload.max_pages,
+        include_patterns: payload.include_patterns,
+        exclude_patterns: payload.exclude_patterns,
+        interval_seconds: payload.interval_seconds,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        last_run_at: None,
+        last_status: None,
+        last_message: None,
+    };
+
+    state.metadata_store.create_crawler(&crawler).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(e.to_string())),
+        )
+    })?;
+
+    Ok((StatusCode::CREATED, Json(ApiResponse::success(crawler))))
+}
+
+/// List crawlers registered for an index
+pub async fn list_crawlers(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&index_name)?;
+
+    let crawlers = state
+        .metadata_store
+        .list_crawlers(Some(&index_name))
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e.to_string())),
+            )
+        })?;
+
+    Ok(Json(ApiResponse::success(crawlers)))
+}
+
+/// Remove a crawler from an index
+pub async fn delete_crawler(
+    State(state): State<Arc<AppState>>,
+    Path((index_name, crawler_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&index_name)?;
+
+    let deleted = state
+        .metadata_store
+        .delete_crawler(&index_name, &crawler_id)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e.to_string())),
+            )
+        })?;
+
+    if !deleted {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(format!("Crawler not found: {}", crawler_id))),
+        ));
+    }
+
+    Ok(Json(ApiResponse::success(serde_json::json!({
+        "message": "Crawler deleted successfully"
+    }))))
+}
+
+/// Start a crawl immediately; the run continues in the background
+pub async fn run_crawler(
+    State(state): State<Arc<AppState>>,
+    Path((index_name, crawler_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&index_name)?;
+
+    let crawler = state
+        .metadata_store
+        .list_crawlers(Some(&index_name))
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e.to_string())),
+            )
+        })?
+        .into_iter()
+        .find(|c| c.id == crawler_id)
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(ApiResponse::error(format!("Crawler not found: {}", crawler_id))),
+            )
+        })?;
+
+    if !jobs::spawn_crawl(state, crawler) {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(ApiResponse::error(format!("Crawler is already running: {}", crawler_id))),
+        ));
+    }
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(ApiResponse::success(serde_json::json!({
+            "message": "Crawl started",
+            "id": crawler_id
+        }))),
+    ))
+}
+
+/// List scheduled jobs with their last run and next scheduled time
+/// Requests running and waiting in the search and indexing pools
+pub async fn get_request_queues(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(ApiResponse::success(state.request_pools.stats()))
+}
+
+/// Entries of the audit log, newest first, filtered by index, actor, action and time
+pub async fn get_audit_log(
+    State(state): State<Arc<AppState>>,
+    Query(mut params): Query<AuditParams>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    // Timestamps are compared as text, so the bounds are rewritten in their format
+    for bound in [&mut params.since, &mut params.until] {
+        if let Some(value) = bound.as_mut() {
+            let at = chrono::DateTime::parse_from_rfc3339(value).map_err(|_| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    Json(ApiResponse::error(format!("Invalid RFC3339 timestamp: {}", value))),
+                )
+            })?;
+            *value = audit::timestamp(at.with_timezone(&chrono::Utc));
+        }
+    }
+    let limit = params.limit.clamp(1, 1000);
+
+    let entries = state.metadata_store.audit_entries(&params, limit).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(e.to_string())),
+        )
+    })?;
+    let next = if entries.len() == limit {
+        entries.last().map(|entry| entry.id)
+    } else {
+        None
+    };
+
+    Ok(Json(ApiResponse::success(AuditResponse { entries, next })))
+}
+
+pub async fn list_jobs(
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    let jobs = state.metadata_store.list_jobs().map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(e.to_string())),
+        )
+    })?;
+
+    let statuses: Vec<JobStatus> = jobs
+        .into_iter()
+        .map(|job| jobs::job_status(&state, job))
+        .collect();
+
+    Ok(Json(ApiResponse::success(statuses)))
+}
+
+/// Register a job that runs on a cron schedule
+pub async fn create_job(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CreateJobRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    validate_index_name(&payload.index_name)?;
+
+    let bad_request =
+        |message: String| (StatusCode::BAD_REQUEST, Json(ApiResponse::error(message)));
+
+    jobs::parse_schedule(&payload.schedule).map_err(|e| bad_request(e.to_string()))?;
+
+    if !jobs::job_index_exists(&state, &payload.task, &payload.index_name) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(format!(
+                "Index not found: {}",
+                payload.index_name
+            ))),
+        ));
+    }
+
+    match &payload.task {
+        JobTask::Crawl { crawler_id } => {
+            let exists = state
+                .metadata_store
+                .list_crawlers(Some(&payload.index_name))
+                .map_err(|e| {
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(ApiResponse::error(e.to_string())),
+                    )
+                })?
+                .iter()
+                .any(|c| &c.id == crawler_id);
+            if !exists {
+                return Err(bad_request(format!("Crawler not found: {}", crawler_id)));
+            }
+        }
+        JobTask::Feed { url, .. } => {
+            let url = reqwest::Url::parse(url)
+                .map_err(|e| bad_request(format!("Invalid feed url: {}", e)))?;
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(bad_request("Feed url must be an http(s) URL".to_string()));
+            }
+        }
+        JobTask::Snapshot { keep } if *keep == 0 => {
+            return Err(bad_request("Snapshot keep must be at least 1".to_string()));
+        }
+        JobTask::Reindex
+        | JobTask::Snapshot { .. }
+        | JobTask::Rollover
+        | JobTask::AnalyticsRetention => {}
+    }
+
+    let name = payload.name.unwrap_or_else(|| {
+        format!("{} {}", jobs::task_kind(&payload.task), payload.index_name)
+    });
+    let job = JobConfig {
+        id: uuid::Uuid::new_v4().to_string(),
+        name,
+        index_name: payload.index_name,
+        schedule: payload.schedule.trim().to_string(),
+        task: payload.task,
+        enabled: payload.enabled,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        last_run_at: None,
+        last_status: None,
+        last_message: None,
+    };
+
+    state.metadata_store.create_job(&job).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(e.to_string())),
+        )
+    })?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ApiResponse::success(jobs::job_status(&state, job))),
+    ))
+}
+
+/// Remove a scheduled job
+pub async fn delete_job(
+    State(state): State<Arc<AppState>>,
+    Path(job_id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    let deleted = state.metadata_store.delete_job(&job_id).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(e.to_string())),
+        )
+    })?;
+
+    if !deleted {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(format!("Job not found: {}", job_id))),
+        ));
+    }
+
+    Ok(Json(ApiResponse::success(serde_json::json!({
+        "message": "Job deleted successfully"
+    }))))
+}
+
+/// Trigger a job immediately; the run continues in the background
+pub async fn run_job(
+    State(state): State<Arc<AppState>>,
+    Path(job_id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
+    let job = state
+        .metadata_store
+        .list_jobs()
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e.to_string())),
+            )
+        })?
+        .into_iter()
+        .find(|j| j.id == job_id)
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(ApiResponse::error(format!("Job not found: {}", job_id))),
+            )
+        })?;
+
+    if !jobs::spawn_job(state.clone(), job) {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(ApiResponse::error(format!("Job is already running: {}", job_id))),
+        ));
+    }
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(ApiResponse::success(serde_json::json!({
+            "message": "Job started",
+            "id": job_id
+        }))),
+    ))
+}
+
+pub async fn create_search_key(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<CreateSearchKeyRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<SearchKeyResponse>>)> {
+    let bad_request =
+        |message: String| (StatusCode::BAD_REQUEST, Json(ApiResponse::error(message)));
+
+    // Keys are signed with the caller's token, which the auth middleware has checked
+    let Some(parent) = auth::bearer_token(&headers)
+        .filter(|token| state.api_tokens.contains(token))
+    else {
+        return Err(bad_request(
+            "Search keys require API_TOKENS to be configured".to_string(),
+        ));
+    };
+    if payload.filter.trim().is_empty() {
+        return Err(bad_request("Search key filter cannot be empty".to_string()));
+    }
+
+    let parent_scopes = state.api_tokens.scopes(parent).cloned().unwrap_or_default();
+    if let Some(scope) = payload.scopes.iter().find(|s| !parent_scopes.contains(*s)) {
+        return Err(bad_request(format!("Token does not have scope: {}", scope)));
+    }
+
+    let filter = StoredFilter {
+        name: "filter".to_string(),
+        query: payload.filter.clone(),
+        geo_filters: Vec::new(),
+    };
+    for index_name in &payload.indices {
+        validate_index_name(index_name).map_err(|e| {
+            (e.0, Json(ApiResponse::error(e.1.error.clone().unwrap_or_default())))
+        })?;
+        state
+            .search_engine
+            .validate_stored_filter(index_name, &filter)
+            .map_err(|e| {
+                (
+                    e.status_code(),
+                    Json(ApiResponse::error(format!(
+                        "Invalid filter for index '{}': {}",
+                        index_name, e
+                    ))),
+                )
+            })?;
+    }
+
+    let expires_at = match payload.expires_in_secs {
+        Some(secs) => Some(
+            i64::try_from(secs)
+                .ok()
+                .and_then(chrono::Duration::try_seconds)
+                .and_then(|ttl| chrono::Utc::now().checked_add_signed(ttl))
+                .ok_or_else(|| bad_request("expires_in_secs is too large".to_string()))?,
+        ),
+        None => None,
+    };
+    // What the key allows, never the key itself
+    let details = AuditDetails(serde_json::json!({
+        "filter": payload.filter,
+        "indices": payload.indices,
+        "scopes": payload.scopes,
+        "expires_at": expires_at.map(|at| at.to_rfc3339()),
+    }));
+    let claims = search_keys::SearchKeyClaims::new(
+        parent,
+        payload.filter,
+        payload.indices,
+        payload.scopes,
+        expires_at.map(|at| at.timestamp()),
+    );
+
+    Ok((
+        StatusCode::CREATED,
+        Extension(details),
+        Json(ApiResponse::success(SearchKeyResponse {
+            key: search_keys::sign(parent, &claims),
+            expires_at: expires_at.map(|at| at.to_rfc3339()),
+        })),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assign_document_id_fills_only_missing_ids() {
+        let mut docs: Vec<Document> = serde_json::from_value(serde_json::json!([
+            {"fields": {}},
+            {"fields": {}},
+            {"id": "kept", "fields": {}},
+        ]))
+        .unwrap();
+        docs.iter_mut().for_each(assign_document_id);
+
+        assert_eq!(docs[2].id, "kept");
+        assert!(uuid::Uuid::parse_str(&docs[0].id).is_ok());
+        assert_ne!(docs[0].id, docs[1].id);
+    }
+
+    /// Engine with a `docs` index, over a fresh directory the caller removes
+    fn temp_engine() -> (Arc<SearchEngine>, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!("handlers-test-{}", uuid::Uuid::new_v4()));
+        let engine = SearchEngine::new(
+            path.to_str().unwrap(),
+            1,
+            crate::search::QueryLimits::default(),
+            crate::filter_cache::FilterCache::from_env(),
+        )
+        .unwrap();
+        let fields: Vec<FieldConfig> =
+            serde_json::from_value(serde_json::json!([{"name": "title", "indexed": true}]))
+                .unwrap();
+        engine.create_index("docs", &fields, &IndexSettings::default(), 1).unwrap();
+        (Arc::new(engine), path)
+    }
+
+    fn documents(ids: &[&str]) -> Vec<Document> {
+        ids.iter()
+            .map(|id| Document { id: id.to_string(), fields: HashMap::new() })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_generation_returns_once_the_commit_is_searchable() {
+        let (engine, path) = temp_engine();
+        let generation = engine.add_documents("docs", &documents(&["1"])).unwrap().commit.opstamp;
+        assert_eq!(engine.index_generation("docs"), Some(generation));
+        wait_for_generation(&engine, "docs", generation).await.unwrap();
+
+        // A later generation is waited for until a write reaches it
+        let writer = engine.clone();
+        let write = tokio::task::spawn_blocking(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            writer.add_documents("docs", &documents(&["2"])).unwrap().commit.opstamp
+        });
+        wait_for_generation(&engine, "docs", generation + 1).await.unwrap();
+        assert!(write.await.unwrap() > generation);
+
+        let missing = wait_for_generation(&engine, "missing", 1).await;
+        assert!(matches!(missing, Err(SearchError::IndexNotFound(_))));
+        let pattern = wait_for_generation(&engine, "do*", 1).await;
+        assert!(matches!(pattern, Err(SearchError::InvalidQuery(_))));
+        assert_eq!(engine.index_generation("do*"), None);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_wait_for_checks_the_write_commit() {
+        let (engine, path) = temp_engine();
+        let commit = engine.add_documents("docs", &documents(&["1"])).unwrap().commit;
+        let params: WriteParams =
+            serde_json::from_value(serde_json::json!({"refresh": "wait_for"})).unwrap();
+        assert_eq!(params.refresh, Some(Refresh::WaitFor));
+        wait_for_refresh(&engine, "docs", params.refresh, &commit).await.unwrap();
+
+        // Other modes respond without looking at the index
+        let unknown = CommitStats { opstamp: commit.opstamp, ..CommitStats::default() };
+        for refresh in [None, Some(Refresh::True), Some(Refresh::False)] {
+            wait_for_refresh(&engine, "missing", refresh, &unknown).await.unwrap();
+        }
+        let missing = wait_for_refresh(&engine, "missing", params.refresh, &unknown).await;
+        assert!(matches!(missing, Err(SearchError::IndexNotFound(_))));
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_search_session_is_logged_as_a_hash() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(search_session(&headers), None);
+        headers.insert("x-search-session", "  ".parse().unwrap());
+        assert_eq!(search_session(&headers), None);
+        headers.insert("x-search-session", " visitor-1 ".parse().unwrap());
+        let session = search_session(&headers).unwrap();
+        assert_eq!(session, quota::token_id("visitor-1"));
+        assert!(!session.contains("visitor"));
+    }
+
+    /// Part of a multipart upload: name, optional filename and content type, body
+    type Part<'a> = (&'a str, Option<(&'a str, &'a str)>, &'a [u8]);
+
+    async fn upload(parts: &[Part<'_>]) -> Result<UploadedDocument, (StatusCode, String)> {
+        use axum::extract::FromRequest;
+
+        let mut body = Vec::new();
+        for (name, file, bytes) in parts {
+            body.extend_from_slice(b"--BOUNDARY\r\n");
+            let disposition = match file {
+                Some((filename, content_type)) => format!(
+                    "Content-Disposition: form-data; name=\"{name}\"; filename=\"{filename}\"\r\n\
+                     Content-Type: {content_type}\r\n"
+                ),
+                None => format!("Content-Disposition: form-data; name=\"{name}\"\r\n"),
+            };
+            body.extend_from_slice(disposition.as_bytes());
+            body.extend_from_slice(b"\r\n");
+            body.extend_from_slice(bytes);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(b"--BOUNDARY--\r\n");
+
+        let request = axum::http::Request::builder()
+            .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+            .body(axum::body::Body::from(body))
+            .unwrap();
+        let multipart = Multipart::from_request(request, &()).await.unwrap();
+        read_upload(multipart)
+            .await
+            .map_err(|(status, Json(response))| (status, response.error.unwrap_or_default()))
+    }
+
+    /// DOCX archive whose body is `paragraphs`, each one split across nested runs
+    fn docx_fixture(paragraphs: &[&[&str]]) -> Vec<u8> {
+        use std::io::Write;
+
+        let body: String = paragraphs
+            .iter()
+            .map(|runs| {
+                let runs: String = runs
+                    .iter()
+                    .map(|run| {
+                        format!(
+                            "<w:hyperlink><w:r><w:rPr><w:i/></w:rPr>\
+                             <w:t xml:space=\"preserve\">{run}</w:t></w:r></w:hyperlink>"
+                        )
+                    })
+                    .collect();
+                format!("<w:p>{runs}</w:p>")
+            })
+            .collect();
+        let document = format!(
+            "<w:document xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\">\
+             <w:body>{body}</w:body></w:document>"
+        );
+
+        let mut buffer = std::io::Cursor::new(Vec::new());
+        let mut writer = zip::ZipWriter::new(&mut buffer);
+        writer
+            .start_file("word/document.xml", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(document.as_bytes()).unwrap();
+        writer.finish().unwrap();
+        buffer.into_inner()
+    }
+
+    #[tokio::test]
+    async fn test_upload_extracts_docx_into_the_document() {
+        let docx = docx_fixture(&[&["Quarterly ", "results"], &["Revenue ", "grew"]]);
+        let uploaded = upload(&[
+            ("id", None, b"report-1"),
+            ("content_field", None, b"body"),
+            ("fields", None, br#"{"team": "finance"}"#),
+            ("file", Some(("report.docx", "application/octet-stream")), &docx),
+        ])
+        .await
+        .unwrap();
+
+        assert_eq!(uploaded.kind, FileKind::Docx);
+        assert_eq!(uploaded.filename, "report.docx");
+        assert_eq!(uploaded.content_field, "body");
+        assert_eq!(uploaded.extracted_chars, "Quarterly results\nRevenue grew".len());
+        let document = uploaded.document;
+        assert_eq!(document.id, "report-1");
+        assert_eq!(document.fields["body"], "Quarterly results\nRevenue grew");
+        assert_eq!(document.fields["team"], "finance");
+        assert_eq!(document.fields["filename"], "report.docx");
+        assert_eq!(document.fields["mime_type"], FileKind::Docx.mime_type());
+        assert!(!document.fields.contains_key("content"));
+    }
+
+    #[tokio::test]
+    async fn test_upload_defaults_the_id_and_content_field() {
+        let uploaded = upload(&[("file", Some(("notes.txt", "text/plain")), b"hello")])
+            .await
+            .unwrap();
+        assert!(uuid::Uuid::parse_str(&uploaded.document.id).is_ok());
+        assert_eq!(uploaded.document.fields["content"], "hello");
+        assert_eq!(uploaded.document.fields["mime_type"], "text/plain");
+    }
+
+    #[tokio::test]
+    async fn test_upload_rejections() {
+        let unsupported = upload(&[("file", Some(("photo.png", "image/png")), b"\x89PNG")]).await;
+        let (status, error) = unsupported.err().unwrap();
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert!(error.contains("photo.png"), "{error}");
+
+        let bad_fields = upload(&[
+            ("fields", None, b"{not json"),
+            ("file", Some(("notes.txt", "text/plain")), b"hello"),
+        ])
+        .await;
+        let (status, error) = bad_fields.err().unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(error.starts_with("Invalid fields JSON"), "{error}");
+
+        let (status, error) = upload(&[("id", None, b"x")]).await.err().unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error, "Missing 'file' field");
+
+        let docx_mime = FileKind::Docx.mime_type();
+        for (filename, mime) in [("broken.docx", docx_mime), ("broken.pdf", "application/pdf")] {
+            let failed = upload(&[("file", Some((filename, mime)), b"not a document")]).await;
+            let (status, _) = failed.err().unwrap();
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{filename}");
+        }
+    }
+}
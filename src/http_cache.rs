@@ -0,0 +1,122 @@
+use axum::http::{header, HeaderMap, HeaderValue};
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::field_access::Access;
+use crate::models::SearchRequest;
+
+/// Cache-Control sent with search responses when SEARCH_CACHE_CONTROL is unset:
+/// caches may keep them but must revalidate with the ETag first
+const DEFAULT_CACHE_CONTROL: &str = "no-cache";
+
+/// Cache-Control and ETag headers for search responses
+pub struct SearchCacheHeaders {
+    cache_control: Option<HeaderValue>,
+    etags: bool,
+    /// Distinguishes ETags from different runs, since the config generation restarts
+    epoch: u128,
+}
+
+impl SearchCacheHeaders {
+    /// Read SEARCH_CACHE_CONTROL (`off` to omit the header) and SEARCH_ETAGS
+    pub fn from_env() -> Self {
+        let cache_control = match std::env::var("SEARCH_CACHE_CONTROL") {
+            Ok(value) if value.trim().eq_ignore_ascii_case("off") => None,
+            Ok(value) if !value.trim().is_empty() => {
+                HeaderValue::from_str(value.trim()).ok().or_else(|| {
+                    tracing::warn!("Invalid SEARCH_CACHE_CONTROL, using the default");
+                    Some(HeaderValue::from_static(DEFAULT_CACHE_CONTROL))
+                })
+            }
+            _ => Some(HeaderValue::from_static(DEFAULT_CACHE_CONTROL)),
+        };
+
+        Self {
+            cache_control,
+            etags: std::env::var("SEARCH_ETAGS")
+                .map(|v| !matches!(v.trim().to_ascii_lowercase().as_str(), "false" | "0"))
+                .unwrap_or(true),
+            epoch: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// ETag of a search: the same request by a caller with the same access gets the
+    /// same results until the index is committed to or its configuration changes
+    pub fn etag(
+        &self,
+        index_name: &str,
+        opstamp: u64,
+        config_generation: u64,
+        request: &SearchRequest,
+        access: &Access,
+    ) -> Option<HeaderValue> {
+        if !self.etags {
+            return None;
+        }
+        let key = serde_json::json!({
+            "index": index_name,
+            "request": request,
+            "hidden": access.hidden_fields,
+            "filters": access.filters,
+        });
+        let digest = Sha256::digest(key.to_string().as_bytes());
+        let hash: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+        // Weak, since compression changes the bytes but not the results
+        let tag = format!(
+            "W/\"{:x}-{}-{}-{}\"",
+            self.epoch, opstamp, config_generation, hash
+        );
+        HeaderValue::from_str(&tag).ok()
+    }
+
+    /// Headers to send with a search response
+    pub fn headers(&self, etag: Option<&HeaderValue>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(cache_control) = &self.cache_control {
+            headers.insert(header::CACHE_CONTROL, cache_control.clone());
+        }
+        if let Some(etag) = etag {
+            headers.insert(header::ETAG, etag.clone());
+        }
+        headers
+    }
+}
+
+/// Whether the request's If-None-Match matches the ETag (weak comparison)
+pub fn not_modified(request_headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let Ok(etag) = etag.to_str() else {
+        return false;
+    };
+    let etag = etag.trim_start_matches("W/");
+    request_headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn if_none_match(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_matches_if_none_match_lists() {
+        let etag = HeaderValue::from_static("\"abc\"");
+        assert!(not_modified(&if_none_match("\"abc\""), &etag));
+        assert!(not_modified(&if_none_match("\"x\", W/\"abc\""), &etag));
+        assert!(not_modified(&if_none_match("*"), &etag));
+        assert!(!not_modified(&if_none_match("\"abcd\""), &etag));
+        assert!(!not_modified(&HeaderMap::new(), &etag));
+    }
+}
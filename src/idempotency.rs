@@ -0,0 +1,207 @@
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    extract::State,
+    http::{header, HeaderValue, Method, Request, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::models::{ApiResponse, IdempotentResponse};
+use crate::quota;
+use crate::AppState;
+
+/// Header carrying the client's key for a write
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Header set on responses replayed for a retried key
+const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+const MAX_KEY_LENGTH: usize = 255;
+
+/// Keys of writes sent with an `Idempotency-Key` header. Successful responses are kept
+/// in the metadata store for `ttl`, so a retried write (after a client timeout, say)
+/// gets the first response back instead of indexing or deleting again.
+pub struct IdempotencyKeys {
+    ttl: Duration,
+    /// Largest request body read, since the body is hashed before the handler runs
+    max_body: usize,
+    /// Keys of the requests being processed, by actor
+    in_flight: Mutex<HashSet<(String, String)>>,
+}
+
+impl IdempotencyKeys {
+    /// Keys are kept for `IDEMPOTENCY_KEY_TTL_SECS` (default 86400)
+    pub fn from_env(max_body: usize) -> Self {
+        let ttl_secs = std::env::var("IDEMPOTENCY_KEY_TTL_SECS")
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(86_400);
+        Self {
+            ttl: Duration::from_secs(ttl_secs),
+            max_body,
+            in_flight: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Mark a key as being processed until the guard is dropped, unless it already is
+    fn begin(&self, actor: &str, key: &str) -> Option<InFlight<'_>> {
+        let id = (actor.to_string(), key.to_string());
+        if !self.in_flight.lock().unwrap().insert(id.clone()) {
+            return None;
+        }
+        Some(InFlight { keys: self, id })
+    }
+
+    /// Unix time before which recorded keys have expired
+    fn expired_before(&self) -> i64 {
+        chrono::Utc::now().timestamp().saturating_sub(self.ttl.as_secs() as i64)
+    }
+}
+
+struct InFlight<'a> {
+    keys: &'a IdempotencyKeys,
+    id: (String, String),
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.keys.in_flight.lock().unwrap().remove(&self.id);
+    }
+}
+
+fn validate_key(key: &str) -> Result<(), String> {
+    if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+        return Err(format!(
+            "Idempotency-Key must be between 1 and {} characters",
+            MAX_KEY_LENGTH
+        ));
+    }
+    if !key.chars().all(|c| c.is_ascii_graphic() || c == ' ') {
+        return Err("Idempotency-Key must be printable ASCII".to_string());
+    }
+    Ok(())
+}
+
+/// Hash identifying a request, so a key reused for a different request is refused
+fn request_hash(method: &Method, uri: &Uri, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_str().as_bytes());
+    hasher.update(b"\n");
+    hasher.update(uri.path_and_query().map_or(uri.path(), |pq| pq.as_str()).as_bytes());
+    hasher.update(b"\n");
+    hasher.update(body);
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn error(status: StatusCode, message: String) -> Response {
+    (status, Json(ApiResponse::<()>::error(message))).into_response()
+}
+
+fn replay(stored: IdempotentResponse) -> Response {
+    let status = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+    let mut response = (status, Bytes::from(stored.body)).into_response();
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    headers.insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+    response
+}
+
+/// Process a write with an `Idempotency-Key` at most once per caller. A retry gets the
+/// recorded response with `Idempotent-Replayed: true`; a retry while the first request
+/// is still running gets 409, and a key reused for a different request 422. Failed
+/// requests aren't recorded, so they can be retried with the same key.
+pub async fn idempotent_writes(
+    State(state): State<Arc<AppState>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(key) = req.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+        return next.run(req).await;
+    };
+    let key = match key.to_str().map_err(|e| e.to_string()).and_then(|key| {
+        validate_key(key)?;
+        Ok(key.to_string())
+    }) {
+        Ok(key) => key,
+        Err(message) => return error(StatusCode::BAD_REQUEST, message),
+    };
+    let keys = &state.idempotency_keys;
+    let actor = quota::api_key_id(req.headers());
+
+    let (parts, body) = req.into_parts();
+    let Ok(body) = to_bytes(body, keys.max_body).await else {
+        return error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("Request body exceeds {} bytes", keys.max_body),
+        );
+    };
+    let request_hash = request_hash(&parts.method, &parts.uri, &body);
+
+    let Some(_in_flight) = keys.begin(&actor, &key) else {
+        return error(
+            StatusCode::CONFLICT,
+            format!("A request with Idempotency-Key '{}' is still being processed", key),
+        );
+    };
+    match state.metadata_store.idempotent_response(&actor, &key, keys.expired_before()) {
+        Ok(Some(stored)) if stored.request_hash == request_hash => return replay(stored),
+        Ok(Some(_)) => {
+            return error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("Idempotency-Key '{}' was already used for a different request", key),
+            );
+        }
+        Ok(None) => {}
+        Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    if !response.status().is_success() {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    };
+    let stored = IdempotentResponse {
+        request_hash,
+        status: parts.status.as_u16(),
+        body: body.to_vec(),
+    };
+    if let Err(e) = state.metadata_store.record_idempotent_response(
+        &actor,
+        &key,
+        &stored,
+        keys.expired_before(),
+    ) {
+        tracing::warn!("Failed to record Idempotency-Key '{}': {}", key, e);
+    }
+    Response::from_parts(parts, Body::from(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identifies_requests_and_keys() {
+        assert!(validate_key("import-2024-05-01 #3").is_ok());
+        assert!(validate_key("").is_err());
+        assert!(validate_key("naïve").is_err());
+        assert!(validate_key(&"k".repeat(MAX_KEY_LENGTH + 1)).is_err());
+
+        let uri: Uri = "/indices/products/documents?refresh=true".parse().unwrap();
+        let hash = request_hash(&Method::POST, &uri, b"{}");
+        assert_eq!(hash, request_hash(&Method::POST, &uri, b"{}"));
+        assert_ne!(hash, request_hash(&Method::POST, &uri, b"{\"documents\":[]}"));
+        let other: Uri = "/indices/products/documents".parse().unwrap();
+        assert_ne!(hash, request_hash(&Method::POST, &other, b"{}"));
+    }
+}
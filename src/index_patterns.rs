@@ -0,0 +1,59 @@
+/// Whether the index name of a search is a pattern (`logs-2024-*`) or a comma-separated
+/// list of names and patterns, rather than the name of one index
+pub fn is_pattern(name: &str) -> bool {
+    name.contains('*') || name.contains(',')
+}
+
+/// Whether an index name matches one of the comma-separated names or patterns
+pub fn matches(pattern: &str, name: &str) -> bool {
+    pattern
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .any(|part| wildcard_match(part, name))
+}
+
+/// Match a name against a pattern where `*` stands for any run of characters
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = name.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    // Taking the first occurrence of each part leaves the most room for the rest
+    for part in middle {
+        match rest.find(part) {
+            Some(position) => rest = &rest[position + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_wildcards() {
+        assert!(matches("logs-2024-*", "logs-2024-01"));
+        assert!(matches("logs-*-eu", "logs-2024-01-eu"));
+        assert!(matches("*", "products"));
+        assert!(matches("*-000001", "logs-000001"));
+        assert!(!matches("logs-2024-*", "logs-2023-12"));
+        assert!(!matches("logs-*-eu", "logs-eu"));
+        assert!(!matches("logs", "logs-000001"));
+    }
+
+    #[test]
+    fn test_matches_lists() {
+        assert!(is_pattern("logs,metrics") && is_pattern("logs-*"));
+        assert!(!is_pattern("logs"));
+        assert!(matches("logs, metrics-*", "metrics-2024"));
+        assert!(matches("logs,metrics", "logs"));
+        assert!(!matches("logs,metrics", "traces"));
+    }
+}
@@ -0,0 +1,510 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use futures_util::FutureExt;
+use reqwest::Client;
+use scraper::Html;
+use std::collections::{HashMap, HashSet};
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::crawler;
+use crate::models::{CrawlerConfig, Document, JobConfig, JobStatus, JobTask, RolloverRequest};
+use crate::rollover;
+use crate::validation::MAX_DOCUMENTS_PER_REQUEST;
+use crate::AppState;
+
+/// How often the scheduler checks for jobs that are due
+const SCHEDULER_TICK_SECS: u64 = 15;
+
+/// Runs scheduled jobs and tracks which ones are in progress
+pub struct JobRunner {
+    http: Client,
+    snapshots_dir: PathBuf,
+    running: Arc<Mutex<HashSet<String>>>,
+}
+
+/// Marks a job as running until dropped, so a job that fails or panics can run again
+struct Running {
+    running: Arc<Mutex<HashSet<String>>>,
+    job_id: String,
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.running.lock().unwrap().remove(&self.job_id);
+    }
+}
+
+impl JobRunner {
+    pub fn new(data_dir: &str) -> Result<Self> {
+        let http = Client::builder()
+            .timeout(Duration::from_secs(30))
+            .user_agent(concat!("simple-search-service/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Ok(Self {
+            http,
+            snapshots_dir: Path::new(data_dir).join("snapshots"),
+            running: Arc::new(Mutex::new(HashSet::new())),
+        })
+    }
+
+    pub fn is_running(&self, job_id: &str) -> bool {
+        self.running.lock().unwrap().contains(job_id)
+    }
+
+    /// Mark a job as running until the guard is dropped, unless it already is
+    fn try_start(&self, job_id: &str) -> Option<Running> {
+        if !self.running.lock().unwrap().insert(job_id.to_string()) {
+            return None;
+        }
+        Some(Running { running: self.running.clone(), job_id: job_id.to_string() })
+    }
+}
+
+/// Message of a panic caught while running a job or crawl
+fn panic_message(panic: Box<dyn std::any::Any + Send>) -> String {
+    let message = panic
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown error".to_string());
+    format!("Panicked: {}", message)
+}
+
+/// Parse a cron expression. Standard 5-field expressions (minute, hour, day of
+/// month, month, day of week) are accepted alongside 6-field ones with seconds.
+pub fn parse_schedule(expression: &str) -> Result<Schedule> {
+    let expression = expression.trim();
+    let normalized = if expression.split_whitespace().count() == 5 {
+        format!("0 {}", expression)
+    } else {
+        expression.to_string()
+    };
+    Schedule::from_str(&normalized)
+        .map_err(|e| anyhow!("Invalid cron expression '{}': {}", expression, e))
+}
+
+/// Short name of a task type, used for default job names
+pub fn task_kind(task: &JobTask) -> &'static str {
+    match task {
+        JobTask::Crawl { .. } => "crawl",
+        JobTask::Feed { .. } => "feed",
+        JobTask::Reindex => "reindex",
+        JobTask::Snapshot { .. } => "snapshot",
+        JobTask::Rollover => "rollover",
+        JobTask::AnalyticsRetention => "analytics retention",
+    }
+}
+
+/// Next time a job is due, counted from its last run (or creation)
+fn next_run(job: &JobConfig) -> Option<DateTime<Utc>> {
+    if !job.enabled {
+        return None;
+    }
+    let schedule = parse_schedule(&job.schedule).ok()?;
+    let since = job.last_run_at.as_deref().unwrap_or(&job.created_at);
+    let since = DateTime::parse_from_rfc3339(since)
+        .ok()?
+        .with_timezone(&Utc);
+    schedule.after(&since).next()
+}
+
+/// Attach the live scheduling state to a stored job
+pub fn job_status(state: &AppState, job: JobConfig) -> JobStatus {
+    JobStatus {
+        running: state.jobs.is_running(&job.id),
+        next_run_at: next_run(&job).map(|t| t.to_rfc3339()),
+        job,
+    }
+}
+
+/// Start a job in the background. Returns false if it is already running.
+pub fn spawn_job(state: Arc<AppState>, job: JobConfig) -> bool {
+    let Some(running) = state.jobs.try_start(&job.id) else {
+        return false;
+    };
+
+    tokio::spawn(async move {
+        let start = Instant::now();
+        let result = AssertUnwindSafe(execute(&state, &job))
+            .catch_unwind()
+            .await
+            .unwrap_or_else(|panic| Err(anyhow!(panic_message(panic))));
+        drop(running);
+
+        let (status, message) = match result {
+            Ok(message) => {
+                tracing::info!(
+                    "Job '{}' finished in {:.0}ms: {}",
+                    job.name,
+                    start.elapsed().as_secs_f64() * 1000.0,
+                    message
+                );
+                ("success", message)
+            }
+            Err(e) => {
+                tracing::warn!("Job '{}' failed: {}", job.name, e);
+                ("failed", e.to_string())
+            }
+        };
+        if let Err(e) = state
+            .metadata_store
+            .record_job_run(&job.id, status, &message)
+        {
+            tracing::warn!("Failed to record run for job '{}': {}", job.id, e);
+        }
+    });
+
+    true
+}
+
+/// Key a crawl is marked running under, so a crawler runs once at a time however it
+/// was started
+fn crawl_key(crawler_id: &str) -> String {
+    format!("crawler:{}", crawler_id)
+}
+
+/// Start a crawl in the background. Returns false if the crawler is already running.
+pub fn spawn_crawl(state: Arc<AppState>, config: CrawlerConfig) -> bool {
+    let Some(running) = state.jobs.try_start(&crawl_key(&config.id)) else {
+        return false;
+    };
+
+    tokio::spawn(async move {
+        let result = AssertUnwindSafe(crawler::run_crawler(&state, &config))
+            .catch_unwind()
+            .await
+            .unwrap_or_else(|panic| Err(anyhow!(panic_message(panic))));
+        drop(running);
+        match result {
+            Ok(report) => tracing::info!(
+                "Crawler '{}' indexed {} document(s) into '{}'",
+                config.id,
+                report.documents_indexed,
+                config.index_name
+            ),
+            Err(e) => tracing::warn!("Crawler '{}' failed: {}", config.id, e),
+        }
+    });
+
+    true
+}
+
+/// Whether the index a job runs on exists. Rollover jobs run on a rolling index's alias.
+pub fn job_index_exists(state: &AppState, task: &JobTask, index_name: &str) -> bool {
+    match task {
+        JobTask::Rollover => state.search_engine.rollover_alias(index_name).is_some(),
+        _ => state.search_engine.list_indices().iter().any(|name| name == index_name),
+    }
+}
+
+async fn execute(state: &Arc<AppState>, job: &JobConfig) -> Result<String> {
+    if !job_index_exists(state, &job.task, &job.index_name) {
+        return Err(anyhow!("Index not found: {}", job.index_name));
+    }
+
+    match &job.task {
+        JobTask::Crawl { crawler_id } => {
+            let config = state
+                .metadata_store
+                .list_crawlers(Some(&job.index_name))?
+                .into_iter()
+                .find(|c| &c.id == crawler_id)
+                .ok_or_else(|| anyhow!("Crawler not found: {}", crawler_id))?;
+            let running = state
+                .jobs
+                .try_start(&crawl_key(crawler_id))
+                .ok_or_else(|| anyhow!("Crawler is already running: {}", crawler_id))?;
+            let report = crawler::run_crawler(state, &config).await;
+            drop(running);
+            let report = report?;
+            Ok(format!(
+                "Fetched {} page(s), indexed {} document(s)",
+                report.pages_fetched, report.documents_indexed
+            ))
+        }
+        JobTask::Feed { url, max_items } => {
+            pull_feed(state, &job.index_name, url, *max_items).await
+        }
+        JobTask::Reindex => {
+            let state = state.clone();
+            let index_name = job.index_name.clone();
+            tokio::task::spawn_blocking(move || reindex(&state, &index_name)).await?
+        }
+        JobTask::Snapshot { keep } => {
+            let state = state.clone();
+            let index_name = job.index_name.clone();
+            let keep = *keep;
+            tokio::task::spawn_blocking(move || snapshot(&state, &index_name, keep)).await?
+        }
+        JobTask::Rollover => {
+            let state = state.clone();
+            let index_name = job.index_name.clone();
+            tokio::task::spawn_blocking(move || roll_over(&state, &index_name)).await?
+        }
+        JobTask::AnalyticsRetention => {
+            let state = state.clone();
+            let index_name = job.index_name.clone();
+            tokio::task::spawn_blocking(move || prune_analytics(&state, &index_name)).await?
+        }
+    }
+}
+
+/// Fetch an RSS/Atom feed and index its entries, replacing earlier copies
+async fn pull_feed(
+    state: &AppState,
+    index_name: &str,
+    url: &str,
+    max_items: Option<usize>,
+) -> Result<String> {
+    let bytes = state
+        .jobs
+        .http
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    let feed = feed_rs::parser::parse(bytes.as_ref())
+        .map_err(|e| anyhow!("Failed to parse feed: {}", e))?;
+
+    let documents: Vec<Document> = feed
+        .entries
+        .into_iter()
+        .take(max_items.unwrap_or(usize::MAX))
+        .map(feed_entry_to_document)
+        .collect();
+
+    let _permit = state.request_pools.indexing.acquire().await;
+    let ids: Vec<String> = documents.iter().map(|d| d.id.clone()).collect();
+    if !ids.is_empty() {
+        state.search_engine.delete_documents(index_name, &ids)?;
+        for doc_id in &ids {
+            state.metadata_store.delete_document(index_name, doc_id)?;
+        }
+    }
+
+    let mut indexed = 0;
+    for chunk in documents.chunks(MAX_DOCUMENTS_PER_REQUEST) {
+        let outcome = state.search_engine.add_documents(index_name, chunk)?;
+        state
+            .metadata_store
+            .apply_ingest_outcome(index_name, &outcome)?;
+        indexed += outcome.indexed_ids.len();
+    }
+
+    Ok(format!("Indexed {} feed entr(ies)", indexed))
+}
+
+fn feed_entry_to_document(entry: feed_rs::model::Entry) -> Document {
+    let mut fields = HashMap::new();
+
+    if let Some(title) = entry.title {
+        fields.insert("title".to_string(), serde_json::json!(title.content));
+    }
+    let body = entry
+        .content
+        .and_then(|c| c.body)
+        .or(entry.summary.map(|s| s.content));
+    if let Some(body) = body {
+        fields.insert(
+            "content".to_string(),
+            serde_json::json!(html_to_text(&body)),
+        );
+    }
+    if let Some(link) = entry.links.first() {
+        fields.insert("url".to_string(), serde_json::json!(link.href));
+    }
+    if let Some(published) = entry.published.or(entry.updated) {
+        fields.insert(
+            "published".to_string(),
+            serde_json::json!(published.to_rfc3339()),
+        );
+    }
+
+    Document {
+        id: entry.id,
+        fields,
+    }
+}
+
+fn html_to_text(html: &str) -> String {
+    let fragment = Html::parse_fragment(html);
+    let text: Vec<&str> = fragment.root_element().text().collect();
+    text.join(" ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Re-analyze the documents, merge segments and rebuild the document metadata
+fn reindex(state: &AppState, index_name: &str) -> Result<String> {
+    let reindexed = state.search_engine.reindex_documents(index_name)?;
+    let segments = state.search_engine.optimize_index(index_name)?;
+    let doc_ids = state.search_engine.collect_document_ids(index_name)?;
+    state
+        .metadata_store
+        .reset_index_documents(index_name, &doc_ids)?;
+    Ok(format!(
+        "Reindexed {} document(s), merged {} segment(s)",
+        reindexed, segments
+    ))
+}
+
+/// Copy the index into a timestamped snapshot directory and prune old snapshots
+fn snapshot(state: &AppState, index_name: &str, keep: usize) -> Result<String> {
+    let index_dir = state.jobs.snapshots_dir.join(index_name);
+    let name = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let files = state
+        .search_engine
+        .snapshot_index(index_name, &index_dir.join(&name))?;
+
+    // Timestamped names sort chronologically
+    let mut snapshots: Vec<PathBuf> = std::fs::read_dir(&index_dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_dir())
+        .collect();
+    snapshots.sort();
+    let excess = snapshots.len().saturating_sub(keep.max(1));
+    for old in &snapshots[..excess] {
+        std::fs::remove_dir_all(old)?;
+    }
+
+    Ok(format!(
+        "Created snapshot {} with {} file(s), removed {} old snapshot(s)",
+        name, files, excess
+    ))
+}
+
+/// Roll a rolling index over if its conditions are met and apply its retention policy
+fn roll_over(state: &AppState, alias: &str) -> Result<String> {
+    let response = rollover::rollover(state, alias, &RolloverRequest::default())?;
+    let rolled = if response.rolled_over {
+        format!("Rolled over to {}", response.new_index)
+    } else {
+        format!("Kept {} (no condition met)", response.old_index)
+    };
+    Ok(format!(
+        "{}, deleted {} old generation(s)",
+        rolled,
+        response.deleted_indices.len()
+    ))
+}
+
+/// Drop search analytics older than the days kept
+fn prune_analytics(state: &AppState, index_name: &str) -> Result<String> {
+    let (days, sessions, queries) = state.metadata_store.prune_query_log(index_name)?;
+    Ok(format!(
+        "Dropped {} day count(s), {} session(s) and {} one-off query(ies)",
+        days, sessions, queries
+    ))
+}
+
+/// Periodically start enabled jobs whose next scheduled time has passed, and crawlers
+/// whose interval has elapsed since their last run
+pub async fn run_scheduler(state: Arc<AppState>) {
+    let mut ticker = tokio::time::interval(Duration::from_secs(SCHEDULER_TICK_SECS));
+
+    loop {
+        ticker.tick().await;
+
+        let jobs = match state.metadata_store.list_jobs() {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                tracing::warn!("Failed to load jobs: {}", e);
+                continue;
+            }
+        };
+
+        let now = Utc::now();
+        for job in jobs {
+            let due = next_run(&job).map(|next| next <= now).unwrap_or(false);
+            if due && !state.jobs.is_running(&job.id) {
+                spawn_job(state.clone(), job);
+            }
+        }
+
+        match state.metadata_store.list_crawlers(None) {
+            Ok(crawlers) => {
+                for config in crawlers {
+                    if crawler::is_due(&config, now) {
+                        spawn_crawl(state.clone(), config);
+                    }
+                }
+            }
+            Err(e) => tracing::warn!("Failed to load crawlers: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_parse_schedule_accepts_five_and_six_fields() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 10, 7, 30).unwrap();
+
+        let every_quarter = parse_schedule("*/15 * * * *").unwrap();
+        assert_eq!(
+            every_quarter.after(&start).next(),
+            Some(Utc.with_ymd_and_hms(2024, 1, 1, 10, 15, 0).unwrap())
+        );
+
+        let with_seconds = parse_schedule("30 0 3 * * *").unwrap();
+        assert_eq!(
+            with_seconds.after(&start).next(),
+            Some(Utc.with_ymd_and_hms(2024, 1, 2, 3, 0, 30).unwrap())
+        );
+
+        assert!(parse_schedule("not a schedule").is_err());
+    }
+
+    #[test]
+    fn test_html_to_text() {
+        assert_eq!(
+            html_to_text("<p>Hello <b>feed</b></p>\n<p>reader</p>"),
+            "Hello feed reader"
+        );
+    }
+
+    #[test]
+    fn test_analytics_jobs_created_as_rollups_still_load() {
+        for kind in ["analytics_retention", "analytics_rollup"] {
+            let task: JobTask = serde_json::from_value(serde_json::json!({"type": kind})).unwrap();
+            assert!(matches!(task, JobTask::AnalyticsRetention));
+            assert_eq!(task_kind(&task), "analytics retention");
+        }
+        let task = serde_json::to_value(JobTask::AnalyticsRetention).unwrap();
+        assert_eq!(task, serde_json::json!({"type": "analytics_retention"}));
+    }
+
+    #[tokio::test]
+    async fn test_jobs_that_panic_can_run_again() {
+        let jobs = JobRunner::new(std::env::temp_dir().to_str().unwrap()).unwrap();
+        let running = jobs.try_start("job").unwrap();
+        assert!(jobs.is_running("job"));
+        assert!(jobs.try_start("job").is_none());
+        drop(running);
+        assert!(!jobs.is_running("job"));
+
+        let running = jobs.try_start("job").unwrap();
+        let job = async move {
+            let _running = running;
+            panic!("index is gone");
+        };
+        let result: Result<String> = AssertUnwindSafe(job)
+            .catch_unwind()
+            .await
+            .unwrap_or_else(|panic| Err(anyhow!(panic_message(panic))));
+        assert_eq!(result.unwrap_err().to_string(), "Panicked: index is gone");
+        assert!(!jobs.is_running("job"));
+        assert!(jobs.try_start("job").is_some());
+    }
+}
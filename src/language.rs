@@ -0,0 +1,120 @@
+use tantivy::tokenizer::{Language, LowerCaser, SimpleTokenizer, Stemmer, TextAnalyzer};
+use tantivy::Index;
+use whatlang::{Detector, Lang};
+
+/// Name of the stored/indexed field holding the detected document language
+pub const LANGUAGE_FIELD: &str = "_lang";
+
+/// Value stored in the language field when detection fails
+pub const UNKNOWN_LANGUAGE: &str = "unknown";
+
+/// Analyzer name that enables language detection for a text field
+pub const AUTO_ANALYZER: &str = "auto";
+
+/// Separator between a text field name and its per-language sub-field suffix
+const SUB_FIELD_SEPARATOR: &str = "__";
+
+/// Languages with a dedicated stemming analyzer: (code, whatlang language, stemmer language)
+const SUPPORTED_LANGUAGES: &[(&str, Lang, Language)] = &[
+    ("en", Lang::Eng, Language::English),
+    ("no", Lang::Nob, Language::Norwegian),
+    ("sv", Lang::Swe, Language::Swedish),
+    ("da", Lang::Dan, Language::Danish),
+    ("de", Lang::Deu, Language::German),
+    ("fr", Lang::Fra, Language::French),
+    ("es", Lang::Spa, Language::Spanish),
+    ("nl", Lang::Nld, Language::Dutch),
+    ("fi", Lang::Fin, Language::Finnish),
+    ("it", Lang::Ita, Language::Italian),
+    ("pt", Lang::Por, Language::Portuguese),
+];
+
+/// Returns all supported language codes
+pub fn supported_codes() -> Vec<String> {
+    SUPPORTED_LANGUAGES
+        .iter()
+        .map(|(code, _, _)| code.to_string())
+        .collect()
+}
+
+/// Checks whether a language code has a dedicated analyzer
+pub fn is_supported(code: &str) -> bool {
+    SUPPORTED_LANGUAGES.iter().any(|(c, _, _)| *c == code)
+}
+
+/// Tokenizer name registered for a language code
+pub fn analyzer_name(code: &str) -> String {
+    format!("lang_{}", code)
+}
+
+/// Name of the per-language sub-field for a text field
+pub fn sub_field_name(field_name: &str, code: &str) -> String {
+    format!("{}{}{}", field_name, SUB_FIELD_SEPARATOR, code)
+}
+
+/// Splits a sub-field name into (base field, language code) if it is one
+pub fn parse_sub_field_name(name: &str) -> Option<(&str, &str)> {
+    let (base, code) = name.rsplit_once(SUB_FIELD_SEPARATOR)?;
+    if base.is_empty() || !is_supported(code) {
+        return None;
+    }
+    Some((base, code))
+}
+
+/// Register a stemming analyzer for every supported language
+pub fn register_analyzers(index: &Index) {
+    for (code, _, language) in SUPPORTED_LANGUAGES {
+        let analyzer = TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(LowerCaser)
+            .filter(Stemmer::new(*language))
+            .build();
+        index.tokenizers().register(&analyzer_name(code), analyzer);
+    }
+}
+
+/// Detect the language of a text, restricted to the given language codes.
+/// Returns `None` when the text is too short or ambiguous to classify.
+pub fn detect(text: &str, codes: &[String]) -> Option<&'static str> {
+    let allowlist: Vec<Lang> = SUPPORTED_LANGUAGES
+        .iter()
+        .filter(|(code, _, _)| codes.iter().any(|c| c == code))
+        .map(|(_, lang, _)| *lang)
+        .collect();
+
+    if allowlist.is_empty() {
+        return None;
+    }
+
+    let info = Detector::with_allowlist(allowlist).detect(text)?;
+
+    SUPPORTED_LANGUAGES
+        .iter()
+        .find(|(_, lang, _)| *lang == info.lang())
+        .map(|(code, _, _)| *code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_norwegian_and_english() {
+        let codes = vec!["en".to_string(), "no".to_string()];
+        assert_eq!(
+            detect("Barnehagen har store uteområder og en flott lekeplass for barna", &codes),
+            Some("no")
+        );
+        assert_eq!(
+            detect("The kindergarten has a large outdoor area and a great playground", &codes),
+            Some("en")
+        );
+    }
+
+    #[test]
+    fn test_sub_field_names() {
+        assert_eq!(sub_field_name("content", "en"), "content__en");
+        assert_eq!(parse_sub_field_name("content__en"), Some(("content", "en")));
+        assert_eq!(parse_sub_field_name("content__xx"), None);
+        assert_eq!(parse_sub_field_name("content"), None);
+    }
+}
@@ -1,16 +1,41 @@
 use anyhow::{anyhow, Result};
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
+/// Name of the provider configured with the `MISTRAL_*` variables
+pub const DEFAULT_PROVIDER: &str = "mistral";
+
+/// Endpoint and model a request can be sent to
 #[derive(Clone)]
-pub struct LlmClient {
-    http: Client,
+struct Provider {
     base_url: String,
     api_key: String,
     model: String,
 }
 
+impl Provider {
+    fn completions_url(&self) -> String {
+        format!(
+            "{}/chat/completions",
+            self.base_url.trim_end_matches('/')
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct LlmClient {
+    http: Client,
+    /// The configured provider first, then the fallbacks in order
+    providers: Vec<Provider>,
+    /// Providers indices and requests can choose by name
+    named: Vec<(String, Provider)>,
+    /// Retries per provider for rate limits, server errors and connection failures
+    max_retries: u32,
+    /// Wait before the first retry; doubled for each further retry
+    retry_backoff: Duration,
+}
+
 impl LlmClient {
     pub fn from_env() -> Option<Self> {
         let api_key = std::env::var("MISTRAL_API_KEY").ok()?;
@@ -28,64 +53,207 @@ impl LlmClient {
             .build()
             .ok()?;
 
-        Some(Self {
-            http,
+        let primary = Provider {
             base_url,
             api_key,
             model,
+        };
+        let mut providers = vec![primary.clone()];
+        if let Ok(fallbacks) = std::env::var("LLM_FALLBACKS") {
+            providers.extend(parse_fallbacks(&fallbacks, &primary));
+        }
+        let mut named = vec![(DEFAULT_PROVIDER.to_string(), primary)];
+        if let Ok(value) = std::env::var("LLM_PROVIDERS") {
+            named.extend(parse_named_providers(&value));
+        }
+
+        let var = |name: &str| -> Option<u64> {
+            std::env::var(name).ok().and_then(|v| v.trim().parse().ok())
+        };
+        Some(Self {
+            http,
+            providers,
+            named,
+            max_retries: var("LLM_MAX_RETRIES").unwrap_or(2) as u32,
+            retry_backoff: Duration::from_millis(var("LLM_RETRY_BACKOFF_MS").unwrap_or(500)),
         })
     }
 
+    /// Model of the configured provider
     pub fn model(&self) -> &str {
-        &self.model
+        &self.providers[0].model
     }
 
-    fn completions_url(&self) -> String {
-        format!(
-            "{}/chat/completions",
-            self.base_url.trim_end_matches('/')
-        )
+    pub fn has_provider(&self, name: &str) -> bool {
+        self.named.iter().any(|(n, _)| n == name)
+    }
+
+    /// Client that sends requests to the named provider and/or model first, falling
+    /// back to the configured fallbacks as usual. Errors for an unknown provider name.
+    pub fn with_model(&self, provider: Option<&str>, model: Option<&str>) -> Result<Self> {
+        let mut client = self.clone();
+        if let Some(name) = provider {
+            let (_, provider) = self
+                .named
+                .iter()
+                .find(|(n, _)| n == name)
+                .ok_or_else(|| anyhow!("Unknown LLM provider: {}", name))?;
+            client.providers[0] = provider.clone();
+        }
+        if let Some(model) = model {
+            client.providers[0].model = model.to_string();
+        }
+        Ok(client)
+    }
+
+    /// Send a request to the first provider that accepts it. Rate limits, server
+    /// errors and connection failures are retried with backoff before moving on to
+    /// the next provider; other errors move on straight away.
+    async fn send(&self, request: &ChatCompletionRequest) -> Result<(reqwest::Response, String)> {
+        let mut last_error = anyhow!("No LLM providers configured");
+        for provider in &self.providers {
+            let mut request = request.clone();
+            request.model = provider.model.clone();
+
+            for attempt in 0..=self.max_retries {
+                if attempt > 0 {
+                    tokio::time::sleep(self.retry_backoff * 2u32.pow(attempt - 1)).await;
+                }
+
+                let transient = match self
+                    .http
+                    .post(provider.completions_url())
+                    .bearer_auth(&provider.api_key)
+                    .json(&request)
+                    .send()
+                    .await
+                {
+                    Ok(response) if response.status().is_success() => {
+                        return Ok((response, provider.model.clone()));
+                    }
+                    Ok(response) => {
+                        let status = response.status();
+                        let body = response.text().await.unwrap_or_default();
+                        last_error = anyhow!(
+                            "LLM request to {} failed with status {}: {}",
+                            provider.model,
+                            status,
+                            body
+                        );
+                        status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+                    }
+                    Err(e) => {
+                        last_error = anyhow!("LLM request to {} failed: {}", provider.model, e);
+                        true
+                    }
+                };
+
+                tracing::warn!("{} (attempt {})", last_error, attempt + 1);
+                if !transient {
+                    break;
+                }
+            }
+        }
+        Err(last_error)
     }
 
     pub async fn complete(
         &self,
         request: ChatCompletionRequest,
     ) -> Result<ChatCompletionResponse> {
-        let response = self
-            .http
-            .post(self.completions_url())
-            .bearer_auth(&self.api_key)
-            .json(&request)
-            .send()
-            .await?
-            .error_for_status()?;
-
-        Ok(response.json::<ChatCompletionResponse>().await?)
+        let (response, model) = self.send(&request).await?;
+        let mut completion = response.json::<ChatCompletionResponse>().await?;
+        completion.model = model;
+        Ok(completion)
     }
 
-    pub async fn stream(&self, request: ChatCompletionRequest) -> Result<reqwest::Response> {
-        let response = self
-            .http
-            .post(self.completions_url())
-            .bearer_auth(&self.api_key)
-            .json(&request)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            return Err(anyhow!(
-                "LLM request failed with status {}: {}",
-                status,
-                body
-            ));
-        }
-
-        Ok(response)
+    /// Start a streamed completion, returning the response and the model that serves it
+    pub async fn stream(
+        &self,
+        request: ChatCompletionRequest,
+    ) -> Result<(reqwest::Response, String)> {
+        self.send(&request).await
     }
 }
 
+/// Fallback providers from a comma-separated list. An entry is either a model name,
+/// served by the configured provider, or `base_url|API_KEY_VARIABLE|model` for
+/// another OpenAI-compatible provider whose key is read from the named variable.
+fn parse_fallbacks(value: &str, primary: &Provider) -> Vec<Provider> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .filter_map(|entry| {
+            let parts: Vec<&str> = entry.split('|').map(str::trim).collect();
+            match parts.as_slice() {
+                [model] => Some(Provider {
+                    model: model.to_string(),
+                    ..primary.clone()
+                }),
+                [base_url, key_variable, model] => match std::env::var(key_variable) {
+                    Ok(api_key) => Some(Provider {
+                        base_url: base_url.to_string(),
+                        api_key,
+                        model: model.to_string(),
+                    }),
+                    Err(_) => {
+                        tracing::warn!(
+                            "Skipping LLM fallback '{}': {} is not set",
+                            model,
+                            key_variable
+                        );
+                        None
+                    }
+                },
+                _ => {
+                    tracing::warn!("Ignoring invalid LLM fallback '{}'", entry);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Providers from a comma-separated list of `name|base_url|API_KEY_VARIABLE|model`
+/// entries; the key is read from the named variable so it never has to be sent to the API
+fn parse_named_providers(value: &str) -> Vec<(String, Provider)> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .filter_map(|entry| {
+            let parts: Vec<&str> = entry.split('|').map(str::trim).collect();
+            let [name, base_url, key_variable, model] = parts.as_slice() else {
+                tracing::warn!("Ignoring invalid LLM provider '{}'", entry);
+                return None;
+            };
+            if *name == DEFAULT_PROVIDER {
+                tracing::warn!("Ignoring LLM provider '{}': the name is reserved", name);
+                return None;
+            }
+            match std::env::var(key_variable) {
+                Ok(api_key) => Some((
+                    name.to_string(),
+                    Provider {
+                        base_url: base_url.to_string(),
+                        api_key,
+                        model: model.to_string(),
+                    },
+                )),
+                Err(_) => {
+                    tracing::warn!(
+                        "Skipping LLM provider '{}': {} is not set",
+                        name,
+                        key_variable
+                    );
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ChatMessage {
     pub role: String,
@@ -106,6 +274,19 @@ pub struct ChatCompletionRequest {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ChatCompletionResponse {
     pub choices: Vec<ChatCompletionChoice>,
+    /// Model that produced the completion, which may be a fallback
+    #[serde(default)]
+    pub model: String,
+    #[serde(default)]
+    pub usage: Option<Usage>,
+}
+
+/// Token counts reported by the provider
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct Usage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -116,6 +297,9 @@ pub struct ChatCompletionChoice {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ChatCompletionStreamChunk {
     pub choices: Vec<ChatCompletionStreamChoice>,
+    /// Sent with the last chunk by providers that report usage while streaming
+    #[serde(default)]
+    pub usage: Option<Usage>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -130,3 +314,122 @@ pub struct ChatCompletionDelta {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub content: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::{Path, State};
+    use axum::Json;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    /// Serve completions at `/<name>/chat/completions`: `down` fails with 503, `bad`
+    /// with 400, and others answer. Returns the base URL and the requests per name.
+    async fn serve_providers() -> (String, Arc<Mutex<HashMap<String, usize>>>) {
+        async fn complete(
+            State(hits): State<Arc<Mutex<HashMap<String, usize>>>>,
+            Path(name): Path<String>,
+        ) -> Result<Json<serde_json::Value>, StatusCode> {
+            *hits.lock().unwrap().entry(name.clone()).or_default() += 1;
+            match name.as_str() {
+                "down" => Err(StatusCode::SERVICE_UNAVAILABLE),
+                "bad" => Err(StatusCode::BAD_REQUEST),
+                _ => Ok(Json(serde_json::json!({
+                    "choices": [{"message": {"role": "assistant", "content": name}}]
+                }))),
+            }
+        }
+
+        let hits = Arc::new(Mutex::new(HashMap::new()));
+        let app = axum::Router::new()
+            .route("/:name/chat/completions", axum::routing::post(complete))
+            .with_state(hits.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (base_url, hits)
+    }
+
+    fn client(base_url: &str, names: &[&str]) -> LlmClient {
+        let providers = names
+            .iter()
+            .map(|name| Provider {
+                base_url: format!("{}/{}", base_url, name),
+                api_key: "key".to_string(),
+                model: name.to_string(),
+            })
+            .collect();
+        LlmClient {
+            http: Client::new(),
+            providers,
+            named: Vec::new(),
+            max_retries: 2,
+            retry_backoff: Duration::from_millis(1),
+        }
+    }
+
+    fn request() -> ChatCompletionRequest {
+        ChatCompletionRequest {
+            model: String::new(),
+            messages: vec![ChatMessage {
+                role: "user".to_string(),
+                content: "hi".to_string(),
+            }],
+            temperature: None,
+            max_tokens: None,
+            stream: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transient_errors_are_retried_before_falling_back() {
+        let (base_url, hits) = serve_providers().await;
+
+        let completion = client(&base_url, &["down", "bad", "up"])
+            .complete(request())
+            .await
+            .unwrap();
+        assert_eq!(completion.model, "up");
+        assert_eq!(completion.choices[0].message.content, "up");
+        // 503 is retried, 400 moves on to the next provider straight away
+        let hits = hits.lock().unwrap().clone();
+        assert_eq!(hits["down"], 3);
+        assert_eq!(hits["bad"], 1);
+        assert_eq!(hits["up"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_last_error_is_returned_when_every_provider_fails() {
+        let (base_url, _) = serve_providers().await;
+
+        let error = client(&base_url, &["bad", "down"])
+            .complete(request())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("down failed with status 503"));
+    }
+
+    #[tokio::test]
+    async fn test_requests_go_to_the_chosen_provider_and_model() {
+        let (base_url, _) = serve_providers().await;
+        let mut llm = client(&base_url, &["primary"]);
+        llm.named = vec![
+            (DEFAULT_PROVIDER.to_string(), llm.providers[0].clone()),
+            ("local".to_string(), client(&base_url, &["local"]).providers[0].clone()),
+        ];
+
+        let local = llm.with_model(Some("local"), None).unwrap();
+        let completion = local.complete(request()).await.unwrap();
+        assert_eq!(completion.choices[0].message.content, "local");
+        assert_eq!(completion.model, "local");
+
+        // A model alone keeps the provider
+        let custom = llm.with_model(None, Some("large")).unwrap();
+        let completion = custom.complete(request()).await.unwrap();
+        assert_eq!(completion.choices[0].message.content, "primary");
+        assert_eq!(completion.model, "large");
+
+        assert!(llm.has_provider("local"));
+        assert!(llm.with_model(Some("other"), None).is_err());
+    }
+}
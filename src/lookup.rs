@@ -0,0 +1,127 @@
+use axum::http::HeaderMap;
+use std::collections::HashMap;
+
+use crate::auth;
+use crate::error::SearchError;
+use crate::field_access;
+use crate::models::{Document, LookupRequest, SearchHit};
+use crate::AppState;
+
+/// IDs held by a hit's key field: a string or number, or an array of them
+fn key_ids(value: &serde_json::Value) -> Vec<String> {
+    match value {
+        serde_json::Value::String(id) => vec![id.clone()],
+        serde_json::Value::Number(id) => vec![id.to_string()],
+        serde_json::Value::Array(values) => values.iter().flat_map(key_ids).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// What is embedded for a key: the document found for a single ID (null if there is
+/// none), or the documents found for an array of IDs, in order
+fn embed(
+    key: &serde_json::Value,
+    documents: &HashMap<String, serde_json::Value>,
+) -> serde_json::Value {
+    match key {
+        serde_json::Value::Array(_) => serde_json::Value::Array(
+            key_ids(key)
+                .iter()
+                .filter_map(|id| documents.get(id).cloned())
+                .collect(),
+        ),
+        _ => key_ids(key)
+            .first()
+            .and_then(|id| documents.get(id).cloned())
+            .unwrap_or(serde_json::Value::Null),
+    }
+}
+
+/// Key field values of each hit, per lookup. Taken before the hit's fields are
+/// filtered, so a key field left out of the response can still be looked up.
+pub fn hit_keys(
+    lookups: &[LookupRequest],
+    hits: &[SearchHit],
+) -> Vec<Vec<Option<serde_json::Value>>> {
+    lookups
+        .iter()
+        .map(|lookup| hits.iter().map(|hit| hit.fields.get(&lookup.field).cloned()).collect())
+        .collect()
+}
+
+/// Embed the looked-up documents in the hits, with one search of each lookup's index
+/// for the whole page. The caller's search key and restricted fields apply to the
+/// looked-up index as they would to a search of it.
+pub fn enrich_hits(
+    state: &AppState,
+    headers: &HeaderMap,
+    lookups: &[LookupRequest],
+    keys: Vec<Vec<Option<serde_json::Value>>>,
+    hits: &mut [SearchHit],
+) -> Result<(), SearchError> {
+    for (lookup, hit_keys) in lookups.iter().zip(keys) {
+        let access = auth::search_access(state, &lookup.index, headers)?;
+        let mut ids: Vec<String> = hit_keys.iter().flatten().flat_map(key_ids).collect();
+        ids.sort();
+        ids.dedup();
+        let found = if ids.is_empty() {
+            HashMap::new()
+        } else {
+            state.search_engine.lookup_documents(&lookup.index, &ids, &access)?
+        };
+
+        let fields = if lookup.fields.is_empty() {
+            state
+                .search_engine
+                .index_settings(&lookup.index)
+                .map(|settings| settings.displayed_fields)
+                .unwrap_or_default()
+        } else {
+            lookup.fields.clone()
+        };
+        let documents: HashMap<String, serde_json::Value> = found
+            .into_iter()
+            .map(|(id, document)| (id, document_value(document, &fields, &access.hidden_fields)))
+            .collect();
+
+        for (hit, key) in hits.iter_mut().zip(hit_keys) {
+            if let Some(key) = key {
+                hit.fields.insert(lookup.target().to_string(), embed(&key, &documents));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A looked-up document as embedded: its ID and the selected fields it may show
+fn document_value(document: Document, fields: &[String], hidden: &[String]) -> serde_json::Value {
+    let mut value: serde_json::Map<String, serde_json::Value> = document
+        .fields
+        .into_iter()
+        .filter(|(name, _)| fields.is_empty() || fields.contains(name))
+        .filter(|(name, _)| !field_access::is_hidden(name, hidden))
+        .collect();
+    value.insert("id".to_string(), serde_json::Value::String(document.id));
+    serde_json::Value::Object(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_embeds_documents_by_key() {
+        let documents = HashMap::from([
+            ("a1".to_string(), json!({"id": "a1", "name": "Ada"})),
+            ("7".to_string(), json!({"id": "7", "name": "Grace"})),
+        ]);
+        assert_eq!(embed(&json!("a1"), &documents), json!({"id": "a1", "name": "Ada"}));
+        assert_eq!(embed(&json!(7), &documents)["name"], "Grace");
+        assert_eq!(embed(&json!("missing"), &documents), json!(null));
+        assert_eq!(
+            embed(&json!(["7", "missing", "a1"]), &documents),
+            json!([{"id": "7", "name": "Grace"}, {"id": "a1", "name": "Ada"}])
+        );
+    }
+}
@@ -1,22 +1,68 @@
 use axum::{
     extract::DefaultBodyLimit,
     middleware,
-    routing::{delete, get, post},
+    routing::{delete, get, post, put},
     Router,
 };
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::compression::CompressionLayer;
 use tower_http::trace::TraceLayer;
 
+mod answer_cache;
+mod audit;
 mod auth;
+mod backpressure;
+mod breaker;
+mod chunking;
+mod citations;
+mod context;
+mod cors;
+mod crawler;
+mod error;
+mod expiration;
+mod export;
+mod extraction;
+mod field_access;
+mod formats;
+mod filter_cache;
+mod geo;
+mod groundedness;
 mod handlers;
+mod http_cache;
+mod idempotency;
+mod index_patterns;
+mod jobs;
+mod language;
 mod llm;
+mod lookup;
+mod messages;
+mod migration;
 mod models;
+mod normalization;
+mod priority;
+mod prompts;
+mod query_syntax;
+mod quota;
+mod reconcile;
+mod redaction;
+mod relevance_report;
+mod rerank;
+mod rewrite;
+mod rollover;
 mod search;
+mod search_keys;
+mod sharding;
+mod stopwords;
 mod storage;
+mod summary;
+mod synonym_suggestions;
 mod validation;
+mod warmup;
+mod watcher;
 
+use crawler::Crawler;
+use jobs::JobRunner;
 use search::SearchEngine;
 use storage::MetadataStore;
 use llm::LlmClient;
@@ -24,8 +70,24 @@ use llm::LlmClient;
 pub struct AppState {
     search_engine: SearchEngine,
     metadata_store: MetadataStore,
-    api_tokens: Vec<String>,
+    api_tokens: auth::ApiTokens,
     llm_client: Option<LlmClient>,
+    crawler: Crawler,
+    jobs: JobRunner,
+    pagination: validation::PaginationLimits,
+    answer_budget: quota::AnswerBudget,
+    query_rewrites: rewrite::QueryRewriteCache,
+    answer_cache: answer_cache::AnswerCache,
+    redactor: redaction::Redactor,
+    metadata_sync: reconcile::SyncReports,
+    metadata_rebuild: reconcile::RebuildProgress,
+    search_cache: http_cache::SearchCacheHeaders,
+    warmup: warmup::Warmup,
+    migrations: migration::Migrations,
+    write_queues: backpressure::WriteQueues,
+    request_pools: priority::RequestPools,
+    exports: export::Exports,
+    idempotency_keys: idempotency::IdempotencyKeys,
 }
 
 #[tokio::main]
@@ -46,12 +108,7 @@ async fn main() -> anyhow::Result<()> {
     std::fs::create_dir_all(&data_dir)?;
 
     // Load API tokens from environment
-    let api_tokens: Vec<String> = std::env::var("API_TOKENS")
-        .unwrap_or_default()
-        .split(',')
-        .filter(|s| !s.is_empty())
-        .map(|s| s.trim().to_string())
-        .collect();
+    let api_tokens = auth::ApiTokens::from_env();
 
     if api_tokens.is_empty() {
         tracing::warn!("No API_TOKENS configured - authentication disabled");
@@ -63,97 +120,284 @@ async fn main() -> anyhow::Result<()> {
     }
 
     let metadata_store = MetadataStore::new(&format!("{}/metadata.db", data_dir))?;
-    let search_engine = SearchEngine::new(&format!("{}/indices", data_dir))?;
+    let search_threads = search::search_threads_from_env();
+    let search_engine = SearchEngine::new(
+        &format!("{}/indices", data_dir),
+        search_threads,
+        search::query_limits_from_env(),
+        filter_cache::FilterCache::from_env(),
+    )?;
     let llm_client = LlmClient::from_env();
+    let redactor = redaction::Redactor::from_env();
+    let crawler = Crawler::new(crawler::crawl_delay_from_env())?;
+    let jobs = JobRunner::new(&data_dir)?;
+
+    tracing::info!("Searching segments with {} thread(s)", search_threads);
 
     if llm_client.is_none() {
         tracing::warn!(
             "MISTRAL_API_KEY not set - generative answer endpoint disabled"
         );
     }
+    if redactor.is_enabled() {
+        tracing::info!("Redacting personal data before it is sent to the LLM");
+    }
 
     let loaded_indices = search_engine.load_indices()?;
+    // Closed indices stay listed, but their metadata isn't rebuilt until they are used
+    metadata_store.sync_indices_from_disk(&search_engine.list_indices())?;
     if loaded_indices.is_empty() {
         tracing::info!("No existing indices found to load");
     } else {
         tracing::info!("Loaded {} index(es): {:?}", loaded_indices.len(), loaded_indices);
-        metadata_store.sync_indices_from_disk(&loaded_indices)?;
-
-        for index_name in &loaded_indices {
-            match search_engine.collect_document_ids(index_name) {
-                Ok(doc_ids) => {
-                    if let Err(e) = metadata_store.reset_index_documents(index_name, &doc_ids) {
-                        tracing::warn!(
-                            "Failed to rebuild metadata documents for index '{}': {}",
-                            index_name,
-                            e
-                        );
-                    } else {
-                        tracing::info!(
-                            "Rebuilt metadata for index '{}' with {} document(s)",
-                            index_name,
-                            doc_ids.len()
-                        );
-                    }
-                }
-                Err(e) => {
-                    tracing::warn!(
-                        "Failed to collect document IDs for index '{}': {}",
-                        index_name,
-                        e
-                    );
-                }
-            }
-        }
     }
 
+    // Searches are small, while imports can carry many large documents
+    let body_limits = validation::body_limits_from_env();
+
     let state = Arc::new(AppState {
         search_engine,
         metadata_store,
         api_tokens,
         llm_client,
+        crawler,
+        jobs,
+        pagination: validation::pagination_limits_from_env(),
+        answer_budget: quota::AnswerBudget::from_env(),
+        query_rewrites: rewrite::QueryRewriteCache::from_env(),
+        answer_cache: answer_cache::AnswerCache::from_env(),
+        redactor,
+        metadata_sync: reconcile::SyncReports::default(),
+        metadata_rebuild: reconcile::RebuildProgress::new(loaded_indices.len()),
+        search_cache: http_cache::SearchCacheHeaders::from_env(),
+        warmup: warmup::Warmup::default(),
+        migrations: migration::Migrations::default(),
+        write_queues: backpressure::WriteQueues::from_env(),
+        request_pools: priority::RequestPools::from_env(),
+        exports: export::Exports::default(),
+        idempotency_keys: idempotency::IdempotencyKeys::from_env(body_limits.import),
     });
 
-    // Public routes (no authentication required)
-    let public_routes = Router::new()
+    // Rebuild document metadata from the indices without holding up startup;
+    // /health/ready reports when it is done
+    tokio::spawn(reconcile::rebuild_metadata(state.clone(), loaded_indices));
+
+    // Warm indices with their warmup queries once loaded and after each commit;
+    // /health/ready waits for the first pass
+    tokio::spawn(warmup::run_warmer(state.clone()));
+
+    // Background deletion of expired documents
+    let sweep_interval = expiration::sweep_interval_from_env();
+    tokio::spawn(expiration::run_sweeper(state.clone(), sweep_interval));
+
+    // Repair drift between the metadata store and the indices
+    let sync_interval = reconcile::sync_interval_from_env();
+    tokio::spawn(reconcile::run_reconciler(state.clone(), sync_interval));
+
+    // Cron-scheduled jobs (crawls, feed pulls, reindexes, snapshots), and re-crawls of
+    // crawlers with an interval
+    tokio::spawn(jobs::run_scheduler(state.clone()));
+
+    // Mirror watched directories into their indices
+    for target in watcher::targets_from_env() {
+        if !state.search_engine.list_indices().contains(&target.index_name) {
+            tracing::warn!(
+                "Not watching {}: index '{}' does not exist",
+                target.root.display(),
+                target.index_name
+            );
+            continue;
+        }
+        tokio::spawn(watcher::run_watcher(state.clone(), target));
+    }
+
+    // Read-only routes are public unless listed in PROTECTED_ROUTES
+    let protected = auth::protected_routes_from_env();
+    if !protected.is_empty() {
+        let mut names: Vec<_> = protected.iter().map(String::as_str).collect();
+        names.sort();
+        if state.api_tokens.is_empty() {
+            tracing::warn!(
+                "PROTECTED_ROUTES is set but API_TOKENS is not - routes remain public"
+            );
+        } else {
+            tracing::info!("Requiring authentication for: {}", names.join(", "));
+        }
+    }
+
+    let search_limit = DefaultBodyLimit::max(body_limits.search);
+
+    let mut public_routes = Router::new()
         .route("/health", get(handlers::health_check))
-        .route("/indices", get(handlers::list_indices))
-        .route("/indices/:name/search", post(handlers::search))
-        .route("/indices/:name/answer", post(handlers::answer))
-        .route("/indices/:name/stats", get(handlers::get_index_stats))
-        .route("/indices/:name/suggest", post(handlers::suggest));
+        .route("/health/ready", get(handlers::readiness_check));
+    let mut guarded_routes = Router::new();
+    let search_slot = middleware::from_fn_with_state(state.clone(), priority::search_slot);
+    for (name, router) in [
+        ("indices", Router::new().route("/indices", get(handlers::list_indices))),
+        (
+            "search",
+            Router::new()
+                .route("/indices/:name/search", post(handlers::search))
+                .route_layer(search_slot.clone())
+                .layer(search_limit),
+        ),
+        (
+            "answer",
+            Router::new()
+                .route("/indices/:name/answer", post(handlers::answer))
+                .route_layer(search_slot.clone())
+                .layer(search_limit),
+        ),
+        ("stats", Router::new().route("/indices/:name/stats", get(handlers::get_index_stats))),
+        (
+            "fields",
+            Router::new().route("/indices/:name/fields", get(handlers::get_field_capabilities)),
+        ),
+        (
+            "suggest",
+            Router::new()
+                .route("/indices/:name/suggest", post(handlers::suggest))
+                .route("/indices/:name/suggest_queries", post(handlers::suggest_queries))
+                .route_layer(search_slot)
+                .layer(search_limit),
+        ),
+    ] {
+        if protected.contains(name) {
+            guarded_routes = guarded_routes.merge(router);
+        } else {
+            public_routes = public_routes.merge(router);
+        }
+    }
+
+    let import_routes = Router::new()
+        .route("/indices/:name/documents", post(handlers::add_documents))
+        .route(
+            "/indices/:name/documents/upload",
+            post(handlers::upload_document),
+        )
+        .route("/indices/:name/bulk", post(handlers::bulk_operation))
+        .route("/indices/:name/_txn", post(handlers::transaction))
+        .layer(DefaultBodyLimit::max(body_limits.import));
+
+    // Writes to an index that can't keep up are refused with 429 rather than queued;
+    // admitted writes then wait for their turn in the indexing pool. Retries of writes
+    // with an Idempotency-Key are answered before either.
+    let write_routes = Router::new()
+        .route(
+            "/indices/:name/documents/:id",
+            delete(handlers::delete_document),
+        )
+        .merge(import_routes)
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            priority::indexing_slot,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            backpressure::write_backpressure,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            idempotency::idempotent_writes,
+        ));
 
     // Protected routes (require authentication when API_TOKENS is set)
     let protected_routes = Router::new()
         .route("/indices", post(handlers::create_index))
         .route("/indices/:name", delete(handlers::delete_index))
-        .route("/indices/:name/documents", post(handlers::add_documents))
         .route(
-            "/indices/:name/documents/:id",
-            delete(handlers::delete_document),
+            "/indices/:name/documents/:id/termvectors",
+            get(handlers::get_term_vectors),
+        )
+        .route("/indices/:name/sync", post(handlers::sync_index_metadata))
+        .route("/indices/:name/close", post(handlers::close_index))
+        .route("/indices/:name/open", post(handlers::open_index))
+        .route("/indices/:name/rollover", post(handlers::rollover_index))
+        .route("/indices/:name/rollover", get(handlers::get_rollover))
+        .route("/indices/:name/migrate", post(handlers::migrate_index))
+        .route("/indices/:name/migrate", get(handlers::get_migration))
+        .route("/indices/:name/export", post(handlers::export_documents))
+        .route("/indices/:name/export/:cursor", delete(handlers::delete_export))
+        .route("/indices/:name/settings", get(handlers::get_index_settings))
+        .route("/indices/:name/settings", put(handlers::update_index_settings))
+        .route("/indices/:name/analyze", post(handlers::analyze))
+        .route("/indices/:name/terms", get(handlers::list_terms))
+        .route(
+            "/indices/:name/analytics/trending",
+            get(handlers::get_trending_queries),
+        )
+        .route(
+            "/indices/:name/analytics/related_queries",
+            get(handlers::get_related_queries),
+        )
+        .route("/indices/:name/crawlers", post(handlers::create_crawler))
+        .route("/indices/:name/crawlers", get(handlers::list_crawlers))
+        .route(
+            "/indices/:name/crawlers/:id",
+            delete(handlers::delete_crawler),
+        )
+        .route(
+            "/indices/:name/crawlers/:id/run",
+            post(handlers::run_crawler),
         )
-        .route("/indices/:name/bulk", post(handlers::bulk_operation))
         .route("/indices/:name/synonyms", post(handlers::add_synonyms))
         .route("/indices/:name/synonyms", get(handlers::get_synonyms))
         .route("/indices/:name/synonyms", delete(handlers::clear_synonyms))
+        .route(
+            "/indices/:name/synonyms/suggest",
+            post(handlers::suggest_synonyms),
+        )
         .route("/indices/:name/pinned", post(handlers::add_pinned_rules))
         .route("/indices/:name/pinned", get(handlers::get_pinned_rules))
         .route("/indices/:name/pinned", delete(handlers::clear_pinned_rules))
+        .route("/indices/:name/filters", post(handlers::add_stored_filters))
+        .route("/indices/:name/filters", get(handlers::get_stored_filters))
+        .route(
+            "/indices/:name/filters/:filter",
+            delete(handlers::delete_stored_filter),
+        )
+        .route("/indices/:name/prompts", post(handlers::add_prompt_templates))
+        .route("/indices/:name/prompts", get(handlers::get_prompt_templates))
+        .route(
+            "/indices/:name/prompts/:prompt",
+            delete(handlers::delete_prompt_template),
+        )
+        .route("/indices/:name/presets", post(handlers::add_search_presets))
+        .route("/indices/:name/presets", get(handlers::get_search_presets))
+        .route(
+            "/indices/:name/presets/:preset",
+            delete(handlers::delete_search_preset),
+        )
+        .route("/keys", post(handlers::create_search_key))
+        .route("/admin/queues", get(handlers::get_request_queues))
+        .route("/admin/jobs", get(handlers::list_jobs))
+        .route("/admin/jobs", post(handlers::create_job))
+        .route("/admin/jobs/:id", delete(handlers::delete_job))
+        .route("/admin/jobs/:id/run", post(handlers::run_job))
+        .route("/admin/audit", get(handlers::get_audit_log))
+        .route("/admin/relevance-report", get(handlers::get_relevance_report))
+        .merge(write_routes)
+        .merge(guarded_routes)
+        // Recorded after authentication, so refused requests aren't logged as actions
+        .route_layer(middleware::from_fn_with_state(state.clone(), audit::audit_log))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             auth::auth_middleware,
         ));
 
     // Configure CORS based on environment
-    let cors_layer = build_cors_layer();
+    let cors_layer = cors::build_cors_layer();
 
     // Combine routes
     let app = Router::new()
         .merge(public_routes)
         .merge(protected_routes)
+        // Inside compression, which would hide the error bodies
+        .layer(middleware::from_fn(messages::localize_errors))
         .layer(cors_layer)
+        .layer(CompressionLayer::new())
         .layer(TraceLayer::new_for_http())
-        .layer(DefaultBodyLimit::max(validation::MAX_REQUEST_BODY_SIZE))
+        .layer(DefaultBodyLimit::max(body_limits.default))
         .with_state(state);
 
     let port = std::env::var("PORT")
@@ -175,47 +419,6 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-/// Build CORS layer based on CORS_ORIGINS environment variable
-fn build_cors_layer() -> CorsLayer {
-    let origins = std::env::var("CORS_ORIGINS").unwrap_or_default();
-
-    if origins.is_empty() || origins == "*" {
-        tracing::warn!("CORS_ORIGINS not set or set to '*' - allowing all origins (not recommended for production)");
-        CorsLayer::permissive()
-    } else {
-        let allowed_origins: Vec<_> = origins
-            .split(',')
-            .filter_map(|s| {
-                let trimmed = s.trim();
-                if trimmed.is_empty() {
-                    None
-                } else {
-                    trimmed.parse().ok()
-                }
-            })
-            .collect();
-
-        if allowed_origins.is_empty() {
-            tracing::warn!("No valid CORS origins parsed, falling back to permissive");
-            CorsLayer::permissive()
-        } else {
-            tracing::info!("CORS configured for {} origin(s)", allowed_origins.len());
-            CorsLayer::new()
-                .allow_origin(AllowOrigin::list(allowed_origins))
-                .allow_methods([
-                    axum::http::Method::GET,
-                    axum::http::Method::POST,
-                    axum::http::Method::DELETE,
-                    axum::http::Method::OPTIONS,
-                ])
-                .allow_headers([
-                    axum::http::header::CONTENT_TYPE,
-                    axum::http::header::AUTHORIZATION,
-                ])
-        }
-    }
-}
-
 /// Graceful shutdown signal handler
 async fn shutdown_signal() {
     let ctrl_c = async {
@@ -0,0 +1,293 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{header, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::formats;
+
+/// An error message of the catalog: a stable code clients can rely on, the English text
+/// handlers produce, and its Norwegian translation. `{}` stands for a value filled in
+/// by the handler, such as an index name, and is carried over to the translation.
+struct Message {
+    code: &'static str,
+    en: &'static str,
+    nb: &'static str,
+}
+
+const fn message(code: &'static str, en: &'static str, nb: &'static str) -> Message {
+    Message { code, en, nb }
+}
+
+/// Error messages with codes and translations. The first matching message applies, so
+/// more specific ones come first.
+const CATALOG: &[Message] = &[
+    message("index_not_found", "Index not found: {}", "Fant ikke indeksen: {}"),
+    message("index_exists", "Index already exists: {}", "Indeksen finnes allerede: {}"),
+    message("index_being_created", "Index is being created: {}", "Indeksen opprettes: {}"),
+    message(
+        "rolling_index_not_found",
+        "Rolling index not found: {}",
+        "Fant ikke den rullerende indeksen: {}",
+    ),
+    message("index_read_only", "Index is read-only: {}", "Indeksen er skrivebeskyttet: {}"),
+    message(
+        "index_busy",
+        "Index '{}' is busy ({}); retry later",
+        "Indeksen '{}' er opptatt ({}); prøv igjen senere",
+    ),
+    message(
+        "index_migrating",
+        "Index '{}' is being migrated",
+        "Indeksen '{}' migreres",
+    ),
+    message(
+        "migration_running",
+        "A migration of index '{}' is already running",
+        "En migrering av indeksen '{}' kjører allerede",
+    ),
+    message("migration_not_found", "No migration of index: {}", "Ingen migrering av indeksen: {}"),
+    message(
+        "index_name_empty",
+        "Index name cannot be empty",
+        "Indeksnavnet kan ikke være tomt",
+    ),
+    message(
+        "index_name_invalid",
+        "Index name contains invalid characters",
+        "Indeksnavnet inneholder ugyldige tegn",
+    ),
+    message("document_not_found", "Document not found: {}", "Fant ikke dokumentet: {}"),
+    message("document_exists", "Document already exists: {}", "Dokumentet finnes allerede: {}"),
+    message("job_not_found", "Job not found: {}", "Fant ikke jobben: {}"),
+    message("job_running", "Job is already running: {}", "Jobben kjører allerede: {}"),
+    message("crawler_not_found", "Crawler not found: {}", "Fant ikke crawleren: {}"),
+    message("filter_not_found", "Filter not found: {}", "Fant ikke filteret: {}"),
+    message(
+        "filter_not_found",
+        "Stored filter not found: {}",
+        "Fant ikke det lagrede filteret: {}",
+    ),
+    message("template_not_found", "Template not found: {}", "Fant ikke malen: {}"),
+    message("template_not_found", "Unknown prompt template: {}", "Ukjent promptmal: {}"),
+    message("preset_not_found", "Preset not found: {}", "Fant ikke søkeoppsettet: {}"),
+    message("preset_not_found", "Unknown search preset: {}", "Ukjent søkeoppsett: {}"),
+    message(
+        "export_cursor_expired",
+        "Export cursor not found or expired: {}",
+        "Fant ikke eksportmarkøren, eller den er utløpt: {}",
+    ),
+    message("field_restricted", "Field '{}' is restricted", "Feltet '{}' er begrenset"),
+    message("search_key_expired", "Search key has expired", "Søkenøkkelen er utløpt"),
+    message("search_key_invalid", "Invalid search key", "Ugyldig søkenøkkel"),
+    message(
+        "search_key_not_allowed",
+        "Search key is not valid for index: {}",
+        "Søkenøkkelen gjelder ikke for indeksen: {}",
+    ),
+    message("query_invalid", "Invalid query: {}", "Ugyldig spørring: {}"),
+    message(
+        "query_too_long",
+        "Query is too long: {} characters (maximum {})",
+        "Spørringen er for lang: {} tegn (maks {})",
+    ),
+    message(
+        "generation_timeout",
+        "Index '{}' did not reach generation {} within {}s (current generation {})",
+        "Indeksen '{}' nådde ikke generasjon {} innen {} s (nåværende generasjon {})",
+    ),
+    message(
+        "regex_timeout",
+        "The regex on '{}' took more than {}ms to match; use a longer literal prefix or a \
+         higher regex.timeout_ms",
+        "Regex-uttrykket på '{}' brukte mer enn {} ms; bruk et lengre fast prefiks eller en \
+         høyere regex.timeout_ms",
+    ),
+    message(
+        "timestamp_invalid",
+        "Invalid RFC3339 timestamp: {}",
+        "Ugyldig RFC3339-tidsstempel: {}",
+    ),
+    message(
+        "idempotency_key_in_flight",
+        "A request with Idempotency-Key '{}' is still being processed",
+        "En forespørsel med Idempotency-Key '{}' behandles fortsatt",
+    ),
+    message(
+        "idempotency_key_reused",
+        "Idempotency-Key '{}' was already used for a different request",
+        "Idempotency-Key '{}' er allerede brukt for en annen forespørsel",
+    ),
+    message(
+        "body_too_large",
+        "Request body exceeds {} bytes",
+        "Forespørselen er større enn {} byte",
+    ),
+    message(
+        "body_invalid",
+        "Failed to deserialize the JSON body into the target type: {}",
+        "Kunne ikke lese JSON-innholdet: {}",
+    ),
+    message(
+        "body_invalid",
+        "Failed to parse the request body as JSON: {}",
+        "Forespørselen er ikke gyldig JSON: {}",
+    ),
+    message("answer_quota_exceeded", "Answer quota exceeded", "Svarkvoten er brukt opp"),
+    message(
+        "llm_not_configured",
+        "MISTRAL_API_KEY not configured",
+        "MISTRAL_API_KEY er ikke konfigurert",
+    ),
+    message("llm_provider_unknown", "Unknown LLM provider: {}", "Ukjent LLM-leverandør: {}"),
+    // Checks of request fields, named by their path
+    message("field_unknown", "{}: unknown field '{}'", "{}: ukjent felt '{}'"),
+    message("value_not_positive", "{} must be greater than 0", "{} må være større enn 0"),
+];
+
+/// Templates of the catalog as patterns capturing the values filled in
+static PATTERNS: Lazy<Vec<(Regex, &'static Message)>> = Lazy::new(|| {
+    CATALOG
+        .iter()
+        .map(|message| {
+            let literals: Vec<String> = message.en.split("{}").map(regex::escape).collect();
+            let pattern = format!("(?s)^{}$", literals.join("(.+?)"));
+            (Regex::new(&pattern).expect("Invalid message template"), message)
+        })
+        .collect()
+});
+
+/// Languages error messages are written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Language {
+    English,
+    Norwegian,
+}
+
+impl Language {
+    /// First language of an `Accept-Language` header that messages are written in;
+    /// English for anything else
+    fn preferred(accept_language: &str) -> Self {
+        formats::preferences(accept_language)
+            .iter()
+            .find_map(|tag| match tag.split('-').next().unwrap_or_default() {
+                "nb" | "nn" | "no" => Some(Self::Norwegian),
+                "en" | "*" => Some(Self::English),
+                _ => None,
+            })
+            .unwrap_or(Self::English)
+    }
+}
+
+/// Catalog message matching an error, with the values filled into it
+fn lookup(error: &str) -> Option<(&'static Message, Vec<&str>)> {
+    PATTERNS.iter().find_map(|(pattern, message)| {
+        let captures = pattern.captures(error)?;
+        let values = captures.iter().skip(1).flatten().map(|value| value.as_str()).collect();
+        Some((*message, values))
+    })
+}
+
+/// Code of an error outside the catalog, named after its status, like `not_found`
+fn status_code(status: StatusCode) -> String {
+    status
+        .canonical_reason()
+        .unwrap_or("error")
+        .to_ascii_lowercase()
+        .replace([' ', '-'], "_")
+}
+
+fn translate(template: &str, values: &[&str]) -> String {
+    let mut parts = template.split("{}");
+    let mut text = parts.next().unwrap_or_default().to_string();
+    for (part, value) in parts.zip(values.iter().chain(std::iter::repeat(&""))) {
+        text.push_str(value);
+        text.push_str(part);
+    }
+    text
+}
+
+/// Give every JSON error response a stable `code`, from the catalog or else its status,
+/// and write catalog messages in the language the `Accept-Language` header prefers
+pub async fn localize_errors(req: Request<Body>, next: Next) -> Response {
+    let language = req
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .map_or(Language::English, Language::preferred);
+    let response = next.run(req).await;
+
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if response.status().is_success() || !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::error!("Failed to read error response: {}", e);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    let mut value: serde_json::Value = match serde_json::from_slice(&bytes) {
+        Ok(value) => value,
+        Err(_) => return Response::from_parts(parts, Body::from(bytes)),
+    };
+    let Some(object) = value
+        .as_object_mut()
+        .filter(|object| object.get("success") == Some(&serde_json::Value::Bool(false)))
+    else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+
+    let error = object.get("error").and_then(|error| error.as_str()).unwrap_or_default();
+    let error = error.to_string();
+    let found = lookup(&error);
+    if object.get("code").is_none_or(|code| code.is_null()) {
+        let code = match &found {
+            Some((message, _)) => message.code.to_string(),
+            None => status_code(parts.status),
+        };
+        object.insert("code".to_string(), serde_json::Value::String(code));
+    }
+    if let (Language::Norwegian, Some((message, values))) = (language, &found) {
+        let translated = translate(message.nb, values);
+        object.insert("error".to_string(), serde_json::Value::String(translated));
+        parts.headers.insert(header::CONTENT_LANGUAGE, HeaderValue::from_static("nb"));
+    }
+    parts.headers.append(header::VARY, HeaderValue::from_static("accept-language"));
+    parts.headers.remove(header::CONTENT_LENGTH);
+
+    Response::from_parts(parts, Body::from(value.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translates_catalog_messages() {
+        let (message, values) = lookup("Index not found: products").unwrap();
+        assert_eq!(message.code, "index_not_found");
+        assert_eq!(translate(message.nb, &values), "Fant ikke indeksen: products");
+
+        let (message, values) = lookup("fields[1]: unknown field 'titel'").unwrap();
+        assert_eq!(message.code, "field_unknown");
+        assert_eq!(translate(message.nb, &values), "fields[1]: ukjent felt 'titel'");
+
+        assert!(lookup("Something else went wrong").is_none());
+        assert_eq!(status_code(StatusCode::TOO_MANY_REQUESTS), "too_many_requests");
+
+        assert_eq!(Language::preferred("nb-NO, en;q=0.8"), Language::Norwegian);
+        assert_eq!(Language::preferred("de, en;q=0.5, nn;q=0.3"), Language::English);
+        assert_eq!(Language::preferred("sv"), Language::English);
+    }
+}
@@ -0,0 +1,359 @@
+use chrono::Utc;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::error::SearchError;
+use crate::models::{
+    Document, FieldConfig, IndexSettings, MigrationState, MigrationStatus, RolloverAlias,
+    RolloverGeneration, RolloverPolicy,
+};
+use crate::reconcile;
+use crate::rollover;
+use crate::search::SnapshotCursor;
+use crate::validation::{validate_index_name, MAX_DOCUMENTS_PER_REQUEST};
+use crate::AppState;
+
+/// An index being built with a new schema, kept up to date with the writes to the
+/// indices it replaces
+pub struct ShadowIndex {
+    pub index: String,
+    /// IDs written to the source indices since the migration started. Locked while a
+    /// page is backfilled or a write is copied, so a copied document is never
+    /// overwritten by the older version read by the backfill.
+    written: Mutex<HashSet<String>>,
+    /// First write that couldn't be copied; the migration fails with it
+    error: Mutex<Option<String>>,
+}
+
+impl ShadowIndex {
+    fn new(index: &str) -> Self {
+        Self {
+            index: index.to_string(),
+            written: Mutex::new(HashSet::new()),
+            error: Mutex::new(None),
+        }
+    }
+
+    pub fn written(&self) -> MutexGuard<'_, HashSet<String>> {
+        self.written.lock().unwrap()
+    }
+
+    pub fn fail(&self, message: String) {
+        self.error.lock().unwrap().get_or_insert(message);
+    }
+
+    pub fn error(&self) -> Option<String> {
+        self.error.lock().unwrap().clone()
+    }
+}
+
+/// Migrations by index or rolling index name, with their progress. Finished ones are
+/// kept until the next migration of the same index.
+#[derive(Default)]
+pub struct Migrations {
+    statuses: Mutex<HashMap<String, MigrationStatus>>,
+}
+
+impl Migrations {
+    pub fn get(&self, name: &str) -> Option<MigrationStatus> {
+        self.statuses.lock().unwrap().get(name).cloned()
+    }
+
+    pub fn is_running(&self, name: &str) -> bool {
+        self.statuses
+            .lock()
+            .unwrap()
+            .get(name)
+            .is_some_and(|status| status.state == MigrationState::Running)
+    }
+
+    /// Record a new migration, returning false if one of the same index is running
+    fn begin(&self, status: MigrationStatus) -> bool {
+        let mut statuses = self.statuses.lock().unwrap();
+        if statuses
+            .get(&status.index)
+            .is_some_and(|current| current.state == MigrationState::Running)
+        {
+            return false;
+        }
+        statuses.insert(status.index.clone(), status);
+        true
+    }
+
+    fn update(&self, name: &str, update: impl FnOnce(&mut MigrationStatus)) {
+        if let Some(status) = self.statuses.lock().unwrap().get_mut(name) {
+            update(status);
+        }
+    }
+
+    fn finish(&self, name: &str, error: Option<String>) {
+        self.update(name, |status| {
+            status.finished_at = Some(Utc::now().to_rfc3339());
+            status.state = match error {
+                Some(_) => MigrationState::Failed,
+                None => MigrationState::Completed,
+            };
+            status.error = error;
+        });
+    }
+}
+
+/// Convert field values to the types of the new schema where possible, such as `"12.5"`
+/// for an f64 field or `3` for a string field. Values that can't be converted are left
+/// as they are, and indexing drops them.
+pub fn coerce_fields(doc: &mut Document, fields: &[FieldConfig]) {
+    use serde_json::Value;
+
+    for field in fields {
+        let Some(value) = doc.fields.get_mut(&field.name) else {
+            continue;
+        };
+        let converted = match (field.field_type.as_str(), &*value) {
+            ("i64", Value::String(s)) => s.trim().parse::<i64>().ok().map(Value::from),
+            ("i64", Value::Number(n)) if n.as_i64().is_none() => n
+                .as_f64()
+                .filter(|f| f.fract() == 0.0)
+                .map(|f| Value::from(f as i64)),
+            ("f64", Value::String(s)) => s.trim().parse::<f64>().ok().map(Value::from),
+            ("text" | "string", Value::Number(_) | Value::Bool(_)) => {
+                Some(Value::String(value.to_string()))
+            }
+            _ => None,
+        };
+        if let Some(converted) = converted {
+            *value = converted;
+        }
+    }
+}
+
+/// Start migrating an index or rolling index to new fields. A new index is created with
+/// them, writes to the current indices are copied into it, and their documents are
+/// copied in the background. The name then becomes a rolling index whose only
+/// generation is the new index, and the old indices are deleted.
+pub fn start(
+    state: &Arc<AppState>,
+    name: &str,
+    fields: Vec<FieldConfig>,
+    settings: IndexSettings,
+    shards: Option<usize>,
+) -> Result<MigrationStatus, SearchError> {
+    let engine = &state.search_engine;
+    let rolling = engine.rollover_alias(name);
+    let source_indices: Vec<String> = match &rolling {
+        Some(rolling) => rolling.generations.iter().map(|g| g.index.clone()).collect(),
+        None if engine.list_indices().iter().any(|index| index == name) => {
+            vec![name.to_string()]
+        }
+        None => return Err(SearchError::IndexNotFound(name.to_string())),
+    };
+    let write_index = engine.resolve_write_index(name);
+    let shards = match shards {
+        Some(shards) => shards,
+        None => engine
+            .index_shard_count(&write_index)
+            .ok_or_else(|| SearchError::IndexNotFound(write_index.clone()))?,
+    };
+    let target_index = match &rolling {
+        Some(rolling) => rollover::next_generation_name(name, rolling),
+        None => rollover::generation_name(name, 1),
+    };
+    if validate_index_name(&target_index).is_err() {
+        return Err(SearchError::InvalidQuery(format!(
+            "Index name '{}' is too long to migrate to '{}'",
+            name, target_index
+        )));
+    }
+
+    let status = MigrationStatus {
+        index: name.to_string(),
+        source_indices: source_indices.clone(),
+        target_index: target_index.clone(),
+        state: MigrationState::Running,
+        started_at: Utc::now().to_rfc3339(),
+        finished_at: None,
+        total_documents: 0,
+        copied_documents: 0,
+        skipped_documents: 0,
+        error: None,
+    };
+    if !state.migrations.begin(status.clone()) {
+        return Err(SearchError::Conflict(format!(
+            "A migration of index '{}' is already running",
+            name
+        )));
+    }
+
+    let created = engine
+        .create_index(&target_index, &fields, &settings, shards)
+        .and_then(|()| rollover::record_index(state, &target_index));
+    if let Err(e) = created {
+        state.migrations.finish(name, Some(e.to_string()));
+        return Err(e);
+    }
+    copy_index_config(state, &write_index, &target_index);
+
+    let policy = rolling.map(|rolling| rolling.policy).unwrap_or_default();
+    let shadow = Arc::new(ShadowIndex::new(&target_index));
+    let task_state = state.clone();
+    let task_name = name.to_string();
+    tokio::spawn(async move {
+        let migrate_state = task_state.clone();
+        let migrate_name = task_name.clone();
+        let migrate_shadow = shadow.clone();
+        let migrate_sources = source_indices.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            migrate(
+                &migrate_state,
+                &migrate_name,
+                &migrate_sources,
+                &migrate_shadow,
+                policy,
+            )
+        })
+        .await
+        .unwrap_or_else(|e| Err(SearchError::Internal(anyhow::anyhow!(e.to_string()))));
+
+        match &result {
+            Ok(()) => tracing::info!("Migrated index '{}' to '{}'", task_name, shadow.index),
+            Err(e) => {
+                tracing::warn!("Migration of index '{}' failed: {}", task_name, e);
+                abort(&task_state, &source_indices, &shadow);
+            }
+        }
+        let error = result.err().map(|e| e.to_string());
+        task_state.migrations.finish(&task_name, error);
+    });
+
+    Ok(status)
+}
+
+/// Synonyms, pinned rules, stored filters, prompt templates and search presets are kept
+/// per index, so the new index takes over those of the current write index
+fn copy_index_config(state: &AppState, from: &str, to: &str) {
+    let engine = &state.search_engine;
+    let copied = engine
+        .add_synonyms(to, engine.get_synonyms(from))
+        .and_then(|()| engine.add_pinned_rules(to, engine.get_pinned_rules(from)))
+        .and_then(|()| engine.add_stored_filters(to, engine.get_stored_filters(from)))
+        .and_then(|()| engine.add_prompt_templates(to, engine.get_prompt_templates(from)))
+        .and_then(|()| engine.add_search_presets(to, engine.get_search_presets(from)));
+    if let Err(e) = copied {
+        tracing::warn!("Failed to copy the configuration of '{}' to '{}': {}", from, to, e);
+    }
+}
+
+/// Copy the documents of the source indices into the shadow index, then swap the alias
+fn migrate(
+    state: &AppState,
+    name: &str,
+    source_indices: &[String],
+    shadow: &Arc<ShadowIndex>,
+    policy: RolloverPolicy,
+) -> Result<(), SearchError> {
+    let engine = &state.search_engine;
+
+    // From here on, writes to the sources are copied into the shadow index as well
+    let mut snapshots = Vec::with_capacity(source_indices.len());
+    for source in source_indices {
+        snapshots.push(engine.start_shadow_writes(source, shadow.clone())?);
+    }
+    let total = snapshots
+        .iter()
+        .flatten()
+        .map(|searcher| searcher.num_docs())
+        .sum();
+    state.migrations.update(name, |status| status.total_documents = total);
+
+    for (source, searchers) in source_indices.iter().zip(&snapshots) {
+        let mut cursor = Some(SnapshotCursor::default());
+        while let Some(from) = cursor {
+            let (documents, next) =
+                engine.snapshot_documents(source, searchers, from, MAX_DOCUMENTS_PER_REQUEST)?;
+            let read = documents.len() as u64;
+            let copied = engine.backfill_shadow(shadow, documents)? as u64;
+            state.migrations.update(name, |status| {
+                status.copied_documents += copied;
+                status.skipped_documents += read - copied;
+            });
+            if let Some(error) = shadow.error() {
+                return Err(SearchError::Internal(anyhow::anyhow!(error)));
+            }
+            cursor = next;
+        }
+    }
+
+    let rolling = RolloverAlias {
+        policy,
+        generations: vec![RolloverGeneration {
+            index: shadow.index.clone(),
+            created_at: Utc::now().to_rfc3339(),
+        }],
+    };
+    engine.swap_alias(name, rolling, source_indices, shadow)?;
+
+    // The alias no longer reaches the old indices, so failures only leave them behind
+    for source in source_indices {
+        if let Err(e) = rollover::delete_generation(state, source) {
+            tracing::warn!("Failed to delete migrated index '{}': {}", source, e);
+        }
+    }
+    state.query_rewrites.invalidate(name);
+    state.answer_cache.invalidate(name);
+    // Copied documents bypass the metadata store
+    if let Err(e) = reconcile::sync_index(state, &shadow.index) {
+        tracing::warn!("Failed to sync metadata of index '{}': {}", shadow.index, e);
+    }
+    Ok(())
+}
+
+/// Stop copying writes and delete the shadow index, leaving the sources as they were
+fn abort(state: &AppState, source_indices: &[String], shadow: &ShadowIndex) {
+    for source in source_indices {
+        state.search_engine.stop_shadow_writes(source);
+    }
+    if let Err(e) = rollover::delete_generation(state, &shadow.index) {
+        tracing::warn!("Failed to delete index '{}': {}", shadow.index, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn field(name: &str, field_type: &str) -> FieldConfig {
+        serde_json::from_value(json!({"name": name, "field_type": field_type})).unwrap()
+    }
+
+    #[test]
+    fn test_coerces_values_to_new_types() {
+        let mut doc: Document = serde_json::from_value(json!({
+            "id": "1",
+            "fields": {
+                "price": "12.5",
+                "stock": "7",
+                "rating": 4.0,
+                "sku": 1234,
+                "title": "Boots",
+                "weight": "heavy"
+            }
+        }))
+        .unwrap();
+        let fields = [
+            field("price", "f64"),
+            field("stock", "i64"),
+            field("rating", "i64"),
+            field("sku", "string"),
+            field("title", "text"),
+            field("weight", "f64"),
+        ];
+        coerce_fields(&mut doc, &fields);
+
+        assert_eq!(doc.fields["price"], json!(12.5));
+        assert_eq!(doc.fields["stock"], json!(7));
+        assert_eq!(doc.fields["rating"], json!(4));
+        assert_eq!(doc.fields["sku"], json!("1234"));
+        assert_eq!(doc.fields["title"], json!("Boots"));
+        assert_eq!(doc.fields["weight"], json!("heavy"));
+    }
+}
@@ -1,11 +1,495 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use crate::field_access;
+use crate::llm::Usage;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateIndexRequest {
     pub name: String,
     #[serde(default)]
     pub fields: Vec<FieldConfig>,
+    #[serde(default)]
+    pub settings: IndexSettings,
+    /// Number of Tantivy indices the documents are split across; fixed once created
+    #[serde(default = "default_shards")]
+    pub shards: usize,
+    /// Create a rolling index: `name` becomes an alias over generations of the index
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollover: Option<RolloverPolicy>,
+}
+
+fn default_shards() -> usize {
+    1
+}
+
+/// When a rolling index starts a new generation and how long old generations are kept
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct RolloverPolicy {
+    /// Conditions checked by rollover requests that don't give their own
+    #[serde(default)]
+    pub conditions: RolloverConditions,
+    #[serde(default)]
+    pub retention: RetentionPolicy,
+}
+
+/// Conditions for a rollover; the write index is rolled over when any of them is met
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct RolloverConditions {
+    /// Seconds since the write index was created
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age_secs: Option<u64>,
+    /// Documents in the write index
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_docs: Option<u64>,
+}
+
+impl RolloverConditions {
+    pub fn is_empty(&self) -> bool {
+        self.max_age_secs.is_none() && self.max_docs.is_none()
+    }
+}
+
+/// Old generations of a rolling index deleted at each rollover. The write index is
+/// always kept.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct RetentionPolicy {
+    /// Generations kept, counting the write index
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_generations: Option<usize>,
+    /// Seconds a generation is kept after it was rolled over
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age_secs: Option<u64>,
+}
+
+/// One generation of a rolling index
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RolloverGeneration {
+    pub index: String,
+    /// When the generation was created (RFC3339)
+    pub created_at: String,
+}
+
+/// A rolling index: an alias written through its newest generation and searched
+/// across all of them
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RolloverAlias {
+    #[serde(flatten)]
+    pub policy: RolloverPolicy,
+    /// Generations, oldest first; the last one is the write index
+    pub generations: Vec<RolloverGeneration>,
+}
+
+impl RolloverAlias {
+    pub fn write_index(&self) -> &str {
+        self.generations
+            .last()
+            .map(|generation| generation.index.as_str())
+            .unwrap_or_default()
+    }
+}
+
+/// Request to roll a rolling index over to a new generation
+#[derive(Debug, Default, Deserialize)]
+pub struct RolloverRequest {
+    /// Conditions to check instead of the index's own; without any, the index is
+    /// rolled over unconditionally
+    #[serde(default)]
+    pub conditions: Option<RolloverConditions>,
+    /// Report whether the conditions are met without rolling over
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Outcome of a rollover request
+#[derive(Debug, Serialize)]
+pub struct RolloverResponse {
+    pub alias: String,
+    /// Write index before the rollover
+    pub old_index: String,
+    /// Write index after the rollover
+    pub new_index: String,
+    pub rolled_over: bool,
+    pub dry_run: bool,
+    /// Whether each checked condition was met
+    pub conditions: HashMap<String, bool>,
+    /// Old generations deleted by the retention policy
+    pub deleted_indices: Vec<String>,
+}
+
+/// A rolling index as shown by `GET /indices/:name/rollover`
+#[derive(Debug, Serialize)]
+pub struct RolloverInfo {
+    pub alias: String,
+    pub write_index: String,
+    #[serde(flatten)]
+    pub state: RolloverAlias,
+}
+
+/// Move an index or rolling index to a new schema without downtime
+#[derive(Debug, Deserialize)]
+pub struct MigrationRequest {
+    pub fields: Vec<FieldConfig>,
+    /// Settings of the new index; those of the current write index when not given
+    #[serde(default)]
+    pub settings: Option<IndexSettings>,
+    /// Shards of the new index; as many as the current write index when not given
+    #[serde(default)]
+    pub shards: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationState {
+    /// Copying documents into the new index
+    Running,
+    /// The alias points at the new index and the old indices are deleted
+    Completed,
+    /// The new index was deleted and the old ones are left as they were
+    Failed,
+}
+
+/// Progress of a schema migration, as shown by `GET /indices/:name/migrate`
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationStatus {
+    /// Index or rolling index being migrated
+    pub index: String,
+    /// Indices the documents are copied from
+    pub source_indices: Vec<String>,
+    /// Index with the new schema, which becomes the alias's only generation
+    pub target_index: String,
+    pub state: MigrationState,
+    pub started_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<String>,
+    /// Documents in the source indices when copying started
+    pub total_documents: u64,
+    /// Documents copied so far
+    pub copied_documents: u64,
+    /// Documents left out of the copy because a write during the migration already
+    /// brought their current version into the new index
+    pub skipped_documents: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Read all documents of an index page by page, from a snapshot taken by the first page
+#[derive(Debug, Deserialize)]
+pub struct ExportRequest {
+    /// Cursor returned by the previous page; a new snapshot is taken without it
+    #[serde(default)]
+    pub cursor: Option<String>,
+    /// Documents per page
+    #[serde(default = "default_export_size")]
+    pub size: usize,
+    /// Seconds the snapshot is kept after this page for the next one to be read
+    #[serde(default = "default_export_keep_alive")]
+    pub keep_alive_secs: u64,
+    /// Columns of a CSV or NDJSON export (`id` and the stored fields when empty)
+    #[serde(default)]
+    pub columns: Vec<String>,
+}
+
+fn default_export_size() -> usize {
+    1000
+}
+
+fn default_export_keep_alive() -> u64 {
+    60
+}
+
+/// One page of an export
+#[derive(Debug, Serialize)]
+pub struct ExportPage {
+    pub documents: Vec<Document>,
+    /// Documents in the snapshot
+    pub total: u64,
+    /// Commit generation of the snapshot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generation: Option<u64>,
+    /// Cursor for the next page; absent once all documents are read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+}
+
+/// Per-index behaviour settings, persisted alongside the index
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct IndexSettings {
+    /// How to handle documents whose content hash already exists in the index
+    #[serde(default)]
+    pub dedup: DedupMode,
+    /// How to handle documents whose ID already exists in the index
+    #[serde(default)]
+    pub on_conflict: OnConflict,
+    /// Time-to-live applied to documents without an `expires_at` field
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_ttl_seconds: Option<u64>,
+    /// Fuzzy matching rules; without them every term allows one typo
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub typo_tolerance: Option<TypoTolerance>,
+    /// Operator between query terms that have no explicit AND/OR
+    #[serde(default)]
+    pub default_operator: DefaultOperator,
+    /// Rewrites applied to search queries, in order, before they are parsed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub query_normalizers: Vec<QueryNormalizer>,
+    /// When stopwords are removed from search queries
+    #[serde(default)]
+    pub remove_stopwords: StopwordRemoval,
+    /// Stopwords of search queries; the built-in Norwegian and English ones when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stopwords: Vec<String>,
+    /// Largest `limit` for searches on this index (below the deployment maximum)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_limit: Option<usize>,
+    /// Largest `offset` for searches on this index (below the deployment maximum)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_offset: Option<usize>,
+    /// Largest `offset` + `limit` of searches on this index
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_result_window: Option<usize>,
+    /// Most aggregation buckets a search on this index may create (below the deployment
+    /// maximum)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_aggregation_buckets: Option<usize>,
+    /// Most fields a search on this index may highlight
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_highlight_fields: Option<usize>,
+    /// Fewest characters a wildcard term of a search on this index needs before its first
+    /// wildcard, instead of the deployment's `MIN_WILDCARD_PREFIX`; `0` allows leading
+    /// wildcards
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_wildcard_prefix: Option<usize>,
+    /// Summary generated at ingest from a long text field
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<SummarySettings>,
+    /// LLM used for answers, reranking, query rewriting and summaries on this index
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub llm: Option<LlmSettings>,
+    /// Text fields searched when a request doesn't list fields; all text fields when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub searchable_fields: Vec<String>,
+    /// Fields returned in hits when a request doesn't set `include_fields`; all stored
+    /// fields when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub displayed_fields: Vec<String>,
+    /// When writes are committed
+    #[serde(default)]
+    pub commit_policy: CommitPolicy,
+    /// Reject document writes and deletes; searches keep working
+    #[serde(default)]
+    pub read_only: bool,
+    /// Fields only callers whose API token has the scope can see or query: field -> scope
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub restricted_fields: HashMap<String, String>,
+    /// Searches run after the index is loaded and after each commit
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warmup_queries: Vec<WarmupQuery>,
+    /// String field holding the ID of a child document's parent, which links the
+    /// documents for `has_child` and `has_parent` searches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_field: Option<String>,
+}
+
+/// When stopwords are removed from search queries
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StopwordRemoval {
+    /// Only when the query found nothing, by searching its keywords again
+    #[default]
+    Fallback,
+    /// Before every search
+    Always,
+    /// Never; queries are searched as written
+    Never,
+}
+
+/// Search run to warm an index, so the first real searches after a deploy or a commit
+/// find its postings and fast fields in the page cache
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WarmupQuery {
+    pub query: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+    /// Names of stored filters to apply
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub filters: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort: Option<SortOption>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aggregations: Vec<AggregationRequest>,
+}
+
+/// When the writes of a request are committed
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct CommitPolicy {
+    /// Also commit every this many written or deleted documents within a request;
+    /// without it a request is one commit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_documents: Option<usize>,
+}
+
+/// Summarize a long text field into another field when documents are added
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SummarySettings {
+    /// Text field that is summarized
+    pub source_field: String,
+    /// Field the summary is stored in; added to the schema when missing
+    #[serde(default = "default_summary_field")]
+    pub target_field: String,
+    #[serde(default)]
+    pub method: SummaryMethod,
+    /// Only texts longer than this many characters are summarized
+    #[serde(default = "default_summary_min_chars")]
+    pub min_chars: usize,
+    /// Longest summary in characters
+    #[serde(default = "default_summary_max_chars")]
+    pub max_chars: usize,
+}
+
+fn default_summary_field() -> String {
+    "summary".to_string()
+}
+
+fn default_summary_min_chars() -> usize {
+    1000
+}
+
+fn default_summary_max_chars() -> usize {
+    300
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryMethod {
+    /// The most representative sentences of the text
+    #[default]
+    Extractive,
+    /// Written by the configured LLM, falling back to extractive when it fails
+    Llm,
+}
+
+/// LLM options; unset options use the index's settings, then the deployment defaults
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct LlmSettings {
+    /// Name of a provider from `LLM_PROVIDERS` (`mistral` for the `MISTRAL_*` provider)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Temperature for answers; the other LLM steps always use 0
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Most tokens in an answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+}
+
+impl LlmSettings {
+    /// These settings with any option set in `overrides` replaced. A model only
+    /// carries over while the provider stays the same.
+    pub fn merged(&self, overrides: &LlmSettings) -> LlmSettings {
+        let model = match &overrides.provider {
+            Some(_) => overrides.model.clone(),
+            None => overrides.model.clone().or_else(|| self.model.clone()),
+        };
+        LlmSettings {
+            provider: overrides.provider.clone().or_else(|| self.provider.clone()),
+            model,
+            temperature: overrides.temperature.or(self.temperature),
+            max_tokens: overrides.max_tokens.or(self.max_tokens),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryNormalizer {
+    /// Unicode NFC normalization (combining accents become single characters)
+    Nfc,
+    /// Typographic quotes become `"` and `'`
+    Quotes,
+    /// Dash and hyphen variants become `-`
+    Dashes,
+    /// Remove emoji
+    StripEmoji,
+    /// Words typed with a Russian keyboard layout are retyped as Latin text
+    KeyboardLayout,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum DefaultOperator {
+    /// Match documents containing any of the terms
+    #[default]
+    #[serde(alias = "or")]
+    Or,
+    /// Match only documents containing all of the terms
+    #[serde(alias = "and")]
+    And,
+}
+
+/// How many typos fuzzy search tolerates, chosen by term length
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TypoTolerance {
+    /// Terms with at least this many characters may have one typo
+    #[serde(default = "default_one_typo_min_length")]
+    pub one_typo_min_length: usize,
+    /// Terms with at least this many characters may have two typos
+    #[serde(default = "default_two_typos_min_length")]
+    pub two_typos_min_length: usize,
+    /// Fields that are always matched exactly, even when fuzzy is enabled
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exact_fields: Vec<String>,
+    /// Number of leading characters that must be typed correctly
+    #[serde(default)]
+    pub prefix_length: usize,
+}
+
+impl TypoTolerance {
+    /// Edit distance allowed for a term
+    pub fn max_edits(&self, term: &str) -> u8 {
+        let length = term.chars().count();
+        if length >= self.two_typos_min_length {
+            2
+        } else if length >= self.one_typo_min_length {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+fn default_one_typo_min_length() -> usize {
+    4
+}
+
+fn default_two_typos_min_length() -> usize {
+    9
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupMode {
+    /// Index every document regardless of content
+    #[default]
+    None,
+    /// Skip documents whose content already exists
+    Skip,
+    /// Replace existing documents that have the same content
+    Overwrite,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict {
+    /// Replace the existing document
+    #[default]
+    Replace,
+    /// Keep the existing document and leave the new one out
+    Skip,
+    /// Reject the document
+    Error,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -18,9 +502,12 @@ pub struct FieldConfig {
     #[serde(default)]
     pub indexed: bool,
     #[serde(default = "default_analyzer")]
-    pub analyzer: String, // "default", "norwegian", "raw"
+    pub analyzer: String, // "default", "norwegian", "raw", "auto"
     #[serde(default)]
     pub fast: bool, // Enable FAST flag for aggregations
+    /// Language codes to detect and route to when analyzer is "auto" (empty = all supported)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub languages: Vec<String>,
 }
 
 fn default_field_type() -> String {
@@ -33,6 +520,8 @@ fn default_analyzer() -> String {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Document {
+    /// Generated by the server when left out
+    #[serde(default)]
     pub id: String,
     pub fields: HashMap<String, serde_json::Value>,
 }
@@ -42,6 +531,103 @@ pub struct AddDocumentsRequest {
     pub documents: Vec<Document>,
 }
 
+/// Query parameters of document writes
+#[derive(Debug, Default, Deserialize)]
+pub struct WriteParams {
+    #[serde(default)]
+    pub refresh: Option<Refresh>,
+}
+
+/// When a write's response is sent relative to its documents becoming searchable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Refresh {
+    /// Respond once the commit is searchable (always the case for committed writes)
+    True,
+    False,
+    /// Respond only after checking that searches see the commit
+    WaitFor,
+}
+
+/// Result of indexing a batch of documents
+#[derive(Debug, Default)]
+pub struct IngestOutcome {
+    /// IDs of documents written to the index
+    pub indexed_ids: Vec<String>,
+    /// IDs of documents skipped because their content already existed
+    pub skipped_ids: Vec<String>,
+    /// IDs of existing documents removed because a new document had the same content
+    pub replaced_ids: Vec<String>,
+    /// IDs of existing documents replaced by a new document with the same ID
+    pub updated_ids: Vec<String>,
+    /// IDs skipped because a document with the same ID already existed
+    pub conflict_ids: Vec<String>,
+    /// IDs deleted by bulk delete operations
+    pub deleted_ids: Vec<String>,
+    pub commit: CommitStats,
+}
+
+/// When a batch of writes was committed, for checking that searches see it
+#[derive(Debug, Default, Serialize, Clone)]
+pub struct CommitStats {
+    /// Opstamp of the commit; the writes are searchable once the index's opstamp
+    /// (in its stats) is at least this
+    pub opstamp: u64,
+    /// Time spent writing and committing the batch
+    pub took_ms: f64,
+    /// Time from which searches see the writes (RFC3339)
+    pub searchable_at: String,
+    /// Documents in the index after the commit
+    pub document_count: u64,
+}
+
+/// Differences found between an index and its metadata rows, and what was repaired
+#[derive(Debug, Default, Serialize, Clone)]
+pub struct SyncReport {
+    /// Time of the check (RFC3339)
+    pub checked_at: String,
+    /// Documents in the index
+    pub index_document_count: u64,
+    /// Metadata rows for the index before the repair
+    pub metadata_document_count: u64,
+    /// Documents in the index without a metadata row (rows added)
+    pub missing: usize,
+    /// Metadata rows without a document in the index (rows removed)
+    pub stale: usize,
+    pub took_ms: f64,
+}
+
+/// Progress of the metadata rebuild run after startup
+#[derive(Debug, Serialize)]
+pub struct RebuildStatus {
+    pub finished: bool,
+    pub indices_total: usize,
+    pub indices_done: usize,
+    /// Index being rebuilt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_index: Option<String>,
+    pub elapsed_ms: f64,
+}
+
+/// Answer requests counted against a key's quotas in the current day and month
+#[derive(Debug, Default)]
+pub struct AnswerUsage {
+    /// Whether the request fit in the quotas (and was counted)
+    pub allowed: bool,
+    pub daily_used: u64,
+    pub monthly_used: u64,
+}
+
+/// Response to a write sent with an `Idempotency-Key`, replayed when it is retried
+#[derive(Debug)]
+pub struct IdempotentResponse {
+    /// Hash of the request's method, path, query and body
+    pub request_hash: String,
+    pub status: u16,
+    /// JSON body of the response
+    pub body: Vec<u8>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SearchRequest {
     pub query: String,
@@ -64,6 +650,162 @@ pub struct SearchRequest {
     /// Minimum number of SHOULD clauses that must match (for BooleanQuery)
     #[serde(default)]
     pub minimum_should_match: Option<usize>,
+    /// Match a plain multi-word query as a phrase whose terms may be up to
+    /// this many positions apart, like `"quick fox"~2`
+    #[serde(default)]
+    pub proximity: Option<u32>,
+    /// Overrides the index's `default_operator` for this request
+    #[serde(default)]
+    pub default_operator: Option<DefaultOperator>,
+    /// Geo filters a document must match (all of them) to be returned
+    #[serde(default)]
+    pub geo_filters: Vec<GeoFilter>,
+    /// Only return these stored fields in hits (all fields when empty)
+    #[serde(default)]
+    pub include_fields: Vec<String>,
+    /// Stored fields to leave out of hits
+    #[serde(default)]
+    pub exclude_fields: Vec<String>,
+    /// Rename hit fields (source -> target); dotted sources like `meta.title`
+    /// move a value out of a JSON field
+    #[serde(default)]
+    pub field_mapping: HashMap<String, String>,
+    /// Names of stored filters to apply on top of the query
+    #[serde(default)]
+    pub filters: Vec<String>,
+    /// Let the LLM reorder the top hits by relevance to the query
+    #[serde(default)]
+    pub rerank: Option<RerankOptions>,
+    /// Wait until the index has reached this commit generation (the `opstamp` of a
+    /// write response) before searching
+    #[serde(default)]
+    pub wait_for_generation: Option<u64>,
+    /// Documents of other indices to embed in each hit
+    #[serde(default)]
+    pub lookups: Vec<LookupRequest>,
+    /// Only match parent documents with a child matching this query
+    #[serde(default)]
+    pub has_child: Option<HasChildQuery>,
+    /// Only match child documents whose parent matches this query
+    #[serde(default)]
+    pub has_parent: Option<HasParentQuery>,
+    /// Columns of a CSV or NDJSON response (`id`, `_score` and the hit fields when
+    /// empty), and of a flat one
+    #[serde(default)]
+    pub columns: Vec<String>,
+    /// Shape of the JSON response
+    #[serde(default)]
+    pub response_format: ResponseFormat,
+    /// Name of a search preset of the index supplying settings the request leaves out
+    #[serde(default)]
+    pub preset: Option<String>,
+    /// Remove stopwords from the query before searching (`true`) or never (`false`),
+    /// instead of the index's `remove_stopwords` setting
+    #[serde(default)]
+    pub remove_stopwords: Option<bool>,
+    /// Read the query as end-user input: quoted phrases and `-` exclusions, with any other
+    /// query syntax searched as text, so it never fails to parse
+    #[serde(default)]
+    pub simple_query: bool,
+    /// Escape query syntax so the query is searched as text, keeping quoted phrases
+    #[serde(default)]
+    pub escape_special_chars: bool,
+    /// Only match documents with a term of a field matching a regex
+    #[serde(default)]
+    pub regex: Option<RegexSearch>,
+}
+
+/// Shape of a JSON search response
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseFormat {
+    /// Hits with their fields, totals and aggregations in the `ApiResponse` envelope
+    #[default]
+    Standard,
+    /// Only the hits, as a bare array of objects holding `id`, `_score` and their fields
+    Flat,
+}
+
+/// Match the parents of the child documents matching a query, which are linked to
+/// their parent through the index's `parent_field`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HasChildQuery {
+    /// Optional label reported in the `matched_queries` of the hits it matches
+    #[serde(default, rename = "_name", skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub query: String,
+    /// Best matching children returned in each parent hit's `children`
+    #[serde(default)]
+    pub inner_hits: usize,
+}
+
+/// Match the documents with a term of `field` matching `pattern` as a whole, like SKUs
+/// of a `string` field
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RegexSearch {
+    /// Optional label reported in the `matched_queries` of the hits it matches
+    #[serde(default, rename = "_name", skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub field: String,
+    pub pattern: String,
+    /// How long the field's terms may be scanned for matches before the search fails
+    #[serde(default = "default_regex_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_regex_timeout_ms() -> u64 {
+    100
+}
+
+/// Match the child documents of the parents matching a query
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HasParentQuery {
+    /// Optional label reported in the `matched_queries` of the hits it matches
+    #[serde(default, rename = "_name", skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub query: String,
+}
+
+/// Embed in each hit the document of another index whose ID a hit field holds, such
+/// as the author profile an `author_id` refers to
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LookupRequest {
+    /// Hit field holding the ID; an array of IDs embeds an array of documents
+    pub field: String,
+    /// Index the documents are looked up in
+    pub index: String,
+    /// Fields of the looked-up documents to embed (their displayed fields when empty)
+    #[serde(default)]
+    pub fields: Vec<String>,
+    /// Hit field the document is embedded in; defaults to `field` without its `_id`
+    /// suffix, which replaces the ID when there is none
+    #[serde(default, rename = "as")]
+    pub target: Option<String>,
+}
+
+impl LookupRequest {
+    /// Hit field the document is embedded in
+    pub fn target(&self) -> &str {
+        match &self.target {
+            Some(target) => target,
+            None => self
+                .field
+                .strip_suffix("_id")
+                .filter(|name| !name.is_empty())
+                .unwrap_or(&self.field),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RerankOptions {
+    /// Number of top hits sent to the reranker; hits below keep their order
+    #[serde(default = "default_rerank_top_k")]
+    pub top_k: usize,
+}
+
+fn default_rerank_top_k() -> usize {
+    20
 }
 
 fn default_limit() -> usize {
@@ -92,9 +834,13 @@ pub enum SortOrder {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SortOption {
+    /// Fast field to sort by, or `_random` for a random sample
     pub field: String,
     #[serde(default)]
     pub order: SortOrder,
+    /// Seed for `_random` sorting; the same seed returns the same order
+    #[serde(default)]
+    pub seed: Option<u64>,
 }
 
 fn default_true() -> bool {
@@ -123,14 +869,23 @@ impl Default for HighlightOptions {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AggregationRequest {
     pub name: String,
-    pub agg_type: String, // "terms", "histogram", "range", "stats"
+    pub agg_type: String, // "terms", "histogram", "range", "stats", "geohash_grid", "weighted_avg", ...
     pub field: String,
     #[serde(default)]
     pub size: Option<usize>,
+    /// Geohash length for geohash_grid aggregations (1-12)
+    #[serde(default)]
+    pub precision: Option<usize>,
     #[serde(default)]
     pub interval: Option<f64>,
     #[serde(default)]
     pub ranges: Option<Vec<RangeSpec>>,
+    /// Values to rank for percentile_ranks aggregations
+    #[serde(default)]
+    pub values: Option<Vec<f64>>,
+    /// Field holding each document's weight for weighted_avg aggregations
+    #[serde(default)]
+    pub weight_field: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -152,18 +907,129 @@ pub struct SearchResponse {
     pub hits: Vec<SearchHit>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub aggregations: Option<tantivy::aggregation::agg_result::AggregationResults>,
+    /// Aggregations that could not be computed, e.g. because of a field type mismatch
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aggregation_errors: Vec<AggregationError>,
+    /// Whether the top hits were reordered by the reranker
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub reranked: bool,
+    /// Commit generation the results include at least; not set for index patterns
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generation: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AggregationError {
+    pub name: String,
+    pub error: String,
+}
+
+/// Memory a search is estimated to need before it runs, reported when it is refused
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MemoryEstimate {
+    pub estimated_bytes: u64,
+    pub max_bytes: u64,
+    /// Candidates collected per segment and the hits loaded for the page
+    pub hits_bytes: u64,
+    pub aggregation_bytes: u64,
+    /// Bitsets of the documents matching wildcard and fuzzy terms
+    pub expansion_bytes: u64,
+    pub buckets: u64,
+    pub max_buckets: u64,
 }
 
 #[derive(Debug, Serialize)]
 pub struct SearchHit {
     pub id: String,
+    /// Index the hit came from, when the search spans more than one index
+    #[serde(rename = "_index", skip_serializing_if = "Option::is_none")]
+    pub index: Option<String>,
+    /// Shard of its index the hit came from, when that index is sharded
+    #[serde(rename = "_shard", skip_serializing_if = "Option::is_none")]
+    pub shard: Option<usize>,
     pub score: f32,
     pub fields: HashMap<String, serde_json::Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub highlights: Option<HashMap<String, Vec<String>>>,
+    /// Names of the named queries and filters this hit matched
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub matched_queries: Vec<String>,
+    /// Best matching children of a parent hit, for `has_child` with `inner_hits`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<SearchHit>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl SearchHit {
+    /// Drop fields not in `include` (when non-empty) and any field in `exclude`
+    pub fn retain_fields(&mut self, include: &[String], exclude: &[String]) {
+        self.fields.retain(|name, _| {
+            (include.is_empty() || include.contains(name)) && !exclude.contains(name)
+        });
+    }
+
+    /// Remove fields and highlights the caller isn't allowed to see
+    pub fn remove_hidden(&mut self, hidden: &[String]) {
+        self.fields.retain(|name, _| !field_access::is_hidden(name, hidden));
+        if let Some(highlights) = self.highlights.as_mut() {
+            highlights.retain(|name, _| !field_access::is_hidden(name, hidden));
+        }
+        for child in &mut self.children {
+            child.remove_hidden(hidden);
+        }
+    }
+
+    /// Keep only the displayed fields and their highlights
+    pub fn retain_displayed(&mut self, displayed: &[String]) {
+        self.fields.retain(|name, _| displayed.contains(name));
+        if let Some(highlights) = self.highlights.as_mut() {
+            highlights.retain(|name, _| displayed.contains(name));
+        }
+        // Children come from the same index
+        for child in &mut self.children {
+            child.retain_displayed(displayed);
+        }
+    }
+
+    /// Move fields to new names. A source naming a hit field renames it (and its
+    /// highlights); otherwise a dotted source is looked up inside JSON fields.
+    pub fn apply_field_mapping(&mut self, mapping: &HashMap<String, String>) {
+        // Take every source value before inserting so mappings can swap names
+        let mut moved_fields = Vec::new();
+        let mut moved_highlights = Vec::new();
+        for (source, target) in mapping {
+            if let Some(value) = self.take_field(source) {
+                moved_fields.push((target.clone(), value));
+            }
+            if let Some(snippets) = self.highlights.as_mut().and_then(|h| h.remove(source)) {
+                moved_highlights.push((target.clone(), snippets));
+            }
+        }
+        self.fields.extend(moved_fields);
+        if let Some(highlights) = self.highlights.as_mut() {
+            highlights.extend(moved_highlights);
+        }
+    }
+
+    fn take_field(&mut self, path: &str) -> Option<serde_json::Value> {
+        if let Some(value) = self.fields.remove(path) {
+            return Some(value);
+        }
+
+        let (root, rest) = path.split_once('.')?;
+        let mut current = self.fields.get_mut(root)?;
+        let mut keys = rest.split('.').peekable();
+        while let Some(key) = keys.next() {
+            let object = current.as_object_mut()?;
+            if keys.peek().is_none() {
+                return object.remove(key);
+            }
+            current = object.get_mut(key)?;
+        }
+        None
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AnswerRequest {
     pub query: String,
     #[serde(default = "default_answer_limit")]
@@ -172,28 +1038,260 @@ pub struct AnswerRequest {
     pub fields: Vec<String>,
     #[serde(default)]
     pub fuzzy: bool,
+    /// Names of stored filters restricting which documents can be sources
+    #[serde(default)]
+    pub filters: Vec<String>,
+    /// Geo filters a source document must match (all of them)
+    #[serde(default)]
+    pub geo_filters: Vec<GeoFilter>,
+    /// Order of the hits used as sources (by relevance when omitted)
+    #[serde(default)]
+    pub sort: Option<SortOption>,
     #[serde(default = "default_true")]
     pub stream: bool,
+    /// Provider for this request, overriding the index's `llm` settings
+    #[serde(default)]
+    pub provider: Option<String>,
+    /// Model for this request, overriding the index's `llm` settings
+    #[serde(default)]
+    pub model: Option<String>,
     #[serde(default)]
     pub temperature: Option<f32>,
     #[serde(default)]
     pub max_tokens: Option<u32>,
     #[serde(default)]
     pub system_prompt: Option<String>,
+    /// Name of a prompt template stored for the index
+    #[serde(default)]
+    pub prompt_template: Option<String>,
+    /// How long text fields are split into passages for the prompt
+    #[serde(default)]
+    pub chunking: ChunkingOptions,
+    /// Let the LLM turn the question into keywords and filters before searching
+    #[serde(default)]
+    pub rewrite_query: bool,
+    /// Let the LLM reorder the top hits before the best ones are used as sources
+    #[serde(default)]
+    pub rerank: Option<RerankOptions>,
+    /// Check each sentence of the answer against the sources afterwards
+    #[serde(default)]
+    pub verify: bool,
+    /// Reuse a cached answer to the same question, when answer caching is enabled
+    #[serde(default = "default_true")]
+    pub cache: bool,
+    /// Which fields of each source the prompt includes, and how much of them
+    #[serde(default)]
+    pub context: ContextOptions,
 }
 
-fn default_answer_limit() -> usize {
-    5
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ContextOptions {
+    /// Fields included for each source, in this order (empty = all fields)
+    #[serde(default)]
+    pub fields: Vec<String>,
+    /// Maximum characters per source; longer sources are cut off
+    #[serde(default)]
+    pub max_source_chars: Option<usize>,
+    /// Maximum characters for all sources; sources that don't fit are left out
+    #[serde(default)]
+    pub max_context_chars: Option<usize>,
 }
 
-#[derive(Debug, Serialize)]
+impl AnswerRequest {
+    /// LLM options given in the request
+    pub fn llm_settings(&self) -> LlmSettings {
+        LlmSettings {
+            provider: self.provider.clone(),
+            model: self.model.clone(),
+            temperature: self.temperature,
+            max_tokens: self.max_tokens,
+        }
+    }
+
+    /// The retrieval part of the request as a search request, for validation
+    pub fn search_request(&self) -> SearchRequest {
+        SearchRequest {
+            query: self.query.clone(),
+            limit: self.search_limit,
+            offset: 0,
+            fields: self.fields.clone(),
+            boost: HashMap::new(),
+            fuzzy: self.fuzzy,
+            sort: self.sort.clone(),
+            highlight: None,
+            aggregations: Vec::new(),
+            minimum_should_match: None,
+            proximity: None,
+            default_operator: None,
+            geo_filters: self.geo_filters.clone(),
+            include_fields: Vec::new(),
+            exclude_fields: Vec::new(),
+            field_mapping: HashMap::new(),
+            filters: self.filters.clone(),
+            rerank: self.rerank.clone(),
+            wait_for_generation: None,
+            lookups: Vec::new(),
+            has_child: None,
+            has_parent: None,
+            columns: Vec::new(),
+            response_format: ResponseFormat::Standard,
+            preset: None,
+            remove_stopwords: None,
+            simple_query: false,
+            escape_special_chars: false,
+            regex: None,
+        }
+    }
+}
+
+fn default_answer_limit() -> usize {
+    5
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChunkingOptions {
+    /// Maximum passage length in characters; longer text fields are split
+    #[serde(default = "default_chunk_size")]
+    pub chunk_size: usize,
+    /// Characters of trailing sentences repeated at the start of the next passage
+    #[serde(default = "default_chunk_overlap")]
+    pub chunk_overlap: usize,
+    /// Best matching passages sent to the model from each document
+    #[serde(default = "default_passages_per_document")]
+    pub passages_per_document: usize,
+}
+
+impl Default for ChunkingOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: default_chunk_size(),
+            chunk_overlap: default_chunk_overlap(),
+            passages_per_document: default_passages_per_document(),
+        }
+    }
+}
+
+fn default_chunk_size() -> usize {
+    1000
+}
+
+fn default_chunk_overlap() -> usize {
+    200
+}
+
+fn default_passages_per_document() -> usize {
+    2
+}
+
+/// Document (or passage of a document) given to the model as a source
+#[derive(Debug, Serialize, Clone)]
+pub struct AnswerSource {
+    /// ID of the document
+    pub id: String,
+    pub score: f32,
+    /// Document fields, without the long text fields that were split into passages
+    pub fields: HashMap<String, serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub passage: Option<Passage>,
+}
+
+/// Part of a long text field, with character offsets into the field value
+#[derive(Debug, Serialize, Clone)]
+pub struct Passage {
+    pub field: String,
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Passage {
+    pub fn location(&self) -> PassageLocation {
+        PassageLocation {
+            field: self.field.clone(),
+            start: self.start,
+            end: self.end,
+        }
+    }
+}
+
+/// Where a passage is in its document: field name and character offsets
+#[derive(Debug, Serialize, Clone)]
+pub struct PassageLocation {
+    pub field: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// `[n]` marker in an answer, resolved to the source it refers to
+#[derive(Debug, Serialize, Clone)]
+pub struct Citation {
+    /// Source number from the marker, e.g. 2 for `[2]`
+    pub marker: usize,
+    /// ID of the cited document
+    pub id: String,
+    /// Character offsets of the marker in the answer
+    pub start: usize,
+    pub end: usize,
+    /// Cited passage, when the source is part of a long field
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub passage: Option<PassageLocation>,
+}
+
+/// Keyword query and field filters the LLM derived from a question
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RewrittenQuery {
+    #[serde(default)]
+    pub query: String,
+    #[serde(default)]
+    pub filters: Vec<FieldFilter>,
+}
+
+/// Exact value a field must contain
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FieldFilter {
+    pub field: String,
+    pub value: String,
+}
+
+/// How well an answer is supported by its sources
+#[derive(Debug, Serialize, Clone)]
+pub struct Groundedness {
+    /// Average support of the sentences, from 0 (unsupported) to 1 (fully supported)
+    pub score: f32,
+    pub sentences: Vec<SentenceSupport>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SentenceSupport {
+    pub text: String,
+    /// Character offsets of the sentence in the answer
+    pub start: usize,
+    pub end: usize,
+    pub score: f32,
+    pub supported: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
 pub struct AnswerResponse {
     pub answer: String,
     pub model: String,
     pub search_took_ms: f64,
     pub llm_took_ms: f64,
     pub total_took_ms: f64,
-    pub sources: Vec<SearchHit>,
+    pub sources: Vec<AnswerSource>,
+    pub citations: Vec<Citation>,
+    /// Query used for the search when the question was rewritten
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rewritten_query: Option<RewrittenQuery>,
+    /// Result of the verification pass, when requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub groundedness: Option<Groundedness>,
+    /// Whether the answer was served from the answer cache
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+    /// Tokens used by the answer call, when the provider reports them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
 }
 
 #[derive(Debug, Serialize)]
@@ -201,6 +1299,8 @@ pub struct IndexInfo {
     pub name: String,
     pub document_count: u64,
     pub created_at: String,
+    /// Closed indices are kept on disk and opened again when used
+    pub closed: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -210,6 +1310,67 @@ pub struct IndexStats {
     pub size_bytes: u64,
     pub fields: Vec<FieldStats>,
     pub created_at: String,
+    /// Opstamp of the last commit, which searches see; summed over the shards
+    pub opstamp: u64,
+    pub shards: usize,
+    /// Searchable segments, summed over the shards
+    pub segment_count: usize,
+    /// Deleted documents still taking space in their segments until they are merged
+    pub deleted_document_count: u64,
+    /// Bytes used by each part of the index files
+    pub storage: StorageStats,
+    /// Document rows in the metadata store; differs from document_count when they drift
+    pub metadata_document_count: u64,
+    /// Result of the last metadata sync for this index
+    pub last_sync: Option<SyncReport>,
+    pub filter_cache: FilterCacheStats,
+    /// Result of the last run of the index's warmup queries
+    pub last_warmup: Option<WarmupReport>,
+    pub write_queue: WriteQueueStats,
+}
+
+/// Requests running and waiting in the search and indexing pools
+#[derive(Debug, Serialize)]
+pub struct RequestQueues {
+    pub search: RequestPoolStats,
+    pub indexing: RequestPoolStats,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RequestPoolStats {
+    pub max_concurrent: usize,
+    pub running: usize,
+    pub waiting: usize,
+}
+
+/// Writes to an index running or waiting for its writers, as tracked for backpressure
+#[derive(Debug, Default, Serialize, Clone)]
+pub struct WriteQueueStats {
+    pub pending: usize,
+    /// Moving average of write latency, waiting included
+    pub avg_latency_ms: f64,
+    /// Writes refused with 429 since startup
+    pub rejected: u64,
+}
+
+/// Outcome of running an index's warmup queries
+#[derive(Debug, Serialize, Clone)]
+pub struct WarmupReport {
+    pub warmed_at: String,
+    /// Commit opstamp the queries ran against
+    pub opstamp: u64,
+    pub queries: usize,
+    pub failed: usize,
+    pub took_ms: f64,
+}
+
+/// Filters of an index cached per segment, and how often searches found them cached
+#[derive(Debug, Serialize)]
+pub struct FilterCacheStats {
+    pub entries: usize,
+    pub size_bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
 }
 
 #[derive(Debug, Serialize)]
@@ -218,6 +1379,43 @@ pub struct FieldStats {
     pub field_type: String,
     pub indexed: bool,
     pub stored: bool,
+    /// Distinct terms of an indexed field, counted per segment and summed, so terms
+    /// in several segments count more than once
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub term_count: Option<u64>,
+}
+
+/// What a field supports, so clients can build queries without knowing the schema
+#[derive(Debug, Serialize)]
+pub struct FieldCapabilities {
+    pub name: String,
+    pub field_type: String,
+    /// Analyzer of an indexed text or string field (`raw` for exact-match fields)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub analyzer: Option<String>,
+    /// Languages indexed for a field with the `auto` analyzer
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub languages: Vec<String>,
+    pub indexed: bool,
+    pub stored: bool,
+    pub fast: bool,
+    /// Searched by queries that don't name their fields
+    pub default_search: bool,
+    pub sortable: bool,
+    pub aggregatable: bool,
+    pub highlightable: bool,
+}
+
+/// Bytes used by the searchable segments, by part of the index
+#[derive(Debug, Default, Serialize)]
+pub struct StorageStats {
+    pub term_dictionary: u64,
+    pub postings: u64,
+    pub positions: u64,
+    pub fast_fields: u64,
+    pub fieldnorms: u64,
+    pub store: u64,
+    pub deletes: u64,
 }
 
 #[derive(Debug, Serialize)]
@@ -227,6 +1425,14 @@ pub struct ApiResponse<T> {
     pub data: Option<T>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+    /// Stable code of the error, such as `index_not_found`. Filled in from the message
+    /// catalog on the way out when a handler leaves it unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    /// Machine-readable context of the error, such as the estimate of a search
+    /// refused for its memory use
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
 }
 
 impl<T> ApiResponse<T> {
@@ -235,6 +1441,8 @@ impl<T> ApiResponse<T> {
             success: true,
             data: Some(data),
             error: None,
+            code: None,
+            details: None,
         }
     }
 
@@ -243,13 +1451,20 @@ impl<T> ApiResponse<T> {
             success: false,
             data: None,
             error: Some(message),
+            code: None,
+            details: None,
         }
     }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BulkOperation {
-    pub operation: String, // "index" or "delete"
+    pub operation: String, // "index", "update", "upsert" or "delete"
     #[serde(skip_serializing_if = "Option::is_none")]
     pub document: Option<Document>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -267,6 +1482,48 @@ pub struct BulkResponse {
     pub successful: usize,
     pub failed: usize,
     pub errors: Vec<String>,
+    /// Result of each operation, in request order
+    pub items: Vec<BulkItem>,
+    pub commit: CommitStats,
+}
+
+/// Bulk operations applied atomically by `POST /indices/:name/_txn`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionRequest {
+    pub operations: Vec<BulkOperation>,
+}
+
+/// Result of a committed transaction; a failed one returns an error instead
+#[derive(Debug, Serialize)]
+pub struct TransactionResponse {
+    /// Result of each operation, in request order
+    pub items: Vec<BulkItem>,
+    pub commit: CommitStats,
+}
+
+/// What a bulk operation did
+#[derive(Debug, Serialize)]
+pub struct BulkItem {
+    pub operation: String,
+    /// ID of the document the operation wrote or deleted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub result: BulkResult,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkResult {
+    Created,
+    Updated,
+    Deleted,
+    /// Not written because the index already has a document with the same content
+    Noop,
+    /// `update` of a document that doesn't exist
+    NotFound,
+    Error,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -288,6 +1545,233 @@ pub struct SuggestResponse {
     pub took_ms: f64,
 }
 
+/// Completed queries from the query log for a search box
+#[derive(Debug, Deserialize)]
+pub struct QuerySuggestRequest {
+    /// Start of the query typed so far
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(default = "default_suggest_limit")]
+    pub limit: usize,
+    /// Days of recent searches trending queries are picked from
+    #[serde(default = "default_trending_window_days")]
+    pub window_days: u32,
+}
+
+fn default_trending_window_days() -> u32 {
+    7
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuerySuggestion {
+    pub query: String,
+    /// Searches of the query, or for a trending query those in the window
+    pub count: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuerySuggestResponse {
+    /// Most searched queries
+    pub popular: Vec<QuerySuggestion>,
+    /// Queries searched more in the window than in as many days before it
+    pub trending: Vec<QuerySuggestion>,
+    pub took_ms: f64,
+}
+
+/// Window and size of the trending queries report
+#[derive(Debug, Deserialize)]
+pub struct TrendingParams {
+    #[serde(default = "default_trending_window_days")]
+    pub window_days: u32,
+    #[serde(default = "default_suggest_limit")]
+    pub limit: usize,
+}
+
+/// Query searched more in a window of days than in as many days before it
+#[derive(Debug, Serialize)]
+pub struct TrendingQuery {
+    pub query: String,
+    /// Searches in the window
+    pub count: u64,
+    /// Searches in the days before the window
+    pub previous_count: u64,
+    /// Whether every logged search of the query found nothing
+    pub zero_results: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrendingResponse {
+    pub window_days: u32,
+    pub queries: Vec<TrendingQuery>,
+}
+
+/// Query to find related queries for, in the `q` query parameter
+#[derive(Debug, Deserialize)]
+pub struct RelatedQueriesParams {
+    pub q: Option<String>,
+    #[serde(default = "default_suggest_limit")]
+    pub limit: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RelatedQuery {
+    pub query: String,
+    /// Sessions that searched both queries
+    pub sessions: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RelatedQueriesResponse {
+    /// The query as logged: lowercased with extra spaces removed
+    pub query: String,
+    pub related: Vec<RelatedQuery>,
+}
+
+/// Text to run through an analyzer, named or taken from a field
+#[derive(Debug, Deserialize)]
+pub struct AnalyzeRequest {
+    pub text: String,
+    /// `default`, `norwegian`, `raw`, or a language code such as `en`
+    #[serde(default)]
+    pub analyzer: Option<String>,
+    /// Use the analyzer of this field instead
+    #[serde(default)]
+    pub field: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnalyzeResponse {
+    /// Name of the analyzer the text was run through
+    pub analyzer: String,
+    pub tokens: Vec<AnalyzedToken>,
+}
+
+/// A token as it is indexed or searched, with its byte offsets in the text
+#[derive(Debug, Serialize)]
+pub struct AnalyzedToken {
+    pub token: String,
+    pub position: usize,
+    pub start_offset: usize,
+    pub end_offset: usize,
+}
+
+/// Fields to return term vectors for; all indexed text and string fields when empty
+#[derive(Debug, Default, Deserialize)]
+pub struct TermVectorsParams {
+    /// Comma-separated field names
+    #[serde(default)]
+    pub fields: Option<String>,
+}
+
+/// Terms of a stored document, for relevance debugging and more-like-this queries
+#[derive(Debug, Serialize)]
+pub struct TermVectors {
+    pub id: String,
+    /// Documents in the index, for turning document frequencies into IDF
+    pub document_count: u64,
+    pub fields: Vec<FieldTermVectors>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldTermVectors {
+    pub field: String,
+    pub analyzer: String,
+    /// Terms in alphabetical order
+    pub terms: Vec<TermVector>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TermVector {
+    pub term: String,
+    /// Occurrences in this document's field
+    pub term_freq: usize,
+    /// Documents of the index with the term in this field
+    pub doc_freq: u64,
+    pub positions: Vec<usize>,
+}
+
+/// Change recorded in the audit log
+#[derive(Debug, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub timestamp: String,
+    /// Hash of the caller's API token, or `anonymous` while authentication is disabled
+    pub actor: String,
+    /// What was done, such as `index.create` or `documents.add`
+    pub action: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<String>,
+    pub method: String,
+    pub path: String,
+    /// HTTP status of the response; refused and failed attempts are recorded too
+    pub status: u16,
+    /// What the request changed beyond its path, such as the IDs of written documents
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+/// Filters of `GET /admin/audit`
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditParams {
+    pub index: Option<String>,
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    /// Entries at or after this time (RFC3339)
+    pub since: Option<String>,
+    /// Entries before this time (RFC3339)
+    pub until: Option<String>,
+    /// Continue with the entries before this ID, the `next` of the previous page
+    pub before: Option<i64>,
+    #[serde(default = "default_audit_limit")]
+    pub limit: usize,
+}
+
+fn default_audit_limit() -> usize {
+    100
+}
+
+/// Page of the audit log, newest entries first
+#[derive(Debug, Serialize)]
+pub struct AuditResponse {
+    pub entries: Vec<AuditEntry>,
+    /// ID to pass as `before` for the next page, if the page is full
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<i64>,
+}
+
+/// Page of a field's term dictionary
+#[derive(Debug, Deserialize)]
+pub struct TermsParams {
+    pub field: Option<String>,
+    #[serde(default)]
+    pub prefix: String,
+    /// Continue after this term, the `next` of the previous page
+    #[serde(default)]
+    pub after: Option<String>,
+    #[serde(default = "default_terms_limit")]
+    pub limit: usize,
+}
+
+fn default_terms_limit() -> usize {
+    100
+}
+
+#[derive(Debug, Serialize)]
+pub struct TermsResponse {
+    pub field: String,
+    pub terms: Vec<TermCount>,
+    /// Term to pass as `after` for the next page, if the page is full
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+}
+
+/// A term of the dictionary and the number of documents containing it
+#[derive(Debug, Serialize)]
+pub struct TermCount {
+    pub term: String,
+    pub doc_freq: u64,
+}
+
 /// Synonym group - all terms in the group are treated as equivalent
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SynonymGroup {
@@ -308,6 +1792,107 @@ pub struct SynonymsResponse {
     pub synonyms: Vec<SynonymGroup>,
 }
 
+/// Request for LLM-proposed synonyms and stopwords for an index
+#[derive(Debug, Deserialize)]
+pub struct SuggestSynonymsRequest {
+    /// Number of the most common index terms shown to the model
+    #[serde(default = "default_suggest_sample_terms")]
+    pub sample_terms: usize,
+    /// Number of the most frequent zero-result queries shown to the model
+    #[serde(default = "default_suggest_sample_queries")]
+    pub sample_queries: usize,
+}
+
+impl Default for SuggestSynonymsRequest {
+    fn default() -> Self {
+        Self {
+            sample_terms: default_suggest_sample_terms(),
+            sample_queries: default_suggest_sample_queries(),
+        }
+    }
+}
+
+fn default_suggest_sample_terms() -> usize {
+    200
+}
+
+fn default_suggest_sample_queries() -> usize {
+    50
+}
+
+/// Synonyms and stopwords proposed by the model, for review before they are applied
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SynonymSuggestions {
+    #[serde(default)]
+    pub synonyms: Vec<SynonymGroup>,
+    #[serde(default)]
+    pub stopwords: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SynonymSuggestionsResponse {
+    #[serde(flatten)]
+    pub suggestions: SynonymSuggestions,
+    pub model: String,
+    /// Index terms and zero-result queries the suggestions are based on
+    pub sampled_terms: usize,
+    pub sampled_queries: usize,
+}
+
+/// Index whose zero-result queries are reported on, and how many of them
+#[derive(Debug, Deserialize)]
+pub struct RelevanceReportParams {
+    pub index: Option<String>,
+    /// Number of the most frequent zero-result queries read from the query log
+    #[serde(default = "default_report_sample_queries")]
+    pub sample_queries: usize,
+}
+
+fn default_report_sample_queries() -> usize {
+    100
+}
+
+/// Zero-result queries of an index grouped by variant, with fixes to consider
+#[derive(Debug, Serialize)]
+pub struct RelevanceReport {
+    pub index: String,
+    pub clusters: Vec<ZeroResultCluster>,
+    /// Zero-result queries and index terms the report is based on
+    pub sampled_queries: usize,
+    pub sampled_terms: usize,
+}
+
+/// Zero-result queries that are variants of each other
+#[derive(Debug, Serialize)]
+pub struct ZeroResultCluster {
+    /// Most searched query of the group
+    pub query: String,
+    pub queries: Vec<String>,
+    /// Times the queries of the group found nothing
+    pub zero_result_count: u64,
+    /// Index terms close to the words that aren't in the index
+    pub corrections: Vec<TermCorrection>,
+    /// Synonym groups joining each of those words to its closest index term
+    pub synonyms: Vec<SynonymGroup>,
+    /// Words with no close index term: content the index may be missing
+    pub missing_words: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TermCorrection {
+    pub word: String,
+    pub terms: Vec<CloseTerm>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CloseTerm {
+    pub term: String,
+    /// Sampled documents containing the term
+    pub doc_freq: u64,
+    /// Edits between the word and the term
+    pub distance: usize,
+}
+
 /// Pinned result rule - promote specific documents for specific queries
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PinnedRule {
@@ -329,3 +1914,431 @@ pub struct AddPinnedRulesRequest {
 pub struct PinnedRulesResponse {
     pub rules: Vec<PinnedRule>,
 }
+
+/// Named filter stored per index and applied by name from search requests
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StoredFilter {
+    pub name: String,
+    /// Filter in query syntax, e.g. `status:published AND in_stock:1`
+    pub query: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub geo_filters: Vec<GeoFilter>,
+}
+
+/// Request to add stored filters to an index (replacing filters with the same name)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AddStoredFiltersRequest {
+    pub filters: Vec<StoredFilter>,
+}
+
+/// Response for stored filter operations
+#[derive(Debug, Serialize)]
+pub struct StoredFiltersResponse {
+    pub filters: Vec<StoredFilter>,
+}
+
+/// Request to derive a search key from the caller's API token
+#[derive(Debug, Deserialize)]
+pub struct CreateSearchKeyRequest {
+    /// Filter in query syntax ANDed into every search and answer made with the key
+    pub filter: String,
+    /// Indices the key can search (default: all)
+    #[serde(default)]
+    pub indices: Vec<String>,
+    /// Restricted field scopes to pass on, out of the caller's token's scopes
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    /// Seconds until the key expires (default: never)
+    #[serde(default)]
+    pub expires_in_secs: Option<u64>,
+}
+
+/// Derived search key
+#[derive(Debug, Serialize)]
+pub struct SearchKeyResponse {
+    pub key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+}
+
+/// Named system/user prompts stored per index and referenced from answer requests.
+/// Prompts can use the `{{query}}` and `{{sources}}` placeholders.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PromptTemplate {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+/// Request to add prompt templates to an index (replacing templates with the same name)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AddPromptTemplatesRequest {
+    pub templates: Vec<PromptTemplate>,
+}
+
+/// Response for prompt template operations
+#[derive(Debug, Serialize)]
+pub struct PromptTemplatesResponse {
+    pub templates: Vec<PromptTemplate>,
+}
+
+/// Named search settings stored per index, so the applications searching it can share
+/// tuned settings by name instead of repeating them in every request
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchPreset {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub boost: HashMap<String, f32>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fuzzy: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_operator: Option<DefaultOperator>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub highlight: Option<HighlightOptions>,
+}
+
+impl SearchPreset {
+    /// Fill in the settings a search request leaves out. Boosts are merged, with the
+    /// request's boost winning for a field both set.
+    pub fn apply(&self, request: &mut SearchRequest) {
+        if request.fields.is_empty() {
+            request.fields = self.fields.clone();
+        }
+        for (field, boost) in &self.boost {
+            request.boost.entry(field.clone()).or_insert(*boost);
+        }
+        request.fuzzy |= self.fuzzy;
+        if request.default_operator.is_none() {
+            request.default_operator = self.default_operator;
+        }
+        if request.highlight.is_none() {
+            request.highlight = self.highlight.clone();
+        }
+    }
+}
+
+/// Request to add search presets to an index (replacing presets with the same name)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AddSearchPresetsRequest {
+    pub presets: Vec<SearchPreset>,
+}
+
+/// Response for search preset operations
+#[derive(Debug, Serialize)]
+pub struct SearchPresetsResponse {
+    pub presets: Vec<SearchPreset>,
+}
+
+/// Request to register a web crawler for an index
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateCrawlerRequest {
+    /// URL the crawl starts from; only pages on the same host are followed
+    pub seed_url: String,
+    /// Maximum number of links to follow from the seed URL
+    #[serde(default = "default_crawl_depth")]
+    pub max_depth: usize,
+    /// Maximum number of pages fetched per run
+    #[serde(default = "default_crawl_max_pages")]
+    pub max_pages: usize,
+    /// Regex patterns a URL must match (any) to be crawled
+    #[serde(default)]
+    pub include_patterns: Vec<String>,
+    /// Regex patterns that exclude a URL from being crawled
+    #[serde(default)]
+    pub exclude_patterns: Vec<String>,
+    /// Re-crawl interval in seconds (omit for manual runs only)
+    #[serde(default)]
+    pub interval_seconds: Option<u64>,
+}
+
+fn default_crawl_depth() -> usize {
+    2
+}
+
+fn default_crawl_max_pages() -> usize {
+    100
+}
+
+/// Registered crawler and the outcome of its last run
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CrawlerConfig {
+    pub id: String,
+    pub index_name: String,
+    pub seed_url: String,
+    pub max_depth: usize,
+    pub max_pages: usize,
+    pub include_patterns: Vec<String>,
+    pub exclude_patterns: Vec<String>,
+    pub interval_seconds: Option<u64>,
+    pub created_at: String,
+    pub last_run_at: Option<String>,
+    pub last_status: Option<String>,
+    pub last_message: Option<String>,
+}
+
+/// Summary of a single crawl run
+#[derive(Debug, Default, Serialize)]
+pub struct CrawlReport {
+    pub pages_fetched: usize,
+    pub documents_indexed: usize,
+    pub errors: Vec<String>,
+    pub took_ms: f64,
+}
+
+/// Work performed by a scheduled job
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobTask {
+    /// Run a registered crawler
+    Crawl { crawler_id: String },
+    /// Pull an RSS or Atom feed and index its entries
+    Feed {
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_items: Option<usize>,
+    },
+    /// Re-analyze the stored documents, merge index segments and rebuild document
+    /// metadata from the index
+    Reindex,
+    /// Copy the index files into the snapshots directory
+    Snapshot {
+        #[serde(default = "default_snapshot_keep")]
+        keep: usize,
+    },
+    /// Roll a rolling index over when its conditions are met
+    Rollover,
+    /// Drop the index's search analytics older than the days kept. Searches are rolled
+    /// up into per-query totals and per-day counts as they are recorded.
+    #[serde(alias = "analytics_rollup")]
+    AnalyticsRetention,
+}
+
+fn default_snapshot_keep() -> usize {
+    5
+}
+
+/// Request to register a scheduled job
+#[derive(Debug, Deserialize)]
+pub struct CreateJobRequest {
+    /// Optional display name (defaults to the task type and index)
+    #[serde(default)]
+    pub name: Option<String>,
+    pub index_name: String,
+    /// Cron expression, either 5 fields (minute precision) or 6 fields with seconds
+    pub schedule: String,
+    pub task: JobTask,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Registered job and the outcome of its last run
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JobConfig {
+    pub id: String,
+    pub name: String,
+    pub index_name: String,
+    pub schedule: String,
+    pub task: JobTask,
+    pub enabled: bool,
+    pub created_at: String,
+    pub last_run_at: Option<String>,
+    pub last_status: Option<String>,
+    pub last_message: Option<String>,
+}
+
+/// Job with its scheduling state, as reported by `GET /admin/jobs`
+#[derive(Debug, Serialize)]
+pub struct JobStatus {
+    #[serde(flatten)]
+    pub job: JobConfig,
+    pub running: bool,
+    pub next_run_at: Option<String>,
+}
+
+/// Latitude/longitude pair in degrees
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct GeoPoint {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+/// Restricts search results to documents whose geo_point field lies in a shape
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GeoFilter {
+    /// Optional label reported in the `matched_queries` of the hits it matches
+    #[serde(default, rename = "_name", skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub field: String,
+    #[serde(flatten)]
+    pub shape: GeoShape,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum GeoShape {
+    /// Box given by its north-west and south-east corners; crossing the antimeridian is allowed
+    BoundingBox {
+        top_left: GeoPoint,
+        bottom_right: GeoPoint,
+    },
+    /// Polygon given by at least three vertices; closing the ring is optional
+    Polygon { points: Vec<GeoPoint> },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retain_displayed_drops_fields_and_highlights() {
+        let mut hit = SearchHit {
+            id: "1".to_string(),
+            index: None,
+            shard: None,
+            score: 1.0,
+            fields: HashMap::from([
+                ("title".to_string(), serde_json::json!("shoe")),
+                ("notes".to_string(), serde_json::json!("internal")),
+            ]),
+            highlights: Some(HashMap::from([
+                ("title".to_string(), vec!["<em>shoe</em>".to_string()]),
+                ("notes".to_string(), vec!["<em>internal</em>".to_string()]),
+            ])),
+            matched_queries: Vec::new(),
+            children: Vec::new(),
+        };
+
+        hit.retain_displayed(&["title".to_string()]);
+        assert_eq!(hit.fields.keys().collect::<Vec<_>>(), ["title"]);
+        assert_eq!(hit.highlights.unwrap().keys().collect::<Vec<_>>(), ["title"]);
+    }
+
+    #[test]
+    fn test_search_preset_fills_in_what_the_request_leaves_out() {
+        let preset: SearchPreset = serde_json::from_value(serde_json::json!({
+            "name": "products",
+            "fields": ["title", "body"],
+            "boost": {"title": 2.0, "body": 0.5},
+            "fuzzy": true,
+            "default_operator": "AND",
+            "highlight": {"fields": ["title"]},
+        }))
+        .unwrap();
+
+        let mut request: SearchRequest =
+            serde_json::from_value(serde_json::json!({"query": "shoe"})).unwrap();
+        preset.apply(&mut request);
+        assert_eq!(request.fields, ["title", "body"]);
+        assert_eq!(
+            request.boost,
+            HashMap::from([("title".to_string(), 2.0), ("body".to_string(), 0.5)])
+        );
+        assert!(request.fuzzy);
+        assert_eq!(request.default_operator, Some(DefaultOperator::And));
+        assert_eq!(request.highlight.unwrap().fields, ["title"]);
+
+        // Settings the request has are kept
+        let mut request: SearchRequest = serde_json::from_value(serde_json::json!({
+            "query": "shoe",
+            "fields": ["body"],
+            "boost": {"title": 3.0},
+            "default_operator": "OR",
+            "highlight": {"enabled": false},
+        }))
+        .unwrap();
+        preset.apply(&mut request);
+        assert_eq!(request.fields, ["body"]);
+        assert_eq!(request.boost["title"], 3.0);
+        assert_eq!(request.boost["body"], 0.5);
+        assert_eq!(request.default_operator, Some(DefaultOperator::Or));
+        let highlight = request.highlight.unwrap();
+        assert!(!highlight.enabled);
+        assert!(highlight.fields.is_empty());
+    }
+
+    fn hit(fields: serde_json::Value) -> SearchHit {
+        SearchHit {
+            id: "1".to_string(),
+            index: None,
+            shard: None,
+            score: 1.0,
+            fields: serde_json::from_value(fields).unwrap(),
+            highlights: None,
+            matched_queries: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    fn mapping(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(from, to)| (from.to_string(), to.to_string())).collect()
+    }
+
+    #[test]
+    fn test_field_mapping_renames_and_flattens_fields() {
+        let mut renamed = hit(serde_json::json!({
+            "name": "Shoe",
+            "meta": {"title": "Red shoe", "source": {"site": "shop"}},
+        }));
+        renamed.highlights = Some(HashMap::from([
+            ("name".to_string(), vec!["<em>Shoe</em>".to_string()]),
+        ]));
+        renamed.apply_field_mapping(&mapping(&[
+            ("name", "label"),
+            ("meta.title", "title"),
+            ("meta.source.site", "site"),
+            ("meta.missing", "missing"),
+            ("unknown", "other"),
+        ]));
+        assert_eq!(
+            serde_json::to_value(&renamed.fields).unwrap(),
+            serde_json::json!({
+                "label": "Shoe",
+                "title": "Red shoe",
+                "site": "shop",
+                "meta": {"source": {}},
+            })
+        );
+        let highlights = renamed.highlights.unwrap();
+        assert_eq!(highlights.keys().collect::<Vec<_>>(), ["label"]);
+
+        // A target that already exists is replaced, and names can be swapped
+        let mut swapped = hit(serde_json::json!({"a": 1, "b": 2, "c": 3}));
+        swapped.apply_field_mapping(&mapping(&[("a", "b"), ("b", "a"), ("c", "a.x")]));
+        assert_eq!(
+            serde_json::to_value(&swapped.fields).unwrap(),
+            serde_json::json!({"a": 2, "b": 1, "a.x": 3})
+        );
+        let mut replaced = hit(serde_json::json!({"title": "old", "name": "new"}));
+        replaced.apply_field_mapping(&mapping(&[("name", "title")]));
+        assert_eq!(
+            serde_json::to_value(&replaced.fields).unwrap(),
+            serde_json::json!({"title": "new"})
+        );
+    }
+
+    #[test]
+    fn test_include_and_exclude_apply_to_mapped_names() {
+        // The order the search handler applies them in
+        let mut mapped = hit(serde_json::json!({
+            "name": "Shoe",
+            "price": 10,
+            "meta": {"title": "Red shoe"},
+        }));
+        mapped.apply_field_mapping(&mapping(&[("name", "label"), ("meta.title", "title")]));
+        mapped.retain_fields(&["label".to_string(), "title".to_string(), "name".to_string()], &[]);
+        let mut names: Vec<&String> = mapped.fields.keys().collect();
+        names.sort();
+        assert_eq!(names, ["label", "title"]);
+
+        mapped.retain_fields(&[], &["title".to_string()]);
+        assert_eq!(mapped.fields.keys().collect::<Vec<_>>(), ["label"]);
+    }
+}
@@ -0,0 +1,153 @@
+use unicode_normalization::UnicodeNormalization;
+
+use crate::models::QueryNormalizer;
+
+/// Russian (ЙЦУКЕН) keys and the Latin (QWERTY) characters on the same keys
+const KEYBOARD_LAYOUT: &[(char, char)] = &[
+    ('й', 'q'),
+    ('ц', 'w'),
+    ('у', 'e'),
+    ('к', 'r'),
+    ('е', 't'),
+    ('н', 'y'),
+    ('г', 'u'),
+    ('ш', 'i'),
+    ('щ', 'o'),
+    ('з', 'p'),
+    ('х', '['),
+    ('ъ', ']'),
+    ('ф', 'a'),
+    ('ы', 's'),
+    ('в', 'd'),
+    ('а', 'f'),
+    ('п', 'g'),
+    ('р', 'h'),
+    ('о', 'j'),
+    ('л', 'k'),
+    ('д', 'l'),
+    ('ж', ';'),
+    ('э', '\''),
+    ('я', 'z'),
+    ('ч', 'x'),
+    ('с', 'c'),
+    ('м', 'v'),
+    ('и', 'b'),
+    ('т', 'n'),
+    ('ь', 'm'),
+    ('б', ','),
+    ('ю', '.'),
+    ('ё', '`'),
+];
+
+/// Run a query through the configured normalizers, in order
+pub fn normalize_query(query: &str, normalizers: &[QueryNormalizer]) -> String {
+    normalizers
+        .iter()
+        .fold(query.to_string(), |query, normalizer| match normalizer {
+            QueryNormalizer::Nfc => query.nfc().collect(),
+            QueryNormalizer::Quotes => normalize_quotes(&query),
+            QueryNormalizer::Dashes => normalize_dashes(&query),
+            QueryNormalizer::StripEmoji => strip_emoji(&query),
+            QueryNormalizer::KeyboardLayout => correct_keyboard_layout(&query),
+        })
+}
+
+/// Replace typographic quotes with their ASCII equivalents
+fn normalize_quotes(query: &str) -> String {
+    query
+        .chars()
+        .map(|c| match c {
+            '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' | '\u{2032}' => '\'',
+            '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' | '\u{2033}' | '\u{00AB}'
+            | '\u{00BB}' => '"',
+            _ => c,
+        })
+        .collect()
+}
+
+/// Replace hyphen and dash variants (and the minus sign) with an ASCII hyphen
+fn normalize_dashes(query: &str) -> String {
+    query
+        .chars()
+        .map(|c| match c {
+            '\u{2010}'..='\u{2015}' | '\u{2212}' | '\u{FE63}' | '\u{FF0D}' => '-',
+            _ => c,
+        })
+        .collect()
+}
+
+fn is_emoji(c: char) -> bool {
+    matches!(
+        c,
+        '\u{1F000}'..='\u{1FAFF}'
+            | '\u{2600}'..='\u{27BF}'
+            | '\u{2B00}'..='\u{2BFF}'
+            | '\u{FE0E}'
+            | '\u{FE0F}'
+            | '\u{200D}'
+            | '\u{20E3}'
+            | '\u{E0020}'..='\u{E007F}'
+    )
+}
+
+/// Remove emoji (including modifiers and joiners) and tidy the left-over spacing
+fn strip_emoji(query: &str) -> String {
+    let stripped: String = query.chars().filter(|c| !is_emoji(*c)).collect();
+    stripped.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Retype words written entirely with a Russian keyboard layout as the Latin
+/// text typed on the same keys, e.g. "ифктурфпу" -> "barnehage"
+fn correct_keyboard_layout(query: &str) -> String {
+    let latin_key = |c: char| {
+        let lower = c.to_lowercase().next().unwrap_or(c);
+        KEYBOARD_LAYOUT
+            .iter()
+            .find(|(cyrillic, _)| *cyrillic == lower)
+            .map(|(_, latin)| *latin)
+    };
+
+    query
+        .split(' ')
+        .map(|word| {
+            let letters: Option<String> = word.chars().map(latin_key).collect();
+            match letters {
+                Some(latin) if word.chars().any(char::is_alphabetic) => latin,
+                _ => word.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_punctuation_and_emoji() {
+        let normalizers = [
+            QueryNormalizer::Nfc,
+            QueryNormalizer::Quotes,
+            QueryNormalizer::Dashes,
+            QueryNormalizer::StripEmoji,
+        ];
+        assert_eq!(
+            normalize_query(
+                "\u{201C}e\u{0301}ventyr\u{201D} \u{2013}barn 🎉👍🏽",
+                &normalizers
+            ),
+            "\"\u{00E9}ventyr\" -barn"
+        );
+        assert_eq!(normalize_query("«sol» 🌞", &[]), "«sol» 🌞");
+    }
+
+    #[test]
+    fn test_keyboard_layout() {
+        let normalizers = [QueryNormalizer::KeyboardLayout];
+        assert_eq!(normalize_query("ифктурфпу", &normalizers), "barnehage");
+        assert_eq!(normalize_query("ИФКТУ oslo", &normalizers), "barne oslo");
+        // Words with characters outside the layout are left alone
+        assert_eq!(normalize_query("привет1", &normalizers), "привет1");
+    }
+}
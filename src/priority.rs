@@ -0,0 +1,164 @@
+use axum::{body::Body, extract::State, http::Request, middleware::Next, response::Response};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::models::{RequestPoolStats, RequestQueues};
+use crate::AppState;
+
+/// Requests of one kind allowed to run at once; the rest wait their turn
+pub struct RequestPool {
+    semaphore: Arc<Semaphore>,
+    max_concurrent: usize,
+    waiting: AtomicUsize,
+}
+
+/// Counts a request as waiting until it gets its turn or gives up
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl RequestPool {
+    fn new(max_concurrent: usize) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_concurrent)),
+            max_concurrent,
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    /// Wait for a turn, which lasts until the permit is dropped
+    pub async fn acquire(&self) -> OwnedSemaphorePermit {
+        self.waiting.fetch_add(1, Ordering::Relaxed);
+        let _waiting = Waiting(&self.waiting);
+        self.semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("request pool semaphores are never closed")
+    }
+
+    pub fn stats(&self) -> RequestPoolStats {
+        RequestPoolStats {
+            max_concurrent: self.max_concurrent,
+            running: self.max_concurrent - self.semaphore.available_permits(),
+            waiting: self.waiting.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Searches and writes run in separate pools. Writes block a request thread while they
+/// wait for the index writers and commit, so capping them keeps threads free for
+/// searches during heavy imports.
+pub struct RequestPools {
+    /// Search, answer and suggest requests
+    pub search: RequestPool,
+    /// Document writes, crawls, feed pulls and watched directories
+    pub indexing: RequestPool,
+}
+
+impl RequestPools {
+    /// Pools sized by `SEARCH_CONCURRENCY` (default: twice the number of CPUs) and
+    /// `INDEXING_CONCURRENCY` (default: half the number of CPUs, at least 1)
+    pub fn from_env() -> Self {
+        fn var(name: &str) -> Option<usize> {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .filter(|n| *n > 0)
+        }
+
+        let cpus = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        Self {
+            search: RequestPool::new(var("SEARCH_CONCURRENCY").unwrap_or(cpus * 2)),
+            indexing: RequestPool::new(var("INDEXING_CONCURRENCY").unwrap_or((cpus / 2).max(1))),
+        }
+    }
+
+    pub fn stats(&self) -> RequestQueues {
+        RequestQueues {
+            search: self.search.stats(),
+            indexing: self.indexing.stats(),
+        }
+    }
+}
+
+/// Run a search request in its turn in the search pool
+pub async fn search_slot(
+    State(state): State<Arc<AppState>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let _permit = state.request_pools.search.acquire().await;
+    next.run(req).await
+}
+
+/// Run a write request in its turn in the indexing pool
+pub async fn indexing_slot(
+    State(state): State<Arc<AppState>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let _permit = state.request_pools.indexing.acquire().await;
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reports_running_and_waiting_requests() {
+        let pool = Arc::new(RequestPool::new(1));
+        let running = pool.acquire().await;
+
+        let waiter = tokio::spawn({
+            let pool = pool.clone();
+            async move {
+                drop(pool.acquire().await);
+            }
+        });
+        while pool.stats().waiting == 0 {
+            tokio::task::yield_now().await;
+        }
+        let stats = pool.stats();
+        assert_eq!((stats.running, stats.waiting), (1, 1));
+
+        drop(running);
+        waiter.await.unwrap();
+        let stats = pool.stats();
+        assert_eq!((stats.running, stats.waiting), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn full_indexing_pool_leaves_searches_running() {
+        let pools = RequestPools {
+            search: RequestPool::new(1),
+            indexing: RequestPool::new(1),
+        };
+        let _import = pools.indexing.acquire().await;
+
+        // A search gets its turn while writes are queued
+        let blocked_write = tokio::time::timeout(
+            std::time::Duration::from_millis(20),
+            pools.indexing.acquire(),
+        );
+        assert!(blocked_write.await.is_err());
+        let search = tokio::time::timeout(
+            std::time::Duration::from_millis(20),
+            pools.search.acquire(),
+        );
+        assert!(search.await.is_ok());
+
+        // A write that gave up waiting is no longer counted
+        let stats = pools.stats();
+        assert_eq!((stats.indexing.running, stats.indexing.waiting), (1, 0));
+        assert_eq!(stats.search.running, 0);
+    }
+}
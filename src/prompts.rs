@@ -0,0 +1,86 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::models::PromptTemplate;
+
+/// Placeholders that can be used in prompt templates
+pub const PLACEHOLDERS: &[&str] = &["query", "sources"];
+
+/// `{{name}}` placeholders, allowing spaces inside the braces
+static PLACEHOLDER_PATTERN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\{\{\s*(\w+)\s*\}\}").expect("Invalid regex pattern"));
+
+/// Check a template's name and placeholders. The user prompt must include both the
+/// question and the sources, since it replaces the built-in one.
+pub fn validate_template(template: &PromptTemplate) -> Result<(), String> {
+    if template.name.trim().is_empty() {
+        return Err("Template name cannot be empty".to_string());
+    }
+    if template.system.is_none() && template.user.is_none() {
+        return Err("Template needs a system or user prompt".to_string());
+    }
+
+    for prompt in [&template.system, &template.user].into_iter().flatten() {
+        for captures in PLACEHOLDER_PATTERN.captures_iter(prompt) {
+            if !PLACEHOLDERS.contains(&&captures[1]) {
+                return Err(format!(
+                    "Unknown placeholder '{}' (expected one of: {})",
+                    &captures[0],
+                    PLACEHOLDERS.join(", ")
+                ));
+            }
+        }
+    }
+    if let Some(user) = &template.user {
+        for name in PLACEHOLDERS {
+            if !uses_placeholder(user, name) {
+                return Err(format!("User prompt must include {{{{{}}}}}", name));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn uses_placeholder(prompt: &str, name: &str) -> bool {
+    PLACEHOLDER_PATTERN
+        .captures_iter(prompt)
+        .any(|captures| &captures[1] == name)
+}
+
+/// Fill in the `{{query}}` and `{{sources}}` placeholders of a prompt
+pub fn render(prompt: &str, query: &str, sources: &str) -> String {
+    PLACEHOLDER_PATTERN
+        .replace_all(prompt, |captures: &regex::Captures| match &captures[1] {
+            "query" => query.to_string(),
+            "sources" => sources.to_string(),
+            _ => captures[0].to_string(),
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(system: Option<&str>, user: Option<&str>) -> PromptTemplate {
+        PromptTemplate {
+            name: "support".to_string(),
+            system: system.map(str::to_string),
+            user: user.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_validate_and_render() {
+        assert!(validate_template(&template(Some("Be brief."), None)).is_ok());
+        assert!(validate_template(&template(None, Some("Q: {{query}}\n{{ sources }}"))).is_ok());
+        assert!(validate_template(&template(None, None)).is_err());
+        assert!(validate_template(&template(None, Some("Q: {{query}}"))).is_err());
+        assert!(validate_template(&template(Some("{{user}}"), None)).is_err());
+
+        assert_eq!(
+            render("Q: {{query}}\n{{ sources }}", "når åpner {{x}}", "[1] a"),
+            "Q: når åpner {{x}}\n[1] a"
+        );
+    }
+}
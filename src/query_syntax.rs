@@ -0,0 +1,290 @@
+/// Characters the query parser gives a meaning to, escaped with `\` to be searched as text
+const RESERVED_CHARS: &[char] = &[
+    '+', '-', '^', '`', ':', '{', '}', '"', '\'', '[', ']', '(', ')', '!', '\\', '~',
+];
+
+/// `*` and `?`, which simple queries leave out since the tokenizer drops them from words
+fn is_wildcard(c: char) -> bool {
+    matches!(c, '*' | '?')
+}
+
+/// A word as a term of the query parser, or `None` when it has nothing to search for
+fn word_clause(word: &str) -> Option<String> {
+    if !word.chars().any(char::is_alphanumeric) {
+        return None;
+    }
+    // Operators are searched as words
+    if matches!(word, "AND" | "OR" | "NOT" | "IN") {
+        return Some(word.to_lowercase());
+    }
+    let mut clause = String::with_capacity(word.len());
+    for c in word.chars().filter(|c| !is_wildcard(*c)) {
+        if RESERVED_CHARS.contains(&c) {
+            clause.push('\\');
+        }
+        clause.push(c);
+    }
+    Some(clause)
+}
+
+/// The text between quotes as a phrase, or `None` when it has nothing to search for
+fn phrase_clause(text: &str) -> Option<String> {
+    let cleaned: String = text
+        .chars()
+        .map(|c| if is_wildcard(c) || c == '\\' { ' ' } else { c })
+        .collect();
+    let words: Vec<&str> = cleaned.split_whitespace().collect();
+    if !words.iter().any(|word| word.chars().any(char::is_alphanumeric)) {
+        return None;
+    }
+    Some(format!("\"{}\"", words.join(" ")))
+}
+
+/// Number of wildcards in a query, not counting those escaped with `\`
+pub fn wildcards(query: &str) -> usize {
+    let mut count = 0;
+    let mut escaped = false;
+    for c in query.chars() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if is_wildcard(c) {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Characters of a term before its first wildcard, not counting its field name and query
+/// syntax; `None` for terms without wildcards
+pub fn literal_prefix(term: &str) -> Option<usize> {
+    let mut chars = 0;
+    let mut escaped = false;
+    for c in term.chars() {
+        if escaped {
+            escaped = false;
+            chars += 1;
+        } else if c == '\\' {
+            escaped = true;
+        } else if is_wildcard(c) {
+            return Some(chars);
+        } else if c == ':' {
+            // What came before was the field name
+            chars = 0;
+        } else if !RESERVED_CHARS.contains(&c) {
+            chars += 1;
+        }
+    }
+    None
+}
+
+/// Largest compiled size of a regex matched against index terms
+const MAX_REGEX_SIZE: usize = 1 << 20;
+
+/// A regex matching whole index terms, refused when it compiles to more than
+/// `MAX_REGEX_SIZE`
+pub fn term_regex(pattern: &str) -> Result<regex::Regex, regex::Error> {
+    regex::RegexBuilder::new(&format!("^(?:{})$", pattern))
+        .size_limit(MAX_REGEX_SIZE)
+        .dfa_size_limit(MAX_REGEX_SIZE)
+        .build()
+}
+
+/// Whether a regex has a `|` outside groups and classes, so its matches may start with
+/// either side
+fn has_top_level_alternation(pattern: &str) -> bool {
+    let mut depth = 0usize;
+    let mut in_class = false;
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '[' => in_class = true,
+            ']' => in_class = false,
+            '(' if !in_class => depth += 1,
+            ')' if !in_class => depth = depth.saturating_sub(1),
+            '|' if !in_class && depth == 0 => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Literal text every match of a regex starts with. Only the index terms starting with it
+/// have to be matched against the regex.
+pub fn regex_prefix(pattern: &str) -> String {
+    let mut prefix = String::new();
+    if has_top_level_alternation(pattern) {
+        return prefix;
+    }
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        let literal = match c {
+            // Escaped punctuation is literal, classes like `\d` are not
+            '\\' => match chars.next() {
+                Some(escaped) if escaped.is_ascii_punctuation() => escaped,
+                _ => break,
+            },
+            '.' | '[' | ']' | '(' | ')' | '{' | '}' | '|' | '^' | '$' | '*' | '+' | '?' => break,
+            c => c,
+        };
+        // A quantifier can leave out or repeat the character
+        match chars.peek() {
+            Some('+') => {
+                prefix.push(literal);
+                break;
+            }
+            Some('*' | '?' | '{') => break,
+            _ => prefix.push(literal),
+        }
+    }
+    prefix
+}
+
+/// Escape every character of `text` the query parser gives a meaning to, and lowercase
+/// operators so they are searched as words
+fn escape_words(text: &str, clauses: &mut Vec<String>) {
+    for word in text.split_whitespace() {
+        if matches!(word, "AND" | "OR" | "NOT" | "IN") {
+            clauses.push(word.to_lowercase());
+            continue;
+        }
+        let mut clause = String::with_capacity(word.len());
+        for c in word.chars() {
+            // `/` starts a regex in Lucene syntax, so it is escaped for queries passed on
+            if RESERVED_CHARS.contains(&c) || c == '/' || is_wildcard(c) {
+                clause.push('\\');
+            }
+            clause.push(c);
+        }
+        clauses.push(clause);
+    }
+}
+
+/// Escape query syntax so a query is searched as text, keeping its quoted phrases.
+/// Unlike [`simple_query`], `-` doesn't exclude, and an unbalanced quote is searched as
+/// a character.
+pub fn escape(input: &str) -> String {
+    let mut clauses: Vec<String> = Vec::new();
+    let mut rest = input;
+    while let Some(start) = rest.find('"') {
+        let Some(length) = rest[start + 1..].find('"') else {
+            break;
+        };
+        escape_words(&rest[..start], &mut clauses);
+        let phrase = &rest[start + 1..start + 1 + length];
+        if !phrase.trim().is_empty() {
+            let mut clause = String::from('"');
+            for c in phrase.chars() {
+                if c == '\\' || is_wildcard(c) {
+                    clause.push('\\');
+                }
+                clause.push(c);
+            }
+            clause.push('"');
+            clauses.push(clause);
+        }
+        rest = &rest[start + length + 2..];
+    }
+    escape_words(rest, &mut clauses);
+    clauses.join(" ")
+}
+
+/// Translate end-user input into a query the parser always accepts. Quoted text is
+/// searched as a phrase and a word or phrase starting with `-` is excluded; everything
+/// else is searched as plain words, with field names, operators, wildcards and brackets
+/// taken literally. Unbalanced quotes are ignored, and exclusions are dropped when
+/// nothing else is searched for, since the parser rejects queries of only exclusions.
+pub fn simple_query(input: &str) -> String {
+    let mut included: Vec<String> = Vec::new();
+    let mut excluded: Vec<String> = Vec::new();
+    let mut rest = input;
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+        let (negated, body) = match rest.strip_prefix('-') {
+            Some(body) if body.starts_with(|c: char| !c.is_whitespace()) => (true, body),
+            _ => (false, rest),
+        };
+        let clause = if let Some(inner) = body.strip_prefix('"') {
+            let Some(end) = inner.find('"') else {
+                // The quote is never closed, so its text is searched as words
+                rest = inner;
+                continue;
+            };
+            rest = &inner[end + 1..];
+            phrase_clause(&inner[..end])
+        } else {
+            let end = body.find(|c: char| c.is_whitespace() || c == '"').unwrap_or(body.len());
+            rest = &body[end..];
+            word_clause(&body[..end])
+        };
+        match clause {
+            Some(clause) if negated => excluded.push(format!("-{}", clause)),
+            Some(clause) => included.push(clause),
+            None => {}
+        }
+    }
+
+    if included.is_empty() {
+        return String::new();
+    }
+    included.extend(excluded);
+    included.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translates_phrases_and_exclusions() {
+        assert_eq!(
+            simple_query("\"red shoes\" -leather -\"high heels\""),
+            "\"red shoes\" -leather -\"high heels\""
+        );
+        assert_eq!(simple_query("shoes \"red"), "shoes red");
+        assert_eq!(simple_query("title:shoes (red AND blue"), "title\\:shoes \\(red and blue");
+        assert_eq!(simple_query("what is c++?"), "what is c\\+\\+");
+        assert_eq!(simple_query("e-mail - * \"\""), "e\\-mail");
+        assert_eq!(simple_query("-leather"), "");
+    }
+
+    #[test]
+    fn test_escape_keeps_phrases() {
+        assert_eq!(
+            escape("\"red shoes\" -leather title:x* (a OR b"),
+            "\"red shoes\" \\-leather title\\:x\\* \\(a or b"
+        );
+        assert_eq!(escape("5\" screen\\"), "5\\\" screen\\\\");
+        assert_eq!(escape("\"size 4*\" and/or"), "\"size 4\\*\" and\\/or");
+        assert_eq!(escape("  "), "");
+        assert_eq!(wildcards("a\\* b? c*"), 2);
+        assert_eq!(wildcards(&escape("sho* what?")), 0);
+    }
+
+    #[test]
+    fn test_literal_prefix() {
+        assert_eq!(literal_prefix("title:(*boots"), Some(0));
+        assert_eq!(literal_prefix("\"bo*"), Some(2));
+        assert_eq!(literal_prefix("c\\+\\+?"), Some(3));
+        assert_eq!(literal_prefix("boots"), None);
+    }
+
+    #[test]
+    fn test_regex_prefix() {
+        assert_eq!(regex_prefix("AB\\-12[0-9]+"), "AB-12");
+        assert_eq!(regex_prefix("abc?d"), "ab");
+        assert_eq!(regex_prefix("ab(c|d)"), "ab");
+        assert_eq!(regex_prefix("abc|xyz"), "");
+        assert_eq!(regex_prefix("\\d+"), "");
+        assert!(term_regex("ab.*").unwrap().is_match("abc"));
+        assert!(!term_regex("ab").unwrap().is_match("xabc"));
+        assert!(term_regex("a{1000}{1000}").is_err());
+    }
+}
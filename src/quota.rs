@@ -0,0 +1,191 @@
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use chrono::{DateTime, Datelike, Days, NaiveDate, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::models::AnswerUsage;
+
+/// Key shared by all requests without a bearer token
+pub const ANONYMOUS_KEY: &str = "anonymous";
+
+/// Per-key limits for the answer endpoint; `None` means unlimited
+pub struct AnswerBudget {
+    pub daily_quota: Option<u64>,
+    pub monthly_quota: Option<u64>,
+    pub max_concurrent: Option<usize>,
+    /// Answer requests currently running per key
+    in_flight: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl AnswerBudget {
+    /// Budget from `ANSWER_DAILY_QUOTA`, `ANSWER_MONTHLY_QUOTA` and `ANSWER_MAX_CONCURRENT`
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            std::env::var(name).ok().and_then(|v| v.trim().parse().ok())
+        }
+
+        Self {
+            daily_quota: var("ANSWER_DAILY_QUOTA"),
+            monthly_quota: var("ANSWER_MONTHLY_QUOTA"),
+            max_concurrent: var::<usize>("ANSWER_MAX_CONCURRENT").filter(|n| *n > 0),
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn has_quota(&self) -> bool {
+        self.daily_quota.is_some() || self.monthly_quota.is_some()
+    }
+
+    /// Reserve one of the key's concurrent request slots until the guard is dropped
+    pub fn try_acquire(&self, key: &str) -> Option<InFlightGuard> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let running = in_flight.entry(key.to_string()).or_insert(0);
+        if self.max_concurrent.is_some_and(|max| *running >= max) {
+            return None;
+        }
+        *running += 1;
+
+        Some(InFlightGuard {
+            in_flight: self.in_flight.clone(),
+            key: key.to_string(),
+        })
+    }
+}
+
+/// Concurrent request slot, released on drop
+pub struct InFlightGuard {
+    in_flight: Arc<Mutex<HashMap<String, usize>>>,
+    key: String,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if let Some(running) = in_flight.get_mut(&self.key) {
+            *running = running.saturating_sub(1);
+            if *running == 0 {
+                in_flight.remove(&self.key);
+            }
+        }
+    }
+}
+
+/// Identify the caller by a hash of its bearer token, so tokens are never stored
+pub fn api_key_id(headers: &HeaderMap) -> String {
+    headers
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(token_id)
+        .unwrap_or_else(|| ANONYMOUS_KEY.to_string())
+}
+
+/// Short hash identifying a token
+pub fn token_id(token: &str) -> String {
+    let digest = Sha256::digest(token.as_bytes());
+    digest[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Quota periods for the current day and month, e.g. ("day:2026-10-18", "month:2026-10")
+pub fn current_periods(now: DateTime<Utc>) -> (String, String) {
+    (
+        format!("day:{}", now.format("%Y-%m-%d")),
+        format!("month:{}", now.format("%Y-%m")),
+    )
+}
+
+/// Unix timestamps at which the daily and monthly quotas reset
+fn reset_times(now: DateTime<Utc>) -> (i64, i64) {
+    let today = now.date_naive();
+    let tomorrow = today + Days::new(1);
+    let next_month = if today.month() == 12 {
+        NaiveDate::from_ymd_opt(today.year() + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(today.year(), today.month() + 1, 1)
+    }
+    .unwrap_or(tomorrow);
+
+    let timestamp = |date: NaiveDate| date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp();
+    (timestamp(tomorrow), timestamp(next_month))
+}
+
+/// `X-RateLimit-*` headers for whichever quota is closest to running out
+pub fn rate_limit_headers(
+    budget: &AnswerBudget,
+    usage: &AnswerUsage,
+    now: DateTime<Utc>,
+) -> HeaderMap {
+    let (daily_reset, monthly_reset) = reset_times(now);
+    let windows = [
+        (budget.daily_quota, usage.daily_used, daily_reset),
+        (budget.monthly_quota, usage.monthly_used, monthly_reset),
+    ];
+
+    let mut headers = HeaderMap::new();
+    let tightest = windows
+        .iter()
+        .filter_map(|(limit, used, reset)| {
+            limit.map(|limit| (limit, limit.saturating_sub(*used), *reset))
+        })
+        .min_by_key(|(_, remaining, _)| *remaining);
+    if let Some((limit, remaining, reset)) = tightest {
+        for (name, value) in [
+            ("x-ratelimit-limit", limit as i64),
+            ("x-ratelimit-remaining", remaining as i64),
+            ("x-ratelimit-reset", reset),
+        ] {
+            headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+        }
+    }
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_periods_and_headers() {
+        let now = Utc.with_ymd_and_hms(2025, 12, 31, 15, 30, 0).unwrap();
+        assert_eq!(
+            current_periods(now),
+            ("day:2025-12-31".to_string(), "month:2025-12".to_string())
+        );
+
+        let budget = AnswerBudget {
+            daily_quota: Some(10),
+            monthly_quota: Some(100),
+            max_concurrent: None,
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+        };
+        // The monthly quota has fewer requests left, so it is reported
+        let usage = AnswerUsage {
+            allowed: true,
+            daily_used: 2,
+            monthly_used: 95,
+        };
+        let headers = rate_limit_headers(&budget, &usage, now);
+        assert_eq!(headers["x-ratelimit-limit"], "100");
+        assert_eq!(headers["x-ratelimit-remaining"], "5");
+        // Both quotas reset at midnight on New Year's Day
+        assert_eq!(headers["x-ratelimit-reset"], "1767225600");
+    }
+
+    #[test]
+    fn test_concurrency_slots() {
+        let budget = AnswerBudget {
+            daily_quota: None,
+            monthly_quota: None,
+            max_concurrent: Some(1),
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+        };
+        let guard = budget.try_acquire("a");
+        assert!(guard.is_some());
+        assert!(budget.try_acquire("a").is_none());
+        assert!(budget.try_acquire("b").is_some());
+        drop(guard);
+        assert!(budget.try_acquire("a").is_some());
+    }
+}
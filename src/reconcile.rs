@@ -0,0 +1,258 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use once_cell::sync::OnceCell;
+
+use crate::models::{RebuildStatus, SyncReport};
+use crate::AppState;
+
+/// Default interval between metadata syncs (seconds)
+const DEFAULT_SYNC_INTERVAL_SECS: u64 = 600;
+
+/// Read the sync interval from METADATA_SYNC_INTERVAL_SECS
+pub fn sync_interval_from_env() -> Duration {
+    let secs = std::env::var("METADATA_SYNC_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_SYNC_INTERVAL_SECS);
+    Duration::from_secs(secs)
+}
+
+/// Last sync report per index, shown in index stats
+#[derive(Default)]
+pub struct SyncReports {
+    reports: Mutex<HashMap<String, SyncReport>>,
+}
+
+impl SyncReports {
+    pub fn get(&self, index_name: &str) -> Option<SyncReport> {
+        self.reports.lock().ok()?.get(index_name).cloned()
+    }
+
+    fn record(&self, index_name: &str, report: &SyncReport) {
+        if let Ok(mut reports) = self.reports.lock() {
+            reports.insert(index_name.to_string(), report.clone());
+        }
+    }
+
+    pub fn remove(&self, index_name: &str) {
+        if let Ok(mut reports) = self.reports.lock() {
+            reports.remove(index_name);
+        }
+    }
+}
+
+/// Progress of the metadata rebuild run after startup
+pub struct RebuildProgress {
+    total: usize,
+    done: AtomicUsize,
+    current: Mutex<Option<String>>,
+    started: Instant,
+    took: OnceCell<Duration>,
+}
+
+impl RebuildProgress {
+    /// A rebuild of `total` indices that hasn't started yet
+    pub fn new(total: usize) -> Self {
+        Self {
+            total,
+            done: AtomicUsize::new(0),
+            current: Mutex::new(None),
+            started: Instant::now(),
+            took: OnceCell::new(),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.done.load(Ordering::Relaxed) >= self.total
+    }
+
+    pub fn status(&self) -> RebuildStatus {
+        RebuildStatus {
+            finished: self.is_finished(),
+            indices_total: self.total,
+            indices_done: self.done.load(Ordering::Relaxed).min(self.total),
+            current_index: self.current.lock().ok().and_then(|current| current.clone()),
+            elapsed_ms: self
+                .took
+                .get()
+                .copied()
+                .unwrap_or_else(|| self.started.elapsed())
+                .as_secs_f64()
+                * 1000.0,
+        }
+    }
+
+    fn set_current(&self, index_name: Option<&str>) {
+        if let Ok(mut current) = self.current.lock() {
+            *current = index_name.map(str::to_string);
+        }
+    }
+}
+
+/// IDs present in the index but not in the metadata store, and the reverse
+fn diff_ids(index_ids: &[String], metadata_ids: &[String]) -> (Vec<String>, Vec<String>) {
+    let in_index: HashSet<&String> = index_ids.iter().collect();
+    let in_metadata: HashSet<&String> = metadata_ids.iter().collect();
+
+    let missing = index_ids
+        .iter()
+        .filter(|id| !in_metadata.contains(id))
+        .cloned()
+        .collect();
+    let stale = metadata_ids
+        .iter()
+        .filter(|id| !in_index.contains(id))
+        .cloned()
+        .collect();
+    (missing, stale)
+}
+
+/// Compare an index's documents with its metadata rows and repair the rows.
+///
+/// Metadata is read before the index: writes update the index first, so a write racing
+/// the sync can only make a row look missing (re-inserting it is harmless), never stale.
+pub fn sync_index(state: &AppState, index_name: &str) -> anyhow::Result<SyncReport> {
+    let started = Instant::now();
+
+    let metadata_ids = state.metadata_store.document_ids(index_name)?;
+    let index_ids = state.search_engine.collect_document_ids(index_name)?;
+    let (missing, stale) = diff_ids(&index_ids, &metadata_ids);
+
+    if !missing.is_empty() || !stale.is_empty() {
+        state
+            .metadata_store
+            .repair_documents(index_name, &missing, &stale)?;
+    }
+
+    let report = SyncReport {
+        checked_at: Utc::now().to_rfc3339(),
+        index_document_count: index_ids.len() as u64,
+        metadata_document_count: metadata_ids.len() as u64,
+        missing: missing.len(),
+        stale: stale.len(),
+        took_ms: started.elapsed().as_secs_f64() * 1000.0,
+    };
+    state.metadata_sync.record(index_name, &report);
+    Ok(report)
+}
+
+/// Rebuild the metadata rows of the indices loaded at startup, one index at a time.
+///
+/// Runs while the service already serves traffic, so each index is synced rather than
+/// reset: a document written during the rebuild keeps its row.
+pub async fn rebuild_metadata(state: Arc<AppState>, index_names: Vec<String>) {
+    for index_name in index_names {
+        state.metadata_rebuild.set_current(Some(&index_name));
+
+        let task_state = state.clone();
+        let name = index_name.clone();
+        match tokio::task::spawn_blocking(move || sync_index(&task_state, &name)).await {
+            Ok(Ok(report)) => {
+                tracing::info!(
+                    "Rebuilt metadata for index '{}' with {} document(s)",
+                    index_name,
+                    report.index_document_count
+                );
+            }
+            Ok(Err(e)) => {
+                tracing::warn!(
+                    "Failed to rebuild metadata documents for index '{}': {}",
+                    index_name,
+                    e
+                );
+            }
+            Err(e) => {
+                tracing::warn!("Metadata rebuild for index '{}' panicked: {}", index_name, e);
+            }
+        }
+        state.metadata_rebuild.done.fetch_add(1, Ordering::Relaxed);
+    }
+
+    state.metadata_rebuild.set_current(None);
+    let _ = state.metadata_rebuild.took.set(state.metadata_rebuild.started.elapsed());
+    let status = state.metadata_rebuild.status();
+    tracing::info!(
+        "Metadata rebuild finished for {} index(es) in {:.0}ms",
+        status.indices_total,
+        status.elapsed_ms
+    );
+}
+
+/// Periodically sync the metadata store with every index
+pub async fn run_reconciler(state: Arc<AppState>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    // The startup rebuild covers the immediate first tick, so skip it
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+
+        for index_name in state.search_engine.open_indices() {
+            match sync_index(&state, &index_name) {
+                Ok(report) if report.missing > 0 || report.stale > 0 => {
+                    tracing::warn!(
+                        "Repaired metadata for index '{}': {} missing row(s), {} stale row(s)",
+                        index_name,
+                        report.missing,
+                        report.stale
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::warn!(
+                        "Failed to sync metadata for index '{}': {}",
+                        index_name,
+                        e
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_finds_missing_and_stale_ids() {
+        let (missing, stale) = diff_ids(&ids(&["a", "b", "c"]), &ids(&["b", "c", "d"]));
+        assert_eq!(missing, ids(&["a"]));
+        assert_eq!(stale, ids(&["d"]));
+    }
+
+    #[test]
+    fn test_matching_ids_need_no_repair() {
+        let (missing, stale) = diff_ids(&ids(&["a", "b"]), &ids(&["b", "a"]));
+        assert!(missing.is_empty());
+        assert!(stale.is_empty());
+    }
+
+    #[test]
+    fn test_rebuild_progress_reports_until_finished() {
+        assert!(RebuildProgress::new(0).status().finished);
+
+        let progress = RebuildProgress::new(2);
+        progress.set_current(Some("news"));
+        let status = progress.status();
+        assert!(!status.finished);
+        assert_eq!((status.indices_done, status.current_index.as_deref()), (0, Some("news")));
+
+        progress.done.fetch_add(2, Ordering::Relaxed);
+        progress.set_current(None);
+        progress.took.set(Duration::from_millis(5)).unwrap();
+        let status = progress.status();
+        assert!(status.finished);
+        assert_eq!((status.indices_done, status.current_index), (2, None));
+        assert_eq!(status.elapsed_ms, 5.0);
+    }
+}
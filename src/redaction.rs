@@ -0,0 +1,212 @@
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::llm::ChatMessage;
+
+/// Built-in patterns, applied in this order so national IDs aren't taken for phone numbers
+const BUILT_IN_PATTERNS: &[(&str, &str)] = &[
+    ("email", r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}"),
+    // Norwegian national identity numbers (11 digits) and US social security numbers
+    ("national_id", r"\b(?:\d{6} ?\d{5}|\d{3}-\d{2}-\d{4})\b"),
+    (
+        "phone",
+        r"(?:\+\d{1,3}[ -]?)?\b\d{2,4}(?:[ -]?\d{2,4}){2,4}\b",
+    ),
+];
+
+/// Longest placeholder held back while streaming, e.g. `[NATIONAL_ID_12]`
+const MAX_PLACEHOLDER_CHARS: usize = 48;
+
+/// Kind of personal data and the pattern that finds it
+struct Rule {
+    kind: String,
+    pattern: Regex,
+}
+
+/// Replaces personal data with placeholders before text is sent to the LLM
+#[derive(Clone)]
+pub struct Redactor {
+    rules: Arc<Vec<Rule>>,
+}
+
+impl Redactor {
+    /// Redactor from `PII_REDACTION` (comma-separated built-in kinds: `email`,
+    /// `phone`, `national_id`, or `all`) and `PII_PATTERNS` (JSON object of custom
+    /// kind -> regex)
+    pub fn from_env() -> Self {
+        let built_in = std::env::var("PII_REDACTION").unwrap_or_default();
+        let custom = std::env::var("PII_PATTERNS").unwrap_or_default();
+        Self::new(&built_in, &custom)
+    }
+
+    fn new(built_in: &str, custom: &str) -> Self {
+        let enabled: Vec<String> = built_in
+            .split(',')
+            .map(|kind| kind.trim().to_lowercase())
+            .filter(|kind| !kind.is_empty())
+            .collect();
+        for kind in &enabled {
+            if kind != "all" && !BUILT_IN_PATTERNS.iter().any(|(name, _)| name == kind) {
+                tracing::warn!("Ignoring unknown PII_REDACTION kind '{}'", kind);
+            }
+        }
+
+        let mut rules: Vec<Rule> = BUILT_IN_PATTERNS
+            .iter()
+            .filter(|(kind, _)| enabled.iter().any(|e| e == "all" || e == kind))
+            .map(|(kind, pattern)| Rule {
+                kind: kind.to_string(),
+                pattern: Regex::new(pattern).expect("Invalid regex pattern"),
+            })
+            .collect();
+
+        if !custom.trim().is_empty() {
+            match serde_json::from_str::<HashMap<String, String>>(custom) {
+                Ok(patterns) => {
+                    let mut patterns: Vec<(String, String)> = patterns.into_iter().collect();
+                    patterns.sort();
+                    for (kind, pattern) in patterns {
+                        match Regex::new(&pattern) {
+                            Ok(pattern) => rules.push(Rule { kind, pattern }),
+                            Err(e) => tracing::warn!("Ignoring PII pattern '{}': {}", kind, e),
+                        }
+                    }
+                }
+                Err(e) => tracing::warn!("Ignoring PII_PATTERNS: {}", e),
+            }
+        }
+
+        Self {
+            rules: Arc::new(rules),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.rules.is_empty()
+    }
+
+    /// Start redacting the text of one request
+    pub fn session(&self) -> Redactions {
+        Redactions {
+            rules: self.rules.clone(),
+            values: Vec::new(),
+        }
+    }
+}
+
+/// Placeholders handed out during one request. The same value always gets the same
+/// placeholder, so the model can still tell values apart.
+pub struct Redactions {
+    rules: Arc<Vec<Rule>>,
+    /// (placeholder, original value)
+    values: Vec<(String, String)>,
+}
+
+impl Redactions {
+    /// Replace personal data in a text with placeholders like `[EMAIL_1]`
+    pub fn redact(&mut self, text: &str) -> String {
+        let mut text = text.to_string();
+        let rules = self.rules.clone();
+        for rule in rules.iter() {
+            if !rule.pattern.is_match(&text) {
+                continue;
+            }
+            let mut redacted = String::with_capacity(text.len());
+            let mut last = 0;
+            for found in rule.pattern.find_iter(&text) {
+                redacted.push_str(&text[last..found.start()]);
+                redacted.push_str(&self.placeholder(&rule.kind, found.as_str()));
+                last = found.end();
+            }
+            redacted.push_str(&text[last..]);
+            text = redacted;
+        }
+        text
+    }
+
+    fn placeholder(&mut self, kind: &str, value: &str) -> String {
+        if let Some((placeholder, _)) = self.values.iter().find(|(_, v)| v == value) {
+            return placeholder.clone();
+        }
+        let prefix = format!("[{}_", kind.to_uppercase());
+        let number = self
+            .values
+            .iter()
+            .filter(|(p, _)| p.starts_with(&prefix))
+            .count()
+            + 1;
+        let placeholder = format!("{}{}]", prefix, number);
+        self.values.push((placeholder.clone(), value.to_string()));
+        placeholder
+    }
+
+    /// Redact the content of every message
+    pub fn redact_messages(&mut self, messages: &mut [ChatMessage]) {
+        for message in messages {
+            message.content = self.redact(&message.content);
+        }
+    }
+
+    /// Put the original values back in place of their placeholders
+    pub fn restore(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for (placeholder, value) in &self.values {
+            if text.contains(placeholder.as_str()) {
+                text = text.replace(placeholder.as_str(), value);
+            }
+        }
+        text
+    }
+
+    /// Restore streamed text chunk by chunk. A trailing `[` that could start a
+    /// placeholder is kept in `pending` until the next chunk completes it; call
+    /// with an empty chunk and `finish` set to flush the rest.
+    pub fn restore_chunk(&self, pending: &mut String, chunk: &str, finish: bool) -> String {
+        pending.push_str(chunk);
+        let split = match pending.rfind('[') {
+            Some(open)
+                if !finish
+                    && !pending[open..].contains(']')
+                    && pending[open..].chars().count() < MAX_PLACEHOLDER_CHARS =>
+            {
+                open
+            }
+            _ => pending.len(),
+        };
+        let ready: String = pending.drain(..split).collect();
+        self.restore(&ready)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_and_restore() {
+        let redactor = Redactor::new("all", r#"{"employee_id": "EMP-\\d{4}"}"#);
+        let mut redactions = redactor.session();
+
+        let text = "Kari (kari@example.no, 912 34 567, 01019012345) is EMP-1234. \
+                    Mail kari@example.no or ola@example.no.";
+        let redacted = redactions.redact(text);
+        assert_eq!(
+            redacted,
+            "Kari ([EMAIL_1], [PHONE_1], [NATIONAL_ID_1]) is [EMPLOYEE_ID_1]. \
+             Mail [EMAIL_1] or [EMAIL_2]."
+        );
+        assert_eq!(redactions.restore(&redacted), text);
+
+        // Placeholders split across streamed chunks are restored once complete
+        let mut pending = String::new();
+        let mut streamed = String::new();
+        for chunk in ["Contact [EMA", "IL_2] at [", "PHONE_1]. [1]", " [x"] {
+            streamed.push_str(&redactions.restore_chunk(&mut pending, chunk, false));
+        }
+        streamed.push_str(&redactions.restore_chunk(&mut pending, "", true));
+        assert_eq!(streamed, "Contact ola@example.no at 912 34 567. [1] [x");
+
+        assert!(!Redactor::new("", "").is_enabled());
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::models::{CloseTerm, SynonymGroup, TermCorrection, ZeroResultCluster};
+
+/// Index terms suggested for each word of a zero-result query that isn't in the index
+const MAX_CLOSE_TERMS: usize = 3;
+
+/// Words shorter than this are left out of corrections: they are rarely misspelt, and
+/// sampled index terms leave them out too
+const MIN_WORD_CHARS: usize = 3;
+
+/// Edits a word can be away from an index term suggested for it, growing with its length
+/// so short words don't match unrelated terms
+fn max_distance(word: &str) -> usize {
+    match word.chars().count() {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// Number of single-character insertions, deletions and substitutions turning `a` into `b`
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Words of a query, lowercased, without operators and punctuation. The query log
+/// lowercases queries, so `and`, `or` and `not` are taken for operators.
+fn words(query: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    for word in query.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()) {
+        let word = word.to_lowercase();
+        if !["and", "or", "not"].contains(&word.as_str()) && !words.contains(&word) {
+            words.push(word);
+        }
+    }
+    words
+}
+
+/// Index terms within reach of a word, closest and then most common first
+fn close_terms(word: &str, vocabulary: &HashMap<String, u64>) -> Vec<CloseTerm> {
+    let max = max_distance(word);
+    let length = word.chars().count();
+    let mut terms: Vec<CloseTerm> = vocabulary
+        .iter()
+        .filter(|(term, _)| term.chars().count().abs_diff(length) <= max)
+        .filter_map(|(term, doc_freq)| {
+            let distance = edit_distance(word, term);
+            (distance <= max).then(|| CloseTerm {
+                term: term.clone(),
+                doc_freq: *doc_freq,
+                distance,
+            })
+        })
+        .collect();
+    terms.sort_by(|a, b| {
+        a.distance
+            .cmp(&b.distance)
+            .then_with(|| b.doc_freq.cmp(&a.doc_freq))
+            .then_with(|| a.term.cmp(&b.term))
+    });
+    terms.truncate(MAX_CLOSE_TERMS);
+    terms
+}
+
+/// Group zero-result queries that are variants of each other: the same words in another
+/// order, or a few edits apart. Queries come most searched first, and the first query of
+/// a group names it. For the words of a group that aren't in the index, the closest index
+/// terms are suggested, along with synonym groups joining each word to the closest term
+/// that existing synonyms don't already cover. Words with no close term are reported as
+/// missing content.
+pub fn cluster(
+    queries: &[(String, u64)],
+    vocabulary: &HashMap<String, u64>,
+    existing: &[SynonymGroup],
+) -> Vec<ZeroResultCluster> {
+    // Each group with the sorted words of its first query, to compare variants with
+    let mut groups: Vec<(String, ZeroResultCluster)> = Vec::new();
+    for (query, count) in queries {
+        let mut key = words(query);
+        key.sort();
+        let key = key.join(" ");
+        let group = groups.iter_mut().find(|(group_key, _)| {
+            *group_key == key || edit_distance(group_key, &key) <= max_distance(&key)
+        });
+        match group {
+            Some((_, cluster)) => {
+                cluster.queries.push(query.clone());
+                cluster.zero_result_count += count;
+            }
+            None => groups.push((
+                key,
+                ZeroResultCluster {
+                    query: query.clone(),
+                    queries: vec![query.clone()],
+                    zero_result_count: *count,
+                    corrections: Vec::new(),
+                    synonyms: Vec::new(),
+                    missing_words: Vec::new(),
+                },
+            )),
+        }
+    }
+
+    let known: Vec<HashSet<String>> = existing
+        .iter()
+        .map(|group| group.terms.iter().map(|term| term.to_lowercase()).collect())
+        .collect();
+    let mut clusters: Vec<ZeroResultCluster> = groups.into_iter().map(|(_, c)| c).collect();
+    for cluster in &mut clusters {
+        let mut seen: HashSet<String> = HashSet::new();
+        for word in cluster.queries.iter().flat_map(|query| words(query)) {
+            if word.chars().count() < MIN_WORD_CHARS
+                || vocabulary.contains_key(&word)
+                || !seen.insert(word.clone())
+            {
+                continue;
+            }
+            let terms = close_terms(&word, vocabulary);
+            let Some(closest) = terms.first() else {
+                cluster.missing_words.push(word);
+                continue;
+            };
+            let covered = known
+                .iter()
+                .any(|group| group.contains(&word) && group.contains(&closest.term));
+            if !covered {
+                cluster.synonyms.push(SynonymGroup {
+                    terms: vec![word.clone(), closest.term.clone()],
+                });
+            }
+            cluster.corrections.push(TermCorrection { word, terms });
+        }
+    }
+    clusters.sort_by_key(|cluster| std::cmp::Reverse(cluster.zero_result_count));
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clusters_variants_and_suggests_terms() {
+        assert_eq!(edit_distance("barnehage", "barnhage"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+
+        let queries = [
+            ("barnhage bergen".to_string(), 5),
+            ("bergen barnhage".to_string(), 3),
+            ("barnehag bergen".to_string(), 2),
+            ("svømmehall".to_string(), 4),
+        ];
+        let vocabulary = HashMap::from([
+            ("barnehage".to_string(), 40),
+            ("bergen".to_string(), 12),
+            ("barnehager".to_string(), 3),
+        ]);
+        let existing = vec![SynonymGroup {
+            terms: vec!["barnehag".to_string(), "barnehage".to_string()],
+        }];
+
+        let clusters = cluster(&queries, &vocabulary, &existing);
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].query, "barnhage bergen");
+        assert_eq!(clusters[0].zero_result_count, 10);
+        assert_eq!(clusters[0].queries.len(), 3);
+
+        let corrections: Vec<(&str, Vec<&str>)> = clusters[0]
+            .corrections
+            .iter()
+            .map(|c| (c.word.as_str(), c.terms.iter().map(|t| t.term.as_str()).collect()))
+            .collect();
+        assert_eq!(
+            corrections,
+            vec![
+                ("barnhage", vec!["barnehage", "barnehager"]),
+                ("barnehag", vec!["barnehage", "barnehager"]),
+            ]
+        );
+        // "barnehag" is covered by the existing group
+        assert_eq!(clusters[0].synonyms.len(), 1);
+        assert_eq!(clusters[0].synonyms[0].terms, vec!["barnhage", "barnehage"]);
+
+        assert_eq!(clusters[1].missing_words, vec!["svømmehall"]);
+    }
+}
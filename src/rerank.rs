@@ -0,0 +1,93 @@
+use crate::llm::ChatMessage;
+use crate::models::SearchHit;
+
+/// Instruction for ordering documents by relevance
+const RERANK_PROMPT: &str = "You rank search results. Order the numbered documents by how \
+well they answer the query, most relevant first. Reply with only a JSON array of document \
+numbers, e.g. [3, 1, 2].";
+
+/// Characters of each document's fields included in the prompt
+const MAX_DOCUMENT_CHARS: usize = 1000;
+
+/// Messages asking the model to order hits by relevance to a query
+pub fn rerank_messages(query: &str, hits: &[SearchHit]) -> Vec<ChatMessage> {
+    let documents: Vec<String> = hits
+        .iter()
+        .enumerate()
+        .map(|(idx, hit)| {
+            let fields = serde_json::to_string(&hit.fields).unwrap_or_default();
+            let fields: String = fields.chars().take(MAX_DOCUMENT_CHARS).collect();
+            format!("[{}] {}", idx + 1, fields)
+        })
+        .collect();
+
+    vec![
+        ChatMessage {
+            role: "system".to_string(),
+            content: RERANK_PROMPT.to_string(),
+        },
+        ChatMessage {
+            role: "user".to_string(),
+            content: format!("Query: {}\n\nDocuments:\n{}", query, documents.join("\n")),
+        },
+    ]
+}
+
+/// Read the model's ranking of `count` documents as zero-based positions. Unknown and
+/// repeated numbers are skipped, and documents the model left out keep their order
+/// after the ranked ones. Returns `None` when the reply contains no ranking.
+pub fn parse_ranking(reply: &str, count: usize) -> Option<Vec<usize>> {
+    let start = reply.find('[')?;
+    let end = start + reply[start..].find(']')?;
+    let numbers: Vec<serde_json::Value> = serde_json::from_str(&reply[start..=end]).ok()?;
+
+    let mut order = Vec::with_capacity(count);
+    for number in numbers {
+        let position = match number {
+            serde_json::Value::Number(n) => n.as_u64(),
+            serde_json::Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        };
+        let Some(position) = position.and_then(|n| (n as usize).checked_sub(1)) else {
+            continue;
+        };
+        if position < count && !order.contains(&position) {
+            order.push(position);
+        }
+    }
+    if order.is_empty() {
+        return None;
+    }
+
+    for position in 0..count {
+        if !order.contains(&position) {
+            order.push(position);
+        }
+    }
+    Some(order)
+}
+
+/// Reorder the first `order.len()` items by the given positions
+pub fn apply_ranking<T>(items: &mut Vec<T>, order: &[usize]) {
+    let rest = items.split_off(order.len());
+    let mut ranked: Vec<Option<T>> = items.drain(..).map(Some).collect();
+    items.extend(order.iter().filter_map(|&position| ranked[position].take()));
+    items.extend(rest);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_apply_ranking() {
+        let order = parse_ranking("Ranking: [3, \"1\", 3, 9]", 4).unwrap();
+        assert_eq!(order, vec![2, 0, 1, 3]);
+        assert!(parse_ranking("[]", 4).is_none());
+        assert!(parse_ranking("no idea", 4).is_none());
+
+        let mut items = vec!["a", "b", "c", "d", "e"];
+        apply_ranking(&mut items, &order);
+        assert_eq!(items, vec!["c", "a", "b", "d", "e"]);
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::llm::ChatMessage;
+use crate::models::{FieldConfig, RewrittenQuery};
+
+/// Instruction for turning a question into a keyword query
+const REWRITE_PROMPT: &str = "Rewrite the user's question into a search query. Reply with \
+only a JSON object: {\"query\": \"keywords\", \"filters\": [{\"field\": \"name\", \"value\": \
+\"exact value\"}]}. Keep the important keywords in the question's language, including \
+synonyms that improve recall, and leave out question words and filler. Only add a filter \
+when the question names an exact value for one of the listed fields.";
+
+/// Rewritten queries per (index, question), so repeated questions skip the LLM call
+pub struct QueryRewriteCache {
+    capacity: usize,
+    entries: Mutex<HashMap<(String, String), RewrittenQuery>>,
+}
+
+impl QueryRewriteCache {
+    /// Cache holding up to `QUERY_REWRITE_CACHE_SIZE` rewrites (default: 1000, 0 disables)
+    pub fn from_env() -> Self {
+        let capacity = std::env::var("QUERY_REWRITE_CACHE_SIZE")
+            .ok()
+            .and_then(|v| v.trim().parse::<usize>().ok())
+            .unwrap_or(1000);
+        Self {
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, index_name: &str, question: &str) -> Option<RewrittenQuery> {
+        let key = (index_name.to_string(), question.trim().to_string());
+        self.entries.lock().unwrap().get(&key).cloned()
+    }
+
+    pub fn insert(&self, index_name: &str, question: &str, rewritten: RewrittenQuery) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        // Start over when full rather than tracking usage; rewrites are cheap to redo
+        if entries.len() >= self.capacity {
+            entries.clear();
+        }
+        entries.insert(
+            (index_name.to_string(), question.trim().to_string()),
+            rewritten,
+        );
+    }
+
+    /// Drop the cached rewrites of an index, e.g. when it is deleted
+    pub fn invalidate(&self, index_name: &str) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|(index, _), _| index != index_name);
+    }
+}
+
+/// Fields a rewrite may filter on: indexed text and string fields
+fn filterable(field: &FieldConfig) -> bool {
+    field.indexed && matches!(field.field_type.as_str(), "text" | "string")
+}
+
+/// Messages asking the model to rewrite a question for an index with the given fields
+pub fn rewrite_messages(question: &str, fields: &[FieldConfig]) -> Vec<ChatMessage> {
+    let field_names: Vec<&str> = fields
+        .iter()
+        .filter(|field| filterable(field))
+        .map(|field| field.name.as_str())
+        .collect();
+
+    vec![
+        ChatMessage {
+            role: "system".to_string(),
+            content: REWRITE_PROMPT.to_string(),
+        },
+        ChatMessage {
+            role: "user".to_string(),
+            content: format!(
+                "Fields: {}\n\nQuestion: {}",
+                field_names.join(", "),
+                question
+            ),
+        },
+    ]
+}
+
+/// Read the model's reply, ignoring filters on fields the index can't filter on.
+/// Returns `None` when the reply isn't a usable rewrite.
+pub fn parse_rewrite(reply: &str, fields: &[FieldConfig]) -> Option<RewrittenQuery> {
+    // Models often wrap the JSON in a code fence or a sentence
+    let start = reply.find('{')?;
+    let end = reply.rfind('}')?;
+    let mut rewritten: RewrittenQuery = serde_json::from_str(reply.get(start..=end)?).ok()?;
+
+    rewritten.query = keywords(&rewritten.query);
+    rewritten.filters.retain(|filter| {
+        !filter.value.trim().is_empty()
+            && fields
+                .iter()
+                .any(|field| field.name == filter.field && filterable(field))
+    });
+
+    if rewritten.query.is_empty() && rewritten.filters.is_empty() {
+        None
+    } else {
+        Some(rewritten)
+    }
+}
+
+/// Words of a query with query syntax removed, so model output can't fail to parse
+fn keywords(query: &str) -> String {
+    query
+        .split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+        .filter(|word| !word.is_empty() && !matches!(*word, "AND" | "OR" | "NOT" | "TO"))
+        .map(|word| word.trim_start_matches('-'))
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Query string for a rewrite: the keywords, with each filter required as a phrase
+pub fn to_query_string(rewritten: &RewrittenQuery) -> String {
+    let mut clauses = Vec::new();
+    if !rewritten.query.is_empty() {
+        clauses.push(format!("({})", rewritten.query));
+    }
+    for filter in &rewritten.filters {
+        let value: String = filter
+            .value
+            .chars()
+            .filter(|c| *c != '"' && *c != '\\')
+            .collect();
+        clauses.push(format!("{}:\"{}\"", filter.field, value.trim()));
+    }
+    clauses.join(" AND ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::FieldFilter;
+
+    fn field(name: &str, field_type: &str) -> FieldConfig {
+        FieldConfig {
+            name: name.to_string(),
+            field_type: field_type.to_string(),
+            stored: true,
+            indexed: true,
+            analyzer: "default".to_string(),
+            fast: false,
+            languages: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_rewrite() {
+        let fields = vec![
+            field("title", "text"),
+            field("city", "string"),
+            field("age", "i64"),
+        ];
+        let reply = "```json\n{\"query\": \"familievennlig barnehage:\", \"filters\": [\
+            {\"field\": \"city\", \"value\": \"Bergen\"}, \
+            {\"field\": \"age\", \"value\": \"3\"}, \
+            {\"field\": \"owner\", \"value\": \"x\"}]}\n```";
+        let rewritten = parse_rewrite(reply, &fields).unwrap();
+        assert_eq!(
+            rewritten,
+            RewrittenQuery {
+                query: "familievennlig barnehage".to_string(),
+                filters: vec![FieldFilter {
+                    field: "city".to_string(),
+                    value: "Bergen".to_string(),
+                }],
+            }
+        );
+        assert_eq!(
+            to_query_string(&rewritten),
+            "(familievennlig barnehage) AND city:\"Bergen\""
+        );
+
+        assert!(parse_rewrite("I can't help with that", &fields).is_none());
+        assert!(parse_rewrite("{\"query\": \"AND ()\"}", &fields).is_none());
+    }
+
+    #[test]
+    fn test_cache_is_bounded() {
+        let cache = QueryRewriteCache {
+            capacity: 2,
+            entries: Mutex::new(HashMap::new()),
+        };
+        let rewritten = RewrittenQuery {
+            query: "sol".to_string(),
+            filters: Vec::new(),
+        };
+        cache.insert("a", "q1 ", rewritten.clone());
+        cache.insert("b", "q1", rewritten.clone());
+        assert_eq!(cache.get("a", "q1"), Some(rewritten.clone()));
+
+        cache.invalidate("a");
+        assert!(cache.get("a", "q1").is_none());
+
+        cache.insert("a", "q2", rewritten.clone());
+        cache.insert("a", "q3", rewritten.clone());
+        assert!(cache.get("b", "q1").is_none());
+        assert!(cache.get("a", "q3").is_some());
+    }
+}
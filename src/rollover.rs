@@ -0,0 +1,295 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+
+use crate::error::SearchError;
+use crate::models::{
+    RetentionPolicy, RolloverAlias, RolloverConditions, RolloverGeneration, RolloverPolicy,
+    RolloverRequest, RolloverResponse,
+};
+use crate::AppState;
+
+/// Digits of the generation number appended to a rolling index's name
+const GENERATION_DIGITS: usize = 6;
+
+/// Name of a generation of a rolling index, e.g. `logs-000002`
+pub fn generation_name(alias: &str, generation: u64) -> String {
+    format!("{}-{:0width$}", alias, generation, width = GENERATION_DIGITS)
+}
+
+/// Generation number of an index named by `generation_name`
+fn generation_number(alias: &str, index_name: &str) -> Option<u64> {
+    index_name.strip_prefix(alias)?.strip_prefix('-')?.parse().ok()
+}
+
+/// Name of the generation following the write index of a rolling index
+pub fn next_generation_name(alias: &str, rolling: &RolloverAlias) -> String {
+    let generation = generation_number(alias, rolling.write_index())
+        .unwrap_or(rolling.generations.len() as u64)
+        + 1;
+    generation_name(alias, generation)
+}
+
+/// Whole seconds from an RFC3339 time until `now`; 0 for times that don't parse
+fn age_secs(since: &str, now: DateTime<Utc>) -> u64 {
+    DateTime::parse_from_rfc3339(since)
+        .map(|since| (now - since.with_timezone(&Utc)).num_seconds().max(0) as u64)
+        .unwrap_or(0)
+}
+
+/// Whether each condition is met by a write index of this age and document count
+fn check_conditions(
+    conditions: &RolloverConditions,
+    age_secs: u64,
+    documents: u64,
+) -> HashMap<String, bool> {
+    let mut met = HashMap::new();
+    if let Some(max_age_secs) = conditions.max_age_secs {
+        met.insert("max_age_secs".to_string(), age_secs >= max_age_secs);
+    }
+    if let Some(max_docs) = conditions.max_docs {
+        met.insert("max_docs".to_string(), documents >= max_docs);
+    }
+    met
+}
+
+/// Old generations the retention policy no longer keeps: those beyond `max_generations`,
+/// and those rolled over (succeeded by the next generation) more than `max_age_secs` ago.
+/// The write index is always kept.
+fn expired_generations(
+    generations: &[RolloverGeneration],
+    retention: &RetentionPolicy,
+    now: DateTime<Utc>,
+) -> Vec<String> {
+    let excess = retention
+        .max_generations
+        .map(|max| generations.len().saturating_sub(max.max(1)))
+        .unwrap_or(0);
+    generations
+        .windows(2)
+        .enumerate()
+        .filter(|(position, pair)| {
+            *position < excess
+                || retention
+                    .max_age_secs
+                    .is_some_and(|max_age| age_secs(&pair[1].created_at, now) >= max_age)
+        })
+        .map(|(_, pair)| pair[0].index.clone())
+        .collect()
+}
+
+/// Record a newly created index in the metadata store, removing the index again if
+/// that fails
+pub fn record_index(state: &AppState, index_name: &str) -> Result<(), SearchError> {
+    let recorded = state.metadata_store.create_index(index_name);
+    if matches!(recorded, Ok(true)) {
+        return Ok(());
+    }
+    if let Err(e) = state.search_engine.delete_index(index_name) {
+        tracing::warn!("Failed to roll back index '{}': {}", index_name, e);
+    }
+    Err(match recorded {
+        Err(e) => SearchError::Internal(e),
+        _ => SearchError::IndexExists(format!("{} (in metadata)", index_name)),
+    })
+}
+
+/// Delete a generation with its metadata rows
+pub fn delete_generation(state: &AppState, index_name: &str) -> Result<(), SearchError> {
+    state.search_engine.delete_index(index_name)?;
+    state.metadata_store.delete_index(index_name)?;
+    state.query_rewrites.invalidate(index_name);
+    state.answer_cache.invalidate(index_name);
+    state.metadata_sync.remove(index_name);
+    Ok(())
+}
+
+/// Make `alias` a rolling index over `first_generation`, an index just created for it
+pub fn create_alias(
+    state: &AppState,
+    alias: &str,
+    first_generation: &str,
+    policy: RolloverPolicy,
+) -> Result<(), SearchError> {
+    let rolling = RolloverAlias {
+        policy,
+        generations: vec![RolloverGeneration {
+            index: first_generation.to_string(),
+            created_at: Utc::now().to_rfc3339(),
+        }],
+    };
+    state.search_engine.set_rollover_alias(alias, rolling)
+}
+
+/// Delete a rolling index: its alias and every generation
+pub fn delete_alias(state: &AppState, alias: &str) -> Result<(), SearchError> {
+    let rolling = state
+        .search_engine
+        .rollover_alias(alias)
+        .ok_or_else(|| SearchError::IndexNotFound(alias.to_string()))?;
+    state.search_engine.remove_rollover_alias(alias)?;
+    for generation in &rolling.generations {
+        delete_generation(state, &generation.index)?;
+    }
+    Ok(())
+}
+
+/// Start a new generation of a rolling index when one of the conditions is met (always,
+/// without conditions), then delete the old generations its retention policy no longer
+/// keeps. The new generation has the fields, settings and shard count of the previous one.
+pub fn rollover(
+    state: &AppState,
+    alias: &str,
+    request: &RolloverRequest,
+) -> Result<RolloverResponse, SearchError> {
+    let engine = &state.search_engine;
+    let mut rolling = engine
+        .rollover_alias(alias)
+        .ok_or_else(|| SearchError::IndexNotFound(alias.to_string()))?;
+    if state.migrations.is_running(alias) {
+        return Err(SearchError::Conflict(format!(
+            "Index '{}' is being migrated",
+            alias
+        )));
+    }
+    let old_index = rolling.write_index().to_string();
+    let now = Utc::now();
+
+    let conditions = request
+        .conditions
+        .as_ref()
+        .unwrap_or(&rolling.policy.conditions);
+    let created_at = rolling
+        .generations
+        .last()
+        .map(|generation| generation.created_at.as_str())
+        .unwrap_or_default();
+    let documents = engine.get_document_count(&old_index)?;
+    let met = check_conditions(conditions, age_secs(created_at, now), documents);
+    let rolled_over = conditions.is_empty() || met.values().any(|met| *met);
+
+    let mut response = RolloverResponse {
+        alias: alias.to_string(),
+        old_index: old_index.clone(),
+        new_index: old_index.clone(),
+        rolled_over,
+        dry_run: request.dry_run,
+        conditions: met,
+        deleted_indices: Vec::new(),
+    };
+    if request.dry_run {
+        return Ok(response);
+    }
+
+    if rolled_over {
+        let not_found = || SearchError::IndexNotFound(old_index.clone());
+        let fields = engine.index_fields(&old_index).ok_or_else(not_found)?;
+        let settings = engine.index_settings(&old_index).ok_or_else(not_found)?;
+        let shards = engine.index_shard_count(&old_index).ok_or_else(not_found)?;
+
+        let new_index = next_generation_name(alias, &rolling);
+        engine.create_index(&new_index, &fields, &settings, shards)?;
+        record_index(state, &new_index)?;
+
+        rolling.generations.push(RolloverGeneration {
+            index: new_index.clone(),
+            created_at: now.to_rfc3339(),
+        });
+        response.new_index = new_index;
+        tracing::info!(
+            "Rolled index '{}' over from '{}' to '{}'",
+            alias,
+            old_index,
+            response.new_index
+        );
+    }
+
+    // Generations that fail to delete stay in the alias and are retried next time
+    for index_name in expired_generations(&rolling.generations, &rolling.policy.retention, now) {
+        match delete_generation(state, &index_name) {
+            Ok(()) => response.deleted_indices.push(index_name),
+            Err(e) => tracing::warn!("Failed to delete old generation '{}': {}", index_name, e),
+        }
+    }
+    rolling
+        .generations
+        .retain(|generation| !response.deleted_indices.contains(&generation.index));
+    engine.set_rollover_alias(alias, rolling)?;
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generations(created_at: &[&str]) -> Vec<RolloverGeneration> {
+        created_at
+            .iter()
+            .enumerate()
+            .map(|(i, created_at)| RolloverGeneration {
+                index: generation_name("logs", i as u64 + 1),
+                created_at: created_at.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_names_generations() {
+        assert_eq!(generation_name("logs", 2), "logs-000002");
+        assert_eq!(generation_number("logs", "logs-000002"), Some(2));
+        assert_eq!(generation_number("logs", "logs-old"), None);
+        assert_eq!(generation_number("logs", "metrics-000001"), None);
+    }
+
+    #[test]
+    fn test_checks_each_condition() {
+        let conditions = RolloverConditions {
+            max_age_secs: Some(3600),
+            max_docs: Some(100),
+        };
+        let met = check_conditions(&conditions, 60, 100);
+        assert_eq!(met.get("max_age_secs"), Some(&false));
+        assert_eq!(met.get("max_docs"), Some(&true));
+        assert!(check_conditions(&RolloverConditions::default(), 60, 100).is_empty());
+    }
+
+    #[test]
+    fn test_expires_generations_by_count_and_age() {
+        let now = DateTime::parse_from_rfc3339("2024-01-10T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let all = generations(&[
+            "2024-01-01T00:00:00Z",
+            "2024-01-05T00:00:00Z",
+            "2024-01-08T00:00:00Z",
+            "2024-01-09T00:00:00Z",
+        ]);
+
+        let by_count = RetentionPolicy {
+            max_generations: Some(2),
+            max_age_secs: None,
+        };
+        assert_eq!(
+            expired_generations(&all, &by_count, now),
+            vec!["logs-000001", "logs-000002"]
+        );
+
+        // Generation 2 was rolled over on the 8th, generation 3 on the 9th
+        let by_age = RetentionPolicy {
+            max_generations: None,
+            max_age_secs: Some(2 * 86400),
+        };
+        assert_eq!(
+            expired_generations(&all, &by_age, now),
+            vec!["logs-000001", "logs-000002"]
+        );
+
+        // The write index is kept even when nothing else is
+        let keep_none = RetentionPolicy {
+            max_generations: Some(0),
+            max_age_secs: Some(0),
+        };
+        assert_eq!(expired_generations(&all, &keep_none, now).len(), 3);
+        assert!(expired_generations(&all, &RetentionPolicy::default(), now).is_empty());
+    }
+}
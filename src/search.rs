@@ -1,33 +1,140 @@
 use anyhow::{anyhow, Result};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Bound;
 use std::path::Path;
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use tantivy::aggregation::agg_req::Aggregations;
-use tantivy::aggregation::agg_result::AggregationResults;
-use tantivy::aggregation::AggregationCollector;
-use tantivy::collector::TopDocs;
+use tantivy::aggregation::agg_result::{
+    AggregationResult, AggregationResults, BucketEntry, BucketResult, MetricResult,
+};
+use tantivy::aggregation::metric::{PercentileValues, PercentilesMetricResult, SingleMetricResult};
+use tantivy::aggregation::intermediate_agg_result::IntermediateAggregationResults;
+use tantivy::aggregation::{
+    AggregationLimitsGuard, DistributedAggregationCollector, Key,
+};
+use tantivy::collector::{DocSetCollector, TopDocs};
+use tantivy::columnar::Column;
+use tantivy::index::SegmentId;
 use tantivy::query::{
-    BooleanQuery, ExistsQuery, FuzzyTermQuery, Occur, Query, QueryParser, RegexPhraseQuery,
-    RegexQuery, TermSetQuery,
+    AllQuery, BooleanQuery, BoostQuery, ConstScoreQuery, EnableScoring, ExistsQuery,
+    FuzzyTermQuery, Occur, Query, QueryParser, RangeQuery, RegexPhraseQuery, RegexQuery,
+    TermQuery, TermSetQuery, Weight,
 };
 use tantivy::schema::*;
 use tantivy::tokenizer::{LowerCaser, SimpleTokenizer, Stemmer, TextAnalyzer};
-use tantivy::{Index, IndexWriter, Order, ReloadPolicy, TantivyDocument, Term};
+use tantivy::{
+    DocAddress, DocId, DocSet, Executor, Index, IndexWriter, Order, ReloadPolicy, Score,
+    SegmentReader, Searcher, TantivyDocument, Term, TERMINATED,
+};
 
+use crate::breaker;
+use crate::error::SearchError;
+use crate::field_access::{self, Access};
+use crate::filter_cache::FilterCache;
+use crate::geo;
+use crate::index_patterns;
+use crate::language;
+use crate::migration::{self, ShadowIndex};
+use crate::normalization;
+use crate::query_syntax;
+use crate::sharding::{self, ShardStatistics};
+use crate::stopwords::Stopwords;
+use crate::summary;
+use crate::validation;
 use crate::models::{
-    AggregationRequest, Document, FieldConfig, FieldStats, HighlightOptions, IndexStats,
-    PinnedRule, SearchHit, SortOption, SortOrder, SynonymGroup,
+    AggregationError, AggregationRequest, AnalyzedToken, BulkItem, BulkOperation, BulkResult,
+    CommitStats, DedupMode, DefaultOperator, Document, FieldCapabilities, FieldConfig,
+    FieldStats, FieldTermVectors, GeoFilter, GeoPoint, GeoShape, HasChildQuery, HasParentQuery,
+    HighlightOptions, IndexSettings, IndexStats, IngestOutcome, LookupRequest, MemoryEstimate,
+    OnConflict, PinnedRule,
+    PromptTemplate, RegexSearch, RolloverAlias, SearchHit, SearchPreset, SearchRequest,
+    SortOption, SortOrder,
+    StopwordRemoval, StorageStats, StoredFilter, SynonymGroup, TermCount, TermVector, TermVectors,
 };
 
-/// Default index writer memory budget (100MB)
+/// Default index writer memory budget (100MB), split between the shards of an index
 const DEFAULT_INDEX_WRITER_MEMORY: usize = 100_000_000;
 
+/// Smallest writer memory budget of a shard; Tantivy needs 15MB per indexing thread
+const MIN_SHARD_WRITER_MEMORY: usize = 15_000_000;
+
+/// Indexed (not stored) field holding the SHA-256 of a document's fields
+const CONTENT_HASH_FIELD: &str = "_hash";
+
+/// Indexed fast date field holding when a document expires
+const EXPIRES_AT_FIELD: &str = "_expires_at";
+
+/// Document field used to set a per-document expiration time
+const EXPIRES_AT_DOCUMENT_FIELD: &str = "expires_at";
+
+/// Per-index settings file, stored inside the index directory
+const SETTINGS_FILE: &str = "settings.json";
+
+/// Marker file of a closed index, so it stays closed across restarts
+const CLOSED_MARKER_FILE: &str = ".closed";
+
+/// Default maximum number of buckets returned by a geohash_grid aggregation
+const DEFAULT_GEOHASH_GRID_SIZE: usize = 10_000;
+
+/// Boost for literal query terms over fuzzy and stemmed matches
+const EXACT_MATCH_BOOST: f32 = 2.0;
+
+/// Default number of buckets returned by a terms aggregation
+pub const DEFAULT_TERMS_SIZE: usize = 10;
+
+/// Default bucket width of a histogram aggregation
+pub const DEFAULT_HISTOGRAM_INTERVAL: f64 = 10.0;
+
+/// Aggregation type giving the percentage of values at or below given values
+const PERCENTILE_RANKS_AGGREGATION: &str = "percentile_ranks";
+
+/// Aggregation type averaging a field weighted by another field
+const WEIGHTED_AVG_AGGREGATION: &str = "weighted_avg";
+
+/// Reads a numeric fast field value of a document within one segment
+type NumericColumn = Box<dyn Fn(DocId) -> Option<f64>>;
+
+/// Sort field that orders matches randomly instead of by a document field
+const RANDOM_SORT_FIELD: &str = "_random";
+
+/// Lookups a search can embed in its hits
+const MAX_LOOKUPS: usize = 5;
+
+/// Most documents a `has_child` or `has_parent` query may match, since the IDs linking
+/// them are collected before the search runs
+const MAX_JOIN_MATCHES: usize = 10_000;
+
+/// Most distinct terms a regex search may match, since they are collected before the
+/// search runs
+const MAX_REGEX_TERMS: usize = 10_000;
+
+/// Most children inlined in each parent hit
+const MAX_INNER_HITS: usize = 100;
+
+/// Deterministic pseudo-random key for a document (SplitMix64 finalizer)
+fn random_sort_key(seed: u64, doc: u64) -> u64 {
+    let mut z = seed.wrapping_add(doc.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
 /// Check if a word is a boolean operator (for query parsing)
 fn is_operator(word: &str) -> bool {
     matches!(word.to_uppercase().as_str(), "AND" | "OR" | "NOT" | "TO")
 }
 
-pub type SearchResult = Result<(Vec<SearchHit>, usize, f64, Option<AggregationResults>)>;
+pub type SearchResult = std::result::Result<
+    (
+        Vec<SearchHit>,
+        usize,
+        f64,
+        Option<AggregationResults>,
+        Vec<AggregationError>,
+    ),
+    SearchError,
+>;
 
 pub struct SearchEngine {
     base_path: String,
@@ -36,1528 +143,5520 @@ pub struct SearchEngine {
     synonyms: Arc<RwLock<HashMap<String, Vec<SynonymGroup>>>>,
     /// Pinned rules stored per index: index_name -> list of pinned rules
     pinned_rules: Arc<RwLock<HashMap<String, Vec<PinnedRule>>>>,
+    /// Stored filters per index: index_name -> list of named filters
+    stored_filters: Arc<RwLock<HashMap<String, Vec<StoredFilter>>>>,
+    /// Prompt templates per index: index_name -> list of named templates
+    prompt_templates: Arc<RwLock<HashMap<String, Vec<PromptTemplate>>>>,
+    /// Search presets per index: index_name -> list of named presets
+    search_presets: Arc<RwLock<HashMap<String, Vec<SearchPreset>>>>,
+    /// Rolling indices: alias -> generations written and searched through it
+    rollover_aliases: Arc<RwLock<HashMap<String, RolloverAlias>>>,
+    /// Thread pool shared by all indices for searching segments in parallel
+    executor: Executor,
+    /// Size limits rejecting queries that would be too expensive to run
+    query_limits: QueryLimits,
+    /// Stored and search key filters evaluated per segment
+    filter_cache: Arc<FilterCache>,
+    /// Bumped whenever settings, synonyms, pinned rules or stored filters change, or an
+    /// index is deleted, since those change results without a commit
+    config_generation: AtomicU64,
+    /// Names of indices being created, so two creations can't build the same directory
+    creating: Mutex<HashSet<String>>,
+    /// Indices kept on disk without a loaded handle until they are next used
+    closed: RwLock<HashSet<String>>,
+    /// Indices being migrated: source index -> the index its writes are copied into
+    shadows: RwLock<HashMap<String, Arc<ShadowIndex>>>,
 }
 
-pub struct IndexHandle {
+/// Position in the segments of a snapshot read by `snapshot_documents`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SnapshotCursor {
+    segment: usize,
+    doc: DocId,
+}
+
+/// Claim on an index name while the index is created, released when dropped
+struct NameReservation<'a> {
+    names: &'a Mutex<HashSet<String>>,
+    name: String,
+}
+
+impl Drop for NameReservation<'_> {
+    fn drop(&mut self) {
+        self.names.lock().unwrap().remove(&self.name);
+    }
+}
+
+/// One Tantivy index holding part of the documents of an index
+pub struct Shard {
     pub index: Index,
+    /// Created on first write, so loading an index doesn't allocate the writer's memory
+    writer: RwLock<Option<IndexWriter>>,
+}
+
+impl Shard {
+    fn new(index: Index) -> Self {
+        Self {
+            index,
+            writer: RwLock::new(None),
+        }
+    }
+}
+
+pub struct IndexHandle {
+    /// A single shard unless the index was created with several; documents are routed
+    /// to a shard by a hash of their ID
+    shards: Vec<Shard>,
     pub schema: Schema,
-    pub writer: Arc<RwLock<IndexWriter>>,
     pub field_map: HashMap<String, Field>,
     pub field_configs: Vec<FieldConfig>,
+    pub settings: IndexSettings,
 }
 
-impl SearchEngine {
-    pub fn new(base_path: &str) -> Result<Self> {
-        std::fs::create_dir_all(base_path)?;
-
-        // Load synonyms from file if exists
-        let synonyms_path = Path::new(base_path).join("synonyms.json");
-        let synonyms: HashMap<String, Vec<SynonymGroup>> = if synonyms_path.exists() {
-            let content = std::fs::read_to_string(&synonyms_path)?;
-            serde_json::from_str(&content).unwrap_or_default()
-        } else {
-            HashMap::new()
-        };
+impl IndexHandle {
+    fn new(
+        shards: Vec<Index>,
+        field_map: HashMap<String, Field>,
+        field_configs: Vec<FieldConfig>,
+        settings: IndexSettings,
+    ) -> Self {
+        Self {
+            schema: shards[0].schema(),
+            shards: shards.into_iter().map(Shard::new).collect(),
+            field_map,
+            field_configs,
+            settings,
+        }
+    }
 
-        // Load pinned rules from file if exists
-        let pinned_path = Path::new(base_path).join("pinned_rules.json");
-        let pinned_rules: HashMap<String, Vec<PinnedRule>> = if pinned_path.exists() {
-            let content = std::fs::read_to_string(&pinned_path)?;
-            serde_json::from_str(&content).unwrap_or_default()
-        } else {
-            HashMap::new()
-        };
+    /// Index used where any shard will do, such as parsing queries: all shards share
+    /// the schema and analyzers
+    fn index(&self) -> &Index {
+        &self.shards[0].index
+    }
 
-        Ok(Self {
-            base_path: base_path.to_string(),
-            indices: Arc::new(RwLock::new(HashMap::new())),
-            synonyms: Arc::new(RwLock::new(synonyms)),
-            pinned_rules: Arc::new(RwLock::new(pinned_rules)),
-        })
+    fn shard_for(&self, doc_id: &str) -> usize {
+        sharding::shard_for(doc_id, self.shards.len())
     }
 
-    /// Save pinned rules to disk
-    fn save_pinned_rules(&self) -> Result<()> {
-        let rules = self.pinned_rules.read().unwrap();
-        let pinned_path = Path::new(&self.base_path).join("pinned_rules.json");
-        let content = serde_json::to_string_pretty(&*rules)?;
-        std::fs::write(pinned_path, content)?;
-        Ok(())
+    /// Exclusive access to a shard's writer, creating it if needed
+    fn writer(&self, shard: usize) -> tantivy::Result<WriterGuard<'_>> {
+        let mut guard = self.shards[shard].writer.write().unwrap();
+        if guard.is_none() {
+            let memory = (DEFAULT_INDEX_WRITER_MEMORY / self.shards.len())
+                .max(MIN_SHARD_WRITER_MEMORY);
+            *guard = Some(self.shards[shard].index.writer(memory)?);
+        }
+        Ok(WriterGuard(guard))
     }
 
-    /// Add pinned rules for an index
-    pub fn add_pinned_rules(&self, index_name: &str, rules: Vec<PinnedRule>) -> Result<()> {
-        let mut pinned = self.pinned_rules.write().unwrap();
-        let entry = pinned.entry(index_name.to_string()).or_default();
-        entry.extend(rules);
-        drop(pinned);
-        self.save_pinned_rules()?;
-        Ok(())
+    /// A searcher per shard, in shard order
+    fn searchers(&self, reload_policy: ReloadPolicy) -> tantivy::Result<Vec<Searcher>> {
+        self.shards
+            .iter()
+            .map(|shard| {
+                let reader: tantivy::IndexReader = shard
+                    .index
+                    .reader_builder()
+                    .reload_policy(reload_policy)
+                    .try_into()?;
+                Ok(reader.searcher())
+            })
+            .collect()
     }
 
-    /// Get pinned rules for an index
-    pub fn get_pinned_rules(&self, index_name: &str) -> Vec<PinnedRule> {
-        let rules = self.pinned_rules.read().unwrap();
-        rules.get(index_name).cloned().unwrap_or_default()
+    /// Sum of the shards' commit opstamps, which grows with every commit to any shard
+    fn opstamp(&self) -> tantivy::Result<u64> {
+        self.shards
+            .iter()
+            .map(|shard| Ok(shard.index.load_metas()?.opstamp))
+            .sum()
     }
+}
 
-    /// Clear all pinned rules for an index
-    pub fn clear_pinned_rules(&self, index_name: &str) -> Result<()> {
-        let mut rules = self.pinned_rules.write().unwrap();
-        rules.remove(index_name);
-        drop(rules);
-        self.save_pinned_rules()?;
-        Ok(())
+/// Write lock on an index writer that has been created
+struct WriterGuard<'a>(RwLockWriteGuard<'a, Option<IndexWriter>>);
+
+impl std::ops::Deref for WriterGuard<'_> {
+    type Target = IndexWriter;
+
+    fn deref(&self) -> &IndexWriter {
+        self.0.as_ref().expect("writer is created before it is guarded")
     }
+}
 
-    /// Get pinned document IDs for a query
-    fn get_pinned_doc_ids(&self, index_name: &str, query_str: &str) -> Vec<String> {
-        let rules = self.pinned_rules.read().unwrap();
-        let query_lower = query_str.to_lowercase();
-        
-        if let Some(index_rules) = rules.get(index_name) {
-            for rule in index_rules {
-                // Check if query matches any of the trigger terms
-                for trigger in &rule.queries {
-                    if query_lower.contains(&trigger.to_lowercase()) {
-                        return rule.document_ids.clone();
-                    }
-                }
-            }
-        }
-        
-        Vec::new()
+impl std::ops::DerefMut for WriterGuard<'_> {
+    fn deref_mut(&mut self) -> &mut IndexWriter {
+        self.0.as_mut().expect("writer is created before it is guarded")
     }
+}
 
-    /// Save synonyms to disk
-    fn save_synonyms(&self) -> Result<()> {
-        let synonyms = self.synonyms.read().unwrap();
-        let synonyms_path = Path::new(&self.base_path).join("synonyms.json");
-        let content = serde_json::to_string_pretty(&*synonyms)?;
-        std::fs::write(synonyms_path, content)?;
-        Ok(())
+/// Documents written to one index under a single writer lock and committed together
+struct IngestBatch<'a> {
+    handle: &'a IndexHandle,
+    /// Writer of each shard
+    writers: Vec<WriterGuard<'a>>,
+    id_field: Field,
+    /// Indices created before content hashing have no hash field
+    hash_field: Option<Field>,
+    /// Committed documents of each shard, opened on first use
+    searchers: Vec<Option<Searcher>>,
+    /// Generated summaries vary between runs, so they don't count as content
+    summary_field: Option<&'a str>,
+    /// Content hashes seen in this batch: hash -> document ID
+    batch_hashes: HashMap<String, String>,
+    /// IDs written in this batch and not deleted since
+    written_ids: HashSet<String>,
+    deleted_ids: HashSet<String>,
+    /// IDs that operations in the batch update, so their latest version is kept
+    tracked_ids: HashSet<String>,
+    /// Copying documents as they are in another index: they replace documents with the
+    /// same ID, and duplicates are kept
+    copying: bool,
+    /// Latest version of the tracked documents written in this batch
+    pending: HashMap<String, Document>,
+    /// Documents written or deleted since the last commit
+    uncommitted: usize,
+    outcome: IngestOutcome,
+    started: std::time::Instant,
+}
+
+impl<'a> IngestBatch<'a> {
+    fn new(handle: &'a IndexHandle, index_name: &str) -> std::result::Result<Self, SearchError> {
+        SearchEngine::check_writable(handle, index_name)?;
+        let writers = (0..handle.shards.len())
+            .map(|shard| handle.writer(shard))
+            .collect::<tantivy::Result<Vec<_>>>()?;
+
+        let id_field = *handle.field_map.get("id").unwrap();
+        let hash_field = handle.field_map.get(CONTENT_HASH_FIELD).copied();
+        let summary_field = handle
+            .settings
+            .summary
+            .as_ref()
+            .map(|settings| settings.target_field.as_str());
+
+        Ok(Self {
+            handle,
+            writers,
+            id_field,
+            hash_field,
+            searchers: vec![None; handle.shards.len()],
+            summary_field,
+            batch_hashes: HashMap::new(),
+            written_ids: HashSet::new(),
+            deleted_ids: HashSet::new(),
+            tracked_ids: HashSet::new(),
+            copying: false,
+            pending: HashMap::new(),
+            uncommitted: 0,
+            outcome: IngestOutcome::default(),
+            started: std::time::Instant::now(),
+        })
     }
 
-    /// Add synonyms for an index
-    pub fn add_synonyms(&self, index_name: &str, synonym_groups: Vec<SynonymGroup>) -> Result<()> {
-        let mut synonyms = self.synonyms.write().unwrap();
-        let entry = synonyms.entry(index_name.to_string()).or_default();
-        entry.extend(synonym_groups);
-        drop(synonyms);
-        self.save_synonyms()?;
-        Ok(())
+    fn searcher(&mut self, shard: usize) -> std::result::Result<Searcher, SearchError> {
+        if self.searchers[shard].is_none() {
+            let reader: tantivy::IndexReader = self.handle.shards[shard]
+                .index
+                .reader_builder()
+                .reload_policy(ReloadPolicy::Manual)
+                .try_into()?;
+            self.searchers[shard] = Some(reader.searcher());
+        }
+        Ok(self.searchers[shard].clone().unwrap())
     }
 
-    /// Get synonyms for an index
-    pub fn get_synonyms(&self, index_name: &str) -> Vec<SynonymGroup> {
-        let synonyms = self.synonyms.read().unwrap();
-        synonyms.get(index_name).cloned().unwrap_or_default()
+    /// Whether the index will have a document with this ID when the batch commits
+    fn exists(&mut self, doc_id: &str) -> std::result::Result<bool, SearchError> {
+        if self.written_ids.contains(doc_id) {
+            return Ok(true);
+        }
+        if self.deleted_ids.contains(doc_id) {
+            return Ok(false);
+        }
+        let searcher = self.searcher(self.handle.shard_for(doc_id))?;
+        let query = TermQuery::new(
+            Term::from_field_text(self.id_field, doc_id),
+            IndexRecordOption::Basic,
+        );
+        Ok(searcher.search(&query, &tantivy::collector::Count)? > 0)
     }
 
-    /// Clear all synonyms for an index
-    pub fn clear_synonyms(&self, index_name: &str) -> Result<()> {
-        let mut synonyms = self.synonyms.write().unwrap();
-        synonyms.remove(index_name);
-        drop(synonyms);
-        self.save_synonyms()?;
-        Ok(())
+    /// Add a document. Returns `Created`, `Updated` when it replaced a document with the
+    /// same ID, or `Noop` when it was skipped.
+    fn add(&mut self, doc: &Document) -> std::result::Result<BulkResult, SearchError> {
+        self.write(doc, true)
     }
 
-    /// Expand a query term with its synonyms
-    fn expand_with_synonyms(&self, index_name: &str, term: &str) -> Vec<String> {
-        let synonyms = self.synonyms.read().unwrap();
-        let term_lower = term.to_lowercase();
-        
-        if let Some(groups) = synonyms.get(index_name) {
-            for group in groups {
-                // Check if this term is in any synonym group
-                if group.terms.iter().any(|t| t.to_lowercase() == term_lower) {
-                    // Return all terms in the group (including the original)
-                    return group.terms.iter()
-                        .map(|t| t.to_lowercase())
-                        .collect();
+    fn write(
+        &mut self,
+        doc: &Document,
+        skip_duplicates: bool,
+    ) -> std::result::Result<BulkResult, SearchError> {
+        let handle = self.handle;
+        let (id_field, hash_field, summary_field) =
+            (self.id_field, self.hash_field, self.summary_field);
+
+        // Conflicts are settled before anything is deleted, so a skipped or rejected
+        // document leaves the index as it was
+        let exists = self.exists(&doc.id)?;
+        if exists && !self.copying {
+            match handle.settings.on_conflict {
+                OnConflict::Replace => {}
+                OnConflict::Skip => {
+                    self.outcome.conflict_ids.push(doc.id.clone());
+                    return Ok(BulkResult::Noop);
+                }
+                OnConflict::Error => {
+                    return Err(SearchError::Conflict(format!(
+                        "Document already exists: {}",
+                        doc.id
+                    )));
                 }
             }
         }
-        
-        // No synonyms found, return just the original term
-        vec![term_lower]
-    }
 
-    /// Expand a full query string with synonyms
-    fn expand_query_with_synonyms(&self, index_name: &str, query_str: &str) -> String {
-        // Simple tokenization - split on whitespace and handle quoted phrases
-        let mut result = String::new();
-        let mut in_quotes = false;
-        let mut current_word = String::new();
-        
-        for ch in query_str.chars() {
-            if ch == '"' {
-                in_quotes = !in_quotes;
-                result.push(ch);
-            } else if ch.is_whitespace() && !in_quotes {
-                if !current_word.is_empty() {
-                    // Check if this is an operator or special syntax
-                    if is_operator(&current_word) 
-                        || current_word.contains(':') 
-                        || current_word.contains('*')
-                        || current_word.contains('?') 
-                    {
-                        result.push_str(&current_word);
-                    } else {
-                        // Expand with synonyms
-                        let expanded = self.expand_with_synonyms(index_name, &current_word);
-                        if expanded.len() > 1 {
-                            // Multiple synonyms - wrap in parentheses with OR
-                            result.push('(');
-                            result.push_str(&expanded.join(" OR "));
-                            result.push(')');
-                        } else {
-                            result.push_str(&expanded[0]);
+        let mut tantivy_doc = TantivyDocument::default();
+
+        // Add ID field
+        tantivy_doc.add_text(id_field, &doc.id);
+
+        if let Some(hash_field) = hash_field {
+            let hash = SearchEngine::content_hash(doc, summary_field)?;
+            let hash_term = Term::from_field_text(hash_field, &hash);
+
+            if handle.settings.dedup != DedupMode::None && !self.copying {
+                // Duplicates can be in any shard, since documents are routed by ID
+                let mut existing_ids = Vec::new();
+                for shard in 0..self.searchers.len() {
+                    let searcher = self.searcher(shard)?;
+                    existing_ids.extend(SearchEngine::ids_for_term(
+                        &searcher,
+                        id_field,
+                        &hash_term,
+                    )?);
+                }
+                // Documents deleted earlier in the batch are gone once it commits
+                existing_ids.retain(|id| !self.deleted_ids.contains(id));
+                if let Some(batch_id) = self.batch_hashes.get(&hash) {
+                    existing_ids.push(batch_id.clone());
+                }
+                // The document this one replaces isn't a duplicate of it
+                existing_ids.retain(|id| *id != doc.id);
+
+                if !existing_ids.is_empty() {
+                    match handle.settings.dedup {
+                        DedupMode::Skip if skip_duplicates => {
+                            self.outcome.skipped_ids.push(doc.id.clone());
+                            return Ok(BulkResult::Noop);
                         }
+                        DedupMode::Overwrite => {
+                            for writer in &mut self.writers {
+                                writer.delete_term(hash_term.clone());
+                            }
+                            self.outcome.indexed_ids.retain(|id| !existing_ids.contains(id));
+                            for id in &existing_ids {
+                                self.written_ids.remove(id);
+                                self.pending.remove(id);
+                                self.deleted_ids.insert(id.clone());
+                            }
+                            self.outcome.replaced_ids.extend(existing_ids);
+                        }
+                        DedupMode::Skip | DedupMode::None => {}
                     }
-                    current_word.clear();
-                }
-                result.push(ch);
-            } else {
-                current_word.push(ch);
-            }
-        }
-        
-        // Handle last word
-        if !current_word.is_empty() {
-            if is_operator(&current_word) 
-                || current_word.contains(':') 
-                || current_word.contains('*')
-                || current_word.contains('?') 
-            {
-                result.push_str(&current_word);
-            } else {
-                let expanded = self.expand_with_synonyms(index_name, &current_word);
-                if expanded.len() > 1 {
-                    result.push('(');
-                    result.push_str(&expanded.join(" OR "));
-                    result.push(')');
-                } else {
-                    result.push_str(&expanded[0]);
                 }
             }
-        }
-        
-        result
-    }
 
-    pub fn load_indices(&self) -> Result<Vec<String>> {
-        let mut loaded = Vec::new();
-        let base_path = Path::new(&self.base_path);
+            tantivy_doc.add_text(hash_field, &hash);
+            self.batch_hashes.retain(|_, id| *id != doc.id);
+            self.batch_hashes.insert(hash, doc.id.clone());
+        }
 
-        if !base_path.exists() {
-            return Ok(loaded);
+        let shard = handle.shard_for(&doc.id);
+        let mut result = BulkResult::Created;
+        if exists {
+            self.writers[shard].delete_term(Term::from_field_text(id_field, &doc.id));
+            self.outcome.indexed_ids.retain(|id| *id != doc.id);
+            self.outcome.updated_ids.push(doc.id.clone());
+            result = BulkResult::Updated;
         }
 
-        for entry in std::fs::read_dir(base_path)? {
-            let entry = entry?;
-            if !entry.file_type()?.is_dir() {
-                continue;
+        // Long texts that arrive without a summary get an extractive one
+        if let Some(settings) = &handle.settings.summary {
+            if let (Some(text), Some(field)) = (
+                summary::text_to_summarize(&doc.fields, settings),
+                handle.field_map.get(&settings.target_field),
+            ) {
+                let text = summary::extractive_summary(text, settings.max_chars);
+                tantivy_doc.add_text(*field, text);
             }
+        }
 
-            let index_name = entry.file_name().to_string_lossy().to_string();
-            let index_path = entry.path();
-
-            match Index::open_in_dir(&index_path) {
-                Ok(index) => {
-                    Self::register_analyzers(&index);
-                    let schema = index.schema();
-                    let field_map = schema
-                        .fields()
-                        .map(|(field, field_entry)| (field_entry.name().to_string(), field))
-                        .collect::<HashMap<_, _>>();
-                    let field_configs = Self::field_configs_from_schema(&schema);
-
-                    match index.writer(DEFAULT_INDEX_WRITER_MEMORY) {
-                        Ok(writer) => {
-                            let handle = IndexHandle {
-                                index,
-                                schema,
-                                writer: Arc::new(RwLock::new(writer)),
-                                field_map,
-                                field_configs,
-                            };
-
-                            match self.indices.write() {
-                                Ok(mut indices) => {
-                                    indices.insert(index_name.clone(), handle);
-                                    loaded.push(index_name);
-                                }
-                                Err(e) => {
-                                    tracing::warn!(
-                                        "Failed to acquire write lock for index '{}': {}",
-                                        index_name,
-                                        e
-                                    );
+        // Add custom fields
+        for (field_name, value) in &doc.fields {
+            if let Some(field) = handle.field_map.get(field_name) {
+                // Get field config to check type
+                let field_type = handle
+                    .field_configs
+                    .iter()
+                    .find(|fc| fc.name == *field_name)
+                    .map(|fc| fc.field_type.as_str())
+                    .unwrap_or("text");
+
+                match field_type {
+                    "date" => {
+                        if let Some(tantivy_dt) = SearchEngine::parse_date_value(value) {
+                            tantivy_doc.add_date(*field, tantivy_dt);
+                        }
+                    }
+                    "json" => {
+                        // JSON field - convert serde_json::Value to OwnedValue
+                        use tantivy::schema::OwnedValue;
+                        let owned_value = OwnedValue::from(value.clone());
+                        tantivy_doc.add_field_value(*field, &owned_value);
+                    }
+                    // Numbers are converted to the column type; mixing i64 and
+                    // f64 values in one fast field panics the writer
+                    "i64" => {
+                        if let Some(i) = value.as_i64() {
+                            tantivy_doc.add_i64(*field, i);
+                        }
+                    }
+                    "f64" => {
+                        if let Some(f) = value.as_f64() {
+                            tantivy_doc.add_f64(*field, f);
+                        }
+                    }
+                    geo::GEO_POINT_TYPE => {
+                        if let Some(point) = geo::parse_point(value) {
+                            let normalized = serde_json::json!({
+                                "lat": point.lat,
+                                "lon": point.lon
+                            });
+                            tantivy_doc.add_field_value(*field, &OwnedValue::from(normalized));
+                            for (sub_name, coordinate) in [
+                                (geo::lat_field_name(field_name), point.lat),
+                                (geo::lon_field_name(field_name), point.lon),
+                            ] {
+                                if let Some(sub_field) = handle.field_map.get(&sub_name) {
+                                    tantivy_doc.add_f64(*sub_field, coordinate);
                                 }
                             }
                         }
-                        Err(e) => {
-                            tracing::warn!(
-                                "Failed to create writer for index '{}': {}",
-                                index_name,
-                                e
-                            );
-                        }
                     }
-                }
-                Err(e) => {
-                    tracing::warn!(
-                        "Failed to load index from {}: {}",
-                        index_path.display(),
-                        e
-                    );
+                    _ => match value {
+                        serde_json::Value::String(s) => {
+                            tantivy_doc.add_text(*field, s);
+                        }
+                        serde_json::Value::Number(n) => {
+                            if let Some(i) = n.as_i64() {
+                                tantivy_doc.add_i64(*field, i);
+                            } else if let Some(f) = n.as_f64() {
+                                tantivy_doc.add_f64(*field, f);
+                            }
+                        }
+                        serde_json::Value::Bool(b) => {
+                            tantivy_doc.add_i64(*field, if *b { 1 } else { 0 });
+                        }
+                        _ => {}
+                    },
                 }
             }
         }
 
-        Ok(loaded)
-    }
+        // Expiration from the document's `expires_at` field or the index default TTL
+        if let Some(expires_field) = handle.field_map.get(EXPIRES_AT_FIELD) {
+            let expires_at = doc
+                .fields
+                .get(EXPIRES_AT_DOCUMENT_FIELD)
+                .and_then(SearchEngine::parse_date_value)
+                .or_else(|| {
+                    // A TTL too large to represent never expires
+                    let ttl = i64::try_from(handle.settings.default_ttl_seconds?).ok()?;
+                    chrono::Utc::now()
+                        .timestamp()
+                        .checked_add(ttl)?
+                        .checked_mul(1_000_000_000)
+                        .map(tantivy::DateTime::from_timestamp_nanos)
+                });
+            if let Some(expires_at) = expires_at {
+                tantivy_doc.add_date(*expires_field, expires_at);
+            }
+        }
 
-    pub fn collect_document_ids(&self, index_name: &str) -> Result<Vec<String>> {
-        let indices = self.indices.read()
-            .map_err(|e| anyhow!("Failed to acquire read lock: {}", e))?;
-        let handle = indices
-            .get(index_name)
-            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        // Detect the document language from its auto-analyzed text fields and
+        // route that text to the matching per-language sub-field
+        if let Some(lang_field) = handle.field_map.get(language::LANGUAGE_FIELD) {
+            let auto_texts: Vec<(&str, &str)> = handle
+                .field_configs
+                .iter()
+                .filter(|fc| fc.analyzer == language::AUTO_ANALYZER)
+                .filter_map(|fc| {
+                    doc.fields
+                        .get(&fc.name)
+                        .and_then(|v| v.as_str())
+                        .map(|text| (fc.name.as_str(), text))
+                })
+                .collect();
 
-        let reader = handle
-            .index
-            .reader_builder()
-            .reload_policy(ReloadPolicy::Manual)
-            .try_into()?;
-        let searcher = reader.searcher();
+            let codes: Vec<String> = language::supported_codes()
+                .into_iter()
+                .filter(|code| {
+                    auto_texts.iter().any(|(name, _)| {
+                        handle
+                            .field_map
+                            .contains_key(&language::sub_field_name(name, code))
+                    })
+                })
+                .collect();
 
-        let id_field = *handle
-            .field_map
-            .get("id")
-            .ok_or_else(|| anyhow!("ID field not found for index: {}", index_name))?;
+            let combined = auto_texts
+                .iter()
+                .map(|(_, text)| *text)
+                .collect::<Vec<_>>()
+                .join("\n");
+            let detected = language::detect(&combined, &codes);
+
+            tantivy_doc.add_text(*lang_field, detected.unwrap_or(language::UNKNOWN_LANGUAGE));
+            if let Some(code) = detected {
+                for (name, text) in &auto_texts {
+                    if let Some(sub_field) =
+                        handle.field_map.get(&language::sub_field_name(name, code))
+                    {
+                        tantivy_doc.add_text(*sub_field, text);
+                    }
+                }
+            }
+        }
 
-        let mut ids = Vec::new();
+        self.writers[shard].add_document(tantivy_doc)?;
+        self.uncommitted += 1;
+        self.outcome.indexed_ids.push(doc.id.clone());
+        self.written_ids.insert(doc.id.clone());
+        if self.tracked_ids.contains(&doc.id) {
+            self.pending.insert(doc.id.clone(), doc.clone());
+        }
+        Ok(result)
+    }
 
-        for segment_reader in searcher.segment_readers() {
-            let store_reader = segment_reader.get_store_reader(0)?;
-            let max_doc = segment_reader.max_doc();
-            let alive_bitset = segment_reader.alive_bitset();
+    /// Latest version of a document: written earlier in the batch, or committed and not
+    /// deleted since
+    fn current(&mut self, doc_id: &str) -> std::result::Result<Option<Document>, SearchError> {
+        if let Some(doc) = self.pending.get(doc_id) {
+            return Ok(Some(doc.clone()));
+        }
+        if self.deleted_ids.contains(doc_id) {
+            return Ok(None);
+        }
+        let searcher = self.searcher(self.handle.shard_for(doc_id))?;
+        let query = TermQuery::new(
+            Term::from_field_text(self.id_field, doc_id),
+            IndexRecordOption::Basic,
+        );
+        let Some((_, address)) = searcher.search(&query, &TopDocs::with_limit(1))?.pop() else {
+            return Ok(None);
+        };
+        let stored: TantivyDocument = searcher.doc(address)?;
+        Ok(Some(SearchEngine::stored_document(self.handle, doc_id, &stored)))
+    }
 
-            for doc_id in 0..max_doc {
-                if let Some(bitset) = alive_bitset {
-                    if !bitset.is_alive(doc_id) {
-                        continue;
+    /// Merge the fields of `doc` into the current version of the document and write it
+    /// back. A missing document is created when `create` is set.
+    fn update(
+        &mut self,
+        doc: &Document,
+        create: bool,
+    ) -> std::result::Result<BulkResult, SearchError> {
+        let (merged, result) = match self.current(&doc.id)? {
+            Some(mut current) => {
+                // New source text needs a new summary
+                if let Some(settings) = &self.handle.settings.summary {
+                    if doc.fields.contains_key(&settings.source_field)
+                        && !doc.fields.contains_key(&settings.target_field)
+                    {
+                        current.fields.remove(&settings.target_field);
                     }
                 }
+                current.fields.extend(doc.fields.clone());
+                (current, BulkResult::Updated)
+            }
+            None if create => (doc.clone(), BulkResult::Created),
+            None => return Ok(BulkResult::NotFound),
+        };
+        self.delete(&doc.id);
+        // An updated document is kept even if its new content duplicates another one
+        self.write(&merged, false)?;
+        Ok(result)
+    }
 
-                let doc: TantivyDocument = store_reader.get(doc_id)?;
-                let id_value = {
-                    let mut values = doc.get_all(id_field);
-                    values.next().and_then(|field_value| {
-                        let owned_value: tantivy::schema::OwnedValue = field_value.into();
-                        if let tantivy::schema::OwnedValue::Str(s) = owned_value {
-                            Some(s.to_string())
-                        } else {
-                            None
-                        }
-                    })
-                };
-
-                if let Some(id) = id_value {
-                    ids.push(id);
-                }
+    /// Run one bulk operation
+    fn apply(&mut self, op: &BulkOperation) -> BulkItem {
+        let id = op
+            .document
+            .as_ref()
+            .map(|doc| doc.id.clone())
+            .filter(|id| !id.is_empty())
+            .or_else(|| op.id.clone());
+        let result = match (op.operation.as_str(), &op.document, &op.id) {
+            ("index", Some(doc), _) => self.add(doc).map_err(|e| e.to_string()),
+            ("update", Some(doc), _) if doc.id.is_empty() => {
+                Err("Missing id for update operation".to_string())
+            }
+            ("update" | "upsert", Some(doc), _) => self
+                .update(doc, op.operation == "upsert")
+                .map_err(|e| e.to_string()),
+            ("index" | "update" | "upsert", None, _) => {
+                Err(format!("Missing document for {} operation", op.operation))
+            }
+            ("delete", _, Some(id)) => {
+                self.delete(id);
+                Ok(BulkResult::Deleted)
+            }
+            ("delete", _, None) => Err("Missing id for delete operation".to_string()),
+            (other, _, _) => Err(format!("Unknown operation: {}", other)),
+        };
+        let (result, error) = match result {
+            Ok(BulkResult::NotFound) => {
+                (BulkResult::NotFound, Some("Document not found".to_string()))
             }
+            Ok(result) => (result, None),
+            Err(e) => (BulkResult::Error, Some(e)),
+        };
+        BulkItem {
+            operation: op.operation.clone(),
+            id,
+            result,
+            error,
         }
-
-        Ok(ids)
     }
 
-    fn field_configs_from_schema(schema: &Schema) -> Vec<FieldConfig> {
-        let mut configs = Vec::new();
+    fn delete(&mut self, doc_id: &str) {
+        let shard = self.handle.shard_for(doc_id);
+        self.writers[shard].delete_term(Term::from_field_text(self.id_field, doc_id));
+        self.uncommitted += 1;
+        self.written_ids.remove(doc_id);
+        self.pending.remove(doc_id);
+        self.batch_hashes.retain(|_, id| id != doc_id);
+        self.outcome.indexed_ids.retain(|id| id != doc_id);
+        self.outcome.deleted_ids.push(doc_id.to_string());
+        self.deleted_ids.insert(doc_id.to_string());
+    }
 
-        for (_field, entry) in schema.fields() {
-            let name = entry.name();
-            if name == "id" {
-                continue;
+    /// Commit early once the index's commit policy allows no more uncommitted documents
+    fn checkpoint(&mut self) -> std::result::Result<(), SearchError> {
+        let Some(max_documents) = self.handle.settings.commit_policy.max_documents else {
+            return Ok(());
+        };
+        if self.uncommitted >= max_documents {
+            for writer in &mut self.writers {
+                writer.commit()?;
             }
+            self.uncommitted = 0;
+            // Later lookups must see the committed documents
+            self.searchers.iter_mut().for_each(|searcher| *searcher = None);
+        }
+        Ok(())
+    }
 
-            match entry.field_type() {
-                FieldType::Str(options) => {
-                    let indexing = options.get_indexing_options();
-                    let indexed = indexing.is_some();
-                    let stored = options.is_stored();
+    /// Drop everything written since the last commit
+    fn rollback(mut self) -> std::result::Result<(), SearchError> {
+        for writer in &mut self.writers {
+            writer.rollback()?;
+        }
+        Ok(())
+    }
 
-                    let (field_type, analyzer) = if let Some(indexing) = indexing {
-                        let tokenizer = indexing.tokenizer().to_string();
-                        let index_option = indexing.index_option();
-                        let is_string = tokenizer == "raw" && index_option == IndexRecordOption::Basic;
-                        (
-                            if is_string { "string" } else { "text" },
-                            tokenizer,
-                        )
-                    } else {
-                        ("text", "default".to_string())
-                    };
+    /// Commit every shard
+    fn commit(self) -> std::result::Result<IngestOutcome, SearchError> {
+        self.commit_with(|_| Ok(()))
+    }
 
-                    configs.push(FieldConfig {
-                        name: name.to_string(),
-                        field_type: field_type.to_string(),
-                        stored,
-                        indexed,
-                        analyzer,
-                        fast: false,
-                    });
-                }
-                FieldType::I64(options) => {
-                    configs.push(FieldConfig {
-                        name: name.to_string(),
-                        field_type: "i64".to_string(),
-                        stored: options.is_stored(),
-                        indexed: options.is_indexed(),
-                        analyzer: "default".to_string(),
-                        fast: options.is_fast(),
-                    });
-                }
-                FieldType::F64(options) => {
-                    configs.push(FieldConfig {
-                        name: name.to_string(),
-                        field_type: "f64".to_string(),
-                        stored: options.is_stored(),
-                        indexed: options.is_indexed(),
-                        analyzer: "default".to_string(),
-                        fast: options.is_fast(),
-                    });
-                }
-                FieldType::Date(options) => {
-                    configs.push(FieldConfig {
-                        name: name.to_string(),
-                        field_type: "date".to_string(),
-                        stored: options.is_stored(),
-                        indexed: options.is_indexed(),
-                        analyzer: "default".to_string(),
-                        fast: options.is_fast(),
-                    });
-                }
-                FieldType::JsonObject(options) => {
-                    configs.push(FieldConfig {
-                        name: name.to_string(),
-                        field_type: "json".to_string(),
-                        stored: options.is_stored(),
-                        indexed: options.get_text_indexing_options().is_some(),
-                        analyzer: "default".to_string(),
-                        fast: options.is_expand_dots_enabled(),
-                    });
+    /// Commit every shard once `before_commit` accepts the outcome. Every shard's commit
+    /// is prepared first, so when preparing or `before_commit` fails, nothing is
+    /// committed; shards then commit one after another, so a failure committing can
+    /// still leave earlier shards committed.
+    fn commit_with(
+        mut self,
+        before_commit: impl FnOnce(&IngestOutcome) -> std::result::Result<(), SearchError>,
+    ) -> std::result::Result<IngestOutcome, SearchError> {
+        let mut outcome = std::mem::take(&mut self.outcome);
+        // A replaced document that was re-added under the same ID still exists
+        let indexed: HashSet<&String> = outcome.indexed_ids.iter().collect();
+        let replaced_ids = outcome
+            .replaced_ids
+            .iter()
+            .filter(|id| !indexed.contains(id))
+            .cloned()
+            .collect();
+        outcome.replaced_ids = replaced_ids;
+
+        let mut prepared = Vec::with_capacity(self.writers.len());
+        let mut result = Ok(());
+        for writer in self.writers.iter_mut() {
+            match writer.prepare_commit() {
+                Ok(commit) => prepared.push(commit),
+                Err(e) => {
+                    result = Err(e.into());
+                    break;
                 }
-                _ => {}
             }
         }
+        if let Err(e) = result.and_then(|_| before_commit(&outcome)) {
+            drop(prepared);
+            self.rollback()?;
+            return Err(e);
+        }
+        let mut opstamp = 0;
+        for commit in prepared {
+            opstamp += commit.commit()?;
+        }
+        let took_ms = self.started.elapsed().as_secs_f64() * 1000.0;
+
+        // Searches open a reader on the latest commit, so the writes are visible now
+        let searchers = self.handle.searchers(ReloadPolicy::Manual)?;
+        outcome.commit = CommitStats {
+            opstamp,
+            took_ms,
+            searchable_at: chrono::Utc::now().to_rfc3339(),
+            document_count: searchers.iter().map(Searcher::num_docs).sum(),
+        };
 
-        configs
+        Ok(outcome)
     }
+}
 
-    fn register_analyzers(index: &Index) {
-        // Register Norwegian analyzer with stemming
-        let norwegian = TextAnalyzer::builder(SimpleTokenizer::default())
-            .filter(LowerCaser)
-            .filter(Stemmer::new(tantivy::tokenizer::Language::Norwegian))
-            .build();
-        index.tokenizers().register("norwegian", norwegian);
+/// An index searched by a request, with the request's queries built against its schema
+struct SearchTarget<'a> {
+    name: &'a str,
+    handle: &'a IndexHandle,
+    searchers: Vec<Searcher>,
+    query_fields: Vec<Field>,
+    default_operator: DefaultOperator,
+    query: Box<dyn Query>,
+    /// Stored, geo and search key filters, which restrict matches without scoring
+    filter_queries: Vec<Box<dyn Query>>,
+    /// Weights of the clauses with a `_name` per shard, since scorers run on a shard's
+    /// segments
+    named_weights: Vec<Vec<(String, Box<dyn Weight>)>>,
+    /// Query matching the children of a `has_child` search, for its inner hits
+    child_query: Option<Box<dyn Query>>,
+}
 
-        // Register raw analyzer (no tokenization)
-        let raw = TextAnalyzer::builder(tantivy::tokenizer::RawTokenizer::default()).build();
-        index.tokenizers().register("raw", raw);
+/// One shard of a searched index. Results of all shards of all searched indices are
+/// merged, so hits and aggregations refer to shards by their position in that list.
+struct ShardSearch<'a> {
+    target: &'a SearchTarget<'a>,
+    shard: usize,
+}
+
+impl ShardSearch<'_> {
+    fn handle(&self) -> &IndexHandle {
+        self.target.handle
     }
 
-    pub fn create_index(&self, name: &str, fields: &[FieldConfig]) -> Result<()> {
-        let mut schema_builder = Schema::builder();
-        let mut field_map = HashMap::new();
+    fn searcher(&self) -> &Searcher {
+        &self.target.searchers[self.shard]
+    }
 
-        // Always add an ID field
-        let id_field = schema_builder.add_text_field("id", STRING | STORED);
-        field_map.insert("id".to_string(), id_field);
+    fn query(&self) -> &dyn Query {
+        self.target.query.as_ref()
+    }
 
-        // Add custom fields
-        for field_config in fields {
-            let field = match field_config.field_type.as_str() {
-                "text" => {
-                    let mut options = TextOptions::default();
-                    if field_config.stored {
-                        options = options.set_stored();
-                    }
-                    if field_config.indexed {
-                        let tokenizer = match field_config.analyzer.as_str() {
-                            "norwegian" => "norwegian",
-                            "raw" => "raw",
-                            _ => "default",
-                        };
-                        options = options.set_indexing_options(
-                            TextFieldIndexing::default()
-                                .set_tokenizer(tokenizer)
-                                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
-                        );
-                    }
-                    schema_builder.add_text_field(&field_config.name, options)
-                }
-                "string" => {
-                    let options = if field_config.indexed {
-                        STRING | STORED
-                    } else {
-                        TextOptions::default().set_stored()
-                    };
-                    schema_builder.add_text_field(&field_config.name, options)
-                }
-                "i64" => {
-                    let mut options = NumericOptions::default();
-                    if field_config.stored {
-                        options = options.set_stored();
-                    }
-                    if field_config.indexed {
-                        options = options.set_indexed();
-                    }
-                    if field_config.fast {
-                        options = options.set_fast();
-                    }
-                    schema_builder.add_i64_field(&field_config.name, options)
-                }
-                "f64" => {
-                    let mut options = NumericOptions::default();
-                    if field_config.stored {
-                        options = options.set_stored();
-                    }
-                    if field_config.indexed {
-                        options = options.set_indexed();
-                    }
-                    if field_config.fast {
-                        options = options.set_fast();
-                    }
-                    schema_builder.add_f64_field(&field_config.name, options)
-                }
-                "date" => {
-                    let mut options = DateOptions::default()
-                        .set_precision(tantivy::schema::DateTimePrecision::Seconds);
-                    if field_config.stored {
-                        options = options.set_stored();
-                    }
-                    if field_config.indexed {
-                        options = options.set_indexed();
-                    }
-                    if field_config.fast {
-                        options = options.set_fast();
-                    }
-                    schema_builder.add_date_field(&field_config.name, options)
-                }
-                "json" => {
-                    // JSON field for dynamic/schemaless data
-                    let mut options = JsonObjectOptions::default();
-                    if field_config.stored {
-                        options = options.set_stored();
-                    }
-                    if field_config.indexed {
-                        options = options.set_indexing_options(
-                            TextFieldIndexing::default()
-                                .set_tokenizer("default")
-                                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
-                        );
-                    }
-                    if field_config.fast {
-                        options = options.set_expand_dots_enabled();
+    /// Call `f` with the segment and ID of each matching document, without collecting
+    /// them, so aggregations reading fast values hold nothing per match
+    fn for_each_match(&self, mut f: impl FnMut(usize, DocId)) -> tantivy::Result<()> {
+        let searcher = self.searcher();
+        let weight = self.query().weight(EnableScoring::disabled_from_searcher(searcher))?;
+        for (segment, reader) in searcher.segment_readers().iter().enumerate() {
+            let alive = reader.alive_bitset();
+            weight.for_each_no_score(reader, &mut |docs| {
+                for &doc in docs {
+                    if alive.is_none_or(|alive| alive.is_alive(doc)) {
+                        f(segment, doc);
                     }
-                    schema_builder.add_json_field(&field_config.name, options)
-                }
-                _ => {
-                    return Err(anyhow!(
-                        "Unsupported field type: {}",
-                        field_config.field_type
-                    ));
                 }
-            };
-            field_map.insert(field_config.name.clone(), field);
+            })?;
         }
+        Ok(())
+    }
+}
 
-        let schema = schema_builder.build();
-        let index_path = Path::new(&self.base_path).join(name);
-        std::fs::create_dir_all(&index_path)?;
+/// Number of threads used to search the segments of a query in parallel,
+/// from `SEARCH_THREADS` (default: number of CPUs)
+pub fn search_threads_from_env() -> usize {
+    std::env::var("SEARCH_THREADS")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        })
+}
 
-        let index = Index::create_in_dir(&index_path, schema.clone())?;
+/// Limits on the size of a search query
+#[derive(Debug, Clone, Copy)]
+pub struct QueryLimits {
+    /// Maximum query length in characters
+    pub max_length: usize,
+    /// Maximum number of terms after synonym expansion
+    pub max_clauses: usize,
+    /// Maximum number of wildcard characters (`*`, `?`), each of which becomes a regex scan
+    pub max_wildcards: usize,
+    /// Minimum number of characters before the first wildcard of a term, since a wildcard
+    /// near the start of a term scans most of the term dictionary
+    pub min_wildcard_prefix: usize,
+    /// Maximum estimated memory of a search in bytes
+    pub max_memory: usize,
+    /// Maximum number of aggregation buckets of a search
+    pub max_buckets: usize,
+}
 
-        // Register custom analyzers
-        Self::register_analyzers(&index);
+impl Default for QueryLimits {
+    fn default() -> Self {
+        Self {
+            max_length: 1024,
+            max_clauses: 256,
+            max_wildcards: 10,
+            min_wildcard_prefix: 1,
+            max_memory: 256 << 20,
+            max_buckets: 65_536,
+        }
+    }
+}
 
-        let writer = index.writer(DEFAULT_INDEX_WRITER_MEMORY)?;
+/// Query limits from `MAX_QUERY_LENGTH`, `MAX_QUERY_CLAUSES`, `MAX_QUERY_WILDCARDS`,
+/// `MIN_WILDCARD_PREFIX`, `MAX_QUERY_MEMORY` (a size such as `256MB`) and
+/// `MAX_AGGREGATION_BUCKETS`
+pub fn query_limits_from_env() -> QueryLimits {
+    let var = |name: &str, default: usize| {
+        std::env::var(name)
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(default)
+    };
+    let defaults = QueryLimits::default();
+    QueryLimits {
+        max_length: var("MAX_QUERY_LENGTH", defaults.max_length),
+        max_clauses: var("MAX_QUERY_CLAUSES", defaults.max_clauses),
+        max_wildcards: var("MAX_QUERY_WILDCARDS", defaults.max_wildcards),
+        min_wildcard_prefix: var("MIN_WILDCARD_PREFIX", defaults.min_wildcard_prefix),
+        max_memory: std::env::var("MAX_QUERY_MEMORY")
+            .ok()
+            .and_then(|v| validation::parse_byte_size(&v))
+            .unwrap_or(defaults.max_memory),
+        max_buckets: var("MAX_AGGREGATION_BUCKETS", defaults.max_buckets),
+    }
+}
 
-        let handle = IndexHandle {
-            index,
-            schema,
-            writer: Arc::new(RwLock::new(writer)),
-            field_map,
-            field_configs: fields.to_vec(),
+impl SearchEngine {
+    pub fn new(
+        base_path: &str,
+        search_threads: usize,
+        query_limits: QueryLimits,
+        filter_cache: FilterCache,
+    ) -> Result<Self> {
+        std::fs::create_dir_all(base_path)?;
+
+        let executor = if search_threads > 1 {
+            Executor::multi_thread(search_threads, "search-")?
+        } else {
+            Executor::single_thread()
         };
 
-        self.indices
-            .write()
-            .unwrap()
-            .insert(name.to_string(), handle);
+        // Load synonyms from file if exists
+        let synonyms_path = Path::new(base_path).join("synonyms.json");
+        let synonyms: HashMap<String, Vec<SynonymGroup>> = if synonyms_path.exists() {
+            let content = std::fs::read_to_string(&synonyms_path)?;
+            serde_json::from_str(&content).unwrap_or_default()
+        } else {
+            HashMap::new()
+        };
 
-        Ok(())
-    }
+        // Load pinned rules from file if exists
+        let pinned_path = Path::new(base_path).join("pinned_rules.json");
+        let pinned_rules: HashMap<String, Vec<PinnedRule>> = if pinned_path.exists() {
+            let content = std::fs::read_to_string(&pinned_path)?;
+            serde_json::from_str(&content).unwrap_or_default()
+        } else {
+            HashMap::new()
+        };
 
-    pub fn add_documents(&self, index_name: &str, documents: &[Document]) -> Result<()> {
-        let indices = self.indices.read().unwrap();
-        let handle = indices
-            .get(index_name)
-            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        // Load stored filters from file if exists
+        let filters_path = Path::new(base_path).join("stored_filters.json");
+        let stored_filters: HashMap<String, Vec<StoredFilter>> = if filters_path.exists() {
+            let content = std::fs::read_to_string(&filters_path)?;
+            serde_json::from_str(&content).unwrap_or_default()
+        } else {
+            HashMap::new()
+        };
 
-        let mut writer = handle.writer.write().unwrap();
+        // Load prompt templates from file if exists
+        let templates_path = Path::new(base_path).join("prompt_templates.json");
+        let prompt_templates: HashMap<String, Vec<PromptTemplate>> = if templates_path.exists() {
+            let content = std::fs::read_to_string(&templates_path)?;
+            serde_json::from_str(&content).unwrap_or_default()
+        } else {
+            HashMap::new()
+        };
 
-        for doc in documents {
-            let mut tantivy_doc = TantivyDocument::default();
-
-            // Add ID field
-            let id_field = handle.field_map.get("id").unwrap();
-            tantivy_doc.add_text(*id_field, &doc.id);
-
-            // Add custom fields
-            for (field_name, value) in &doc.fields {
-                if let Some(field) = handle.field_map.get(field_name) {
-                    // Get field config to check type
-                    let field_type = handle
-                        .field_configs
-                        .iter()
-                        .find(|fc| fc.name == *field_name)
-                        .map(|fc| fc.field_type.as_str())
-                        .unwrap_or("text");
-
-                    match field_type {
-                        "date" => {
-                            // Parse date from RFC3339 string or Unix timestamp
-                            if let Some(date_str) = value.as_str() {
-                                if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(date_str) {
-                                    let tantivy_dt =
-                                        tantivy::DateTime::from_timestamp_secs(dt.timestamp());
-                                    tantivy_doc.add_date(*field, tantivy_dt);
-                                }
-                            } else if let Some(ts) = value.as_i64() {
-                                let tantivy_dt = tantivy::DateTime::from_timestamp_secs(ts);
-                                tantivy_doc.add_date(*field, tantivy_dt);
-                            }
-                        }
-                        "json" => {
-                            // JSON field - convert serde_json::Value to OwnedValue
-                            use tantivy::schema::OwnedValue;
-                            let owned_value = OwnedValue::from(value.clone());
-                            tantivy_doc.add_field_value(*field, &owned_value);
-                        }
-                        _ => match value {
-                            serde_json::Value::String(s) => {
-                                tantivy_doc.add_text(*field, s);
-                            }
-                            serde_json::Value::Number(n) => {
-                                if let Some(i) = n.as_i64() {
-                                    tantivy_doc.add_i64(*field, i);
-                                } else if let Some(f) = n.as_f64() {
-                                    tantivy_doc.add_f64(*field, f);
-                                }
-                            }
-                            serde_json::Value::Bool(b) => {
-                                tantivy_doc.add_i64(*field, if *b { 1 } else { 0 });
-                            }
-                            _ => {}
-                        },
-                    }
-                }
-            }
+        // Load search presets from file if exists
+        let presets_path = Path::new(base_path).join("search_presets.json");
+        let search_presets: HashMap<String, Vec<SearchPreset>> = if presets_path.exists() {
+            let content = std::fs::read_to_string(&presets_path)?;
+            serde_json::from_str(&content).unwrap_or_default()
+        } else {
+            HashMap::new()
+        };
 
-            writer.add_document(tantivy_doc)?;
-        }
+        // Load rolling index aliases from file if exists
+        let aliases_path = Path::new(base_path).join("rollover_aliases.json");
+        let rollover_aliases: HashMap<String, RolloverAlias> = if aliases_path.exists() {
+            let content = std::fs::read_to_string(&aliases_path)?;
+            serde_json::from_str(&content).unwrap_or_default()
+        } else {
+            HashMap::new()
+        };
 
-        writer.commit()?;
+        Ok(Self {
+            base_path: base_path.to_string(),
+            indices: Arc::new(RwLock::new(HashMap::new())),
+            synonyms: Arc::new(RwLock::new(synonyms)),
+            pinned_rules: Arc::new(RwLock::new(pinned_rules)),
+            stored_filters: Arc::new(RwLock::new(stored_filters)),
+            prompt_templates: Arc::new(RwLock::new(prompt_templates)),
+            search_presets: Arc::new(RwLock::new(search_presets)),
+            rollover_aliases: Arc::new(RwLock::new(rollover_aliases)),
+            executor,
+            query_limits,
+            filter_cache: Arc::new(filter_cache),
+            config_generation: AtomicU64::new(0),
+            creating: Mutex::new(HashSet::new()),
+            closed: RwLock::new(HashSet::new()),
+            shadows: RwLock::new(HashMap::new()),
+        })
+    }
+
+    /// Changes whenever search results can change without a commit to the index
+    pub fn config_generation(&self) -> u64 {
+        self.config_generation.load(Ordering::SeqCst)
+    }
+
+    fn bump_config_generation(&self) {
+        self.config_generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Save stored filters to disk
+    fn save_stored_filters(&self) -> Result<()> {
+        let filters = self.stored_filters.read().unwrap();
+        let filters_path = Path::new(&self.base_path).join("stored_filters.json");
+        let content = serde_json::to_string_pretty(&*filters)?;
+        std::fs::write(filters_path, content)?;
         Ok(())
     }
 
-    #[allow(clippy::too_many_arguments)]
-    #[allow(dead_code)]
-    pub fn search(
+    /// Check that a stored filter parses against the index schema
+    pub fn validate_stored_filter(
         &self,
         index_name: &str,
-        query_str: &str,
-        limit: usize,
-        offset: usize,
-        fields: &[String],
-        highlight_options: Option<&HighlightOptions>,
-        aggregations: &[AggregationRequest],
-    ) -> SearchResult {
-        self.search_internal(
-            index_name,
-            query_str,
-            limit,
-            offset,
-            fields,
-            highlight_options,
-            aggregations,
+        filter: &StoredFilter,
+    ) -> std::result::Result<(), SearchError> {
+        if filter.name.trim().is_empty() {
+            return Err(SearchError::InvalidQuery("Filter name cannot be empty".to_string()));
+        }
+
+        let write_index = self.resolve_write_index(index_name);
+        let indices = self.read_open(&write_index);
+        let handle = indices
+            .get(&write_index)
+            .ok_or_else(|| SearchError::IndexNotFound(index_name.to_string()))?;
+
+        Self::build_query(
+            handle,
+            &filter.query,
+            &Self::default_query_fields(handle),
             false,
-            None,
-            None,
-        )
+            handle.settings.default_operator,
+        )?;
+        for geo_filter in &filter.geo_filters {
+            Self::geo_filter_query(handle, geo_filter)?;
+        }
+        Ok(())
     }
 
-    #[allow(clippy::too_many_arguments)]
-    pub fn search_with_options(
+    /// Check that the fields a search preset names exist in the index
+    pub fn validate_search_preset(
         &self,
         index_name: &str,
-        query_str: &str,
-        limit: usize,
-        offset: usize,
-        fields: &[String],
-        highlight_options: Option<&HighlightOptions>,
-        aggregations: &[AggregationRequest],
-        fuzzy: bool,
-        sort: Option<&SortOption>,
-        minimum_should_match: Option<usize>,
-    ) -> SearchResult {
-        self.search_internal(
-            index_name,
-            query_str,
-            limit,
-            offset,
-            fields,
-            highlight_options,
-            aggregations,
-            fuzzy,
-            sort,
-            minimum_should_match,
-        )
+        preset: &SearchPreset,
+    ) -> std::result::Result<(), SearchError> {
+        if preset.name.trim().is_empty() {
+            return Err(SearchError::InvalidQuery("Preset name cannot be empty".to_string()));
+        }
+
+        let write_index = self.resolve_write_index(index_name);
+        let indices = self.read_open(&write_index);
+        let handle = indices
+            .get(&write_index)
+            .ok_or_else(|| SearchError::IndexNotFound(index_name.to_string()))?;
+
+        let highlight_fields = preset.highlight.iter().flat_map(|options| &options.fields);
+        let fields = preset
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| (format!("fields[{}]", i), field))
+            .chain(preset.boost.keys().map(|field| (format!("boost.{}", field), field)))
+            .chain(
+                highlight_fields
+                    .enumerate()
+                    .map(|(i, field)| (format!("highlight.fields[{}]", i), field)),
+            );
+        for (path, field) in fields {
+            if !Self::has_field(handle, field) {
+                return Err(SearchError::InvalidQuery(format!(
+                    "{}: unknown field '{}'",
+                    path, field
+                )));
+            }
+        }
+        Ok(())
     }
 
-    #[allow(clippy::too_many_arguments)]
-    fn search_internal(
+    /// Check a search request against the index schema before running it, so typos in
+    /// field names are reported (with the offending path) instead of matching nothing
+    pub fn validate_search_request(
         &self,
         index_name: &str,
-        query_str: &str,
-        limit: usize,
-        offset: usize,
-        fields: &[String],
-        highlight_options: Option<&HighlightOptions>,
-        aggregations: &[AggregationRequest],
-        fuzzy: bool,
-        sort: Option<&SortOption>,
-        minimum_should_match: Option<usize>,
-    ) -> SearchResult {
-        let start = std::time::Instant::now();
-
-        // Get pinned document IDs for this query BEFORE synonym expansion
-        // (we want to match on the original user query)
-        let pinned_ids = self.get_pinned_doc_ids(index_name, query_str);
-        let pinned_count = pinned_ids.len();
-
-        // Expand query with synonyms before processing
-        let expanded_query = self.expand_query_with_synonyms(index_name, query_str);
-        let query_str = expanded_query.as_str();
+        request: &SearchRequest,
+    ) -> std::result::Result<(), SearchError> {
+        let invalid = |path: String, message: String| {
+            Err(SearchError::InvalidQuery(format!("{}: {}", path, message)))
+        };
+        let unknown = |path: String, field: &str| invalid(path, format!("unknown field '{}'", field));
+        // Checked before taking the lock on the searched indices
+        self.validate_lookups(&request.lookups)?;
+
+        // A field searched across several indices only needs to exist in one of them
+        let index_names = self.search_indices(index_name);
+        let indices = self.read_open_all(&index_names);
+        let handles: Vec<&IndexHandle> = index_names
+            .iter()
+            .filter_map(|name| indices.get(name))
+            .collect();
+        if handles.is_empty() {
+            return Err(SearchError::IndexNotFound(index_name.to_string()));
+        }
+        let has_field = |name: &str| handles.iter().any(|handle| Self::has_field(handle, name));
+        // Limits set in the index settings; across several indices the strictest applies
+        let strictest = |limit: fn(&IndexSettings) -> Option<usize>| {
+            handles.iter().filter_map(|handle| limit(&handle.settings)).min()
+        };
 
-        let indices = self.indices.read().unwrap();
-        let handle = indices
-            .get(index_name)
-            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        if let Some(max) = strictest(|settings| settings.max_result_window) {
+            let window = request.offset.saturating_add(request.limit);
+            if window > max {
+                return invalid(
+                    "limit".to_string(),
+                    format!(
+                        "offset + limit is {}, above the index's max_result_window of {}",
+                        window, max
+                    ),
+                );
+            }
+        }
 
-        let reader = handle
-            .index
-            .reader_builder()
-            .reload_policy(ReloadPolicy::OnCommitWithDelay)
-            .try_into()?;
+        for (i, field) in request.fields.iter().enumerate() {
+            if !has_field(field) {
+                return unknown(format!("fields[{}]", i), field);
+            }
+        }
+        for field in request.boost.keys() {
+            if !has_field(field) {
+                return unknown(format!("boost.{}", field), field);
+            }
+        }
 
-        let searcher = reader.searcher();
+        if let Some(sort) = &request.sort {
+            if sort.field != RANDOM_SORT_FIELD {
+                let Some(config) = handles.iter().find_map(|handle| {
+                    handle.field_configs.iter().find(|fc| fc.name == sort.field)
+                }) else {
+                    return unknown("sort.field".to_string(), &sort.field);
+                };
+                if !config.fast {
+                    return invalid(
+                        "sort.field".to_string(),
+                        format!("field '{}' must be configured with fast: true", sort.field),
+                    );
+                }
+                if !matches!(config.field_type.as_str(), "i64" | "f64" | "date") {
+                    return invalid(
+                        "sort.field".to_string(),
+                        format!(
+                            "field '{}' of type '{}' cannot be sorted on",
+                            sort.field, config.field_type
+                        ),
+                    );
+                }
+                if sort.seed.is_some() {
+                    return invalid(
+                        "sort.seed".to_string(),
+                        format!("seed only applies to {} sorting", RANDOM_SORT_FIELD),
+                    );
+                }
+            }
+        }
 
-        // Build query parser for specified fields or all text fields
-        let query_fields: Vec<Field> = if fields.is_empty() {
-            // Only include text fields in the default query parser to avoid parse errors
-            handle
-                .field_map
+        if let Some(regex) = &request.regex {
+            let Some(config) = handles.iter().find_map(|handle| {
+                handle.field_configs.iter().find(|fc| fc.name == regex.field)
+            }) else {
+                return unknown("regex.field".to_string(), &regex.field);
+            };
+            if !config.indexed || !matches!(config.field_type.as_str(), "text" | "string") {
+                return invalid(
+                    "regex.field".to_string(),
+                    format!("field '{}' must be an indexed text or string field", regex.field),
+                );
+            }
+            // Like wildcard terms, patterns need a literal prefix to narrow the terms scanned
+            let min_prefix = handles
                 .iter()
-                .filter(|(_, field)| {
-                    matches!(
-                        handle.schema.get_field_entry(**field).field_type(),
-                        FieldType::Str(_)
-                    )
+                .map(|handle| {
+                    handle
+                        .settings
+                        .min_wildcard_prefix
+                        .unwrap_or(self.query_limits.min_wildcard_prefix)
                 })
-                .map(|(_, field)| *field)
-                .collect()
-        } else {
-            fields
-                .iter()
-                .filter_map(|f| handle.field_map.get(f).copied())
-                .collect()
-        };
-
-        let mut query = Self::build_query(handle, query_str, &query_fields, fuzzy)?;
-
-        // Apply minimum_should_match if specified
-        // This wraps the query in a BooleanQuery with the minimum_should_match setting
-        if let Some(min_match) = minimum_should_match {
-            if min_match > 0 {
-                let mut bool_query = BooleanQuery::from(vec![(Occur::Should, query)]);
-                bool_query.set_minimum_number_should_match(min_match);
-                query = Box::new(bool_query);
+                .max()
+                .unwrap_or(self.query_limits.min_wildcard_prefix);
+            let prefix = query_syntax::regex_prefix(&regex.pattern);
+            if prefix.chars().count() < min_prefix {
+                return invalid(
+                    "regex.pattern".to_string(),
+                    format!(
+                        "needs {} or more literal characters before its first regex operator",
+                        min_prefix
+                    ),
+                );
             }
         }
 
-        // Get total document count that matches the query
-        let mut total = searcher.search(query.as_ref(), &tantivy::collector::Count)?;
+        if let Some(highlight) = &request.highlight {
+            for (i, field) in highlight.fields.iter().enumerate() {
+                if !has_field(field) {
+                    return unknown(format!("highlight.fields[{}]", i), field);
+                }
+            }
+            // Without a list, the searched fields are highlighted
+            let max_fields = strictest(|settings| settings.max_highlight_fields);
+            if let Some(max) = max_fields.filter(|_| highlight.enabled) {
+                let highlighted = if !highlight.fields.is_empty() {
+                    highlight.fields.len()
+                } else {
+                    handles
+                        .iter()
+                        .map(|handle| {
+                            if request.fields.is_empty() {
+                                Self::default_query_fields(handle).len()
+                            } else {
+                                Self::expand_query_fields(handle, &request.fields).len()
+                            }
+                        })
+                        .max()
+                        .unwrap_or(0)
+                };
+                if highlighted > max {
+                    return invalid(
+                        "highlight.fields".to_string(),
+                        format!(
+                            "{} fields would be highlighted, above the index's \
+                             max_highlight_fields of {}",
+                            highlighted, max
+                        ),
+                    );
+                }
+            }
+        }
 
-        // Fallback: if no hits, try a keyword-only query (removes question/stop words)
-        if total == 0 {
-            if let Some(fallback_query) = Self::fallback_query_string(query_str) {
-                if fallback_query != query_str {
-                    let fallback = Self::build_query(handle, &fallback_query, &query_fields, fuzzy)?;
-                    let fallback_total = searcher.search(fallback.as_ref(), &tantivy::collector::Count)?;
-                    if fallback_total > 0 {
-                        query = fallback;
-                        total = fallback_total;
-                    }
+        let mut aggregation_names = HashSet::new();
+        for (i, agg) in request.aggregations.iter().enumerate() {
+            if !aggregation_names.insert(agg.name.as_str()) {
+                return invalid(
+                    format!("aggregations[{}].name", i),
+                    format!("duplicate aggregation name '{}'", agg.name),
+                );
+            }
+            if !has_field(&agg.field) {
+                return unknown(format!("aggregations[{}].field", i), &agg.field);
+            }
+            if let Some(weight_field) = &agg.weight_field {
+                if !has_field(weight_field) {
+                    return unknown(format!("aggregations[{}].weight_field", i), weight_field);
+                }
+            }
+            // Requested bucket counts are checked here; all buckets, including those of
+            // histograms, are estimated before the search runs
+            if let Some(max) = strictest(|settings| settings.max_aggregation_buckets) {
+                let requested = match agg.agg_type.as_str() {
+                    "range" => agg.ranges.as_ref().map(Vec::len),
+                    _ => agg.size,
+                };
+                if let Some(requested) = requested.filter(|requested| *requested > max) {
+                    return invalid(
+                        format!("aggregations[{}]", i),
+                        format!(
+                            "{} buckets requested, above the index's max_aggregation_buckets \
+                             of {}",
+                            requested, max
+                        ),
+                    );
                 }
             }
         }
 
-        let mut hits = Vec::new();
-        let mut add_hit = |score: f32, doc_address: tantivy::DocAddress| -> Result<()> {
-            let retrieved_doc: TantivyDocument = searcher.doc(doc_address)?;
-            let mut field_values = HashMap::new();
+        for (i, filter) in request.geo_filters.iter().enumerate() {
+            let sub_fields = handles
+                .iter()
+                .map(|handle| Self::geo_sub_fields(handle, &filter.field))
+                .reduce(|first, next| first.or(next));
+            if let Some(Err(e)) = sub_fields {
+                return invalid(format!("geo_filters[{}].field", i), e.to_string());
+            }
+        }
 
-            for (field_name, field) in &handle.field_map {
-                if let Some(field_value) = retrieved_doc.get_all(*field).next() {
-                    let owned_value: tantivy::schema::OwnedValue = field_value.into();
-                    let value = match owned_value {
-                        tantivy::schema::OwnedValue::Str(s) => {
-                            serde_json::Value::String(s.to_string())
-                        }
-                        tantivy::schema::OwnedValue::U64(n) => serde_json::json!(n),
-                        tantivy::schema::OwnedValue::I64(n) => serde_json::json!(n),
-                        tantivy::schema::OwnedValue::F64(n) => serde_json::json!(n),
-                        tantivy::schema::OwnedValue::Date(d) => {
-                            serde_json::Value::String(d.into_utc().to_string())
-                        }
-                        _ => continue,
-                    };
-                    field_values.insert(field_name.clone(), value);
-                }
+        // Clause names are reported per hit, so each has to tell its clause apart
+        let clause_names = request
+            .geo_filters
+            .iter()
+            .enumerate()
+            .map(|(i, filter)| (format!("geo_filters[{}]._name", i), filter.name.as_ref()))
+            .chain([
+                (
+                    "has_child._name".to_string(),
+                    request.has_child.as_ref().and_then(|c| c.name.as_ref()),
+                ),
+                (
+                    "has_parent._name".to_string(),
+                    request.has_parent.as_ref().and_then(|p| p.name.as_ref()),
+                ),
+                (
+                    "regex._name".to_string(),
+                    request.regex.as_ref().and_then(|r| r.name.as_ref()),
+                ),
+            ]);
+        let mut query_names = HashSet::new();
+        for (path, name) in clause_names {
+            let Some(name) = name else {
+                continue;
+            };
+            if name.trim().is_empty() {
+                return invalid(path, "name cannot be empty".to_string());
+            }
+            if !query_names.insert(name) {
+                return invalid(path, format!("duplicate clause name '{}'", name));
             }
+        }
 
-            // Generate highlights if requested
-            let highlights = if let Some(opts) = highlight_options {
-                if opts.enabled {
-                    let mut highlight_map = HashMap::new();
-                    let highlight_fields: Vec<&String> = if opts.fields.is_empty() {
-                        query_fields
-                            .iter()
-                            .filter_map(|f| {
-                                handle.field_map.iter().find_map(|(name, field)| {
-                                    if field == f {
-                                        Some(name)
-                                    } else {
-                                        None
-                                    }
-                                })
-                            })
-                            .collect()
-                    } else {
-                        opts.fields.iter().collect()
-                    };
-
-                    for field_name in highlight_fields {
-                        if let Some(field) = handle.field_map.get(field_name) {
-                            // Check if this is a text field
-                            let field_entry = handle.schema.get_field_entry(*field);
-                            if let FieldType::Str(_) = field_entry.field_type() {
-                                if let Ok(snippet_gen) = tantivy::snippet::SnippetGenerator::create(
-                                    &searcher,
-                                    query.as_ref(),
-                                    *field,
-                                ) {
-                                    let mut snippet = snippet_gen.snippet_from_doc(&retrieved_doc);
-                                    // Use custom highlight tags via the Snippet method
-                                    snippet.set_snippet_prefix_postfix(&opts.pre_tag, &opts.post_tag);
-                                    let highlighted = snippet.to_html();
-                                    if !highlighted.is_empty() {
-                                        highlight_map.insert(field_name.clone(), vec![highlighted]);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    if highlight_map.is_empty() {
-                        None
-                    } else {
-                        Some(highlight_map)
-                    }
-                } else {
-                    None
-                }
-            } else {
-                None
+        let joins = [
+            ("has_child", request.has_child.as_ref().map(|c| &c.query)),
+            ("has_parent", request.has_parent.as_ref().map(|p| &p.query)),
+        ];
+        for (path, query) in joins {
+            let Some(query) = query else {
+                continue;
             };
+            if query.trim().is_empty() {
+                return invalid(format!("{}.query", path), "query cannot be empty".to_string());
+            }
+            // Children are found through the parent field of each searched index
+            let unlinked = index_names.iter().find(|name| {
+                indices.get(*name).is_some_and(|handle| handle.settings.parent_field.is_none())
+            });
+            if let Some(name) = unlinked {
+                return invalid(
+                    path.to_string(),
+                    format!("index '{}' has no parent_field setting", name),
+                );
+            }
+        }
+        for (i, column) in request.columns.iter().enumerate() {
+            if !matches!(column.as_str(), "id" | "_index" | "_score") && !has_field(column) {
+                return unknown(format!("columns[{}]", i), column);
+            }
+        }
 
-            let id = field_values
-                .get("id")
-                .and_then(|v| v.as_str())
-                .unwrap_or("unknown")
-                .to_string();
+        if let Some(has_child) = &request.has_child {
+            if has_child.inner_hits > MAX_INNER_HITS {
+                return invalid(
+                    "has_child.inner_hits".to_string(),
+                    format!("at most {} inner hits are allowed", MAX_INNER_HITS),
+                );
+            }
+        }
 
-            hits.push(SearchHit {
-                id,
-                score,
-                fields: field_values,
-                highlights,
-            });
+        let stored_filters = self.get_stored_filters(index_name);
+        for (i, name) in request.filters.iter().enumerate() {
+            if !stored_filters.iter().any(|filter| &filter.name == name) {
+                return invalid(
+                    format!("filters[{}]", i),
+                    format!("stored filter '{}' not found", name),
+                );
+            }
+        }
 
-            Ok(())
-        };
+        for (i, field) in request.exclude_fields.iter().enumerate() {
+            if request.include_fields.contains(field) {
+                return invalid(
+                    format!("exclude_fields[{}]", i),
+                    format!("field '{}' is also listed in include_fields", field),
+                );
+            }
+        }
 
-        if let Some(sort) = sort {
-            let field_name = sort.field.as_str();
-            let _field = handle
-                .field_map
-                .get(field_name)
-                .ok_or_else(|| anyhow!("Sort field not found: {}", field_name))?;
-            let field_config = handle
-                .field_configs
-                .iter()
-                .find(|fc| fc.name == field_name)
-                .ok_or_else(|| anyhow!("Sort field not found: {}", field_name))?;
-            if !field_config.fast {
-                return Err(anyhow!(
-                    "Sort field '{}' must be configured with fast: true",
-                    field_name
-                ));
+        for (i, lookup) in request.lookups.iter().enumerate() {
+            if !has_field(&lookup.field) {
+                return unknown(format!("lookups[{}].field", i), &lookup.field);
             }
+        }
 
-            let order = match sort.order {
-                SortOrder::Asc => Order::Asc,
-                SortOrder::Desc => Order::Desc,
-            };
+        Ok(())
+    }
 
-            // Fetch extra results to ensure pinned documents are included
-            let fetch_limit = limit + pinned_count;
+    /// Check the index and fields of each lookup
+    fn validate_lookups(&self, lookups: &[LookupRequest]) -> std::result::Result<(), SearchError> {
+        let invalid = |path: String, message: String| {
+            Err(SearchError::InvalidQuery(format!("{}: {}", path, message)))
+        };
+        if lookups.len() > MAX_LOOKUPS {
+            return invalid(
+                "lookups".to_string(),
+                format!("at most {} lookups are allowed", MAX_LOOKUPS),
+            );
+        }
 
-            match field_config.field_type.as_str() {
-                "i64" => {
-                    let collector = TopDocs::with_limit(fetch_limit)
-                        .and_offset(offset)
-                        .order_by_fast_field::<i64>(field_name, order);
-                    let top_docs = searcher.search(query.as_ref(), &collector)?;
-                    for (_sort_value, doc_address) in top_docs {
-                        let score = query
-                            .explain(&searcher, doc_address)
-                            .map(|e| e.value())
-                            .unwrap_or(0.0);
-                        add_hit(score, doc_address)?;
-                    }
-                }
-                "f64" => {
-                    let collector = TopDocs::with_limit(fetch_limit)
-                        .and_offset(offset)
-                        .order_by_fast_field::<f64>(field_name, order);
-                    let top_docs = searcher.search(query.as_ref(), &collector)?;
-                    for (_sort_value, doc_address) in top_docs {
-                        let score = query
-                            .explain(&searcher, doc_address)
-                            .map(|e| e.value())
-                            .unwrap_or(0.0);
-                        add_hit(score, doc_address)?;
-                    }
-                }
-                "date" => {
-                    let collector = TopDocs::with_limit(fetch_limit)
-                        .and_offset(offset)
-                        .order_by_fast_field::<tantivy::DateTime>(field_name, order);
-                    let top_docs = searcher.search(query.as_ref(), &collector)?;
-                    for (_sort_value, doc_address) in top_docs {
-                        let score = query
-                            .explain(&searcher, doc_address)
-                            .map(|e| e.value())
-                            .unwrap_or(0.0);
-                        add_hit(score, doc_address)?;
-                    }
-                }
-                _ => {
-                    return Err(anyhow!(
-                        "Sorting is only supported on fast i64, f64, date, or string fields. Field '{}' is type '{}'.",
-                        field_name,
-                        field_config.field_type
-                    ));
+        let mut targets = HashSet::new();
+        for (i, lookup) in lookups.iter().enumerate() {
+            if index_patterns::is_pattern(&lookup.index) {
+                return invalid(
+                    format!("lookups[{}].index", i),
+                    "documents can't be looked up in an index pattern".to_string(),
+                );
+            }
+            let index_names = self.search_indices(&lookup.index);
+            let indices = self.read_open_all(&index_names);
+            let handles: Vec<&IndexHandle> = index_names
+                .iter()
+                .filter_map(|name| indices.get(name))
+                .collect();
+            if handles.is_empty() {
+                return invalid(
+                    format!("lookups[{}].index", i),
+                    format!("index '{}' not found", lookup.index),
+                );
+            }
+            for (j, field) in lookup.fields.iter().enumerate() {
+                if !handles.iter().any(|handle| Self::has_field(handle, field)) {
+                    return invalid(
+                        format!("lookups[{}].fields[{}]", i, j),
+                        format!("unknown field '{}' in index '{}'", field, lookup.index),
+                    );
                 }
             }
-        } else {
-            // Fetch extra results to ensure pinned documents are included
-            let fetch_limit = offset + limit + pinned_count;
-            let top_docs = searcher.search(query.as_ref(), &TopDocs::with_limit(fetch_limit))?;
-            for (score, doc_address) in top_docs.into_iter().skip(offset) {
-                add_hit(score, doc_address)?;
+            if !targets.insert(lookup.target()) {
+                return invalid(
+                    format!("lookups[{}].as", i),
+                    format!("another lookup is embedded as '{}'", lookup.target()),
+                );
             }
         }
+        Ok(())
+    }
 
-        // Process aggregations using Tantivy's built-in AggregationCollector
-        let agg_results = if !aggregations.is_empty() {
-            match Self::build_aggregation_request(aggregations) {
-                Ok(agg_req) => {
-                    let collector = AggregationCollector::from_aggs(agg_req, Default::default());
-                    match searcher.search(query.as_ref(), &collector) {
-                        Ok(results) => Some(results),
-                        Err(e) => {
-                            tracing::warn!("Aggregation failed: {}", e);
-                            None
-                        }
-                    }
-                }
-                Err(e) => {
-                    tracing::warn!("Failed to build aggregation request: {}", e);
-                    None
+    /// Stored fields of an index, or of all indices searched through a name, in schema
+    /// order. `string` fields are always stored.
+    pub fn stored_field_names(&self, index_name: &str) -> Vec<String> {
+        let index_names = self.search_indices(index_name);
+        let indices = self.read_open_all(&index_names);
+        let mut names: Vec<String> = Vec::new();
+        for handle in index_names.iter().filter_map(|name| indices.get(name)) {
+            for config in &handle.field_configs {
+                if (config.stored || config.field_type == "string") && !names.contains(&config.name)
+                {
+                    names.push(config.name.clone());
                 }
             }
-        } else {
-            None
-        };
+        }
+        names
+    }
 
-        let took_ms = start.elapsed().as_secs_f64() * 1000.0;
+    /// Whether a field exists in the index; sub-paths of JSON fields count as existing
+    fn has_field(handle: &IndexHandle, name: &str) -> bool {
+        handle.field_map.contains_key(name)
+            || name
+                .split_once('.')
+                .is_some_and(|(base, _)| handle.field_map.contains_key(base))
+    }
 
-        // Reorder hits based on pinned rules and truncate to requested limit
-        let hits = self.apply_pinned_results(&pinned_ids, hits, limit);
+    /// Add stored filters for an index, replacing existing filters with the same name
+    pub fn add_stored_filters(&self, index_name: &str, filters: Vec<StoredFilter>) -> Result<()> {
+        let mut stored = self.stored_filters.write().unwrap();
+        let entry = stored.entry(index_name.to_string()).or_default();
+        for filter in filters {
+            entry.retain(|existing| existing.name != filter.name);
+            entry.push(filter);
+        }
+        drop(stored);
+        self.bump_config_generation();
+        self.save_stored_filters()?;
+        Ok(())
+    }
 
-        Ok((hits, total, took_ms, agg_results))
+    /// Settings of an index, if it exists. A rolling index has the settings of its write
+    /// index.
+    pub fn index_settings(&self, index_name: &str) -> Option<IndexSettings> {
+        let index_name = self.resolve_write_index(index_name);
+        self.read_open(&index_name)
+            .get(&index_name)
+            .map(|handle| handle.settings.clone())
     }
 
-    /// Apply pinned results - move pinned documents to the top in the specified order
-    /// and truncate to the requested limit
-    fn apply_pinned_results(
+    /// Replace an index's settings and persist them. A rolling index's settings are
+    /// changed on its write index, and later generations copy them.
+    pub fn update_index_settings(
         &self,
-        pinned_ids: &[String],
-        mut hits: Vec<SearchHit>,
-        limit: usize,
-    ) -> Vec<SearchHit> {
-        if pinned_ids.is_empty() {
-            // No pinned rules, just truncate to limit
-            hits.truncate(limit);
-            return hits;
-        }
+        index_name: &str,
+        settings: IndexSettings,
+    ) -> std::result::Result<(), SearchError> {
+        let index_name = self.resolve_write_index(index_name);
+        drop(self.read_open(&index_name));
+        let mut indices = self.indices.write().unwrap();
+        let handle = indices
+            .get_mut(&index_name)
+            .ok_or_else(|| SearchError::IndexNotFound(index_name.clone()))?;
 
-        // Extract pinned hits from the result set (maintain pinned order)
-        let mut pinned_hits: Vec<SearchHit> = Vec::new();
-        let mut remaining_hits: Vec<SearchHit> = Vec::new();
+        let index_path = Path::new(&self.base_path).join(&index_name);
+        Self::save_index_settings(&index_path, &settings)?;
+        handle.settings = settings;
+        self.bump_config_generation();
+        Ok(())
+    }
 
-        // Create a set of pinned IDs for quick lookup
-        let pinned_set: std::collections::HashSet<&String> = pinned_ids.iter().collect();
+    /// Writes and deletes are rejected while an index is read-only
+    fn check_writable(
+        handle: &IndexHandle,
+        index_name: &str,
+    ) -> std::result::Result<(), SearchError> {
+        if handle.settings.read_only {
+            return Err(SearchError::ReadOnly(index_name.to_string()));
+        }
+        Ok(())
+    }
 
-        // Separate pinned and non-pinned hits
-        for hit in hits.drain(..) {
-            if pinned_set.contains(&hit.id) {
-                pinned_hits.push(hit);
-            } else {
-                remaining_hits.push(hit);
-            }
+    /// Opstamp of the last commit to an index, which changes whenever documents are
+    /// added or deleted. For a rolling index, the sum over its generations.
+    pub fn commit_opstamp(&self, index_name: &str) -> Option<u64> {
+        let index_names = self.search_indices(index_name);
+        let indices = self.read_open_all(&index_names);
+        index_names
+            .iter()
+            .map(|index_name| indices.get(index_name)?.opstamp().ok())
+            .sum()
+    }
+
+    /// Commit generation of an index: the opstamp of its last commit, which write
+    /// responses return. A rolling index has the generation of its write index, where
+    /// writes go; index patterns have none.
+    pub fn index_generation(&self, index_name: &str) -> Option<u64> {
+        if index_patterns::is_pattern(index_name) {
+            return None;
         }
+        self.commit_opstamp(&self.resolve_write_index(index_name))
+    }
 
-        // Sort pinned hits according to the order in pinned_ids
-        pinned_hits.sort_by(|a, b| {
-            let pos_a = pinned_ids.iter().position(|id| id == &a.id).unwrap_or(usize::MAX);
-            let pos_b = pinned_ids.iter().position(|id| id == &b.id).unwrap_or(usize::MAX);
-            pos_a.cmp(&pos_b)
-        });
+    /// Field definitions of an index, if it exists. A rolling index has the fields of its
+    /// write index.
+    pub fn index_fields(&self, index_name: &str) -> Option<Vec<FieldConfig>> {
+        let index_name = self.resolve_write_index(index_name);
+        self.read_open(&index_name)
+            .get(&index_name)
+            .map(|handle| handle.field_configs.clone())
+    }
 
-        // Combine: pinned first, then remaining
-        pinned_hits.extend(remaining_hits);
-        
-        // Truncate to the requested limit
-        pinned_hits.truncate(limit);
-        pinned_hits
+    /// What each field of an index supports: its analyzer, how it is stored, and whether
+    /// it can be sorted, aggregated and highlighted. A rolling index has the fields of its
+    /// write index.
+    pub fn field_capabilities(
+        &self,
+        index_name: &str,
+    ) -> std::result::Result<Vec<FieldCapabilities>, SearchError> {
+        let index_name = self.resolve_write_index(index_name);
+        let indices = self.read_open(&index_name);
+        let handle = indices
+            .get(&index_name)
+            .ok_or_else(|| SearchError::IndexNotFound(index_name.clone()))?;
+
+        let default_fields = Self::default_query_fields(handle);
+        let capabilities = handle
+            .field_configs
+            .iter()
+            .filter_map(|fc| {
+                let field = *handle.field_map.get(&fc.name)?;
+                let entry = handle.schema.get_field_entry(field);
+                let is_str = matches!(entry.field_type(), FieldType::Str(_));
+                let is_auto = fc.analyzer == language::AUTO_ANALYZER;
+                let analyzer = if is_str && entry.is_indexed() && is_auto {
+                    Some(language::AUTO_ANALYZER.to_string())
+                } else {
+                    Self::field_tokenizer(handle, field).map(str::to_string)
+                };
+                let languages = match analyzer.as_deref() {
+                    Some(language::AUTO_ANALYZER) if fc.languages.is_empty() => {
+                        language::supported_codes()
+                    }
+                    Some(language::AUTO_ANALYZER) => fc.languages.clone(),
+                    _ => Vec::new(),
+                };
+                // Geo points are filtered and aggregated through their hidden lat/lon fields,
+                // and terms aggregations fall back to stored values of string fields
+                let is_geo = fc.field_type == geo::GEO_POINT_TYPE;
+                Some(FieldCapabilities {
+                    name: fc.name.clone(),
+                    field_type: fc.field_type.clone(),
+                    analyzer,
+                    languages,
+                    indexed: entry.is_indexed() || is_geo,
+                    stored: entry.is_stored(),
+                    fast: entry.is_fast() || is_geo,
+                    // The query parser skips fields that aren't indexed
+                    default_search: entry.is_indexed() && default_fields.contains(&field),
+                    sortable: fc.fast && matches!(fc.field_type.as_str(), "i64" | "f64" | "date"),
+                    aggregatable: entry.is_fast() || is_geo || (is_str && entry.is_stored()),
+                    highlightable: is_str && entry.is_indexed() && entry.is_stored(),
+                })
+            })
+            .collect();
+        Ok(capabilities)
     }
 
-    fn build_query(
-        handle: &IndexHandle,
-        query_str: &str,
-        query_fields: &[Field],
-        fuzzy: bool,
-    ) -> Result<Box<dyn Query>> {
-        // Preprocess field grouping syntax: title:(foo AND bar) -> (title:foo AND title:bar)
-        let query_str = Self::expand_field_grouping(query_str);
-        let query_str = query_str.as_str();
-        
-        let query_parser = QueryParser::for_index(&handle.index, query_fields.to_vec());
-        
-        // Check for _exists_ query (e.g., "_exists_:field_name")
-        if let Some(field_name) = query_str.strip_prefix("_exists_:") {
-            let field_name = field_name.trim();
-            if handle.field_map.contains_key(field_name) {
-                // ExistsQuery::new(field_name, json_subpaths) - second param enables JSON subpath matching
-                return Ok(Box::new(ExistsQuery::new(field_name.to_string(), false)));
-            } else {
-                return Err(anyhow!("Field not found for exists query: {}", field_name));
+    /// Run text through an analyzer of an index and return the tokens it produces. The
+    /// analyzer is named, or that of an indexed text or string field; without either, the
+    /// default analyzer is used.
+    pub fn analyze(
+        &self,
+        index_name: &str,
+        text: &str,
+        analyzer: Option<&str>,
+        field_name: Option<&str>,
+    ) -> std::result::Result<(String, Vec<AnalyzedToken>), SearchError> {
+        let index_name = self.resolve_write_index(index_name);
+        let indices = self.read_open(&index_name);
+        let handle = indices
+            .get(&index_name)
+            .ok_or_else(|| SearchError::IndexNotFound(index_name.clone()))?;
+
+        let tokenizer = match (analyzer, field_name) {
+            (Some(_), Some(_)) => {
+                return Err(SearchError::InvalidQuery(
+                    "Set either analyzer or field, not both".to_string(),
+                ));
             }
-        }
-        
-        // Check for TermSetQuery syntax: field:IN[term1,term2,term3]
-        // This is more efficient than field:term1 OR field:term2 OR field:term3
-        if let Some(in_pos) = query_str.find(":IN[") {
-            let field_name = &query_str[..in_pos];
-            if let Some(field) = handle.field_map.get(field_name) {
-                // Find closing bracket
-                if let Some(close_pos) = query_str[in_pos..].find(']') {
-                    let terms_str = &query_str[in_pos + 4..in_pos + close_pos];
-                    let terms: Vec<Term> = terms_str
-                        .split(',')
-                        .map(|t| t.trim())
-                        .filter(|t| !t.is_empty())
-                        .map(|t| Term::from_field_text(*field, t))
-                        .collect();
-                    
-                    if !terms.is_empty() {
-                        return Ok(Box::new(TermSetQuery::new(terms)));
-                    }
-                }
+            (None, Some(field_name)) => {
+                let field = handle.field_map.get(field_name).ok_or_else(|| {
+                    SearchError::InvalidQuery(format!("Field not found: {}", field_name))
+                })?;
+                Self::field_tokenizer(handle, *field)
+                    .ok_or_else(|| {
+                        SearchError::InvalidQuery(format!(
+                            "Field '{}' is not an indexed text or string field",
+                            field_name
+                        ))
+                    })?
+                    .to_string()
             }
+            (Some(name @ ("default" | "norwegian" | "raw")), None) => name.to_string(),
+            (Some(code), None) if language::is_supported(code) => language::analyzer_name(code),
+            (Some(name), None) => {
+                return Err(SearchError::InvalidQuery(format!(
+                    "Unknown analyzer '{}'. Use default, norwegian, raw or a language code ({})",
+                    name,
+                    language::supported_codes().join(", ")
+                )));
+            }
+            (None, None) => "default".to_string(),
+        };
+
+        let mut text_analyzer = handle.index().tokenizers().get(&tokenizer).ok_or_else(|| {
+            SearchError::Internal(anyhow!("Analyzer '{}' is not registered", tokenizer))
+        })?;
+        let mut tokens = Vec::new();
+        let mut stream = text_analyzer.token_stream(text);
+        while let Some(token) = stream.next() {
+            tokens.push(AnalyzedToken {
+                token: token.text.clone(),
+                position: token.position,
+                start_offset: token.offset_from,
+                end_offset: token.offset_to,
+            });
         }
-        
-        // Check if the query contains wildcards (* or ?)
-        let has_wildcard = query_str.chars().any(|ch| matches!(ch, '*' | '?'));
-        
-        // Check if this is a phrase query with wildcards (e.g., "b.* b.* wolf")
-        // RegexPhraseQuery handles multi-term wildcard phrase searches
-        if has_wildcard && query_str.starts_with('"') && query_str.ends_with('"') {
-            let phrase_content = &query_str[1..query_str.len() - 1];
-            let query_lower = phrase_content.to_lowercase();
-            
-            // Split into terms and convert each to regex pattern
-            let terms: Vec<String> = query_lower
-                .split_whitespace()
-                .map(|term| {
-                    // Convert wildcard to regex: * -> .*, ? -> .
-                    term.chars()
-                        .map(|c| match c {
-                            '*' => ".*".to_string(),
-                            '?' => ".".to_string(),
-                            '.' | '+' | '^' | '$' | '(' | ')' | '[' | ']' | '{' | '}' | '|' | '\\' => {
-                                format!("\\{}", c)
-                            }
-                            _ => c.to_string(),
-                        })
-                        .collect::<String>()
-                })
-                .collect();
-            
-            // Need at least 2 terms for a phrase query
-            if terms.len() >= 2 {
-                let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
-                
-                for field in query_fields {
-                    let field_entry = handle.schema.get_field_entry(*field);
-                    if matches!(field_entry.field_type(), FieldType::Str(_)) {
-                        let regex_phrase_query = RegexPhraseQuery::new(*field, terms.clone());
-                        clauses.push((Occur::Should, Box::new(regex_phrase_query)));
-                    }
+        Ok((tokenizer, tokens))
+    }
+
+    /// Terms of the indexed text and string fields of a document, with their frequency and
+    /// positions in the document and the number of documents containing them. Tantivy
+    /// keeps no term vectors, so stored values are analyzed again; fields that aren't
+    /// stored are left out. `None` if the document doesn't exist.
+    pub fn term_vectors(
+        &self,
+        index_name: &str,
+        doc_id: &str,
+        field_names: &[String],
+    ) -> std::result::Result<Option<TermVectors>, SearchError> {
+        let indices = self.read_open(index_name);
+        let handle = indices
+            .get(index_name)
+            .ok_or_else(|| SearchError::IndexNotFound(index_name.to_string()))?;
+        if let Some(name) = field_names.iter().find(|name| !handle.field_map.contains_key(*name)) {
+            return Err(SearchError::InvalidQuery(format!("Field not found: {}", name)));
+        }
+
+        let searchers = handle.searchers(ReloadPolicy::OnCommitWithDelay)?;
+        let searcher = &searchers[handle.shard_for(doc_id)];
+        let id_field = *handle.field_map.get("id").unwrap();
+        let query = TermQuery::new(
+            Term::from_field_text(id_field, doc_id),
+            IndexRecordOption::Basic,
+        );
+        let Some((_, address)) = searcher.search(&query, &TopDocs::with_limit(1))?.pop() else {
+            return Ok(None);
+        };
+        let stored: TantivyDocument = searcher.doc(address)?;
+
+        let mut fields = Vec::new();
+        for fc in &handle.field_configs {
+            if !field_names.is_empty() && !field_names.contains(&fc.name) {
+                continue;
+            }
+            let Some(&field) = handle.field_map.get(&fc.name) else {
+                continue;
+            };
+            let Some(tokenizer) = Self::field_tokenizer(handle, field) else {
+                continue;
+            };
+            if !handle.schema.get_field_entry(field).is_stored() {
+                continue;
+            }
+            let Some(mut analyzer) = handle.index().tokenizers().get(tokenizer) else {
+                continue;
+            };
+
+            // Positions continue across the values of a multi-valued field
+            let mut positions: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+            let mut next_position = 0;
+            for value in stored.get_all(field) {
+                let OwnedValue::Str(text) = OwnedValue::from(value) else {
+                    continue;
+                };
+                let mut stream = analyzer.token_stream(&text);
+                let mut last_position = None;
+                while let Some(token) = stream.next() {
+                    let position = next_position + token.position;
+                    positions.entry(token.text.clone()).or_default().push(position);
+                    last_position = Some(position);
                 }
-                
-                if !clauses.is_empty() {
-                    return Ok(if clauses.len() == 1 {
-                        clauses.into_iter().next().unwrap().1
-                    } else {
-                        Box::new(BooleanQuery::from(clauses))
-                    });
+                if let Some(position) = last_position {
+                    next_position = position + 1;
+                }
+            }
+
+            let mut terms = Vec::with_capacity(positions.len());
+            for (term, positions) in positions {
+                let index_term = Term::from_field_text(field, &term);
+                let mut doc_freq = 0;
+                for searcher in &searchers {
+                    doc_freq += searcher.doc_freq(&index_term)?;
                 }
+                terms.push(TermVector {
+                    term,
+                    term_freq: positions.len(),
+                    doc_freq,
+                    positions,
+                });
             }
+            fields.push(FieldTermVectors {
+                field: fc.name.clone(),
+                analyzer: tokenizer.to_string(),
+                terms,
+            });
         }
-        
-        // For non-phrase wildcard queries, we use RegexQuery
-        // because Tantivy's default QueryParser doesn't support single-term wildcards
-        if has_wildcard {
-            // Convert wildcard syntax to regex syntax
-            // * becomes .* and ? becomes .
-            // Also lowercase the query to match indexed (lowercased) terms
-            let query_lower = query_str.to_lowercase();
-            
-            // Check if it's a field-specific query like "title:eventyr*"
-            let (target_fields, pattern) = if let Some(colon_pos) = query_lower.find(':') {
-                let field_name = &query_lower[..colon_pos];
-                let pattern_part = &query_lower[colon_pos + 1..];
-                
-                // Find the matching field
-                let target_field = handle.field_map.get(field_name).copied();
-                let fields = if let Some(f) = target_field {
-                    vec![f]
-                } else {
-                    // Field not found, use default fields
-                    query_fields.to_vec()
+
+        Ok(Some(TermVectors {
+            id: doc_id.to_string(),
+            document_count: searchers.iter().map(Searcher::num_docs).sum(),
+            fields,
+        }))
+    }
+
+    /// Terms of an indexed text or string field in byte order, starting with `prefix` and
+    /// following `after`, with the number of documents containing each. Every segment
+    /// of every shard is read up to `limit` matching terms and the streams are merged.
+    pub fn list_terms(
+        &self,
+        index_name: &str,
+        field_name: &str,
+        prefix: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> std::result::Result<Vec<TermCount>, SearchError> {
+        let indices = self.read_open(index_name);
+        let handle = indices
+            .get(index_name)
+            .ok_or_else(|| SearchError::IndexNotFound(index_name.to_string()))?;
+        let field = *handle.field_map.get(field_name).ok_or_else(|| {
+            SearchError::InvalidQuery(format!("Field not found: {}", field_name))
+        })?;
+        if Self::field_tokenizer(handle, field).is_none() {
+            return Err(SearchError::InvalidQuery(format!(
+                "Field '{}' is not an indexed text or string field",
+                field_name
+            )));
+        }
+
+        let mut merged: BTreeMap<Vec<u8>, u64> = BTreeMap::new();
+        for searcher in handle.searchers(ReloadPolicy::OnCommitWithDelay)? {
+            for reader in searcher.segment_readers() {
+                let inverted_index = reader.inverted_index(field)?;
+                let range = inverted_index.terms().range();
+                let range = match after {
+                    Some(after) if after >= prefix => range.gt(after),
+                    _ => range.ge(prefix),
                 };
-                (fields, pattern_part.to_string())
-            } else {
-                (query_fields.to_vec(), query_lower)
-            };
-            
-            // Convert wildcard pattern to regex pattern
-            // Escape regex special chars first, then convert wildcards
-            let regex_pattern = pattern
-                .chars()
-                .map(|c| match c {
-                    '*' => ".*".to_string(),
-                    '?' => ".".to_string(),
-                    // Escape regex special characters
-                    '.' | '+' | '^' | '$' | '(' | ')' | '[' | ']' | '{' | '}' | '|' | '\\' => {
-                        format!("\\{}", c)
-                    }
-                    _ => c.to_string(),
-                })
-                .collect::<String>();
-            
-            // Create regex queries for each target field
-            let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
-            for field in &target_fields {
-                // Only create regex queries for text fields
-                let field_entry = handle.schema.get_field_entry(*field);
-                if matches!(field_entry.field_type(), FieldType::Str(_)) {
-                    if let Ok(regex_query) = RegexQuery::from_pattern(&regex_pattern, *field) {
-                        clauses.push((Occur::Should, Box::new(regex_query)));
+                let mut stream = range.into_stream().map_err(anyhow::Error::from)?;
+                let mut taken = 0;
+                while taken < limit && stream.advance() {
+                    if !stream.key().starts_with(prefix.as_bytes()) {
+                        break;
                     }
+                    *merged.entry(stream.key().to_vec()).or_default() +=
+                        u64::from(stream.value().doc_freq);
+                    taken += 1;
                 }
             }
-            
-            if !clauses.is_empty() {
-                let wildcard_query: Box<dyn Query> = if clauses.len() == 1 {
-                    clauses.into_iter().next().unwrap().1
-                } else {
-                    Box::new(BooleanQuery::from(clauses))
-                };
-                
-                // If fuzzy is enabled, also add fuzzy queries for the non-wildcard part
-                if fuzzy {
-                    // Extract the prefix (part before the first wildcard)
-                    let prefix = pattern.split(['*', '?']).next().unwrap_or("");
-                    if !prefix.is_empty() && prefix.len() >= 2 {
-                        let mut fuzzy_clauses: Vec<(Occur, Box<dyn Query>)> = vec![
-                            (Occur::Should, wildcard_query)
-                        ];
-                        
-                        for field in &target_fields {
-                            let field_entry = handle.schema.get_field_entry(*field);
-                            if matches!(field_entry.field_type(), FieldType::Str(_)) {
-                                let term = Term::from_field_text(*field, prefix);
-                                fuzzy_clauses.push((
-                                    Occur::Should,
-                                    Box::new(FuzzyTermQuery::new(term, 1, true))
-                                ));
-                            }
+        }
+
+        Ok(merged
+            .into_iter()
+            .take(limit)
+            .map(|(term, doc_freq)| TermCount {
+                term: String::from_utf8_lossy(&term).into_owned(),
+                doc_freq,
+            })
+            .collect())
+    }
+
+    /// Number of shards of an index, if it exists
+    pub fn index_shard_count(&self, index_name: &str) -> Option<usize> {
+        self.read_open(index_name)
+            .get(index_name)
+            .map(|handle| handle.shards.len())
+    }
+
+    /// Most common words in the stored text fields of up to `max_docs` documents, with
+    /// the number of documents each appears in
+    pub fn sample_terms(
+        &self,
+        index_name: &str,
+        limit: usize,
+        max_docs: usize,
+    ) -> std::result::Result<Vec<(String, u64)>, SearchError> {
+        let indices = self.read_open(index_name);
+        let handle = indices
+            .get(index_name)
+            .ok_or_else(|| SearchError::IndexNotFound(index_name.to_string()))?;
+
+        let text_fields: Vec<Field> = handle
+            .field_configs
+            .iter()
+            .filter(|config| config.field_type == "text" && config.stored)
+            .filter_map(|config| handle.field_map.get(&config.name).copied())
+            .collect();
+
+        let searchers = handle.searchers(ReloadPolicy::Manual)?;
+
+        let mut doc_freqs: HashMap<String, u64> = HashMap::new();
+        let mut sampled = 0;
+        let segment_readers = searchers.iter().flat_map(Searcher::segment_readers);
+        'segments: for segment_reader in segment_readers {
+            let store_reader = segment_reader.get_store_reader(0).map_err(anyhow::Error::from)?;
+            for doc_id in 0..segment_reader.max_doc() {
+                if sampled >= max_docs {
+                    break 'segments;
+                }
+                if segment_reader.is_deleted(doc_id) {
+                    continue;
+                }
+                sampled += 1;
+
+                let doc: TantivyDocument = store_reader.get(doc_id)?;
+                let mut words: HashSet<String> = HashSet::new();
+                for field in &text_fields {
+                    for value in doc.get_all(*field) {
+                        let owned_value: tantivy::schema::OwnedValue = value.into();
+                        if let tantivy::schema::OwnedValue::Str(text) = owned_value {
+                            words.extend(
+                                text.split(|c: char| !c.is_alphanumeric())
+                                    .filter(|word| {
+                                        word.chars().count() > 2
+                                            && word.chars().any(char::is_alphabetic)
+                                    })
+                                    .map(str::to_lowercase),
+                            );
                         }
-                        
-                        return Ok(Box::new(BooleanQuery::from(fuzzy_clauses)));
                     }
                 }
-                
-                return Ok(wildcard_query);
+                for word in words {
+                    *doc_freqs.entry(word).or_default() += 1;
+                }
             }
         }
-        
-        // For non-wildcard queries, use the standard query parser
-        let base_query = query_parser.parse_query(query_str)?;
 
-        if !fuzzy {
-            return Ok(base_query);
-        }
+        let mut terms: Vec<(String, u64)> = doc_freqs.into_iter().collect();
+        terms.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        terms.truncate(limit);
+        Ok(terms)
+    }
 
-        let tokens: Vec<&str> = query_str.split_whitespace().collect();
-        if tokens.is_empty() {
-            return Ok(base_query);
+    /// Get stored filters for an index
+    pub fn get_stored_filters(&self, index_name: &str) -> Vec<StoredFilter> {
+        let filters = self.stored_filters.read().unwrap();
+        filters.get(index_name).cloned().unwrap_or_default()
+    }
+
+    /// Delete a stored filter by name, returning whether it existed
+    pub fn delete_stored_filter(&self, index_name: &str, filter_name: &str) -> Result<bool> {
+        let mut stored = self.stored_filters.write().unwrap();
+        let Some(entry) = stored.get_mut(index_name) else {
+            return Ok(false);
+        };
+        let before = entry.len();
+        entry.retain(|filter| filter.name != filter_name);
+        let deleted = entry.len() < before;
+        drop(stored);
+        if deleted {
+            self.bump_config_generation();
+            self.save_stored_filters()?;
         }
+        Ok(deleted)
+    }
 
-        let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
-        for token in tokens.iter() {
-            if token.chars().any(|ch| {
-                matches!(
-                    ch,
-                    '*' | '?'
-                        | ':'
-                        | '"'
-                        | '['
-                        | ']'
-                        | '{'
-                        | '}'
-                        | '('
-                        | ')'
-                        | '!'
-                        | '^'
-                        | '~'
-                        | '\\'
-                        | '/'
-                        | '<'
-                        | '>'
-                        | '='
-                )
-            }) {
-                continue;
-            }
+    /// Save prompt templates to disk
+    fn save_prompt_templates(&self) -> Result<()> {
+        let templates = self.prompt_templates.read().unwrap();
+        let templates_path = Path::new(&self.base_path).join("prompt_templates.json");
+        let content = serde_json::to_string_pretty(&*templates)?;
+        std::fs::write(templates_path, content)?;
+        Ok(())
+    }
 
-            let trimmed =
-                token.trim_matches(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'));
-            if trimmed.is_empty() {
-                continue;
-            }
+    /// Add prompt templates for an index, replacing existing templates with the same name
+    pub fn add_prompt_templates(
+        &self,
+        index_name: &str,
+        templates: Vec<PromptTemplate>,
+    ) -> Result<()> {
+        let mut stored = self.prompt_templates.write().unwrap();
+        let entry = stored.entry(index_name.to_string()).or_default();
+        for template in templates {
+            entry.retain(|existing| existing.name != template.name);
+            entry.push(template);
+        }
+        drop(stored);
+        self.save_prompt_templates()?;
+        Ok(())
+    }
 
-            let normalized = trimmed.to_lowercase();
-            if normalized.is_empty() {
-                continue;
-            }
-            if matches!(normalized.as_str(), "and" | "or" | "not") {
-                continue;
-            }
+    /// Get prompt templates for an index
+    pub fn get_prompt_templates(&self, index_name: &str) -> Vec<PromptTemplate> {
+        let templates = self.prompt_templates.read().unwrap();
+        templates.get(index_name).cloned().unwrap_or_default()
+    }
 
-            let mut field_clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
-            for field in query_fields {
-                let term = Term::from_field_text(*field, &normalized);
-                field_clauses.push((Occur::Should, Box::new(FuzzyTermQuery::new(term, 1, true))));
-            }
+    /// Get a prompt template by name
+    pub fn get_prompt_template(&self, index_name: &str, name: &str) -> Option<PromptTemplate> {
+        let templates = self.prompt_templates.read().unwrap();
+        templates
+            .get(index_name)?
+            .iter()
+            .find(|template| template.name == name)
+            .cloned()
+    }
 
-            if !field_clauses.is_empty() {
-                let clause: Box<dyn Query> = if field_clauses.len() == 1 {
-                    field_clauses.into_iter().next().unwrap().1
-                } else {
-                    Box::new(BooleanQuery::from(field_clauses))
-                };
-                clauses.push((Occur::Must, clause));
-            }
+    /// Delete a prompt template by name, returning whether it existed
+    pub fn delete_prompt_template(&self, index_name: &str, name: &str) -> Result<bool> {
+        let mut stored = self.prompt_templates.write().unwrap();
+        let Some(entry) = stored.get_mut(index_name) else {
+            return Ok(false);
+        };
+        let before = entry.len();
+        entry.retain(|template| template.name != name);
+        let deleted = entry.len() < before;
+        drop(stored);
+        if deleted {
+            self.save_prompt_templates()?;
         }
+        Ok(deleted)
+    }
 
-        if clauses.is_empty() {
-            return Ok(base_query);
+    /// Save search presets to disk
+    fn save_search_presets(&self) -> Result<()> {
+        let presets = self.search_presets.read().unwrap();
+        let presets_path = Path::new(&self.base_path).join("search_presets.json");
+        let content = serde_json::to_string_pretty(&*presets)?;
+        std::fs::write(presets_path, content)?;
+        Ok(())
+    }
+
+    /// Add search presets for an index, replacing existing presets with the same name
+    pub fn add_search_presets(&self, index_name: &str, presets: Vec<SearchPreset>) -> Result<()> {
+        let mut stored = self.search_presets.write().unwrap();
+        let entry = stored.entry(index_name.to_string()).or_default();
+        for preset in presets {
+            entry.retain(|existing| existing.name != preset.name);
+            entry.push(preset);
         }
+        drop(stored);
+        self.bump_config_generation();
+        self.save_search_presets()?;
+        Ok(())
+    }
 
-        let fuzzy_query: Box<dyn Query> = if clauses.len() == 1 {
-            clauses.into_iter().next().unwrap().1
-        } else {
-            Box::new(BooleanQuery::from(clauses))
-        };
+    /// Get search presets for an index
+    pub fn get_search_presets(&self, index_name: &str) -> Vec<SearchPreset> {
+        let presets = self.search_presets.read().unwrap();
+        presets.get(index_name).cloned().unwrap_or_default()
+    }
 
-        let combined: Vec<(Occur, Box<dyn Query>)> = vec![
-            (Occur::Should, base_query),
-            (Occur::Should, fuzzy_query),
-        ];
+    /// Get a search preset by name
+    pub fn get_search_preset(&self, index_name: &str, name: &str) -> Option<SearchPreset> {
+        let presets = self.search_presets.read().unwrap();
+        presets
+            .get(index_name)?
+            .iter()
+            .find(|preset| preset.name == name)
+            .cloned()
+    }
 
-        Ok(Box::new(BooleanQuery::from(combined)))
+    /// Delete a search preset by name, returning whether it existed
+    pub fn delete_search_preset(&self, index_name: &str, name: &str) -> Result<bool> {
+        let mut stored = self.search_presets.write().unwrap();
+        let Some(entry) = stored.get_mut(index_name) else {
+            return Ok(false);
+        };
+        let before = entry.len();
+        entry.retain(|preset| preset.name != name);
+        let deleted = entry.len() < before;
+        drop(stored);
+        if deleted {
+            self.bump_config_generation();
+            self.save_search_presets()?;
+        }
+        Ok(deleted)
     }
 
-    /// Expand field grouping syntax: title:(foo AND bar) -> (title:foo AND title:bar)
-    /// This enables Elasticsearch-style field grouping in queries
-    fn expand_field_grouping(query_str: &str) -> String {
-        // Pattern: field_name:(content)
-        // We need to find these and expand them
-        let mut i = 0;
-        let chars: Vec<char> = query_str.chars().collect();
-        let mut output = String::new();
-        
-        while i < chars.len() {
-            // Check if this could be the start of a field name
-            if chars[i].is_alphanumeric() || chars[i] == '_' {
-                // Collect potential field name
-                let field_start = i;
-                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
-                    i += 1;
-                }
-                let field_name: String = chars[field_start..i].iter().collect();
-                
-                // Check if followed by :(
-                if i + 1 < chars.len() && chars[i] == ':' && chars[i + 1] == '(' {
-                    // Find matching closing parenthesis
-                    let content_start = i + 2;
-                    let mut depth = 1;
-                    let mut content_end = content_start;
-                    
-                    while content_end < chars.len() && depth > 0 {
-                        if chars[content_end] == '(' {
-                            depth += 1;
-                        } else if chars[content_end] == ')' {
-                            depth -= 1;
-                        }
-                        content_end += 1;
-                    }
-                    
-                    if depth == 0 {
-                        // Extract the content (excluding the final closing paren)
-                        let content: String = chars[content_start..content_end - 1].iter().collect();
-                        
-                        // Expand: add field: prefix to each term that doesn't have a field
-                        let expanded = Self::add_field_prefix_to_terms(&field_name, &content);
-                        output.push('(');
-                        output.push_str(&expanded);
-                        output.push(')');
-                        i = content_end;
-                        continue;
+    /// Save pinned rules to disk
+    fn save_pinned_rules(&self) -> Result<()> {
+        let rules = self.pinned_rules.read().unwrap();
+        let pinned_path = Path::new(&self.base_path).join("pinned_rules.json");
+        let content = serde_json::to_string_pretty(&*rules)?;
+        std::fs::write(pinned_path, content)?;
+        Ok(())
+    }
+
+    /// Add pinned rules for an index
+    pub fn add_pinned_rules(&self, index_name: &str, rules: Vec<PinnedRule>) -> Result<()> {
+        let mut pinned = self.pinned_rules.write().unwrap();
+        let entry = pinned.entry(index_name.to_string()).or_default();
+        entry.extend(rules);
+        drop(pinned);
+        self.bump_config_generation();
+        self.save_pinned_rules()?;
+        Ok(())
+    }
+
+    /// Get pinned rules for an index
+    pub fn get_pinned_rules(&self, index_name: &str) -> Vec<PinnedRule> {
+        let rules = self.pinned_rules.read().unwrap();
+        rules.get(index_name).cloned().unwrap_or_default()
+    }
+
+    /// Clear all pinned rules for an index
+    pub fn clear_pinned_rules(&self, index_name: &str) -> Result<()> {
+        let mut rules = self.pinned_rules.write().unwrap();
+        rules.remove(index_name);
+        drop(rules);
+        self.bump_config_generation();
+        self.save_pinned_rules()?;
+        Ok(())
+    }
+
+    /// Get pinned document IDs for a query
+    fn get_pinned_doc_ids(&self, index_name: &str, query_str: &str) -> Vec<String> {
+        let rules = self.pinned_rules.read().unwrap();
+        let query_lower = query_str.to_lowercase();
+        
+        if let Some(index_rules) = rules.get(index_name) {
+            for rule in index_rules {
+                // Check if query matches any of the trigger terms
+                for trigger in &rule.queries {
+                    if query_lower.contains(&trigger.to_lowercase()) {
+                        return rule.document_ids.clone();
                     }
                 }
-                
-                // Not a field grouping, output as-is
-                output.push_str(&field_name);
-                continue;
             }
-            
-            output.push(chars[i]);
-            i += 1;
         }
         
-        output
+        Vec::new()
     }
-    
-    /// Add field: prefix to terms in an expression that don't already have a field prefix
-    fn add_field_prefix_to_terms(field: &str, content: &str) -> String {
-        // Simple tokenization: split by spaces and operators, add prefix to words
-        let mut result = String::new();
-        let mut current_word = String::new();
-        let mut in_quotes = false;
-        let mut quote_char = '"';
+
+    /// Save synonyms to disk
+    fn save_synonyms(&self) -> Result<()> {
+        let synonyms = self.synonyms.read().unwrap();
+        let synonyms_path = Path::new(&self.base_path).join("synonyms.json");
+        let content = serde_json::to_string_pretty(&*synonyms)?;
+        std::fs::write(synonyms_path, content)?;
+        Ok(())
+    }
+
+    /// Add synonyms for an index
+    pub fn add_synonyms(&self, index_name: &str, synonym_groups: Vec<SynonymGroup>) -> Result<()> {
+        let mut synonyms = self.synonyms.write().unwrap();
+        let entry = synonyms.entry(index_name.to_string()).or_default();
+        entry.extend(synonym_groups);
+        drop(synonyms);
+        self.bump_config_generation();
+        self.save_synonyms()?;
+        Ok(())
+    }
+
+    /// Get synonyms for an index
+    pub fn get_synonyms(&self, index_name: &str) -> Vec<SynonymGroup> {
+        let synonyms = self.synonyms.read().unwrap();
+        synonyms.get(index_name).cloned().unwrap_or_default()
+    }
+
+    /// Clear all synonyms for an index
+    pub fn clear_synonyms(&self, index_name: &str) -> Result<()> {
+        let mut synonyms = self.synonyms.write().unwrap();
+        synonyms.remove(index_name);
+        drop(synonyms);
+        self.bump_config_generation();
+        self.save_synonyms()?;
+        Ok(())
+    }
+
+    /// Expand a query term with its synonyms
+    fn expand_with_synonyms(&self, index_name: &str, term: &str) -> Vec<String> {
+        let synonyms = self.synonyms.read().unwrap();
+        let term_lower = term.to_lowercase();
         
-        for c in content.chars() {
-            if (c == '"' || c == '\'') && !in_quotes {
-                // Starting a quote - output current word and start quoted section
-                if !current_word.is_empty() {
-                    if !current_word.contains(':') && !is_operator(&current_word) {
-                        result.push_str(field);
-                        result.push(':');
-                    }
-                    result.push_str(&current_word);
-                    current_word.clear();
-                }
-                in_quotes = true;
-                quote_char = c;
-                result.push(c);
-            } else if c == quote_char && in_quotes {
-                // Ending a quote
-                in_quotes = false;
-                // For phrases in quotes, prefix the whole quoted section
-                if !current_word.is_empty() {
-                    result.push_str(field);
-                    result.push(':');
-                    result.push(quote_char);
-                    result.push_str(&current_word);
-                }
-                result.push(c);
-                current_word.clear();
-            } else if in_quotes {
-                current_word.push(c);
-            } else if c.is_whitespace() || c == '(' || c == ')' {
-                // End of word
-                if !current_word.is_empty() {
-                    if !current_word.contains(':') && !is_operator(&current_word) {
-                        result.push_str(field);
-                        result.push(':');
-                    }
-                    result.push_str(&current_word);
-                    current_word.clear();
+        if let Some(groups) = synonyms.get(index_name) {
+            for group in groups {
+                // Check if this term is in any synonym group
+                if group.terms.iter().any(|t| t.to_lowercase() == term_lower) {
+                    // Return all terms in the group (including the original)
+                    return group.terms.iter()
+                        .map(|t| t.to_lowercase())
+                        .collect();
                 }
-                result.push(c);
-            } else {
-                current_word.push(c);
             }
         }
         
-        // Handle final word
-        if !current_word.is_empty() {
-            if !current_word.contains(':') && !is_operator(&current_word) {
-                result.push_str(field);
-                result.push(':');
+        // No synonyms found, return just the original term
+        vec![term_lower]
+    }
+
+    /// Rewrite plain words as a phrase with slop, e.g. `quick fox` -> `"quick fox"~2`.
+    /// Queries using any query syntax, or with fewer than two words, are left alone.
+    fn proximity_query(query_str: &str, slop: u32) -> Option<String> {
+        let words: Vec<&str> = query_str.split_whitespace().collect();
+        let is_plain = words.iter().all(|word| {
+            !is_operator(word)
+                && word
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '\'' | '.' | ','))
+        });
+        (words.len() >= 2 && is_plain).then(|| format!("\"{}\"~{}", words.join(" "), slop))
+    }
+
+    /// Expand a full query string with synonyms
+    fn expand_query_with_synonyms(&self, index_name: &str, query_str: &str) -> String {
+        // Simple tokenization - split on whitespace and keep quoted phrases as written
+        let mut result = String::new();
+        let mut in_quotes = false;
+        let mut current_word = String::new();
+
+        let push_word = |result: &mut String, word: &mut String| {
+            if word.is_empty() {
+                return;
+            }
+            // Operators and special syntax (fields, wildcards, slop/boost suffixes) are kept
+            if is_operator(word)
+                || word.contains(':')
+                || word.contains('*')
+                || word.contains('?')
+                || word.starts_with(['~', '^'])
+            {
+                result.push_str(word);
+            } else {
+                let expanded = self.expand_with_synonyms(index_name, word);
+                if expanded.len() > 1 {
+                    // Multiple synonyms - wrap in parentheses with OR
+                    result.push('(');
+                    result.push_str(&expanded.join(" OR "));
+                    result.push(')');
+                } else {
+                    result.push_str(&expanded[0]);
+                }
+            }
+            word.clear();
+        };
+
+        for ch in query_str.chars() {
+            if ch == '"' {
+                push_word(&mut result, &mut current_word);
+                in_quotes = !in_quotes;
+                result.push(ch);
+            } else if in_quotes {
+                result.push(ch);
+            } else if ch.is_whitespace() {
+                push_word(&mut result, &mut current_word);
+                result.push(ch);
+            } else {
+                current_word.push(ch);
             }
-            result.push_str(&current_word);
         }
-        
+        push_word(&mut result, &mut current_word);
+
         result
     }
 
-    fn fallback_query_string(query_str: &str) -> Option<String> {
-        let stopwords: HashSet<&'static str> = [
-            "hva", "hvem", "hvor", "hvilken", "hvilke", "hvordan", "når", "hvorfor",
-            "what", "who", "where", "which", "how", "when", "why",
-            "er", "var", "bli", "blir", "være",
-            "og", "eller", "for", "av", "til", "med", "i", "på", "om", "som",
-            "en", "et", "den", "det", "de", "du", "jeg", "vi", "oss",
-        ]
-        .into_iter()
-        .collect();
+    /// Save rolling index aliases to disk
+    fn save_rollover_aliases(&self) -> Result<()> {
+        let aliases = self.rollover_aliases.read().unwrap();
+        let aliases_path = Path::new(&self.base_path).join("rollover_aliases.json");
+        let content = serde_json::to_string_pretty(&*aliases)?;
+        std::fs::write(aliases_path, content)?;
+        Ok(())
+    }
 
-        let cleaned: String = query_str
-            .to_lowercase()
-            .chars()
-            .map(|c| if c.is_alphanumeric() || c == '_' || c == '-' { c } else { ' ' })
-            .collect();
+    /// The rolling index named `name`, if there is one
+    pub fn rollover_alias(&self, name: &str) -> Option<RolloverAlias> {
+        self.rollover_aliases.read().unwrap().get(name).cloned()
+    }
 
-        let tokens: Vec<String> = cleaned
-            .split_whitespace()
-            .filter(|token| token.len() > 1 && !stopwords.contains(*token))
-            .map(|token| token.to_string())
-            .collect();
+    /// Add or replace a rolling index. A new alias can't take the name of an index.
+    pub fn set_rollover_alias(
+        &self,
+        name: &str,
+        alias: RolloverAlias,
+    ) -> std::result::Result<(), SearchError> {
+        // Lock order matches reserve_index_name: indices, the reservations, then aliases
+        let indices = self.indices.read().unwrap();
+        let creating = self.creating.lock().unwrap();
+        let mut aliases = self.rollover_aliases.write().unwrap();
+        if !aliases.contains_key(name)
+            && (indices.contains_key(name)
+                || creating.contains(name)
+                || self.is_closed(name))
+        {
+            return Err(SearchError::IndexExists(name.to_string()));
+        }
+        aliases.insert(name.to_string(), alias);
+        drop((indices, creating, aliases));
 
-        if tokens.is_empty() {
-            None
-        } else {
-            Some(tokens.join(" "))
+        self.bump_config_generation();
+        self.save_rollover_aliases()?;
+        Ok(())
+    }
+
+    /// Remove a rolling index alias, leaving its generations in place
+    pub fn remove_rollover_alias(&self, name: &str) -> Result<bool> {
+        let removed = self.rollover_aliases.write().unwrap().remove(name).is_some();
+        if removed {
+            self.bump_config_generation();
+            self.save_rollover_aliases()?;
         }
+        Ok(removed)
     }
 
-    /// Build an Elasticsearch-compatible aggregation request from our AggregationRequest format
-    fn build_aggregation_request(aggregations: &[AggregationRequest]) -> Result<Aggregations> {
-        let mut agg_map = serde_json::Map::new();
+    /// Start copying writes to `index_name` into a shadow index, returning a snapshot of
+    /// the documents it holds. Taken while no write is in progress, so every write is
+    /// either in the snapshot or copied.
+    pub fn start_shadow_writes(
+        &self,
+        index_name: &str,
+        shadow: Arc<ShadowIndex>,
+    ) -> std::result::Result<Vec<Searcher>, SearchError> {
+        let indices = self.read_open(index_name);
+        let handle = indices
+            .get(index_name)
+            .ok_or_else(|| SearchError::IndexNotFound(index_name.to_string()))?;
+        let _writers = (0..handle.shards.len())
+            .map(|shard| handle.writer(shard))
+            .collect::<tantivy::Result<Vec<_>>>()?;
+        self.shadows
+            .write()
+            .unwrap()
+            .insert(index_name.to_string(), shadow);
+        Ok(handle.searchers(ReloadPolicy::Manual)?)
+    }
 
-        for agg_req in aggregations {
-            let agg_def = match agg_req.agg_type.as_str() {
-                "terms" => {
-                    let mut terms = serde_json::json!({
-                        "field": agg_req.field
-                    });
-                    if let Some(size) = agg_req.size {
-                        terms["size"] = serde_json::json!(size);
-                    }
-                    serde_json::json!({ "terms": terms })
+    pub fn stop_shadow_writes(&self, index_name: &str) {
+        self.shadows.write().unwrap().remove(index_name);
+    }
+
+    /// Replace the index or rolling index `alias` with a rolling index over a migrated
+    /// index, provided it still consists of `sources`, and stop copying their writes.
+    /// Waits for writes in progress, which copy their documents before finishing.
+    pub fn swap_alias(
+        &self,
+        alias: &str,
+        rolling: RolloverAlias,
+        sources: &[String],
+        shadow: &ShadowIndex,
+    ) -> std::result::Result<(), SearchError> {
+        let mut indices = self.indices.write().unwrap();
+        let mut shadows = self.shadows.write().unwrap();
+        for source in sources {
+            shadows.remove(source);
+        }
+        drop(shadows);
+        if let Some(error) = shadow.error() {
+            return Err(SearchError::Internal(anyhow!(error)));
+        }
+
+        let mut aliases = self.rollover_aliases.write().unwrap();
+        let unchanged = match aliases.get(alias) {
+            Some(current) => current
+                .generations
+                .iter()
+                .map(|generation| &generation.index)
+                .eq(sources),
+            None => indices.contains_key(alias) && sources == [alias],
+        };
+        if !unchanged {
+            return Err(SearchError::Conflict(format!(
+                "Index '{}' changed during the migration",
+                alias
+            )));
+        }
+        indices.remove(alias);
+        aliases.insert(alias.to_string(), rolling);
+        drop((indices, aliases));
+
+        self.filter_cache.invalidate(alias);
+        self.bump_config_generation();
+        self.save_rollover_aliases()?;
+        Ok(())
+    }
+
+    /// Index written through `name`: the write index of a rolling index, otherwise the
+    /// index of that name
+    pub fn resolve_write_index(&self, name: &str) -> String {
+        self.rollover_aliases
+            .read()
+            .unwrap()
+            .get(name)
+            .map(|alias| alias.write_index().to_string())
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    /// Indices and rolling index aliases whose names match an index pattern, sorted
+    pub fn resolve_pattern(&self, pattern: &str) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .list_indices()
+            .into_iter()
+            .chain(self.rollover_aliases.read().unwrap().keys().cloned())
+            .filter(|name| index_patterns::matches(pattern, name))
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Indices searched through `name`: every generation of a rolling index (newest
+    /// first), the indices matching an index pattern (with the generations of matching
+    /// rolling indices), otherwise the index of that name
+    pub fn search_indices(&self, name: &str) -> Vec<String> {
+        if index_patterns::is_pattern(name) {
+            let mut names: Vec<String> = self
+                .resolve_pattern(name)
+                .iter()
+                .flat_map(|matched| self.search_indices(matched))
+                .collect();
+            names.sort();
+            names.dedup();
+            return names;
+        }
+        match self.rollover_aliases.read().unwrap().get(name) {
+            Some(alias) => alias
+                .generations
+                .iter()
+                .rev()
+                .map(|generation| generation.index.clone())
+                .collect(),
+            None => vec![name.to_string()],
+        }
+    }
+
+    /// Load the indices found on disk, returning the names of those loaded. Closed
+    /// indices are only registered, and opened when first used.
+    pub fn load_indices(&self) -> Result<Vec<String>> {
+        let mut loaded = Vec::new();
+        let base_path = Path::new(&self.base_path);
+
+        if !base_path.exists() {
+            return Ok(loaded);
+        }
+
+        for entry in std::fs::read_dir(base_path)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+
+            let index_name = entry.file_name().to_string_lossy().to_string();
+            let index_path = entry.path();
+
+            if index_path.join(CLOSED_MARKER_FILE).exists() {
+                self.closed.write().unwrap().insert(index_name);
+                continue;
+            }
+
+            match self.open_index_handle(&index_path) {
+                Ok(handle) => {
+                    self.indices.write().unwrap().insert(index_name.clone(), handle);
+                    loaded.push(index_name);
                 }
-                "stats" => {
-                    serde_json::json!({
-                        "stats": { "field": agg_req.field }
-                    })
+                Err(e) => {
+                    tracing::warn!(
+                        "Failed to load index from {}: {}",
+                        index_path.display(),
+                        e
+                    );
                 }
-                "avg" => {
-                    serde_json::json!({
-                        "avg": { "field": agg_req.field }
-                    })
+            }
+        }
+
+        Ok(loaded)
+    }
+
+    /// Open an index directory with its shards, schema and settings
+    fn open_index_handle(&self, index_path: &Path) -> Result<IndexHandle> {
+        let shard_paths = match sharding::shard_count(index_path) {
+            0 => vec![index_path.to_path_buf()],
+            count => (0..count)
+                .map(|shard| sharding::shard_path(index_path, shard))
+                .collect(),
+        };
+        let shards = shard_paths
+            .iter()
+            .map(|path| {
+                let mut index = Index::open_in_dir(path)?;
+                index.set_executor(self.executor.clone());
+                Self::register_analyzers(&index);
+                Ok(index)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let schema = shards[0].schema();
+        let field_map = schema
+            .fields()
+            .map(|(field, field_entry)| (field_entry.name().to_string(), field))
+            .collect::<HashMap<_, _>>();
+        let field_configs = Self::field_configs_from_schema(&schema);
+        let settings = Self::load_index_settings(index_path);
+
+        Ok(IndexHandle::new(shards, field_map, field_configs, settings))
+    }
+
+    /// Release an index's writer and reader memory, keeping it on disk and listed.
+    /// The index is opened again by the next request that uses it.
+    pub fn close_index(&self, index_name: &str) -> std::result::Result<(), SearchError> {
+        let mut indices = self.indices.write().unwrap();
+        if self.closed.read().unwrap().contains(index_name) {
+            return Ok(());
+        }
+        if !indices.contains_key(index_name) {
+            return Err(SearchError::IndexNotFound(index_name.to_string()));
+        }
+
+        let index_path = Path::new(&self.base_path).join(index_name);
+        std::fs::write(index_path.join(CLOSED_MARKER_FILE), "").map_err(anyhow::Error::from)?;
+        // Holding the write lock means no request is using the writer
+        indices.remove(index_name);
+        self.closed.write().unwrap().insert(index_name.to_string());
+        self.filter_cache.invalidate(index_name);
+        Ok(())
+    }
+
+    /// Load a closed index again; open indices are left as they are
+    pub fn open_index(&self, index_name: &str) -> std::result::Result<(), SearchError> {
+        let mut indices = self.indices.write().unwrap();
+        if indices.contains_key(index_name) {
+            return Ok(());
+        }
+        if !self.closed.read().unwrap().contains(index_name) {
+            return Err(SearchError::IndexNotFound(index_name.to_string()));
+        }
+
+        let index_path = Path::new(&self.base_path).join(index_name);
+        let handle = self.open_index_handle(&index_path)?;
+        std::fs::remove_file(index_path.join(CLOSED_MARKER_FILE)).map_err(anyhow::Error::from)?;
+        indices.insert(index_name.to_string(), handle);
+        self.closed.write().unwrap().remove(index_name);
+        tracing::info!("Opened index '{}'", index_name);
+        Ok(())
+    }
+
+    pub fn is_closed(&self, index_name: &str) -> bool {
+        self.closed.read().unwrap().contains(index_name)
+    }
+
+    /// Loaded indices, opening `index_name` first if it is closed
+    fn read_open(&self, index_name: &str) -> RwLockReadGuard<'_, HashMap<String, IndexHandle>> {
+        loop {
+            let indices = self.indices.read().unwrap();
+            if indices.contains_key(index_name) || !self.is_closed(index_name) {
+                return indices;
+            }
+            drop(indices);
+            // Callers report the index as not found if it can't be opened
+            if let Err(e) = self.open_index(index_name) {
+                tracing::warn!("Failed to open index '{}': {}", index_name, e);
+                return self.indices.read().unwrap();
+            }
+        }
+    }
+
+    /// Loaded indices, opening any of `index_names` that are closed
+    fn read_open_all(
+        &self,
+        index_names: &[String],
+    ) -> RwLockReadGuard<'_, HashMap<String, IndexHandle>> {
+        for index_name in index_names.iter().skip(1) {
+            drop(self.read_open(index_name));
+        }
+        match index_names.first() {
+            Some(index_name) => self.read_open(index_name),
+            None => self.indices.read().unwrap(),
+        }
+    }
+
+    pub fn collect_document_ids(&self, index_name: &str) -> Result<Vec<String>> {
+        let indices = self.read_open(index_name);
+        let handle = indices
+            .get(index_name)
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+
+        let searchers = handle.searchers(ReloadPolicy::Manual)?;
+
+        let id_field = *handle
+            .field_map
+            .get("id")
+            .ok_or_else(|| anyhow!("ID field not found for index: {}", index_name))?;
+
+        let mut ids = Vec::new();
+
+        for segment_reader in searchers.iter().flat_map(Searcher::segment_readers) {
+            let store_reader = segment_reader.get_store_reader(0)?;
+            let max_doc = segment_reader.max_doc();
+            let alive_bitset = segment_reader.alive_bitset();
+
+            for doc_id in 0..max_doc {
+                if let Some(bitset) = alive_bitset {
+                    if !bitset.is_alive(doc_id) {
+                        continue;
+                    }
                 }
-                "min" => {
-                    serde_json::json!({
-                        "min": { "field": agg_req.field }
+
+                let doc: TantivyDocument = store_reader.get(doc_id)?;
+                let id_value = {
+                    let mut values = doc.get_all(id_field);
+                    values.next().and_then(|field_value| {
+                        let owned_value: tantivy::schema::OwnedValue = field_value.into();
+                        if let tantivy::schema::OwnedValue::Str(s) = owned_value {
+                            Some(s.to_string())
+                        } else {
+                            None
+                        }
                     })
+                };
+
+                if let Some(id) = id_value {
+                    ids.push(id);
                 }
-                "max" => {
-                    serde_json::json!({
-                        "max": { "field": agg_req.field }
-                    })
+            }
+        }
+
+        Ok(ids)
+    }
+
+    /// Read per-index settings, falling back to defaults if missing or invalid
+    fn load_index_settings(index_path: &Path) -> IndexSettings {
+        std::fs::read_to_string(index_path.join(SETTINGS_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Write per-index settings next to the index files
+    fn save_index_settings(index_path: &Path, settings: &IndexSettings) -> Result<()> {
+        let content = serde_json::to_string_pretty(settings)?;
+        std::fs::write(index_path.join(SETTINGS_FILE), content)?;
+        Ok(())
+    }
+
+    fn field_configs_from_schema(schema: &Schema) -> Vec<FieldConfig> {
+        let mut configs = Vec::new();
+
+        for (_field, entry) in schema.fields() {
+            let name = entry.name();
+            if name == "id"
+                || name == CONTENT_HASH_FIELD
+                || name == EXPIRES_AT_FIELD
+                || name == language::LANGUAGE_FIELD
+                || language::parse_sub_field_name(name).is_some()
+                || geo::parse_sub_field_name(name)
+                    .map(|base| schema.get_field(base).is_ok())
+                    .unwrap_or(false)
+            {
+                continue;
+            }
+
+            match entry.field_type() {
+                FieldType::Str(options) => {
+                    let indexing = options.get_indexing_options();
+                    let indexed = indexing.is_some();
+                    let stored = options.is_stored();
+
+                    let (field_type, analyzer) = if let Some(indexing) = indexing {
+                        let tokenizer = indexing.tokenizer().to_string();
+                        let index_option = indexing.index_option();
+                        let is_string = tokenizer == "raw" && index_option == IndexRecordOption::Basic;
+                        (
+                            if is_string { "string" } else { "text" },
+                            tokenizer,
+                        )
+                    } else {
+                        ("text", "default".to_string())
+                    };
+
+                    // Language sub-fields mark the field as auto-detected
+                    let languages: Vec<String> = language::supported_codes()
+                        .into_iter()
+                        .filter(|code| {
+                            schema
+                                .get_field(&language::sub_field_name(name, code))
+                                .is_ok()
+                        })
+                        .collect();
+                    let analyzer = if languages.is_empty() {
+                        analyzer
+                    } else {
+                        language::AUTO_ANALYZER.to_string()
+                    };
+
+                    configs.push(FieldConfig {
+                        name: name.to_string(),
+                        field_type: field_type.to_string(),
+                        stored,
+                        indexed,
+                        analyzer,
+                        fast: false,
+                        languages,
+                    });
                 }
-                "sum" => {
-                    serde_json::json!({
-                        "sum": { "field": agg_req.field }
-                    })
+                FieldType::I64(options) => {
+                    configs.push(FieldConfig {
+                        name: name.to_string(),
+                        field_type: "i64".to_string(),
+                        stored: options.is_stored(),
+                        indexed: options.is_indexed(),
+                        analyzer: "default".to_string(),
+                        fast: options.is_fast(),
+                        languages: Vec::new(),
+                    });
                 }
-                "count" => {
-                    serde_json::json!({
-                        "value_count": { "field": agg_req.field }
-                    })
+                FieldType::F64(options) => {
+                    configs.push(FieldConfig {
+                        name: name.to_string(),
+                        field_type: "f64".to_string(),
+                        stored: options.is_stored(),
+                        indexed: options.is_indexed(),
+                        analyzer: "default".to_string(),
+                        fast: options.is_fast(),
+                        languages: Vec::new(),
+                    });
                 }
-                "cardinality" => {
-                    serde_json::json!({
-                        "cardinality": { "field": agg_req.field }
-                    })
+                FieldType::Date(options) => {
+                    configs.push(FieldConfig {
+                        name: name.to_string(),
+                        field_type: "date".to_string(),
+                        stored: options.is_stored(),
+                        indexed: options.is_indexed(),
+                        analyzer: "default".to_string(),
+                        fast: options.is_fast(),
+                        languages: Vec::new(),
+                    });
                 }
-                "histogram" => {
-                    let interval = agg_req.interval.unwrap_or(10.0);
-                    serde_json::json!({
-                        "histogram": {
-                            "field": agg_req.field,
-                            "interval": interval
-                        }
-                    })
+                FieldType::JsonObject(options) => {
+                    // Geo points are JSON fields backed by hidden lat/lon sub-fields
+                    let is_geo_point = schema.get_field(&geo::lat_field_name(name)).is_ok();
+                    configs.push(FieldConfig {
+                        name: name.to_string(),
+                        field_type: if is_geo_point {
+                            geo::GEO_POINT_TYPE.to_string()
+                        } else {
+                            "json".to_string()
+                        },
+                        stored: options.is_stored(),
+                        indexed: is_geo_point || options.get_text_indexing_options().is_some(),
+                        analyzer: "default".to_string(),
+                        fast: is_geo_point || options.is_expand_dots_enabled(),
+                        languages: Vec::new(),
+                    });
                 }
-                "range" => {
-                    let ranges: Vec<serde_json::Value> = agg_req
-                        .ranges
-                        .as_ref()
+                _ => {}
+            }
+        }
+
+        configs
+    }
+
+    fn register_analyzers(index: &Index) {
+        // Register Norwegian analyzer with stemming
+        let norwegian = TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(LowerCaser)
+            .filter(Stemmer::new(tantivy::tokenizer::Language::Norwegian))
+            .build();
+        index.tokenizers().register("norwegian", norwegian);
+
+        // Register raw analyzer (no tokenization)
+        let raw = TextAnalyzer::builder(tantivy::tokenizer::RawTokenizer::default()).build();
+        index.tokenizers().register("raw", raw);
+
+        // Register per-language stemming analyzers used by "auto" fields
+        language::register_analyzers(index);
+    }
+
+    /// Create an index split into `shards` Tantivy indices (1 for an unsharded index)
+    pub fn create_index(
+        &self,
+        name: &str,
+        fields: &[FieldConfig],
+        settings: &IndexSettings,
+        shards: usize,
+    ) -> std::result::Result<(), SearchError> {
+        // A second creation of the same name fails instead of sharing the directory
+        let _reservation = self.reserve_index_name(name)?;
+
+        let mut schema_builder = Schema::builder();
+        let mut field_map = HashMap::new();
+
+        // Always add an ID field
+        let id_field = schema_builder.add_text_field("id", STRING | STORED);
+        field_map.insert("id".to_string(), id_field);
+
+        // Content hash used for deduplication
+        let hash_field = schema_builder.add_text_field(CONTENT_HASH_FIELD, STRING);
+        field_map.insert(CONTENT_HASH_FIELD.to_string(), hash_field);
+
+        // Expiration time used by the TTL sweeper
+        let expires_field = schema_builder.add_date_field(
+            EXPIRES_AT_FIELD,
+            DateOptions::default()
+                .set_indexed()
+                .set_fast()
+                .set_precision(tantivy::schema::DateTimePrecision::Seconds),
+        );
+        field_map.insert(EXPIRES_AT_FIELD.to_string(), expires_field);
+        let mut needs_language_field = false;
+
+        // Add custom fields
+        for field_config in fields {
+            let field = match field_config.field_type.as_str() {
+                "text" => {
+                    let mut options = TextOptions::default();
+                    if field_config.stored {
+                        options = options.set_stored();
+                    }
+                    if field_config.indexed {
+                        let tokenizer = match field_config.analyzer.as_str() {
+                            "norwegian" => "norwegian",
+                            "raw" => "raw",
+                            _ => "default",
+                        };
+                        options = options.set_indexing_options(
+                            TextFieldIndexing::default()
+                                .set_tokenizer(tokenizer)
+                                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+                        );
+                    }
+                    let field = schema_builder.add_text_field(&field_config.name, options);
+
+                    // Auto-detected fields get an index-only sub-field per language,
+                    // analyzed with that language's stemmer
+                    if field_config.indexed && field_config.analyzer == language::AUTO_ANALYZER {
+                        let codes = if field_config.languages.is_empty() {
+                            language::supported_codes()
+                        } else {
+                            field_config.languages.clone()
+                        };
+                        for code in &codes {
+                            if !language::is_supported(code) {
+                                return Err(SearchError::InvalidQuery(format!(
+                                    "Unsupported language '{}' for field '{}'",
+                                    code, field_config.name
+                                )));
+                            }
+                            let sub_name = language::sub_field_name(&field_config.name, code);
+                            let sub_options = TextOptions::default().set_indexing_options(
+                                TextFieldIndexing::default()
+                                    .set_tokenizer(&language::analyzer_name(code))
+                                    .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+                            );
+                            let sub_field = schema_builder.add_text_field(&sub_name, sub_options);
+                            field_map.insert(sub_name, sub_field);
+                        }
+                        needs_language_field = true;
+                    }
+
+                    field
+                }
+                "string" => {
+                    let options = if field_config.indexed {
+                        STRING | STORED
+                    } else {
+                        TextOptions::default().set_stored()
+                    };
+                    schema_builder.add_text_field(&field_config.name, options)
+                }
+                "i64" => {
+                    let mut options = NumericOptions::default();
+                    if field_config.stored {
+                        options = options.set_stored();
+                    }
+                    if field_config.indexed {
+                        options = options.set_indexed();
+                    }
+                    if field_config.fast {
+                        options = options.set_fast();
+                    }
+                    schema_builder.add_i64_field(&field_config.name, options)
+                }
+                "f64" => {
+                    let mut options = NumericOptions::default();
+                    if field_config.stored {
+                        options = options.set_stored();
+                    }
+                    if field_config.indexed {
+                        options = options.set_indexed();
+                    }
+                    if field_config.fast {
+                        options = options.set_fast();
+                    }
+                    schema_builder.add_f64_field(&field_config.name, options)
+                }
+                "date" => {
+                    let mut options = DateOptions::default()
+                        .set_precision(tantivy::schema::DateTimePrecision::Seconds);
+                    if field_config.stored {
+                        options = options.set_stored();
+                    }
+                    if field_config.indexed {
+                        options = options.set_indexed();
+                    }
+                    if field_config.fast {
+                        options = options.set_fast();
+                    }
+                    schema_builder.add_date_field(&field_config.name, options)
+                }
+                "json" => {
+                    // JSON field for dynamic/schemaless data
+                    let mut options = JsonObjectOptions::default();
+                    if field_config.stored {
+                        options = options.set_stored();
+                    }
+                    if field_config.indexed {
+                        options = options.set_indexing_options(
+                            TextFieldIndexing::default()
+                                .set_tokenizer("default")
+                                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+                        );
+                    }
+                    if field_config.fast {
+                        options = options.set_expand_dots_enabled();
+                    }
+                    schema_builder.add_json_field(&field_config.name, options)
+                }
+                geo::GEO_POINT_TYPE => {
+                    // The point is kept as a JSON object for retrieval; filters and
+                    // aggregations read the hidden fast lat/lon sub-fields
+                    for sub_name in [
+                        geo::lat_field_name(&field_config.name),
+                        geo::lon_field_name(&field_config.name),
+                    ] {
+                        let sub_field = schema_builder.add_f64_field(
+                            &sub_name,
+                            NumericOptions::default().set_indexed().set_fast(),
+                        );
+                        field_map.insert(sub_name, sub_field);
+                    }
+
+                    let mut options = JsonObjectOptions::default();
+                    if field_config.stored {
+                        options = options.set_stored();
+                    }
+                    schema_builder.add_json_field(&field_config.name, options)
+                }
+                _ => {
+                    return Err(SearchError::InvalidQuery(format!(
+                        "Unsupported field type: {}",
+                        field_config.field_type
+                    )));
+                }
+            };
+            field_map.insert(field_config.name.clone(), field);
+        }
+
+        // Detected document language, filterable with e.g. `_lang:no`
+        if needs_language_field {
+            let lang_field =
+                schema_builder.add_text_field(language::LANGUAGE_FIELD, STRING | STORED);
+            field_map.insert(language::LANGUAGE_FIELD.to_string(), lang_field);
+        }
+
+        let schema = schema_builder.build();
+        let index_path = Path::new(&self.base_path).join(name);
+        // A leftover directory (e.g. one that failed to load) is never overwritten
+        match std::fs::create_dir(&index_path) {
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                return Err(SearchError::IndexExists(name.to_string()));
+            }
+            result => result.map_err(anyhow::Error::from)?,
+        }
+
+        // An unsharded index keeps its files in the index directory itself
+        let shard_paths = if shards > 1 {
+            (0..shards)
+                .map(|shard| sharding::shard_path(&index_path, shard))
+                .collect()
+        } else {
+            vec![index_path.clone()]
+        };
+        let open = || -> Result<Vec<Index>> {
+            let mut indices = Vec::with_capacity(shard_paths.len());
+            for path in &shard_paths {
+                std::fs::create_dir_all(path)?;
+                let mut index = Index::create_in_dir(path, schema.clone())?;
+                index.set_executor(self.executor.clone());
+
+                // Register custom analyzers
+                Self::register_analyzers(&index);
+                indices.push(index);
+            }
+            Self::save_index_settings(&index_path, settings)?;
+            Ok(indices)
+        };
+        let indices = match open() {
+            Ok(indices) => indices,
+            Err(e) => {
+                // Leave nothing behind that would block creating the index again
+                let _ = std::fs::remove_dir_all(&index_path);
+                return Err(e.into());
+            }
+        };
+
+        let handle = IndexHandle::new(indices, field_map, fields.to_vec(), settings.clone());
+
+        self.indices
+            .write()
+            .unwrap()
+            .insert(name.to_string(), handle);
+
+        Ok(())
+    }
+
+    /// Claim a name for an index about to be created
+    fn reserve_index_name(
+        &self,
+        name: &str,
+    ) -> std::result::Result<NameReservation<'_>, SearchError> {
+        // Lock order matches delete_index: indices, then the reservations
+        let indices = self.indices.read().unwrap();
+        let mut creating = self.creating.lock().unwrap();
+        if indices.contains_key(name)
+            || self.rollover_aliases.read().unwrap().contains_key(name)
+            || !creating.insert(name.to_string())
+        {
+            return Err(SearchError::IndexExists(name.to_string()));
+        }
+        Ok(NameReservation {
+            names: &self.creating,
+            name: name.to_string(),
+        })
+    }
+
+    pub fn add_documents(
+        &self,
+        index_name: &str,
+        documents: &[Document],
+    ) -> std::result::Result<IngestOutcome, SearchError> {
+        let indices = self.read_open(index_name);
+        let handle = indices
+            .get(index_name)
+            .ok_or_else(|| SearchError::IndexNotFound(index_name.to_string()))?;
+
+        let mut batch = IngestBatch::new(handle, index_name)?;
+        let mut ids: Vec<String> = documents.iter().map(|doc| doc.id.clone()).collect();
+        for doc in documents {
+            if let Err(e) = batch.add(doc).and_then(|_| batch.checkpoint()) {
+                // A failed request writes nothing after its last commit
+                batch.rollback()?;
+                self.mirror_writes(&indices, index_name, ids);
+                return Err(e);
+            }
+        }
+        let outcome = batch.commit()?;
+        ids.extend(outcome.replaced_ids.iter().cloned());
+        self.mirror_writes(&indices, index_name, ids);
+        Ok(outcome)
+    }
+
+    /// Run bulk operations with a single commit, returning the outcome and the result
+    /// of each operation in order
+    pub fn bulk(
+        &self,
+        index_name: &str,
+        operations: &[BulkOperation],
+    ) -> std::result::Result<(IngestOutcome, Vec<BulkItem>), SearchError> {
+        let indices = self.read_open(index_name);
+        let handle = indices
+            .get(index_name)
+            .ok_or_else(|| SearchError::IndexNotFound(index_name.to_string()))?;
+
+        let mut batch = IngestBatch::new(handle, index_name)?;
+        batch.tracked_ids = operations
+            .iter()
+            .filter(|op| matches!(op.operation.as_str(), "update" | "upsert"))
+            .filter_map(|op| op.document.as_ref().map(|doc| doc.id.clone()))
+            .collect();
+
+        let mut items = Vec::with_capacity(operations.len());
+        for op in operations {
+            items.push(batch.apply(op));
+            batch.checkpoint()?;
+        }
+        let outcome = batch.commit()?;
+        let ids = items
+            .iter()
+            .filter_map(|item| item.id.clone())
+            .chain(outcome.replaced_ids.iter().cloned())
+            .collect();
+        self.mirror_writes(&indices, index_name, ids);
+        Ok((outcome, items))
+    }
+
+    /// Run bulk operations as one transaction: they are committed together with
+    /// `on_commit`, which records them in the metadata store, or not at all. The first
+    /// operation that fails, an update of a missing document included, rolls back the
+    /// others, and so does a failure of `on_commit`.
+    pub fn transaction(
+        &self,
+        index_name: &str,
+        operations: &[BulkOperation],
+        on_commit: impl FnOnce(&IngestOutcome) -> std::result::Result<(), SearchError>,
+    ) -> std::result::Result<(IngestOutcome, Vec<BulkItem>), SearchError> {
+        let indices = self.read_open(index_name);
+        let handle = indices
+            .get(index_name)
+            .ok_or_else(|| SearchError::IndexNotFound(index_name.to_string()))?;
+
+        let mut batch = IngestBatch::new(handle, index_name)?;
+        batch.tracked_ids = operations
+            .iter()
+            .filter(|op| matches!(op.operation.as_str(), "update" | "upsert"))
+            .filter_map(|op| op.document.as_ref().map(|doc| doc.id.clone()))
+            .collect();
+
+        // Nothing is committed before the end, whatever the index's commit policy
+        let mut items = Vec::with_capacity(operations.len());
+        for (position, op) in operations.iter().enumerate() {
+            let item = batch.apply(op);
+            if let Some(error) = &item.error {
+                let message = format!(
+                    "Operation {} ({}) failed: {}; no operations were applied",
+                    position, op.operation, error
+                );
+                batch.rollback()?;
+                return Err(match item.result {
+                    BulkResult::NotFound => SearchError::Conflict(message),
+                    _ => SearchError::InvalidQuery(message),
+                });
+            }
+            items.push(item);
+        }
+        let outcome = batch.commit_with(on_commit)?;
+        let ids = items
+            .iter()
+            .filter_map(|item| item.id.clone())
+            .chain(outcome.replaced_ids.iter().cloned())
+            .collect();
+        self.mirror_writes(&indices, index_name, ids);
+        Ok((outcome, items))
+    }
+
+    /// Copy the current version of documents just written to an index into the index
+    /// it is being migrated to, if any. Called under the lock the write held, so the
+    /// migration can't finish between the write and its copy. A failed copy fails the
+    /// migration rather than the write.
+    fn mirror_writes(
+        &self,
+        indices: &HashMap<String, IndexHandle>,
+        index_name: &str,
+        ids: Vec<String>,
+    ) {
+        let Some(shadow) = self.shadows.read().unwrap().get(index_name).cloned() else {
+            return;
+        };
+        if let Err(e) = Self::copy_to_shadow(indices, index_name, &shadow, ids) {
+            tracing::warn!(
+                "Failed to copy a write to '{}' into '{}': {}",
+                index_name,
+                shadow.index,
+                e
+            );
+            shadow.fail(format!("Copying a write to '{}' failed: {}", index_name, e));
+        }
+    }
+
+    fn copy_to_shadow(
+        indices: &HashMap<String, IndexHandle>,
+        index_name: &str,
+        shadow: &ShadowIndex,
+        ids: Vec<String>,
+    ) -> std::result::Result<(), SearchError> {
+        let source = indices
+            .get(index_name)
+            .ok_or_else(|| SearchError::IndexNotFound(index_name.to_string()))?;
+        let target = indices
+            .get(&shadow.index)
+            .ok_or_else(|| SearchError::IndexNotFound(shadow.index.clone()))?;
+        let searchers = source.searchers(ReloadPolicy::Manual)?;
+        let id_field = *source.field_map.get("id").unwrap();
+
+        let mut written = shadow.written();
+        let mut batch = IngestBatch::new(target, &shadow.index)?;
+        batch.copying = true;
+        for id in ids {
+            let searcher = &searchers[source.shard_for(&id)];
+            let query = TermQuery::new(
+                Term::from_field_text(id_field, &id),
+                IndexRecordOption::Basic,
+            );
+            match searcher.search(&query, &TopDocs::with_limit(1))?.first() {
+                Some((_, address)) => {
+                    let stored: TantivyDocument = searcher.doc(*address)?;
+                    let mut doc = Self::stored_document(source, &id, &stored);
+                    migration::coerce_fields(&mut doc, &target.field_configs);
+                    batch.add(&doc)?;
+                    batch.checkpoint()?;
+                }
+                None => batch.delete(&id),
+            }
+            written.insert(id);
+        }
+        batch.commit()?;
+        Ok(())
+    }
+
+    /// Stored fields of the documents of an index, or of every generation of a rolling
+    /// index, with the given IDs, by ID. Documents the caller's search key filters out
+    /// aren't returned.
+    pub fn lookup_documents(
+        &self,
+        index_name: &str,
+        ids: &[String],
+        access: &Access,
+    ) -> std::result::Result<HashMap<String, Document>, SearchError> {
+        use std::collections::hash_map::Entry;
+        use tantivy::schema::OwnedValue;
+
+        let index_names = self.search_indices(index_name);
+        let indices = self.read_open_all(&index_names);
+        let mut documents = HashMap::new();
+        let mut found = false;
+        // Newest generation first, so its version of a document wins
+        for name in &index_names {
+            let Some(handle) = indices.get(name) else {
+                continue;
+            };
+            found = true;
+            let id_field = *handle.field_map.get("id").unwrap();
+            let ids_query: Box<dyn Query> = Box::new(TermSetQuery::new(
+                ids.iter().map(|id| Term::from_field_text(id_field, id)),
+            ));
+            let mut clauses = vec![(Occur::Must, ids_query)];
+            for filter in &access.filters {
+                let filter_query = Self::build_query(
+                    handle,
+                    filter,
+                    &Self::default_query_fields(handle),
+                    false,
+                    handle.settings.default_operator,
+                )?;
+                clauses.push((Occur::Must, filter_query));
+            }
+            let query = BooleanQuery::new(clauses);
+
+            for searcher in handle.searchers(ReloadPolicy::Manual)? {
+                for address in searcher.search(&query, &DocSetCollector)? {
+                    let stored: TantivyDocument = searcher.doc(address)?;
+                    if let Some(OwnedValue::Str(id)) =
+                        stored.get_first(id_field).map(OwnedValue::from)
+                    {
+                        if let Entry::Vacant(entry) = documents.entry(id) {
+                            let document = Self::stored_document(handle, entry.key(), &stored);
+                            entry.insert(document);
+                        }
+                    }
+                }
+            }
+        }
+        if !found {
+            return Err(SearchError::IndexNotFound(index_name.to_string()));
+        }
+        Ok(documents)
+    }
+
+    /// Searchers of every index searched through a name, each pinned to its current
+    /// commit, so `snapshot_documents` reads the same documents however long it takes
+    pub fn pin_searchers(
+        &self,
+        index_name: &str,
+    ) -> std::result::Result<Vec<(String, Vec<Searcher>)>, SearchError> {
+        let index_names = self.search_indices(index_name);
+        let indices = self.read_open_all(&index_names);
+        let mut pinned = Vec::with_capacity(index_names.len());
+        for name in index_names {
+            if let Some(handle) = indices.get(&name) {
+                let searchers = handle.searchers(ReloadPolicy::Manual)?;
+                pinned.push((name, searchers));
+            }
+        }
+        if pinned.is_empty() {
+            return Err(SearchError::IndexNotFound(index_name.to_string()));
+        }
+        Ok(pinned)
+    }
+
+    /// Up to `limit` live documents of a snapshot taken by `start_shadow_writes` or
+    /// `pin_searchers`, read from `cursor` on, and the cursor to continue from (`None`
+    /// once all are read)
+    pub fn snapshot_documents(
+        &self,
+        index_name: &str,
+        searchers: &[Searcher],
+        cursor: SnapshotCursor,
+        limit: usize,
+    ) -> std::result::Result<(Vec<Document>, Option<SnapshotCursor>), SearchError> {
+        let indices = self.read_open(index_name);
+        let handle = indices
+            .get(index_name)
+            .ok_or_else(|| SearchError::IndexNotFound(index_name.to_string()))?;
+        Self::read_snapshot(handle, searchers, cursor, limit)
+    }
+
+    fn read_snapshot(
+        handle: &IndexHandle,
+        searchers: &[Searcher],
+        cursor: SnapshotCursor,
+        limit: usize,
+    ) -> std::result::Result<(Vec<Document>, Option<SnapshotCursor>), SearchError> {
+        use tantivy::schema::OwnedValue;
+
+        let id_field = *handle.field_map.get("id").unwrap();
+        let segments: Vec<&SegmentReader> =
+            searchers.iter().flat_map(Searcher::segment_readers).collect();
+        let SnapshotCursor {
+            mut segment,
+            mut doc,
+        } = cursor;
+        let mut documents = Vec::new();
+        while let Some(segment_reader) = segments.get(segment) {
+            let store_reader = segment_reader.get_store_reader(0).map_err(anyhow::Error::from)?;
+            let alive_bitset = segment_reader.alive_bitset();
+            while doc < segment_reader.max_doc() {
+                if documents.len() == limit {
+                    return Ok((documents, Some(SnapshotCursor { segment, doc })));
+                }
+                if alive_bitset.is_none_or(|bitset| bitset.is_alive(doc)) {
+                    let stored: TantivyDocument = store_reader.get(doc)?;
+                    if let Some(OwnedValue::Str(id)) =
+                        stored.get_first(id_field).map(OwnedValue::from)
+                    {
+                        documents.push(Self::stored_document(handle, &id, &stored));
+                    }
+                }
+                doc += 1;
+            }
+            segment += 1;
+            doc = 0;
+        }
+        Ok((documents, None))
+    }
+
+    /// Write backfilled documents into a shadow index, leaving out those written to the
+    /// source since the migration started, and return how many were written
+    pub fn backfill_shadow(
+        &self,
+        shadow: &ShadowIndex,
+        documents: Vec<Document>,
+    ) -> std::result::Result<usize, SearchError> {
+        let indices = self.read_open(&shadow.index);
+        let handle = indices
+            .get(&shadow.index)
+            .ok_or_else(|| SearchError::IndexNotFound(shadow.index.clone()))?;
+
+        // Held while writing, so a write copied meanwhile isn't replaced by this version
+        let written = shadow.written();
+        let mut batch = IngestBatch::new(handle, &shadow.index)?;
+        batch.copying = true;
+        let mut count = 0;
+        for mut doc in documents {
+            if written.contains(&doc.id) {
+                continue;
+            }
+            migration::coerce_fields(&mut doc, &handle.field_configs);
+            if let Err(e) = batch.add(&doc).and_then(|_| batch.checkpoint()) {
+                batch.rollback()?;
+                return Err(match e {
+                    SearchError::InvalidQuery(message) => SearchError::InvalidQuery(format!(
+                        "Document '{}': {}",
+                        doc.id, message
+                    )),
+                    e => e,
+                });
+            }
+            count += 1;
+        }
+        batch.commit()?;
+        Ok(count)
+    }
+
+    /// Document made of the stored fields of an indexed document. Fields that aren't
+    /// stored can't be recovered; dates become Unix timestamps.
+    fn stored_document(handle: &IndexHandle, doc_id: &str, stored: &TantivyDocument) -> Document {
+        use tantivy::schema::OwnedValue;
+
+        let mut fields = HashMap::new();
+        for config in &handle.field_configs {
+            let Some(value) = handle
+                .field_map
+                .get(&config.name)
+                .and_then(|field| stored.get_first(*field))
+            else {
+                continue;
+            };
+            let value = match OwnedValue::from(value) {
+                OwnedValue::Str(s) => serde_json::Value::String(s),
+                OwnedValue::U64(n) => serde_json::json!(n),
+                OwnedValue::I64(n) => serde_json::json!(n),
+                OwnedValue::F64(n) => serde_json::json!(n),
+                OwnedValue::Bool(b) => serde_json::json!(b),
+                OwnedValue::Date(d) => serde_json::json!(d.into_timestamp_secs()),
+                value @ (OwnedValue::Object(_) | OwnedValue::Array(_)) => {
+                    serde_json::to_value(&value).unwrap_or_default()
+                }
+                _ => continue,
+            };
+            fields.insert(config.name.clone(), value);
+        }
+
+        // Keep the expiration time unless the update sets a new one
+        if let Some(OwnedValue::Date(expires_at)) = handle
+            .field_map
+            .get(EXPIRES_AT_FIELD)
+            .and_then(|field| stored.get_first(*field))
+            .map(OwnedValue::from)
+        {
+            fields
+                .entry(EXPIRES_AT_DOCUMENT_FIELD.to_string())
+                .or_insert_with(|| serde_json::json!(expires_at.into_timestamp_secs()));
+        }
+
+        Document {
+            id: doc_id.to_string(),
+            fields,
+        }
+    }
+
+    /// SHA-256 over a document's fields except `skip_field`, serialized with sorted keys
+    fn content_hash(doc: &Document, skip_field: Option<&str>) -> Result<String> {
+        use sha2::{Digest, Sha256};
+
+        let sorted: std::collections::BTreeMap<&String, &serde_json::Value> = doc
+            .fields
+            .iter()
+            .filter(|(name, _)| skip_field != Some(String::as_str(name)))
+            .collect();
+        let digest = Sha256::digest(serde_json::to_vec(&sorted)?);
+        Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+
+    /// Parse a date from an RFC3339 string or Unix timestamp
+    fn parse_date_value(value: &serde_json::Value) -> Option<tantivy::DateTime> {
+        if let Some(date_str) = value.as_str() {
+            chrono::DateTime::parse_from_rfc3339(date_str)
+                .ok()
+                .map(|dt| tantivy::DateTime::from_timestamp_secs(dt.timestamp()))
+        } else {
+            value.as_i64().map(tantivy::DateTime::from_timestamp_secs)
+        }
+    }
+
+    fn check_query_length(&self, query: &str) -> std::result::Result<(), SearchError> {
+        let length = query.chars().count();
+        if length > self.query_limits.max_length {
+            return Err(SearchError::InvalidQuery(format!(
+                "Query is too long: {} characters (maximum {})",
+                length, self.query_limits.max_length
+            )));
+        }
+        Ok(())
+    }
+
+    /// Reject queries with more terms or wildcards than the configured limits allow
+    fn check_query_complexity(&self, query: &str) -> std::result::Result<(), SearchError> {
+        let clauses = query
+            .split_whitespace()
+            .map(|word| word.trim_matches(|c| matches!(c, '(' | ')' | '"')))
+            .filter(|word| !word.is_empty() && !is_operator(word))
+            .count();
+        if clauses > self.query_limits.max_clauses {
+            return Err(SearchError::InvalidQuery(format!(
+                "Query has too many terms after synonym expansion: {} (maximum {})",
+                clauses, self.query_limits.max_clauses
+            )));
+        }
+
+        let wildcards = query_syntax::wildcards(query);
+        if wildcards > self.query_limits.max_wildcards {
+            return Err(SearchError::InvalidQuery(format!(
+                "Query has too many wildcards: {} (maximum {})",
+                wildcards, self.query_limits.max_wildcards
+            )));
+        }
+        Ok(())
+    }
+
+    /// Reject wildcard terms with fewer characters before their first wildcard than
+    /// `min_prefix`. A query of just `*` matches all documents and is allowed.
+    fn check_wildcard_prefix(
+        query: &str,
+        min_prefix: usize,
+    ) -> std::result::Result<(), SearchError> {
+        if query.trim() == "*" {
+            return Ok(());
+        }
+        for term in query.split_whitespace() {
+            if query_syntax::literal_prefix(term).is_some_and(|prefix| prefix < min_prefix) {
+                return Err(SearchError::InvalidQuery(format!(
+                    "Wildcard term '{}' needs {} or more characters before its first wildcard",
+                    term, min_prefix
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Estimate the memory a search holds at once from its hit window, aggregations and
+    /// wildcard or fuzzy terms, and refuse it beyond the configured limits
+    fn check_memory(
+        &self,
+        targets: &[SearchTarget],
+        query: &str,
+        fuzzy: bool,
+        offset: usize,
+        limit: usize,
+        aggregations: &[AggregationRequest],
+    ) -> std::result::Result<(), SearchError> {
+        let searchers = || targets.iter().flat_map(|target| &target.searchers);
+        let docs: u64 = searchers().map(|searcher| searcher.num_docs()).sum();
+        let segments = searchers().map(|searcher| searcher.segment_readers().len()).sum();
+
+        let terms = query
+            .split_whitespace()
+            .map(|word| word.trim_matches(|c| matches!(c, '(' | ')' | '"')))
+            .filter(|word| !word.is_empty() && !is_operator(word))
+            .filter(|word| fuzzy || word.contains(['*', '?']))
+            .count();
+        let expansion_bytes = targets
+            .iter()
+            .map(|target| {
+                let max_doc = target
+                    .searchers
+                    .iter()
+                    .flat_map(|searcher| searcher.segment_readers())
+                    .map(|reader| reader.max_doc() as u64)
+                    .sum();
+                breaker::expansion_bytes(terms, target.query_fields.len(), max_doc)
+            })
+            .sum();
+
+        let mut buckets = 0u64;
+        let mut aggregation_bytes = 0u64;
+        for agg in aggregations {
+            let histogram_span = (agg.agg_type == "histogram")
+                .then(|| Self::value_span(targets, &agg.field))
+                .flatten();
+            let stored_terms = Self::uses_stored_terms(targets[0].handle, agg);
+            let (agg_buckets, bytes) =
+                breaker::aggregation_cost(agg, docs, histogram_span, stored_terms);
+            buckets = buckets.saturating_add(agg_buckets);
+            aggregation_bytes = aggregation_bytes.saturating_add(bytes);
+        }
+
+        let hits_bytes = breaker::hits_bytes(offset + limit, limit, segments, docs);
+        let estimate = MemoryEstimate {
+            estimated_bytes: hits_bytes
+                .saturating_add(aggregation_bytes)
+                .saturating_add(expansion_bytes),
+            max_bytes: self.query_limits.max_memory as u64,
+            hits_bytes,
+            aggregation_bytes,
+            expansion_bytes,
+            buckets,
+            max_buckets: self.max_buckets(targets.iter().map(|target| target.handle)) as u64,
+        };
+        match breaker::exceeded(&estimate) {
+            Some(message) => Err(SearchError::MemoryLimit(message, estimate)),
+            None => Ok(()),
+        }
+    }
+
+    /// Bucket limit of a search, lowered by the `max_aggregation_buckets` of its indices
+    fn max_buckets<'a>(&self, handles: impl Iterator<Item = &'a IndexHandle>) -> usize {
+        handles
+            .filter_map(|handle| handle.settings.max_aggregation_buckets)
+            .fold(self.query_limits.max_buckets, usize::min)
+    }
+
+    /// Distance between the smallest and largest value of a fast i64 or f64 field
+    /// across the searched segments
+    fn value_span(targets: &[SearchTarget], field_name: &str) -> Option<f64> {
+        let mut bounds: Option<(f64, f64)> = None;
+        for target in targets {
+            let handle = target.handle;
+            let Some(field_config) = handle.field_configs.iter().find(|fc| fc.name == field_name)
+            else {
+                continue;
+            };
+            if !field_config.fast {
+                continue;
+            }
+            for searcher in &target.searchers {
+                for reader in searcher.segment_readers() {
+                    let fast_fields = reader.fast_fields();
+                    let (min, max) = match field_config.field_type.as_str() {
+                        "i64" => match fast_fields.i64(field_name) {
+                            Ok(column) => (column.min_value() as f64, column.max_value() as f64),
+                            Err(_) => continue,
+                        },
+                        "f64" => match fast_fields.f64(field_name) {
+                            Ok(column) => (column.min_value(), column.max_value()),
+                            Err(_) => continue,
+                        },
+                        _ => return None,
+                    };
+                    let (low, high) = bounds.get_or_insert((min, max));
+                    *low = low.min(min);
+                    *high = high.max(max);
+                }
+            }
+        }
+        bounds.map(|(low, high)| high - low)
+    }
+
+    /// Collect the stored IDs of all live documents matching a term
+    fn ids_for_term(
+        searcher: &tantivy::Searcher,
+        id_field: Field,
+        term: &Term,
+    ) -> Result<Vec<String>> {
+        let query = tantivy::query::TermQuery::new(term.clone(), IndexRecordOption::Basic);
+        Self::ids_for_query(searcher, id_field, &query)
+    }
+
+    /// Collect the stored IDs of all live documents matching a query
+    fn ids_for_query(
+        searcher: &tantivy::Searcher,
+        id_field: Field,
+        query: &dyn Query,
+    ) -> Result<Vec<String>> {
+        let doc_addresses = searcher.search(query, &tantivy::collector::DocSetCollector)?;
+
+        let mut ids = Vec::new();
+        for doc_address in doc_addresses {
+            let doc: TantivyDocument = searcher.doc(doc_address)?;
+            if let Some(OwnedValue::Str(id)) = doc.get_first(id_field).map(OwnedValue::from) {
+                ids.push(id);
+            }
+        }
+        Ok(ids)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    #[allow(dead_code)]
+    pub fn search(
+        &self,
+        index_name: &str,
+        query_str: &str,
+        limit: usize,
+        offset: usize,
+        fields: &[String],
+        highlight_options: Option<&HighlightOptions>,
+        aggregations: &[AggregationRequest],
+    ) -> SearchResult {
+        self.search_internal(
+            index_name,
+            query_str,
+            limit,
+            offset,
+            fields,
+            highlight_options,
+            aggregations,
+            false,
+            None,
+            None,
+            None,
+            None,
+            &[],
+            &[],
+            None,
+            None,
+            None,
+            None,
+            &Access::default(),
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn search_with_options(
+        &self,
+        index_name: &str,
+        query_str: &str,
+        limit: usize,
+        offset: usize,
+        fields: &[String],
+        highlight_options: Option<&HighlightOptions>,
+        aggregations: &[AggregationRequest],
+        fuzzy: bool,
+        sort: Option<&SortOption>,
+        minimum_should_match: Option<usize>,
+        proximity: Option<u32>,
+        default_operator: Option<DefaultOperator>,
+        geo_filters: &[GeoFilter],
+        filter_names: &[String],
+        has_child: Option<&HasChildQuery>,
+        has_parent: Option<&HasParentQuery>,
+        regex: Option<&RegexSearch>,
+        remove_stopwords: Option<bool>,
+        access: &Access,
+    ) -> SearchResult {
+        self.search_internal(
+            index_name,
+            query_str,
+            limit,
+            offset,
+            fields,
+            highlight_options,
+            aggregations,
+            fuzzy,
+            sort,
+            minimum_should_match,
+            proximity,
+            default_operator,
+            geo_filters,
+            filter_names,
+            has_child,
+            has_parent,
+            regex,
+            remove_stopwords,
+            access,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn search_internal(
+        &self,
+        index_name: &str,
+        query_str: &str,
+        limit: usize,
+        offset: usize,
+        fields: &[String],
+        highlight_options: Option<&HighlightOptions>,
+        aggregations: &[AggregationRequest],
+        fuzzy: bool,
+        sort: Option<&SortOption>,
+        minimum_should_match: Option<usize>,
+        proximity: Option<u32>,
+        default_operator: Option<DefaultOperator>,
+        geo_filters: &[GeoFilter],
+        filter_names: &[String],
+        has_child: Option<&HasChildQuery>,
+        has_parent: Option<&HasParentQuery>,
+        regex: Option<&RegexSearch>,
+        remove_stopwords: Option<bool>,
+        access: &Access,
+    ) -> SearchResult {
+        let start = std::time::Instant::now();
+        let hidden_fields = access.hidden_fields.as_slice();
+
+        let settings = self.index_settings(index_name).unwrap_or_default();
+        let min_wildcard_prefix = settings
+            .min_wildcard_prefix
+            .unwrap_or(self.query_limits.min_wildcard_prefix);
+
+        self.check_query_length(query_str)?;
+        Self::check_hidden_reference(query_str, hidden_fields)?;
+        let join_queries = [has_child.map(|c| &c.query), has_parent.map(|p| &p.query)];
+        for join_query in join_queries.into_iter().flatten() {
+            self.check_query_length(join_query)?;
+            self.check_query_complexity(join_query)?;
+            Self::check_wildcard_prefix(join_query, min_wildcard_prefix)?;
+            Self::check_hidden_reference(join_query, hidden_fields)?;
+        }
+
+        // Clean up characters that would otherwise fail to match (smart quotes, emoji, ...)
+        let normalized_query =
+            normalization::normalize_query(query_str, &settings.query_normalizers);
+        let query_str = normalized_query.as_str();
+
+        // Get pinned document IDs for this query BEFORE synonym expansion
+        // (we want to match on the original user query)
+        let pinned_ids = self.get_pinned_doc_ids(index_name, query_str);
+        let pinned_count = pinned_ids.len();
+
+        let stopword_removal = match remove_stopwords {
+            Some(true) => StopwordRemoval::Always,
+            Some(false) => StopwordRemoval::Never,
+            None => settings.remove_stopwords,
+        };
+        let stopwords = Stopwords::new(&settings.stopwords);
+        let without_stopwords = match stopword_removal {
+            StopwordRemoval::Always => stopwords.remove(query_str),
+            _ => None,
+        };
+        let query_str = without_stopwords.as_deref().unwrap_or(query_str);
+
+        // Resolve stored filters by name
+        let stored_filters: Vec<StoredFilter> = {
+            let available = self.get_stored_filters(index_name);
+            filter_names
+                .iter()
+                .map(|name| {
+                    available
+                        .iter()
+                        .find(|filter| &filter.name == name)
+                        .cloned()
+                        .ok_or_else(|| {
+                            SearchError::InvalidQuery(format!("Stored filter not found: {}", name))
+                        })
+                })
+                .collect::<std::result::Result<_, _>>()?
+        };
+
+        // A proximity search turns a plain multi-word query into a sloppy phrase
+        let proximity_query = proximity.and_then(|slop| Self::proximity_query(query_str, slop));
+        let query_str = proximity_query.as_deref().unwrap_or(query_str);
+
+        // Expand query with synonyms before processing
+        let expanded_query = self.expand_query_with_synonyms(index_name, query_str);
+        let query_str = expanded_query.as_str();
+        self.check_query_complexity(query_str)?;
+        Self::check_wildcard_prefix(query_str, min_wildcard_prefix)?;
+
+        // A rolling index is searched across all of its generations, and an index pattern
+        // across every matching index
+        let target_names = self.search_indices(index_name);
+        let indices = self.read_open_all(&target_names);
+        let handles: Vec<(&str, &IndexHandle)> = target_names
+            .iter()
+            .filter_map(|name| Some((name.as_str(), indices.get(name)?)))
+            .collect();
+        if handles.is_empty() {
+            return Err(SearchError::IndexNotFound(index_name.to_string()));
+        }
+        // Hits name their index unless only the requested index itself was searched
+        let names_hit_index = target_names != [index_name];
+
+        // The regex timeout covers scanning the terms of all searched indices
+        let regex_timeout = regex.map_or(0, |regex| regex.timeout_ms);
+        let regex_deadline =
+            std::time::Instant::now() + std::time::Duration::from_millis(regex_timeout);
+        let mut targets = Vec::with_capacity(handles.len());
+        for (name, handle) in handles {
+            let searchers = handle.searchers(ReloadPolicy::OnCommitWithDelay)?;
+
+            // Build query parser for specified fields or all text fields
+            let visible_fields: Vec<Field> = Self::default_query_fields(handle)
+                .into_iter()
+                .filter(|field| {
+                    let name = handle.schema.get_field_name(*field);
+                    !field_access::is_hidden(name, hidden_fields)
+                })
+                .collect();
+            let query_fields: Vec<Field> = if fields.is_empty() {
+                visible_fields.clone()
+            } else {
+                Self::expand_query_fields(handle, fields)
+            };
+
+            let default_operator = default_operator.unwrap_or(handle.settings.default_operator);
+            // A regex search may leave the query empty to match on the regex alone
+            let mut query: Box<dyn Query> = if regex.is_some() && query_str.trim().is_empty() {
+                Box::new(AllQuery)
+            } else {
+                Self::build_query(handle, query_str, &query_fields, fuzzy, default_operator)?
+            };
+
+            // Apply minimum_should_match if specified
+            // This wraps the query in a BooleanQuery with the minimum_should_match setting
+            if let Some(min_match) = minimum_should_match {
+                if min_match > 0 {
+                    let mut bool_query = BooleanQuery::from(vec![(Occur::Should, query)]);
+                    bool_query.set_minimum_number_should_match(min_match);
+                    query = Box::new(bool_query);
+                }
+            }
+
+            // Stored and geo filters restrict the matches without contributing to the score.
+            // Stored and search key filters repeat across searches, so their matches are
+            // cached per segment.
+            if !stored_filters.is_empty() || !access.filters.is_empty() {
+                self.filter_cache.retain_segments(name, &searchers);
+            }
+            let mut filter_queries = Vec::new();
+            let mut key_filters = Vec::new();
+            for filter in &stored_filters {
+                let filter_query = Self::build_query(
+                    handle,
+                    &filter.query,
+                    &Self::default_query_fields(handle),
+                    false,
+                    handle.settings.default_operator,
+                )?;
+                filter_queries.push(self.filter_cache.wrap(name, filter_query));
+            }
+            // The caller's search key limits the documents it can find, and expired
+            // documents are never found
+            for filter in &access.filters {
+                let filter_query = Self::build_query(
+                    handle,
+                    filter,
+                    &Self::default_query_fields(handle),
+                    false,
+                    handle.settings.default_operator,
+                )?;
+                key_filters.push(self.filter_cache.wrap(name, filter_query));
+            }
+            key_filters.extend(Self::unexpired_query(handle));
+            filter_queries.extend(key_filters.iter().map(|filter| filter.box_clone()));
+
+            // Parents and children are linked through the parent field: a parent matches
+            // `has_child` when its ID is the parent of a matching child, a child matches
+            // `has_parent` when its parent is a matching document. The search key also
+            // applies to the documents matched on the other side.
+            let join_query = |query: &str| -> std::result::Result<Box<dyn Query>, SearchError> {
+                let join = Self::build_query(
+                    handle,
+                    query,
+                    &visible_fields,
+                    false,
+                    handle.settings.default_operator,
+                )?;
+                Ok(Self::with_filters(join, &key_filters))
+            };
+            // Clauses with a `_name` are checked again for each hit to report the names
+            // it matched
+            let mut named_clauses: Vec<(String, Box<dyn Query>)> = Vec::new();
+            let mut add_clause = |name: Option<&String>, clause: Box<dyn Query>| {
+                if let Some(name) = name {
+                    named_clauses.push((name.clone(), clause.box_clone()));
+                }
+                filter_queries.push(clause);
+            };
+            let id_field = *handle.field_map.get("id").unwrap();
+            let mut child_query = None;
+            if let Some(has_child) = has_child {
+                let parent_field = Self::parent_field(handle, "has_child")?;
+                let children = join_query(&has_child.query)?;
+                let parent_ids = Self::join_values(&searchers, children.as_ref(), parent_field)?;
+                add_clause(
+                    has_child.name.as_ref(),
+                    Box::new(TermSetQuery::new(
+                        parent_ids.iter().map(|id| Term::from_field_text(id_field, id)),
+                    )),
+                );
+                child_query = Some(children);
+            }
+            if let Some(has_parent) = has_parent {
+                let parent_field = Self::parent_field(handle, "has_parent")?;
+                let parents = join_query(&has_parent.query)?;
+                let parent_ids = Self::join_values(&searchers, parents.as_ref(), id_field)?;
+                add_clause(
+                    has_parent.name.as_ref(),
+                    Box::new(TermSetQuery::new(
+                        parent_ids.iter().map(|id| Term::from_field_text(parent_field, id)),
+                    )),
+                );
+            }
+            if let Some(regex) = regex {
+                add_clause(
+                    regex.name.as_ref(),
+                    Self::regex_query(handle, &searchers, regex, regex_deadline)?,
+                );
+            }
+            for geo_filter in geo_filters
+                .iter()
+                .chain(stored_filters.iter().flat_map(|f| &f.geo_filters))
+            {
+                add_clause(
+                    geo_filter.name.as_ref(),
+                    Self::geo_filter_query(handle, geo_filter)?,
+                );
+            }
+
+            let named_weights = searchers
+                .iter()
+                .map(|searcher| {
+                    named_clauses
+                        .iter()
+                        .map(|(name, named_query)| {
+                            let scoring = EnableScoring::disabled_from_searcher(searcher);
+                            Ok((name.clone(), named_query.weight(scoring)?))
+                        })
+                        .collect::<tantivy::Result<Vec<_>>>()
+                })
+                .collect::<tantivy::Result<Vec<_>>>()?;
+
+            targets.push(SearchTarget {
+                name,
+                handle,
+                searchers,
+                query_fields,
+                default_operator,
+                query: Self::with_filters(query, &filter_queries),
+                filter_queries,
+                named_weights,
+                child_query,
+            });
+        }
+        let window = limit + pinned_count;
+        self.check_memory(&targets, query_str, fuzzy, offset, window, aggregations)?;
+
+        // Get total document count that matches the query
+        let count = |target: &SearchTarget, query: &dyn Query| -> tantivy::Result<usize> {
+            target
+                .searchers
+                .iter()
+                .map(|searcher| searcher.search(query, &tantivy::collector::Count))
+                .sum()
+        };
+        let mut total = 0;
+        for target in &targets {
+            total += count(target, target.query.as_ref())?;
+        }
+
+        // Fallback: if no hits, try a keyword-only query (removes question/stop words)
+        if total == 0 && stopword_removal == StopwordRemoval::Fallback {
+            if let Some(fallback_query) = stopwords.keywords(query_str) {
+                if fallback_query != query_str {
+                    let mut fallbacks = Vec::with_capacity(targets.len());
+                    let mut fallback_total = 0;
+                    for target in &targets {
+                        let fallback = Self::with_filters(
+                            Self::build_query(
+                                target.handle,
+                                &fallback_query,
+                                &target.query_fields,
+                                fuzzy,
+                                target.default_operator,
+                            )?,
+                            &target.filter_queries,
+                        );
+                        fallback_total += count(target, fallback.as_ref())?;
+                        fallbacks.push(fallback);
+                    }
+                    if fallback_total > 0 {
+                        for (target, fallback) in targets.iter_mut().zip(fallbacks) {
+                            target.query = fallback;
+                        }
+                        total = fallback_total;
+                    }
+                }
+            }
+        }
+
+        let shards: Vec<ShardSearch> = targets
+            .iter()
+            .flat_map(|target| {
+                (0..target.searchers.len()).map(move |shard| ShardSearch { target, shard })
+            })
+            .collect();
+
+        let mut hits = Vec::new();
+        let mut add_hit = |score: f32, shard: &ShardSearch, doc_address: DocAddress| -> Result<()> {
+            let handle = shard.handle();
+            let searcher = shard.searcher();
+            let retrieved_doc: TantivyDocument = searcher.doc(doc_address)?;
+            let field_values = Self::hit_fields(handle, &retrieved_doc)?;
+
+            // Generate highlights if requested
+            let highlights = if let Some(opts) = highlight_options {
+                if opts.enabled {
+                    let mut highlight_map = HashMap::new();
+                    let highlight_fields: Vec<&String> = if opts.fields.is_empty() {
+                        shard
+                            .target
+                            .query_fields
+                            .iter()
+                            .filter_map(|f| {
+                                handle.field_map.iter().find_map(|(name, field)| {
+                                    if field == f {
+                                        Some(name)
+                                    } else {
+                                        None
+                                    }
+                                })
+                            })
+                            .collect()
+                    } else {
+                        opts.fields.iter().collect()
+                    };
+
+                    for field_name in highlight_fields {
+                        if let Some(field) = handle.field_map.get(field_name) {
+                            // Check if this is a text field
+                            let field_entry = handle.schema.get_field_entry(*field);
+                            if let FieldType::Str(_) = field_entry.field_type() {
+                                if let Ok(snippet_gen) = tantivy::snippet::SnippetGenerator::create(
+                                    searcher,
+                                    shard.query(),
+                                    *field,
+                                ) {
+                                    let mut snippet = snippet_gen.snippet_from_doc(&retrieved_doc);
+                                    // Use custom highlight tags via the Snippet method
+                                    snippet.set_snippet_prefix_postfix(&opts.pre_tag, &opts.post_tag);
+                                    let highlighted = snippet.to_html();
+                                    if !highlighted.is_empty() {
+                                        highlight_map.insert(field_name.clone(), vec![highlighted]);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    if highlight_map.is_empty() {
+                        None
+                    } else {
+                        Some(highlight_map)
+                    }
+                } else {
+                    None
+                }
+            } else {
+                None
+            };
+
+            let id = field_values
+                .get("id")
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown")
+                .to_string();
+
+            let mut matched_queries = Vec::new();
+            let segment_reader = searcher.segment_reader(doc_address.segment_ord);
+            for (name, weight) in &shard.target.named_weights[shard.shard] {
+                // A new scorer starts on its first match, which may already be past the hit
+                let mut scorer = weight.scorer(segment_reader, 1.0)?;
+                let target = doc_address.doc_id;
+                if scorer.doc() == target
+                    || (scorer.doc() < target && scorer.seek(target) == target)
+                {
+                    matched_queries.push(name.clone());
+                }
+            }
+
+            hits.push(SearchHit {
+                id,
+                index: names_hit_index.then(|| shard.target.name.to_string()),
+                shard: (shard.target.searchers.len() > 1).then_some(shard.shard),
+                score,
+                fields: field_values,
+                highlights,
+                matched_queries,
+                children: Vec::new(),
+            });
+
+            Ok(())
+        };
+
+        if let Some(sort) = sort.filter(|sort| sort.field == RANDOM_SORT_FIELD) {
+            // Without a seed every request gets a different sample
+            let seed = sort
+                .seed
+                .unwrap_or_else(|| uuid::Uuid::new_v4().as_u64_pair().0);
+
+            // Fetch extra results to ensure pinned documents are included
+            let fetch_limit = limit + pinned_count;
+
+            // Keys come from document IDs, so an order survives merges and reindexing
+            let mut segment_keys: HashMap<SegmentId, Vec<u64>> = HashMap::new();
+            for shard in &shards {
+                let id_field = *shard.target.handle.field_map.get("id").unwrap();
+                for segment_reader in shard.searcher().segment_readers() {
+                    segment_keys.insert(
+                        segment_reader.segment_id(),
+                        Self::random_sort_keys(segment_reader, id_field, seed)?,
+                    );
+                }
+            }
+            let segment_keys = Arc::new(segment_keys);
+            let collector = TopDocs::with_limit(offset + fetch_limit).tweak_score(
+                move |segment_reader: &SegmentReader| {
+                    let keys = segment_keys.clone();
+                    let segment = segment_reader.segment_id();
+                    move |doc: DocId, score: Score| (keys[&segment][doc as usize], score)
+                },
+            );
+            let per_shard = shards
+                .iter()
+                .map(|shard| {
+                    shard.searcher().search_with_statistics_provider(
+                        shard.query(),
+                        &collector,
+                        &ShardStatistics(&shard.target.searchers),
+                    )
+                })
+                .collect::<tantivy::Result<Vec<_>>>()?;
+            let top_docs = sharding::merge_top_docs(per_shard, |a, b| b.0.cmp(&a.0));
+            for ((_sort_value, score), shard, doc_address) in
+                top_docs.into_iter().skip(offset).take(fetch_limit)
+            {
+                add_hit(score, &shards[shard], doc_address)?;
+            }
+        } else if let Some(sort) = sort {
+            let field_name = sort.field.as_str();
+            let mut field_type = None;
+            for target in &targets {
+                let field_config = target
+                    .handle
+                    .field_configs
+                    .iter()
+                    .find(|fc| fc.name == field_name)
+                    .ok_or_else(|| {
+                        SearchError::InvalidQuery(format!("Sort field not found: {}", field_name))
+                    })?;
+                if !field_config.fast {
+                    return Err(SearchError::InvalidQuery(format!(
+                        "Sort field '{}' must be configured with fast: true",
+                        field_name
+                    )));
+                }
+                // Values of searched indices must compare with each other
+                if field_type.is_some_and(|field_type| field_type != field_config.field_type) {
+                    return Err(SearchError::InvalidQuery(format!(
+                        "Sort field '{}' has different types in the searched indices",
+                        field_name
+                    )));
+                }
+                field_type = Some(field_config.field_type.as_str());
+            }
+            let field_type = field_type.unwrap_or_default();
+
+            let order = match sort.order {
+                SortOrder::Asc => Order::Asc,
+                SortOrder::Desc => Order::Desc,
+            };
+
+            // Fetch extra results to ensure pinned documents are included
+            let fetch_limit = limit + pinned_count;
+
+            let shard_limit = offset + fetch_limit;
+            let top_docs = match field_type {
+                "i64" => Self::top_docs_by_fast_field::<i64>(
+                    &shards,
+                    field_name,
+                    order,
+                    shard_limit,
+                )?,
+                "f64" => Self::top_docs_by_fast_field::<f64>(
+                    &shards,
+                    field_name,
+                    order,
+                    shard_limit,
+                )?,
+                "date" => Self::top_docs_by_fast_field::<tantivy::DateTime>(
+                    &shards,
+                    field_name,
+                    order,
+                    shard_limit,
+                )?,
+                _ => {
+                    return Err(SearchError::InvalidQuery(format!(
+                        "Sorting is only supported on fast i64, f64, date, or string fields. Field '{}' is type '{}'.",
+                        field_name,
+                        field_type
+                    )));
+                }
+            };
+            for (shard, doc_address) in top_docs.into_iter().skip(offset).take(fetch_limit) {
+                let shard = &shards[shard];
+                let score = shard
+                    .query()
+                    .explain(shard.searcher(), doc_address)
+                    .map(|e| e.value())
+                    .unwrap_or(0.0);
+                add_hit(score, shard, doc_address)?;
+            }
+        } else {
+            // Fetch extra results to ensure pinned documents are included
+            let fetch_limit = offset + limit + pinned_count;
+            let collector = TopDocs::with_limit(fetch_limit);
+            let per_shard = shards
+                .iter()
+                .map(|shard| {
+                    // Scores use the statistics of all shards of the index, so hits from
+                    // different shards compare
+                    shard.searcher().search_with_statistics_provider(
+                        shard.query(),
+                        &collector,
+                        &ShardStatistics(&shard.target.searchers),
+                    )
+                })
+                .collect::<tantivy::Result<Vec<_>>>()?;
+            let top_docs = sharding::merge_top_docs(per_shard, |a, b| b.total_cmp(a));
+            for (score, shard, doc_address) in top_docs.into_iter().take(fetch_limit).skip(offset) {
+                add_hit(score, &shards[shard], doc_address)?;
+            }
+        }
+
+        // Geohash grids, percentile ranks and weighted averages are computed here;
+        // everything else goes through Tantivy's built-in AggregationCollector
+        let (custom_aggregations, aggregations): (
+            Vec<AggregationRequest>,
+            Vec<AggregationRequest>,
+        ) = aggregations.iter().cloned().partition(|agg| {
+            matches!(
+                agg.agg_type.as_str(),
+                geo::GEOHASH_GRID_AGGREGATION
+                    | PERCENTILE_RANKS_AGGREGATION
+                    | WEIGHTED_AVG_AGGREGATION
+            ) || Self::uses_stored_terms(targets[0].handle, agg)
+        });
+
+        // Failed aggregations are reported next to the results instead of failing the search
+        let mut aggregation_errors = Vec::new();
+        let mut agg_results = AggregationResults(Default::default());
+
+        // Unknown fields would silently produce empty results; JSON fields allow sub-paths
+        let (aggregations, unknown_fields): (Vec<AggregationRequest>, Vec<AggregationRequest>) =
+            aggregations
+                .into_iter()
+                .partition(|agg| {
+                    targets
+                        .iter()
+                        .any(|target| Self::has_field(target.handle, &agg.field))
+                });
+        aggregation_errors.extend(unknown_fields.into_iter().map(|agg| AggregationError {
+            error: format!("Aggregation field not found: {}", agg.field),
+            name: agg.name,
+        }));
+
+        if !aggregations.is_empty() {
+            match self.run_aggregations(&shards, &aggregations) {
+                Ok(results) => agg_results = results,
+                Err(e) if aggregations.len() == 1 => aggregation_errors.push(AggregationError {
+                    name: aggregations[0].name.clone(),
+                    error: e.to_string(),
+                }),
+                // Run them one by one so a bad aggregation does not hide the others
+                Err(_) => {
+                    for agg in &aggregations {
+                        match self.run_aggregations(&shards, std::slice::from_ref(agg)) {
+                            Ok(results) => agg_results.0.extend(results.0),
+                            Err(e) => aggregation_errors.push(AggregationError {
+                                name: agg.name.clone(),
+                                error: e.to_string(),
+                            }),
+                        }
+                    }
+                }
+            }
+        }
+
+        for agg in &custom_aggregations {
+            let result = match agg.agg_type.as_str() {
+                geo::GEOHASH_GRID_AGGREGATION => Self::geohash_grid(&shards, agg),
+                PERCENTILE_RANKS_AGGREGATION => Self::percentile_ranks(&shards, agg),
+                "terms" => Self::stored_terms(&shards, agg),
+                _ => Self::weighted_avg(&shards, agg),
+            };
+            match result {
+                Ok(result) => {
+                    agg_results.0.insert(agg.name.clone(), result);
+                }
+                Err(e) => aggregation_errors.push(AggregationError {
+                    name: agg.name.clone(),
+                    error: e.to_string(),
+                }),
+            }
+        }
+        let agg_results = (!agg_results.0.is_empty()).then_some(agg_results);
+
+        let took_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+        // Reorder hits based on pinned rules and truncate to requested limit
+        let mut hits = self.apply_pinned_results(&pinned_ids, hits, limit);
+
+        if let Some(inner_hits) = has_child.map(|c| c.inner_hits).filter(|n| *n > 0) {
+            for hit in &mut hits {
+                let target = targets
+                    .iter()
+                    .find(|target| hit.index.as_deref().is_none_or(|index| index == target.name));
+                if let Some(target) = target {
+                    hit.children = Self::inner_hits(target, &hit.id, inner_hits)?;
+                }
+            }
+        }
+
+        Ok((hits, total, took_ms, agg_results, aggregation_errors))
+    }
+
+    /// Stored fields of a hit; dates are shown as UTC timestamps
+    fn hit_fields(
+        handle: &IndexHandle,
+        doc: &TantivyDocument,
+    ) -> Result<HashMap<String, serde_json::Value>> {
+        let mut field_values = HashMap::new();
+
+        for (field_name, field) in &handle.field_map {
+            if let Some(field_value) = doc.get_all(*field).next() {
+                let owned_value: tantivy::schema::OwnedValue = field_value.into();
+                let value = match owned_value {
+                    tantivy::schema::OwnedValue::Str(s) => serde_json::Value::String(s.to_string()),
+                    tantivy::schema::OwnedValue::U64(n) => serde_json::json!(n),
+                    tantivy::schema::OwnedValue::I64(n) => serde_json::json!(n),
+                    tantivy::schema::OwnedValue::F64(n) => serde_json::json!(n),
+                    tantivy::schema::OwnedValue::Date(d) => {
+                        serde_json::Value::String(d.into_utc().to_string())
+                    }
+                    tantivy::schema::OwnedValue::Bool(b) => serde_json::json!(b),
+                    // JSON fields (and geo points) are stored as objects or arrays
+                    value @ (tantivy::schema::OwnedValue::Object(_)
+                    | tantivy::schema::OwnedValue::Array(_)) => serde_json::to_value(&value)?,
+                    _ => continue,
+                };
+                field_values.insert(field_name.clone(), value);
+            }
+        }
+        Ok(field_values)
+    }
+
+    /// Field of an index's child documents holding their parent's ID
+    fn parent_field(handle: &IndexHandle, clause: &str) -> std::result::Result<Field, SearchError> {
+        handle
+            .settings
+            .parent_field
+            .as_ref()
+            .and_then(|name| handle.field_map.get(name))
+            .copied()
+            .ok_or_else(|| {
+                SearchError::InvalidQuery(format!(
+                    "{} requires the index's parent_field setting",
+                    clause
+                ))
+            })
+    }
+
+    /// Values of a string field in the documents of all shards matching a join query
+    fn join_values(
+        searchers: &[Searcher],
+        query: &dyn Query,
+        field: Field,
+    ) -> std::result::Result<HashSet<String>, SearchError> {
+        use tantivy::schema::OwnedValue;
+
+        let mut values = HashSet::new();
+        let mut matches = 0;
+        for searcher in searchers {
+            let addresses = searcher.search(query, &DocSetCollector)?;
+            matches += addresses.len();
+            if matches > MAX_JOIN_MATCHES {
+                return Err(SearchError::InvalidQuery(format!(
+                    "The has_child or has_parent query matches more than {} documents; \
+                     narrow it down",
+                    MAX_JOIN_MATCHES
+                )));
+            }
+            for address in addresses {
+                let doc: TantivyDocument = searcher.doc(address)?;
+                if let Some(OwnedValue::Str(value)) = doc.get_first(field).map(OwnedValue::from) {
+                    values.insert(value);
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    /// Documents with a term of the regex field matching its pattern, across all shards.
+    /// The matching terms are collected first, scanning only the terms that start with
+    /// the pattern's literal prefix, and the search fails once the scan passes `deadline`.
+    fn regex_query(
+        handle: &IndexHandle,
+        searchers: &[Searcher],
+        regex: &RegexSearch,
+        deadline: std::time::Instant,
+    ) -> std::result::Result<Box<dyn Query>, SearchError> {
+        // Only searched indices that have the field can match
+        let Some(&field) = handle.field_map.get(&regex.field) else {
+            return Ok(Box::new(TermSetQuery::new(Vec::<Term>::new())));
+        };
+        let matcher = query_syntax::term_regex(&regex.pattern)
+            .map_err(|e| SearchError::InvalidQuery(format!("Invalid regex pattern: {}", e)))?;
+        let prefix = query_syntax::regex_prefix(&regex.pattern);
+
+        let mut terms: HashSet<String> = HashSet::new();
+        let mut scanned = 0usize;
+        for searcher in searchers {
+            for reader in searcher.segment_readers() {
+                let inverted_index = reader.inverted_index(field)?;
+                let mut stream = inverted_index
+                    .terms()
+                    .range()
+                    .ge(prefix.as_bytes())
+                    .into_stream()
+                    .map_err(anyhow::Error::from)?;
+                while stream.advance() {
+                    let key = stream.key();
+                    if !key.starts_with(prefix.as_bytes()) {
+                        break;
+                    }
+                    scanned += 1;
+                    if scanned.is_multiple_of(256) && std::time::Instant::now() > deadline {
+                        return Err(SearchError::Timeout(format!(
+                            "The regex on '{}' took more than {}ms to match; use a longer \
+                             literal prefix or a higher regex.timeout_ms",
+                            regex.field, regex.timeout_ms
+                        )));
+                    }
+                    let Ok(term) = std::str::from_utf8(key) else {
+                        continue;
+                    };
+                    if matcher.is_match(term)
+                        && terms.insert(term.to_string())
+                        && terms.len() > MAX_REGEX_TERMS
+                    {
+                        return Err(SearchError::InvalidQuery(format!(
+                            "The regex on '{}' matches more than {} terms; narrow it down",
+                            regex.field, MAX_REGEX_TERMS
+                        )));
+                    }
+                }
+            }
+        }
+        Ok(Box::new(TermSetQuery::new(
+            terms.iter().map(|term| Term::from_field_text(field, term)),
+        )))
+    }
+
+    /// Best matching children of a parent hit, across the shards of its index
+    fn inner_hits(
+        target: &SearchTarget,
+        parent_id: &str,
+        limit: usize,
+    ) -> std::result::Result<Vec<SearchHit>, SearchError> {
+        let Some(child_query) = &target.child_query else {
+            return Ok(Vec::new());
+        };
+        let parent_field = Self::parent_field(target.handle, "has_child")?;
+        let parent: Box<dyn Query> = Box::new(TermQuery::new(
+            Term::from_field_text(parent_field, parent_id),
+            IndexRecordOption::Basic,
+        ));
+        let query = BooleanQuery::new(vec![
+            (Occur::Must, child_query.box_clone()),
+            (Occur::Must, parent),
+        ]);
+
+        let collector = TopDocs::with_limit(limit);
+        let per_shard = target
+            .searchers
+            .iter()
+            .map(|searcher| {
+                searcher.search_with_statistics_provider(
+                    &query,
+                    &collector,
+                    &ShardStatistics(&target.searchers),
+                )
+            })
+            .collect::<tantivy::Result<Vec<_>>>()?;
+        let mut children = Vec::new();
+        for (score, shard, address) in
+            sharding::merge_top_docs(per_shard, |a, b| b.total_cmp(a)).into_iter().take(limit)
+        {
+            let stored: TantivyDocument = target.searchers[shard].doc(address)?;
+            let fields = Self::hit_fields(target.handle, &stored)?;
+            children.push(SearchHit {
+                id: fields.get("id").and_then(|v| v.as_str()).unwrap_or("unknown").to_string(),
+                index: None,
+                shard: (target.searchers.len() > 1).then_some(shard),
+                score,
+                fields,
+                highlights: None,
+                matched_queries: Vec::new(),
+                children: Vec::new(),
+            });
+        }
+        Ok(children)
+    }
+
+    /// Top documents of every shard ordered by a fast field, merged into one list
+    fn top_docs_by_fast_field<T>(
+        shards: &[ShardSearch],
+        field_name: &str,
+        order: Order,
+        limit: usize,
+    ) -> tantivy::Result<Vec<(usize, DocAddress)>>
+    where
+        T: tantivy::fastfield::FastValue + PartialOrd,
+    {
+        let descending = matches!(order, Order::Desc);
+        let collector = TopDocs::with_limit(limit).order_by_fast_field::<T>(field_name, order);
+        let per_shard = shards
+            .iter()
+            .map(|shard| shard.searcher().search(shard.query(), &collector))
+            .collect::<tantivy::Result<Vec<_>>>()?;
+        let top_docs = sharding::merge_top_docs(per_shard, |a, b| {
+            let ordering = a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal);
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+        Ok(top_docs
+            .into_iter()
+            .map(|(_, shard, address)| (shard, address))
+            .collect())
+    }
+
+    /// Random sort key of each document of a segment, from the hash of its ID, so the
+    /// same seed orders documents the same way whatever segment they are in
+    fn random_sort_keys(
+        segment_reader: &SegmentReader,
+        id_field: Field,
+        seed: u64,
+    ) -> tantivy::Result<Vec<u64>> {
+        let mut keys = vec![0; segment_reader.max_doc() as usize];
+        let inverted_index = segment_reader.inverted_index(id_field)?;
+        let mut terms = inverted_index.terms().stream()?;
+        while terms.advance() {
+            let key = random_sort_key(seed, sharding::id_hash(terms.key()));
+            let mut postings = inverted_index
+                .read_postings_from_terminfo(terms.value(), IndexRecordOption::Basic)?;
+            while postings.doc() != TERMINATED {
+                keys[postings.doc() as usize] = key;
+                postings.advance();
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Documents whose expiration time has passed
+    fn expired_query(expires_field: Field) -> RangeQuery {
+        let now = tantivy::DateTime::from_timestamp_secs(chrono::Utc::now().timestamp());
+        RangeQuery::new(
+            Bound::Unbounded,
+            Bound::Included(Term::from_field_date(expires_field, now)),
+        )
+    }
+
+    /// Documents that haven't expired, or `None` for indices created before TTL support.
+    /// The sweeper deletes expired documents only every so often, so searches leave them
+    /// out until then.
+    fn unexpired_query(handle: &IndexHandle) -> Option<Box<dyn Query>> {
+        let expires_field = *handle.field_map.get(EXPIRES_AT_FIELD)?;
+        Some(Box::new(BooleanQuery::from(vec![
+            (Occur::Must, Box::new(AllQuery) as Box<dyn Query>),
+            (Occur::MustNot, Box::new(Self::expired_query(expires_field))),
+        ])))
+    }
+
+    /// `query` restricted to the documents matching every filter, which don't add to the score
+    fn with_filters(query: Box<dyn Query>, filter_queries: &[Box<dyn Query>]) -> Box<dyn Query> {
+        if filter_queries.is_empty() {
+            return query;
+        }
+        let mut clauses = vec![(Occur::Must, query)];
+        for filter_query in filter_queries {
+            let filter: Box<dyn Query> =
+                Box::new(ConstScoreQuery::new(filter_query.box_clone(), 0.0));
+            clauses.push((Occur::Must, filter));
+        }
+        Box::new(BooleanQuery::from(clauses))
+    }
+
+    /// Apply pinned results - move pinned documents to the top in the specified order
+    /// and truncate to the requested limit
+    fn apply_pinned_results(
+        &self,
+        pinned_ids: &[String],
+        mut hits: Vec<SearchHit>,
+        limit: usize,
+    ) -> Vec<SearchHit> {
+        if pinned_ids.is_empty() {
+            // No pinned rules, just truncate to limit
+            hits.truncate(limit);
+            return hits;
+        }
+
+        // Extract pinned hits from the result set (maintain pinned order)
+        let mut pinned_hits: Vec<SearchHit> = Vec::new();
+        let mut remaining_hits: Vec<SearchHit> = Vec::new();
+
+        // Create a set of pinned IDs for quick lookup
+        let pinned_set: std::collections::HashSet<&String> = pinned_ids.iter().collect();
+
+        // Separate pinned and non-pinned hits
+        for hit in hits.drain(..) {
+            if pinned_set.contains(&hit.id) {
+                pinned_hits.push(hit);
+            } else {
+                remaining_hits.push(hit);
+            }
+        }
+
+        // Sort pinned hits according to the order in pinned_ids
+        pinned_hits.sort_by(|a, b| {
+            let pos_a = pinned_ids.iter().position(|id| id == &a.id).unwrap_or(usize::MAX);
+            let pos_b = pinned_ids.iter().position(|id| id == &b.id).unwrap_or(usize::MAX);
+            pos_a.cmp(&pos_b)
+        });
+
+        // Combine: pinned first, then remaining
+        pinned_hits.extend(remaining_hits);
+        
+        // Truncate to the requested limit
+        pinned_hits.truncate(limit);
+        pinned_hits
+    }
+
+    /// Queries may not name fields the caller isn't allowed to see
+    fn check_hidden_reference(
+        query: &str,
+        hidden_fields: &[String],
+    ) -> std::result::Result<(), SearchError> {
+        match field_access::hidden_query_reference(query, hidden_fields) {
+            Some(field) => Err(SearchError::RestrictedField(field)),
+            None => Ok(()),
+        }
+    }
+
+    /// Fields with the given names, plus their per-language sub-fields
+    fn expand_query_fields(handle: &IndexHandle, names: &[String]) -> Vec<Field> {
+        names
+            .iter()
+            .flat_map(|name| {
+                let mut matched: Vec<Field> =
+                    handle.field_map.get(name).copied().into_iter().collect();
+                matched.extend(language::supported_codes().iter().filter_map(|code| {
+                    handle
+                        .field_map
+                        .get(&language::sub_field_name(name, code))
+                        .copied()
+                }));
+                matched
+            })
+            .collect()
+    }
+
+    /// Text fields searched when a request does not list fields
+    fn default_query_fields(handle: &IndexHandle) -> Vec<Field> {
+        if !handle.settings.searchable_fields.is_empty() {
+            return Self::expand_query_fields(handle, &handle.settings.searchable_fields);
+        }
+
+        // Only include text fields in the default query parser to avoid parse errors
+        handle
+            .field_map
+            .iter()
+            .filter(|(_, field)| {
+                matches!(
+                    handle.schema.get_field_entry(**field).field_type(),
+                    FieldType::Str(_)
+                )
+            })
+            .map(|(_, field)| *field)
+            .collect()
+    }
+
+    fn build_query(
+        handle: &IndexHandle,
+        query_str: &str,
+        query_fields: &[Field],
+        fuzzy: bool,
+        default_operator: DefaultOperator,
+    ) -> std::result::Result<Box<dyn Query>, SearchError> {
+        // Preprocess field grouping syntax: title:(foo AND bar) -> (title:foo AND title:bar)
+        let query_str = Self::expand_field_grouping(query_str);
+        let query_str = query_str.as_str();
+        
+        let mut query_parser = QueryParser::for_index(handle.index(), query_fields.to_vec());
+        if default_operator == DefaultOperator::And {
+            query_parser.set_conjunction_by_default();
+        }
+        
+        // Check for _exists_ query (e.g., "_exists_:field_name")
+        if let Some(field_name) = query_str.strip_prefix("_exists_:") {
+            let field_name = field_name.trim();
+            if handle.field_map.contains_key(field_name) {
+                // ExistsQuery::new(field_name, json_subpaths) - second param enables JSON subpath matching
+                return Ok(Box::new(ExistsQuery::new(field_name.to_string(), false)));
+            } else {
+                return Err(SearchError::InvalidQuery(format!(
+                    "Field not found for exists query: {}",
+                    field_name
+                )));
+            }
+        }
+        
+        // Check for TermSetQuery syntax: field:IN[term1,term2,term3]
+        // This is more efficient than field:term1 OR field:term2 OR field:term3
+        if let Some(in_pos) = query_str.find(":IN[") {
+            let field_name = &query_str[..in_pos];
+            if let Some(field) = handle.field_map.get(field_name) {
+                // Find closing bracket
+                if let Some(close_pos) = query_str[in_pos..].find(']') {
+                    let terms_str = &query_str[in_pos + 4..in_pos + close_pos];
+                    let terms: Vec<Term> = terms_str
+                        .split(',')
+                        .map(|t| t.trim())
+                        .filter(|t| !t.is_empty())
+                        .map(|t| Term::from_field_text(*field, t))
+                        .collect();
+                    
+                    if !terms.is_empty() {
+                        return Ok(Box::new(TermSetQuery::new(terms)));
+                    }
+                }
+            }
+        }
+        
+        // Check if the query contains wildcards (* or ?), other than escaped ones
+        let has_wildcard = query_syntax::wildcards(query_str) > 0;
+        
+        // Check if this is a phrase query with wildcards (e.g., "b.* b.* wolf")
+        // RegexPhraseQuery handles multi-term wildcard phrase searches
+        if has_wildcard && query_str.starts_with('"') && query_str.ends_with('"') {
+            let phrase_content = &query_str[1..query_str.len() - 1];
+            let query_lower = phrase_content.to_lowercase();
+            
+            // Split into terms and convert each to regex pattern
+            let terms: Vec<String> = query_lower
+                .split_whitespace()
+                .map(|term| {
+                    // Convert wildcard to regex: * -> .*, ? -> .
+                    term.chars()
+                        .map(|c| match c {
+                            '*' => ".*".to_string(),
+                            '?' => ".".to_string(),
+                            '.' | '+' | '^' | '$' | '(' | ')' | '[' | ']' | '{' | '}' | '|' | '\\' => {
+                                format!("\\{}", c)
+                            }
+                            _ => c.to_string(),
+                        })
+                        .collect::<String>()
+                })
+                .collect();
+            
+            // Need at least 2 terms for a phrase query
+            if terms.len() >= 2 {
+                let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+                
+                for field in query_fields {
+                    let field_entry = handle.schema.get_field_entry(*field);
+                    if matches!(field_entry.field_type(), FieldType::Str(_)) {
+                        let regex_phrase_query = RegexPhraseQuery::new(*field, terms.clone());
+                        clauses.push((Occur::Should, Box::new(regex_phrase_query)));
+                    }
+                }
+                
+                if !clauses.is_empty() {
+                    return Ok(if clauses.len() == 1 {
+                        clauses.into_iter().next().unwrap().1
+                    } else {
+                        Box::new(BooleanQuery::from(clauses))
+                    });
+                }
+            }
+        }
+        
+        // For non-phrase wildcard queries, we use RegexQuery
+        // because Tantivy's default QueryParser doesn't support single-term wildcards
+        if has_wildcard {
+            // Convert wildcard syntax to regex syntax
+            // * becomes .* and ? becomes .
+            // Also lowercase the query to match indexed (lowercased) terms
+            let query_lower = query_str.to_lowercase();
+            
+            // Check if it's a field-specific query like "title:eventyr*"
+            let (target_fields, pattern) = if let Some(colon_pos) = query_lower.find(':') {
+                let field_name = &query_lower[..colon_pos];
+                let pattern_part = &query_lower[colon_pos + 1..];
+                
+                // Find the matching field
+                let target_field = handle.field_map.get(field_name).copied();
+                let fields = if let Some(f) = target_field {
+                    vec![f]
+                } else {
+                    // Field not found, use default fields
+                    query_fields.to_vec()
+                };
+                (fields, pattern_part.to_string())
+            } else {
+                (query_fields.to_vec(), query_lower)
+            };
+            
+            // Convert wildcard pattern to regex pattern
+            // Escape regex special chars first, then convert wildcards
+            let regex_pattern = pattern
+                .chars()
+                .map(|c| match c {
+                    '*' => ".*".to_string(),
+                    '?' => ".".to_string(),
+                    // Escape regex special characters
+                    '.' | '+' | '^' | '$' | '(' | ')' | '[' | ']' | '{' | '}' | '|' | '\\' => {
+                        format!("\\{}", c)
+                    }
+                    _ => c.to_string(),
+                })
+                .collect::<String>();
+            
+            // Create regex queries for each target field
+            let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+            for field in &target_fields {
+                // Only create regex queries for text fields
+                let field_entry = handle.schema.get_field_entry(*field);
+                if matches!(field_entry.field_type(), FieldType::Str(_)) {
+                    if let Ok(regex_query) = RegexQuery::from_pattern(&regex_pattern, *field) {
+                        clauses.push((Occur::Should, Box::new(regex_query)));
+                    }
+                }
+            }
+            
+            if !clauses.is_empty() {
+                let wildcard_query: Box<dyn Query> = if clauses.len() == 1 {
+                    clauses.into_iter().next().unwrap().1
+                } else {
+                    Box::new(BooleanQuery::from(clauses))
+                };
+                
+                // If fuzzy is enabled, also add fuzzy queries for the non-wildcard part
+                if fuzzy {
+                    // Extract the prefix (part before the first wildcard)
+                    let prefix = pattern.split(['*', '?']).next().unwrap_or("");
+                    if !prefix.is_empty() && prefix.len() >= 2 {
+                        let mut fuzzy_clauses: Vec<(Occur, Box<dyn Query>)> = vec![
+                            (Occur::Should, wildcard_query)
+                        ];
+                        
+                        for field in &target_fields {
+                            let field_entry = handle.schema.get_field_entry(*field);
+                            if matches!(field_entry.field_type(), FieldType::Str(_)) {
+                                fuzzy_clauses.push((
+                                    Occur::Should,
+                                    Self::fuzzy_term_query(handle, *field, prefix),
+                                ));
+                            }
+                        }
+                        
+                        return Ok(Box::new(BooleanQuery::from(fuzzy_clauses)));
+                    }
+                }
+                
+                return Ok(wildcard_query);
+            }
+        }
+        
+        // For non-wildcard queries, use the standard query parser
+        let base_query = query_parser.parse_query(query_str)?;
+
+        let stemmed = query_fields.iter().any(|field| {
+            !matches!(
+                Self::field_tokenizer(handle, *field),
+                None | Some("default" | "raw")
+            )
+        });
+        if !fuzzy && !stemmed {
+            return Ok(base_query);
+        }
+
+        let terms = Self::plain_query_terms(query_str);
+        if terms.is_empty() {
+            return Ok(base_query);
+        }
+
+        let mut matching_query = base_query;
+        if fuzzy {
+            let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+            for normalized in &terms {
+                let mut field_clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+                for field in query_fields {
+                    field_clauses.push((
+                        Occur::Should,
+                        Self::fuzzy_term_query(handle, *field, normalized),
+                    ));
+                }
+
+                if !field_clauses.is_empty() {
+                    let clause: Box<dyn Query> = if field_clauses.len() == 1 {
+                        field_clauses.into_iter().next().unwrap().1
+                    } else {
+                        Box::new(BooleanQuery::from(field_clauses))
+                    };
+                    clauses.push((Occur::Must, clause));
+                }
+            }
+
+            if !clauses.is_empty() {
+                let fuzzy_query: Box<dyn Query> = if clauses.len() == 1 {
+                    clauses.into_iter().next().unwrap().1
+                } else {
+                    Box::new(BooleanQuery::from(clauses))
+                };
+
+                matching_query = Box::new(BooleanQuery::from(vec![
+                    (Occur::Should, matching_query),
+                    (Occur::Should, fuzzy_query),
+                ]));
+            }
+        }
+
+        // Literal terms in unstemmed fields get a boosted clause so exact matches rank
+        // above fuzzy neighbours and other words sharing the same stem. It only adds
+        // to the score; which documents match is still decided by the query above.
+        let exact_clauses: Vec<(Occur, Box<dyn Query>)> = query_fields
+            .iter()
+            .filter(|field| Self::field_tokenizer(handle, **field) == Some("default"))
+            .flat_map(|field| {
+                terms.iter().map(move |text| {
+                    let term = Term::from_field_text(*field, text);
+                    let term_query: Box<dyn Query> =
+                        Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs));
+                    (Occur::Should, term_query)
+                })
+            })
+            .collect();
+        if exact_clauses.is_empty() {
+            return Ok(matching_query);
+        }
+
+        let exact_query =
+            BoostQuery::new(Box::new(BooleanQuery::from(exact_clauses)), EXACT_MATCH_BOOST);
+        Ok(Box::new(BooleanQuery::from(vec![
+            (Occur::Must, matching_query),
+            (Occur::Should, Box::new(exact_query) as Box<dyn Query>),
+        ])))
+    }
+
+    /// Name of the tokenizer a text field is indexed with
+    fn field_tokenizer(handle: &IndexHandle, field: Field) -> Option<&str> {
+        match handle.schema.get_field_entry(field).field_type() {
+            FieldType::Str(options) => options.get_indexing_options().map(|o| o.tokenizer()),
+            _ => None,
+        }
+    }
+
+    /// Lowercased plain words of a query, skipping operators, negated terms and
+    /// query syntax
+    fn plain_query_terms(query_str: &str) -> Vec<String> {
+        let mut terms = Vec::new();
+        let mut negated = false;
+        for token in query_str.split_whitespace() {
+            if token == "NOT" {
+                negated = true;
+                continue;
+            }
+            if std::mem::take(&mut negated) || token.starts_with('-') {
+                continue;
+            }
+            if token.chars().any(|ch| {
+                matches!(
+                    ch,
+                    '*' | '?'
+                        | ':'
+                        | '"'
+                        | '['
+                        | ']'
+                        | '{'
+                        | '}'
+                        | '('
+                        | ')'
+                        | '!'
+                        | '^'
+                        | '~'
+                        | '\\'
+                        | '/'
+                        | '<'
+                        | '>'
+                        | '='
+                )
+            }) {
+                continue;
+            }
+
+            let trimmed =
+                token.trim_matches(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'));
+            if trimmed.is_empty() {
+                continue;
+            }
+
+            let normalized = trimmed.to_lowercase();
+            if normalized.is_empty() {
+                continue;
+            }
+            if matches!(normalized.as_str(), "and" | "or" | "not") {
+                continue;
+            }
+            terms.push(normalized);
+        }
+        terms
+    }
+
+    /// Typo-tolerant query for one term, following the index's typo tolerance settings
+    fn fuzzy_term_query(handle: &IndexHandle, field: Field, text: &str) -> Box<dyn Query> {
+        let term = Term::from_field_text(field, text);
+        let Some(tolerance) = &handle.settings.typo_tolerance else {
+            return Box::new(FuzzyTermQuery::new(term, 1, true));
+        };
+
+        // Per-language sub-fields follow the setting of their base field
+        let field_name = handle.schema.get_field_name(field);
+        let base_name = language::parse_sub_field_name(field_name)
+            .map(|(base, _)| base)
+            .unwrap_or(field_name);
+        let distance = if tolerance.exact_fields.iter().any(|f| f == base_name) {
+            0
+        } else {
+            tolerance.max_edits(text)
+        };
+        let fuzzy_query: Box<dyn Query> = Box::new(FuzzyTermQuery::new(term, distance, true));
+
+        let prefix: String = text.chars().take(tolerance.prefix_length).collect();
+        if distance == 0 || prefix.is_empty() {
+            return fuzzy_query;
+        }
+
+        // Require the leading characters to match exactly without affecting the score
+        let pattern = prefix
+            .chars()
+            .map(|c| match c {
+                '.' | '+' | '*' | '?' | '^' | '$' | '(' | ')' | '[' | ']' | '{' | '}' | '|'
+                | '\\' => format!("\\{}", c),
+                _ => c.to_string(),
+            })
+            .collect::<String>()
+            + ".*";
+        match RegexQuery::from_pattern(&pattern, field) {
+            Ok(prefix_query) => Box::new(BooleanQuery::from(vec![
+                (Occur::Must, fuzzy_query),
+                (
+                    Occur::Must,
+                    Box::new(ConstScoreQuery::new(Box::new(prefix_query), 0.0)) as Box<dyn Query>,
+                ),
+            ])),
+            Err(_) => fuzzy_query,
+        }
+    }
+
+    /// Expand field grouping syntax: title:(foo AND bar) -> (title:foo AND title:bar)
+    /// This enables Elasticsearch-style field grouping in queries
+    fn expand_field_grouping(query_str: &str) -> String {
+        // Pattern: field_name:(content)
+        // We need to find these and expand them
+        let mut i = 0;
+        let chars: Vec<char> = query_str.chars().collect();
+        let mut output = String::new();
+        
+        while i < chars.len() {
+            // Check if this could be the start of a field name
+            if chars[i].is_alphanumeric() || chars[i] == '_' {
+                // Collect potential field name
+                let field_start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let field_name: String = chars[field_start..i].iter().collect();
+                
+                // Check if followed by :(
+                if i + 1 < chars.len() && chars[i] == ':' && chars[i + 1] == '(' {
+                    // Find matching closing parenthesis
+                    let content_start = i + 2;
+                    let mut depth = 1;
+                    let mut content_end = content_start;
+                    
+                    while content_end < chars.len() && depth > 0 {
+                        if chars[content_end] == '(' {
+                            depth += 1;
+                        } else if chars[content_end] == ')' {
+                            depth -= 1;
+                        }
+                        content_end += 1;
+                    }
+                    
+                    if depth == 0 {
+                        // Extract the content (excluding the final closing paren)
+                        let content: String = chars[content_start..content_end - 1].iter().collect();
+                        
+                        // Expand: add field: prefix to each term that doesn't have a field
+                        let expanded = Self::add_field_prefix_to_terms(&field_name, &content);
+                        output.push('(');
+                        output.push_str(&expanded);
+                        output.push(')');
+                        i = content_end;
+                        continue;
+                    }
+                }
+                
+                // Not a field grouping, output as-is
+                output.push_str(&field_name);
+                continue;
+            }
+            
+            output.push(chars[i]);
+            i += 1;
+        }
+        
+        output
+    }
+    
+    /// Add field: prefix to terms in an expression that don't already have a field prefix
+    fn add_field_prefix_to_terms(field: &str, content: &str) -> String {
+        // Simple tokenization: split by spaces and operators, add prefix to words
+        let mut result = String::new();
+        let mut current_word = String::new();
+        let mut in_quotes = false;
+        let mut quote_char = '"';
+        
+        for c in content.chars() {
+            if (c == '"' || c == '\'') && !in_quotes {
+                // Starting a quote - output current word and start quoted section
+                if !current_word.is_empty() {
+                    if !current_word.contains(':') && !is_operator(&current_word) {
+                        result.push_str(field);
+                        result.push(':');
+                    }
+                    result.push_str(&current_word);
+                    current_word.clear();
+                }
+                in_quotes = true;
+                quote_char = c;
+                result.push(c);
+            } else if c == quote_char && in_quotes {
+                // Ending a quote
+                in_quotes = false;
+                // For phrases in quotes, prefix the whole quoted section
+                if !current_word.is_empty() {
+                    result.push_str(field);
+                    result.push(':');
+                    result.push(quote_char);
+                    result.push_str(&current_word);
+                }
+                result.push(c);
+                current_word.clear();
+            } else if in_quotes {
+                current_word.push(c);
+            } else if c.is_whitespace() || c == '(' || c == ')' {
+                // End of word
+                if !current_word.is_empty() {
+                    if !current_word.contains(':') && !is_operator(&current_word) {
+                        result.push_str(field);
+                        result.push(':');
+                    }
+                    result.push_str(&current_word);
+                    current_word.clear();
+                }
+                result.push(c);
+            } else {
+                current_word.push(c);
+            }
+        }
+        
+        // Handle final word
+        if !current_word.is_empty() {
+            if !current_word.contains(':') && !is_operator(&current_word) {
+                result.push_str(field);
+                result.push(':');
+            }
+            result.push_str(&current_word);
+        }
+        
+        result
+    }
+
+    /// Per-segment latitude/longitude columns of a geo_point field
+    fn geo_columns(
+        searcher: &Searcher,
+        field_name: &str,
+    ) -> Result<Vec<(Column<f64>, Column<f64>)>> {
+        searcher
+            .segment_readers()
+            .iter()
+            .map(|reader| {
+                let fast_fields = reader.fast_fields();
+                Ok((
+                    fast_fields.f64(&geo::lat_field_name(field_name))?,
+                    fast_fields.f64(&geo::lon_field_name(field_name))?,
+                ))
+            })
+            .collect()
+    }
+
+    fn geo_point_at(
+        columns: &[(Column<f64>, Column<f64>)],
+        doc_address: tantivy::DocAddress,
+    ) -> Option<GeoPoint> {
+        let (lat, lon) = &columns[doc_address.segment_ord as usize];
+        Some(GeoPoint {
+            lat: lat.first(doc_address.doc_id)?,
+            lon: lon.first(doc_address.doc_id)?,
+        })
+    }
+
+    /// Fields backing a geo_point field as (lat, lon), or an error if it is not one
+    fn geo_sub_fields(
+        handle: &IndexHandle,
+        field_name: &str,
+    ) -> std::result::Result<(Field, Field), SearchError> {
+        match (
+            handle.field_map.get(&geo::lat_field_name(field_name)),
+            handle.field_map.get(&geo::lon_field_name(field_name)),
+        ) {
+            (Some(lat), Some(lon)) => Ok((*lat, *lon)),
+            _ => Err(SearchError::InvalidQuery(format!(
+                "Field '{}' is not a geo_point field",
+                field_name
+            ))),
+        }
+    }
+
+    fn bounding_box_query(
+        lat_field: Field,
+        lon_field: Field,
+        top_left: &GeoPoint,
+        bottom_right: &GeoPoint,
+    ) -> Box<dyn Query> {
+        let range = |field: Field, from: f64, to: f64| -> Box<dyn Query> {
+            Box::new(RangeQuery::new(
+                Bound::Included(Term::from_field_f64(field, from)),
+                Bound::Included(Term::from_field_f64(field, to)),
+            ))
+        };
+
+        let lon_query = if top_left.lon <= bottom_right.lon {
+            range(lon_field, top_left.lon, bottom_right.lon)
+        } else {
+            // The box crosses the antimeridian
+            Box::new(BooleanQuery::from(vec![
+                (Occur::Should, range(lon_field, top_left.lon, 180.0)),
+                (Occur::Should, range(lon_field, -180.0, bottom_right.lon)),
+            ]))
+        };
+
+        Box::new(BooleanQuery::from(vec![
+            (Occur::Must, range(lat_field, bottom_right.lat, top_left.lat)),
+            (Occur::Must, lon_query),
+        ]))
+    }
+
+    /// Build a query matching documents whose geo_point lies inside the filter shape
+    fn geo_filter_query(
+        handle: &IndexHandle,
+        filter: &GeoFilter,
+    ) -> std::result::Result<Box<dyn Query>, SearchError> {
+        let (lat_field, lon_field) = Self::geo_sub_fields(handle, &filter.field)?;
+
+        match &filter.shape {
+            GeoShape::BoundingBox {
+                top_left,
+                bottom_right,
+            } => {
+                if !geo::is_valid(top_left) || !geo::is_valid(bottom_right) {
+                    return Err(SearchError::InvalidQuery("Bounding box coordinates are out of range".to_string()));
+                }
+                if top_left.lat < bottom_right.lat {
+                    return Err(SearchError::InvalidQuery(
+                        "Bounding box top_left must be north of bottom_right".to_string(),
+                    ));
+                }
+                Ok(Self::bounding_box_query(
+                    lat_field,
+                    lon_field,
+                    top_left,
+                    bottom_right,
+                ))
+            }
+            GeoShape::Polygon { points } => {
+                if points.len() < 3 {
+                    return Err(SearchError::InvalidQuery("Polygon must have at least 3 points".to_string()));
+                }
+                if !points.iter().all(geo::is_valid) {
+                    return Err(SearchError::InvalidQuery("Polygon coordinates are out of range".to_string()));
+                }
+
+                // Narrow down with the polygon's bounding box, then test each candidate
+                let (top_left, bottom_right) = geo::bounding_box(points);
+                let candidates =
+                    Self::bounding_box_query(lat_field, lon_field, &top_left, &bottom_right);
+                Ok(Box::new(geo::PolygonQuery::new(&filter.field, points.clone(), candidates)))
+            }
+        }
+    }
+
+    /// Count matching documents per geohash cell, largest cells first
+    fn geohash_grid(shards: &[ShardSearch], agg: &AggregationRequest) -> Result<AggregationResult> {
+        let precision = agg
+            .precision
+            .unwrap_or(geo::DEFAULT_GEOHASH_PRECISION)
+            .clamp(1, geo::MAX_GEOHASH_PRECISION);
+        let size = agg.size.unwrap_or(DEFAULT_GEOHASH_GRID_SIZE);
+
+        let mut counts: HashMap<String, u64> = HashMap::new();
+        for shard in shards {
+            Self::geo_sub_fields(shard.handle(), &agg.field)?;
+            let searcher = shard.searcher();
+            let columns = Self::geo_columns(searcher, &agg.field)?;
+            for doc_address in searcher.search(shard.query(), &DocSetCollector)? {
+                if let Some(point) = Self::geo_point_at(&columns, doc_address) {
+                    *counts.entry(geo::geohash(&point, precision)).or_default() += 1;
+                }
+            }
+        }
+
+        let mut cells: Vec<(String, u64)> = counts.into_iter().collect();
+        cells.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let sum_other_doc_count = cells.iter().skip(size).map(|(_, count)| count).sum();
+        cells.truncate(size);
+
+        let buckets = cells
+            .into_iter()
+            .map(|(cell, doc_count)| BucketEntry {
+                key_as_string: None,
+                key: Key::Str(cell),
+                doc_count,
+                sub_aggregation: AggregationResults(Default::default()),
+            })
+            .collect();
+
+        Ok(AggregationResult::BucketResult(BucketResult::Terms {
+            buckets,
+            sum_other_doc_count,
+            doc_count_error_upper_bound: None,
+        }))
+    }
+
+    /// Terms aggregations on string fields without fast values count stored values instead
+    fn uses_stored_terms(handle: &IndexHandle, agg: &AggregationRequest) -> bool {
+        agg.agg_type == "terms"
+            && handle
+                .field_map
+                .get(&agg.field)
+                .map(|field| {
+                    let entry = handle.schema.get_field_entry(*field);
+                    matches!(entry.field_type(), FieldType::Str(_)) && !entry.is_fast()
+                })
+                .unwrap_or(false)
+    }
+
+    fn stored_terms_field(handle: &IndexHandle, agg: &AggregationRequest) -> Result<Field> {
+        let field = handle
+            .field_map
+            .get(&agg.field)
+            .copied()
+            .ok_or_else(|| anyhow!("Aggregation field not found: {}", agg.field))?;
+        if !handle.schema.get_field_entry(field).is_stored() {
+            return Err(anyhow!(
+                "Terms aggregation '{}' needs field '{}' to be configured with fast: true or stored: true",
+                agg.name,
+                agg.field
+            ));
+        }
+        Ok(field)
+    }
+
+    /// Terms aggregation over the stored values of matching documents. Whole values
+    /// are counted (text is not split into words), at the cost of reading each document.
+    fn stored_terms(shards: &[ShardSearch], agg: &AggregationRequest) -> Result<AggregationResult> {
+        let size = agg.size.unwrap_or(DEFAULT_TERMS_SIZE);
+
+        let mut counts: HashMap<String, u64> = HashMap::new();
+        for shard in shards {
+            let field = Self::stored_terms_field(shard.handle(), agg)?;
+            let searcher = shard.searcher();
+            for doc_address in searcher.search(shard.query(), &DocSetCollector)? {
+                let doc: TantivyDocument = searcher.doc(doc_address)?;
+                let values: HashSet<String> = doc
+                    .get_all(field)
+                    .filter_map(|value| match OwnedValue::from(value) {
+                        OwnedValue::Str(s) => Some(s),
+                        _ => None,
+                    })
+                    .collect();
+                for value in values {
+                    *counts.entry(value).or_default() += 1;
+                }
+            }
+        }
+
+        let mut terms: Vec<(String, u64)> = counts.into_iter().collect();
+        terms.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let sum_other_doc_count = terms.iter().skip(size).map(|(_, count)| count).sum();
+        terms.truncate(size);
+
+        let buckets = terms
+            .into_iter()
+            .map(|(term, doc_count)| BucketEntry {
+                key_as_string: None,
+                key: Key::Str(term),
+                doc_count,
+                sub_aggregation: AggregationResults(Default::default()),
+            })
+            .collect();
+
+        Ok(AggregationResult::BucketResult(BucketResult::Terms {
+            buckets,
+            sum_other_doc_count,
+            doc_count_error_upper_bound: Some(0),
+        }))
+    }
+
+    /// Per-segment readers for a fast i64 or f64 field, returning values as f64
+    fn numeric_columns(
+        handle: &IndexHandle,
+        searcher: &Searcher,
+        field_name: &str,
+    ) -> Result<Vec<NumericColumn>> {
+        let field_config = handle
+            .field_configs
+            .iter()
+            .find(|fc| fc.name == field_name)
+            .ok_or_else(|| anyhow!("Aggregation field not found: {}", field_name))?;
+        if !field_config.fast || !matches!(field_config.field_type.as_str(), "i64" | "f64") {
+            return Err(anyhow!(
+                "Aggregation field '{}' must be an i64 or f64 field with fast: true",
+                field_name
+            ));
+        }
+
+        searcher
+            .segment_readers()
+            .iter()
+            .map(|reader| {
+                let fast_fields = reader.fast_fields();
+                let column: NumericColumn = if field_config.field_type == "i64" {
+                    let column = fast_fields.i64(field_name)?;
+                    Box::new(move |doc| column.first(doc).map(|v| v as f64))
+                } else {
+                    let column = fast_fields.f64(field_name)?;
+                    Box::new(move |doc| column.first(doc))
+                };
+                Ok(column)
+            })
+            .collect()
+    }
+
+    /// Percentage of matching documents whose value is at or below each requested value
+    fn percentile_ranks(
+        shards: &[ShardSearch],
+        agg: &AggregationRequest,
+    ) -> Result<AggregationResult> {
+        let rank_values = agg
+            .values
+            .as_ref()
+            .filter(|values| !values.is_empty())
+            .ok_or_else(|| {
+                anyhow!("percentile_ranks aggregation '{}' requires values", agg.name)
+            })?;
+        // Only counts are kept: the values at or below each rank value, and all values
+        let mut at_or_below = vec![0u64; rank_values.len()];
+        let mut count = 0u64;
+        for shard in shards {
+            let columns = Self::numeric_columns(shard.handle(), shard.searcher(), &agg.field)?;
+            shard.for_each_match(|segment, doc| {
+                if let Some(value) = columns[segment](doc) {
+                    count += 1;
+                    for (below, rank_value) in at_or_below.iter_mut().zip(rank_values) {
+                        *below += u64::from(value <= *rank_value);
+                    }
+                }
+            })?;
+        }
+
+        // Without values the ranks are undefined and serialize as null
+        let ranks = rank_values
+            .iter()
+            .zip(at_or_below)
+            .map(|(rank_value, below)| {
+                let percent = if count == 0 {
+                    f64::NAN
+                } else {
+                    below as f64 * 100.0 / count as f64
+                };
+                (format!("{:?}", rank_value), percent)
+            })
+            .collect();
+
+        Ok(AggregationResult::MetricResult(MetricResult::Percentiles(
+            PercentilesMetricResult {
+                values: PercentileValues::HashMap(ranks),
+            },
+        )))
+    }
+
+    /// Average of a field weighted by another field; documents missing either are skipped
+    fn weighted_avg(shards: &[ShardSearch], agg: &AggregationRequest) -> Result<AggregationResult> {
+        let weight_field = agg.weight_field.as_deref().ok_or_else(|| {
+            anyhow!("weighted_avg aggregation '{}' requires weight_field", agg.name)
+        })?;
+        let (mut weighted_sum, mut total_weight) = (0.0, 0.0);
+        for shard in shards {
+            let searcher = shard.searcher();
+            let value_columns = Self::numeric_columns(shard.handle(), searcher, &agg.field)?;
+            let weight_columns = Self::numeric_columns(shard.handle(), searcher, weight_field)?;
+            shard.for_each_match(|segment, doc| {
+                if let (Some(value), Some(weight)) =
+                    (value_columns[segment](doc), weight_columns[segment](doc))
+                {
+                    weighted_sum += value * weight;
+                    total_weight += weight;
+                }
+            })?;
+        }
+
+        let value = (total_weight != 0.0).then(|| weighted_sum / total_weight);
+        Ok(AggregationResult::MetricResult(MetricResult::Average(
+            SingleMetricResult { value },
+        )))
+    }
+
+    /// Run aggregations through Tantivy's aggregation collector, merging the
+    /// intermediate results of each shard
+    fn run_aggregations(
+        &self,
+        shards: &[ShardSearch],
+        aggregations: &[AggregationRequest],
+    ) -> Result<AggregationResults> {
+        // The estimate checked before the search can't see every bucket in advance, so
+        // Tantivy enforces the same limits while collecting
+        let max_buckets = self.max_buckets(shards.iter().map(|shard| shard.handle()));
+        let limits = || {
+            AggregationLimitsGuard::new(
+                Some(self.query_limits.max_memory as u64),
+                Some(max_buckets.min(u32::MAX as usize) as u32),
+            )
+        };
+        let agg_req = Self::build_aggregation_request(aggregations)?;
+        let collector = DistributedAggregationCollector::from_aggs(agg_req.clone(), limits());
+        // Merging into empty results would drop every aggregation, so the first shard's
+        // results are merged into
+        let mut merged: Option<IntermediateAggregationResults> = None;
+        for shard in shards {
+            let fruit = shard.searcher().search(shard.query(), &collector)?;
+            match merged.as_mut() {
+                Some(merged) => merged.merge_fruits(fruit)?,
+                None => merged = Some(fruit),
+            }
+        }
+        Ok(merged.unwrap_or_default().into_final_result(agg_req, limits())?)
+    }
+
+    /// Build an Elasticsearch-compatible aggregation request from our AggregationRequest format
+    fn build_aggregation_request(aggregations: &[AggregationRequest]) -> Result<Aggregations> {
+        let mut agg_map = serde_json::Map::new();
+
+        for agg_req in aggregations {
+            let agg_def = match agg_req.agg_type.as_str() {
+                "terms" => {
+                    let mut terms = serde_json::json!({
+                        "field": agg_req.field
+                    });
+                    if let Some(size) = agg_req.size {
+                        terms["size"] = serde_json::json!(size);
+                    }
+                    serde_json::json!({ "terms": terms })
+                }
+                "stats" => {
+                    serde_json::json!({
+                        "stats": { "field": agg_req.field }
+                    })
+                }
+                "avg" => {
+                    serde_json::json!({
+                        "avg": { "field": agg_req.field }
+                    })
+                }
+                "min" => {
+                    serde_json::json!({
+                        "min": { "field": agg_req.field }
+                    })
+                }
+                "max" => {
+                    serde_json::json!({
+                        "max": { "field": agg_req.field }
+                    })
+                }
+                "sum" => {
+                    serde_json::json!({
+                        "sum": { "field": agg_req.field }
+                    })
+                }
+                "count" => {
+                    serde_json::json!({
+                        "value_count": { "field": agg_req.field }
+                    })
+                }
+                "cardinality" => {
+                    serde_json::json!({
+                        "cardinality": { "field": agg_req.field }
+                    })
+                }
+                "histogram" => {
+                    let interval = agg_req.interval.unwrap_or(DEFAULT_HISTOGRAM_INTERVAL);
+                    serde_json::json!({
+                        "histogram": {
+                            "field": agg_req.field,
+                            "interval": interval
+                        }
+                    })
+                }
+                "range" => {
+                    let ranges: Vec<serde_json::Value> = agg_req
+                        .ranges
+                        .as_ref()
                         .map(|r| {
                             r.iter()
                                 .map(|range| {
@@ -1574,213 +5673,2487 @@ impl SearchEngine {
                         })
                         .unwrap_or_default();
 
-                    serde_json::json!({
-                        "range": {
-                            "field": agg_req.field,
-                            "ranges": ranges
-                        }
-                    })
-                }
-                "percentiles" => {
-                    serde_json::json!({
-                        "percentiles": { "field": agg_req.field }
-                    })
-                }
-                "extended_stats" => {
-                    serde_json::json!({
-                        "extended_stats": { "field": agg_req.field }
-                    })
-                }
-                _ => {
-                    return Err(anyhow!("Unsupported aggregation type: {}", agg_req.agg_type));
-                }
-            };
+                    serde_json::json!({
+                        "range": {
+                            "field": agg_req.field,
+                            "ranges": ranges
+                        }
+                    })
+                }
+                "percentiles" => {
+                    serde_json::json!({
+                        "percentiles": { "field": agg_req.field }
+                    })
+                }
+                "extended_stats" => {
+                    serde_json::json!({
+                        "extended_stats": { "field": agg_req.field }
+                    })
+                }
+                _ => {
+                    return Err(anyhow!("Unsupported aggregation type: {}", agg_req.agg_type));
+                }
+            };
+
+            agg_map.insert(agg_req.name.clone(), agg_def);
+        }
+
+        let agg_json = serde_json::Value::Object(agg_map);
+        let aggregations: Aggregations = serde_json::from_value(agg_json)
+            .map_err(|e| anyhow!("Failed to parse aggregations: {}", e))?;
+
+        Ok(aggregations)
+    }
+
+    pub fn suggest(
+        &self,
+        index_name: &str,
+        prefix: &str,
+        field: Option<&str>,
+        limit: usize,
+        hidden_fields: &[String],
+    ) -> std::result::Result<(Vec<String>, f64), SearchError> {
+        let start = std::time::Instant::now();
+        self.check_query_length(prefix)?;
+        Self::check_hidden_reference(prefix, hidden_fields)?;
+        if let Some(field) = field.filter(|f| field_access::is_hidden(f, hidden_fields)) {
+            return Err(SearchError::RestrictedField(field.to_string()));
+        }
+
+        let indices = self.read_open(index_name);
+        let handle = indices
+            .get(index_name)
+            .ok_or_else(|| SearchError::IndexNotFound(index_name.to_string()))?;
+
+        let searchers = handle.searchers(ReloadPolicy::OnCommitWithDelay)?;
+
+        // Build prefix query
+        let query_fields: Vec<Field> = if let Some(f) = field {
+            handle
+                .field_map
+                .get(f)
+                .map(|f| vec![*f])
+                .unwrap_or_default()
+        } else {
+            handle
+                .field_map
+                .iter()
+                .filter(|(name, _)| !field_access::is_hidden(name, hidden_fields))
+                .map(|(_, field)| *field)
+                .collect()
+        };
+
+        let prefix = normalization::normalize_query(prefix, &handle.settings.query_normalizers);
+        let prefix_query = format!("{}*", prefix);
+        let query_parser = QueryParser::for_index(handle.index(), query_fields.clone());
+        let query = query_parser.parse_query(&prefix_query)?;
+
+        let collector = TopDocs::with_limit(limit * 10);
+        let per_shard = searchers
+            .iter()
+            .map(|searcher| searcher.search(&query, &collector))
+            .collect::<tantivy::Result<Vec<_>>>()?;
+        let top_docs = sharding::merge_top_docs(per_shard, |a, b| b.total_cmp(a));
+
+        // Collect unique field values
+        let mut suggestions: HashSet<String> = HashSet::new();
+
+        for (_score, shard, doc_address) in top_docs {
+            let doc: TantivyDocument = searchers[shard].doc(doc_address)?;
+
+            for field in &query_fields {
+                if let Some(field_value) = doc.get_all(*field).next() {
+                    let owned_value: tantivy::schema::OwnedValue = field_value.into();
+                    if let tantivy::schema::OwnedValue::Str(s) = owned_value {
+                        // Check if any word starts with the prefix
+                        for word in s.split_whitespace() {
+                            if word.to_lowercase().starts_with(&prefix.to_lowercase()) {
+                                suggestions.insert(word.to_string());
+                            }
+                        }
+                    }
+                }
+            }
+
+            if suggestions.len() >= limit {
+                break;
+            }
+        }
+
+        let took_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+        let mut result: Vec<_> = suggestions.into_iter().collect();
+        result.sort();
+        result.truncate(limit);
+
+        Ok((result, took_ms))
+    }
+
+    pub fn get_index_stats(
+        &self,
+        index_name: &str,
+        created_at: &str,
+    ) -> std::result::Result<IndexStats, SearchError> {
+        let indices = self.read_open(index_name);
+        let handle = indices
+            .get(index_name)
+            .ok_or_else(|| SearchError::IndexNotFound(index_name.to_string()))?;
+
+        let searchers = handle.searchers(ReloadPolicy::OnCommitWithDelay)?;
+        let doc_count = searchers.iter().map(Searcher::num_docs).sum();
+        let segment_readers: Vec<&SegmentReader> =
+            searchers.iter().flat_map(Searcher::segment_readers).collect();
+        let deleted_document_count = segment_readers
+            .iter()
+            .map(|reader| reader.num_deleted_docs() as u64)
+            .sum();
+
+        // Calculate index size
+        let index_path = Path::new(&self.base_path).join(index_name);
+        let size_bytes = Self::dir_size(&index_path).unwrap_or(0);
+
+        let mut storage = StorageStats::default();
+        for reader in &segment_readers {
+            let usage = reader.space_usage().map_err(anyhow::Error::from)?;
+            storage.term_dictionary += usage.termdict().total().get_bytes();
+            storage.postings += usage.postings().total().get_bytes();
+            storage.positions += usage.positions().total().get_bytes();
+            storage.fast_fields += usage.fast_fields().total().get_bytes();
+            storage.fieldnorms += usage.fieldnorms().total().get_bytes();
+            storage.store += usage.store().total().get_bytes();
+            storage.deletes += usage.deletes().get_bytes();
+        }
+
+        // Build field stats
+        let mut fields = Vec::with_capacity(handle.field_configs.len());
+        for fc in &handle.field_configs {
+            let term_count = match handle.field_map.get(&fc.name) {
+                Some(field) if fc.indexed => {
+                    let mut terms = 0;
+                    for reader in &segment_readers {
+                        terms += reader.inverted_index(*field)?.terms().num_terms() as u64;
+                    }
+                    Some(terms)
+                }
+                _ => None,
+            };
+            fields.push(FieldStats {
+                name: fc.name.clone(),
+                field_type: fc.field_type.clone(),
+                indexed: fc.indexed,
+                stored: fc.stored,
+                term_count,
+            });
+        }
+
+        Ok(IndexStats {
+            name: index_name.to_string(),
+            document_count: doc_count,
+            size_bytes,
+            fields,
+            created_at: created_at.to_string(),
+            opstamp: handle.opstamp()?,
+            shards: handle.shards.len(),
+            segment_count: segment_readers.len(),
+            deleted_document_count,
+            storage,
+            // Filled in from the metadata store and the warmer by the caller
+            metadata_document_count: 0,
+            last_sync: None,
+            filter_cache: self.filter_cache.stats(index_name),
+            last_warmup: None,
+            write_queue: Default::default(),
+        })
+    }
+
+    fn dir_size(path: &Path) -> std::io::Result<u64> {
+        let mut size = 0;
+        if path.is_dir() {
+            for entry in std::fs::read_dir(path)? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                if metadata.is_file() {
+                    size += metadata.len();
+                } else if metadata.is_dir() {
+                    size += Self::dir_size(&entry.path())?;
+                }
+            }
+        }
+        Ok(size)
+    }
+
+    pub fn delete_document(
+        &self,
+        index_name: &str,
+        doc_id: &str,
+    ) -> std::result::Result<(), SearchError> {
+        let indices = self.read_open(index_name);
+        let handle = indices
+            .get(index_name)
+            .ok_or_else(|| SearchError::IndexNotFound(index_name.to_string()))?;
+        Self::check_writable(handle, index_name)?;
+
+        let mut writer = handle.writer(handle.shard_for(doc_id))?;
+        let id_field = handle.field_map.get("id").unwrap();
+
+        writer.delete_term(Term::from_field_text(*id_field, doc_id));
+        writer.commit()?;
+        drop(writer);
+        self.mirror_writes(&indices, index_name, vec![doc_id.to_string()]);
+
+        Ok(())
+    }
+
+    /// Delete several documents by ID with a single commit
+    pub fn delete_documents(&self, index_name: &str, doc_ids: &[String]) -> Result<()> {
+        let indices = self.read_open(index_name);
+        let handle = indices
+            .get(index_name)
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        Self::check_writable(handle, index_name)?;
+
+        let id_field = handle.field_map.get("id").unwrap();
+        let mut by_shard: HashMap<usize, Vec<&String>> = HashMap::new();
+        for doc_id in doc_ids {
+            by_shard.entry(handle.shard_for(doc_id)).or_default().push(doc_id);
+        }
+
+        // Only the shards holding the documents are committed to
+        for (shard, doc_ids) in by_shard {
+            let mut writer = handle.writer(shard)?;
+            for doc_id in doc_ids {
+                writer.delete_term(Term::from_field_text(*id_field, doc_id));
+            }
+            writer.commit()?;
+        }
+        self.mirror_writes(&indices, index_name, doc_ids.to_vec());
+
+        Ok(())
+    }
+
+    /// Delete documents whose expiration time has passed, returning their IDs
+    pub fn delete_expired(&self, index_name: &str) -> Result<Vec<String>> {
+        let indices = self.read_open(index_name);
+        let handle = indices
+            .get(index_name)
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+
+        // Expired documents of a read-only index are deleted once it accepts writes again
+        if handle.settings.read_only {
+            return Ok(Vec::new());
+        }
+
+        // Indices created before TTL support have no expiration field
+        let Some(expires_field) = handle.field_map.get(EXPIRES_AT_FIELD).copied() else {
+            return Ok(Vec::new());
+        };
+        let id_field = *handle.field_map.get("id").unwrap();
+
+        let query = Self::expired_query(expires_field);
+
+        let mut expired_ids = Vec::new();
+        for (shard, searcher) in handle.searchers(ReloadPolicy::Manual)?.iter().enumerate() {
+            let ids = Self::ids_for_query(searcher, id_field, &query)?;
+            if ids.is_empty() {
+                continue;
+            }
+
+            let mut writer = handle.writer(shard)?;
+            writer.delete_query(Box::new(query.clone()))?;
+            writer.commit()?;
+            expired_ids.extend(ids);
+        }
+        if !expired_ids.is_empty() {
+            self.mirror_writes(&indices, index_name, expired_ids.clone());
+        }
+
+        Ok(expired_ids)
+    }
+
+    /// Merge the searchable segments of each shard into one and remove obsolete files,
+    /// returning the number of segments before merging
+    pub fn optimize_index(&self, index_name: &str) -> Result<usize> {
+        let indices = self.read_open(index_name);
+        let handle = indices
+            .get(index_name)
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+
+        let mut segments = 0;
+        for (shard, shard_handle) in handle.shards.iter().enumerate() {
+            let mut writer = handle.writer(shard)?;
+            let segment_ids = shard_handle.index.searchable_segment_ids()?;
+            if segment_ids.len() > 1 {
+                writer.merge(&segment_ids).wait()?;
+            }
+            writer.garbage_collect_files().wait()?;
+            segments += segment_ids.len();
+        }
+
+        Ok(segments)
+    }
+
+    /// Re-add the stored documents of an index, so they are analyzed again with its
+    /// current analyzers, returning the number of documents rewritten. Refused when a
+    /// field isn't stored, since its values would be lost.
+    pub fn reindex_documents(&self, index_name: &str) -> std::result::Result<usize, SearchError> {
+        let indices = self.read_open(index_name);
+        let handle = indices
+            .get(index_name)
+            .ok_or_else(|| SearchError::IndexNotFound(index_name.to_string()))?;
+        let unstored: Vec<&str> = handle
+            .field_configs
+            .iter()
+            .filter(|config| !config.stored)
+            .map(|config| config.name.as_str())
+            .collect();
+        if !unstored.is_empty() {
+            return Err(SearchError::InvalidQuery(format!(
+                "Fields that aren't stored can't be reindexed: {}",
+                unstored.join(", ")
+            )));
+        }
+
+        // The batch holds every shard's writer, so no write lands between reading a
+        // document and writing it back
+        let mut batch = IngestBatch::new(handle, index_name)?;
+        batch.copying = true;
+        let searchers = handle.searchers(ReloadPolicy::Manual)?;
+        let mut ids = Vec::new();
+        let mut cursor = Some(SnapshotCursor::default());
+        while let Some(from) = cursor {
+            let (documents, next) = Self::read_snapshot(handle, &searchers, from, 1000)?;
+            for doc in documents {
+                if let Err(e) = batch.add(&doc).and_then(|_| batch.checkpoint()) {
+                    batch.rollback()?;
+                    self.mirror_writes(&indices, index_name, ids);
+                    return Err(e);
+                }
+                ids.push(doc.id);
+            }
+            cursor = next;
+        }
+        batch.commit()?;
+        let count = ids.len();
+        self.mirror_writes(&indices, index_name, ids);
+        Ok(count)
+    }
+
+    /// Copy the files of the index's current commit into `destination`, returning the
+    /// number of files copied
+    pub fn snapshot_index(&self, index_name: &str, destination: &Path) -> Result<usize> {
+        use tantivy::directory::{Directory, META_LOCK};
+        use tantivy::index::SegmentComponent;
+
+        let indices = self.read_open(index_name);
+        let handle = indices
+            .get(index_name)
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+
+        let index_path = Path::new(&self.base_path).join(index_name);
+        let shard_paths = match sharding::shard_count(&index_path) {
+            0 => vec![index_path.clone()],
+            count => (0..count)
+                .map(|shard| sharding::shard_path(&index_path, shard))
+                .collect(),
+        };
+        std::fs::create_dir_all(destination)?;
+        let mut copied = 0;
+        if index_path.join(SETTINGS_FILE).exists() {
+            std::fs::copy(index_path.join(SETTINGS_FILE), destination.join(SETTINGS_FILE))?;
+            copied += 1;
+        }
+        for (shard, shard_path) in handle.shards.iter().zip(&shard_paths) {
+            // Loaded under the meta lock, like a searcher's segments, and kept while
+            // copying: garbage collection keeps the files of loaded segments, so merges
+            // and commits meanwhile don't remove them
+            let metas = {
+                let _meta_lock = shard.index.directory().acquire_lock(&META_LOCK)?;
+                shard.index.load_metas()?
+            };
+            let shard_destination = destination.join(shard_path.strip_prefix(&index_path)?);
+            std::fs::create_dir_all(&shard_destination)?;
+            for segment in &metas.segments {
+                for file in segment.list_files() {
+                    // A segment without deletes has no delete file
+                    if !segment.has_deletes()
+                        && file == segment.relative_path(SegmentComponent::Delete)
+                    {
+                        continue;
+                    }
+                    std::fs::copy(shard_path.join(&file), shard_destination.join(&file))?;
+                    copied += 1;
+                }
+            }
+            let mut meta_json = serde_json::to_vec_pretty(&metas)?;
+            meta_json.push(b'\n');
+            std::fs::write(shard_destination.join("meta.json"), meta_json)?;
+            copied += 1;
+        }
+
+        Ok(copied)
+    }
+
+    pub fn delete_index(&self, index_name: &str) -> std::result::Result<(), SearchError> {
+        let mut indices = self.indices.write().unwrap();
+        if self.creating.lock().unwrap().contains(index_name) {
+            return Err(SearchError::Conflict(format!(
+                "Index is being created: {}",
+                index_name
+            )));
+        }
+        indices.remove(index_name);
+        self.closed.write().unwrap().remove(index_name);
+        self.filter_cache.invalidate(index_name);
+        self.bump_config_generation();
+
+        let index_path = Path::new(&self.base_path).join(index_name);
+        if index_path.exists() {
+            std::fs::remove_dir_all(&index_path).map_err(anyhow::Error::from)?;
+        }
+
+        Ok(())
+    }
+
+    /// Open and closed indices
+    pub fn list_indices(&self) -> Vec<String> {
+        let mut names = self.open_indices();
+        names.extend(self.closed.read().unwrap().iter().cloned());
+        names
+    }
+
+    /// Indices currently loaded, for background tasks that shouldn't open closed ones
+    pub fn open_indices(&self) -> Vec<String> {
+        self.indices.read().unwrap().keys().cloned().collect()
+    }
+
+    pub fn get_document_count(&self, index_name: &str) -> Result<u64> {
+        let indices = self.read_open(index_name);
+        let handle = indices
+            .get(index_name)
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+
+        let searchers = handle.searchers(ReloadPolicy::OnCommitWithDelay)?;
+        Ok(searchers.iter().map(Searcher::num_docs).sum())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// Engine over a fresh directory, removed when dropped
+    struct TestEngine {
+        engine: SearchEngine,
+        path: std::path::PathBuf,
+    }
+
+    impl TestEngine {
+        fn new() -> Self {
+            Self::with_limits(QueryLimits::default())
+        }
+
+        fn with_limits(limits: QueryLimits) -> Self {
+            let path = std::env::temp_dir().join(format!("search-test-{}", uuid::Uuid::new_v4()));
+            let engine =
+                SearchEngine::new(path.to_str().unwrap(), 1, limits, FilterCache::from_env())
+                    .unwrap();
+            Self { engine, path }
+        }
+
+        /// Create an index from field configs as JSON
+        fn create(&self, name: &str, fields: serde_json::Value) {
+            let fields: Vec<FieldConfig> = serde_json::from_value(fields).unwrap();
+            self.engine
+                .create_index(name, &fields, &IndexSettings::default(), 1)
+                .unwrap();
+        }
+
+        /// Create an index from field configs and settings as JSON
+        fn create_with_settings(
+            &self,
+            name: &str,
+            fields: serde_json::Value,
+            settings: serde_json::Value,
+        ) {
+            let fields: Vec<FieldConfig> = serde_json::from_value(fields).unwrap();
+            let settings: IndexSettings = serde_json::from_value(settings).unwrap();
+            self.engine.create_index(name, &fields, &settings, 1).unwrap();
+        }
+
+        /// Add documents as JSON objects of `id` and `fields`
+        fn add(&self, name: &str, documents: serde_json::Value) {
+            let documents: Vec<Document> = serde_json::from_value(documents).unwrap();
+            self.engine.add_documents(name, &documents).unwrap();
+        }
+
+        /// IDs of the hits of a query, best first
+        fn search_ids(&self, name: &str, query: &str) -> Vec<String> {
+            let (hits, ..) = self.engine.search(name, query, 10, 0, &[], None, &[]).unwrap();
+            hits.into_iter().map(|hit| hit.id).collect()
+        }
+
+        /// Hits of a search request given as JSON, validated first like the search
+        /// handler does
+        fn search_request(
+            &self,
+            name: &str,
+            request: serde_json::Value,
+        ) -> std::result::Result<Vec<SearchHit>, SearchError> {
+            let request: SearchRequest = serde_json::from_value(request).unwrap();
+            self.run_request(name, &request)
+        }
+
+        fn run_request(
+            &self,
+            name: &str,
+            request: &SearchRequest,
+        ) -> std::result::Result<Vec<SearchHit>, SearchError> {
+            self.engine.validate_search_request(name, request)?;
+            let (hits, ..) = self.engine.search_with_options(
+                name,
+                &request.query,
+                request.limit,
+                request.offset,
+                &request.fields,
+                request.highlight.as_ref(),
+                &request.aggregations,
+                request.fuzzy,
+                request.sort.as_ref(),
+                request.minimum_should_match,
+                request.proximity,
+                request.default_operator,
+                &request.geo_filters,
+                &request.filters,
+                request.has_child.as_ref(),
+                request.has_parent.as_ref(),
+                request.regex.as_ref(),
+                request.remove_stopwords,
+                &Access::default(),
+            )?;
+            Ok(hits)
+        }
+
+        /// Hits of a query with its aggregations as JSON and the aggregations that failed
+        fn aggregate(
+            &self,
+            name: &str,
+            query: &str,
+            aggregations: serde_json::Value,
+        ) -> (Vec<SearchHit>, serde_json::Value, Vec<AggregationError>) {
+            let request: SearchRequest = serde_json::from_value(serde_json::json!({
+                "query": query,
+                "aggregations": aggregations,
+            }))
+            .unwrap();
+            let (hits, _, _, results, errors) = self
+                .engine
+                .search_with_options(
+                    name,
+                    &request.query,
+                    10,
+                    0,
+                    &[],
+                    None,
+                    &request.aggregations,
+                    false,
+                    None,
+                    None,
+                    None,
+                    None,
+                    &[],
+                    &[],
+                    None,
+                    None,
+                    None,
+                    None,
+                    &Access::default(),
+                )
+                .unwrap();
+            (hits, serde_json::to_value(results).unwrap(), errors)
+        }
+    }
+
+    impl std::ops::Deref for TestEngine {
+        type Target = SearchEngine;
+
+        fn deref(&self) -> &SearchEngine {
+            &self.engine
+        }
+    }
+
+    impl Drop for TestEngine {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+
+    /// Index of one more SKU than a regex may match, plus one with another prefix
+    fn sku_engine() -> TestEngine {
+        let engine = TestEngine::new();
+        engine.create(
+            "skus",
+            serde_json::json!([{"name": "sku", "field_type": "string", "indexed": true}]),
+        );
+        let mut documents: Vec<serde_json::Value> = (0..=MAX_REGEX_TERMS)
+            .map(|i| {
+                serde_json::json!({"id": i.to_string(), "fields": {"sku": format!("AB-{:05}", i)}})
+            })
+            .collect();
+        documents.push(serde_json::json!({"id": "cd", "fields": {"sku": "CD-00001"}}));
+        engine.add("skus", serde_json::Value::Array(documents));
+        engine
+    }
+
+    /// Documents matching a regex on the `sku` field, or the error of the scan
+    fn regex_matches(
+        engine: &SearchEngine,
+        pattern: &str,
+        deadline: Instant,
+    ) -> std::result::Result<usize, SearchError> {
+        let indices = engine.indices.read().unwrap();
+        let handle = &indices["skus"];
+        let searchers = handle.searchers(ReloadPolicy::Manual)?;
+        let regex = RegexSearch {
+            name: None,
+            field: "sku".to_string(),
+            pattern: pattern.to_string(),
+            timeout_ms: 100,
+        };
+        let query = SearchEngine::regex_query(handle, &searchers, &regex, deadline)?;
+        let mut count = 0;
+        for searcher in &searchers {
+            count += searcher.search(&query, &tantivy::collector::Count)?;
+        }
+        Ok(count)
+    }
+
+    #[test]
+    fn test_regex_query_scans_prefix() {
+        let engine = sku_engine();
+        let later = Instant::now() + Duration::from_secs(60);
+        assert_eq!(regex_matches(&engine, "AB-0000[0-9]", later).unwrap(), 10);
+        assert_eq!(regex_matches(&engine, "[A-Z]{2}-00001", later).unwrap(), 2);
+        assert_eq!(regex_matches(&engine, "XY-.*", later).unwrap(), 0);
+
+        // Only the terms after the prefix are scanned, too few to check the deadline
+        let passed = Instant::now() - Duration::from_secs(1);
+        assert_eq!(regex_matches(&engine, "AB-0000[0-9]", passed).unwrap(), 10);
+        assert!(matches!(
+            regex_matches(&engine, "AB-.*", passed),
+            Err(SearchError::Timeout(_))
+        ));
+        assert!(matches!(
+            regex_matches(&engine, "AB-.*", later),
+            Err(SearchError::InvalidQuery(_))
+        ));
+        assert_eq!(regex_matches(&engine, "AB-0.*", later).unwrap(), MAX_REGEX_TERMS);
+    }
+
+    #[test]
+    fn test_search_leaves_out_expired_documents() {
+        let engine = TestEngine::new();
+        engine.create(
+            "news",
+            serde_json::json!([{"name": "title", "stored": true, "indexed": true}]),
+        );
+        let past = chrono::Utc::now().timestamp() - 60;
+        let future = chrono::Utc::now().timestamp() + 3600;
+        engine.add(
+            "news",
+            serde_json::json!([
+                {"id": "old", "fields": {"title": "storm warning", "expires_at": past}},
+                {"id": "new", "fields": {"title": "storm warning", "expires_at": future}},
+                {"id": "kept", "fields": {"title": "storm warning"}},
+            ]),
+        );
+
+        let mut ids = engine.search_ids("news", "storm");
+        ids.sort();
+        assert_eq!(ids, ["kept", "new"]);
+        assert_eq!(engine.delete_expired("news").unwrap(), ["old"]);
+    }
 
-            agg_map.insert(agg_req.name.clone(), agg_def);
+    #[test]
+    fn test_random_sort_survives_merges() {
+        let engine = TestEngine::new();
+        engine.create(
+            "products",
+            serde_json::json!([{"name": "title", "stored": true, "indexed": true}]),
+        );
+        // Two batches leave two segments to merge
+        for batch in 0..2 {
+            let documents: Vec<serde_json::Value> = (0..10)
+                .map(|i| {
+                    let title = if i % 2 == 0 { "red shoes" } else { "red red red" };
+                    let id = format!("{}-{}", batch, i);
+                    serde_json::json!({"id": id, "fields": {"title": title}})
+                })
+                .collect();
+            engine.add("products", serde_json::Value::Array(documents));
+        }
+        let random = |seed: u64| {
+            let request = serde_json::json!({
+                "query": "red",
+                "sort": {"field": RANDOM_SORT_FIELD, "seed": seed},
+            });
+            engine.search_request("products", request).unwrap()
+        };
+        let ids = |hits: Vec<SearchHit>| hits.into_iter().map(|hit| hit.id).collect::<Vec<_>>();
+
+        let before = ids(random(42));
+        assert_eq!(before.len(), 10);
+        assert_ne!(ids(random(7)), before);
+        assert_eq!(engine.optimize_index("products").unwrap(), 2);
+        assert_eq!(ids(random(42)), before);
+
+        // Hits keep their relevance scores
+        let by_score = random(42);
+        let (hits, ..) = engine.search("products", "red", 20, 0, &[], None, &[]).unwrap();
+        for hit in &by_score {
+            let relevance = hits.iter().find(|h| h.id == hit.id).unwrap().score;
+            assert!(hit.score > 0.0 && (hit.score - relevance).abs() < 1e-6);
         }
+    }
 
-        let agg_json = serde_json::Value::Object(agg_map);
-        let aggregations: Aggregations = serde_json::from_value(agg_json)
-            .map_err(|e| anyhow!("Failed to parse aggregations: {}", e))?;
+    #[test]
+    fn test_proximity_query_rewrites_plain_words() {
+        assert_eq!(
+            SearchEngine::proximity_query("quick  brown fox", 2).as_deref(),
+            Some("\"quick brown fox\"~2")
+        );
+        assert_eq!(
+            SearchEngine::proximity_query("e-mail o'neil", 0).as_deref(),
+            Some("\"e-mail o'neil\"~0")
+        );
+        // Single words and queries with syntax are left alone
+        assert_eq!(SearchEngine::proximity_query("fox", 2), None);
+        assert_eq!(SearchEngine::proximity_query("quick AND fox", 2), None);
+        assert_eq!(SearchEngine::proximity_query("title:quick fox", 2), None);
+        assert_eq!(SearchEngine::proximity_query("\"quick fox\"", 2), None);
+        assert_eq!(SearchEngine::proximity_query("quick fox*", 2), None);
+    }
 
-        Ok(aggregations)
+    #[test]
+    fn test_expand_query_with_synonyms() {
+        let engine = TestEngine::new();
+        engine
+            .add_synonyms(
+                "shop",
+                vec![SynonymGroup {
+                    terms: vec!["shoe".to_string(), "sneaker".to_string()],
+                }],
+            )
+            .unwrap();
+        let expand = |query| engine.expand_query_with_synonyms("shop", query);
+
+        assert_eq!(expand("red shoe"), "red (shoe OR sneaker)");
+        assert_eq!(expand("Shoe"), "(shoe OR sneaker)");
+        // Phrases, operators and query syntax are kept as written
+        assert_eq!(expand("\"red shoe\" shoe"), "\"red shoe\" (shoe OR sneaker)");
+        assert_eq!(expand("shoe AND title:shoe"), "(shoe OR sneaker) AND title:shoe");
+        assert_eq!(expand("sho* ~2 shoe"), "sho* ~2 (shoe OR sneaker)");
+        assert_eq!(expand("boot"), "boot");
     }
 
-    pub fn suggest(
-        &self,
-        index_name: &str,
-        prefix: &str,
-        field: Option<&str>,
-        limit: usize,
-    ) -> Result<(Vec<String>, f64)> {
-        let start = std::time::Instant::now();
+    #[test]
+    fn test_named_clauses_report_matches() {
+        let engine = TestEngine::new();
+        engine.create(
+            "shops",
+            serde_json::json!([
+                {"name": "sku", "field_type": "string", "stored": true, "indexed": true},
+                {"name": "location", "field_type": "geo_point", "stored": true},
+            ]),
+        );
+        let shop = |id: &str, sku: &str, lat: f64, lon: f64| {
+            let location = serde_json::json!({"lat": lat, "lon": lon});
+            serde_json::json!({"id": id, "fields": {"sku": sku, "location": location}})
+        };
+        engine.add(
+            "shops",
+            serde_json::json!([
+                shop("oslo", "AB-1", 59.91, 10.75),
+                shop("bergen", "AB-2", 60.39, 5.32),
+                shop("tromso", "CD-1", 69.65, 18.96),
+            ]),
+        );
+        let east = serde_json::json!({
+            "_name": "east",
+            "field": "location",
+            "bounding_box": {
+                "top_left": {"lat": 61.0, "lon": 8.0},
+                "bottom_right": {"lat": 59.0, "lon": 12.0},
+            },
+        });
+        let matched = |request: serde_json::Value| {
+            let mut hits = engine.search_request("shops", request).unwrap();
+            hits.sort_by(|a, b| a.id.cmp(&b.id));
+            hits.into_iter()
+                .map(|hit| (hit.id, hit.matched_queries))
+                .collect::<Vec<_>>()
+        };
 
-        let indices = self.indices.read().unwrap();
-        let handle = indices
-            .get(index_name)
-            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        let regex = serde_json::json!({"_name": "ab", "field": "sku", "pattern": "AB-.*"});
+        assert_eq!(
+            matched(serde_json::json!({"query": "", "regex": regex, "geo_filters": [east]})),
+            [("oslo".to_string(), vec!["ab".to_string(), "east".to_string()])]
+        );
+        assert_eq!(
+            matched(serde_json::json!({"query": "", "regex": regex})),
+            [
+                ("bergen".to_string(), vec!["ab".to_string()]),
+                ("oslo".to_string(), vec!["ab".to_string()]),
+            ]
+        );
+
+        let duplicate = serde_json::json!({"_name": "east", "field": "sku", "pattern": "AB-.*"});
+        let request = serde_json::json!({"query": "", "regex": duplicate, "geo_filters": [east]});
+        assert!(matches!(
+            engine.search_request("shops", request),
+            Err(SearchError::InvalidQuery(message)) if message.contains("duplicate clause name")
+        ));
+    }
 
-        let reader = handle
-            .index
-            .reader_builder()
-            .reload_policy(ReloadPolicy::OnCommitWithDelay)
-            .try_into()?;
+    #[test]
+    fn test_reindex_rewrites_stored_documents() {
+        let engine = TestEngine::new();
+        engine.create(
+            "products",
+            serde_json::json!([{"name": "title", "stored": true, "indexed": true}]),
+        );
+        engine.add(
+            "products",
+            serde_json::json!([
+                {"id": "a", "fields": {"title": "red shoes"}},
+                {"id": "b", "fields": {"title": "blue shoes"}},
+                {"id": "c", "fields": {"title": "red hat"}},
+            ]),
+        );
+        engine.delete_documents("products", &["c".to_string()]).unwrap();
+
+        assert_eq!(engine.reindex_documents("products").unwrap(), 2);
+        assert_eq!(engine.get_document_count("products").unwrap(), 2);
+        assert_eq!(engine.search_ids("products", "red"), ["a"]);
+        let documents = engine
+            .lookup_documents("products", &["b".to_string()], &Access::default())
+            .unwrap();
+        assert_eq!(documents["b"].fields["title"], "blue shoes");
+
+        // Values of fields that aren't stored would be lost
+        engine.create(
+            "notes",
+            serde_json::json!([{"name": "body", "stored": false, "indexed": true}]),
+        );
+        assert!(matches!(
+            engine.reindex_documents("notes"),
+            Err(SearchError::InvalidQuery(message)) if message.contains("body")
+        ));
+    }
 
-        let searcher = reader.searcher();
+    #[test]
+    fn test_snapshot_copies_last_commit() {
+        let engine = TestEngine::new();
+        engine.create(
+            "products",
+            serde_json::json!([{"name": "title", "stored": true, "indexed": true}]),
+        );
+        engine.add(
+            "products",
+            serde_json::json!([
+                {"id": "a", "fields": {"title": "red"}},
+                {"id": "b", "fields": {"title": "blue"}},
+            ]),
+        );
+        engine.add("products", serde_json::json!([{"id": "c", "fields": {"title": "red"}}]));
+        engine.delete_documents("products", &["a".to_string()]).unwrap();
+
+        let destination = engine.path.join("snapshot");
+        let copied = engine.snapshot_index("products", &destination).unwrap();
+        // Merged away after the snapshot, its files stay in it
+        engine.optimize_index("products").unwrap();
+
+        let files = std::fs::read_dir(&destination).unwrap().count();
+        assert_eq!(copied, files);
+        assert!(destination.join(SETTINGS_FILE).exists());
+        let index = Index::open_in_dir(&destination).unwrap();
+        let searcher = index.reader().unwrap().searcher();
+        assert_eq!(searcher.num_docs(), 2);
+        assert_eq!(searcher.segment_readers().len(), 2);
+    }
 
-        // Build prefix query
-        let query_fields: Vec<Field> = if let Some(f) = field {
-            handle
-                .field_map
-                .get(f)
-                .map(|f| vec![*f])
-                .unwrap_or_default()
-        } else {
-            handle.field_map.values().copied().collect()
+    #[test]
+    fn test_typo_tolerance_follows_term_length() {
+        let engine = TestEngine::new();
+        engine.create_with_settings(
+            "products",
+            serde_json::json!([
+                {"name": "title", "stored": true, "indexed": true},
+                {"name": "sku", "field_type": "string", "stored": true, "indexed": true},
+            ]),
+            serde_json::json!({"typo_tolerance": {
+                "one_typo_min_length": 4,
+                "two_typos_min_length": 8,
+                "exact_fields": ["sku"],
+                "prefix_length": 1,
+            }}),
+        );
+        engine.add(
+            "products",
+            serde_json::json!([
+                {"id": "car", "fields": {"title": "car"}},
+                {"id": "kost", "fields": {"title": "kost"}},
+                {"id": "umbrella", "fields": {"title": "umbrella"}},
+                {"id": "code", "fields": {"sku": "abcd"}},
+            ]),
+        );
+        let fuzzy = |query: &str| {
+            let request = serde_json::json!({"query": query, "fuzzy": true});
+            let hits = engine.search_request("products", request).unwrap();
+            hits.into_iter().map(|hit| hit.id).collect::<Vec<_>>()
         };
 
-        let prefix_query = format!("{}*", prefix);
-        let query_parser = QueryParser::for_index(&handle.index, query_fields.clone());
-        let query = query_parser.parse_query(&prefix_query)?;
+        // Short terms are matched exactly
+        assert!(fuzzy("cat").is_empty());
+        assert_eq!(fuzzy("kast"), ["kost"]);
+        assert!(fuzzy("kazt").is_empty());
+        assert_eq!(fuzzy("umbrelxx"), ["umbrella"]);
+        // The first character must be typed correctly
+        assert!(fuzzy("bost").is_empty());
+        // Exact fields allow no typos
+        assert!(fuzzy("abce").is_empty());
+        assert_eq!(fuzzy("abcd"), ["code"]);
+    }
+
+    #[test]
+    fn test_exact_matches_outrank_fuzzy_neighbours() {
+        let engine = TestEngine::new();
+        engine.create(
+            "words",
+            serde_json::json!([
+                {"name": "title", "stored": true, "indexed": true},
+                {"name": "body", "stored": true, "indexed": true},
+            ]),
+        );
+        // Matching fuzzily in both fields would outscore the literal match alone
+        engine.add(
+            "words",
+            serde_json::json!([
+                {"id": "1", "fields": {"title": "kort", "body": "kort"}},
+                {"id": "2", "fields": {"title": "kost", "body": "pris"}},
+            ]),
+        );
+
+        let request = serde_json::json!({"query": "kost", "fuzzy": true});
+        let hits = engine.search_request("words", request).unwrap();
+        let ids: Vec<&str> = hits.iter().map(|hit| hit.id.as_str()).collect();
+        assert_eq!(ids, ["2", "1"]);
+        assert!(hits[0].score > hits[1].score);
+    }
 
-        let top_docs = searcher.search(&query, &TopDocs::with_limit(limit * 10))?;
+    #[test]
+    fn test_default_operator_requires_all_terms() {
+        let engine = TestEngine::new();
+        let fields = serde_json::json!([{"name": "title", "stored": true, "indexed": true}]);
+        let documents = serde_json::json!([
+            {"id": "1", "fields": {"title": "red shoes"}},
+            {"id": "2", "fields": {"title": "red hat"}},
+        ]);
+        engine.create("any", fields.clone());
+        engine.add("any", documents.clone());
+        engine.create_with_settings("all", fields, serde_json::json!({"default_operator": "AND"}));
+        engine.add("all", documents);
+        let ids = |name: &str, request: serde_json::Value| {
+            let hits = engine.search_request(name, request).unwrap();
+            let mut ids: Vec<String> = hits.into_iter().map(|hit| hit.id).collect();
+            ids.sort();
+            ids
+        };
 
-        // Collect unique field values
-        let mut suggestions: HashSet<String> = HashSet::new();
+        assert_eq!(ids("any", serde_json::json!({"query": "red shoes"})), ["1", "2"]);
+        assert_eq!(ids("all", serde_json::json!({"query": "red shoes"})), ["1"]);
+        // A request overrides the index's operator
+        let request = serde_json::json!({"query": "red shoes", "default_operator": "and"});
+        assert_eq!(ids("any", request), ["1"]);
+        let request = serde_json::json!({"query": "red shoes", "default_operator": "OR"});
+        assert_eq!(ids("all", request), ["1", "2"]);
+        // Explicit operators still apply
+        assert_eq!(ids("all", serde_json::json!({"query": "shoes OR hat"})), ["1", "2"]);
+    }
 
-        for (_score, doc_address) in top_docs {
-            let doc: TantivyDocument = searcher.doc(doc_address)?;
+    #[test]
+    fn test_search_errors_map_to_client_statuses() {
+        use axum::http::StatusCode;
+
+        let engine = TestEngine::new();
+        engine.create(
+            "products",
+            serde_json::json!([{"name": "title", "stored": true, "indexed": true}]),
+        );
+        let status = |name: &str, request: serde_json::Value| {
+            engine.search_request(name, request).unwrap_err().status_code()
+        };
 
-            for field in &query_fields {
-                if let Some(field_value) = doc.get_all(*field).next() {
-                    let owned_value: tantivy::schema::OwnedValue = field_value.into();
-                    if let tantivy::schema::OwnedValue::Str(s) = owned_value {
-                        // Check if any word starts with the prefix
-                        for word in s.split_whitespace() {
-                            if word.to_lowercase().starts_with(&prefix.to_lowercase()) {
-                                suggestions.insert(word.to_string());
-                            }
-                        }
-                    }
-                }
-            }
+        let missing = engine.search("missing", "red", 10, 0, &[], None, &[]).unwrap_err();
+        assert!(matches!(&missing, SearchError::IndexNotFound(name) if name == "missing"));
+        assert_eq!(missing.status_code(), StatusCode::NOT_FOUND);
+        assert_eq!(status("missing", serde_json::json!({"query": "red"})), StatusCode::NOT_FOUND);
+        assert_eq!(
+            status("products", serde_json::json!({"query": "title:(red"})),
+            StatusCode::BAD_REQUEST
+        );
+        let request = serde_json::json!({"query": "red", "sort": {"field": "price"}});
+        assert_eq!(status("products", request), StatusCode::BAD_REQUEST);
+        let exists = engine
+            .create_index("products", &[], &IndexSettings::default(), 1)
+            .unwrap_err();
+        assert_eq!(exists.status_code(), StatusCode::CONFLICT);
+    }
 
-            if suggestions.len() >= limit {
-                break;
-            }
+    #[test]
+    fn test_search_request_validation_names_the_offending_path() {
+        let engine = TestEngine::new();
+        engine.create(
+            "products",
+            serde_json::json!([
+                {"name": "title", "stored": true, "indexed": true},
+                {"name": "price", "field_type": "f64", "stored": true, "indexed": true},
+                {"name": "stock", "field_type": "i64", "stored": true, "fast": true},
+            ]),
+        );
+        let error = |request: serde_json::Value| match engine.search_request("products", request) {
+            Err(SearchError::InvalidQuery(message)) => message,
+            other => panic!("expected an invalid request, got {:?}", other.map(|_| ())),
+        };
+
+        let request = serde_json::json!({"query": "red", "fields": ["title", "titel"]});
+        assert_eq!(error(request), "fields[1]: unknown field 'titel'");
+        let request = serde_json::json!({"query": "red", "sort": {"field": "price"}});
+        assert!(error(request).starts_with("sort.field: field 'price' must be configured"));
+        let request = serde_json::json!({"query": "red", "aggregations": [
+            {"name": "stock", "agg_type": "stats", "field": "stock"},
+            {"name": "stock", "agg_type": "stats", "field": "stock"},
+        ]});
+        assert!(error(request).starts_with("aggregations[1].name: duplicate aggregation name"));
+        let request = serde_json::json!({"query": "red", "aggregations": [
+            {"name": "colors", "agg_type": "terms", "field": "color"},
+        ]});
+        assert_eq!(error(request), "aggregations[0].field: unknown field 'color'");
+
+        let request = serde_json::json!({"query": "red", "sort": {"field": "stock"}});
+        assert!(engine.search_request("products", request).is_ok());
+        // Negative offsets are refused when the request is read
+        let request = serde_json::json!({"query": "red", "offset": -1});
+        assert!(serde_json::from_value::<SearchRequest>(request).is_err());
+    }
+
+    #[test]
+    fn test_query_limits_refuse_long_and_complex_queries() {
+        let engine = TestEngine::with_limits(QueryLimits {
+            max_length: 40,
+            max_clauses: 4,
+            max_wildcards: 2,
+            min_wildcard_prefix: 2,
+            ..QueryLimits::default()
+        });
+        engine.create(
+            "shop",
+            serde_json::json!([{"name": "title", "stored": true, "indexed": true}]),
+        );
+        engine
+            .add_synonyms(
+                "shop",
+                vec![SynonymGroup {
+                    terms: vec!["shoe".to_string(), "sneaker".to_string(), "boot".to_string()],
+                }],
+            )
+            .unwrap();
+        let error = |query: &str| match engine.search("shop", query, 10, 0, &[], None, &[]) {
+            Err(SearchError::InvalidQuery(message)) => message,
+            other => panic!("expected an invalid query, got {:?}", other.map(|_| ())),
+        };
+
+        assert!(error(&"shoe ".repeat(10)).starts_with("Query is too long"));
+        // Three words become five terms once "shoe" is expanded
+        assert!(error("red shoe laces").contains("too many terms after synonym expansion"));
+        assert!(engine.search("shop", "red shoe", 10, 0, &[], None, &[]).is_ok());
+        assert!(error("ab* cd* ef*").contains("too many wildcards"));
+        assert!(error("a*").contains("2 or more characters before its first wildcard"));
+        assert!(engine.search("shop", "ab* cd*", 10, 0, &[], None, &[]).is_ok());
+    }
+
+    #[test]
+    fn test_answer_sources_follow_filters_and_sort() {
+        use crate::models::AnswerRequest;
+
+        let engine = TestEngine::new();
+        engine.create(
+            "docs",
+            serde_json::json!([
+                {"name": "title", "stored": true, "indexed": true},
+                {"name": "status", "field_type": "string", "stored": true, "indexed": true},
+                {"name": "rank", "field_type": "i64", "stored": true, "fast": true},
+            ]),
+        );
+        engine.add(
+            "docs",
+            serde_json::json!([
+                {"id": "draft", "fields": {"title": "refund", "status": "draft", "rank": 3}},
+                {"id": "old", "fields": {"title": "refund", "status": "published", "rank": 1}},
+                {"id": "new", "fields": {"title": "refund", "status": "published", "rank": 2}},
+            ]),
+        );
+        engine
+            .add_stored_filters(
+                "docs",
+                vec![StoredFilter {
+                    name: "published".to_string(),
+                    query: "status:published".to_string(),
+                    geo_filters: Vec::new(),
+                }],
+            )
+            .unwrap();
+
+        let answer: AnswerRequest = serde_json::from_value(serde_json::json!({
+            "query": "refund",
+            "filters": ["published"],
+            "sort": {"field": "rank", "order": "desc"},
+        }))
+        .unwrap();
+        let hits = engine.run_request("docs", &answer.search_request()).unwrap();
+        let ids: Vec<&str> = hits.iter().map(|hit| hit.id.as_str()).collect();
+        assert_eq!(ids, ["new", "old"]);
+
+        let answer: AnswerRequest = serde_json::from_value(serde_json::json!({
+            "query": "refund",
+            "filters": ["drafts"],
+        }))
+        .unwrap();
+        assert!(engine.run_request("docs", &answer.search_request()).is_err());
+    }
+
+    #[test]
+    fn test_bulk_commits_once_per_request() {
+        let engine = TestEngine::new();
+        engine.create(
+            "products",
+            serde_json::json!([{"name": "title", "stored": true, "indexed": true}]),
+        );
+        let mut operations: Vec<serde_json::Value> = (0..20)
+            .map(|i| {
+                serde_json::json!({"operation": "index", "document": {
+                    "id": i.to_string(), "fields": {"title": format!("shoe {}", i)}
+                }})
+            })
+            .collect();
+        operations.push(serde_json::json!({"operation": "delete", "id": "3"}));
+        operations.push(serde_json::json!({"operation": "index"}));
+        let operations: Vec<BulkOperation> =
+            serde_json::from_value(serde_json::Value::Array(operations)).unwrap();
+
+        let (outcome, items) = engine.bulk("products", &operations).unwrap();
+        assert_eq!(items.len(), 22);
+        assert_eq!(items[20].result, BulkResult::Deleted);
+        // A failed operation doesn't stop the others
+        assert_eq!(items[21].result, BulkResult::Error);
+        assert_eq!(outcome.indexed_ids.len(), 19);
+        assert_eq!(engine.get_document_count("products").unwrap(), 19);
+
+        // All writes landed in a single commit, so in one segment
+        let indices = engine.indices.read().unwrap();
+        let segments = indices["products"].index().searchable_segment_ids().unwrap();
+        assert_eq!(segments.len(), 1);
+    }
+
+    #[test]
+    fn test_bulk_update_and_upsert_merge_fields() {
+        let engine = TestEngine::new();
+        engine.create(
+            "products",
+            serde_json::json!([
+                {"name": "title", "stored": true, "indexed": true},
+                {"name": "color", "field_type": "string", "stored": true, "indexed": true},
+            ]),
+        );
+        engine.add("products", serde_json::json!([{"id": "1", "fields": {"title": "shoe"}}]));
+        let operations: Vec<BulkOperation> = serde_json::from_value(serde_json::json!([
+            {"operation": "update", "document": {"id": "1", "fields": {"color": "red"}}},
+            // Later operations on the same document see the earlier ones
+            {"operation": "update", "document": {"id": "1", "fields": {"title": "boot"}}},
+            {"operation": "update", "document": {"id": "2", "fields": {"color": "blue"}}},
+            {"operation": "upsert", "document": {"id": "3", "fields": {"title": "hat"}}},
+            {"operation": "upsert", "document": {"id": "3", "fields": {"color": "green"}}},
+        ]))
+        .unwrap();
+
+        let (_, items) = engine.bulk("products", &operations).unwrap();
+        let results: Vec<(Option<&str>, BulkResult)> =
+            items.iter().map(|item| (item.id.as_deref(), item.result)).collect();
+        assert_eq!(
+            results,
+            [
+                (Some("1"), BulkResult::Updated),
+                (Some("1"), BulkResult::Updated),
+                (Some("2"), BulkResult::NotFound),
+                (Some("3"), BulkResult::Created),
+                (Some("3"), BulkResult::Updated),
+            ]
+        );
+
+        let ids = ["1".to_string(), "2".to_string(), "3".to_string()];
+        let documents = engine.lookup_documents("products", &ids, &Access::default()).unwrap();
+        assert_eq!(documents.len(), 2);
+        let fields = |id: &str| serde_json::to_value(&documents[id].fields).unwrap();
+        assert_eq!(fields("1"), serde_json::json!({"title": "boot", "color": "red"}));
+        assert_eq!(fields("3"), serde_json::json!({"title": "hat", "color": "green"}));
+    }
+
+    #[test]
+    fn test_bulk_requires_an_id_to_update() {
+        let engine = TestEngine::new();
+        engine.create("products", serde_json::json!([{"name": "title", "stored": true}]));
+        engine.add("products", serde_json::json!([{"id": "1", "fields": {"title": "shoe"}}]));
+        let operations: Vec<BulkOperation> = serde_json::from_value(serde_json::json!([
+            {"operation": "update", "document": {"fields": {"title": "boot"}}},
+            // An empty document id falls back to the operation's
+            {"operation": "delete", "id": "1", "document": {"fields": {}}},
+        ]))
+        .unwrap();
+
+        let (_, items) = engine.bulk("products", &operations).unwrap();
+        assert_eq!(items[0].result, BulkResult::Error);
+        assert_eq!(items[0].error.as_deref(), Some("Missing id for update operation"));
+        assert_eq!((items[1].id.as_deref(), items[1].result), (Some("1"), BulkResult::Deleted));
+        assert_eq!(engine.get_document_count("products").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_on_conflict_replaces_skips_or_rejects() {
+        let engine = TestEngine::new();
+        let ids = ["1".to_string()];
+        let title = |name: &str| {
+            let documents = engine.lookup_documents(name, &ids, &Access::default()).unwrap();
+            documents["1"].fields["title"].clone()
+        };
+        let fields = serde_json::json!([{"name": "title", "stored": true}]);
+        let boot: Vec<Document> =
+            serde_json::from_value(serde_json::json!([{"id": "1", "fields": {"title": "boot"}}]))
+                .unwrap();
+
+        for mode in ["replace", "skip", "error"] {
+            let settings = serde_json::json!({"on_conflict": mode});
+            engine.create_with_settings(mode, fields.clone(), settings);
+            engine.add(mode, serde_json::json!([{"id": "1", "fields": {"title": "shoe"}}]));
         }
 
-        let took_ms = start.elapsed().as_secs_f64() * 1000.0;
+        let outcome = engine.add_documents("replace", &boot).unwrap();
+        assert_eq!(outcome.updated_ids, ["1"]);
+        assert_eq!(title("replace"), "boot");
 
-        let mut result: Vec<_> = suggestions.into_iter().collect();
-        result.sort();
-        result.truncate(limit);
+        let outcome = engine.add_documents("skip", &boot).unwrap();
+        assert_eq!(outcome.conflict_ids, ["1"]);
+        assert!(outcome.indexed_ids.is_empty());
+        assert_eq!(title("skip"), "shoe");
 
-        Ok((result, took_ms))
+        let err = engine.add_documents("error", &boot).unwrap_err();
+        assert!(matches!(err, SearchError::Conflict(_)), "{err:?}");
+        assert_eq!(title("error"), "shoe");
+        for mode in ["replace", "skip", "error"] {
+            assert_eq!(engine.get_document_count(mode).unwrap(), 1, "{mode}");
+        }
     }
 
-    pub fn get_index_stats(&self, index_name: &str, created_at: &str) -> Result<IndexStats> {
-        let indices = self.indices.read().unwrap();
-        let handle = indices
-            .get(index_name)
-            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+    #[test]
+    fn test_commit_stats_match_the_index_opstamp() {
+        let engine = TestEngine::new();
+        engine.create(
+            "products",
+            serde_json::json!([{"name": "title", "stored": true, "indexed": true}]),
+        );
+        let documents = |json: serde_json::Value| -> Vec<Document> {
+            serde_json::from_value(json).unwrap()
+        };
 
-        let reader = handle
-            .index
-            .reader_builder()
-            .reload_policy(ReloadPolicy::OnCommitWithDelay)
-            .try_into()?;
+        let first = engine
+            .add_documents("products", &documents(serde_json::json!([
+                {"id": "1", "fields": {"title": "shoe"}},
+                {"id": "2", "fields": {"title": "boot"}},
+            ])))
+            .unwrap()
+            .commit;
+        assert_eq!(first.document_count, 2);
+        assert!(chrono::DateTime::parse_from_rfc3339(&first.searchable_at).is_ok());
+        assert!(first.took_ms >= 0.0);
+
+        let second = engine
+            .add_documents("products", &documents(serde_json::json!([
+                {"id": "3", "fields": {"title": "hat"}},
+            ])))
+            .unwrap()
+            .commit;
+        assert!(second.opstamp > first.opstamp);
+        assert_eq!(second.document_count, 3);
+        // Once a commit is reported, searches see its writes
+        assert_eq!(engine.search_ids("products", "hat"), ["3"]);
+        let stats = engine.get_index_stats("products", "").unwrap();
+        assert_eq!(stats.opstamp, second.opstamp);
+    }
 
-        let searcher = reader.searcher();
-        let doc_count = searcher.num_docs();
+    #[test]
+    fn test_index_settings_apply_to_searches_and_writes() {
+        let engine = TestEngine::new();
+        engine.create(
+            "notes",
+            serde_json::json!([
+                {"name": "title", "stored": true, "indexed": true},
+                {"name": "internal", "stored": true, "indexed": true},
+            ]),
+        );
+        engine.add("notes", serde_json::json!([
+            {"id": "1", "fields": {"title": "shoe", "internal": "boot"}},
+        ]));
+        assert_eq!(engine.search_ids("notes", "boot"), ["1"]);
+
+        let mut settings = engine.index_settings("notes").unwrap();
+        settings.searchable_fields = vec!["title".to_string()];
+        settings.read_only = true;
+        engine.update_index_settings("notes", settings.clone()).unwrap();
+        assert!(engine.search_ids("notes", "boot").is_empty());
+        assert_eq!(engine.search_ids("notes", "shoe"), ["1"]);
+        // Fields a request names are still searched
+        let hits = engine
+            .search_request("notes", serde_json::json!({"query": "boot", "fields": ["internal"]}))
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+
+        let documents: Vec<Document> =
+            serde_json::from_value(serde_json::json!([{"id": "2", "fields": {"title": "hat"}}]))
+                .unwrap();
+        let err = engine.add_documents("notes", &documents).unwrap_err();
+        assert!(matches!(err, SearchError::ReadOnly(_)), "{err:?}");
+        let err = engine.delete_document("notes", "1").unwrap_err();
+        assert!(matches!(err, SearchError::ReadOnly(_)), "{err:?}");
+        assert_eq!(engine.get_document_count("notes").unwrap(), 1);
+
+        // Settings are loaded again when the index is reopened
+        let settings = SearchEngine::load_index_settings(&engine.path.join("notes"));
+        assert_eq!(settings.searchable_fields, ["title"]);
+        assert!(settings.read_only);
+    }
 
-        // Calculate index size
-        let index_path = Path::new(&self.base_path).join(index_name);
-        let size_bytes = Self::dir_size(&index_path).unwrap_or(0);
+    #[test]
+    fn test_commit_policy_keeps_documents_committed_before_a_failure() {
+        let engine = TestEngine::new();
+        let fields = serde_json::json!([{"name": "title", "stored": true}]);
+        engine.create_with_settings(
+            "batched",
+            fields.clone(),
+            serde_json::json!({"on_conflict": "error", "commit_policy": {"max_documents": 2}}),
+        );
+        engine.create_with_settings("single", fields, serde_json::json!({"on_conflict": "error"}));
+        // The repeated ID fails the request after two documents
+        let documents: Vec<Document> = serde_json::from_value(serde_json::json!([
+            {"id": "1", "fields": {"title": "shoe"}},
+            {"id": "2", "fields": {"title": "boot"}},
+            {"id": "1", "fields": {"title": "hat"}},
+        ]))
+        .unwrap();
+
+        assert!(engine.add_documents("batched", &documents).is_err());
+        assert_eq!(engine.get_document_count("batched").unwrap(), 2);
+        assert!(engine.add_documents("single", &documents).is_err());
+        assert_eq!(engine.get_document_count("single").unwrap(), 0);
+    }
 
-        // Build field stats
-        let fields: Vec<FieldStats> = handle
-            .field_configs
+    #[test]
+    fn test_index_names_are_created_once() {
+        let engine = TestEngine::new();
+        let fields: Vec<FieldConfig> =
+            serde_json::from_value(serde_json::json!([{"name": "title"}])).unwrap();
+        let create = |name: &str| engine.create_index(name, &fields, &IndexSettings::default(), 1);
+
+        // Only one of several concurrent creations of a name succeeds
+        let created = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..4).map(|_| scope.spawn(|| create("docs"))).collect();
+            threads.into_iter().map(|t| t.join().unwrap()).collect::<Vec<_>>()
+        });
+        assert_eq!(created.iter().filter(|result| result.is_ok()).count(), 1);
+        for result in created.into_iter().filter(|result| result.is_err()) {
+            assert!(matches!(result, Err(SearchError::IndexExists(_))), "{result:?}");
+        }
+        assert!(matches!(create("docs"), Err(SearchError::IndexExists(_))));
+
+        // A leftover directory is left alone
+        std::fs::create_dir(engine.path.join("leftover")).unwrap();
+        std::fs::write(engine.path.join("leftover").join("keep"), "data").unwrap();
+        assert!(matches!(create("leftover"), Err(SearchError::IndexExists(_))));
+        assert!(engine.path.join("leftover").join("keep").exists());
+
+        // An index still being created can't be deleted
+        let reservation = engine.reserve_index_name("pending").unwrap();
+        assert!(matches!(engine.delete_index("pending"), Err(SearchError::Conflict(_))));
+        drop(reservation);
+        assert!(create("pending").is_ok());
+    }
+
+    #[test]
+    fn test_closed_indices_reopen_on_use_and_stay_closed_across_restarts() {
+        let engine = TestEngine::new();
+        engine.create(
+            "docs",
+            serde_json::json!([{"name": "title", "stored": true, "indexed": true}]),
+        );
+        engine.add("docs", serde_json::json!([{"id": "1", "fields": {"title": "shoe"}}]));
+        assert!(matches!(engine.close_index("missing"), Err(SearchError::IndexNotFound(_))));
+
+        engine.close_index("docs").unwrap();
+        assert!(engine.is_closed("docs"));
+        assert!(!engine.indices.read().unwrap().contains_key("docs"));
+        // Any use opens the index again
+        assert_eq!(engine.search_ids("docs", "shoe"), ["1"]);
+        assert!(!engine.is_closed("docs"));
+        assert!(!engine.path.join("docs").join(CLOSED_MARKER_FILE).exists());
+
+        engine.close_index("docs").unwrap();
+        let restarted = SearchEngine::new(
+            engine.path.to_str().unwrap(),
+            1,
+            QueryLimits::default(),
+            FilterCache::from_env(),
+        )
+        .unwrap();
+        assert!(restarted.load_indices().unwrap().is_empty());
+        assert!(restarted.is_closed("docs"));
+        restarted.open_index("docs").unwrap();
+        assert!(!restarted.is_closed("docs"));
+        assert_eq!(restarted.get_document_count("docs").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_writers_are_created_on_first_write() {
+        let engine = TestEngine::new();
+        engine.create(
+            "docs",
+            serde_json::json!([{"name": "title", "stored": true, "indexed": true}]),
+        );
+        let has_writer =
+            || engine.indices.read().unwrap()["docs"].shards[0].writer.read().unwrap().is_some();
+
+        assert!(!has_writer());
+        assert!(engine.search_ids("docs", "shoe").is_empty());
+        assert!(!has_writer());
+        engine.add("docs", serde_json::json!([{"id": "1", "fields": {"title": "shoe"}}]));
+        assert!(has_writer());
+
+        // Loading the index elsewhere doesn't need the writer's lock
+        let loaded = SearchEngine::new(
+            engine.path.to_str().unwrap(),
+            1,
+            QueryLimits::default(),
+            FilterCache::from_env(),
+        )
+        .unwrap();
+        assert_eq!(loaded.load_indices().unwrap(), ["docs"]);
+        assert_eq!(loaded.get_document_count("docs").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_index_stats_report_segments_deletes_and_terms() {
+        let engine = TestEngine::new();
+        engine.create(
+            "docs",
+            serde_json::json!([
+                {"name": "title", "stored": true, "indexed": true},
+                {"name": "notes", "stored": true, "indexed": false},
+            ]),
+        );
+        engine.add("docs", serde_json::json!([
+            {"id": "1", "fields": {"title": "red shoe", "notes": "a"}},
+            {"id": "2", "fields": {"title": "blue shoe", "notes": "b"}},
+        ]));
+        engine.add("docs", serde_json::json!([{"id": "3", "fields": {"title": "red hat"}}]));
+        engine.delete_document("docs", "2").unwrap();
+
+        let stats = engine.get_index_stats("docs", "").unwrap();
+        assert_eq!(stats.document_count, 2);
+        assert_eq!(stats.segment_count, 2);
+        assert_eq!(stats.deleted_document_count, 1);
+        let term_count = |name: &str| {
+            stats.fields.iter().find(|field| field.name == name).unwrap().term_count
+        };
+        // red, blue, shoe in the first segment; red, hat in the second
+        assert_eq!(term_count("title"), Some(5));
+        assert_eq!(term_count("notes"), None);
+        assert!(stats.storage.store > 0);
+        assert!(stats.storage.postings > 0);
+        assert!(stats.storage.deletes > 0);
+    }
+
+    #[test]
+    fn test_field_capabilities_describe_each_field() {
+        let engine = TestEngine::new();
+        engine.create(
+            "docs",
+            serde_json::json!([
+                {"name": "title", "stored": true, "indexed": true},
+                {"name": "body", "indexed": true, "analyzer": "auto", "languages": ["en", "no"]},
+                {"name": "sku", "field_type": "string", "indexed": true, "stored": true},
+                {"name": "price", "field_type": "f64", "indexed": true, "fast": true},
+                {"name": "notes", "stored": true},
+            ]),
+        );
+
+        let capabilities = engine.field_capabilities("docs").unwrap();
+        let field = |name: &str| capabilities.iter().find(|field| field.name == name).unwrap();
+        let title = field("title");
+        assert_eq!(title.analyzer.as_deref(), Some("default"));
+        assert!(title.default_search && title.highlightable && title.aggregatable);
+        assert!(!title.sortable);
+        let body = field("body");
+        assert_eq!(body.analyzer.as_deref(), Some(language::AUTO_ANALYZER));
+        assert_eq!(body.languages, ["en", "no"]);
+        assert!(!body.highlightable);
+        assert_eq!(field("sku").analyzer.as_deref(), Some("raw"));
+        let price = field("price");
+        assert!(price.sortable && price.aggregatable && price.fast);
+        assert!(!price.default_search && price.analyzer.is_none());
+        let notes = field("notes");
+        assert!(notes.stored && !notes.indexed && !notes.default_search);
+
+        assert!(matches!(
+            engine.field_capabilities("missing"),
+            Err(SearchError::IndexNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_analyze_runs_named_and_field_analyzers() {
+        let engine = TestEngine::new();
+        engine.create(
+            "docs",
+            serde_json::json!([
+                {"name": "sku", "field_type": "string", "indexed": true},
+                {"name": "price", "field_type": "f64", "indexed": true},
+            ]),
+        );
+        let tokens = |analyzer: Option<&str>, field: Option<&str>| {
+            let (name, tokens) = engine.analyze("docs", "Red Shoes", analyzer, field).unwrap();
+            let tokens: Vec<(String, usize, usize, usize)> = tokens
+                .into_iter()
+                .map(|t| (t.token, t.position, t.start_offset, t.end_offset))
+                .collect();
+            (name, tokens)
+        };
+
+        let (name, default) = tokens(None, None);
+        assert_eq!(name, "default");
+        assert_eq!(default, [("red".into(), 0, 0, 3), ("shoes".into(), 1, 4, 9)]);
+        assert_eq!(tokens(Some("raw"), None).1, [("Red Shoes".into(), 0, 0, 9)]);
+        assert_eq!(tokens(None, Some("sku")), tokens(Some("raw"), None));
+        let (_, english) = tokens(Some("en"), None);
+        assert_eq!(english[1].0, "shoe");
+
+        for (analyzer, field) in [
+            (Some("raw"), Some("sku")),
+            (Some("klingon"), None),
+            (None, Some("price")),
+            (None, Some("missing")),
+        ] {
+            let result = engine.analyze("docs", "Red Shoes", analyzer, field);
+            assert!(matches!(result, Err(SearchError::InvalidQuery(_))), "{analyzer:?} {field:?}");
+        }
+    }
+
+    #[test]
+    fn test_term_vectors_of_stored_fields() {
+        let engine = TestEngine::new();
+        engine.create(
+            "docs",
+            serde_json::json!([
+                {"name": "title", "stored": true, "indexed": true},
+                {"name": "sku", "field_type": "string", "stored": true, "indexed": true},
+                {"name": "body", "indexed": true},
+            ]),
+        );
+        engine.add("docs", serde_json::json!([
+            {"id": "1", "fields": {"title": "Red shoe, red laces", "sku": "A-1", "body": "x"}},
+            {"id": "2", "fields": {"title": "red hat"}},
+        ]));
+
+        let vectors = engine.term_vectors("docs", "1", &[]).unwrap().unwrap();
+        assert_eq!(vectors.document_count, 2);
+        // Fields that aren't stored can't be analyzed again
+        let fields: Vec<&str> = vectors.fields.iter().map(|f| f.field.as_str()).collect();
+        assert_eq!(fields, ["title", "sku"]);
+        let terms: Vec<(&str, usize, u64, &[usize])> = vectors.fields[0]
+            .terms
             .iter()
-            .map(|fc| FieldStats {
-                name: fc.name.clone(),
-                field_type: fc.field_type.clone(),
-                indexed: fc.indexed,
-                stored: fc.stored,
-            })
+            .map(|t| (t.term.as_str(), t.term_freq, t.doc_freq, t.positions.as_slice()))
             .collect();
+        assert_eq!(
+            terms,
+            [("laces", 1, 1, &[3][..]), ("red", 2, 2, &[0, 2][..]), ("shoe", 1, 1, &[1][..])]
+        );
+
+        let only_sku = engine.term_vectors("docs", "1", &["sku".to_string()]).unwrap().unwrap();
+        assert_eq!(only_sku.fields.len(), 1);
+        assert_eq!(only_sku.fields[0].terms[0].term, "A-1");
+        assert!(engine.term_vectors("docs", "3", &[]).unwrap().is_none());
+        let unknown = engine.term_vectors("docs", "1", &["missing".to_string()]);
+        assert!(matches!(unknown, Err(SearchError::InvalidQuery(_))));
+    }
 
-        Ok(IndexStats {
-            name: index_name.to_string(),
-            document_count: doc_count,
-            size_bytes,
-            fields,
-            created_at: created_at.to_string(),
-        })
+    #[test]
+    fn test_list_terms_merges_segments_and_pages() {
+        let engine = TestEngine::new();
+        engine.create(
+            "docs",
+            serde_json::json!([
+                {"name": "title", "indexed": true},
+                {"name": "price", "field_type": "f64", "indexed": true},
+            ]),
+        );
+        engine.add("docs", serde_json::json!([
+            {"id": "1", "fields": {"title": "red shoe"}},
+            {"id": "2", "fields": {"title": "red shirt"}},
+        ]));
+        // A second segment holding some of the same terms
+        engine.add("docs", serde_json::json!([{"id": "3", "fields": {"title": "shoe shop"}}]));
+        let terms = |prefix: &str, after: Option<&str>, limit: usize| -> Vec<(String, u64)> {
+            engine
+                .list_terms("docs", "title", prefix, after, limit)
+                .unwrap()
+                .into_iter()
+                .map(|t| (t.term, t.doc_freq))
+                .collect()
+        };
+        let pairs = |expected: &[(&str, u64)]| -> Vec<(String, u64)> {
+            expected.iter().map(|(term, n)| (term.to_string(), *n)).collect()
+        };
+
+        assert_eq!(
+            terms("", None, 10),
+            pairs(&[("red", 2), ("shirt", 1), ("shoe", 2), ("shop", 1)])
+        );
+        assert_eq!(terms("sh", None, 2), pairs(&[("shirt", 1), ("shoe", 2)]));
+        assert_eq!(terms("sh", Some("shoe"), 2), pairs(&[("shop", 1)]));
+        // An `after` before the prefix starts at the prefix
+        assert_eq!(terms("sh", Some("a"), 1), pairs(&[("shirt", 1)]));
+
+        for field in ["price", "missing"] {
+            let result = engine.list_terms("docs", field, "", None, 10);
+            assert!(matches!(result, Err(SearchError::InvalidQuery(_))), "{field}");
+        }
     }
 
-    fn dir_size(path: &Path) -> std::io::Result<u64> {
-        let mut size = 0;
-        if path.is_dir() {
-            for entry in std::fs::read_dir(path)? {
-                let entry = entry?;
-                let metadata = entry.metadata()?;
-                if metadata.is_file() {
-                    size += metadata.len();
-                } else if metadata.is_dir() {
-                    size += Self::dir_size(&entry.path())?;
+    #[test]
+    fn test_index_search_limits() {
+        let engine = TestEngine::new();
+        engine.create_with_settings(
+            "products",
+            serde_json::json!([
+                {"name": "title", "stored": true, "indexed": true},
+                {"name": "body", "stored": true, "indexed": true},
+                {"name": "price", "field_type": "f64", "indexed": true, "fast": true},
+            ]),
+            serde_json::json!({
+                "max_result_window": 20,
+                "max_aggregation_buckets": 3,
+                "max_highlight_fields": 1,
+            }),
+        );
+        engine.add("products", serde_json::json!([
+            {"id": "1", "fields": {"title": "shoe", "price": 10.0}},
+            {"id": "2", "fields": {"title": "shoe", "price": 30.0}},
+        ]));
+        // Path named by the error of an invalid request
+        let path_of = |request: serde_json::Value| {
+            match engine.search_request("products", request) {
+                Err(SearchError::InvalidQuery(message)) => {
+                    Some(message.split(':').next().unwrap().to_string())
                 }
+                Err(e) => panic!("{e:?}"),
+                Ok(_) => None,
             }
-        }
-        Ok(size)
-    }
+        };
+        let ranges = |n: usize| -> Vec<serde_json::Value> {
+            (0..n).map(|i| serde_json::json!({"from": i as f64 * 10.0})).collect()
+        };
 
-    pub fn delete_document(&self, index_name: &str, doc_id: &str) -> Result<()> {
-        let indices = self.indices.read().unwrap();
-        let handle = indices
-            .get(index_name)
-            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        assert_eq!(path_of(serde_json::json!({"query": "shoe", "offset": 10, "limit": 10})), None);
+        assert_eq!(
+            path_of(serde_json::json!({"query": "shoe", "offset": 11, "limit": 10})).as_deref(),
+            Some("limit")
+        );
+        let highlight = serde_json::json!({"query": "shoe", "highlight": {"fields": ["title"]}});
+        assert_eq!(path_of(highlight), None);
+        // Without a field list, every searched field would be highlighted
+        let highlight = serde_json::json!({"query": "shoe", "highlight": {}});
+        assert_eq!(path_of(highlight).as_deref(), Some("highlight.fields"));
+        let aggregation = |agg: serde_json::Value| {
+            path_of(serde_json::json!({"query": "shoe", "aggregations": [agg]}))
+        };
+        let fits = serde_json::json!(
+            {"name": "prices", "agg_type": "range", "field": "price", "ranges": ranges(3)}
+        );
+        assert_eq!(aggregation(fits), None);
+        let too_many = serde_json::json!(
+            {"name": "prices", "agg_type": "range", "field": "price", "ranges": ranges(4)}
+        );
+        assert_eq!(aggregation(too_many).as_deref(), Some("aggregations[0]"));
+        // Buckets a histogram only creates while collecting are limited as well
+        let histogram = serde_json::json!(
+            {"name": "prices", "agg_type": "histogram", "field": "price", "interval": 1.0}
+        );
+        let result = engine.search_request(
+            "products",
+            serde_json::json!({"query": "shoe", "aggregations": [histogram]}),
+        );
+        assert!(matches!(result, Err(SearchError::MemoryLimit(..))), "{result:?}");
+    }
 
-        let mut writer = handle.writer.write().unwrap();
-        let id_field = handle.field_map.get("id").unwrap();
+    #[test]
+    fn test_transactions_apply_all_operations_or_none() {
+        let engine = TestEngine::new();
+        let fields: Vec<FieldConfig> =
+            serde_json::from_value(serde_json::json!([{"name": "title", "stored": true}]))
+                .unwrap();
+        // Early commits of the commit policy are ignored, and shards commit together
+        let settings: IndexSettings =
+            serde_json::from_value(serde_json::json!({"commit_policy": {"max_documents": 1}}))
+                .unwrap();
+        engine.create_index("docs", &fields, &settings, 2).unwrap();
+        engine.add("docs", serde_json::json!([{"id": "1", "fields": {"title": "shoe"}}]));
+        let operations = |json: serde_json::Value| -> Vec<BulkOperation> {
+            serde_json::from_value(json).unwrap()
+        };
+        let count = || engine.get_document_count("docs").unwrap();
+
+        let missing_update = operations(serde_json::json!([
+            {"operation": "index", "document": {"id": "2", "fields": {"title": "boot"}}},
+            {"operation": "index", "document": {"id": "3", "fields": {"title": "hat"}}},
+            {"operation": "delete", "id": "1"},
+            {"operation": "update", "document": {"id": "9", "fields": {"title": "sock"}}},
+        ]));
+        let err = engine.transaction("docs", &missing_update, |_| Ok(())).unwrap_err();
+        assert!(matches!(err, SearchError::Conflict(_)), "{err:?}");
+        assert_eq!(count(), 1);
+
+        let unknown = operations(serde_json::json!([
+            {"operation": "index", "document": {"id": "2", "fields": {"title": "boot"}}},
+            {"operation": "replace", "id": "1"},
+        ]));
+        let err = engine.transaction("docs", &unknown, |_| Ok(())).unwrap_err();
+        assert!(matches!(err, SearchError::InvalidQuery(_)), "{err:?}");
+        assert_eq!(count(), 1);
+
+        let valid = operations(serde_json::json!([
+            {"operation": "index", "document": {"id": "2", "fields": {"title": "boot"}}},
+            {"operation": "index", "document": {"id": "3", "fields": {"title": "hat"}}},
+            {"operation": "delete", "id": "1"},
+        ]));
+        // A failure recording the outcome rolls the operations back too
+        let err = engine
+            .transaction("docs", &valid, |_| Err(SearchError::Internal(anyhow!("metadata"))))
+            .unwrap_err();
+        assert!(matches!(err, SearchError::Internal(_)), "{err:?}");
+        assert_eq!(count(), 1);
+
+        let mut recorded = Vec::new();
+        let (outcome, items) = engine
+            .transaction("docs", &valid, |outcome| {
+                recorded = outcome.indexed_ids.clone();
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(recorded, ["2", "3"]);
+        assert_eq!(outcome.commit.document_count, 2);
+        assert_eq!(count(), 2);
+    }
 
-        writer.delete_term(Term::from_field_text(*id_field, doc_id));
-        writer.commit()?;
+    #[test]
+    fn test_has_child_and_has_parent_follow_the_parent_field() {
+        let engine = TestEngine::new();
+        let fields = serde_json::json!([
+            {"name": "title", "stored": true, "indexed": true},
+            {"name": "parent", "field_type": "string", "stored": true, "indexed": true},
+        ]);
+        engine.create_with_settings("shop", fields.clone(), serde_json::json!({
+            "parent_field": "parent",
+        }));
+        engine.add("shop", serde_json::json!([
+            {"id": "p1", "fields": {"title": "red shoe"}},
+            {"id": "p2", "fields": {"title": "blue shoe"}},
+            {"id": "c1", "fields": {"title": "great comfort", "parent": "p1"}},
+            {"id": "c2", "fields": {"title": "poor fit", "parent": "p2"}},
+            {"id": "c3", "fields": {"title": "great colour", "parent": "p2"}},
+        ]));
+        let ids = |hits: &[SearchHit]| {
+            let mut ids: Vec<String> = hits.iter().map(|hit| hit.id.clone()).collect();
+            ids.sort();
+            ids
+        };
 
-        Ok(())
+        let hits = engine
+            .search_request("shop", serde_json::json!({
+                "query": "shoe",
+                "has_child": {"query": "comfort", "inner_hits": 2},
+            }))
+            .unwrap();
+        assert_eq!(ids(&hits), ["p1"]);
+        assert_eq!(ids(&hits[0].children), ["c1"]);
+        let hits = engine
+            .search_request("shop", serde_json::json!({
+                "query": "shoe",
+                "has_child": {"query": "great"},
+            }))
+            .unwrap();
+        assert_eq!(ids(&hits), ["p1", "p2"]);
+        assert!(hits.iter().all(|hit| hit.children.is_empty()));
+        let hits = engine
+            .search_request("shop", serde_json::json!({
+                "query": "great",
+                "has_parent": {"query": "blue"},
+            }))
+            .unwrap();
+        assert_eq!(ids(&hits), ["c3"]);
+
+        // Without a parent field, documents aren't linked
+        engine.create("flat", fields);
+        let result = engine.search_request("flat", serde_json::json!({
+            "query": "shoe",
+            "has_child": {"query": "great"},
+        }));
+        assert!(matches!(result, Err(SearchError::InvalidQuery(m)) if m.starts_with("has_child")));
     }
 
-    pub fn delete_index(&self, index_name: &str) -> Result<()> {
-        let mut indices = self.indices.write().unwrap();
-        indices.remove(index_name);
+    #[test]
+    fn test_search_presets_are_checked_replaced_and_kept_across_restarts() {
+        let engine = TestEngine::new();
+        engine.create(
+            "docs",
+            serde_json::json!([
+                {"name": "title", "stored": true, "indexed": true},
+                {"name": "body", "stored": true, "indexed": true},
+            ]),
+        );
+        engine.add(
+            "docs",
+            serde_json::json!([
+                {"id": "1", "fields": {"title": "shoe", "body": "lace"}},
+                {"id": "2", "fields": {"title": "lace", "body": "shoe"}},
+            ]),
+        );
+        let preset = |preset: serde_json::Value| -> SearchPreset {
+            serde_json::from_value(preset).unwrap()
+        };
+        let invalid = |preset: SearchPreset| match engine.validate_search_preset("docs", &preset) {
+            Err(SearchError::InvalidQuery(message)) => message,
+            other => panic!("expected an invalid preset, got {:?}", other),
+        };
+        assert_eq!(
+            invalid(preset(serde_json::json!({"name": "a", "fields": ["title", "tilte"]}))),
+            "fields[1]: unknown field 'tilte'"
+        );
+        assert_eq!(
+            invalid(preset(serde_json::json!({"name": "a", "boost": {"price": 2.0}}))),
+            "boost.price: unknown field 'price'"
+        );
+        assert_eq!(
+            invalid(preset(serde_json::json!({"name": "a", "highlight": {"fields": ["x"]}}))),
+            "highlight.fields[0]: unknown field 'x'"
+        );
+        assert_eq!(
+            invalid(preset(serde_json::json!({"name": " "}))),
+            "Preset name cannot be empty"
+        );
+        let missing = preset(serde_json::json!({"name": "a"}));
+        assert!(matches!(
+            engine.validate_search_preset("missing", &missing),
+            Err(SearchError::IndexNotFound(_))
+        ));
+
+        let titles = preset(serde_json::json!({"name": "titles", "fields": ["title"]}));
+        engine.validate_search_preset("docs", &titles).unwrap();
+        engine
+            .add_search_presets("docs", vec![titles, preset(serde_json::json!({"name": "all"}))])
+            .unwrap();
+        let mut request: SearchRequest =
+            serde_json::from_value(serde_json::json!({"query": "shoe"})).unwrap();
+        engine.get_search_preset("docs", "titles").unwrap().apply(&mut request);
+        let ids: Vec<String> =
+            engine.run_request("docs", &request).unwrap().into_iter().map(|h| h.id).collect();
+        assert_eq!(ids, ["1"]);
+
+        // A preset with the same name replaces the earlier one
+        let bodies = preset(serde_json::json!({"name": "titles", "fields": ["body"]}));
+        engine.add_search_presets("docs", vec![bodies]).unwrap();
+        let names = |presets: Vec<SearchPreset>| -> Vec<String> {
+            presets.into_iter().map(|preset| preset.name).collect()
+        };
+        assert_eq!(names(engine.get_search_presets("docs")), ["all", "titles"]);
+        assert_eq!(engine.get_search_preset("docs", "titles").unwrap().fields, ["body"]);
+        assert!(engine.get_search_preset("other", "titles").is_none());
+
+        assert!(engine.delete_search_preset("docs", "all").unwrap());
+        assert!(!engine.delete_search_preset("docs", "all").unwrap());
+        assert!(!engine.delete_search_preset("other", "all").unwrap());
+
+        let restarted = SearchEngine::new(
+            engine.path.to_str().unwrap(),
+            1,
+            QueryLimits::default(),
+            FilterCache::from_env(),
+        )
+        .unwrap();
+        assert_eq!(names(restarted.get_search_presets("docs")), ["titles"]);
+        assert_eq!(restarted.get_search_preset("docs", "titles").unwrap().fields, ["body"]);
+    }
 
-        let index_path = Path::new(&self.base_path).join(index_name);
-        if index_path.exists() {
-            std::fs::remove_dir_all(&index_path)?;
+    #[test]
+    fn test_wildcard_terms_need_a_literal_prefix() {
+        let engine = TestEngine::new();
+        let fields = serde_json::json!([
+            {"name": "title", "stored": true, "indexed": true},
+            {"name": "parent", "field_type": "string", "stored": true, "indexed": true},
+        ]);
+        engine.create_with_settings(
+            "shop",
+            fields.clone(),
+            serde_json::json!({"parent_field": "parent"}),
+        );
+        engine.create_with_settings("open", fields.clone(), serde_json::json!({
+            "min_wildcard_prefix": 0,
+        }));
+        engine.create_with_settings("strict", fields, serde_json::json!({
+            "min_wildcard_prefix": 3,
+        }));
+        let docs = serde_json::json!([
+            {"id": "1", "fields": {"title": "shoes"}},
+            {"id": "2", "fields": {"title": "boots", "parent": "1"}},
+        ]);
+        for name in ["shop", "open", "strict"] {
+            engine.add(name, docs.clone());
         }
+        let search = |name: &str, query: &str| engine.search(name, query, 10, 0, &[], None, &[]);
+        let refused = |name: &str, query: &str| {
+            matches!(search(name, query), Err(SearchError::InvalidQuery(message))
+                if message.contains("before its first wildcard"))
+        };
 
-        Ok(())
+        assert!(refused("shop", "*oes"));
+        // The field name doesn't count towards the prefix
+        assert!(refused("shop", "title:*oes"));
+        assert!(refused("shop", "red (?hoes)"));
+        assert_eq!(engine.search_ids("shop", "sh*"), ["1"]);
+        // Matching everything and escaped wildcards are allowed
+        assert_eq!(engine.search_ids("shop", "*").len(), 2);
+        assert!(search("shop", "\\*oes").is_ok());
+        // Join queries are checked too
+        let join = serde_json::json!({"query": "shoes", "has_child": {"query": "*oots"}});
+        assert!(matches!(
+            engine.search_request("shop", join),
+            Err(SearchError::InvalidQuery(message)) if message.contains("'*oots'")
+        ));
+
+        // An index can allow leading wildcards or ask for a longer prefix
+        assert_eq!(engine.search_ids("open", "*oes"), ["1"]);
+        assert!(refused("strict", "sh*"));
+        assert_eq!(engine.search_ids("strict", "sho*"), ["1"]);
     }
 
-    #[allow(dead_code)]
-    pub fn list_indices(&self) -> Vec<String> {
-        self.indices.read().unwrap().keys().cloned().collect()
+    #[test]
+    fn test_dedup_skips_or_overwrites_documents_with_the_same_content() {
+        let engine = TestEngine::new();
+        let fields = serde_json::json!([
+            {"name": "title", "stored": true, "indexed": true},
+            {"name": "color", "field_type": "string", "stored": true, "indexed": true},
+        ]);
+        engine.create_with_settings("skip", fields.clone(), serde_json::json!({"dedup": "skip"}));
+        engine.create_with_settings(
+            "overwrite",
+            fields.clone(),
+            serde_json::json!({"dedup": "overwrite"}),
+        );
+        engine.create("plain", fields);
+        let add = |name: &str, docs: serde_json::Value| {
+            let docs: Vec<Document> = serde_json::from_value(docs).unwrap();
+            engine.add_documents(name, &docs).unwrap()
+        };
+        let ids = |name: &str| {
+            let mut ids = engine.search_ids(name, "*");
+            ids.sort();
+            ids
+        };
+        let shoe = |id: &str| serde_json::json!({"id": id, "fields": {"title": "shoe"}});
+
+        add("skip", serde_json::json!([shoe("1")]));
+        // The same content under another ID is left out, whatever the order of its fields
+        let outcome = add("skip", serde_json::json!([
+            {"id": "2", "fields": {"color": "red", "title": "boot"}},
+            shoe("3"),
+            {"id": "4", "fields": {"title": "boot", "color": "red"}},
+        ]));
+        assert_eq!(outcome.indexed_ids, ["2"]);
+        assert_eq!(outcome.skipped_ids, ["3", "4"]);
+        assert!(outcome.replaced_ids.is_empty());
+        assert_eq!(ids("skip"), ["1", "2"]);
+        // Rewriting a document under its own ID isn't a duplicate
+        let outcome = add("skip", serde_json::json!([shoe("1")]));
+        assert_eq!(outcome.indexed_ids, ["1"]);
+        assert!(outcome.skipped_ids.is_empty());
+
+        add("overwrite", serde_json::json!([shoe("1")]));
+        let outcome = add("overwrite", serde_json::json!([shoe("2")]));
+        assert_eq!(outcome.indexed_ids, ["2"]);
+        assert_eq!(outcome.replaced_ids, ["1"]);
+        assert_eq!(ids("overwrite"), ["2"]);
+        // Within a batch the last of the duplicates is kept, and the others counted
+        let outcome = add("overwrite", serde_json::json!([
+            {"id": "3", "fields": {"title": "boot"}},
+            {"id": "4", "fields": {"title": "boot"}},
+        ]));
+        assert_eq!(outcome.indexed_ids, ["4"]);
+        assert_eq!(outcome.replaced_ids, ["3"]);
+        assert_eq!(ids("overwrite"), ["2", "4"]);
+
+        add("plain", serde_json::json!([shoe("1"), shoe("2")]));
+        assert_eq!(ids("plain"), ["1", "2"]);
     }
 
-    #[allow(dead_code)]
-    pub fn get_document_count(&self, index_name: &str) -> Result<u64> {
-        let indices = self.indices.read().unwrap();
-        let handle = indices
-            .get(index_name)
-            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+    #[test]
+    fn test_content_hash_ignores_field_order_and_the_skipped_field() {
+        let doc = |fields: &[(&str, serde_json::Value)]| Document {
+            id: "1".to_string(),
+            fields: fields.iter().map(|(name, value)| (name.to_string(), value.clone())).collect(),
+        };
+        let hash = |doc: &Document, skip: Option<&str>| {
+            SearchEngine::content_hash(doc, skip).unwrap()
+        };
+        let a = doc(&[("title", serde_json::json!("shoe")), ("price", serde_json::json!(10))]);
+        let b = doc(&[("price", serde_json::json!(10)), ("title", serde_json::json!("shoe"))]);
+        assert_eq!(hash(&a, None), hash(&b, None));
+        assert_eq!(hash(&a, None).len(), 64);
+
+        let summarized = doc(&[
+            ("title", serde_json::json!("shoe")),
+            ("price", serde_json::json!(10)),
+            ("summary", serde_json::json!("A shoe")),
+        ]);
+        assert_ne!(hash(&a, None), hash(&summarized, None));
+        assert_eq!(hash(&a, Some("summary")), hash(&summarized, Some("summary")));
+        let cheaper = doc(&[("title", serde_json::json!("shoe")), ("price", serde_json::json!(9))]);
+        assert_ne!(hash(&a, None), hash(&cheaper, None));
+    }
+
+    #[test]
+    fn test_polygon_filter_skips_points_outside_a_concave_polygon() {
+        let engine = TestEngine::new();
+        engine.create(
+            "shops",
+            serde_json::json!([
+                {"name": "title", "stored": true, "indexed": true},
+                {"name": "location", "field_type": "geo_point", "stored": true},
+            ]),
+        );
+        let shop = |id: &str, lat: f64, lon: f64| {
+            let location = serde_json::json!({"lat": lat, "lon": lon});
+            serde_json::json!({"id": id, "fields": {"title": "shop", "location": location}})
+        };
+        // Two batches, so the points are read from more than one segment
+        engine.add(
+            "shops",
+            serde_json::json!([shop("left", 5.0, 2.0), shop("notch", 7.0, 5.0)]),
+        );
+        engine.add(
+            "shops",
+            serde_json::json!([
+                shop("right", 5.0, 8.0),
+                shop("bottom", 1.0, 5.0),
+                shop("north", 20.0, 5.0),
+                {"id": "nowhere", "fields": {"title": "shop"}},
+            ]),
+        );
+        // A U shape: the notch between its arms is inside the bounding box only
+        let points: Vec<serde_json::Value> = [
+            (0.0, 0.0), (10.0, 0.0), (10.0, 4.0), (3.0, 4.0),
+            (3.0, 6.0), (10.0, 6.0), (10.0, 10.0), (0.0, 10.0),
+        ]
+        .iter()
+        .map(|(lat, lon)| serde_json::json!({"lat": lat, "lon": lon}))
+        .collect();
+        let polygon = serde_json::json!({
+            "_name": "u",
+            "field": "location",
+            "polygon": {"points": points},
+        });
+        let mut hits = engine
+            .search_request("shops", serde_json::json!({"query": "shop", "geo_filters": [polygon]}))
+            .unwrap();
+        hits.sort_by(|a, b| a.id.cmp(&b.id));
+        let ids: Vec<&str> = hits.iter().map(|hit| hit.id.as_str()).collect();
+        assert_eq!(ids, ["bottom", "left", "right"]);
+        assert!(hits.iter().all(|hit| hit.matched_queries == ["u"]));
+    }
+
+    #[test]
+    fn test_stored_filters_narrow_searches_by_name_and_are_kept_across_restarts() {
+        let engine = TestEngine::new();
+        engine.create(
+            "docs",
+            serde_json::json!([
+                {"name": "title", "stored": true, "indexed": true},
+                {"name": "status", "field_type": "string", "stored": true, "indexed": true},
+                {"name": "location", "field_type": "geo_point", "stored": true},
+            ]),
+        );
+        let doc = |id: &str, title: &str, status: &str, lat: f64| {
+            let location = serde_json::json!({"lat": lat, "lon": 10.0});
+            serde_json::json!(
+                {"id": id, "fields": {"title": title, "status": status, "location": location}}
+            )
+        };
+        engine.add(
+            "docs",
+            serde_json::json!([
+                doc("1", "red shoe", "published", 60.0),
+                doc("2", "red boot", "published", 70.0),
+                doc("3", "red shoe", "draft", 60.0),
+            ]),
+        );
+        let filter = |filter: serde_json::Value| -> StoredFilter {
+            serde_json::from_value(filter).unwrap()
+        };
+        let invalid = |filter: StoredFilter| match engine.validate_stored_filter("docs", &filter) {
+            Err(SearchError::InvalidQuery(message)) => message,
+            other => panic!("expected an invalid filter, got {:?}", other),
+        };
+        assert_eq!(
+            invalid(filter(serde_json::json!({"name": " ", "query": "status:draft"}))),
+            "Filter name cannot be empty"
+        );
+        assert!(invalid(filter(serde_json::json!({"name": "a", "query": "colour:red"})))
+            .contains("colour"));
+        let not_geo = serde_json::json!({
+            "field": "title",
+            "bounding_box": {
+                "top_left": {"lat": 61.0, "lon": 9.0},
+                "bottom_right": {"lat": 59.0, "lon": 11.0},
+            },
+        });
+        assert!(invalid(filter(serde_json::json!(
+            {"name": "a", "query": "*", "geo_filters": [not_geo]}
+        )))
+        .contains("not a geo_point field"));
+
+        let published =
+            filter(serde_json::json!({"name": "published", "query": "status:published"}));
+        let mut south = not_geo.clone();
+        south["field"] = serde_json::json!("location");
+        let south =
+            filter(serde_json::json!({"name": "south", "query": "*", "geo_filters": [south]}));
+        engine.validate_stored_filter("docs", &published).unwrap();
+        engine.validate_stored_filter("docs", &south).unwrap();
+        engine.add_stored_filters("docs", vec![published, south]).unwrap();
+
+        let ids = |engine: &SearchEngine, request: serde_json::Value| {
+            let request: SearchRequest = serde_json::from_value(request).unwrap();
+            engine.validate_search_request("docs", &request)?;
+            let (hits, ..) = engine.search_with_options(
+                "docs",
+                &request.query,
+                10,
+                0,
+                &[],
+                None,
+                &[],
+                false,
+                None,
+                None,
+                None,
+                None,
+                &[],
+                &request.filters,
+                None,
+                None,
+                None,
+                None,
+                &Access::default(),
+            )?;
+            let mut ids: Vec<String> = hits.into_iter().map(|hit| hit.id).collect();
+            ids.sort();
+            Ok::<_, SearchError>(ids)
+        };
+        // Stored filters are ANDed with the user's query and with each other
+        let search = |query: &str, filters: &[&str]| {
+            ids(&engine, serde_json::json!({"query": query, "filters": filters}))
+        };
+        assert_eq!(search("red", &["published"]).unwrap(), ["1", "2"]);
+        assert_eq!(search("shoe", &["published"]).unwrap(), ["1"]);
+        assert_eq!(search("red", &["published", "south"]).unwrap(), ["1"]);
+        let unknown = search("red", &["published", "drafts"]).unwrap_err();
+        assert_eq!(unknown.status_code(), axum::http::StatusCode::BAD_REQUEST);
+        assert!(unknown.to_string().contains("filters[1]"), "{unknown}");
+
+        // A filter with the same name replaces the earlier one
+        let drafts = filter(serde_json::json!({"name": "published", "query": "status:draft"}));
+        engine.add_stored_filters("docs", vec![drafts]).unwrap();
+        assert_eq!(search("red", &["published"]).unwrap(), ["3"]);
+        assert!(engine.delete_stored_filter("docs", "south").unwrap());
+        assert!(!engine.delete_stored_filter("docs", "south").unwrap());
+        assert!(!engine.delete_stored_filter("other", "published").unwrap());
+        assert!(search("red", &["south"]).is_err());
+
+        let restarted = SearchEngine::new(
+            engine.path.to_str().unwrap(),
+            1,
+            QueryLimits::default(),
+            FilterCache::from_env(),
+        )
+        .unwrap();
+        restarted.load_indices().unwrap();
+        let filters = restarted.get_stored_filters("docs");
+        assert_eq!(filters.len(), 1);
+        assert_eq!(filters[0].query, "status:draft");
+        let request = serde_json::json!({"query": "red", "filters": ["published"]});
+        assert_eq!(ids(&restarted, request).unwrap(), ["3"]);
+    }
+
+    #[test]
+    fn test_percentile_ranks_and_weighted_avg() {
+        let engine = TestEngine::new();
+        engine.create(
+            "orders",
+            serde_json::json!([
+                {"name": "title", "stored": true, "indexed": true},
+                {"name": "latency", "field_type": "f64", "stored": true, "fast": true},
+                {"name": "items", "field_type": "i64", "stored": true, "fast": true},
+            ]),
+        );
+        // Two batches, so values are read from more than one segment
+        engine.add("orders", serde_json::json!([
+            {"id": "1", "fields": {"title": "order", "latency": 100.0, "items": 1}},
+            {"id": "2", "fields": {"title": "order", "latency": 200.0, "items": 3}},
+        ]));
+        engine.add("orders", serde_json::json!([
+            {"id": "3", "fields": {"title": "order", "latency": 300.0}},
+            {"id": "4", "fields": {"title": "order", "latency": 400.0, "items": 0}},
+            {"id": "5", "fields": {"title": "refund", "latency": 50.0, "items": 0}},
+        ]));
+        let aggregations = serde_json::json!([
+            {
+                "name": "ranks",
+                "agg_type": "percentile_ranks",
+                "field": "latency",
+                "values": [50.0, 200.0, 250.0, 1000.0],
+            },
+            {
+                "name": "avg",
+                "agg_type": "weighted_avg",
+                "field": "latency",
+                "weight_field": "items",
+            },
+        ]);
+
+        let (_, results, errors) = engine.aggregate("orders", "order", aggregations.clone());
+        assert!(errors.is_empty(), "{errors:?}");
+        // Below, at and between the values, and above all of them
+        assert_eq!(
+            results["ranks"]["values"],
+            serde_json::json!({"50.0": 0.0, "200.0": 50.0, "250.0": 50.0, "1000.0": 100.0})
+        );
+        // Order 3 has no weight and is skipped; order 4 weighs nothing
+        assert_eq!(results["avg"]["value"], serde_json::json!((100.0 + 200.0 * 3.0) / 4.0));
+
+        // Without matches the ranks are undefined, and so is an average of zero weight
+        let (_, results, errors) = engine.aggregate("orders", "missing", aggregations.clone());
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(
+            results["ranks"]["values"],
+            serde_json::json!({"50.0": null, "200.0": null, "250.0": null, "1000.0": null})
+        );
+        assert_eq!(results["avg"]["value"], serde_json::Value::Null);
+        let (_, results, _) = engine.aggregate("orders", "refund", aggregations);
+        assert_eq!(results["avg"]["value"], serde_json::Value::Null);
+        assert_eq!(results["ranks"]["values"]["50.0"], serde_json::json!(100.0));
+
+        // Deleted documents aren't counted
+        engine.delete_document("orders", "2").unwrap();
+        let ranks = serde_json::json!([
+            {"name": "ranks", "agg_type": "percentile_ranks", "field": "latency", "values": [200]},
+        ]);
+        let (_, results, _) = engine.aggregate("orders", "order", ranks);
+        assert_eq!(results["ranks"]["values"]["200.0"], serde_json::json!(100.0 / 3.0));
+    }
+
+    #[test]
+    fn test_terms_aggregation_counts_stored_values_without_fast_values() {
+        let engine = TestEngine::new();
+        engine.create(
+            "shoes",
+            serde_json::json!([
+                {"name": "title", "stored": true, "indexed": true},
+                {"name": "color", "field_type": "string", "stored": true, "indexed": true},
+                {"name": "notes", "indexed": true},
+            ]),
+        );
+        engine.add("shoes", serde_json::json!([
+            {"id": "1", "fields": {"title": "Red shoe", "color": "red"}},
+            {"id": "2", "fields": {"title": "Red shoe", "color": "red"}},
+            {"id": "3", "fields": {"title": "Blue shoe", "color": "blue"}},
+        ]));
+        engine.add("shoes", serde_json::json!([
+            {"id": "4", "fields": {"title": "Green shoe", "color": "green"}},
+            {"id": "5", "fields": {"title": "Red boot", "color": "red"}},
+        ]));
+        let terms = |field: &str| {
+            serde_json::json!([
+                {"name": "terms", "agg_type": "terms", "field": field, "size": 2},
+            ])
+        };
+        let buckets = |results: &serde_json::Value| -> Vec<(String, u64)> {
+            results["terms"]["buckets"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|bucket| {
+                    let key = bucket["key"].as_str().unwrap().to_string();
+                    (key, bucket["doc_count"].as_u64().unwrap())
+                })
+                .collect()
+        };
+
+        let (hits, results, errors) = engine.aggregate("shoes", "shoe", terms("color"));
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(hits.len(), 4);
+        assert_eq!(buckets(&results), [("red".to_string(), 2), ("blue".to_string(), 1)]);
+        assert_eq!(results["terms"]["sum_other_doc_count"], serde_json::json!(1));
+        // Whole text values are counted, not their words
+        let (_, results, _) = engine.aggregate("shoes", "red", terms("title"));
+        assert_eq!(
+            buckets(&results),
+            [("Red shoe".to_string(), 2), ("Red boot".to_string(), 1)]
+        );
+
+        // A text field that isn't stored has nothing to count
+        let (hits, _, errors) = engine.aggregate("shoes", "shoe", terms("notes"));
+        assert_eq!(hits.len(), 4);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].error.contains("fast: true or stored: true"), "{errors:?}");
+    }
+
+    #[test]
+    fn test_aggregations_merge_the_results_of_every_shard() {
+        let engine = TestEngine::new();
+        let fields: Vec<FieldConfig> = serde_json::from_value(serde_json::json!([
+            {"name": "title", "stored": true, "indexed": true},
+            {"name": "price", "field_type": "f64", "stored": true, "fast": true},
+        ]))
+        .unwrap();
+        for (name, shards) in [("single", 1), ("sharded", 3)] {
+            engine.create_index(name, &fields, &IndexSettings::default(), shards).unwrap();
+            let docs: Vec<serde_json::Value> = (1..=6)
+                .map(|i| serde_json::json!({"id": i.to_string(), "fields": {
+                    "title": "shoe",
+                    "price": i as f64 * 10.0,
+                }}))
+                .collect();
+            engine.add(name, serde_json::Value::Array(docs));
+        }
+        let aggregations = serde_json::json!([
+            {"name": "prices", "agg_type": "stats", "field": "price"},
+            {"name": "cheap", "agg_type": "range", "field": "price", "ranges": [{"to": 35.0}]},
+        ]);
+
+        for name in ["single", "sharded"] {
+            let (hits, results, errors) = engine.aggregate(name, "shoe", aggregations.clone());
+            assert!(errors.is_empty(), "{errors:?}");
+            assert_eq!(hits.len(), 6);
+            assert_eq!(results["prices"]["count"], serde_json::json!(6), "{name}");
+            assert_eq!(results["prices"]["sum"], serde_json::json!(210.0), "{name}");
+            assert_eq!(results["prices"]["max"], serde_json::json!(60.0), "{name}");
+            assert_eq!(results["cheap"]["buckets"][0]["doc_count"], serde_json::json!(3), "{name}");
+        }
+    }
 
-        let reader = handle
-            .index
-            .reader_builder()
-            .reload_policy(ReloadPolicy::OnCommitWithDelay)
-            .try_into()?;
+    #[test]
+    fn test_failed_aggregations_are_reported_next_to_the_results() {
+        let engine = TestEngine::new();
+        engine.create(
+            "shoes",
+            serde_json::json!([
+                {"name": "title", "stored": true, "indexed": true},
+                {"name": "price", "field_type": "f64", "stored": true, "fast": true},
+                {"name": "weight", "field_type": "f64", "stored": true},
+            ]),
+        );
+        engine.add("shoes", serde_json::json!([
+            {"id": "1", "fields": {"title": "shoe", "price": 10.0, "weight": 1.0}},
+            {"id": "2", "fields": {"title": "shoe", "price": 30.0, "weight": 2.0}},
+        ]));
+        let (hits, results, errors) = engine.aggregate("shoes", "shoe", serde_json::json!([
+            {"name": "prices", "agg_type": "stats", "field": "price"},
+            {"name": "weights", "agg_type": "stats", "field": "weight"},
+            {"name": "colors", "agg_type": "terms", "field": "color"},
+        ]));
+        assert_eq!(hits.len(), 2);
+        assert_eq!(results["prices"]["avg"], serde_json::json!(20.0));
+        assert!(results.get("weights").is_none());
+        let mut names: Vec<&str> = errors.iter().map(|e| e.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["colors", "weights"]);
+        let colors = errors.iter().find(|e| e.name == "colors").unwrap();
+        assert_eq!(colors.error, "Aggregation field not found: color");
+
+        // A search whose only aggregation fails still returns its hits
+        let (hits, results, errors) = engine.aggregate("shoes", "shoe", serde_json::json!([
+            {"name": "weights", "agg_type": "stats", "field": "weight"},
+        ]));
+        assert_eq!(hits.len(), 2);
+        assert_eq!(results, serde_json::Value::Null);
+        assert_eq!(errors.len(), 1);
+        assert!(!errors[0].error.is_empty());
+    }
 
-        let searcher = reader.searcher();
-        Ok(searcher.num_docs())
+    #[test]
+    fn test_json_values_come_back_as_json() {
+        let engine = TestEngine::new();
+        engine.create(
+            "docs",
+            serde_json::json!([
+                {"name": "title", "stored": true, "indexed": true},
+                {"name": "meta", "field_type": "json", "stored": true, "indexed": true},
+                {"name": "location", "field_type": "geo_point", "stored": true},
+            ]),
+        );
+        let meta = serde_json::json!({
+            "author": {"name": "Ada", "verified": true},
+            "tags": ["red", "shoe"],
+            "pages": 12,
+            "draft": false,
+        });
+        engine.add("docs", serde_json::json!([{"id": "1", "fields": {
+            "title": "shoe",
+            "meta": meta,
+            "location": {"lat": 59.91, "lon": 10.75},
+        }}]));
+
+        let (hits, ..) = engine.search("docs", "shoe", 10, 0, &[], None, &[]).unwrap();
+        assert_eq!(hits.len(), 1);
+        let fields = &hits[0].fields;
+        assert_eq!(fields["title"], serde_json::json!("shoe"));
+        assert_eq!(fields["meta"], meta);
+        assert_eq!(fields["meta"]["author"]["verified"], serde_json::json!(true));
+        assert_eq!(fields["meta"]["tags"], serde_json::json!(["red", "shoe"]));
+        assert!(fields["location"].is_object(), "{:?}", fields["location"]);
+
+        // Re-adding the stored document keeps the JSON too
+        assert_eq!(engine.reindex_documents("docs").unwrap(), 1);
+        let documents = engine
+            .lookup_documents("docs", &["1".to_string()], &Access::default())
+            .unwrap();
+        assert_eq!(documents["1"].fields["meta"], meta);
+        assert_eq!(engine.search_ids("docs", "meta.author.name:ada"), ["1"]);
     }
 }
@@ -0,0 +1,154 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::auth::ApiTokens;
+use crate::quota;
+
+/// Prefix telling search keys apart from API tokens
+const KEY_PREFIX: &str = "sk_";
+
+/// What a search key allows, signed with the API token it was derived from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchKeyClaims {
+    /// Identifies the parent token without revealing it
+    parent: String,
+    /// Query every search made with the key must also match
+    pub filter: String,
+    /// Indices the key can search; any index when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub indices: Vec<String>,
+    /// Restricted field scopes granted to the key, out of the parent token's scopes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scopes: Vec<String>,
+    /// Unix time after which the key is rejected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+}
+
+impl SearchKeyClaims {
+    pub fn new(
+        parent_token: &str,
+        filter: String,
+        indices: Vec<String>,
+        scopes: Vec<String>,
+        expires_at: Option<i64>,
+    ) -> Self {
+        Self {
+            parent: quota::token_id(parent_token),
+            filter,
+            indices,
+            scopes,
+            expires_at,
+        }
+    }
+
+    pub fn allows_index(&self, index_name: &str) -> bool {
+        self.indices.is_empty() || self.indices.iter().any(|name| name == index_name)
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| now >= expires_at)
+    }
+}
+
+/// Whether a bearer token is a search key rather than an API token
+pub fn is_search_key(token: &str) -> bool {
+    token.starts_with(KEY_PREFIX)
+}
+
+/// Encode and sign claims with the parent token
+pub fn sign(parent_token: &str, claims: &SearchKeyClaims) -> String {
+    let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).unwrap_or_default());
+    let mac = hmac_sha256(parent_token.as_bytes(), payload.as_bytes());
+    let signature = URL_SAFE_NO_PAD.encode(mac);
+    format!("{}{}.{}", KEY_PREFIX, payload, signature)
+}
+
+/// Claims of a search key and the configured token that signed it. Expiry is left to
+/// the caller, so it can report it separately.
+pub fn verify<'a>(key: &str, tokens: &'a ApiTokens) -> Option<(SearchKeyClaims, &'a str)> {
+    let (payload, signature) = key.strip_prefix(KEY_PREFIX)?.split_once('.')?;
+    let claims: SearchKeyClaims =
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+    let parent = tokens.find(|token| quota::token_id(token) == claims.parent)?;
+
+    let expected = hmac_sha256(parent.as_bytes(), payload.as_bytes());
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+    constant_time_eq(&expected, &signature).then_some((claims, parent))
+}
+
+/// HMAC-SHA256 (RFC 2104)
+fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    const BLOCK_SIZE: usize = 64;
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let inner_pad: Vec<u8> = block.iter().map(|b| b ^ 0x36).collect();
+    let outer_pad: Vec<u8> = block.iter().map(|b| b ^ 0x5c).collect();
+    let inner = Sha256::new().chain_update(&inner_pad).chain_update(message).finalize();
+    Sha256::new()
+        .chain_update(&outer_pad)
+        .chain_update(inner)
+        .finalize()
+        .to_vec()
+}
+
+/// Compare signatures without leaking where they differ through timing
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(parent: &str) -> SearchKeyClaims {
+        SearchKeyClaims::new(
+            parent,
+            "tenant_id:123".to_string(),
+            vec!["products".to_string()],
+            Vec::new(),
+            None,
+        )
+    }
+
+    #[test]
+    fn test_hmac_matches_rfc_4231() {
+        let mac = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        let hex: String = mac.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(hex, "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+
+    #[test]
+    fn test_verifies_keys_signed_by_a_configured_token() {
+        let tokens = ApiTokens::parse("admin,other");
+        let key = sign("admin", &claims("admin"));
+        assert!(is_search_key(&key));
+
+        let (verified, parent) = verify(&key, &tokens).unwrap();
+        assert_eq!(parent, "admin");
+        assert_eq!(verified.filter, "tenant_id:123");
+        assert!(verified.allows_index("products") && !verified.allows_index("orders"));
+
+        assert!(verify(&key, &ApiTokens::parse("other")).is_none());
+    }
+
+    #[test]
+    fn test_rejects_tampered_keys() {
+        let tokens = ApiTokens::parse("admin");
+        let key = sign("admin", &claims("admin"));
+        let (_, signature) = key.split_once('.').unwrap();
+
+        let mut widened = claims("admin");
+        widened.filter = "*".to_string();
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&widened).unwrap());
+        let forged = format!("{}{}.{}", KEY_PREFIX, payload, signature);
+        assert!(verify(&forged, &tokens).is_none());
+    }
+}
@@ -0,0 +1,108 @@
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+
+use tantivy::query::Bm25StatisticsProvider;
+use tantivy::schema::Field;
+use tantivy::{DocAddress, Searcher, Term};
+
+/// Most shards an index can be split into
+pub const MAX_SHARDS: usize = 64;
+
+/// Prefix of the shard directories inside a sharded index's directory
+const SHARD_DIR_PREFIX: &str = "shard_";
+
+/// Directory of one shard of a sharded index
+pub fn shard_path(index_path: &Path, shard: usize) -> PathBuf {
+    index_path.join(format!("{}{}", SHARD_DIR_PREFIX, shard))
+}
+
+/// Number of shards found in an index directory; 0 for an unsharded index, whose
+/// files live in the directory itself
+pub fn shard_count(index_path: &Path) -> usize {
+    (0..MAX_SHARDS)
+        .take_while(|shard| shard_path(index_path, *shard).is_dir())
+        .count()
+}
+
+/// Hash of a document ID. FNV-1a keeps it stable across builds and platforms, unlike
+/// the std hasher.
+pub fn id_hash(doc_id: &[u8]) -> u64 {
+    doc_id.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Shard holding a document
+pub fn shard_for(doc_id: &str, shards: usize) -> usize {
+    if shards <= 1 {
+        return 0;
+    }
+    (id_hash(doc_id.as_bytes()) % shards as u64) as usize
+}
+
+/// BM25 statistics over all shards, so scores are comparable when shard results are merged
+pub struct ShardStatistics<'a>(pub &'a [Searcher]);
+
+impl Bm25StatisticsProvider for ShardStatistics<'_> {
+    fn total_num_tokens(&self, field: Field) -> tantivy::Result<u64> {
+        self.0.iter().map(|searcher| searcher.total_num_tokens(field)).sum()
+    }
+
+    fn total_num_docs(&self) -> tantivy::Result<u64> {
+        self.0.iter().map(|searcher| searcher.total_num_docs()).sum()
+    }
+
+    fn doc_freq(&self, term: &Term) -> tantivy::Result<u64> {
+        self.0.iter().map(|searcher| searcher.doc_freq(term)).sum()
+    }
+}
+
+/// Merge the top documents of each shard into one list, best first. `ordering` compares
+/// two sort keys the way each shard ordered them; ties keep shard order.
+pub fn merge_top_docs<K>(
+    per_shard: Vec<Vec<(K, DocAddress)>>,
+    ordering: impl Fn(&K, &K) -> Ordering,
+) -> Vec<(K, usize, DocAddress)> {
+    let mut merged: Vec<(K, usize, DocAddress)> = per_shard
+        .into_iter()
+        .enumerate()
+        .flat_map(|(shard, docs)| {
+            docs.into_iter()
+                .map(move |(key, address)| (key, shard, address))
+        })
+        .collect();
+    merged.sort_by(|a, b| ordering(&a.0, &b.0));
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes_documents_to_stable_shards() {
+        assert_eq!(shard_for("doc-1", 1), 0);
+        assert_eq!(shard_for("doc-1", 4), shard_for("doc-1", 4));
+        // Changing the hash would move existing documents to other shards
+        assert_eq!(shard_for("doc-1", 8), 3);
+        assert_eq!(shard_for("doc-2", 8), 6);
+        assert!((0..1000).all(|i| shard_for(&i.to_string(), 3) < 3));
+    }
+
+    #[test]
+    fn test_merges_shards_in_order() {
+        let address = |doc| DocAddress::new(0, doc);
+        let merged = merge_top_docs(
+            vec![
+                vec![(3.0_f32, address(0)), (1.0, address(1))],
+                vec![(2.0, address(0)), (1.0, address(1))],
+            ],
+            |a, b| b.total_cmp(a),
+        );
+        let order: Vec<(usize, u32)> = merged
+            .iter()
+            .map(|(_, shard, address)| (*shard, address.doc_id))
+            .collect();
+        assert_eq!(order, vec![(0, 0), (1, 0), (0, 1), (1, 1)]);
+    }
+}
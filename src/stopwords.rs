@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+
+/// Stopwords used when an index doesn't list its own: question words and common Norwegian
+/// and English function words
+const DEFAULT_STOPWORDS: &[&str] = &[
+    "hva", "hvem", "hvor", "hvilken", "hvilke", "hvordan", "når", "hvorfor",
+    "what", "who", "where", "which", "how", "when", "why",
+    "er", "var", "bli", "blir", "være",
+    "og", "eller", "for", "av", "til", "med", "i", "på", "om", "som",
+    "en", "et", "den", "det", "de", "du", "jeg", "vi", "oss",
+];
+
+/// Stopwords of an index's search queries
+pub struct Stopwords(HashSet<String>);
+
+impl Stopwords {
+    /// The index's own stopwords, or the built-in ones when it lists none
+    pub fn new(words: &[String]) -> Self {
+        if words.is_empty() {
+            return Self(DEFAULT_STOPWORDS.iter().map(|word| word.to_string()).collect());
+        }
+        Self(words.iter().map(|word| word.trim().to_lowercase()).collect())
+    }
+
+    fn contains(&self, word: &str) -> bool {
+        self.0.contains(&word.to_lowercase())
+    }
+
+    /// The query without its stopwords, keeping its syntax: words in quoted phrases, field
+    /// queries like `title:og` and the `AND`/`OR`/`NOT` operators stay. `None` when there
+    /// is nothing to remove, or nothing but stopwords.
+    pub fn remove(&self, query: &str) -> Option<String> {
+        let mut kept: Vec<&str> = Vec::new();
+        let mut removed = false;
+        let mut in_phrase = false;
+        for token in query.split_whitespace() {
+            let quoted = in_phrase || token.starts_with('"');
+            in_phrase ^= token.matches('"').count() % 2 == 1;
+            let word = token.trim_end_matches(['?', '!', '.', ',', ';']);
+            let plain = !word.is_empty() && word.chars().all(char::is_alphanumeric);
+            if !quoted && plain && !matches!(word, "AND" | "OR" | "NOT") && self.contains(word) {
+                removed = true;
+            } else {
+                kept.push(token);
+            }
+        }
+        (removed && !kept.is_empty()).then(|| kept.join(" "))
+    }
+
+    /// Keywords of a query for a retry after it found nothing: its words without
+    /// punctuation, query syntax and stopwords
+    pub fn keywords(&self, query: &str) -> Option<String> {
+        let cleaned: String = query
+            .to_lowercase()
+            .chars()
+            .map(|c| if c.is_alphanumeric() || c == '_' || c == '-' { c } else { ' ' })
+            .collect();
+
+        let tokens: Vec<&str> = cleaned
+            .split_whitespace()
+            .filter(|token| token.len() > 1 && !self.contains(token))
+            .collect();
+
+        if tokens.is_empty() {
+            None
+        } else {
+            Some(tokens.join(" "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_removes_stopwords_outside_query_syntax() {
+        let stopwords = Stopwords::new(&[]);
+        assert_eq!(
+            stopwords.remove("Hvor er barnehagen i Bergen?").as_deref(),
+            Some("barnehagen Bergen?")
+        );
+        assert_eq!(
+            stopwords.remove("\"salt og pepper\" og title:i").as_deref(),
+            Some("\"salt og pepper\" title:i")
+        );
+        assert_eq!(stopwords.remove("hva er det"), None);
+        assert_eq!(stopwords.remove("barnehage"), None);
+        assert_eq!(stopwords.keywords("Hva er SFO?").as_deref(), Some("sfo"));
+
+        let custom = Stopwords::new(&["Barnehage".to_string()]);
+        assert_eq!(custom.remove("barnehage i bergen").as_deref(), Some("i bergen"));
+    }
+}
@@ -1,9 +1,18 @@
 use anyhow::Result;
 use chrono::Utc;
-use rusqlite::{params, Connection};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
-use crate::models::IndexInfo;
+use crate::models::{
+    AnswerUsage, AuditEntry, AuditParams, CrawlerConfig, IdempotentResponse, IndexInfo,
+    IngestOutcome, JobConfig, TrendingQuery,
+};
+
+/// Days the searches per query and day are kept for; trending queries compare at most
+/// half of them with the other half
+pub const QUERY_DAYS_KEPT: i64 = 60;
 
 pub struct MetadataStore {
     conn: Arc<Mutex<Connection>>,
@@ -22,33 +31,179 @@ impl MetadataStore {
             [],
         )?;
 
+        // Document ids are only unique within an index; older databases keyed rows by id
+        // alone, so drop that table and let the startup sync rebuild it from the indices
+        let id_only_key: bool = conn.query_row(
+            "SELECT COUNT(*) = 1 FROM pragma_table_info('documents') WHERE pk > 0",
+            [],
+            |row| row.get(0),
+        )?;
+        if id_only_key {
+            conn.execute("DROP TABLE documents", [])?;
+        }
+
         conn.execute(
             "CREATE TABLE IF NOT EXISTS documents (
-                id TEXT PRIMARY KEY,
+                id TEXT NOT NULL,
                 index_name TEXT NOT NULL,
                 created_at TEXT NOT NULL,
                 updated_at TEXT NOT NULL,
+                PRIMARY KEY (index_name, id),
                 FOREIGN KEY (index_name) REFERENCES indices(name) ON DELETE CASCADE
             )",
             [],
         )?;
 
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS crawlers (
+                id TEXT PRIMARY KEY,
+                index_name TEXT NOT NULL,
+                seed_url TEXT NOT NULL,
+                max_depth INTEGER NOT NULL,
+                max_pages INTEGER NOT NULL,
+                include_patterns TEXT NOT NULL,
+                exclude_patterns TEXT NOT NULL,
+                interval_seconds INTEGER,
+                created_at TEXT NOT NULL,
+                last_run_at TEXT,
+                last_status TEXT,
+                last_message TEXT
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS jobs (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                index_name TEXT NOT NULL,
+                schedule TEXT NOT NULL,
+                task TEXT NOT NULL,
+                enabled INTEGER NOT NULL,
+                created_at TEXT NOT NULL,
+                last_run_at TEXT,
+                last_status TEXT,
+                last_message TEXT
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS answer_usage (
+                api_key TEXT NOT NULL,
+                period TEXT NOT NULL,
+                count INTEGER NOT NULL,
+                PRIMARY KEY (api_key, period)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS search_queries (
+                index_name TEXT NOT NULL,
+                query TEXT NOT NULL,
+                search_count INTEGER NOT NULL,
+                zero_result_count INTEGER NOT NULL,
+                last_searched_at TEXT NOT NULL,
+                PRIMARY KEY (index_name, query)
+            )",
+            [],
+        )?;
+
+        // Searches per query and day, for trending queries; see `QUERY_DAYS_KEPT`
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS search_query_days (
+                index_name TEXT NOT NULL,
+                query TEXT NOT NULL,
+                day TEXT NOT NULL,
+                search_count INTEGER NOT NULL,
+                PRIMARY KEY (index_name, query, day)
+            );
+            CREATE INDEX IF NOT EXISTS search_query_days_day ON search_query_days (day);",
+        )?;
+
+        // Queries searched in the same session, for related queries. Sessions are kept
+        // as a hash of the ID clients send, for `QUERY_DAYS_KEPT` days.
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS search_sessions (
+                index_name TEXT NOT NULL,
+                session TEXT NOT NULL,
+                query TEXT NOT NULL,
+                searched_at TEXT NOT NULL,
+                PRIMARY KEY (index_name, session, query)
+            );
+            CREATE INDEX IF NOT EXISTS search_sessions_query
+                ON search_sessions (index_name, query);
+            CREATE INDEX IF NOT EXISTS search_sessions_searched_at
+                ON search_sessions (searched_at);",
+        )?;
+
+        // Append-only: entries can't be changed or removed, not even through SQL
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS audit_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp TEXT NOT NULL,
+                actor TEXT NOT NULL,
+                action TEXT NOT NULL,
+                index_name TEXT,
+                method TEXT NOT NULL,
+                path TEXT NOT NULL,
+                status INTEGER NOT NULL,
+                details TEXT
+            );
+            CREATE INDEX IF NOT EXISTS audit_log_index_name ON audit_log (index_name, id);
+            CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+            BEGIN
+                SELECT RAISE(ABORT, 'the audit log is append-only');
+            END;
+            CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+            BEGIN
+                SELECT RAISE(ABORT, 'the audit log is append-only');
+            END;",
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS idempotency_keys (
+                actor TEXT NOT NULL,
+                key TEXT NOT NULL,
+                request_hash TEXT NOT NULL,
+                status INTEGER NOT NULL,
+                response BLOB NOT NULL,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY (actor, key)
+            )",
+            [],
+        )?;
+
+        // Documents indexed from each file of a watched directory, so files deleted while
+        // the service was down are still removed from the index
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS watched_files (
+                index_name TEXT NOT NULL,
+                path TEXT NOT NULL,
+                doc_ids TEXT NOT NULL,
+                PRIMARY KEY (index_name, path)
+            )",
+            [],
+        )?;
+
         Ok(Self {
             conn: Arc::new(Mutex::new(conn)),
         })
     }
 
-    pub fn create_index(&self, name: &str) -> Result<()> {
+    /// Record a new index, returning false if a row for the name already exists
+    pub fn create_index(&self, name: &str) -> Result<bool> {
         let conn = self.conn.lock()
             .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
         let now = Utc::now().to_rfc3339();
 
-        conn.execute(
-            "INSERT INTO indices (name, created_at, updated_at) VALUES (?1, ?2, ?3)",
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO indices (name, created_at, updated_at) VALUES (?1, ?2, ?3)",
             params![name, now, now],
         )?;
 
-        Ok(())
+        Ok(inserted > 0)
     }
 
     pub fn sync_indices_from_disk(&self, index_names: &[String]) -> Result<()> {
@@ -77,6 +232,12 @@ impl MetadataStore {
             .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
 
         conn.execute("DELETE FROM documents WHERE index_name = ?1", params![name])?;
+        conn.execute("DELETE FROM crawlers WHERE index_name = ?1", params![name])?;
+        conn.execute("DELETE FROM jobs WHERE index_name = ?1", params![name])?;
+        conn.execute("DELETE FROM search_queries WHERE index_name = ?1", params![name])?;
+        conn.execute("DELETE FROM search_query_days WHERE index_name = ?1", params![name])?;
+        conn.execute("DELETE FROM search_sessions WHERE index_name = ?1", params![name])?;
+        conn.execute("DELETE FROM watched_files WHERE index_name = ?1", params![name])?;
         conn.execute("DELETE FROM indices WHERE name = ?1", params![name])?;
 
         Ok(())
@@ -99,6 +260,8 @@ impl MetadataStore {
                     name: row.get(0)?,
                     created_at: row.get(1)?,
                     document_count: row.get(2)?,
+                    // Filled in from the search engine by the caller
+                    closed: false,
                 })
             })?
             .collect::<Result<Vec<_>, _>>()?;
@@ -106,54 +269,133 @@ impl MetadataStore {
         Ok(indices)
     }
 
-    pub fn add_document(&self, index_name: &str, doc_id: &str) -> Result<()> {
-        let conn = self.conn.lock()
-            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+    /// Insert or refresh document rows with one prepared statement
+    fn insert_documents(tx: &Transaction, index_name: &str, doc_ids: &[String]) -> Result<()> {
         let now = Utc::now().to_rfc3339();
-
-        conn.execute(
+        let mut stmt = tx.prepare(
             "INSERT OR REPLACE INTO documents (id, index_name, created_at, updated_at) 
              VALUES (?1, ?2, ?3, ?4)",
-            params![doc_id, index_name, now, now],
         )?;
+        for doc_id in doc_ids {
+            stmt.execute(params![doc_id, index_name, now, now])?;
+        }
+        Ok(())
+    }
+
+    /// Record the documents written and replaced by an ingest call in one transaction
+    pub fn apply_ingest_outcome(&self, index_name: &str, outcome: &IngestOutcome) -> Result<()> {
+        let mut conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
 
+        let tx = conn.transaction()?;
+        {
+            let mut stmt =
+                tx.prepare("DELETE FROM documents WHERE index_name = ?1 AND id = ?2")?;
+            for doc_id in outcome.replaced_ids.iter().chain(&outcome.deleted_ids) {
+                stmt.execute(params![index_name, doc_id])?;
+            }
+        }
+        Self::insert_documents(&tx, index_name, &outcome.indexed_ids)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// IDs of the documents recorded for an index
+    pub fn document_ids(&self, index_name: &str) -> Result<Vec<String>> {
+        let conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+
+        let mut stmt = conn.prepare("SELECT id FROM documents WHERE index_name = ?1")?;
+        let ids = stmt
+            .query_map(params![index_name], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        Ok(ids)
+    }
+
+    /// Add missing document rows and remove stale ones in one transaction
+    pub fn repair_documents(
+        &self,
+        index_name: &str,
+        missing_ids: &[String],
+        stale_ids: &[String],
+    ) -> Result<()> {
+        let mut conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+
+        let tx = conn.transaction()?;
+        {
+            let mut stmt =
+                tx.prepare("DELETE FROM documents WHERE index_name = ?1 AND id = ?2")?;
+            for doc_id in stale_ids {
+                stmt.execute(params![index_name, doc_id])?;
+            }
+        }
+        Self::insert_documents(&tx, index_name, missing_ids)?;
+        tx.commit()?;
         Ok(())
     }
 
     pub fn reset_index_documents(&self, index_name: &str, doc_ids: &[String]) -> Result<()> {
         let mut conn = self.conn.lock()
             .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
-        let now = Utc::now().to_rfc3339();
 
         let tx = conn.transaction()?;
         tx.execute(
             "DELETE FROM documents WHERE index_name = ?1",
             params![index_name],
         )?;
+        Self::insert_documents(&tx, index_name, doc_ids)?;
+        tx.commit()?;
+        Ok(())
+    }
 
-        {
-            let mut stmt = tx.prepare(
-                "INSERT OR REPLACE INTO documents (id, index_name, created_at, updated_at) 
-                 VALUES (?1, ?2, ?3, ?4)",
-            )?;
+    pub fn delete_document(&self, index_name: &str, doc_id: &str) -> Result<()> {
+        let conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+        conn.execute(
+            "DELETE FROM documents WHERE index_name = ?1 AND id = ?2",
+            params![index_name, doc_id],
+        )?;
+        Ok(())
+    }
 
-            for doc_id in doc_ids {
-                stmt.execute(params![doc_id, index_name, now, now])?;
-            }
-        }
+    /// Document IDs indexed from each watched file of an index, by path
+    pub fn watched_files(&self, index_name: &str) -> Result<HashMap<String, Vec<String>>> {
+        let conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
 
-        tx.commit()?;
-        Ok(())
+        let mut stmt =
+            conn.prepare("SELECT path, doc_ids FROM watched_files WHERE index_name = ?1")?;
+        let rows = stmt
+            .query_map(params![index_name], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        rows.into_iter()
+            .map(|(path, doc_ids)| Ok((path, serde_json::from_str(&doc_ids)?)))
+            .collect()
     }
 
-    pub fn delete_document(&self, doc_id: &str) -> Result<()> {
+    /// Record the documents indexed from a watched file; without any, the file is forgotten
+    pub fn set_watched_file(&self, index_name: &str, path: &str, doc_ids: &[String]) -> Result<()> {
         let conn = self.conn.lock()
             .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
-        conn.execute("DELETE FROM documents WHERE id = ?1", params![doc_id])?;
+
+        if doc_ids.is_empty() {
+            conn.execute(
+                "DELETE FROM watched_files WHERE index_name = ?1 AND path = ?2",
+                params![index_name, path],
+            )?;
+        } else {
+            conn.execute(
+                "INSERT OR REPLACE INTO watched_files (index_name, path, doc_ids)
+                 VALUES (?1, ?2, ?3)",
+                params![index_name, path, serde_json::to_string(doc_ids)?],
+            )?;
+        }
         Ok(())
     }
 
-    #[allow(dead_code)]
     pub fn get_document_count(&self, index_name: &str) -> Result<u64> {
         let conn = self.conn.lock()
             .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
@@ -167,6 +409,570 @@ impl MetadataStore {
         Ok(count)
     }
 
+    pub fn create_crawler(&self, crawler: &CrawlerConfig) -> Result<()> {
+        let conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+
+        conn.execute(
+            "INSERT INTO crawlers (id, index_name, seed_url, max_depth, max_pages,
+                include_patterns, exclude_patterns, interval_seconds, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                crawler.id,
+                crawler.index_name,
+                crawler.seed_url,
+                crawler.max_depth as i64,
+                crawler.max_pages as i64,
+                serde_json::to_string(&crawler.include_patterns)?,
+                serde_json::to_string(&crawler.exclude_patterns)?,
+                crawler.interval_seconds.map(|s| s as i64),
+                crawler.created_at,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// List crawlers, optionally restricted to one index
+    pub fn list_crawlers(&self, index_name: Option<&str>) -> Result<Vec<CrawlerConfig>> {
+        let conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+
+        let mut stmt = conn.prepare(
+            "SELECT id, index_name, seed_url, max_depth, max_pages, include_patterns,
+                    exclude_patterns, interval_seconds, created_at, last_run_at,
+                    last_status, last_message
+             FROM crawlers
+             WHERE ?1 IS NULL OR index_name = ?1
+             ORDER BY created_at",
+        )?;
+
+        let crawlers = stmt
+            .query_map(params![index_name], |row| {
+                let include_patterns: String = row.get(5)?;
+                let exclude_patterns: String = row.get(6)?;
+                Ok(CrawlerConfig {
+                    id: row.get(0)?,
+                    index_name: row.get(1)?,
+                    seed_url: row.get(2)?,
+                    max_depth: row.get::<_, i64>(3)? as usize,
+                    max_pages: row.get::<_, i64>(4)? as usize,
+                    include_patterns: serde_json::from_str(&include_patterns).unwrap_or_default(),
+                    exclude_patterns: serde_json::from_str(&exclude_patterns).unwrap_or_default(),
+                    interval_seconds: row.get::<_, Option<i64>>(7)?.map(|s| s as u64),
+                    created_at: row.get(8)?,
+                    last_run_at: row.get(9)?,
+                    last_status: row.get(10)?,
+                    last_message: row.get(11)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(crawlers)
+    }
+
+    /// Delete a crawler, returning whether it existed
+    pub fn delete_crawler(&self, index_name: &str, crawler_id: &str) -> Result<bool> {
+        let conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+
+        let deleted = conn.execute(
+            "DELETE FROM crawlers WHERE id = ?1 AND index_name = ?2",
+            params![crawler_id, index_name],
+        )?;
+
+        Ok(deleted > 0)
+    }
+
+    /// Store the outcome of a crawl run
+    pub fn record_crawl_run(&self, crawler_id: &str, status: &str, message: &str) -> Result<()> {
+        let conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+        let now = Utc::now().to_rfc3339();
+
+        conn.execute(
+            "UPDATE crawlers SET last_run_at = ?1, last_status = ?2, last_message = ?3
+             WHERE id = ?4",
+            params![now, status, message, crawler_id],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn create_job(&self, job: &JobConfig) -> Result<()> {
+        let conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+
+        conn.execute(
+            "INSERT INTO jobs (id, name, index_name, schedule, task, enabled, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                job.id,
+                job.name,
+                job.index_name,
+                job.schedule,
+                serde_json::to_string(&job.task)?,
+                job.enabled,
+                job.created_at,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn list_jobs(&self) -> Result<Vec<JobConfig>> {
+        let conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+
+        let mut stmt = conn.prepare(
+            "SELECT id, name, index_name, schedule, task, enabled, created_at,
+                    last_run_at, last_status, last_message
+             FROM jobs
+             ORDER BY created_at",
+        )?;
+
+        let jobs = stmt
+            .query_map([], |row| {
+                let task: String = row.get(4)?;
+                Ok(JobConfig {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    index_name: row.get(2)?,
+                    schedule: row.get(3)?,
+                    task: serde_json::from_str(&task).map_err(|e| {
+                        rusqlite::Error::FromSqlConversionFailure(4, Type::Text, Box::new(e))
+                    })?,
+                    enabled: row.get(5)?,
+                    created_at: row.get(6)?,
+                    last_run_at: row.get(7)?,
+                    last_status: row.get(8)?,
+                    last_message: row.get(9)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(jobs)
+    }
+
+    /// Delete a job, returning whether it existed
+    pub fn delete_job(&self, job_id: &str) -> Result<bool> {
+        let conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+
+        let deleted = conn.execute("DELETE FROM jobs WHERE id = ?1", params![job_id])?;
+
+        Ok(deleted > 0)
+    }
+
+    /// Store the outcome of a job run
+    pub fn record_job_run(&self, job_id: &str, status: &str, message: &str) -> Result<()> {
+        let conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+        let now = Utc::now().to_rfc3339();
+
+        conn.execute(
+            "UPDATE jobs SET last_run_at = ?1, last_status = ?2, last_message = ?3
+             WHERE id = ?4",
+            params![now, status, message, job_id],
+        )?;
+
+        Ok(())
+    }
+
+    /// Count an answer request against a key's daily and monthly quotas. A request
+    /// that would exceed either quota is not counted and returns `allowed: false`.
+    pub fn consume_answer_quota(
+        &self,
+        api_key: &str,
+        (day, month): (&str, &str),
+        daily_quota: Option<u64>,
+        monthly_quota: Option<u64>,
+    ) -> Result<AnswerUsage> {
+        let mut conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+        let tx = conn.transaction()?;
+
+        // Counts from earlier days and months are no longer needed
+        tx.execute(
+            "DELETE FROM answer_usage WHERE api_key = ?1 AND period NOT IN (?2, ?3)",
+            params![api_key, day, month],
+        )?;
+
+        let count = |period: &str| -> Result<u64> {
+            let count: Option<i64> = tx
+                .query_row(
+                    "SELECT count FROM answer_usage WHERE api_key = ?1 AND period = ?2",
+                    params![api_key, period],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(count.unwrap_or(0) as u64)
+        };
+        let mut usage = AnswerUsage {
+            allowed: false,
+            daily_used: count(day)?,
+            monthly_used: count(month)?,
+        };
+
+        usage.allowed = daily_quota.is_none_or(|quota| usage.daily_used < quota)
+            && monthly_quota.is_none_or(|quota| usage.monthly_used < quota);
+        if usage.allowed {
+            for period in [day, month] {
+                tx.execute(
+                    "INSERT INTO answer_usage (api_key, period, count) VALUES (?1, ?2, 1)
+                     ON CONFLICT(api_key, period) DO UPDATE SET count = count + 1",
+                    params![api_key, period],
+                )?;
+            }
+            usage.daily_used += 1;
+            usage.monthly_used += 1;
+        }
+
+        tx.commit()?;
+        Ok(usage)
+    }
+
+    /// Count a search in the query log, along with whether it found nothing and the
+    /// session it was part of, if the client named one
+    pub fn record_search(
+        &self,
+        index_name: &str,
+        query: &str,
+        total: usize,
+        session: Option<&str>,
+    ) -> Result<()> {
+        let mut conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+        let zero_results = i64::from(total == 0);
+        let now = Utc::now();
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "INSERT INTO search_queries
+                 (index_name, query, search_count, zero_result_count, last_searched_at)
+             VALUES (?1, ?2, 1, ?3, ?4)
+             ON CONFLICT(index_name, query) DO UPDATE SET
+                 search_count = search_count + 1,
+                 zero_result_count = zero_result_count + ?3,
+                 last_searched_at = ?4",
+            params![index_name, query, zero_results, now.to_rfc3339()],
+        )?;
+        tx.execute(
+            "INSERT INTO search_query_days (index_name, query, day, search_count)
+             VALUES (?1, ?2, ?3, 1)
+             ON CONFLICT(index_name, query, day) DO UPDATE SET search_count = search_count + 1",
+            params![index_name, query, day(now)],
+        )?;
+        if let Some(session) = session {
+            tx.execute(
+                "INSERT INTO search_sessions (index_name, session, query, searched_at)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT(index_name, session, query) DO UPDATE SET searched_at = ?4",
+                params![index_name, session, query, now.to_rfc3339()],
+            )?;
+        }
+        let expired = now - chrono::Duration::days(QUERY_DAYS_KEPT);
+        tx.execute("DELETE FROM search_query_days WHERE day < ?1", params![day(expired)])?;
+        tx.execute(
+            "DELETE FROM search_sessions WHERE searched_at < ?1",
+            params![expired.to_rfc3339()],
+        )?;
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Most searched queries starting with `prefix` that found something at least once,
+    /// searched `min_count` times or more. Queries `keep` rejects are skipped.
+    pub fn popular_queries(
+        &self,
+        index_name: &str,
+        prefix: &str,
+        min_count: u64,
+        limit: usize,
+        keep: impl Fn(&str) -> bool,
+    ) -> Result<Vec<(String, u64)>> {
+        let conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+
+        let mut stmt = conn.prepare(
+            "SELECT query, search_count FROM search_queries
+             WHERE index_name = ?1 AND query LIKE ?2 ESCAPE '\\'
+                 AND search_count >= ?3 AND zero_result_count < search_count
+             ORDER BY search_count DESC, last_searched_at DESC",
+        )?;
+        let rows = stmt.query_map(
+            params![index_name, like_prefix(prefix), min_count as i64],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64)),
+        )?;
+        collect_kept(rows, limit, |(query, _)| keep(query))
+    }
+
+    /// Queries starting with `prefix` searched more in the last `window_days` days than
+    /// in the days before them, by how much more. Queries `keep` rejects are skipped.
+    pub fn trending_queries(
+        &self,
+        index_name: &str,
+        prefix: &str,
+        window_days: u32,
+        min_count: u64,
+        limit: usize,
+        keep: impl Fn(&TrendingQuery) -> bool,
+    ) -> Result<Vec<TrendingQuery>> {
+        let conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+        let today = Utc::now();
+        let window_start = today - chrono::Duration::days(i64::from(window_days) - 1);
+        let previous_start = window_start - chrono::Duration::days(i64::from(window_days));
+
+        let mut stmt = conn.prepare(
+            "SELECT d.query,
+                    SUM(CASE WHEN d.day >= ?3 THEN d.search_count ELSE 0 END) AS recent,
+                    SUM(CASE WHEN d.day < ?3 THEN d.search_count ELSE 0 END) AS previous,
+                    q.zero_result_count = q.search_count
+             FROM search_query_days d
+             JOIN search_queries q ON q.index_name = d.index_name AND q.query = d.query
+             WHERE d.index_name = ?1 AND d.query LIKE ?2 ESCAPE '\\' AND d.day >= ?4
+             GROUP BY d.query
+             HAVING recent >= ?5 AND recent > previous
+             ORDER BY recent - previous DESC, recent DESC",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                index_name,
+                like_prefix(prefix),
+                day(window_start),
+                day(previous_start),
+                min_count as i64
+            ],
+            |row| {
+                Ok(TrendingQuery {
+                    query: row.get(0)?,
+                    count: row.get::<_, i64>(1)? as u64,
+                    previous_count: row.get::<_, i64>(2)? as u64,
+                    zero_results: row.get(3)?,
+                })
+            },
+        )?;
+        collect_kept(rows, limit, keep)
+    }
+
+    /// Queries searched in the same sessions as `query`, by the number of sessions, with
+    /// at least `min_sessions` of them. Queries `keep` rejects are skipped.
+    pub fn related_queries(
+        &self,
+        index_name: &str,
+        query: &str,
+        min_sessions: u64,
+        limit: usize,
+        keep: impl Fn(&str) -> bool,
+    ) -> Result<Vec<(String, u64)>> {
+        let conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+
+        let mut stmt = conn.prepare(
+            "SELECT o.query, COUNT(*) AS sessions
+             FROM search_sessions s
+             JOIN search_sessions o ON o.index_name = s.index_name AND o.session = s.session
+                 AND o.query <> s.query
+             WHERE s.index_name = ?1 AND s.query = ?2
+             GROUP BY o.query
+             HAVING sessions >= ?3
+             ORDER BY sessions DESC, MAX(o.searched_at) DESC",
+        )?;
+        let rows = stmt.query_map(params![index_name, query, min_sessions as i64], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64))
+        })?;
+        collect_kept(rows, limit, |(query, _)| keep(query))
+    }
+
+    /// Prune an index's query log: per-day counts and sessions older than
+    /// `QUERY_DAYS_KEPT` days are dropped, their searches still counting in the query
+    /// totals, and so are queries searched only once, before then. Returns how many day
+    /// counts, sessions and queries were dropped.
+    pub fn prune_query_log(&self, index_name: &str) -> Result<(usize, usize, usize)> {
+        let mut conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+        let expired = Utc::now() - chrono::Duration::days(QUERY_DAYS_KEPT);
+        let tx = conn.transaction()?;
+
+        let days = tx.execute(
+            "DELETE FROM search_query_days WHERE index_name = ?1 AND day < ?2",
+            params![index_name, day(expired)],
+        )?;
+        let sessions = tx.execute(
+            "DELETE FROM search_sessions WHERE index_name = ?1 AND searched_at < ?2",
+            params![index_name, expired.to_rfc3339()],
+        )?;
+        let queries = tx.execute(
+            "DELETE FROM search_queries
+             WHERE index_name = ?1 AND search_count = 1 AND last_searched_at < ?2",
+            params![index_name, expired.to_rfc3339()],
+        )?;
+
+        tx.commit()?;
+        Ok((days, sessions, queries))
+    }
+
+    /// Queries that most often found nothing, with how many times they did
+    pub fn zero_result_queries(
+        &self,
+        index_name: &str,
+        limit: usize,
+    ) -> Result<Vec<(String, u64)>> {
+        let conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+
+        let mut stmt = conn.prepare(
+            "SELECT query, zero_result_count FROM search_queries
+             WHERE index_name = ?1 AND zero_result_count > 0
+             ORDER BY zero_result_count DESC, last_searched_at DESC
+             LIMIT ?2",
+        )?;
+        let queries = stmt
+            .query_map(params![index_name, limit as i64], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(queries)
+    }
+
+    /// Append an entry to the audit log; its `id` is assigned here
+    pub fn record_audit(&self, entry: &AuditEntry) -> Result<()> {
+        let conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+
+        conn.execute(
+            "INSERT INTO audit_log
+                 (timestamp, actor, action, index_name, method, path, status, details)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                entry.timestamp,
+                entry.actor,
+                entry.action,
+                entry.index,
+                entry.method,
+                entry.path,
+                entry.status,
+                entry.details.as_ref().map(|details| details.to_string()),
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Audit log entries matching the filters, newest first. `since` and `until` must be
+    /// in the format of the recorded timestamps.
+    pub fn audit_entries(&self, filter: &AuditParams, limit: usize) -> Result<Vec<AuditEntry>> {
+        let conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+
+        let mut stmt = conn.prepare(
+            "SELECT id, timestamp, actor, action, index_name, method, path, status, details
+             FROM audit_log
+             WHERE (?1 IS NULL OR index_name = ?1)
+               AND (?2 IS NULL OR actor = ?2)
+               AND (?3 IS NULL OR action = ?3)
+               AND (?4 IS NULL OR timestamp >= ?4)
+               AND (?5 IS NULL OR timestamp < ?5)
+               AND (?6 IS NULL OR id < ?6)
+             ORDER BY id DESC
+             LIMIT ?7",
+        )?;
+        let entries = stmt
+            .query_map(
+                params![
+                    filter.index,
+                    filter.actor,
+                    filter.action,
+                    filter.since,
+                    filter.until,
+                    filter.before,
+                    limit as i64
+                ],
+                |row| {
+                    let details: Option<String> = row.get(8)?;
+                    Ok(AuditEntry {
+                        id: row.get(0)?,
+                        timestamp: row.get(1)?,
+                        actor: row.get(2)?,
+                        action: row.get(3)?,
+                        index: row.get(4)?,
+                        method: row.get(5)?,
+                        path: row.get(6)?,
+                        status: row.get(7)?,
+                        details: details.and_then(|details| serde_json::from_str(&details).ok()),
+                    })
+                },
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(entries)
+    }
+
+    /// Response recorded for an idempotency key of an actor, unless it was recorded
+    /// before `since` (unix seconds) and has expired
+    pub fn idempotent_response(
+        &self,
+        actor: &str,
+        key: &str,
+        since: i64,
+    ) -> Result<Option<IdempotentResponse>> {
+        let conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+
+        let response = conn
+            .query_row(
+                "SELECT request_hash, status, response FROM idempotency_keys
+                 WHERE actor = ?1 AND key = ?2 AND created_at >= ?3",
+                params![actor, key, since],
+                |row| {
+                    Ok(IdempotentResponse {
+                        request_hash: row.get(0)?,
+                        status: row.get(1)?,
+                        body: row.get(2)?,
+                    })
+                },
+            )
+            .optional()?;
+
+        Ok(response)
+    }
+
+    /// Record the response to a request with an idempotency key, dropping the keys
+    /// recorded before `expired_before` (unix seconds)
+    pub fn record_idempotent_response(
+        &self,
+        actor: &str,
+        key: &str,
+        response: &IdempotentResponse,
+        expired_before: i64,
+    ) -> Result<()> {
+        let conn = self.conn.lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire database lock: {}", e))?;
+
+        conn.execute(
+            "DELETE FROM idempotency_keys WHERE created_at < ?1",
+            params![expired_before],
+        )?;
+        conn.execute(
+            "INSERT OR REPLACE INTO idempotency_keys
+                 (actor, key, request_hash, status, response, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                actor,
+                key,
+                response.request_hash,
+                response.status,
+                response.body,
+                Utc::now().timestamp()
+            ],
+        )?;
+
+        Ok(())
+    }
+
     /// Health check - verifies database connectivity
     pub fn health_check(&self) -> Result<()> {
         let conn = self.conn.lock()
@@ -177,3 +983,208 @@ impl MetadataStore {
         Ok(())
     }
 }
+
+/// Day of the searches per query and day, in UTC
+fn day(at: chrono::DateTime<Utc>) -> String {
+    at.format("%Y-%m-%d").to_string()
+}
+
+/// LIKE pattern matching text that starts with `prefix`
+fn like_prefix(prefix: &str) -> String {
+    let escaped = prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("{}%", escaped)
+}
+
+/// Read rows until `limit` of them are kept
+fn collect_kept<T>(
+    rows: impl Iterator<Item = rusqlite::Result<T>>,
+    limit: usize,
+    keep: impl Fn(&T) -> bool,
+) -> Result<Vec<T>> {
+    let mut kept = Vec::new();
+    for row in rows {
+        let row = row?;
+        if keep(&row) {
+            kept.push(row);
+            if kept.len() == limit {
+                break;
+            }
+        }
+    }
+    Ok(kept)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Store in a fresh database file, and the file's path
+    fn temp_store() -> (MetadataStore, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!("storage-test-{}.db", uuid::Uuid::new_v4()));
+        (MetadataStore::new(path.to_str().unwrap()).unwrap(), path)
+    }
+
+    #[test]
+    fn test_prune_query_log_drops_expired_analytics() {
+        let (store, path) = temp_store();
+        store.record_search("news", "storm", 3, Some("s1")).unwrap();
+        store.record_search("news", "rain", 0, Some("s1")).unwrap();
+
+        // Back-date "rain" past the days kept, and "storm" too, searched twice
+        let old = Utc::now() - chrono::Duration::days(QUERY_DAYS_KEPT + 1);
+        {
+            let conn = store.conn.lock().unwrap();
+            conn.execute(
+                "UPDATE search_queries SET last_searched_at = ?1,
+                     search_count = CASE query WHEN 'storm' THEN 2 ELSE 1 END",
+                params![old.to_rfc3339()],
+            )
+            .unwrap();
+            conn.execute("UPDATE search_query_days SET day = ?1", params![day(old)])
+                .unwrap();
+            conn.execute(
+                "UPDATE search_sessions SET searched_at = ?1 WHERE query = 'rain'",
+                params![old.to_rfc3339()],
+            )
+            .unwrap();
+        }
+
+        assert_eq!(store.prune_query_log("news").unwrap(), (2, 1, 1));
+        let popular = store.popular_queries("news", "", 1, 10, |_| true).unwrap();
+        assert_eq!(popular, [("storm".to_string(), 2)]);
+        assert_eq!(store.prune_query_log("news").unwrap(), (0, 0, 0));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_apply_ingest_outcome_records_a_batch() {
+        let (store, path) = temp_store();
+        store.create_index("news").unwrap();
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+
+        let outcome = IngestOutcome {
+            indexed_ids: ids(&["a", "b", "c"]),
+            ..IngestOutcome::default()
+        };
+        store.apply_ingest_outcome("news", &outcome).unwrap();
+        assert_eq!(store.get_document_count("news").unwrap(), 3);
+
+        // Replaced and deleted documents are removed, re-added ones kept
+        let outcome = IngestOutcome {
+            indexed_ids: ids(&["c", "d"]),
+            replaced_ids: ids(&["a"]),
+            deleted_ids: ids(&["b"]),
+            ..IngestOutcome::default()
+        };
+        store.apply_ingest_outcome("news", &outcome).unwrap();
+        let mut recorded = store.document_ids("news").unwrap();
+        recorded.sort();
+        assert_eq!(recorded, ["c", "d"]);
+
+        let outcome = IngestOutcome {
+            indexed_ids: ids(&["e"]),
+            ..IngestOutcome::default()
+        };
+        assert!(store.apply_ingest_outcome("missing", &outcome).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_create_index_reports_an_existing_row() {
+        let (store, path) = temp_store();
+        assert!(store.create_index("docs").unwrap());
+        assert!(!store.create_index("docs").unwrap());
+        assert_eq!(store.list_indices().unwrap().len(), 1);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_popular_and_trending_queries() {
+        let (store, path) = temp_store();
+        let search = |query: &str, total: usize, times: usize| {
+            for _ in 0..times {
+                store.record_search("news", query, total, None).unwrap();
+            }
+        };
+        search("storm warning", 3, 3);
+        search("storm", 2, 2);
+        search("stormy", 0, 4); // never found anything
+        search("50% off", 1, 1);
+        search("500 mb", 1, 1);
+        let queries = |pairs: Vec<(String, u64)>| -> Vec<String> {
+            pairs.into_iter().map(|(query, _)| query).collect()
+        };
+
+        let popular = store.popular_queries("news", "storm", 1, 10, |_| true).unwrap();
+        assert_eq!(popular, [("storm warning".to_string(), 3), ("storm".to_string(), 2)]);
+        let popular = store.popular_queries("news", "storm", 3, 10, |_| true).unwrap();
+        assert_eq!(queries(popular), ["storm warning"]);
+        let popular = store.popular_queries("news", "storm", 1, 1, |q| q != "storm warning");
+        assert_eq!(queries(popular.unwrap()), ["storm"]);
+        // LIKE wildcards in the prefix are matched literally
+        let popular = store.popular_queries("news", "50%", 1, 10, |_| true).unwrap();
+        assert_eq!(queries(popular), ["50% off"]);
+
+        // "storm" was searched more in the week before this one
+        let before = Utc::now() - chrono::Duration::days(8);
+        store
+            .conn
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO search_query_days (index_name, query, day, search_count)
+                 VALUES ('news', 'storm', ?1, 5)",
+                params![day(before)],
+            )
+            .unwrap();
+        let trending = store.trending_queries("news", "storm", 7, 1, 10, |_| true).unwrap();
+        let trending: Vec<(&str, u64, u64)> = trending
+            .iter()
+            .map(|t| (t.query.as_str(), t.count, t.previous_count))
+            .collect();
+        assert_eq!(trending, [("stormy", 4, 0), ("storm warning", 3, 0)]);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_related_queries_share_sessions() {
+        let (store, path) = temp_store();
+        let search = |session: Option<&str>, query: &str, total: usize| {
+            store.record_search("news", query, total, session).unwrap();
+        };
+        for session in ["a", "b", "c"] {
+            search(Some(session), "storm", 1);
+            search(Some(session), "flood", 1);
+        }
+        search(Some("a"), "storm", 1); // searched twice, counted once
+        search(Some("a"), "wind", 1);
+        search(Some("b"), "wind", 0);
+        search(Some("c"), "rain", 1);
+        search(None, "hail", 1);
+        search(None, "storm", 1);
+
+        let related = store.related_queries("news", "storm", 1, 10, |_| true).unwrap();
+        assert_eq!(
+            related,
+            [("flood".to_string(), 3), ("wind".to_string(), 2), ("rain".to_string(), 1)]
+        );
+        let related = store.related_queries("news", "storm", 2, 10, |q| q != "flood").unwrap();
+        assert_eq!(related, [("wind".to_string(), 2)]);
+        assert_eq!(store.related_queries("news", "storm", 1, 1, |_| true).unwrap().len(), 1);
+        assert!(store.related_queries("news", "hail", 1, 10, |_| true).unwrap().is_empty());
+        assert!(store.related_queries("other", "storm", 1, 10, |_| true).unwrap().is_empty());
+
+        // Queries that never found anything still trend, flagged as such
+        let trending = store.trending_queries("news", "wind", 7, 1, 10, |_| true).unwrap();
+        assert_eq!(trending.len(), 1);
+        assert!(!trending[0].zero_results);
+        search(None, "hurricane", 0);
+        let trending = store.trending_queries("news", "hurr", 7, 1, 10, |_| true).unwrap();
+        assert!(trending[0].zero_results);
+
+        // Sessions are dropped with the index
+        store.delete_index("news").unwrap();
+        assert!(store.related_queries("news", "storm", 1, 10, |_| true).unwrap().is_empty());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use crate::chunking;
+use crate::llm::ChatMessage;
+use crate::models::SummarySettings;
+
+/// Instruction for summarizing a document at ingest
+const SUMMARY_PROMPT: &str = "Summarize the document for a search results page. Write in the \
+document's language, keep names, numbers and other facts exact, and don't add anything that \
+isn't in the document. Reply with only the summary.";
+
+/// Most text sent to the LLM for one summary; the start of a document says the most
+const MAX_LLM_INPUT_CHARS: usize = 20_000;
+
+/// Text a document needs summarized: its source field, when the summary field wasn't
+/// supplied and the text is longer than `min_chars`
+pub fn text_to_summarize<'a>(
+    fields: &'a HashMap<String, serde_json::Value>,
+    settings: &SummarySettings,
+) -> Option<&'a str> {
+    if fields.contains_key(&settings.target_field) {
+        return None;
+    }
+    let text = fields.get(&settings.source_field)?.as_str()?;
+    (text.chars().count() > settings.min_chars).then_some(text)
+}
+
+/// Cut text to `max_chars` characters at a word boundary, marking the cut with an ellipsis
+fn truncate_words(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    let cut = match cut.rfind(char::is_whitespace) {
+        Some(space) if space > 0 => &cut[..space],
+        _ => cut.as_str(),
+    };
+    format!("{}…", cut.trim_end())
+}
+
+/// Words counted when scoring sentences; short words are mostly function words
+fn content_words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 3)
+        .map(str::to_lowercase)
+}
+
+/// Summary made of the text's most representative sentences, in their original order.
+/// Sentences are scored by how frequent their words are in the whole text, with a bonus
+/// for the opening sentences, and added until `max_chars` is reached.
+pub fn extractive_summary(text: &str, max_chars: usize) -> String {
+    let spans = chunking::sentence_spans(text);
+    let mut frequencies: HashMap<String, usize> = HashMap::new();
+    for word in content_words(text) {
+        *frequencies.entry(word).or_default() += 1;
+    }
+
+    let mut scored: Vec<(usize, f64)> = spans
+        .iter()
+        .enumerate()
+        .map(|(position, (start, end))| {
+            let words: Vec<String> = content_words(&text[*start..*end]).collect();
+            let score = if words.is_empty() {
+                0.0
+            } else {
+                let total: usize = words.iter().map(|word| frequencies[word]).sum();
+                total as f64 / words.len() as f64
+            };
+            // Opening sentences usually introduce the topic
+            let lead_bonus = 1.0 + 0.5 / (position + 1) as f64;
+            (position, score * lead_bonus)
+        })
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let mut chosen: Vec<usize> = Vec::new();
+    let mut length = 0;
+    for (position, _) in scored {
+        let (start, end) = spans[position];
+        let sentence_chars = text[start..end].chars().count();
+        let added = sentence_chars + usize::from(!chosen.is_empty());
+        if length + added > max_chars {
+            continue;
+        }
+        length += added;
+        chosen.push(position);
+    }
+
+    if chosen.is_empty() {
+        // Not even one sentence fits, so shorten the first one
+        return spans
+            .first()
+            .map(|(start, end)| truncate_words(&text[*start..*end], max_chars))
+            .unwrap_or_default();
+    }
+    chosen.sort_unstable();
+    chosen
+        .into_iter()
+        .map(|position| &text[spans[position].0..spans[position].1])
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Messages asking the model to summarize a document in about `max_chars` characters
+pub fn summary_messages(text: &str, max_chars: usize) -> Vec<ChatMessage> {
+    let text: String = text.chars().take(MAX_LLM_INPUT_CHARS).collect();
+    vec![
+        ChatMessage {
+            role: "system".to_string(),
+            content: SUMMARY_PROMPT.to_string(),
+        },
+        ChatMessage {
+            role: "user".to_string(),
+            content: format!(
+                "Use at most {} characters.\n\nDocument:\n{}",
+                max_chars, text
+            ),
+        },
+    ]
+}
+
+/// Summary from the model's reply, cut to `max_chars`. Returns `None` for an empty reply.
+pub fn parse_summary(reply: &str, max_chars: usize) -> Option<String> {
+    let summary = reply.trim().trim_matches('"').trim();
+    let summary = summary.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!summary.is_empty()).then(|| truncate_words(&summary, max_chars))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extractive_summary() {
+        let text = "Solsikken barnehage ligger i Bergen sentrum. Barnehagen har tre \
+                    avdelinger. Været var fint i går. Barnehagen har åpent fra 7 til 17, \
+                    og barnehagen holder stengt i juli.";
+
+        let summary = extractive_summary(text, 120);
+        assert_eq!(
+            summary,
+            "Barnehagen har tre avdelinger. \
+             Barnehagen har åpent fra 7 til 17, og barnehagen holder stengt i juli."
+        );
+        assert!(summary.chars().count() <= 120);
+
+        assert_eq!(
+            extractive_summary("Ett veldig langt ord etter et annet", 15),
+            "Ett veldig…"
+        );
+        assert_eq!(
+            parse_summary("  \"Kort  sammendrag.\"\n", 100).unwrap(),
+            "Kort sammendrag."
+        );
+        assert!(parse_summary("  ", 100).is_none());
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::HashSet;
+
+use crate::llm::ChatMessage;
+use crate::models::{SynonymGroup, SynonymSuggestions};
+
+/// Instruction for proposing synonyms and stopwords from an index's vocabulary
+const SUGGEST_PROMPT: &str = "You help curate a search index. Given the most common words in \
+the indexed documents and searches that found nothing, propose synonym groups that would let \
+those searches and other likely variants (abbreviations, spelling variants, plural and \
+singular forms, everyday and formal terms) match the documents, and stopwords: words that \
+carry no meaning for search in the documents' language. Each synonym group must include at \
+least one of the index words. Don't repeat existing synonym groups. Reply with only a JSON \
+object: {\"synonyms\": [[\"word\", \"synonym\"]], \"stopwords\": [\"word\"]}.";
+
+/// Messages asking the model for synonym and stopword suggestions
+pub fn suggestion_messages(
+    terms: &[(String, u64)],
+    zero_result_queries: &[(String, u64)],
+    existing: &[SynonymGroup],
+) -> Vec<ChatMessage> {
+    let list = |items: &[(String, u64)]| -> String {
+        if items.is_empty() {
+            return "(none)".to_string();
+        }
+        items
+            .iter()
+            .map(|(text, count)| format!("{} ({})", text, count))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let existing = if existing.is_empty() {
+        "(none)".to_string()
+    } else {
+        existing
+            .iter()
+            .map(|group| group.terms.join(", "))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    vec![
+        ChatMessage {
+            role: "system".to_string(),
+            content: SUGGEST_PROMPT.to_string(),
+        },
+        ChatMessage {
+            role: "user".to_string(),
+            content: format!(
+                "Index words (documents containing them):\n{}\n\n\
+                 Searches without results (times searched):\n{}\n\n\
+                 Existing synonym groups:\n{}",
+                list(terms),
+                list(zero_result_queries),
+                existing
+            ),
+        },
+    ]
+}
+
+/// Read the model's reply. Terms are lowercased and deduplicated, groups need at least two
+/// terms, and groups already covered by an existing group are left out. Returns `None` when
+/// the reply isn't usable.
+pub fn parse_suggestions(reply: &str, existing: &[SynonymGroup]) -> Option<SynonymSuggestions> {
+    #[derive(serde::Deserialize)]
+    struct Reply {
+        #[serde(default)]
+        synonyms: Vec<Vec<String>>,
+        #[serde(default)]
+        stopwords: Vec<String>,
+    }
+
+    // Models often wrap the JSON in a code fence or a sentence
+    let start = reply.find('{')?;
+    let end = reply.rfind('}')?;
+    let reply: Reply = serde_json::from_str(reply.get(start..=end)?).ok()?;
+
+    let normalize = |term: &str| {
+        term.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    };
+    let known: Vec<HashSet<String>> = existing
+        .iter()
+        .map(|group| group.terms.iter().map(|term| normalize(term)).collect())
+        .collect();
+
+    let mut suggestions = SynonymSuggestions::default();
+    let mut seen: Vec<HashSet<String>> = Vec::new();
+    for group in reply.synonyms {
+        let mut terms: Vec<String> = Vec::new();
+        for term in group.iter().map(|term| normalize(term)) {
+            if !term.is_empty() && !terms.contains(&term) {
+                terms.push(term);
+            }
+        }
+        let set: HashSet<String> = terms.iter().cloned().collect();
+        // Groups whose terms are all in one existing group add nothing
+        if terms.len() < 2 || known.iter().chain(&seen).any(|k| set.is_subset(k)) {
+            continue;
+        }
+        seen.push(set);
+        suggestions.synonyms.push(SynonymGroup { terms });
+    }
+
+    for word in reply.stopwords.iter().map(|word| normalize(word)) {
+        if !word.is_empty() && !word.contains(' ') && !suggestions.stopwords.contains(&word) {
+            suggestions.stopwords.push(word);
+        }
+    }
+
+    Some(suggestions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_suggestions() {
+        let existing = vec![SynonymGroup {
+            terms: vec!["barnehage".to_string(), "bhg".to_string()],
+        }];
+        let reply = "Here you go:\n```json\n{\"synonyms\": [[\"Barnehage\", \"bhg\"], \
+                     [\"SFO\", \"skolefritidsordning\", \"sfo\"], [\"alone\"]], \
+                     \"stopwords\": [\"og\", \"i\", \"og\", \"to words\"]}\n```";
+
+        let suggestions = parse_suggestions(reply, &existing).unwrap();
+        let groups: Vec<Vec<String>> = suggestions.synonyms.into_iter().map(|g| g.terms).collect();
+        assert_eq!(
+            groups,
+            vec![vec!["sfo".to_string(), "skolefritidsordning".to_string()]]
+        );
+        assert_eq!(suggestions.stopwords, vec!["og", "i"]);
+
+        assert!(parse_suggestions("I can't help with that.", &existing).is_none());
+    }
+}
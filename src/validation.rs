@@ -3,7 +3,12 @@ use axum::Json;
 use once_cell::sync::Lazy;
 use regex::Regex;
 
-use crate::models::ApiResponse;
+use crate::models::{
+    ApiResponse, ChunkingOptions, ContextOptions, ExportRequest, FieldConfig, IndexSettings,
+    LlmSettings, QuerySuggestRequest, RegexSearch, RerankOptions, SuggestSynonymsRequest,
+    SummarySettings,
+};
+use crate::{index_patterns, query_syntax, sharding};
 
 /// Maximum length for index names
 pub const MAX_INDEX_NAME_LENGTH: usize = 64;
@@ -14,17 +19,40 @@ pub const MAX_DOCUMENTS_PER_REQUEST: usize = 1000;
 /// Maximum number of bulk operations in a single request
 pub const MAX_BULK_OPERATIONS: usize = 1000;
 
+/// Maximum number of warmup queries per index
+pub const MAX_WARMUP_QUERIES: usize = 20;
+
+/// Maximum number of stopwords in index settings
+pub const MAX_STOPWORDS: usize = 1000;
+
 /// Maximum pagination limit
 pub const MAX_PAGINATION_LIMIT: usize = 1000;
 
+/// Maximum number of terms listed per request from an index's term dictionary
+pub const MAX_TERMS_LIMIT: usize = 1000;
+
+/// Maximum result offset; deeper pages are rejected
+pub const MAX_RESULT_OFFSET: usize = 10_000;
+
 /// Default request body size limit (10MB)
 pub const MAX_REQUEST_BODY_SIZE: usize = 10 * 1024 * 1024;
 
+/// Body size limit for search, answer and suggest requests (1MB)
+pub const MAX_SEARCH_BODY_SIZE: usize = 1024 * 1024;
+
+/// Body size limit for document imports, uploads and bulk requests (100MB)
+pub const MAX_IMPORT_BODY_SIZE: usize = 100 * 1024 * 1024;
+
 /// Regex pattern for valid index names: alphanumeric, underscore, hyphen
 static INDEX_NAME_PATTERN: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r"^[a-zA-Z][a-zA-Z0-9_-]*$").expect("Invalid regex pattern")
 });
 
+/// Regex pattern for one part of an index pattern: an index name that can contain `*`
+static INDEX_PATTERN_PART: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^[a-zA-Z*][a-zA-Z0-9_*-]*$").expect("Invalid regex pattern")
+});
+
 /// Validates an index name for security and consistency
 pub fn validate_index_name(name: &str) -> Result<(), (StatusCode, Json<ApiResponse<()>>)> {
     if name.is_empty() {
@@ -64,6 +92,28 @@ pub fn validate_index_name(name: &str) -> Result<(), (StatusCode, Json<ApiRespon
     Ok(())
 }
 
+/// Validates the index name of a search, which can also be a pattern (`logs-2024-*`) or a
+/// comma-separated list of names and patterns
+pub fn validate_index_pattern(pattern: &str) -> Result<(), (StatusCode, Json<ApiResponse<()>>)> {
+    if !index_patterns::is_pattern(pattern) {
+        return validate_index_name(pattern);
+    }
+
+    for part in pattern.split(',').map(str::trim) {
+        if part.len() > MAX_INDEX_NAME_LENGTH || !INDEX_PATTERN_PART.is_match(part) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::error(format!(
+                    "Invalid index pattern '{}': each comma-separated part must be an index name that can contain '*'",
+                    part
+                ))),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
 /// Validates document count in a request
 pub fn validate_document_count(count: usize) -> Result<(), (StatusCode, Json<ApiResponse<()>>)> {
     if count > MAX_DOCUMENTS_PER_REQUEST {
@@ -78,6 +128,20 @@ pub fn validate_document_count(count: usize) -> Result<(), (StatusCode, Json<Api
     Ok(())
 }
 
+/// Validates the number of shards of a new index
+pub fn validate_shard_count(shards: usize) -> Result<(), (StatusCode, Json<ApiResponse<()>>)> {
+    if shards == 0 || shards > sharding::MAX_SHARDS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(format!(
+                "shards must be between 1 and {}",
+                sharding::MAX_SHARDS
+            ))),
+        ));
+    }
+    Ok(())
+}
+
 /// Validates bulk operation count
 pub fn validate_bulk_operation_count(count: usize) -> Result<(), (StatusCode, Json<ApiResponse<()>>)> {
     if count > MAX_BULK_OPERATIONS {
@@ -92,9 +156,406 @@ pub fn validate_bulk_operation_count(count: usize) -> Result<(), (StatusCode, Js
     Ok(())
 }
 
+/// Validates answer chunking options
+pub fn validate_chunking(options: &ChunkingOptions) -> Result<(), (StatusCode, Json<ApiResponse<()>>)> {
+    let error = if options.chunk_size == 0 {
+        "chunk_size must be greater than 0"
+    } else if options.chunk_overlap >= options.chunk_size {
+        "chunk_overlap must be smaller than chunk_size"
+    } else if options.passages_per_document == 0 {
+        "passages_per_document must be greater than 0"
+    } else {
+        return Ok(());
+    };
+    Err((StatusCode::BAD_REQUEST, Json(ApiResponse::error(error.to_string()))))
+}
+
+/// Validates answer context options against the fields of the index
+pub fn validate_context(
+    options: &ContextOptions,
+    index_fields: &[FieldConfig],
+) -> Result<(), (StatusCode, Json<ApiResponse<()>>)> {
+    let unknown = options.fields.iter().enumerate().find(|(_, name)| {
+        name.as_str() != "id" && !index_fields.iter().any(|field| &field.name == *name)
+    });
+    let error = if let Some((i, name)) = unknown {
+        format!("context.fields[{}]: unknown field '{}'", i, name)
+    } else if options.max_source_chars == Some(0) {
+        "context.max_source_chars must be greater than 0".to_string()
+    } else if options.max_context_chars == Some(0) {
+        "context.max_context_chars must be greater than 0".to_string()
+    } else {
+        return Ok(());
+    };
+    Err((StatusCode::BAD_REQUEST, Json(ApiResponse::error(error))))
+}
+
+/// Validates summary settings: the source must be a stored text field, and the summary
+/// field a different text field (it is added to the schema when missing)
+pub fn validate_summary(
+    settings: &SummarySettings,
+    index_fields: &[FieldConfig],
+) -> Result<(), (StatusCode, Json<ApiResponse<()>>)> {
+    let field = |name: &str| index_fields.iter().find(|field| field.name == name);
+    let error = match (field(&settings.source_field), field(&settings.target_field)) {
+        (None, _) => format!("summary.source_field: unknown field '{}'", settings.source_field),
+        (Some(source), _) if source.field_type != "text" || !source.stored => format!(
+            "summary.source_field: '{}' must be a stored text field",
+            settings.source_field
+        ),
+        (_, Some(target)) if target.field_type != "text" || !target.stored => format!(
+            "summary.target_field: '{}' must be a stored text field",
+            settings.target_field
+        ),
+        _ if settings.source_field == settings.target_field => {
+            "summary.target_field must differ from summary.source_field".to_string()
+        }
+        _ if settings.max_chars == 0 => "summary.max_chars must be greater than 0".to_string(),
+        _ => return Ok(()),
+    };
+    Err((StatusCode::BAD_REQUEST, Json(ApiResponse::error(error))))
+}
+
+/// Validates the field lists, search limits and commit policy of index settings
+pub fn validate_index_settings(
+    settings: &IndexSettings,
+    index_fields: &[FieldConfig],
+) -> Result<(), (StatusCode, Json<ApiResponse<()>>)> {
+    for name in &settings.searchable_fields {
+        let error = match index_fields.iter().find(|field| &field.name == name) {
+            None => format!("searchable_fields: unknown field '{}'", name),
+            Some(field)
+                if !matches!(field.field_type.as_str(), "text" | "string") || !field.indexed =>
+            {
+                format!("searchable_fields: '{}' must be an indexed text or string field", name)
+            }
+            Some(_) => continue,
+        };
+        return Err((StatusCode::BAD_REQUEST, Json(ApiResponse::error(error))));
+    }
+    for name in &settings.displayed_fields {
+        let error = match index_fields.iter().find(|field| &field.name == name) {
+            None => format!("displayed_fields: unknown field '{}'", name),
+            Some(field) if !field.stored => {
+                format!("displayed_fields: '{}' must be a stored field", name)
+            }
+            Some(_) => continue,
+        };
+        return Err((StatusCode::BAD_REQUEST, Json(ApiResponse::error(error))));
+    }
+    for (name, scope) in &settings.restricted_fields {
+        let error = if !index_fields.iter().any(|field| &field.name == name) {
+            format!("restricted_fields: unknown field '{}'", name)
+        } else if scope.trim().is_empty() {
+            format!("restricted_fields.{}: scope must not be empty", name)
+        } else {
+            continue;
+        };
+        return Err((StatusCode::BAD_REQUEST, Json(ApiResponse::error(error))));
+    }
+    let limits = [
+        ("max_result_window", settings.max_result_window),
+        ("max_aggregation_buckets", settings.max_aggregation_buckets),
+        ("max_highlight_fields", settings.max_highlight_fields),
+    ];
+    if let Some((name, _)) = limits.iter().find(|(_, limit)| *limit == Some(0)) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(format!("{} must be greater than 0", name))),
+        ));
+    }
+    if settings.commit_policy.max_documents == Some(0) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(
+                "commit_policy.max_documents must be greater than 0".to_string(),
+            )),
+        ));
+    }
+    if settings.warmup_queries.len() > MAX_WARMUP_QUERIES {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(format!(
+                "warmup_queries: at most {} queries are allowed",
+                MAX_WARMUP_QUERIES
+            ))),
+        ));
+    }
+    if let Some(i) = settings.warmup_queries.iter().position(|w| w.query.trim().is_empty()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(format!("warmup_queries[{}].query must not be empty", i))),
+        ));
+    }
+    if settings.stopwords.len() > MAX_STOPWORDS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(format!(
+                "stopwords: at most {} words are allowed",
+                MAX_STOPWORDS
+            ))),
+        ));
+    }
+    if let Some(i) = settings
+        .stopwords
+        .iter()
+        .position(|word| !word.trim().chars().all(char::is_alphanumeric) || word.trim().is_empty())
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(format!("stopwords[{}] must be a single word", i))),
+        ));
+    }
+    if let Some(name) = &settings.parent_field {
+        let error = match index_fields.iter().find(|field| &field.name == name) {
+            None => format!("parent_field: unknown field '{}'", name),
+            Some(field) if field.field_type != "string" || !field.indexed => {
+                format!("parent_field: '{}' must be an indexed string field", name)
+            }
+            Some(_) => return Ok(()),
+        };
+        return Err((StatusCode::BAD_REQUEST, Json(ApiResponse::error(error))));
+    }
+    Ok(())
+}
+
+/// Validates LLM options, from index settings or an answer request
+pub fn validate_llm_settings(
+    settings: &LlmSettings,
+) -> Result<(), (StatusCode, Json<ApiResponse<()>>)> {
+    let error = if settings.model.as_deref().is_some_and(|m| m.trim().is_empty()) {
+        "model must not be empty"
+    } else if settings.temperature.is_some_and(|t| !(0.0..=2.0).contains(&t)) {
+        "temperature must be between 0 and 2"
+    } else if settings.max_tokens == Some(0) {
+        "max_tokens must be greater than 0"
+    } else {
+        return Ok(());
+    };
+    Err((StatusCode::BAD_REQUEST, Json(ApiResponse::error(error.to_string()))))
+}
+
+/// Maximum number of hits sent to the reranker in one request
+pub const MAX_RERANK_TOP_K: usize = 50;
+
+/// Validates rerank options
+pub fn validate_rerank(options: &RerankOptions) -> Result<(), (StatusCode, Json<ApiResponse<()>>)> {
+    if options.top_k == 0 || options.top_k > MAX_RERANK_TOP_K {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(format!(
+                "rerank.top_k must be between 1 and {}",
+                MAX_RERANK_TOP_K
+            ))),
+        ));
+    }
+    Ok(())
+}
+
+/// Longest regex pattern of a search
+pub const MAX_REGEX_PATTERN_LENGTH: usize = 256;
+
+/// Longest time a search may scan index terms for regex matches
+pub const MAX_REGEX_TIMEOUT_MS: u64 = 1000;
+
+/// Validates the pattern and timeout of a regex search
+pub fn validate_regex(regex: &RegexSearch) -> Result<(), (StatusCode, Json<ApiResponse<()>>)> {
+    let error = if regex.pattern.is_empty() {
+        "regex.pattern cannot be empty".to_string()
+    } else if regex.pattern.chars().count() > MAX_REGEX_PATTERN_LENGTH {
+        format!("regex.pattern is longer than {} characters", MAX_REGEX_PATTERN_LENGTH)
+    } else if regex.timeout_ms == 0 || regex.timeout_ms > MAX_REGEX_TIMEOUT_MS {
+        format!("regex.timeout_ms must be between 1 and {}", MAX_REGEX_TIMEOUT_MS)
+    } else if let Err(e) = query_syntax::term_regex(&regex.pattern) {
+        match e {
+            regex::Error::CompiledTooBig(_) => "regex.pattern is too complex".to_string(),
+            // The last line of a syntax error names the problem, after a drawing of where
+            // it is in the wrapped pattern
+            e => {
+                let message = e.to_string();
+                let reason = message.lines().last().unwrap_or_default();
+                format!("regex.pattern is invalid: {}", reason.trim_start_matches("error: "))
+            }
+        }
+    } else {
+        return Ok(());
+    };
+    Err((StatusCode::BAD_REQUEST, Json(ApiResponse::error(error))))
+}
+
+/// Longest time an export snapshot is kept between two pages
+pub const MAX_EXPORT_KEEP_ALIVE_SECS: u64 = 3600;
+
+/// Validates the page size and keep-alive of an export
+pub fn validate_export(request: &ExportRequest) -> Result<(), (StatusCode, Json<ApiResponse<()>>)> {
+    let error = if request.size == 0 || request.size > MAX_DOCUMENTS_PER_REQUEST {
+        format!("size must be between 1 and {}", MAX_DOCUMENTS_PER_REQUEST)
+    } else if request.keep_alive_secs == 0 || request.keep_alive_secs > MAX_EXPORT_KEEP_ALIVE_SECS
+    {
+        format!("keep_alive_secs must be between 1 and {}", MAX_EXPORT_KEEP_ALIVE_SECS)
+    } else {
+        return Ok(());
+    };
+    Err((StatusCode::BAD_REQUEST, Json(ApiResponse::error(error))))
+}
+
+/// Maximum number of index terms sampled for synonym suggestions
+pub const MAX_SUGGEST_SAMPLE_TERMS: usize = 1000;
+
+/// Maximum number of zero-result queries sampled for synonym suggestions
+pub const MAX_SUGGEST_SAMPLE_QUERIES: usize = 500;
+
+/// Validates synonym suggestion sample sizes
+pub fn validate_synonym_suggestions(
+    request: &SuggestSynonymsRequest,
+) -> Result<(), (StatusCode, Json<ApiResponse<()>>)> {
+    let error = if request.sample_terms > MAX_SUGGEST_SAMPLE_TERMS {
+        format!("sample_terms must be at most {}", MAX_SUGGEST_SAMPLE_TERMS)
+    } else if request.sample_queries > MAX_SUGGEST_SAMPLE_QUERIES {
+        format!("sample_queries must be at most {}", MAX_SUGGEST_SAMPLE_QUERIES)
+    } else if request.sample_terms == 0 && request.sample_queries == 0 {
+        "sample_terms or sample_queries must be greater than 0".to_string()
+    } else {
+        return Ok(());
+    };
+    Err((StatusCode::BAD_REQUEST, Json(ApiResponse::error(error))))
+}
+
+/// Most queries of each kind returned by query suggestions
+pub const MAX_QUERY_SUGGESTIONS: usize = 50;
+
+/// Longest prefix query suggestions are looked up for
+const MAX_QUERY_SUGGEST_PREFIX: usize = 200;
+
+/// Validates the size and trending window of query suggestions
+pub fn validate_query_suggestions(
+    request: &QuerySuggestRequest,
+) -> Result<(), (StatusCode, Json<ApiResponse<()>>)> {
+    validate_trending_window(request.window_days)?;
+    let error = if request.limit == 0 || request.limit > MAX_QUERY_SUGGESTIONS {
+        format!("limit must be between 1 and {}", MAX_QUERY_SUGGESTIONS)
+    } else if request.prefix.chars().count() > MAX_QUERY_SUGGEST_PREFIX {
+        format!("prefix must be at most {} characters", MAX_QUERY_SUGGEST_PREFIX)
+    } else {
+        return Ok(());
+    };
+    Err((StatusCode::BAD_REQUEST, Json(ApiResponse::error(error))))
+}
+
+/// Validates the days of recent searches trending queries are picked from
+pub fn validate_trending_window(
+    window_days: u32,
+) -> Result<(), (StatusCode, Json<ApiResponse<()>>)> {
+    // Trending compares the window with as many days before it
+    let max_window_days = (crate::storage::QUERY_DAYS_KEPT / 2) as u32;
+    if (1..=max_window_days).contains(&window_days) {
+        return Ok(());
+    }
+    let error = format!("window_days must be between 1 and {}", max_window_days);
+    Err((StatusCode::BAD_REQUEST, Json(ApiResponse::error(error))))
+}
+
+/// Pagination ceilings for the deployment, which an index can lower in its settings
+#[derive(Debug, Clone, Copy)]
+pub struct PaginationLimits {
+    pub max_limit: usize,
+    pub max_offset: usize,
+}
+
+impl PaginationLimits {
+    /// Apply an index's `max_limit`/`max_offset` settings
+    pub fn for_index(self, settings: &IndexSettings) -> Self {
+        Self {
+            max_limit: settings.max_limit.map_or(self.max_limit, |max| max.min(self.max_limit)),
+            max_offset: settings
+                .max_offset
+                .map_or(self.max_offset, |max| max.min(self.max_offset)),
+        }
+    }
+}
+
+/// Pagination ceilings from `MAX_PAGINATION_LIMIT` and `MAX_RESULT_OFFSET`
+pub fn pagination_limits_from_env() -> PaginationLimits {
+    let var = |name: &str, default: usize| {
+        std::env::var(name)
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(default)
+    };
+    PaginationLimits {
+        max_limit: var("MAX_PAGINATION_LIMIT", MAX_PAGINATION_LIMIT),
+        max_offset: var("MAX_RESULT_OFFSET", MAX_RESULT_OFFSET),
+    }
+}
+
+/// Request body size limits per route group
+#[derive(Debug, Clone, Copy)]
+pub struct BodyLimits {
+    /// Search, answer and suggest
+    pub search: usize,
+    /// Adding, uploading and bulk-writing documents
+    pub import: usize,
+    /// Every other route
+    pub default: usize,
+}
+
+/// Body size limits from `MAX_SEARCH_BODY_SIZE`, `MAX_IMPORT_BODY_SIZE` and
+/// `MAX_REQUEST_BODY_SIZE`
+pub fn body_limits_from_env() -> BodyLimits {
+    let var = |name: &str, default: usize| {
+        let Ok(value) = std::env::var(name) else {
+            return default;
+        };
+        parse_byte_size(&value).unwrap_or_else(|| {
+            tracing::warn!("Invalid {}: {}, using {} bytes", name, value, default);
+            default
+        })
+    };
+    BodyLimits {
+        search: var("MAX_SEARCH_BODY_SIZE", MAX_SEARCH_BODY_SIZE),
+        import: var("MAX_IMPORT_BODY_SIZE", MAX_IMPORT_BODY_SIZE),
+        default: var("MAX_REQUEST_BODY_SIZE", MAX_REQUEST_BODY_SIZE),
+    }
+}
+
+/// Parse a size in bytes, optionally with a `KB`, `MB` or `GB` suffix (powers of 1024)
+pub fn parse_byte_size(value: &str) -> Option<usize> {
+    let value = value.trim().to_ascii_uppercase();
+    let (number, multiplier) = [("GB", 1 << 30), ("MB", 1 << 20), ("KB", 1 << 10), ("B", 1)]
+        .into_iter()
+        .find_map(|(suffix, multiplier)| {
+            value.strip_suffix(suffix).map(|number| (number.trim(), multiplier))
+        })
+        .unwrap_or((value.as_str(), 1));
+    number
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .filter(|n| *n > 0)
+}
+
 /// Clamps pagination limit to maximum allowed value
-pub fn clamp_pagination_limit(limit: usize) -> usize {
-    limit.min(MAX_PAGINATION_LIMIT)
+pub fn clamp_pagination_limit(limit: usize, limits: &PaginationLimits) -> usize {
+    limit.min(limits.max_limit)
+}
+
+/// Rejects offsets past the maximum, since every skipped hit still has to be collected
+pub fn validate_offset(
+    offset: usize,
+    limits: &PaginationLimits,
+) -> Result<(), (StatusCode, Json<ApiResponse<()>>)> {
+    if offset > limits.max_offset {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(format!(
+                "Offset {} exceeds the maximum of {}. To page deeper, sort on a fast field \
+                 and filter on values past the last hit (search_after-style) instead of \
+                 raising the offset",
+                offset, limits.max_offset
+            ))),
+        ));
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -121,4 +582,167 @@ mod tests {
         assert!(validate_index_name("my/index").is_err()); // contains slash
         assert!(validate_index_name("my\\index").is_err()); // contains backslash
     }
+
+    #[test]
+    fn test_index_patterns() {
+        assert!(validate_index_pattern("logs-2024-*").is_ok());
+        assert!(validate_index_pattern("logs, metrics-*").is_ok());
+        assert!(validate_index_pattern("*").is_ok());
+        assert!(validate_index_pattern("logs,").is_err()); // empty part
+        assert!(validate_index_pattern("logs-*,../etc").is_err()); // path traversal
+        assert!(validate_index_pattern("1logs").is_err()); // plain names are index names
+    }
+
+    #[test]
+    fn test_parse_byte_size() {
+        assert_eq!(parse_byte_size("1048576"), Some(1024 * 1024));
+        assert_eq!(parse_byte_size("100MB"), Some(100 * 1024 * 1024));
+        assert_eq!(parse_byte_size(" 512 kb "), Some(512 * 1024));
+        assert_eq!(parse_byte_size("1GB"), Some(1024 * 1024 * 1024));
+        assert_eq!(parse_byte_size("0"), None);
+        assert_eq!(parse_byte_size("lots"), None);
+    }
+
+    #[test]
+    fn test_llm_settings() {
+        let settings = |json| serde_json::from_value::<LlmSettings>(json).unwrap();
+        let index = settings(serde_json::json!({
+            "provider": "local", "model": "small", "temperature": 0.2, "max_tokens": 300
+        }));
+        assert!(validate_llm_settings(&index).is_ok());
+        assert!(validate_llm_settings(&settings(serde_json::json!({"model": " "}))).is_err());
+        assert!(validate_llm_settings(&settings(serde_json::json!({"temperature": 2.5}))).is_err());
+        assert!(validate_llm_settings(&settings(serde_json::json!({"max_tokens": 0}))).is_err());
+
+        // Requests override the index's options; a model only carries over while the
+        // provider stays the same
+        let merged = index.merged(&settings(serde_json::json!({"temperature": 0.7})));
+        assert_eq!(merged.provider.as_deref(), Some("local"));
+        assert_eq!(merged.model.as_deref(), Some("small"));
+        assert_eq!((merged.temperature, merged.max_tokens), (Some(0.7), Some(300)));
+        let merged = index.merged(&settings(serde_json::json!({"provider": "mistral"})));
+        assert_eq!(merged.provider.as_deref(), Some("mistral"));
+        assert_eq!(merged.model, None);
+    }
+
+    #[test]
+    fn test_index_settings() {
+        let fields: Vec<FieldConfig> = serde_json::from_value(serde_json::json!([
+            {"name": "title", "indexed": true},
+            {"name": "price", "field_type": "f64", "indexed": true},
+            {"name": "notes", "indexed": false},
+        ]))
+        .unwrap();
+        let settings = |json: serde_json::Value| -> IndexSettings {
+            serde_json::from_value(json).unwrap()
+        };
+
+        let valid = settings(serde_json::json!({
+            "searchable_fields": ["title"],
+            "commit_policy": {"max_documents": 100},
+            "read_only": true,
+        }));
+        assert!(validate_index_settings(&valid, &fields).is_ok());
+        for invalid in [
+            serde_json::json!({"searchable_fields": ["missing"]}),
+            serde_json::json!({"searchable_fields": ["price"]}), // not text
+            serde_json::json!({"searchable_fields": ["notes"]}), // not indexed
+            serde_json::json!({"commit_policy": {"max_documents": 0}}),
+        ] {
+            let result = validate_index_settings(&settings(invalid.clone()), &fields);
+            assert!(result.is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_displayed_fields_must_be_stored() {
+        let fields: Vec<FieldConfig> = serde_json::from_value(serde_json::json!([
+            {"name": "title", "stored": true},
+            {"name": "body", "stored": false},
+        ]))
+        .unwrap();
+        let settings = |displayed: serde_json::Value| -> IndexSettings {
+            serde_json::from_value(serde_json::json!({"displayed_fields": displayed})).unwrap()
+        };
+
+        assert!(validate_index_settings(&settings(serde_json::json!(["title"])), &fields).is_ok());
+        assert!(validate_index_settings(&settings(serde_json::json!(["body"])), &fields).is_err());
+        assert!(validate_index_settings(&settings(serde_json::json!(["tags"])), &fields).is_err());
+    }
+
+    #[test]
+    fn test_warmup_queries() {
+        let settings = |queries: Vec<serde_json::Value>| -> IndexSettings {
+            serde_json::from_value(serde_json::json!({"warmup_queries": queries})).unwrap()
+        };
+        let query = serde_json::json!({"query": "storm"});
+
+        let valid = settings(vec![query.clone(); MAX_WARMUP_QUERIES]);
+        assert!(validate_index_settings(&valid, &[]).is_ok());
+        let too_many = settings(vec![query.clone(); MAX_WARMUP_QUERIES + 1]);
+        assert!(validate_index_settings(&too_many, &[]).is_err());
+        let empty = settings(vec![query, serde_json::json!({"query": " "})]);
+        assert!(validate_index_settings(&empty, &[]).is_err());
+    }
+
+    #[test]
+    fn test_search_limits_must_be_positive() {
+        for name in ["max_result_window", "max_aggregation_buckets", "max_highlight_fields"] {
+            let settings = |limit: usize| -> IndexSettings {
+                serde_json::from_value(serde_json::json!({name: limit})).unwrap()
+            };
+            assert!(validate_index_settings(&settings(1), &[]).is_ok(), "{name}");
+            assert!(validate_index_settings(&settings(0), &[]).is_err(), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_export() {
+        let request = |size: usize, keep_alive_secs: u64| -> ExportRequest {
+            serde_json::from_value(
+                serde_json::json!({"size": size, "keep_alive_secs": keep_alive_secs}),
+            )
+            .unwrap()
+        };
+        assert!(validate_export(&request(MAX_DOCUMENTS_PER_REQUEST, 60)).is_ok());
+        assert!(validate_export(&request(0, 60)).is_err());
+        assert!(validate_export(&request(MAX_DOCUMENTS_PER_REQUEST + 1, 60)).is_err());
+        assert!(validate_export(&request(100, 0)).is_err());
+        assert!(validate_export(&request(100, MAX_EXPORT_KEEP_ALIVE_SECS + 1)).is_err());
+    }
+
+    #[test]
+    fn test_parent_field_must_be_an_indexed_string() {
+        let fields: Vec<FieldConfig> = serde_json::from_value(serde_json::json!([
+            {"name": "parent", "field_type": "string", "indexed": true},
+            {"name": "title", "indexed": true},
+            {"name": "stored_only", "field_type": "string", "stored": true},
+        ]))
+        .unwrap();
+        let settings = |name: &str| -> IndexSettings {
+            serde_json::from_value(serde_json::json!({"parent_field": name})).unwrap()
+        };
+
+        assert!(validate_index_settings(&settings("parent"), &fields).is_ok());
+        for name in ["title", "stored_only", "missing"] {
+            assert!(validate_index_settings(&settings(name), &fields).is_err(), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_query_suggestions() {
+        let request = |json: serde_json::Value| -> QuerySuggestRequest {
+            serde_json::from_value(json).unwrap()
+        };
+        assert!(validate_query_suggestions(&request(serde_json::json!({}))).is_ok());
+        for invalid in [
+            serde_json::json!({"limit": 0}),
+            serde_json::json!({"limit": MAX_QUERY_SUGGESTIONS + 1}),
+            serde_json::json!({"window_days": 0}),
+            serde_json::json!({"window_days": crate::storage::QUERY_DAYS_KEPT / 2 + 1}),
+            serde_json::json!({"prefix": "a".repeat(MAX_QUERY_SUGGEST_PREFIX + 1)}),
+        ] {
+            assert!(validate_query_suggestions(&request(invalid.clone())).is_err(), "{invalid}");
+        }
+    }
 }
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+
+use crate::field_access::Access;
+use crate::models::{WarmupQuery, WarmupReport};
+use crate::search::SearchEngine;
+use crate::AppState;
+
+/// How often open indices are checked for commits that haven't been warmed yet
+const WARMUP_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Hits fetched by a warmup query; enough to load the stored documents of a first page
+const WARMUP_LIMIT: usize = 10;
+
+/// What each index was last warmed for, and whether the startup pass is done
+#[derive(Default)]
+pub struct Warmup {
+    /// Index -> commit opstamp and warmup queries it was last warmed with, and the report
+    warmed: Mutex<HashMap<String, (String, WarmupReport)>>,
+    startup_finished: AtomicBool,
+}
+
+impl Warmup {
+    /// Whether the indices loaded at startup have been warmed
+    pub fn is_startup_finished(&self) -> bool {
+        self.startup_finished.load(Ordering::Relaxed)
+    }
+
+    pub fn last_report(&self, index_name: &str) -> Option<WarmupReport> {
+        let warmed = self.warmed.lock().ok()?;
+        warmed.get(index_name).map(|(_, report)| report.clone())
+    }
+
+    fn is_warm(&self, index_name: &str, version: &str) -> bool {
+        self.warmed
+            .lock()
+            .map(|warmed| warmed.get(index_name).is_some_and(|(warm, _)| warm == version))
+            .unwrap_or(false)
+    }
+
+    fn record(&self, index_name: &str, version: String, report: WarmupReport) {
+        if let Ok(mut warmed) = self.warmed.lock() {
+            warmed.insert(index_name.to_string(), (version, report));
+        }
+    }
+
+    /// Forget indices that were closed or deleted, so they are warmed when opened again
+    fn retain_open(&self, open: &[String]) {
+        if let Ok(mut warmed) = self.warmed.lock() {
+            warmed.retain(|index_name, _| open.contains(index_name));
+        }
+    }
+}
+
+/// Run an index's warmup queries against its latest commit
+fn warm_index(
+    engine: &SearchEngine,
+    index_name: &str,
+    opstamp: u64,
+    queries: &[WarmupQuery],
+) -> WarmupReport {
+    let started = Instant::now();
+    let access = Access::default();
+    let mut failed = 0;
+    for (i, warmup) in queries.iter().enumerate() {
+        let result = engine.search_with_options(
+            index_name,
+            &warmup.query,
+            WARMUP_LIMIT,
+            0,
+            &warmup.fields,
+            None,
+            &warmup.aggregations,
+            false,
+            warmup.sort.as_ref(),
+            None,
+            None,
+            None,
+            &[],
+            &warmup.filters,
+            None,
+            None,
+            None,
+            None,
+            &access,
+        );
+        if let Err(e) = result {
+            tracing::warn!("Warmup query {} on index '{}' failed: {}", i, index_name, e);
+            failed += 1;
+        }
+    }
+    WarmupReport {
+        warmed_at: Utc::now().to_rfc3339(),
+        opstamp,
+        queries: queries.len(),
+        failed,
+        took_ms: started.elapsed().as_secs_f64() * 1000.0,
+    }
+}
+
+/// Run the warmup queries of every open index when it is loaded or opened, after each
+/// commit, and when its warmup queries change
+pub async fn run_warmer(state: Arc<AppState>) {
+    let mut ticker = tokio::time::interval(WARMUP_CHECK_INTERVAL);
+
+    loop {
+        ticker.tick().await;
+
+        let open = state.search_engine.open_indices();
+        state.warmup.retain_open(&open);
+        for index_name in open {
+            let Some(settings) = state.search_engine.index_settings(&index_name) else {
+                continue;
+            };
+            if settings.warmup_queries.is_empty() {
+                continue;
+            }
+            let Some(opstamp) = state.search_engine.commit_opstamp(&index_name) else {
+                continue;
+            };
+            let queries = serde_json::to_string(&settings.warmup_queries).unwrap_or_default();
+            let version = format!("{}:{}", opstamp, queries);
+            if state.warmup.is_warm(&index_name, &version) {
+                continue;
+            }
+
+            let task_state = state.clone();
+            let name = index_name.clone();
+            let queries = settings.warmup_queries;
+            let warmed = tokio::task::spawn_blocking(move || {
+                warm_index(&task_state.search_engine, &name, opstamp, &queries)
+            })
+            .await;
+            match warmed {
+                Ok(report) => {
+                    tracing::debug!(
+                        "Warmed index '{}' with {} query(ies) in {:.0}ms",
+                        index_name,
+                        report.queries,
+                        report.took_ms
+                    );
+                    state.warmup.record(&index_name, version, report);
+                }
+                Err(e) => tracing::warn!("Warmup of index '{}' panicked: {}", index_name, e),
+            }
+        }
+
+        if !state.warmup.startup_finished.swap(true, Ordering::Relaxed) {
+            tracing::info!("Warmup of loaded indices finished");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter_cache::FilterCache;
+    use crate::models::{FieldConfig, IndexSettings};
+    use crate::search::QueryLimits;
+
+    #[test]
+    fn test_warm_index_counts_failed_queries() {
+        let path = std::env::temp_dir().join(format!("warmup-test-{}", uuid::Uuid::new_v4()));
+        let engine = SearchEngine::new(
+            path.to_str().unwrap(),
+            1,
+            QueryLimits::default(),
+            FilterCache::from_env(),
+        )
+        .unwrap();
+        let fields: Vec<FieldConfig> =
+            serde_json::from_value(serde_json::json!([{"name": "title", "indexed": true}]))
+                .unwrap();
+        engine.create_index("news", &fields, &IndexSettings::default(), 1).unwrap();
+        let queries: Vec<WarmupQuery> = serde_json::from_value(serde_json::json!([
+            {"query": "storm"},
+            {"query": "storm", "filters": ["missing"]},
+        ]))
+        .unwrap();
+
+        let report = warm_index(&engine, "news", 7, &queries);
+        assert_eq!((report.opstamp, report.queries, report.failed), (7, 2, 1));
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_indices_are_warmed_again_when_their_version_changes() {
+        let warmup = Warmup::default();
+        let report = WarmupReport {
+            warmed_at: Utc::now().to_rfc3339(),
+            opstamp: 1,
+            queries: 1,
+            failed: 0,
+            took_ms: 0.0,
+        };
+        warmup.record("news", "1:[]".to_string(), report);
+        assert!(warmup.is_warm("news", "1:[]"));
+        assert!(!warmup.is_warm("news", "2:[]"));
+
+        // A closed index is warmed again once it is opened
+        warmup.retain_open(&[]);
+        assert!(!warmup.is_warm("news", "1:[]"));
+        assert!(warmup.last_report("news").is_none());
+    }
+}
@@ -0,0 +1,443 @@
+use anyhow::{anyhow, Result};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::models::Document;
+use crate::search::SearchEngine;
+use crate::storage::MetadataStore;
+use crate::validation::MAX_DOCUMENTS_PER_REQUEST;
+use crate::AppState;
+
+/// Quiet period used to coalesce bursts of file events (e.g. editor saves)
+const DEBOUNCE_MILLIS: u64 = 250;
+
+/// A local directory whose files are mirrored into an index
+#[derive(Debug, Clone)]
+pub struct WatchTarget {
+    pub index_name: String,
+    pub root: PathBuf,
+}
+
+/// Parse WATCH_DIRECTORIES, a comma-separated list of `index_name=/path/to/dir`
+pub fn targets_from_env() -> Vec<WatchTarget> {
+    std::env::var("WATCH_DIRECTORIES")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|entry| {
+            let (index_name, path) = entry.trim().split_once('=')?;
+            let (index_name, path) = (index_name.trim(), path.trim());
+            if index_name.is_empty() || path.is_empty() {
+                tracing::warn!("Ignoring invalid WATCH_DIRECTORIES entry: '{}'", entry);
+                return None;
+            }
+            Some(WatchTarget {
+                index_name: index_name.to_string(),
+                root: PathBuf::from(path),
+            })
+        })
+        .collect()
+}
+
+/// Index every supported file under the root, then keep the index in sync with changes
+pub async fn run_watcher(state: Arc<AppState>, target: WatchTarget) {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<PathBuf>();
+
+    let on_event = move |event: notify::Result<notify::Event>| match event {
+        Ok(event) => {
+            if matches!(
+                event.kind,
+                EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+            ) {
+                for path in event.paths {
+                    let _ = tx.send(path);
+                }
+            }
+        }
+        Err(e) => tracing::warn!("File watcher error: {}", e),
+    };
+
+    let mut watcher = match notify::recommended_watcher(on_event) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            tracing::warn!(
+                "Failed to create file watcher for {}: {}",
+                target.root.display(),
+                e
+            );
+            return;
+        }
+    };
+
+    if let Err(e) = watcher.watch(&target.root, RecursiveMode::Recursive) {
+        tracing::warn!("Failed to watch {}: {}", target.root.display(), e);
+        return;
+    }
+
+    tracing::info!(
+        "Watching {} for index '{}'",
+        target.root.display(),
+        target.index_name
+    );
+
+    let (engine, store) = (&state.search_engine, &state.metadata_store);
+    let mut synced = DirectorySync::new(target.clone(), store);
+    let permit = state.request_pools.indexing.acquire().await;
+    let count = synced.sync_all(engine, store);
+    tracing::info!(
+        "Indexed {} file(s) from {} into '{}'",
+        count,
+        target.root.display(),
+        target.index_name
+    );
+    drop(permit);
+
+    while let Some(path) = rx.recv().await {
+        // Collect the rest of the burst before touching the index
+        let mut pending: HashSet<PathBuf> = HashSet::from([path]);
+        tokio::time::sleep(Duration::from_millis(DEBOUNCE_MILLIS)).await;
+        while let Ok(path) = rx.try_recv() {
+            pending.insert(path);
+        }
+
+        let _permit = state.request_pools.indexing.acquire().await;
+        for path in pending {
+            if path.is_dir() {
+                for file in collect_files(&path) {
+                    synced.sync_path(engine, store, &file);
+                }
+            } else {
+                synced.sync_path(engine, store, &path);
+            }
+        }
+    }
+}
+
+/// Tracks which document IDs came from which file so changes can be mirrored. The
+/// mapping is kept in the metadata store, so it outlives restarts.
+struct DirectorySync {
+    target: WatchTarget,
+    documents_by_file: HashMap<PathBuf, Vec<String>>,
+}
+
+impl DirectorySync {
+    fn new(target: WatchTarget, store: &MetadataStore) -> Self {
+        let documents_by_file = match store.watched_files(&target.index_name) {
+            Ok(files) => files
+                .into_iter()
+                .map(|(path, doc_ids)| (PathBuf::from(path), doc_ids))
+                .collect(),
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to load the watched files of '{}': {}",
+                    target.index_name,
+                    e
+                );
+                HashMap::new()
+            }
+        };
+        Self {
+            target,
+            documents_by_file,
+        }
+    }
+
+    /// Index every supported file under the root and remove the documents of files
+    /// indexed before that are gone, returning the number of files found
+    fn sync_all(&mut self, engine: &SearchEngine, store: &MetadataStore) -> usize {
+        let files = collect_files(&self.target.root);
+        let found: HashSet<&PathBuf> = files.iter().collect();
+        let removed: Vec<PathBuf> = self
+            .documents_by_file
+            .keys()
+            .filter(|path| !found.contains(path))
+            .cloned()
+            .collect();
+        for path in files.iter().chain(&removed) {
+            self.sync_path(engine, store, path);
+        }
+        files.len()
+    }
+
+    /// Re-index a file, or remove its documents if it no longer exists
+    fn sync_path(&mut self, engine: &SearchEngine, store: &MetadataStore, path: &Path) {
+        if file_kind(path).is_none() {
+            return;
+        }
+
+        let documents = if path.is_file() {
+            match parse_file(&self.target.root, path) {
+                Ok(documents) => documents,
+                Err(e) => {
+                    tracing::warn!("Skipping {}: {}", path.display(), e);
+                    return;
+                }
+            }
+        } else {
+            Vec::new()
+        };
+
+        if let Err(e) = self.replace_documents(engine, store, path, documents) {
+            tracing::warn!(
+                "Failed to sync {} into '{}': {}",
+                path.display(),
+                self.target.index_name,
+                e
+            );
+        }
+    }
+
+    fn replace_documents(
+        &mut self,
+        engine: &SearchEngine,
+        store: &MetadataStore,
+        path: &Path,
+        documents: Vec<Document>,
+    ) -> Result<()> {
+        let index_name = &self.target.index_name;
+
+        // Remove everything the file produced before, plus any IDs about to be re-added
+        let mut stale_ids = self.documents_by_file.remove(path).unwrap_or_default();
+        stale_ids.extend(documents.iter().map(|d| d.id.clone()));
+        if !stale_ids.is_empty() {
+            engine.delete_documents(index_name, &stale_ids)?;
+            for doc_id in &stale_ids {
+                store.delete_document(index_name, doc_id)?;
+            }
+        }
+
+        let mut indexed_ids = Vec::new();
+        for chunk in documents.chunks(MAX_DOCUMENTS_PER_REQUEST) {
+            let outcome = engine.add_documents(index_name, chunk)?;
+            store.apply_ingest_outcome(index_name, &outcome)?;
+            indexed_ids.extend(outcome.indexed_ids);
+        }
+
+        store.set_watched_file(index_name, &path.to_string_lossy(), &indexed_ids)?;
+        if !indexed_ids.is_empty() {
+            self.documents_by_file
+                .insert(path.to_path_buf(), indexed_ids);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileKind {
+    Json,
+    Ndjson,
+    Markdown,
+}
+
+fn file_kind(path: &Path) -> Option<FileKind> {
+    match path.extension()?.to_str()?.to_lowercase().as_str() {
+        "json" => Some(FileKind::Json),
+        "ndjson" | "jsonl" => Some(FileKind::Ndjson),
+        "md" | "markdown" => Some(FileKind::Markdown),
+        _ => None,
+    }
+}
+
+/// Recursively list supported files below a directory
+fn collect_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return files;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            files.extend(collect_files(&path));
+        } else if file_kind(&path).is_some() {
+            files.push(path);
+        }
+    }
+    files
+}
+
+/// Convert a file into documents.
+///
+/// JSON files hold one document or an array of documents, NDJSON files hold one
+/// document per line, and Markdown files become a single document. A JSON value
+/// without `id`/`fields` is treated as the field map itself, keyed by the file path.
+fn parse_file(root: &Path, path: &Path) -> Result<Vec<Document>> {
+    let content = std::fs::read_to_string(path)?;
+    let relative_path = path
+        .strip_prefix(root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/");
+
+    match file_kind(path) {
+        Some(FileKind::Json) => {
+            let value: serde_json::Value = serde_json::from_str(&content)?;
+            match value {
+                serde_json::Value::Array(items) => items
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, item)| json_to_document(item, &format!("{}#{}", relative_path, i)))
+                    .collect(),
+                item => Ok(vec![json_to_document(item, &relative_path)?]),
+            }
+        }
+        Some(FileKind::Ndjson) => content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                let item: serde_json::Value =
+                    serde_json::from_str(line).map_err(|e| anyhow!("line {}: {}", i + 1, e))?;
+                json_to_document(item, &format!("{}#{}", relative_path, i + 1))
+            })
+            .collect(),
+        Some(FileKind::Markdown) => {
+            let title = content
+                .lines()
+                .find_map(|line| line.strip_prefix("# "))
+                .map(|t| t.trim().to_string())
+                .or_else(|| path.file_stem().map(|s| s.to_string_lossy().to_string()))
+                .unwrap_or_else(|| relative_path.clone());
+
+            let mut fields = HashMap::new();
+            fields.insert("title".to_string(), serde_json::json!(title));
+            fields.insert("content".to_string(), serde_json::json!(content));
+            fields.insert("path".to_string(), serde_json::json!(relative_path));
+            Ok(vec![Document {
+                id: relative_path,
+                fields,
+            }])
+        }
+        None => Ok(Vec::new()),
+    }
+}
+
+fn json_to_document(value: serde_json::Value, default_id: &str) -> Result<Document> {
+    let serde_json::Value::Object(mut object) = value else {
+        return Err(anyhow!("expected a JSON object"));
+    };
+
+    if object.contains_key("fields") {
+        object
+            .entry("id")
+            .or_insert_with(|| serde_json::json!(default_id));
+        return Ok(serde_json::from_value(serde_json::Value::Object(object))?);
+    }
+
+    let id = match object.remove("id") {
+        Some(serde_json::Value::String(id)) => id,
+        Some(other) => other.to_string(),
+        None => default_id.to_string(),
+    };
+    Ok(Document {
+        id,
+        fields: object.into_iter().collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter_cache::FilterCache;
+    use crate::models::{FieldConfig, IndexSettings};
+    use crate::search::QueryLimits;
+
+    /// Fresh directory under the system temp dir
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("watcher-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_parse_files() {
+        let root = temp_dir();
+        std::fs::create_dir_all(root.join("guides")).unwrap();
+        let write = |name: &str, content: &str| {
+            let path = root.join(name);
+            std::fs::write(&path, content).unwrap();
+            path
+        };
+
+        let json = write(
+            "items.json",
+            r#"[{"id": 7, "title": "Seven"}, {"fields": {"title": "Eight"}}]"#,
+        );
+        let documents = parse_file(&root, &json).unwrap();
+        assert_eq!(documents[0].id, "7");
+        assert_eq!(documents[0].fields["title"], "Seven");
+        assert_eq!(documents[1].id, "items.json#1");
+        assert_eq!(documents[1].fields["title"], "Eight");
+
+        let ndjson = write("lines.ndjson", "{\"title\": \"One\"}\n\n{\"title\": \"Three\"}\n");
+        let ids: Vec<String> = parse_file(&root, &ndjson)
+            .unwrap()
+            .into_iter()
+            .map(|doc| doc.id)
+            .collect();
+        assert_eq!(ids, ["lines.ndjson#1", "lines.ndjson#3"]);
+        let broken = write("broken.ndjson", "{\"title\": \"One\"}\nnot json\n");
+        assert!(parse_file(&root, &broken).unwrap_err().to_string().starts_with("line 2:"));
+
+        let markdown = write("guides/setup.md", "Intro\n# Getting started\nText\n");
+        let documents = parse_file(&root, &markdown).unwrap();
+        assert_eq!(documents[0].id, "guides/setup.md");
+        assert_eq!(documents[0].fields["title"], "Getting started");
+        assert_eq!(documents[0].fields["path"], "guides/setup.md");
+
+        assert!(parse_file(&root, &write("list.json", "[1]")).is_err());
+        assert_eq!(collect_files(&root).len(), 5);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_removes_documents_of_deleted_files() {
+        let data = temp_dir();
+        let root = data.join("docs");
+        std::fs::create_dir_all(&root).unwrap();
+        let engine = SearchEngine::new(
+            data.join("indices").to_str().unwrap(),
+            1,
+            QueryLimits::default(),
+            FilterCache::from_env(),
+        )
+        .unwrap();
+        let store = MetadataStore::new(data.join("metadata.db").to_str().unwrap()).unwrap();
+        let fields: Vec<FieldConfig> = serde_json::from_value(serde_json::json!([
+            {"name": "title", "stored": true, "indexed": true},
+            {"name": "content", "stored": true, "indexed": true},
+            {"name": "path", "stored": true},
+        ]))
+        .unwrap();
+        engine
+            .create_index("docs", &fields, &IndexSettings::default(), 1)
+            .unwrap();
+        store.create_index("docs").unwrap();
+        let target = WatchTarget {
+            index_name: "docs".to_string(),
+            root: root.clone(),
+        };
+
+        std::fs::write(root.join("a.md"), "# A").unwrap();
+        std::fs::write(root.join("b.json"), r#"[{"title": "B1"}, {"title": "B2"}]"#).unwrap();
+        let mut synced = DirectorySync::new(target.clone(), &store);
+        assert_eq!(synced.sync_all(&engine, &store), 2);
+        assert_eq!(engine.get_document_count("docs").unwrap(), 3);
+
+        // Deleted while watching
+        std::fs::remove_file(root.join("b.json")).unwrap();
+        synced.sync_path(&engine, &store, &root.join("b.json"));
+        assert_eq!(engine.get_document_count("docs").unwrap(), 1);
+
+        // Deleted while stopped: the restarted watcher still knows what the file indexed
+        drop(synced);
+        std::fs::remove_file(root.join("a.md")).unwrap();
+        let mut synced = DirectorySync::new(target, &store);
+        assert_eq!(synced.sync_all(&engine, &store), 0);
+        assert_eq!(engine.get_document_count("docs").unwrap(), 0);
+        assert!(store.watched_files("docs").unwrap().is_empty());
+        assert_eq!(store.get_document_count("docs").unwrap(), 0);
+        std::fs::remove_dir_all(&data).unwrap();
+    }
+}